@@ -0,0 +1,316 @@
+# English messages of modpack-sync, the fallback of every other locale.
+#
+# To translate, copy this file to <locale>.ftl, e.g. de.ftl or pt-BR.ftl,
+# and translate the text after each `=`. Keep the `{ $name }` placeholders,
+# they are filled in when the message is shown. Indented lines continue the
+# message above them. Messages about a number have a variant per plural
+# category of your language, e.g. [one], [few], [many] and the default
+# *[other], or for an exact number such as [0]. Put the file into the
+# locales directory of the modpack-sync config directory to try it, or send
+# it in to ship it.
+
+## prompts
+
+prompt-yes = yes
+prompt-no = no
+prompt-answer-either = please answer { $yes } or { $no }
+prompt-remember = { $question } ({ $yes }/{ $no }, prefix with always to remember)
+
+## sync
+
+sync-starting = Starting new run of modpack-sync...
+sync-finished = modpack-sync finished successfully...
+sync-failed = modpack-sync failed: { $error }
+sync-failure-report = a report was written to { $report }, attach it to bug reports or run `modpack-sync support-bundle`
+sync-traced = planner decisions were traced to { $log }
+sync-summary = synced in { $seconds }s: { $downloads } downloaded ({ $size }), { $removed } removed
+sync-summary-retries = , { $retries } requests retried
+sync-manual-download = { $file } needs a manual download: { $error }
+sync-failed-mods = { $count ->
+        [one] one mod failed to sync, see { $report }
+       *[other] { $count } mods failed to sync, see { $report }
+    }
+sync-host-struggled = { $host } had { $failures } failed downloads and { $retries } retried requests
+sync-host-throttled = { $host } is failing or throttling, downloading from it one file at a time
+sync-pack-updated = updated the pack { $from } -> { $to }
+sync-fresh-start = No mod metadata found, will now clean directory and start fresh.
+    Please check for updates for Prism to generate metadata
+sync-world-backup = Backing up world { $world } before changing mods...
+sync-world-backup-client = world_backup is only supported for server instances, ignoring it
+sync-panel-stopping = stopping the server on the panel to upload the changed files
+sync-pushed = { $count ->
+        [one] pushed the managed file to { $target }
+       *[other] pushed the { $count } managed files to { $target }
+    }
+sync-interrupted = the sync started { $started } was interrupted, deleted { $count } files it left half installed, they are installed again
+sync-retry-empty = nothing to retry, every entry installed
+sync-usage-started = keeping usage statistics in { $path }, they never leave this machine, see `stats`
+sync-profile-chosen = installing into the game directory of launcher profile { $profile } from now on
+sync-launcher-outdated = the launcher may show outdated mods: { $error }
+sync-permissions-failed = failed to set the permissions of installed files: { $error }
+sync-launch-failed = failed to apply the pack's launch settings: { $error }
+sync-stamp-failed = failed to stamp the pack version into the instance: { $error }
+sync-java-hint = install a matching Java, e.g. from https://adoptium.net, and select it for this instance
+sync-incompatible = { $name } is declared incompatible with { $other } ({ $source })
+sync-link-outside = not deleting { $path }, a symbolic link leads it outside the instance
+sync-removals = this sync deletes { $count } files, { $unmanaged } of them not installed by modpack-sync:
+sync-removal-unmanaged = (not installed by modpack-sync)
+sync-removals-confirm = Delete these files?
+sync-tags-removed = { $count ->
+        [one] removing the file of an entry whose tag is turned off
+       *[other] removing { $count } files of entries whose tag is turned off
+    }
+sync-prefetched = { $count ->
+        [one] one file ({ $size }) is downloaded for the next sync to install
+       *[other] { $count } files ({ $size }) are downloaded for the next sync to install
+    }
+sync-dry-run-update = the dry run looks at the update of the pack to { $to }
+sync-dry-run = dry run, nothing was changed: { $files } file changes and { $overrides } override changes would be made
+sync-override-conflict = { $file } conflicts with local edits, the pack's version was saved as { $file }.new
+sync-parent-cached = { $location } is unreachable, using the copy cached by an earlier run
+sync-missing-url = skipping { $file }, it has no url or other source, check the modlist
+sync-policy-violation = { $file } is not allowed: { $reason }
+sync-unmanaged-entry = skipping { $file }, it is installed into an unmanaged directory
+sync-hold-expired = { $name } was held on its file until { $until }, run `modpack-sync update` to move it on or extend hold_until
+sync-unmanaged-override = not installing override { $file } into an unmanaged directory
+sync-content-type = { $file } is installed outside { $dir }/, where CurseForge says files of its project belong
+sync-script-not-allowed = not running `{ $command }` for { $file }, pass --allow-scripts or set allow_scripts to run the pack's post-install commands
+sync-loader-skipped = { $file } is built for { $built_for } and was not installed, the pack uses { $loader }
+sync-restarting = restarting the server with { $hook }
+owner-changed = CurseForge project { $project } changed hands: { $from } is now { $to }
+
+## line by line progress of --plain
+
+progress-planned = planned { $actions } actions
+progress-downloaded = downloaded { $file } ({ $size }) from { $host }
+progress-failed = failed to download { $file }: { $error }
+progress-removed = removed { $file }
+progress-retry = attempt { $attempt } of { $url } failed, trying again: { $error }
+
+## decisions
+
+decide-modified = { $path } was modified since it was installed, replace it?
+decide-duplicate = { $path } is another copy of the mod { $entry } installs, remove it?
+decide-loader = { $file } is built for { $built_for } but the pack uses { $loader }, install it anyway?
+decide-owner = Install from project { $project } under its new owner?
+
+## html report
+
+report-title = { $pack } sync report
+report-overview = Synced { $date }: { $installed } installed, { $removed } removed, { $failed } failed, { $size } downloaded.
+report-installed = Installed
+report-file = File
+report-previous = Previous
+report-provider = Provider
+report-size = Size
+report-new = new
+report-changelogs = Changelogs
+report-changelog-unavailable = changelog unavailable
+report-removed = Removed
+report-manual = Needs a manual download
+report-what-to-do = What to do
+report-failures = Failures
+report-error = Error
+report-alternatives = Alternatives with a file for the pack:
+report-warnings = Warnings
+report-code = Code
+report-warning = Warning
+report-suppressed = { $count ->
+        [one] One warning suppressed by the instance settings is not shown.
+       *[other] { $count } warnings suppressed by the instance settings are not shown.
+    }
+
+## daemon dashboard
+
+dashboard-title = modpack-sync daemon
+dashboard-instance = Instance
+dashboard-pack = Pack version
+dashboard-pending = Pending update
+dashboard-state = State
+dashboard-last-check = Last check
+dashboard-actions = Actions
+dashboard-queued = { $action } queued
+dashboard-checking = checking now
+dashboard-paused = paused
+dashboard-next-check = { $minutes ->
+        [one] next check in a minute
+       *[other] next check in { $minutes } minutes
+    }
+dashboard-failed = { $at }, failed: { $error }
+dashboard-fine = { $at }, went fine
+dashboard-never = not checked yet
+dashboard-sync = Sync now
+dashboard-pause = Pause
+dashboard-resume = Resume
+dashboard-rollback = Roll back
+dashboard-rollback-confirm = Restore the newest backup of { $instance } and pause it?
+dashboard-done = { $action } done.
+dashboard-login = Log in
+dashboard-login-needed = Enter a token of the daemon settings to see and operate the instances.
+dashboard-login-wrong = That token is not one of the daemon settings.
+
+## commands
+
+analyze-unresolved = could not look up { $name }, its size and dependencies are unknown
+analyze-total = { $count ->
+        [one] one entry, { $size } to download
+       *[other] { $count } entries, { $size } to download
+    }
+analyze-unknown = { $count ->
+        [one] the size of one entry is unknown: { $names }
+       *[other] the size of { $count } entries is unknown: { $names }
+    }
+analyze-largest = Largest mods:
+analyze-size-unknown = unknown
+analyze-by-category = By category:
+analyze-libraries-used = every library is required by a mod of the pack
+analyze-libraries-unused = Libraries no mod of the pack depends on:
+
+bisect-started = bisecting { $mods } mods, { $launches ->
+        [one] one launch at most
+       *[other] { $launches } launches at most
+    }
+bisect-stopped = { $launches ->
+        [one] stopped bisecting after one launch, every mod is loaded again
+       *[other] stopped bisecting after { $launches } launches, every mod is loaded again
+    }
+bisect-found = { $launches ->
+        [one] { $culprit } is behind the crash, found in one launch
+       *[other] { $culprit } is behind the crash, found in { $launches } launches
+    }
+bisect-found-next = every mod is loaded again, remove or roll it back, then sync
+bisect-round = { $disabled } of { $suspects } suspects disabled, { $launches ->
+        [one] about one launch left
+       *[other] about { $launches } launches left
+    }
+bisect-launch = launch the game, then run `modpack-sync bisect good { $dir }` if it started or `bisect bad { $dir }` if it crashed
+
+create-created = Created { $name } { $side } in { $dir }
+create-registered = Registered { $name } with Prism in { $dir }
+create-loader-version = the loader has no version, pick one under Version in Prism's instance settings
+create-no-prism = Prism is not installed for this user, the instance is not registered with a launcher
+create-next = Add mods to { $modlist }, then run `modpack-sync { $dir }` to install them
+
+diff-same = the instance has the files { $backup } holds
+diff-changed = since { $backup }: { $added } added, { $removed } removed, { $changed } changed
+
+control-check-failed = last check at { $at } failed: { $error }
+control-check-fine = last check at { $at } went fine
+control-never = not checked yet
+
+fleet-clients = { $count ->
+        [one] one client: { $versions }
+       *[other] { $count } clients: { $versions }
+    }
+fleet-broken = { $count ->
+        [one] one client failed its last sync or has missing or changed files
+       *[other] { $count } clients failed their last sync or have missing or changed files
+    }
+
+gc-would-delete = would delete { $what } { $path } ({ $size })
+gc-deleted = deleted { $what } { $path } ({ $size })
+gc-nothing = nothing to clean up
+gc-would-reclaim = { $count ->
+        [one] would reclaim { $size } from one file
+       *[other] would reclaim { $size } from { $count } files
+    }
+gc-reclaimed = { $count ->
+        [one] reclaimed { $size } from one file
+       *[other] reclaimed { $size } from { $count } files
+    }
+
+import-ftb-importing = Importing { $pack } { $version } from FTB
+import-ftb-imported = Imported { $entries } entries from CurseForge and { $overrides } overrides ({ $size }) for minecraft { $minecraft } with { $loader }
+import-ftb-optional = FTB's optional files are tagged { $tag }, install them with --with { $tag }
+import-ftb-failed = { $count ->
+        [one] one file failed to download, add it to overrides by hand: { $files }
+       *[other] { $count } files failed to download, add them to overrides by hand: { $files }
+    }
+import-ftb-next = Run `modpack-sync { $dir }` to install it
+
+inspect-holds = { $count ->
+        [one] { $backup } holds one file, { $size }
+       *[other] { $backup } holds { $count } files, { $size }
+    }
+inspect-extracted = { $count ->
+        [one] extracted one file from { $backup } into { $dir }
+       *[other] extracted { $count } files from { $backup } into { $dir }
+    }
+
+join-plays = { $address } plays { $pack } { $version } for minecraft { $minecraft } / { $loader }, { $entries } entries
+join-source = { $address } publishes its pack to { $source }
+join-no-api-key = no API key configured, run `modpack-sync setup` or set CURSE_API_KEY before syncing
+join-done = Set up { $dir } for { $address }, run `modpack-sync { $dir }` to install the pack
+
+lint-lookup-failed = could not look up { $name }: { $error }
+lint-fixed = fixed, { $fix }
+lint-would-fix = --fix would { $fix }
+lint-fixed-count = { $count ->
+        [one] fixed one problem in { $modlist }
+       *[other] fixed { $count } problems in { $modlist }
+    }
+lint-clean = { $count ->
+        [one] one entry checked, no problems left
+       *[other] { $count } entries checked, no problems left
+    }
+
+migrate-pack-lookup-failed = could not look up { $name }: { $error }
+migrate-pack-unchanged = { $name }: { $file } works as it is
+migrate-pack-moved = { $name }: { $from } -> { $to }
+migrate-pack-by-hand = { $name } is kept as it is, check it by hand
+migrate-pack-loader-only = { $name } only exists for { $loader }, left out of the candidate
+migrate-pack-stranded = { $name } has no file for { $target }, left out of the candidate
+migrate-pack-summary = { $resolved } of { $entries } entries resolved, { $stranded } without a compatible file and { $by_hand } to check by hand
+migrate-pack-written = wrote the candidate manifest to { $path }, the modlist is unchanged
+migrate-pack-loader-version = { $version } is not built for { $target }, set the loader version in the candidate
+
+preview-pack = { $pack } { $version } for minecraft { $minecraft } / { $loader }
+preview-size = { $count ->
+        [one] one entry, { $size } to download
+       *[other] { $count } entries, { $size } to download
+    }
+preview-size-unknown = { $count } entries, { $size } to download and { $unknown } of unknown size
+preview-no-api-key = no CurseForge API key configured, sizes of CurseForge files are unknown
+preview-compared = compared with { $pack } { $version } in { $dir }:
+preview-same = { $count ->
+        [one] one entry is the same
+       *[other] { $count } entries are the same
+    }
+
+quarantine-no-record = (no record of why)
+quarantine-listed = { $count ->
+        [one] one quarantined file in { $dir }
+       *[other] { $count } quarantined files in { $dir }
+    }
+quarantine-cleared = { $count ->
+        [one] deleted one quarantined file
+       *[other] deleted { $count } quarantined files
+    }
+
+stats-empty = no syncs recorded yet, they are kept in { $path }
+stats-syncs = { $count ->
+        [one] one sync since { $since }, { $failed } failed, the last one { $last }
+       *[other] { $count } syncs since { $since }, { $failed } failed, the last one { $last }
+    }
+stats-downloads = downloaded { $count } files, { $hosts } from their hosts and { $peers } from peers, { $copied } copied instead of downloaded again
+stats-kept = { $count ->
+        [one] one installed entry left in place
+       *[other] { $count } installed entries left in place
+    }
+stats-cache = shared cache: { $rate } hit rate, { $hits } served, { $revalidated } unchanged on the server, { $misses } fetched, { $saved } not transferred
+stats-hash-cache = hash cache: { $rate } hit rate
+stats-duration = a sync takes { $seconds }s on average, { $downloading }s of it downloading
+
+support-bundle-written = wrote { $count } files to { $path }
+support-bundle-redacted = secrets in the settings were redacted, look the bundle over before attaching it to an issue
+
+bench-files = { $count ->
+        [one] one file, { $size } on { $cores } cores
+       *[other] { $count } files, { $size } on { $cores } cores
+    }
+bench-hashing = hashing reads { $speed } MB/s
+bench-suggestions = Suggestions:
+
+blame-reading = reading { $report }
+blame-unknown-mod = the report names { $id }, which no entry of the modlist provides
+blame-none = the report implicates no mod of the modlist, the game, the loader or the JVM may be at fault
@@ -0,0 +1,479 @@
+use anyhow::{anyhow, Result};
+
+/// Command line arguments split into positionals and `--flags`.
+///
+/// `switches` are boolean flags, `options` take a value either as
+/// `--name value` or `--name=value` and may be repeated.
+pub struct Args {
+    pub positional: Vec<String>,
+    flags: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    pub fn parse(args: &[String], switches: &[&str], options: &[&str]) -> Result<Args> {
+        let mut positional = Vec::new();
+        let mut flags = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            // single letter short flags such as `-o` are accepted as aliases
+            let short = arg.strip_prefix('-').filter(|f| f.len() == 1);
+            let Some(flag) = arg.strip_prefix("--").or(short) else {
+                positional.push(arg.clone());
+                continue;
+            };
+
+            let (name, inline) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (flag, None),
+            };
+
+            if switches.contains(&name) {
+                if inline.is_some() {
+                    return Err(anyhow!("flag --{} does not take a value", name));
+                }
+                flags.push((name.to_string(), None));
+            } else if options.contains(&name) {
+                let value = match inline {
+                    Some(value) => value,
+                    None => iter
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| anyhow!("flag --{} expects a value", name))?,
+                };
+                flags.push((name.to_string(), Some(value)));
+            } else {
+                return Err(anyhow!("unknown flag --{}", name));
+            }
+        }
+
+        Ok(Args { positional, flags })
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.flags.iter().any(|(n, _)| n == name)
+    }
+
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.flags
+            .iter()
+            .filter(|(n, _)| n == name)
+            .filter_map(|(_, v)| v.as_deref())
+            .collect()
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref())
+    }
+}
+
+pub struct Flag {
+    pub name: &'static str,
+    pub help: &'static str,
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub flags: &'static [Flag],
+}
+
+/// Every subcommand with its flags, used for help output and shell
+/// completions. Keep in sync with the dispatch in main.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "sync",
+        about: "Sync the instance with its modlist (default)",
+        flags: &[
+            Flag { name: "report", help: "Write a report of the run, e.g. html=report.html, json=- for stdout or prometheus=<path>" },
+            Flag { name: "modlist", help: "Sync this modlist instead of the instance's, - reads it from stdin" },
+            Flag { name: "var", help: "Value of a manifest placeholder, e.g. mc_version=1.21" },
+            Flag { name: "force", help: "Replace managed files that were modified locally" },
+            Flag { name: "backup", help: "Back up managed files before applying changes" },
+            Flag { name: "yes", help: "Delete files without asking past the approval thresholds" },
+            Flag { name: "channel", help: "Release channel of the source to follow, e.g. beta" },
+            Flag { name: "jobs", help: "Downloads to run at once, 4 by default" },
+            Flag { name: "target", help: "Push the managed files to a server only reachable over sftp, e.g. sftp://user@host/srv/minecraft" },
+            Flag { name: "profile-name", help: "Install into the game directory of this vanilla launcher profile, remembered for later syncs" },
+            Flag { name: "trace-plan", help: "Log every planner decision with its inputs to the run log" },
+            Flag { name: "with", help: "Turn on the entries with a tag, remembered for later syncs, repeatable" },
+            Flag { name: "without", help: "Turn off the entries with a tag, remembered for later syncs, repeatable" },
+            Flag { name: "deny", help: "Fail the run when warnings with these codes are raised, e.g. W001,W007, repeatable" },
+            Flag { name: "strict", help: "Fail the run on any warning the instance doesn't suppress" },
+            Flag { name: "dry-run", help: "Print what would change with diffs of the overrides, changing nothing" },
+            Flag { name: "allow-scripts", help: "Run the post-install commands of entries for this sync" },
+            Flag { name: "suggest", help: "Search for alternatives to entries without a file for the pack, listed in the reports" },
+        ],
+    },
+    CommandSpec {
+        name: "setup",
+        about: "Interactive first-run setup",
+        flags: &[],
+    },
+    CommandSpec {
+        name: "create",
+        about: "Lay out a new instance with its directories and modlist",
+        flags: &[
+            Flag { name: "dir", help: "Directory of the new instance" },
+            Flag { name: "mc", help: "Minecraft version of the pack" },
+            Flag { name: "loader", help: "fabric, quilt, forge or neoforge, optionally followed by -<version>" },
+            Flag { name: "name", help: "Name of the pack, the directory's name by default" },
+            Flag { name: "server", help: "Create a dedicated server instance" },
+            Flag { name: "register", help: "List the instance in Prism if it is installed" },
+        ],
+    },
+    CommandSpec {
+        name: "doctor",
+        about: "Diagnose environment problems",
+        flags: &[],
+    },
+    CommandSpec {
+        name: "migrate",
+        about: "Upgrade a legacy modlist to the current format",
+        flags: &[],
+    },
+    CommandSpec {
+        name: "import-ftb",
+        about: "Make an instance of a Feed The Beast pack, taking the files FTB mirrors from CurseForge into the modlist and lockfile",
+        flags: &[
+            Flag { name: "pack", help: "Id of the pack on FTB" },
+            Flag { name: "version", help: "Name or id of the version to import, the newest release by default" },
+            Flag { name: "server", help: "Import as a dedicated server instance" },
+        ],
+    },
+    CommandSpec {
+        name: "migrate-pack",
+        about: "Write a candidate manifest of the pack for another Minecraft version or loader, leaving the modlist as it is",
+        flags: &[
+            Flag { name: "to-mc", help: "Minecraft version to move the pack to" },
+            Flag { name: "to-loader", help: "Loader to move the pack to, e.g. neoforge" },
+            Flag { name: "out", help: "File to write the candidate to, modlist-<loader>-<version>.json by default" },
+            Flag { name: "suggest", help: "Search for alternatives to entries without a compatible file" },
+        ],
+    },
+    CommandSpec {
+        name: "fmt",
+        about: "Sort and normalize the modlist",
+        flags: &[Flag { name: "check", help: "Fail instead of rewriting when unformatted" }],
+    },
+    CommandSpec {
+        name: "merge",
+        about: "Merge several modlists into one",
+        flags: &[
+            Flag { name: "out", help: "File to write the merged modlist to" },
+            Flag { name: "strategy", help: "Conflict resolution: first, last or fail" },
+        ],
+    },
+    CommandSpec {
+        name: "resolve",
+        about: "Show the effective modlist after overlays and conditions",
+        flags: &[
+            Flag { name: "out", help: "Write the flattened modlist to a file" },
+            Flag { name: "as-of", help: "Resolve to the files published before a date, e.g. 2024-06-01" },
+        ],
+    },
+    CommandSpec {
+        name: "update",
+        about: "Move entries to the newest file of their project",
+        flags: &[
+            Flag { name: "dry-run", help: "Show the updates without writing the modlist" },
+            Flag { name: "only", help: "Largest update to apply: patch, minor (the default) or major" },
+            Flag { name: "suggest", help: "Search for alternatives to entries without a file for the pack" },
+            Flag { name: "as-of", help: "Only consider files published before a date, e.g. 2024-06-01" },
+        ],
+    },
+    CommandSpec {
+        name: "outdated",
+        about: "List entries with newer files and how big each update is",
+        flags: &[
+            Flag { name: "only", help: "Largest update to list: patch, minor or major" },
+            Flag { name: "stale-months", help: "Months without a new file after which a project looks abandoned, 12 by default" },
+            Flag { name: "suggest", help: "Search for alternatives to entries without a file for the pack" },
+        ],
+    },
+    CommandSpec {
+        name: "remove",
+        about: "Remove mods from the modlist along with libraries only they need",
+        flags: &[Flag { name: "yes", help: "Remove libraries only the removed mods need without asking" }],
+    },
+    CommandSpec {
+        name: "verify",
+        about: "Check the installed files against the effective modlist",
+        flags: &[
+            Flag { name: "quick", help: "Only check that recorded files are present with their size, for launch hooks" },
+            Flag { name: "deep", help: "Hash every file again instead of trusting unchanged ones" },
+            Flag { name: "identify", help: "Look the jars up on Modrinth and CurseForge, offering to switch entries to Modrinth" },
+            Flag { name: "export-hashes", help: "Write the hashes of every file to a file once the instance verified" },
+            Flag { name: "import-hashes", help: "Check this and later verifications against the hashes exported from a golden instance" },
+        ],
+    },
+    CommandSpec {
+        name: "list",
+        about: "List the effective modlist after overlays and conditions",
+        flags: &[
+            Flag { name: "format", help: "Output format: table, csv or json" },
+            Flag { name: "sort", help: "Order by modlist, name, size or source" },
+            Flag { name: "filter", help: "Only list entries with field=value, e.g. loader=fabric or installed=no, repeatable" },
+            Flag { name: "all", help: "List every entry in csv too, not only the installed mods, with their loader, side, directory and remarks" },
+        ],
+    },
+    CommandSpec {
+        name: "search",
+        about: "Search CurseForge and Modrinth for mods",
+        flags: &[
+            Flag { name: "mc", help: "Minecraft version to filter by" },
+            Flag { name: "loader", help: "Mod loader to filter by" },
+            Flag { name: "provider", help: "curseforge, modrinth or all" },
+            Flag { name: "page", help: "Page of the CurseForge results to show, 1 by default" },
+        ],
+    },
+    CommandSpec {
+        name: "info",
+        about: "Show provider details for a single mod",
+        flags: &[],
+    },
+    CommandSpec {
+        name: "deps",
+        about: "Export the dependency graph of the pack",
+        flags: &[Flag { name: "format", help: "Output format: dot or json" }],
+    },
+    CommandSpec {
+        name: "analyze",
+        about: "Report the pack's download size, largest mods and unused libraries",
+        flags: &[
+            Flag { name: "top", help: "Number of largest mods to show, 10 by default" },
+            Flag { name: "format", help: "Output format: text or json" },
+        ],
+    },
+    CommandSpec {
+        name: "bench",
+        about: "Time resolving and hashing the instance without downloading, suggesting concurrency and cache settings",
+        flags: &[Flag { name: "format", help: "Output format: text or json" }],
+    },
+    CommandSpec {
+        name: "blame",
+        about: "Work out which mods a crash report implicates and whether to roll them back or disable them",
+        flags: &[Flag { name: "crash", help: "latest for the newest crash report of the instance, or the path of one" }],
+    },
+    CommandSpec {
+        name: "bisect",
+        about: "Find the mod behind a crash by disabling half of the mods per launch: bisect <start|good|bad|reset> <instance dir>",
+        flags: &[Flag { name: "keep", help: "Keep an entry loaded throughout besides the pinned ones, e.g. a library the others need, repeatable" }],
+    },
+    CommandSpec {
+        name: "lint",
+        about: "Check the modlist for missing files, slugs in urls, mods listed twice, files older than the lockfile and content in the wrong directory",
+        flags: &[Flag { name: "fix", help: "Apply the corrections that have only one answer" }],
+    },
+    CommandSpec {
+        name: "control",
+        about: "Drive a running daemon: control <instance dir> <trigger-sync|get-status|pause|resume|reload-config|rollback> [instance|all]",
+        flags: &[Flag { name: "json", help: "Print the daemon's answer as JSON" }],
+    },
+    CommandSpec {
+        name: "join",
+        about: "Set up an instance for the pack a server advertises, given its address and optionally the directory",
+        flags: &[],
+    },
+    CommandSpec {
+        name: "preview",
+        about: "Show a remote pack's contents and size, and its differences to an instance given after the url",
+        flags: &[],
+    },
+    CommandSpec {
+        name: "publish",
+        about: "Validate, version and upload the pack to a distribution backend",
+        flags: &[
+            Flag { name: "to", help: "s3://, rsync:, sftp:// or git+ target, defaults to publish_to" },
+            Flag { name: "channel", help: "Release channel to publish to, e.g. beta" },
+            Flag { name: "bump", help: "Version component to bump: major, minor or patch" },
+            Flag { name: "no-bump", help: "Publish without changing the pack version" },
+            Flag { name: "rollout", help: "Percentage of clients to roll the version out to, the others keep theirs" },
+            Flag { name: "redistribute-restricted", help: "Push jars whose authors forbid redistribution anyway" },
+        ],
+    },
+    CommandSpec {
+        name: "export",
+        about: "Export the pack, `export serverpack` writes a ready to run server, `export sbom` an inventory, `export bundle` an offline installer, `export stats` a summary for websites",
+        flags: &[
+            Flag { name: "out", help: "Directory to write the server pack to, or file for the inventory, bundle or statistics" },
+            Flag { name: "format", help: "Inventory format, cyclonedx (default) or spdx" },
+            Flag { name: "installer", help: "Download the loader's server installer" },
+            Flag { name: "no-sync", help: "Skip installing the server's mods" },
+            Flag { name: "from", help: "Bundle only what changed since this bundle file or exported version" },
+            Flag { name: "to", help: "Version the instance must have installed for the bundle" },
+            Flag { name: "badge", help: "Write the shields.io badge of one statistic: mods, version, minecraft, size or updated" },
+        ],
+    },
+    CommandSpec {
+        name: "plan",
+        about: "Write the changes a sync would make to a file for review",
+        flags: &[
+            Flag { name: "out", help: "File to write the plan to, plan.json by default" },
+            Flag { name: "trace-plan", help: "Log every planner decision with its inputs to the run log" },
+            Flag { name: "deny", help: "Fail when warnings with these codes are raised, e.g. W001,W007, repeatable" },
+            Flag { name: "strict", help: "Fail on any warning the instance doesn't suppress" },
+            Flag { name: "acknowledge-owner", help: "Install from a CurseForge project that changed hands, by project id, repeatable" },
+            Flag { name: "modlist", help: "Plan this modlist instead of the instance's, - reads it from stdin" },
+        ],
+    },
+    CommandSpec {
+        name: "prefetch",
+        about: "Download the files the next sync installs ahead of time, without installing them",
+        flags: &[
+            Flag { name: "channel", help: "Release channel of the source to follow, e.g. beta" },
+            Flag { name: "jobs", help: "Downloads to run at once, 4 by default" },
+            Flag { name: "with", help: "Prefetch the entries with a tag as well, repeatable" },
+            Flag { name: "without", help: "Leave out the entries with a tag, repeatable" },
+        ],
+    },
+    CommandSpec {
+        name: "retry",
+        about: "Install again only the entries that kept failing in earlier runs, without pulling or planning the rest of the pack",
+        flags: &[
+            Flag { name: "jobs", help: "Downloads to run at once, 4 by default" },
+        ],
+    },
+    CommandSpec {
+        name: "stats",
+        about: "Show the usage statistics syncs kept on this machine: bytes downloaded, cache hit rates, sync counts and durations",
+        flags: &[Flag { name: "format", help: "Output format: text or json" }],
+    },
+    CommandSpec {
+        name: "gc",
+        about: "Delete partial downloads, orphaned staging files, expired backups and quarantined files and old history, reporting the space reclaimed",
+        flags: &[
+            Flag { name: "dry-run", help: "List what would be deleted without deleting it" },
+            Flag { name: "keep", help: "Run logs and exported bundle indexes kept, 20 by default" },
+            Flag { name: "quarantine-days", help: "Days quarantined files are kept, 30 by default" },
+        ],
+    },
+    CommandSpec {
+        name: "serve",
+        about: "Share the files the lockfile pins with players listing this instance as a peer, and the pack's statistics at /stats.json",
+        flags: &[
+            Flag { name: "port", help: "Port to listen on, 8990 by default" },
+            Flag { name: "bind", help: "Address to listen on, all interfaces by default" },
+            Flag { name: "fleet", help: "Record the versions and state clients report after their syncs" },
+            Flag { name: "url", help: "Url players reach this instance at, advertised to `join` as a peer" },
+            Flag { name: "manifest", help: "Url of the manifest advertised to `join`, the instance's source otherwise" },
+        ],
+    },
+    CommandSpec {
+        name: "fleet",
+        about: "List the players and servers that reported to `serve --fleet` with the version they are on",
+        flags: &[
+            Flag { name: "format", help: "Output format: text or json" },
+            Flag { name: "days", help: "Only list clients that reported within this many days" },
+        ],
+    },
+    CommandSpec {
+        name: "apply",
+        about: "Apply a reviewed plan file, failing if the instance changed since, or with `apply bundle` install a bundle offline",
+        flags: &[
+            Flag { name: "force", help: "Replace managed files that were modified locally" },
+            Flag { name: "yes", help: "Delete files without asking past the approval thresholds" },
+            Flag { name: "modlist", help: "The modlist the plan was made from with --modlist, - reads it from stdin" },
+        ],
+    },
+    CommandSpec {
+        name: "daemon",
+        about: "Watch the sources of one or more instances and sync new pack versions in maintenance windows",
+        flags: &[
+            Flag { name: "once", help: "Check once and exit, for running from cron or a timer" },
+            Flag { name: "workers", help: "Instances to check and sync at once when watching several, 2 by default" },
+            Flag { name: "now", help: "Apply pending updates right away, whatever the windows and players online" },
+            Flag { name: "dashboard", help: "Serve a web dashboard on an address or port, e.g. 8991" },
+            Flag { name: "yes", help: "Delete files without asking past the approval thresholds" },
+            Flag { name: "channel", help: "Release channel of the source to follow, e.g. beta" },
+            Flag { name: "jobs", help: "Downloads to run at once, 4 by default" },
+        ],
+    },
+    CommandSpec {
+        name: "quarantine",
+        about: "List or clear downloads rejected by checks: quarantine <list|clear> <instance dir>",
+        flags: &[],
+    },
+    CommandSpec {
+        name: "restore-backup",
+        about: "Restore managed files from a backup taken before a sync",
+        flags: &[Flag { name: "list", help: "List the available backups" }],
+    },
+    CommandSpec {
+        name: "inspect",
+        about: "List the files of a backup or extract some of them, without restoring it",
+        flags: &[
+            Flag { name: "snapshot", help: "Backup to inspect, as restore-backup --list names it or the time it was taken" },
+            Flag { name: "extract", help: "File or directory to extract, relative to the game directory, repeatable" },
+            Flag { name: "to", help: "Directory to extract into, one named after the backup by default" },
+        ],
+    },
+    CommandSpec {
+        name: "diff",
+        about: "Compare the managed files of the instance with those of a backup",
+        flags: &[Flag { name: "snapshot", help: "Backup to compare against, as restore-backup --list names it or the time it was taken" }],
+    },
+    CommandSpec {
+        name: "encrypt-secret",
+        about: "Encrypt a secret read from stdin for the settings, with MODPACK_SYNC_PASSPHRASE or to an age key",
+        flags: &[Flag { name: "age", help: "age recipient to encrypt to instead of the passphrase" }],
+    },
+    CommandSpec {
+        name: "support-bundle",
+        about: "Collect logs, the last failure, the modlist, lockfile and redacted settings into a zip for bug reports",
+        flags: &[
+            Flag { name: "out", help: "Where to write the zip, modpack-sync-support-<time>.zip by default" },
+            Flag { name: "logs", help: "Number of recent run logs to include, 5 by default" },
+        ],
+    },
+    CommandSpec {
+        name: "logs",
+        about: "List or print the logs of previous syncs",
+        flags: &[
+            Flag { name: "last", help: "Print the most recent run" },
+            Flag { name: "failed", help: "Only consider runs that logged errors" },
+        ],
+    },
+    CommandSpec {
+        name: "self-update",
+        about: "Update modpack-sync to the latest release",
+        flags: &[Flag { name: "check", help: "Only report whether an update exists" }],
+    },
+    CommandSpec {
+        name: "completions",
+        about: "Print shell completions for bash, zsh, fish or powershell",
+        flags: &[],
+    },
+    CommandSpec {
+        name: "help",
+        about: "Show this help",
+        flags: &[],
+    },
+];
+
+pub fn print_help() {
+    println!("usage: modpack-sync <command> <instance dir> [flags]");
+    println!("       modpack-sync <instance dir>");
+    println!();
+    let plain = crate::sync::log::plain();
+    for command in COMMANDS {
+        match plain {
+            true => println!("{}: {}", command.name, command.about),
+            false => println!("  {:<14} {}", command.name, command.about),
+        }
+        for flag in command.flags {
+            match plain {
+                true => println!("--{}: {}", flag.name, flag.help),
+                false => println!("      --{:<12} {}", flag.name, flag.help),
+            }
+        }
+    }
+    println!();
+    println!("Every command also takes --plain for simple line by line output,");
+    println!("--debug-http <dir> to record its API requests with keys redacted for bug reports,");
+    println!("and --replay <dir> to run from such a recording without the network.");
+}
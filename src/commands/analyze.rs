@@ -0,0 +1,173 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::launcher::Launcher;
+use crate::sync::report::human_size;
+use crate::sync::settings::{self, InstanceSettings};
+use crate::sync::{self, deps};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub top: usize,
+    pub format: Format,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["top", "format"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let top = match parsed.value("top") {
+            Some(top) => top.parse().map_err(|_| anyhow!("--top expects a number, got {}", top))?,
+            None => 10,
+        };
+        let format = match parsed.value("format").unwrap_or("text") {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            other => return Err(anyhow!("unknown format {}, expected text or json", other)),
+        };
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            top,
+            format,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    filename: String,
+    /// `library` or `content` by the project's CurseForge categories, `other`
+    /// for entries from elsewhere.
+    category: &'static str,
+    /// Bytes, `None` when neither CurseForge nor the installed file tell.
+    size: Option<u64>,
+}
+
+#[derive(Serialize, Default)]
+struct Category {
+    entries: usize,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct Analysis {
+    total: u64,
+    /// Entries whose size is unknown and missing from `total`.
+    unknown: Vec<String>,
+    largest: Vec<Entry>,
+    categories: BTreeMap<&'static str, Category>,
+    /// Libraries nothing in the pack depends on.
+    unused: Vec<String>,
+}
+
+/// Reports how large the effective modlist is to download, where the size
+/// goes and which libraries no longer serve any mod, for trimming packs.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+    let effective = sync::load_effective(&base_dir, &config.mods_file)?;
+    let metadata = Launcher::detect(&base_dir).metadata(&base_dir, &mods_dir)?;
+    let graph = deps::build(&effective.mods, &metadata, &settings::api_key()?)?;
+
+    let mut entries = Vec::new();
+    for m in effective.mods.iter() {
+        let node = m
+            .project_id()
+            .and_then(|id| graph.nodes.iter().find(|n| n.project_id == id && n.filename == m.filename));
+        let installed = fs::metadata(m.install_path(&game_dir, &mods_dir)?).ok().filter(|meta| meta.is_file());
+        entries.push(Entry {
+            name: m.name.clone(),
+            filename: m.filename.clone(),
+            category: match node {
+                Some(n) if n.library => "library",
+                Some(_) => "content",
+                None => "other",
+            },
+            size: node.map(|n| n.size).filter(|size| *size > 0).or(installed.map(|meta| meta.len())),
+        });
+    }
+
+    let mut categories: BTreeMap<&'static str, Category> = BTreeMap::new();
+    for e in entries.iter() {
+        let category = categories.entry(e.category).or_default();
+        category.entries += 1;
+        category.size += e.size.unwrap_or(0);
+    }
+    let unknown = entries.iter().filter(|e| e.size.is_none()).map(|e| e.name.clone()).collect();
+    entries.sort_by_key(|e| std::cmp::Reverse(e.size.unwrap_or(0)));
+    entries.truncate(config.top);
+    let analysis = Analysis {
+        total: categories.values().map(|c| c.size).sum(),
+        unknown,
+        largest: entries,
+        categories,
+        unused: graph.orphans().iter().map(|n| n.name.clone()).collect(),
+    };
+
+    match config.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&analysis)?),
+        Format::Text => print_text(&analysis, effective.mods.len()),
+    }
+    for unresolved in graph.unresolved.iter() {
+        eprintln!("[WARN] {}", t!("analyze-unresolved", name = unresolved));
+    }
+    Ok(())
+}
+
+fn print_text(analysis: &Analysis, count: usize) {
+    println!("[INFO] {}", t!("analyze-total", count = count, size = human_size(analysis.total)));
+    if !analysis.unknown.is_empty() {
+        println!("[WARN] {}", t!("analyze-unknown", count = analysis.unknown.len(), names = analysis.unknown.join(", ")));
+    }
+    println!();
+    println!("{}", t!("analyze-largest"));
+    for e in analysis.largest.iter() {
+        let size = e.size.map_or_else(|| t!("analyze-size-unknown"), human_size);
+        match sync::log::plain() {
+            true => println!("{}, {}, {}", e.name, size, e.category),
+            false => println!("  {:<36} {:>10}  {}", e.name, size, e.category),
+        }
+    }
+    println!();
+    println!("{}", t!("analyze-by-category"));
+    for (name, c) in analysis.categories.iter() {
+        let share = match analysis.total {
+            0 => 0.0,
+            total => c.size as f64 * 100.0 / total as f64,
+        };
+        match sync::log::plain() {
+            true => println!("{}, {} entries, {}, {:.0}%", name, c.entries, human_size(c.size), share),
+            false => println!("  {:<10} {:>4} entries {:>10} {:>4.0}%", name, c.entries, human_size(c.size), share),
+        }
+    }
+    println!();
+    match analysis.unused.is_empty() {
+        true => println!("[INFO] {}", t!("analyze-libraries-used")),
+        false => {
+            println!("{}", t!("analyze-libraries-unused"));
+            for name in analysis.unused.iter() {
+                println!("  {}", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::cli::Args;
+use crate::sync::overrides;
+use crate::sync::plan::PlanFile;
+use crate::sync::settings::InstanceSettings;
+use crate::sync::{self, bundle, Mode};
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Config {
+    pub plan: PathBuf,
+    /// The file is a bundle from `export bundle` rather than a plan.
+    pub bundle: bool,
+    pub base_dir: String,
+    pub force: bool,
+    pub yes: bool,
+    /// Modlist the plan was made from with `--modlist`, `-` for stdin.
+    pub modlist: Option<String>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["force", "yes"], &["modlist"])?;
+        let bundle = parsed.positional.first().is_some_and(|p| p == "bundle");
+        let (plan, base_dir) = match &parsed.positional[bundle as usize..] {
+            [plan] => (plan.clone(), ".".to_string()),
+            [plan, base_dir, ..] => (plan.clone(), base_dir.clone()),
+            [] => return Err(anyhow!("usage: modpack-sync apply [bundle] <plan or bundle file> [instance dir]")),
+        };
+
+        Ok(Config {
+            plan: PathBuf::from(plan),
+            bundle,
+            base_dir,
+            force: parsed.has("force"),
+            yes: parsed.has("yes"),
+            modlist: parsed.value("modlist").map(str::to_string),
+        })
+    }
+}
+
+/// Carries out a plan written by `modpack-sync plan`, refusing when the
+/// instance changed since so only the reviewed changes are made.
+pub fn run(config: Config) -> Result<()> {
+    if config.bundle {
+        return install_bundle(&config);
+    }
+    let mut args = vec!["sync".to_string(), config.base_dir.clone()];
+    if let Some(modlist) = &config.modlist {
+        args.extend(["--modlist".to_string(), modlist.clone()]);
+    }
+    let mut sync_config = sync::Config::build(&args)?;
+    // the owners acknowledged when the plan was reviewed are trusted
+    sync_config.acknowledged = PlanFile::load(&config.plan)?.acknowledged;
+    sync_config.mode = Mode::Apply(config.plan.clone());
+    sync_config.force = config.force;
+    sync_config.yes = config.yes;
+    sync::run(sync_config)?;
+    println!("[INFO] applied {} to {}", config.plan.display(), config.base_dir);
+    Ok(())
+}
+
+/// Installs a bundle from `export bundle` without going online, listing
+/// the files it could not hold that are still missing.
+fn install_bundle(config: &Config) -> Result<()> {
+    let base_dir = Path::new(&config.base_dir);
+    fs::create_dir_all(base_dir)?;
+    let instance = InstanceSettings::load(base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let protected = instance
+        .protected
+        .clone()
+        .unwrap_or_else(|| overrides::DEFAULT_PROTECTED.iter().map(|p| p.to_string()).collect());
+    let unmanaged = instance.unmanaged.clone().unwrap_or_default();
+    let applied = bundle::apply(&config.plan, base_dir, &game_dir, "modlist.json", &protected, &unmanaged)?;
+    println!(
+        "[INFO] installed {} files of {} {} from {}",
+        applied.files,
+        applied.pack.name,
+        applied.pack.version,
+        config.plan.display()
+    );
+    for key in applied.missing.iter() {
+        println!("[WARN] {} is missing, it should have been there from the version the bundle updates", key);
+    }
+    let missing: Vec<_> = applied.restricted.iter().filter(|r| !game_dir.join(&r.path).is_file()).collect();
+    if !missing.is_empty() {
+        println!("[WARN] {} files the bundle may not hold have to be placed by hand:", missing.len());
+        for r in missing {
+            println!(
+                "  {}{}",
+                game_dir.join(&r.path).display(),
+                r.url.as_ref().map(|u| format!(", download it from {}", u)).unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
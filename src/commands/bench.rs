@@ -0,0 +1,193 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::launcher::Launcher;
+use crate::sync::report::human_size;
+use crate::sync::settings::InstanceSettings;
+use crate::sync::state::State;
+use crate::sync::{self, hash, hosts, plan};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Instant;
+
+/// Below this hashing is held up by the disk rather than the cores.
+const SLOW_DISK_MB_PER_SEC: f64 = 50.0;
+/// Less than this is read too fast to tell how fast the disk is.
+const MIN_MEASURED_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub format: Format,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["format"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let format = match parsed.value("format").unwrap_or("text") {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            other => return Err(anyhow!("unknown format {}, expected text or json", other)),
+        };
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            format,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Phase {
+    name: &'static str,
+    millis: f64,
+    detail: String,
+}
+
+#[derive(Serialize)]
+struct Bench {
+    cores: usize,
+    files: usize,
+    bytes: u64,
+    /// MB per second every file was read and hashed at.
+    hash_mb_per_sec: f64,
+    /// Files the hash cache of the instance lacked or had outdated.
+    cache_misses: usize,
+    phases: Vec<Phase>,
+    suggestions: Vec<String>,
+}
+
+/// Times what a sync does before downloading against the instance as it
+/// is: loading the modlist, reading the launcher's metadata, resolving the
+/// plan and hashing the files, from the hash cache and from disk. Files the
+/// system keeps in memory read faster than the disk would. Nothing is
+/// downloaded or changed, the hash cache included.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+    let mut phases = Vec::new();
+
+    let started = Instant::now();
+    let effective = sync::load_effective(&base_dir, &config.mods_file)?;
+    phases.push(phase("load the modlist", started, format!("{} entries, {} layers", effective.mods.len(), effective.layers.len())));
+
+    let started = Instant::now();
+    let metadata = Launcher::detect(&base_dir).metadata(&base_dir, &mods_dir)?;
+    let state = State::load(&base_dir)?;
+    phases.push(phase("read the metadata", started, format!("{} launcher entries, {} recorded files", metadata.len(), state.files.len())));
+
+    let started = Instant::now();
+    let managed_dirs = effective.pack.managed_dirs(&game_dir, &mods_dir)?;
+    let moved = plan::moved_artifacts(&effective.mods, &game_dir, &mods_dir)?;
+    let lookups = plan::Lookups { metadata: &metadata, moved: &moved };
+    let plan = plan::build(&effective.mods, &game_dir, &mods_dir, &managed_dirs, &lookups, &state, instance.symlinks.unwrap_or_default())?;
+    phases.push(phase("resolve the plan", started, format!("{} actions", plan.actions.len())));
+
+    let paths = files(&game_dir, &mods_dir, &state);
+    let bytes: u64 = paths.iter().filter_map(|p| fs::metadata(p).ok()).map(|m| m.len()).sum();
+    let started = Instant::now();
+    let mut cache = hash::Cache::load(&base_dir);
+    let misses = cache.stale(&paths);
+    cache.hash_all(&paths)?;
+    phases.push(phase("hash from the cache", started, format!("{} of {} files cached", paths.len() - misses, paths.len())));
+
+    let started = Instant::now();
+    hash::Cache::load(&base_dir).rehash_all(&paths)?;
+    let cold = started.elapsed().as_secs_f64();
+    phases.push(phase("hash every file", started, human_size(bytes)));
+
+    let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut bench = Bench {
+        cores,
+        files: paths.len(),
+        bytes,
+        hash_mb_per_sec: bytes as f64 / 1_000_000.0 / cold.max(0.001),
+        cache_misses: misses,
+        phases,
+        suggestions: Vec::new(),
+    };
+    bench.suggestions = suggestions(&bench, &instance);
+
+    match config.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&bench)?),
+        Format::Text => print_text(&bench),
+    }
+    Ok(())
+}
+
+fn phase(name: &'static str, started: Instant, detail: String) -> Phase {
+    Phase { name, millis: started.elapsed().as_secs_f64() * 1000.0, detail }
+}
+
+/// The files a sync hashes: those the state records and every file of the
+/// mods directory.
+fn files(game_dir: &Path, mods_dir: &Path, state: &State) -> Vec<PathBuf> {
+    let mut paths = state.paths(game_dir);
+    if let Ok(entries) = fs::read_dir(mods_dir) {
+        paths.extend(entries.filter_map(|e| e.ok().map(|e| e.path())));
+    }
+    paths.retain(|p| p.is_file());
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+fn suggestions(bench: &Bench, instance: &InstanceSettings) -> Vec<String> {
+    let mut found = Vec::new();
+    if bench.cores <= 2 {
+        found.push(format!(
+            "only {} cores, pass --jobs 2 to sync and daemon so hashing and unpacking keep up with the downloads",
+            bench.cores
+        ));
+    } else if bench.cores >= hosts::DEFAULT_JOBS * 2 {
+        found.push(format!("{} cores, --jobs {} may download faster on a fast connection", bench.cores, hosts::DEFAULT_JOBS * 2));
+    }
+    let measured = bench.bytes >= MIN_MEASURED_BYTES;
+    if measured && bench.hash_mb_per_sec < SLOW_DISK_MB_PER_SEC {
+        found.push(format!(
+            "files hash at {:.0} MB/s, the disk is slow: keep .modpack-sync/hashes.json and use `verify --quick` where a full verify isn't needed",
+            bench.hash_mb_per_sec
+        ));
+    }
+    if bench.cache_misses > 0 {
+        found.push(format!("{} files are missing from the hash cache, the next sync or verify reads them and fills it in", bench.cache_misses));
+    }
+    if measured && instance.daemon.is_some() && !instance.durable && bench.hash_mb_per_sec >= SLOW_DISK_MB_PER_SEC * 4.0 {
+        found.push("the disk is fast enough for durable = true, which lets the daemon recover from a power loss mid-sync".to_string());
+    }
+    found
+}
+
+fn print_text(bench: &Bench) {
+    println!("[INFO] {}", t!("bench-files", count = bench.files, size = human_size(bench.bytes), cores = bench.cores));
+    for p in bench.phases.iter() {
+        match sync::log::plain() {
+            true => println!("{}, {:.1}ms, {}", p.name, p.millis, p.detail),
+            false => println!("  {:<22} {:>10.1}ms  {}", p.name, p.millis, p.detail),
+        }
+    }
+    println!("[INFO] {}", t!("bench-hashing", speed = format!("{:.0}", bench.hash_mb_per_sec)));
+    if !bench.suggestions.is_empty() {
+        println!();
+        println!("{}", t!("bench-suggestions"));
+        for s in bench.suggestions.iter() {
+            println!("  {}", s);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync;
+use crate::sync::bisect::{self, Bisect};
+use crate::sync::settings::InstanceSettings;
+use crate::sync::state::{self, State};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Start,
+    /// The launch crashed or not.
+    Answer(bool),
+    Reset,
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub action: Action,
+    /// Names or file names of entries to keep loaded throughout besides the
+    /// pinned ones, e.g. libraries the others need.
+    pub keep: Vec<String>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["keep"])?;
+        let (action, base_dir) = match parsed.positional.as_slice() {
+            [action, base_dir, ..] => (action.as_str(), base_dir.clone()),
+            _ => return Err(anyhow!("usage: modpack-sync bisect <start|good|bad|reset> <instance dir>")),
+        };
+        let action = match action {
+            "start" => Action::Start,
+            "good" => Action::Answer(false),
+            "bad" => Action::Answer(true),
+            "reset" => Action::Reset,
+            other => return Err(anyhow!("unknown bisect action {}, expected start, good, bad or reset", other)),
+        };
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            action,
+            keep: parsed.values("keep").into_iter().map(str::to_string).collect(),
+        })
+    }
+}
+
+/// Finds the mod behind a crash by launching with half of the suspects
+/// disabled at a time: `start` disables the first half, `good` and `bad`
+/// tell whether the launch crashed and disable the next half, until one mod
+/// is left. `reset` gives up and loads every mod again.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+    let mut state = State::load(&base_dir)?;
+
+    let bisect = match (config.action, state.bisect.take()) {
+        (Action::Start, Some(_)) => {
+            return Err(anyhow!("a bisect is running already, answer it with good or bad, or run `bisect reset`"));
+        }
+        (Action::Start, None) => {
+            let effective = sync::load_effective(&base_dir, &config.mods_file)?;
+            let mut suspects = Vec::new();
+            for m in effective.mods.iter() {
+                let kept = m.pinned
+                    || config
+                        .keep
+                        .iter()
+                        .any(|k| m.key() == *k || m.filename == *k || m.name.eq_ignore_ascii_case(k));
+                let path = m.install_path(&game_dir, &mods_dir)?;
+                if !kept && path.is_file() && path.extension().is_some_and(|e| e == "jar") {
+                    suspects.push(state::key(&game_dir, &path));
+                }
+            }
+            if suspects.len() < 2 {
+                return Err(anyhow!("{} mods to bisect, there is nothing to narrow down", suspects.len()));
+            }
+            let bisect = Bisect::new(suspects);
+            println!("[INFO] {}", t!("bisect-started", mods = bisect.suspects.len(), launches = bisect.remaining()));
+            bisect
+        }
+        (_, None) => return Err(anyhow!("no bisect is running, start one with `bisect start`")),
+        (Action::Reset, Some(bisect)) => {
+            restore(&game_dir, &bisect)?;
+            state.save(&base_dir)?;
+            println!("[INFO] {}", t!("bisect-stopped", launches = bisect.rounds));
+            return Ok(());
+        }
+        (Action::Answer(crashed), Some(mut bisect)) => {
+            restore(&game_dir, &bisect)?;
+            if let Some(culprit) = bisect.answer(crashed) {
+                state.save(&base_dir)?;
+                println!("[INFO] {}", t!("bisect-found", culprit = culprit, launches = bisect.rounds));
+                println!("[INFO] {}", t!("bisect-found-next"));
+                return Ok(());
+            }
+            bisect
+        }
+    };
+
+    for key in bisect.disabled() {
+        bisect::disable(&game_dir, key)?;
+    }
+    println!(
+        "[INFO] {}",
+        t!("bisect-round", disabled = bisect.disabled().count(), suspects = bisect.suspects.len(), launches = bisect.remaining())
+    );
+    println!("[INFO] {}", t!("bisect-launch", dir = config.base_dir));
+    state.bisect = Some(bisect);
+    state.save(&base_dir)
+}
+
+/// Loads the suspects `bisect` disabled again.
+fn restore(game_dir: &Path, bisect: &Bisect) -> Result<()> {
+    for key in bisect.suspects.iter() {
+        bisect::enable(game_dir, key)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::manifest::Mod;
+use crate::sync::settings::InstanceSettings;
+use crate::sync::state::State;
+use crate::sync::{self, backup, crash, jar};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Ids of the game and the loaders themselves, which reports name too.
+const PLATFORM_IDS: &[&str] = &["minecraft", "forge", "neoforge", "fabricloader", "fabric", "quilt_loader", "java", "mixinextras"];
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    /// `latest` or the path of the crash report to look at.
+    pub crash: String,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["crash"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let crash = parsed
+            .value("crash")
+            .ok_or_else(|| anyhow!("expected --crash with latest or the path of a crash report"))?
+            .to_string();
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            crash,
+        })
+    }
+}
+
+/// An entry of the modlist the crash report points at.
+struct Suspect<'a> {
+    entry: &'a Mod,
+    path: PathBuf,
+    /// The report names one of its mod ids or its jar.
+    named: Vec<String>,
+    /// Classes of the stack traces it holds.
+    frames: usize,
+}
+
+/// Reads a crash report of the instance, maps the mods it implicates to
+/// entries of the modlist by the ids and classes of their jars, and
+/// suggests which to roll back or disable, most implicated first.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+    let report = match config.crash.as_str() {
+        "latest" => crash::latest(&game_dir)
+            .ok_or_else(|| anyhow!("no crash reports in {}", game_dir.display()))?,
+        path => PathBuf::from(path),
+    };
+    let clues = crash::read(&report)?;
+    println!("[INFO] {}", t!("blame-reading", report = report.display()));
+
+    let effective = sync::load_effective(&base_dir, &config.mods_file)?;
+    let mut suspects = Vec::new();
+    let mut accounted: Vec<String> = Vec::new();
+    for entry in effective.mods.iter() {
+        let path = entry.install_path(&game_dir, &mods_dir)?;
+        let Some(filename) = path.file_name().map(|n| n.to_string_lossy().to_string()) else {
+            continue;
+        };
+        if !path.is_file() || !filename.ends_with(".jar") {
+            continue;
+        }
+        let ids = jar::mod_ids(&path).unwrap_or_default();
+        let bundled = jar::bundled_ids(&path).unwrap_or_default();
+        let mut named: Vec<String> = ids.iter().filter(|id| clues.mod_ids.contains(*id)).cloned().collect();
+        // a library bundled in the jar is loaded as a mod of its own
+        named.extend(bundled.iter().filter(|id| clues.mod_ids.contains(*id)).map(|id| format!("{} (bundled)", id)));
+        if clues.jars.contains(&filename) {
+            named.push(filename.clone());
+        }
+        let frames = match clues.classes.is_empty() {
+            true => 0,
+            false => jar::classes(&path).map(|c| clues.classes.iter().filter(|f| c.contains(*f)).count()).unwrap_or(0),
+        };
+        accounted.extend(ids);
+        accounted.extend(bundled);
+        accounted.push(filename);
+        if !named.is_empty() || frames > 0 {
+            suspects.push(Suspect { entry, path, named, frames });
+        }
+    }
+    suspects.sort_by_key(|s| std::cmp::Reverse((s.named.len(), s.frames)));
+
+    for id in clues.mod_ids.iter().chain(clues.jars.iter()) {
+        if !accounted.contains(id) && !PLATFORM_IDS.contains(&id.as_str()) {
+            println!("[WARN] {}", t!("blame-unknown-mod", id = id));
+        }
+    }
+    if suspects.is_empty() {
+        println!("[INFO] {}", t!("blame-none"));
+        return Ok(());
+    }
+
+    let state = State::load(&base_dir)?;
+    let backups = backup::list(&base_dir, backup::MODS)?;
+    for suspect in suspects.iter() {
+        let mut why = Vec::new();
+        if !suspect.named.is_empty() {
+            why.push(format!("named as {}", suspect.named.join(", ")));
+        }
+        if suspect.frames > 0 {
+            why.push(format!("{} of its classes in the stack traces", suspect.frames));
+        }
+        println!("[WARN] {} ({}): {}", suspect.entry.name, suspect.entry.filename, why.join(", "));
+        println!("       {}", suggestion(suspect, &state, &game_dir, &backups, &config.base_dir));
+    }
+    Ok(())
+}
+
+/// Rolling back when a backup from before the file was installed exists,
+/// disabling otherwise.
+fn suggestion(suspect: &Suspect, state: &State, game_dir: &Path, backups: &[PathBuf], base_dir: &str) -> String {
+    let installed_at = state.get(game_dir, &suspect.path).map(|m| m.installed_at);
+    let before = installed_at.and_then(|installed_at| {
+        backups.iter().rev().find(|b| {
+            let taken = fs::metadata(b).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from);
+            taken.is_some_and(|taken| taken <= installed_at)
+        })
+    });
+    match (installed_at, before) {
+        (Some(installed_at), Some(backup)) => format!(
+            "roll back: it was installed {}, `modpack-sync restore-backup {} {}` puts back the files from before",
+            installed_at.format("%Y-%m-%d %H:%M UTC"),
+            base_dir,
+            backup.file_name().unwrap_or_default().to_string_lossy()
+        ),
+        _ => match suspect.entry.tags.first() {
+            Some(tag) => format!("disable it: `modpack-sync sync {} --without {}`", base_dir, tag),
+            None => format!("disable it: `modpack-sync remove {} {}`", base_dir, suspect.entry.name),
+        },
+    }
+}
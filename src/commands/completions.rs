@@ -0,0 +1,165 @@
+use crate::cli::COMMANDS;
+use anyhow::{anyhow, Result};
+
+const BIN: &str = "modpack-sync";
+
+pub struct Config {
+    pub shell: String,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let shell = args
+            .get(1)
+            .ok_or_else(|| anyhow!("expected a shell: bash, zsh, fish or powershell"))?;
+        Ok(Config { shell: shell.clone() })
+    }
+}
+
+pub fn run(config: Config) -> Result<()> {
+    let script = match config.shell.as_str() {
+        "bash" => bash(),
+        "zsh" => zsh(),
+        "fish" => fish(),
+        "powershell" | "pwsh" => powershell(),
+        other => return Err(anyhow!("unsupported shell {}, expected bash, zsh, fish or powershell", other)),
+    };
+    print!("{}", script);
+    Ok(())
+}
+
+fn names() -> String {
+    COMMANDS.iter().map(|c| c.name).collect::<Vec<_>>().join(" ")
+}
+
+fn bash() -> String {
+    let mut cases = String::new();
+    for command in COMMANDS.iter().filter(|c| !c.flags.is_empty()) {
+        let flags: Vec<String> = command.flags.iter().map(|f| format!("--{}", f.name)).collect();
+        cases.push_str(&format!("        {}) opts=\"{}\" ;;\n", command.name, flags.join(" ")));
+    }
+
+    format!(
+        r#"_modpack_sync() {{
+    local cur opts
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    if [ "$COMP_CWORD" -eq 1 ]; then
+        COMPREPLY=($(compgen -W "{names}" -- "$cur"))
+        return
+    fi
+    opts=""
+    case "${{COMP_WORDS[1]}}" in
+{cases}    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "$opts" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}}
+complete -o filenames -F _modpack_sync {bin}
+"#,
+        names = names(),
+        cases = cases,
+        bin = BIN
+    )
+}
+
+/// `text` to put between single quotes of a POSIX-like shell, fish
+/// included: a quote ends the quoted text, is escaped and starts it again.
+fn quoted(text: &str) -> String {
+    text.replace('\'', "'\\''")
+}
+
+fn zsh() -> String {
+    let mut commands = String::new();
+    let mut cases = String::new();
+    for command in COMMANDS {
+        commands.push_str(&format!("        '{}:{}'\n", command.name, quoted(command.about)));
+        if command.flags.is_empty() {
+            continue;
+        }
+        let flags: Vec<String> = command
+            .flags
+            .iter()
+            // brackets would end the description early
+            .map(|f| format!("'--{}[{}]'", f.name, quoted(&f.help.replace('[', "\\[").replace(']', "\\]"))))
+            .collect();
+        cases.push_str(&format!(
+            "        {}) _arguments {} '*:file:_files' ;;\n",
+            command.name,
+            flags.join(" ")
+        ));
+    }
+
+    format!(
+        r#"#compdef {bin}
+
+_modpack_sync() {{
+    local -a commands
+    commands=(
+{commands}    )
+    if (( CURRENT == 2 )); then
+        _describe 'command' commands
+        return
+    fi
+    case "$words[2]" in
+{cases}        *) _files ;;
+    esac
+}}
+
+compdef _modpack_sync {bin}
+"#,
+        bin = BIN,
+        commands = commands,
+        cases = cases
+    )
+}
+
+fn fish() -> String {
+    let mut script = format!("complete -c {} -f\n", BIN);
+    for command in COMMANDS {
+        script.push_str(&format!(
+            "complete -c {} -n __fish_use_subcommand -a {} -d '{}'\n",
+            BIN, command.name, quoted(command.about)
+        ));
+        for flag in command.flags {
+            script.push_str(&format!(
+                "complete -c {} -n '__fish_seen_subcommand_from {}' -l {} -d '{}'\n",
+                BIN, command.name, flag.name, quoted(flag.help)
+            ));
+        }
+    }
+    script.push_str(&format!("complete -c {} -n 'not __fish_use_subcommand' -F\n", BIN));
+    script
+}
+
+fn powershell() -> String {
+    let mut cases = String::new();
+    for command in COMMANDS.iter().filter(|c| !c.flags.is_empty()) {
+        // PowerShell doubles quotes inside single quotes
+        let flags: Vec<String> = command.flags.iter().map(|f| format!("'--{}'", f.name.replace('\'', "''"))).collect();
+        cases.push_str(&format!("        '{}' {{ @({}) }}\n", command.name.replace('\'', "''"), flags.join(", ")));
+    }
+    let commands: Vec<String> = COMMANDS.iter().map(|c| format!("'{}'", c.name.replace('\'', "''"))).collect();
+
+    format!(
+        r#"Register-ArgumentCompleter -Native -CommandName {bin} -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $elements = $commandAst.CommandElements
+    if ($elements.Count -le 2 -and -not $wordToComplete.StartsWith('-')) {{
+        $candidates = @({commands})
+    }} else {{
+        $candidates = switch ($elements[1].Value) {{
+{cases}        default {{ @() }}
+        }}
+    }}
+    $candidates | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }}
+}}
+"#,
+        bin = BIN,
+        commands = commands.join(", "),
+        cases = cases
+    )
+}
@@ -0,0 +1,68 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::control::{self, Request};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::path::Path;
+
+pub struct Config {
+    pub base_dir: String,
+    /// The request as sent, a command and optionally an instance or `all`.
+    pub request: String,
+    /// Print the daemon's answer as it is.
+    pub json: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["json"], &[])?;
+        let (base_dir, request) = match parsed.positional.as_slice() {
+            [base_dir, request @ ..] if !request.is_empty() => (base_dir.clone(), request.join(" ")),
+            _ => {
+                return Err(anyhow!(
+                    "usage: modpack-sync control <instance dir> <trigger-sync|get-status|pause|resume|reload-config|rollback> [instance|all]"
+                ))
+            }
+        };
+        // refused here rather than by the daemon, with the same message
+        Request::parse(&request)?;
+
+        Ok(Config {
+            base_dir,
+            request,
+            json: parsed.has("json"),
+        })
+    }
+}
+
+/// Sends a request to the daemon watching the instance and prints its
+/// answer. Fails when the daemon could not carry it out.
+pub fn run(config: Config) -> Result<()> {
+    let answer = control::send(Path::new(&config.base_dir), &config.request)?;
+    if config.json {
+        println!("{}", answer);
+    }
+    if answer["ok"] != Value::Bool(true) {
+        return Err(anyhow!("{}", answer["error"].as_str().unwrap_or("the daemon refused the request")));
+    }
+    if config.json {
+        return Ok(());
+    }
+    for instance in answer["instances"].as_array().into_iter().flatten() {
+        let state = match (instance["running"].as_bool(), instance["paused"].as_bool()) {
+            (Some(true), _) => "checking now".to_string(),
+            (_, Some(true)) => "paused".to_string(),
+            _ => match instance["next_check_seconds"].as_u64() {
+                Some(seconds) => format!("next check in {} minutes", seconds.div_ceil(60)),
+                None => "idle".to_string(),
+            },
+        };
+        println!("[INFO] {}: {}", instance["dir"].as_str().unwrap_or_default(), state);
+        match (instance["last_check"].as_str(), instance["last_error"].as_str()) {
+            (Some(at), Some(error)) => println!("       {}", t!("control-check-failed", at = at, error = error)),
+            (Some(at), None) => println!("       {}", t!("control-check-fine", at = at)),
+            (None, _) => println!("       {}", t!("control-never")),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::curse_files;
+use crate::sync::launcher;
+use crate::sync::manifest::{self, Manifest, PackHeader};
+use crate::sync::settings::{InstanceSettings, Side};
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: PathBuf,
+    pub mods_file: String,
+    pub name: Option<String>,
+    pub minecraft: Option<String>,
+    /// Loader name, optionally followed by `-<version>`.
+    pub loader: Option<String>,
+    pub side: Side,
+    /// Make the instance a Prism instance and list it in Prism.
+    pub register: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["server", "register"], &["dir", "mc", "loader", "name"])?;
+        let base_dir = parsed
+            .value("dir")
+            .or(parsed.positional.first().map(String::as_str))
+            .ok_or_else(|| anyhow!("expected --dir with the directory of the new instance"))?;
+        let loader = parsed.value("loader").map(str::to_string);
+        if let Some(loader) = &loader {
+            let name = loader.split_once('-').map_or(loader.as_str(), |(name, _)| name);
+            if curse_files::loader_type(name).is_none() {
+                return Err(anyhow!("unknown loader {}, expected fabric, quilt, forge or neoforge", name));
+            }
+        }
+        let side = match parsed.has("server") {
+            true => Side::Server,
+            false => Side::Client,
+        };
+        if side == Side::Server && parsed.has("register") {
+            return Err(anyhow!("--register is for client instances, servers aren't run from a launcher"));
+        }
+
+        Ok(Config {
+            base_dir: PathBuf::from(base_dir),
+            mods_file: "modlist.json".to_string(),
+            name: parsed.value("name").map(str::to_string),
+            minecraft: parsed.value("mc").map(str::to_string),
+            loader,
+            side,
+            register: parsed.has("register"),
+        })
+    }
+}
+
+/// Lays out a new instance: the settings, the game directory with its `mods`
+/// and `config` directories and a modlist with the pack header, then with
+/// `--register` lists it in Prism.
+pub fn run(config: Config) -> Result<()> {
+    let modlist = config.base_dir.join(&config.mods_file);
+    if modlist.exists() {
+        return Err(anyhow!("{} is an instance already, it has a {}", config.base_dir.display(), config.mods_file));
+    }
+    fs::create_dir_all(&config.base_dir)?;
+    let name = match config.name {
+        Some(name) => name,
+        None => config
+            .base_dir
+            .canonicalize()?
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "modpack".to_string()),
+    };
+
+    let mut instance = InstanceSettings::load(&config.base_dir)?;
+    instance.side = config.side;
+    instance.save(&config.base_dir)?;
+    // a new client instance is laid out the way Prism keeps it
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir.to_string_lossy()));
+    for dir in ["mods", "config"] {
+        fs::create_dir_all(game_dir.join(dir))?;
+    }
+
+    let manifest = Manifest {
+        format_version: manifest::FORMAT_VERSION,
+        extends: None,
+        pack: PackHeader {
+            name: name.clone(),
+            version: "0.1.0".to_string(),
+            minecraft: config.minecraft,
+            loader: config.loader,
+            ..Default::default()
+        },
+        remove: Vec::new(),
+        mods: Vec::new(),
+    };
+    manifest::save(&modlist, &manifest)?;
+    println!("[INFO] {}", t!("create-created", name = name, side = config.side.name(), dir = config.base_dir.display()));
+
+    if config.register {
+        match launcher::prism_instances() {
+            Some(instances) => {
+                let missing_loader = launcher::register_prism(&config.base_dir, &name, &manifest.pack, &instances)?;
+                println!("[INFO] {}", t!("create-registered", name = name, dir = instances.display()));
+                if missing_loader {
+                    println!("[WARN] {}", t!("create-loader-version"));
+                }
+            }
+            None => println!("[WARN] {}", t!("create-no-prism")),
+        }
+    }
+    println!("[INFO] {}", t!("create-next", modlist = modlist.display(), dir = config.base_dir.display()));
+    Ok(())
+}
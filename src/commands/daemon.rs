@@ -0,0 +1,629 @@
+use crate::sync::backup;
+use crate::sync::control::{self, Access, Incoming, Target};
+use crate::sync::dashboard;
+use crate::sync::log::console;
+use crate::sync::schedule::Schedule;
+use crate::sync::notify::{self, Notification, Notifier};
+use crate::sync::settings::{self, DaemonSettings, InstanceSettings, OutsideWindow, RestartSettings, Scope, Side};
+use crate::sync::stamp;
+use crate::sync::state::State;
+use crate::sync::{self, dist, rcon, restart, Mode};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Instances synced at once when several are watched.
+const DEFAULT_WORKERS: usize = 2;
+
+pub struct Config {
+    pub instances: Vec<Instance>,
+    /// Check once and exit instead of running forever, for cron or timers.
+    pub once: bool,
+    /// Apply pending updates right away, whatever the windows and players
+    /// online.
+    pub now: bool,
+    /// Instances checked and synced at the same time.
+    pub workers: usize,
+    /// Address the web dashboard is served on, e.g. `127.0.0.1:8991`.
+    pub dashboard: Option<String>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let mut dirs = Vec::new();
+        let mut flags = Vec::new();
+        let mut once = false;
+        let mut now = false;
+        let mut workers = None;
+        let mut dashboard = None;
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            let short = arg.strip_prefix('-').filter(|f| f.len() == 1);
+            let Some(flag) = arg.strip_prefix("--").or(short) else {
+                dirs.push(arg.clone());
+                continue;
+            };
+            let (name, inline) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (flag, None),
+            };
+            let takes_value = matches!(name, "workers" | "dashboard") || sync::OPTIONS.contains(&name);
+            let value = match (takes_value, inline) {
+                (true, None) => iter.next().cloned(),
+                (_, inline) => inline,
+            };
+            match name {
+                "once" => once = true,
+                "now" => now = true,
+                "workers" => workers = value,
+                "dashboard" => dashboard = value,
+                _ => {
+                    flags.push(arg.clone());
+                    if takes_value && !arg.contains('=') {
+                        flags.extend(value);
+                    }
+                }
+            }
+        }
+        if dirs.is_empty() {
+            return Err(anyhow!("expected argument containing path to modpack"));
+        }
+        // a bare port is served to this machine only
+        let dashboard = dashboard.map(|d| match d.parse::<u16>() {
+            Ok(port) => format!("127.0.0.1:{}", port),
+            Err(_) => d,
+        });
+        let workers = match workers {
+            Some(workers) => workers
+                .parse::<usize>()
+                .ok()
+                .filter(|w| *w > 0)
+                .ok_or_else(|| anyhow!("--workers expects a positive number, got {}", workers))?,
+            None => DEFAULT_WORKERS,
+        };
+        let instances = dirs
+            .iter()
+            .map(|dir| {
+                let mut sync_args = vec![args[0].clone(), dir.clone()];
+                sync_args.extend(flags.iter().cloned());
+                Instance::build(sync_args)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Config {
+            instances,
+            once,
+            now,
+            workers,
+            dashboard,
+        })
+    }
+}
+
+/// An instance the daemon watches, with its own schedule, restart and
+/// notifications from its settings.
+pub struct Instance {
+    /// Arguments of the sync run whenever an update is applied, everything
+    /// but the daemon's own flags.
+    pub sync_args: Vec<String>,
+    pub base_dir: String,
+    pub game_dir: String,
+    pub settings: DaemonSettings,
+    pub schedule: Schedule,
+    /// What clients of the dashboard may do with the instance.
+    pub access: Access,
+    /// Restart sequence run after every applied update of a server.
+    pub restart: Option<RestartSettings>,
+    /// Players a server may have online for an update to go ahead.
+    pub max_players: Option<usize>,
+    /// Where summaries of applied updates and failures are sent.
+    pub notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Instance {
+    fn build(sync_args: Vec<String>) -> Result<Instance> {
+        let sync = sync::Config::build(&sync_args)?;
+        if sync.modlist.is_some() {
+            return Err(anyhow!("the daemon syncs the instance's source, --modlist can't be used with it"));
+        }
+        if sync.source.is_none() {
+            return Err(anyhow!(
+                "{} has no source to watch, set source in its modpack-sync.toml",
+                sync.base_dir
+            ));
+        }
+        let instance = InstanceSettings::load(Path::new(&sync.base_dir))?;
+        let settings = instance.daemon.unwrap_or_default();
+        let schedule = Schedule::parse(&settings).with_context(|| format!("invalid daemon settings of {}", sync.base_dir))?;
+        let access = Access::load(&settings).with_context(|| format!("invalid daemon settings of {}", sync.base_dir))?;
+        let max_players = match (settings.max_players, instance.side) {
+            (Some(_), Side::Client) => {
+                console!("[WARN] max_players is only supported for server instances, ignoring it");
+                None
+            }
+            (max, _) => max,
+        };
+        let restart = match (instance.restart, instance.side) {
+            (Some(restart), Side::Server) => Some(restart),
+            (Some(_), Side::Client) => {
+                console!("[WARN] restart is only supported for server instances, ignoring it");
+                None
+            }
+            (None, _) => None,
+        };
+
+        Ok(Instance {
+            sync_args,
+            base_dir: sync.base_dir,
+            game_dir: sync.game_dir,
+            settings,
+            schedule,
+            access,
+            restart,
+            max_players,
+            notifiers: match &instance.notify {
+                Some(settings) => notify::notifiers(settings)?,
+                None => Vec::new(),
+            },
+        })
+    }
+}
+
+/// Watches the sources of the instances and syncs whenever a new version
+/// of a pack is published, holding updates found outside the maintenance
+/// windows until the next one opens. Each instance is checked at its own
+/// interval, `--workers` of them at a time. Unless checking once, the
+/// daemon listens on a control socket in the state directory of every
+/// instance, see [`control`], and serves the `--dashboard`.
+pub fn run(config: Config) -> Result<()> {
+    if let (true, [instance]) = (config.once, config.instances.as_slice()) {
+        return check(instance, config.now);
+    }
+    let single = config.instances.len() == 1;
+    let exe = std::env::current_exe().context("failed to find the modpack-sync executable")?;
+    let mut watched: Vec<Watched> = config
+        .instances
+        .into_iter()
+        .map(|instance| Watched {
+            instance: Arc::new(instance),
+            due: Some(Instant::now()),
+            paused: false,
+            queued: config.now.then_some(Task::Check { now: true }),
+            last: None,
+        })
+        .collect();
+    let (events, incoming) = mpsc::channel::<Event>();
+    if !config.once {
+        let (requests, forwarded) = mpsc::channel::<Incoming>();
+        for (index, w) in watched.iter().enumerate() {
+            if let Err(e) = control::listen(Path::new(&w.instance.base_dir), index, requests.clone()) {
+                console!("[WARN] no control socket for {}: {:#}", w.instance.base_dir, e);
+            }
+        }
+        if let Some(addr) = &config.dashboard {
+            let listener = TcpListener::bind(addr).with_context(|| format!("failed to listen on {}", addr))?;
+            let remote = listener.local_addr().is_ok_and(|a| !a.ip().is_loopback());
+            if remote && !watched.iter().any(|w| w.instance.access.has_tokens()) {
+                return Err(anyhow!(
+                    "the dashboard at {} could be reached from other machines and no instance has tokens, set tokens in the daemon settings or listen on 127.0.0.1",
+                    addr
+                ));
+            }
+            dashboard::serve(listener, requests.clone());
+            console!("[INFO] dashboard at http://{}/", addr);
+        }
+        let events = events.clone();
+        thread::spawn(move || {
+            for request in forwarded {
+                if events.send(Event::Control(request)).is_err() {
+                    return;
+                }
+            }
+        });
+        match watched.as_slice() {
+            [w] => console!("[INFO] watching {} every {} minutes", w.instance.base_dir, w.instance.settings.interval),
+            _ => console!("[INFO] watching {} instances, {} at a time", watched.len(), config.workers),
+        }
+    }
+
+    let (jobs, queue) = mpsc::channel::<Job>();
+    let queue = Mutex::new(queue);
+    thread::scope(|s| {
+        for _ in 0..config.workers.min(watched.len()) {
+            let (queue, events, exe) = (&queue, events.clone(), &exe);
+            s.spawn(move || loop {
+                let next = queue.lock().expect("queue lock poisoned").recv();
+                let Ok(job) = next else {
+                    return;
+                };
+                // a sync's log and warnings belong to its process, several
+                // instances are checked in processes of their own
+                let result = match (job.task, single) {
+                    (Task::Rollback, _) => rollback(&job.instance),
+                    (Task::Check { now }, true) => check(&job.instance, now),
+                    (Task::Check { now }, false) => check_apart(exe, &job.instance.base_dir, &job.instance.sync_args[1..], now),
+                };
+                let _ = events.send(Event::Finished(job.index, result));
+            });
+        }
+
+        let mut running = 0;
+        let mut failed = 0;
+        loop {
+            let now = Instant::now();
+            for (index, w) in watched.iter_mut().enumerate() {
+                let task = match w.due {
+                    // running
+                    None => None,
+                    Some(_) if w.queued.is_some() => w.queued.take(),
+                    Some(at) if at <= now && !w.paused => Some(Task::Check { now: false }),
+                    Some(_) => None,
+                };
+                if let Some(task) = task {
+                    w.due = None;
+                    running += 1;
+                    let job = Job { index, instance: w.instance.clone(), task };
+                    jobs.send(job).expect("the workers outlive the scheduler");
+                }
+            }
+            if config.once && running == 0 {
+                break;
+            }
+            let wait = watched
+                .iter()
+                .filter(|w| !w.paused)
+                .filter_map(|w| w.due)
+                .min()
+                .map_or(Duration::from_secs(3600), |at| at.saturating_duration_since(now));
+            let event = match incoming.recv_timeout(wait) {
+                std::result::Result::Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            match event {
+                Event::Finished(index, result) => {
+                    running -= 1;
+                    let w = &mut watched[index];
+                    if let Err(e) = &result {
+                        failed += 1;
+                        console!("[WARN] {} check failed: {:#}", Local::now().format("%Y-%m-%d %H:%M"), e);
+                    }
+                    w.last = Some((Local::now(), result.map_err(|e| format!("{:#}", e))));
+                    if !config.once {
+                        w.due = Some(next_check(&w.instance));
+                    }
+                }
+                Event::Control(incoming) => {
+                    let answer = answer(&mut watched, &incoming);
+                    // the files of the instances are read on a thread of its
+                    // own, so a slow disk doesn't hold up the schedule
+                    thread::spawn(move || {
+                        let _ = incoming.reply.send(with_files(answer));
+                    });
+                }
+            }
+        }
+        drop(jobs);
+        match failed {
+            0 => Ok(()),
+            n => Err(anyhow!("{} of {} instance checks failed", n, watched.len())),
+        }
+    })
+}
+
+/// An instance as the scheduler keeps track of it.
+struct Watched {
+    instance: Arc<Instance>,
+    /// When it is checked next, none while a task of it runs.
+    due: Option<Instant>,
+    paused: bool,
+    /// Asked for through the control socket, run as soon as nothing else of
+    /// the instance runs.
+    queued: Option<Task>,
+    /// When the last task finished and its error.
+    last: Option<(DateTime<Local>, std::result::Result<(), String>)>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Task {
+    /// `now` applies pending updates whatever the windows and players.
+    Check { now: bool },
+    Rollback,
+}
+
+enum Event {
+    Finished(usize, Result<()>),
+    Control(Incoming),
+}
+
+struct Job {
+    index: usize,
+    instance: Arc<Instance>,
+    task: Task,
+}
+
+fn interval(instance: &Instance) -> Duration {
+    Duration::from_secs(instance.settings.interval.max(1) * 60)
+}
+
+/// When the instance is checked next: after its interval, or as its next
+/// maintenance window opens when that comes first.
+fn next_check(instance: &Instance) -> Instant {
+    let interval = interval(instance);
+    let opening = instance
+        .schedule
+        .next_opening(Utc::now())
+        .and_then(|at| (at - Utc::now()).to_std().ok());
+    Instant::now() + opening.filter(|wait| *wait < interval).unwrap_or(interval)
+}
+
+/// Carries out a control request, answering with the instances it was
+/// about.
+fn answer(watched: &mut [Watched], incoming: &Incoming) -> Value {
+    let selected: Vec<usize> = match &incoming.request.target {
+        Target::Own => (incoming.instance < watched.len()).then_some(incoming.instance).into_iter().collect(),
+        Target::All => (0..watched.len()).collect(),
+        Target::Named(name) => watched
+            .iter()
+            .position(|w| {
+                let dir = Path::new(&w.instance.base_dir);
+                w.instance.base_dir == *name || dir.file_name().is_some_and(|n| n.to_string_lossy() == *name)
+            })
+            .into_iter()
+            .collect(),
+    };
+    if selected.is_empty() {
+        return control::failure("the daemon doesn't watch that instance");
+    }
+    let needed = match incoming.request.command {
+        control::Command::Status => Scope::Read,
+        _ => Scope::Operator,
+    };
+    let allowed: Vec<(usize, Scope)> = selected
+        .into_iter()
+        .filter_map(|index| Some((index, watched[index].instance.access.scope(&incoming.caller).filter(|s| *s >= needed)?)))
+        .collect();
+    if allowed.is_empty() {
+        return control::denied();
+    }
+    let mut instances = Vec::new();
+    for (index, scope) in allowed {
+        let w = &mut watched[index];
+        match incoming.request.command {
+            control::Command::TriggerSync => {
+                if w.queued != Some(Task::Rollback) {
+                    w.queued = Some(Task::Check { now: true });
+                }
+            }
+            control::Command::Pause => w.paused = true,
+            control::Command::Resume => w.paused = false,
+            control::Command::ReloadConfig => match Instance::build(w.instance.sync_args.clone()) {
+                // a running check finishes with the settings it started with
+                Ok(instance) => {
+                    if w.due.is_some() {
+                        w.due = Some(next_check(&instance));
+                    }
+                    w.instance = Arc::new(instance);
+                }
+                Err(e) => return control::failure(&format!("{}: {:#}", w.instance.base_dir, e)),
+            },
+            control::Command::Rollback => {
+                if w.due.is_none() {
+                    return control::failure(&format!("{} is being checked, roll it back once that finished", w.instance.base_dir));
+                }
+                // the update rolled back would be applied again at once
+                w.paused = true;
+                w.queued = Some(Task::Rollback);
+            }
+            control::Command::Status => {}
+        }
+        instances.push(status(index, w, scope));
+    }
+    json!({ "ok": true, "instances": instances })
+}
+
+/// Restores the newest backup of the instance's managed files, as
+/// `restore-backup` does.
+fn rollback(instance: &Instance) -> Result<()> {
+    let base_dir = Path::new(&instance.base_dir);
+    let backups = backup::list(base_dir, backup::MODS)?;
+    let archive = backups
+        .last()
+        .ok_or_else(|| anyhow!("{} has no backups to roll back to, turn on backup in its modpack-sync.toml", instance.base_dir))?;
+    let settings = InstanceSettings::load(base_dir)?;
+    let game_dir = settings.game_dir(&instance.base_dir);
+    let mods_dir = settings.mods_dir(&instance.base_dir);
+    let restored = backup::restore(base_dir, Path::new(&game_dir), Path::new(&mods_dir), archive)?;
+    console!("[INFO] rolled {} back, restored {} files from {}", instance.base_dir, restored, archive.display());
+    Ok(())
+}
+
+/// What the scheduler knows of an instance, [`with_files`] adds what its
+/// files tell.
+fn status(index: usize, w: &Watched, scope: Scope) -> Value {
+    let (last_check, last_error) = match &w.last {
+        Some((at, result)) => (Some(at.to_rfc3339()), result.as_ref().err().cloned()),
+        None => (None, None),
+    };
+    json!({
+        "index": index,
+        "dir": w.instance.base_dir,
+        "game_dir": w.instance.game_dir,
+        "scope": scope.name(),
+        "paused": w.paused,
+        "running": w.due.is_none(),
+        "queued": match w.queued {
+            Some(Task::Check { .. }) => Some("trigger-sync"),
+            Some(Task::Rollback) => Some("rollback"),
+            None => None,
+        },
+        "next_check_seconds": w.due.map(|at| at.saturating_duration_since(Instant::now()).as_secs()),
+        "interval_minutes": w.instance.settings.interval,
+        "last_check": last_check,
+        "last_error": last_error,
+    })
+}
+
+/// `answer` with the installed and pending version, retry queue and
+/// backups of each instance in it, read from their files.
+fn with_files(mut answer: Value) -> Value {
+    for instance in answer["instances"].as_array_mut().into_iter().flatten() {
+        let (Some(base_dir), Some(game_dir)) = (instance["dir"].as_str(), instance["game_dir"].as_str()) else {
+            continue;
+        };
+        let base_dir = Path::new(base_dir).to_path_buf();
+        let stamp = fs::read_to_string(Path::new(game_dir).join(stamp::FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str::<Value>(&s).ok());
+        let state = State::load(&base_dir).ok();
+        instance["pack_version"] = json!(stamp.as_ref().and_then(|s| s["version"].as_str().map(str::to_string)));
+        instance["pending_version"] = json!(state.as_ref().and_then(|s| s.announced.clone()));
+        instance["retry_queue"] = json!(state.map(|s| s.retry.into_keys().collect::<Vec<_>>()).unwrap_or_default());
+        instance["backups"] = json!(backup::list(&base_dir, backup::MODS).map_or(0, |b| b.len()));
+    }
+    answer
+}
+
+/// Runs one check of the instance in `base_dir` as `daemon --once` with
+/// `args` in a child process, passing its output on prefixed with the
+/// instance's name. The download cache is shared through the user's cache
+/// directory.
+fn check_apart(exe: &Path, base_dir: &str, args: &[String], now: bool) -> Result<()> {
+    let name = Path::new(base_dir)
+        .file_name()
+        .map_or_else(|| base_dir.to_string(), |n| n.to_string_lossy().to_string());
+    let mut child = Command::new(exe)
+        .arg("daemon")
+        .arg("--once")
+        .args(now.then_some("--now"))
+        .args(args)
+        .env("MODPACK_SYNC_NO_UPDATE_CHECK", "1")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to start the check of {}", base_dir))?;
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    thread::scope(|s| {
+        s.spawn(|| forward(stdout, &name));
+        forward(stderr, &name);
+    });
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("the check of {} exited with {}", base_dir, status));
+    }
+    Ok(())
+}
+
+fn forward(output: Option<impl Read>, name: &str) {
+    for line in output.into_iter().flat_map(|o| BufReader::new(o).lines().map_while(std::result::Result::ok)) {
+        console!("[{}] {}", name, line);
+    }
+}
+
+/// Installs the entries of the retry queue when no update is pending, in
+/// the maintenance window unless `now`, restarting the server when any of
+/// them made it.
+fn retry(config: &Instance, mut sync: sync::Config, now: bool) -> Result<()> {
+    let queued = State::load(Path::new(&config.base_dir))?.retry.len();
+    if queued == 0 || !(now || config.schedule.allows(Utc::now())) {
+        return Ok(());
+    }
+    console!("[INFO] {} retrying {} entries that failed before", Local::now().format("%Y-%m-%d %H:%M"), queued);
+    sync.mode = Mode::Retry;
+    let game_dir = sync.game_dir.clone();
+    let (run, mut result) = sync::execute(sync);
+    if let (Ok(()), Some(settings), false) = (&result, &config.restart, run.installed.is_empty()) {
+        result = restart::run(Path::new(&game_dir), settings).context("the failed entries were installed but restarting the server failed");
+    }
+    result
+}
+
+/// Applies a pending update when the schedule and the players online allow
+/// it. Held updates are announced once, the instance's state remembers the
+/// version announced. `now` applies it whatever the windows and players.
+fn check(config: &Instance, now: bool) -> Result<()> {
+    let sync = sync::Config::build(&config.sync_args)?;
+    let source = sync.source.as_deref().ok_or_else(|| anyhow!("the instance no longer has a source"))?;
+    let mut backend = dist::Backend::parse(source)?;
+    if let Some(channel) = &sync.channel {
+        backend = backend.channel(channel);
+    }
+    let Some(version) = dist::pending(Path::new(&config.base_dir), &backend, sync.channel.as_deref())? else {
+        return retry(config, sync, now);
+    };
+
+    let base_dir = Path::new(&config.base_dir);
+    let mut state = State::load(base_dir)?;
+    let found_at = match &state.found {
+        Some((found, at)) if *found == version => *at,
+        _ => {
+            state.found = Some((version.clone(), Utc::now()));
+            state.save(base_dir)?;
+            Utc::now()
+        }
+    };
+    let min_age = InstanceSettings::load(base_dir)?.updates.map_or(0, |u| u.min_age_hours);
+
+    let forced = now;
+    let now = Local::now().format("%Y-%m-%d %H:%M");
+    let held = match config.max_players {
+        _ if forced => None,
+        _ if Utc::now() - found_at < settings::hours(min_age) => Some(format!("it is applied once it was out for {} hours", min_age)),
+        _ if !config.schedule.allows(Utc::now()) => Some("it is applied in the next maintenance window".to_string()),
+        Some(max) => {
+            let restart = config.restart.as_ref();
+            let online = rcon::connect_server(
+                Path::new(&sync.game_dir),
+                restart.and_then(|r| r.rcon.as_deref()),
+                restart.and_then(|r| r.password.as_deref()),
+            )
+            .and_then(|mut rcon| rcon.players())
+            .context("failed to check the players online")?;
+            (online > max).then(|| format!("waiting for the server to empty out, {} players are online", online))
+        }
+        None => None,
+    };
+    if let Some(reason) = held {
+        let new = state.announced.as_deref() != Some(version.as_str());
+        if new && config.settings.outside_window == OutsideWindow::Notify {
+            console!("[INFO] {} pack {} is available, {}", now, version, reason);
+        }
+        if new && config.settings.prefetch {
+            let mut prefetch = sync;
+            prefetch.mode = Mode::Prefetch;
+            if let Err(e) = sync::run(prefetch) {
+                console!("[WARN] {} failed to prefetch pack {}: {:#}", now, version, e);
+            }
+        }
+        if new {
+            state.announced = Some(version);
+            state.save(base_dir)?;
+        }
+        return Ok(());
+    }
+
+    console!("[INFO] {} applying pack {}", now, version);
+    if state.announced.take().is_some() | state.found.take().is_some() {
+        state.save(base_dir)?;
+    }
+    let game_dir = sync.game_dir.clone();
+    let (run, mut result) = sync::execute(sync);
+    if let (Ok(()), Some(settings)) = (&result, &config.restart) {
+        result = restart::run(Path::new(&game_dir), settings).context("the pack was updated but restarting the server failed");
+    }
+    if !config.notifiers.is_empty() {
+        let notification = Notification::from_run(&config.base_dir, &run, &result);
+        if let Err(e) = notify::send(&config.notifiers, &notification) {
+            console!("[WARN] {:#}", e);
+        }
+    }
+    result
+}
@@ -0,0 +1,123 @@
+use crate::cli::Args;
+use crate::sync::launcher::Launcher;
+use crate::sync::settings::{self, InstanceSettings};
+use crate::sync::{self, deps};
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Dot,
+    Json,
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub format: Format,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["format"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let format = match parsed.value("format").unwrap_or("dot") {
+            "dot" => Format::Dot,
+            "json" => Format::Json,
+            other => return Err(anyhow!("unknown format {}, expected dot or json", other)),
+        };
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            format,
+        })
+    }
+}
+
+/// Prints the pack's dependency graph on stdout and reports missing required
+/// dependencies and orphaned libraries on stderr.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+    let effective = sync::load_effective(&base_dir, &config.mods_file)?;
+    let metadata = Launcher::detect(&base_dir).metadata(&base_dir, &mods_dir)?;
+    let api_key = settings::api_key()?;
+    let mut graph = deps::build(&effective.mods, &metadata, &api_key)?;
+    let jars: Vec<(String, PathBuf)> = effective
+        .mods
+        .iter()
+        .filter_map(|m| Some((m.filename.clone(), m.install_path(&game_dir, &mods_dir).ok().filter(|p| p.is_file())?)))
+        .collect();
+    graph.find_bundled(&jars, &api_key);
+
+    match config.format {
+        Format::Json => {
+            let value = serde_json::json!({
+                "graph": &graph,
+                "missing": graph.missing().iter().map(|(from, to)| serde_json::json!({"from": from, "to": to})).collect::<Vec<_>>(),
+                "incompatible": graph.incompatible().iter().map(|(from, to)| serde_json::json!({"from": from, "to": to})).collect::<Vec<_>>(),
+                "orphans": graph.orphans().iter().map(|n| &n.project_id).collect::<Vec<_>>(),
+            });
+            println!("{}", serde_json::to_string_pretty(&value)?);
+        }
+        Format::Dot => print_dot(&graph),
+    }
+
+    for (from, to) in graph.missing() {
+        eprintln!("[WARN] {} requires project {} which is not in the modlist", name_of(&graph, from), to);
+    }
+    for bundled in graph.bundled.iter() {
+        eprintln!("[INFO] project {} is not in the modlist but bundled in {}", bundled.project_id, bundled.bundled_in);
+    }
+    for (from, to) in graph.incompatible() {
+        eprintln!("[WARN] {} is declared incompatible with {}", name_of(&graph, from), name_of(&graph, to));
+    }
+    for orphan in graph.orphans() {
+        eprintln!("[WARN] library {} is not required by any mod in the pack", orphan.name);
+    }
+    for unresolved in graph.unresolved.iter() {
+        eprintln!("[WARN] could not look up {}, its dependencies are unknown", unresolved);
+    }
+    Ok(())
+}
+
+fn name_of<'a>(graph: &'a deps::Graph, project_id: &'a str) -> &'a str {
+    graph
+        .nodes
+        .iter()
+        .find(|n| n.project_id == project_id)
+        .map(|n| n.name.as_str())
+        .unwrap_or(project_id)
+}
+
+fn print_dot(graph: &deps::Graph) {
+    println!("digraph modpack {{");
+    println!("  rankdir=LR;");
+    for n in graph.nodes.iter() {
+        let shape = if n.library { "box" } else { "ellipse" };
+        println!("  \"{}\" [label=\"{}\", shape={}];", n.project_id, n.name.replace('"', "'"), shape);
+    }
+    for (_, to) in graph.missing() {
+        println!("  \"{}\" [label=\"missing {}\", color=red];", to, to);
+    }
+    for bundled in graph.bundled.iter() {
+        println!("  \"{}\" [label=\"bundled in {}\", shape=box, style=dashed];", bundled.project_id, bundled.bundled_in.replace('"', "'"));
+    }
+    for e in graph.edges.iter() {
+        let style = match e.relation {
+            "required" => "solid",
+            "optional" => "dashed",
+            "incompatible" => "dotted, color=red",
+            _ => continue,
+        };
+        println!("  \"{}\" -> \"{}\" [style={}];", e.from, e.to, style);
+    }
+    println!("}}");
+}
@@ -0,0 +1,75 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::report::human_size;
+use crate::sync::settings::InstanceSettings;
+use crate::sync::{backup, hash};
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Config {
+    pub base_dir: String,
+    /// Backup to compare against, as `restore-backup --list` names it.
+    pub snapshot: String,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["snapshot"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let snapshot = parsed
+            .value("snapshot")
+            .ok_or_else(|| anyhow!("expected --snapshot with the backup to compare against, see `restore-backup --list`"))?
+            .to_string();
+
+        Ok(Config { base_dir, snapshot })
+    }
+}
+
+/// Compares the files of the instance a backup takes with the ones it
+/// took, showing what the syncs since changed on disk.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let archive = backup::find(&base_dir, &config.snapshot)?;
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+
+    let then: BTreeMap<String, backup::Snapshotted> = backup::contents(&archive)?.into_iter().map(|f| (f.path.clone(), f)).collect();
+    let now: BTreeMap<String, PathBuf> = backup::snapshot_paths(&base_dir, &game_dir, &mods_dir)?
+        .into_iter()
+        .filter(|p| p.is_file())
+        .filter_map(|p| Some((p.strip_prefix(&game_dir).ok()?.to_string_lossy().replace('\\', "/"), p)))
+        .collect();
+    let size = |path: &Path| fs::metadata(path).map_or(0, |m| m.len());
+
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for (key, file) in then.iter() {
+        match now.get(key) {
+            None => {
+                println!("  - {} ({})", key, human_size(file.size));
+                removed += 1;
+            }
+            Some(path) if hash::sha256_file(path)? != file.sha256 => {
+                println!("  ~ {} ({} -> {})", key, human_size(file.size), human_size(size(path)));
+                changed += 1;
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, path) in now.iter().filter(|(key, _)| !then.contains_key(*key)) {
+        println!("  + {} ({})", key, human_size(size(path)));
+        added += 1;
+    }
+    let name = archive.file_name().unwrap_or_default().to_string_lossy();
+    match added + removed + changed {
+        0 => println!("[INFO] {}", t!("diff-same", backup = name)),
+        _ => println!("[INFO] {}", t!("diff-changed", backup = name, added = added, removed = removed, changed = changed)),
+    }
+    Ok(())
+}
@@ -0,0 +1,249 @@
+use crate::cli::Args;
+use crate::sync::http::{self, RequestExt};
+use crate::sync::launcher::Launcher;
+use crate::sync::settings::{self, InstanceSettings};
+use crate::sync::{self, curse_files, java};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// Clock differences beyond this break TLS and signed download urls.
+const MAX_SKEW_SECS: i64 = 300;
+/// Warn when less free space than this remains for downloads.
+const MIN_FREE_BYTES: u64 = 1024 * 1024 * 1024;
+
+pub struct Config {
+    pub base_dir: String,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &[])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        Ok(Config { base_dir })
+    }
+}
+
+enum Outcome {
+    Pass(String),
+    Warn(String, &'static str),
+    Fail(String, &'static str),
+}
+
+/// Runs every environment check and prints the outcome with a remediation
+/// hint for anything that is not right. Fails when any check failed.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let client = http::bind(reqwest::blocking::Client::builder())
+        .timeout(Duration::from_secs(10))
+        .build()?;
+
+    let mut outcomes = vec![check_instance(&base_dir)];
+    outcomes.push(check_mods_dir(&base_dir));
+    outcomes.push(check_api_key());
+    outcomes.push(check_reachable(&client, "CurseForge", "https://www.curseforge.com/api/v1/mods/238222"));
+    outcomes.push(check_reachable(&client, "Modrinth", "https://api.modrinth.com/v2"));
+    outcomes.push(check_disk_space(&base_dir));
+    outcomes.push(check_clock(&client));
+    outcomes.push(check_java(&base_dir));
+
+    let mut failed = 0;
+    for outcome in outcomes {
+        match outcome {
+            Outcome::Pass(message) => println!("[PASS] {}", message),
+            Outcome::Warn(message, hint) => {
+                println!("[WARN] {}", message);
+                println!("       {}", hint);
+            }
+            Outcome::Fail(message, hint) => {
+                failed += 1;
+                println!("[FAIL] {}", message);
+                println!("       {}", hint);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!("{} check(s) failed", failed));
+    }
+    Ok(())
+}
+
+fn check_instance(base_dir: &Path) -> Outcome {
+    if !base_dir.is_dir() {
+        return Outcome::Fail(
+            format!("instance directory {} does not exist", base_dir.display()),
+            "check the path passed to modpack-sync or run `modpack-sync setup`",
+        );
+    }
+
+    let probe = base_dir.join(".modpack-sync-doctor");
+    let writable = fs::write(&probe, b"").is_ok();
+    let _ = fs::remove_file(&probe);
+    match writable {
+        true => Outcome::Pass(format!("instance directory {} is writable", base_dir.display())),
+        false => Outcome::Fail(
+            format!("instance directory {} is not writable", base_dir.display()),
+            "run as the user owning the instance or fix the directory permissions",
+        ),
+    }
+}
+
+fn check_mods_dir(base_dir: &Path) -> Outcome {
+    let instance = match InstanceSettings::load(base_dir) {
+        Ok(instance) => instance,
+        Err(e) => {
+            return Outcome::Fail(
+                format!("instance settings are invalid: {}", e),
+                "fix or delete modpack-sync.toml and run `modpack-sync setup`",
+            )
+        }
+    };
+
+    let mods_dir = PathBuf::from(instance.game_dir(&base_dir.to_string_lossy())).join("mods");
+    if !mods_dir.exists() {
+        return Outcome::Warn(
+            format!("mods directory {} does not exist yet", mods_dir.display()),
+            "it will be created on the first sync, check the client/server setting if this is unexpected",
+        );
+    }
+    match fs::read_dir(&mods_dir) {
+        Ok(_) => Outcome::Pass(format!("mods directory {} is accessible", mods_dir.display())),
+        Err(e) => Outcome::Fail(
+            format!("mods directory {} cannot be read: {}", mods_dir.display(), e),
+            "fix the permissions of the mods directory",
+        ),
+    }
+}
+
+fn check_api_key() -> Outcome {
+    let key = match settings::api_key() {
+        Ok(key) => key,
+        Err(_) => {
+            return Outcome::Fail(
+                "no CurseForge API key configured".to_string(),
+                "set CURSE_API_KEY or run `modpack-sync setup`",
+            )
+        }
+    };
+
+    let checked = curse_files::files_page("238222", curse_files::Page { index: 0, size: 1 }, &key);
+    let limit = curse_files::rate_limit().map(|l| format!(", {}", l)).unwrap_or_default();
+    match checked {
+        Ok(_) => Outcome::Pass(format!("CurseForge API key accepted{}", limit)),
+        Err(e) => match e.downcast_ref::<curse_files::ApiError>() {
+            Some(e) if e.unauthorized() => Outcome::Fail(
+                format!("CurseForge rejected the API key ({})", e.status),
+                "generate a new key and store it with `modpack-sync setup`",
+            ),
+            Some(e) if e.throttled() => Outcome::Warn(
+                format!("CurseForge is throttling the API key{}", limit),
+                "wait for the allowance to renew before syncing",
+            ),
+            Some(e) => Outcome::Warn(
+                format!("CurseForge answered the key check with {}", e.status),
+                "the API may be having issues, try again later",
+            ),
+            None => Outcome::Fail(
+                format!("could not validate the API key: {:#}", e),
+                "check the network connection, see the reachability checks below",
+            ),
+        },
+    }
+}
+
+fn check_reachable(client: &reqwest::blocking::Client, name: &str, url: &str) -> Outcome {
+    match client.get(url).send_captured() {
+        Ok(r) => Outcome::Pass(format!("{} is reachable ({})", name, r.status())),
+        Err(e) => Outcome::Fail(
+            format!("{} is not reachable: {}", name, e),
+            "check your internet connection, proxy or firewall settings",
+        ),
+    }
+}
+
+fn check_disk_space(base_dir: &Path) -> Outcome {
+    match free_bytes(base_dir) {
+        Some(free) if free < MIN_FREE_BYTES => Outcome::Warn(
+            format!("only {} MB free on the instance disk", free / 1024 / 1024),
+            "large packs need several GB, free up space before syncing",
+        ),
+        Some(free) => Outcome::Pass(format!("{} MB free on the instance disk", free / 1024 / 1024)),
+        None => Outcome::Warn(
+            "could not determine free disk space".to_string(),
+            "make sure the instance disk has a few GB free",
+        ),
+    }
+}
+
+/// Free space as reported by `df`, there is no portable std api for it.
+fn free_bytes(path: &Path) -> Option<u64> {
+    let output = Command::new("df").arg("-Pk").arg(path).output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().nth(1)?;
+    let available: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+    Some(available * 1024)
+}
+
+fn check_clock(client: &reqwest::blocking::Client) -> Outcome {
+    let server_time = client
+        .head("https://www.curseforge.com")
+        .send_captured()
+        .ok()
+        .and_then(|r| r.headers().get("date").and_then(|d| d.to_str().ok()).map(str::to_string))
+        .and_then(|d| DateTime::parse_from_rfc2822(&d).ok());
+
+    let Some(server_time) = server_time else {
+        return Outcome::Warn(
+            "could not determine the clock skew".to_string(),
+            "make sure the system clock is synchronized",
+        );
+    };
+
+    let skew = (Utc::now() - server_time.with_timezone(&Utc)).num_seconds();
+    if skew.abs() > MAX_SKEW_SECS {
+        return Outcome::Fail(
+            format!("system clock is off by {} seconds", skew),
+            "enable time synchronization (NTP) on this machine",
+        );
+    }
+    Outcome::Pass(format!("system clock is within {} seconds of CurseForge", skew.abs()))
+}
+
+fn check_java(base_dir: &Path) -> Outcome {
+    let launcher = Launcher::detect(base_dir);
+    if launcher.manages_java() {
+        return Outcome::Pass(format!("java is managed by the {} launcher", launcher.name()));
+    }
+    let pack = sync::load_effective(base_dir, "modlist.json").ok().map(|e| e.pack);
+    match pack.as_ref().and_then(|p| java::check(base_dir, p)) {
+        Some(java::Problem::Missing(problem)) => {
+            return Outcome::Fail(problem, "install Java, e.g. from https://adoptium.net, or point the launcher at it")
+        }
+        Some(java::Problem::TooOld(problem)) => return Outcome::Fail(problem, "install a newer Java and select it for this instance"),
+        Some(java::Problem::TooNew(problem)) => {
+            return Outcome::Warn(problem, "install Java 8 and select it for this instance if the game fails to start")
+        }
+        None => {}
+    }
+    let found = match java::find(base_dir, launcher) {
+        Ok(found) => found,
+        Err(e) => {
+            return Outcome::Fail(
+                format!("{:#}", e),
+                "install Java, e.g. from https://adoptium.net, or point the launcher at it",
+            )
+        }
+    };
+    match pack.as_ref().and_then(java::required) {
+        Some(required) => Outcome::Pass(format!("{} is Java {}, the pack needs {}", found.describe(), found.major, required)),
+        None => Outcome::Pass(format!("{} is Java {}", found.describe(), found.major)),
+    }
+}
@@ -0,0 +1,46 @@
+use crate::cli::Args;
+use crate::prompt;
+use crate::sync::secrets;
+use anyhow::{anyhow, Context, Result};
+use std::io::{self, IsTerminal, Read};
+
+pub struct Config {
+    /// age recipient to encrypt to instead of the passphrase.
+    pub age: Option<String>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["age"])?;
+        Ok(Config { age: parsed.value("age").map(str::to_string) })
+    }
+}
+
+/// Encrypts a secret read from stdin for a settings file, with the
+/// passphrase of `MODPACK_SYNC_PASSPHRASE` or to an age recipient. The
+/// printed value replaces the plaintext of a key, password, token or
+/// notification url or header, e.g. `api_key = "enc:..."`, and is
+/// decrypted whenever the settings are read.
+pub fn run(config: Config) -> Result<()> {
+    let secret = match io::stdin().is_terminal() {
+        true => prompt::ask("Secret to encrypt", None)?,
+        false => {
+            let mut secret = String::new();
+            io::stdin().read_to_string(&mut secret).context("failed to read the secret from stdin")?;
+            secret.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    if secret.is_empty() {
+        return Err(anyhow!("expected the secret to encrypt on stdin"));
+    }
+    let sealed = match &config.age {
+        Some(recipient) => secrets::encrypt_age(&secret, recipient)?,
+        None => {
+            let passphrase = secrets::passphrase()?
+                .ok_or_else(|| anyhow!("set MODPACK_SYNC_PASSPHRASE or MODPACK_SYNC_PASSPHRASE_FILE, or encrypt to --age <recipient>"))?;
+            secrets::encrypt(&secret, &passphrase)?
+        }
+    };
+    println!("{}", sealed);
+    Ok(())
+}
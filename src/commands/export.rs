@@ -0,0 +1,153 @@
+use crate::cli::Args;
+use crate::sync::settings::{self, InstanceSettings};
+use crate::sync::{self, bundle, sbom, serverpack, stats};
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub enum Kind {
+    /// A ready to run server instance generated from a client pack.
+    ServerPack { out: String, installer: bool, sync: bool },
+    /// A software inventory of the pack, written to `out` or printed.
+    Sbom { format: sbom::Format, out: Option<String> },
+    /// One archive of the installed pack for machines without internet.
+    /// With `from`, only what changed since that bundle or version.
+    Bundle { out: String, from: Option<String>, to: Option<String> },
+    /// A small summary of the pack for websites, or with `badge` the
+    /// shields.io endpoint of one of its fields.
+    Stats { out: Option<String>, badge: Option<String> },
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub kind: Kind,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["installer", "no-sync"], &["out", "format", "from", "to", "badge"])?;
+        let (kind, base_dir) = match parsed.positional.as_slice() {
+            [kind, base_dir, ..] => (kind.as_str(), base_dir.clone()),
+            _ => return Err(anyhow!("usage: modpack-sync export <serverpack|sbom|bundle|stats> <instance dir> [--out <path>]")),
+        };
+        let kind = match kind {
+            "serverpack" => Kind::ServerPack {
+                out: parsed
+                    .value("out")
+                    .ok_or_else(|| anyhow!("export serverpack expects --out <dir>"))?
+                    .to_string(),
+                installer: parsed.has("installer"),
+                sync: !parsed.has("no-sync"),
+            },
+            "sbom" => Kind::Sbom {
+                format: match parsed.value("format").unwrap_or("cyclonedx") {
+                    "cyclonedx" => sbom::Format::CycloneDx,
+                    "spdx" => sbom::Format::Spdx,
+                    other => return Err(anyhow!("unknown sbom format {}, expected cyclonedx or spdx", other)),
+                },
+                out: parsed.value("out").map(str::to_string),
+            },
+            "bundle" => Kind::Bundle {
+                out: parsed.value("out").unwrap_or("bundle.tar.zst").to_string(),
+                from: parsed.value("from").map(str::to_string),
+                to: parsed.value("to").map(str::to_string),
+            },
+            "stats" => Kind::Stats {
+                out: parsed.value("out").map(str::to_string),
+                badge: parsed.value("badge").map(str::to_string),
+            },
+            other => return Err(anyhow!("unknown export {}, expected serverpack, sbom, bundle or stats", other)),
+        };
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            kind,
+        })
+    }
+}
+
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    match config.kind {
+        Kind::ServerPack { out, installer, sync } => {
+            let exported = serverpack::export(&base_dir, &config.mods_file, Path::new(&out), installer)?;
+            for filename in exported.client_only.iter() {
+                println!("[INFO] leaving out client only {}", filename);
+            }
+            if let Some(file) = &exported.installer {
+                println!("[INFO] fetched the loader as {}", file);
+            }
+            println!(
+                "[INFO] wrote a server pack with {} mods and {} overrides to {}",
+                exported.mods, exported.overrides, out
+            );
+            if sync {
+                println!("[INFO] installing the server's mods...");
+                let args = ["sync".to_string(), out.clone()];
+                sync::run(sync::Config::build(&args)?)?;
+            }
+            println!("[INFO] set eula=true in {}/eula.txt, then start the server with start.sh or start.bat", out);
+            Ok(())
+        }
+        Kind::Sbom { format, out } => {
+            let document = serde_json::to_string_pretty(&sbom::build(&base_dir, &config.mods_file, format)?)?;
+            match out {
+                Some(out) => {
+                    fs::write(&out, document).with_context(|| format!("failed to write {}", out))?;
+                    println!("[INFO] wrote the software inventory of the pack to {}", out);
+                }
+                None => println!("{}", document),
+            }
+            Ok(())
+        }
+        Kind::Stats { out, badge } => {
+            let stats = stats::build(&base_dir, &config.mods_file)?;
+            let document = match &badge {
+                Some(field) => serde_json::to_string_pretty(&stats::badge(&stats, field)?)?,
+                None => serde_json::to_string_pretty(&stats)?,
+            };
+            match out {
+                Some(out) => {
+                    fs::write(&out, document).with_context(|| format!("failed to write {}", out))?;
+                    println!("[INFO] wrote the statistics of the pack to {}", out);
+                }
+                None => println!("{}", document),
+            }
+            Ok(())
+        }
+        Kind::Bundle { out, from, to } => {
+            let instance = InstanceSettings::load(&base_dir)?;
+            let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+            let api_key = settings::api_key().ok();
+            if api_key.is_none() {
+                println!("[WARN] no CurseForge API key configured, only licenses tell which files may be bundled");
+            }
+            let exported = bundle::export(
+                &base_dir,
+                &game_dir,
+                &config.mods_file,
+                Path::new(&out),
+                api_key.as_deref(),
+                from.as_deref(),
+                to.as_deref(),
+            )?;
+            match &from {
+                Some(from) => println!(
+                    "[INFO] wrote {} changed files and {} overrides of {} since {} to {}, {} unchanged and {} deleted",
+                    exported.files, exported.overrides, exported.version, from, out, exported.unchanged, exported.removed
+                ),
+                None => println!("[INFO] wrote {} files and {} overrides of {} to {}", exported.files, exported.overrides, exported.version, out),
+            }
+            println!("[INFO] install it with `modpack-sync apply bundle {} <instance dir>`", out);
+            if !exported.restricted.is_empty() {
+                println!("[WARN] {} files may not be passed on and have to be placed by hand:", exported.restricted.len());
+                for r in exported.restricted.iter() {
+                    println!("  {} ({}){}", r.path, r.reason, r.url.as_ref().map(|u| format!(", from {}", u)).unwrap_or_default());
+                }
+            }
+            Ok(())
+        }
+    }
+}
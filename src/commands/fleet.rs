@@ -0,0 +1,91 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::fleet::{Fleet, Report};
+use crate::sync::{log, settings, version};
+use anyhow::{anyhow, Result};
+use chrono::{Local, Utc};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub format: Format,
+    /// Only clients that reported within this many days.
+    pub days: Option<u64>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["format", "days"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let format = match parsed.value("format").unwrap_or("text") {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            other => return Err(anyhow!("unknown format {}, expected text or json", other)),
+        };
+        let days = match parsed.value("days") {
+            Some(days) => Some(days.parse().map_err(|_| anyhow!("--days expects a number, got {}", days))?),
+            None => None,
+        };
+
+        Ok(Config { base_dir, format, days })
+    }
+}
+
+/// Lists the clients that reported to the instance's `serve --fleet` with
+/// the version they are on, newest version first, and how many are on
+/// each.
+pub fn run(config: Config) -> Result<()> {
+    let fleet = Fleet::load(&PathBuf::from(&config.base_dir))?;
+    let mut clients: Vec<&Report> = fleet
+        .clients
+        .values()
+        .filter(|r| config.days.is_none_or(|days| Utc::now() - r.reported_at <= settings::days(days)))
+        .collect();
+    clients.sort_by(|a, b| {
+        let (a_version, b_version) = (a.version.as_deref().unwrap_or_default(), b.version.as_deref().unwrap_or_default());
+        version::compare(b_version, a_version).then_with(|| b.reported_at.cmp(&a.reported_at))
+    });
+
+    if config.format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&clients)?);
+        return Ok(());
+    }
+    let status = |r: &Report| match (r.synced, r.problems) {
+        (false, _) => "sync failed".to_string(),
+        (true, 0) => "ok".to_string(),
+        (true, n) => format!("{} problems", n),
+    };
+    for r in clients.iter() {
+        let name = r.name.clone().unwrap_or_else(|| r.client_id[..8.min(r.client_id.len())].to_string());
+        let version = r.version.as_deref().unwrap_or("-");
+        let reported = r.reported_at.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+        match log::plain() {
+            true => println!("{}, version {}, {}, reported {}", name, version, status(r), reported),
+            false => println!("{:<24} {:<12} {:<14} {}", name, version, status(r), reported),
+        }
+    }
+    let mut versions: BTreeMap<&str, usize> = BTreeMap::new();
+    for r in clients.iter() {
+        *versions.entry(r.version.as_deref().unwrap_or("no version")).or_default() += 1;
+    }
+    let mut versions: Vec<(&str, usize)> = versions.into_iter().collect();
+    versions.sort_by(|a, b| version::compare(b.0, a.0));
+    let summary: Vec<String> = versions.iter().map(|(v, n)| format!("{} on {}", n, v)).collect();
+    println!("[INFO] {}", t!("fleet-clients", count = clients.len(), versions = summary.join(", ")));
+    let broken = clients.iter().filter(|r| !r.synced || r.problems > 0).count();
+    if broken > 0 {
+        println!("[WARN] {}", t!("fleet-broken", count = broken));
+    }
+    Ok(())
+}
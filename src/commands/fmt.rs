@@ -0,0 +1,109 @@
+use crate::cli::Args;
+use crate::sync::log_to_file;
+use crate::sync::manifest::{self, Manifest};
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub check: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["check"], &[])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            check: parsed.has("check"),
+        })
+    }
+}
+
+/// Rewrites the modlist in canonical form: entries sorted by name, exact
+/// duplicates dropped, unknown fields stripped and pretty printed with a
+/// stable field order. With `--check` nothing is written and an error is
+/// returned when the file is not already formatted.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let path = base_dir.join(&config.mods_file);
+    let original = fs::read_to_string(&path)?;
+    let loaded = manifest::parse(&original, &base_dir)?;
+
+    if loaded.legacy {
+        return Err(anyhow!(
+            "{} uses the legacy format, run `modpack-sync migrate` first",
+            path.display()
+        ));
+    }
+
+    let mut manifest = loaded.manifest;
+    let removed = normalize(&mut manifest);
+    let formatted = manifest::to_string(&manifest)?;
+
+    if formatted == original {
+        println!("[INFO] {} is already formatted", path.display());
+        return Ok(());
+    }
+
+    if config.check {
+        return Err(anyhow!("{} is not formatted, run `modpack-sync fmt`", path.display()));
+    }
+
+    manifest::save(&path, &manifest)?;
+    let _ = log_to_file(&format!(
+        "[INFO] Formatted {} ({} duplicate entries removed)",
+        path.display(),
+        removed
+    ));
+    println!("[INFO] Formatted {} ({} duplicate entries removed)", path.display(), removed);
+    Ok(())
+}
+
+/// Sorts and deduplicates the entries of a manifest, returning how many
+/// duplicates were dropped. Entries that share a url but point at different
+/// files are kept and reported since picking one is not fmt's call.
+pub fn normalize(manifest: &mut Manifest) -> usize {
+    for m in manifest.mods.iter_mut() {
+        m.filename = m.filename.trim().to_string();
+        m.name = m.name.trim().to_string();
+        m.version = m.version.trim().to_string();
+        m.url = m.url.take().map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+        m.note = m.note.take().map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+        m.reason = m.reason.take().map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+    }
+
+    let before = manifest.mods.len();
+    let mut seen = HashSet::new();
+    manifest.mods.retain(|m| seen.insert(m.filename.clone()));
+    let removed = before - manifest.mods.len();
+
+    let mut by_url: HashMap<&str, Vec<&str>> = HashMap::new();
+    for m in manifest.mods.iter() {
+        if let Some(url) = &m.url {
+            by_url.entry(url.as_str()).or_default().push(m.filename.as_str());
+        }
+    }
+    let mut conflicts: Vec<_> = by_url.into_iter().filter(|(_, files)| files.len() > 1).collect();
+    conflicts.sort();
+    for (url, files) in conflicts {
+        println!("[WARN] {} is listed more than once: {}", url, files.join(", "));
+    }
+
+    manifest.mods.sort_by(|a, b| {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.filename.cmp(&b.filename))
+    });
+    removed
+}
@@ -0,0 +1,224 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::prefetch::{self, Store};
+use crate::sync::report::human_size;
+use crate::sync::settings::{self, InstanceSettings};
+use crate::sync::state::{self, State};
+use crate::sync::{backup, bundle, cache, log, quarantine, staging};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Leftovers younger than this may belong to a sync still running.
+const STALE: Duration = Duration::from_secs(60 * 60);
+/// Quarantined files are kept this many days by default.
+const DEFAULT_QUARANTINE_DAYS: u64 = 30;
+
+pub struct Config {
+    pub base_dir: String,
+    pub dry_run: bool,
+    /// Run logs and exported bundle indexes kept.
+    pub keep: usize,
+    /// Quarantined files older than this many days are deleted.
+    pub quarantine_days: u64,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["dry-run"], &["keep", "quarantine-days"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let keep = match parsed.value("keep") {
+            Some(keep) => keep.parse().map_err(|_| anyhow!("--keep expects a number, got {}", keep))?,
+            None => log::KEEP_RUNS,
+        };
+        let quarantine_days = match parsed.value("quarantine-days") {
+            Some(days) => days.parse().map_err(|_| anyhow!("--quarantine-days expects a number, got {}", days))?,
+            None => DEFAULT_QUARANTINE_DAYS,
+        };
+
+        Ok(Config { base_dir, dry_run: parsed.has("dry-run"), keep, quarantine_days })
+    }
+}
+
+/// A file or directory nothing refers to anymore.
+struct Garbage {
+    path: PathBuf,
+    bytes: u64,
+    what: &'static str,
+}
+
+impl Garbage {
+    fn of(path: PathBuf, what: &'static str) -> Garbage {
+        let bytes = match path.is_dir() {
+            true => backup::dir_size(&path),
+            false => fs::metadata(&path).map_or(0, |m| m.len()),
+        };
+        Garbage { path, bytes, what }
+    }
+
+    fn delete(&self) -> Result<()> {
+        match self.path.is_dir() {
+            true => fs::remove_dir_all(&self.path),
+            false => fs::remove_file(&self.path),
+        }
+        .with_context(|| format!("failed to delete {}", self.path.display()))
+    }
+}
+
+fn stale(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age >= STALE))
+}
+
+/// Files of `dir` with the extension `extension`, not descending into
+/// subdirectories.
+fn files_ending(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == extension))
+        .collect()
+}
+
+/// Downloads interrupted before they were moved into place: `.part` files
+/// in the staging directory and beside the files the sync manages, and
+/// the shared cache's temporary files.
+fn partial_downloads(base_dir: &Path, instance: &InstanceSettings, game_dir: &Path) -> Result<Vec<Garbage>> {
+    let state = State::load(base_dir)?;
+    let mut dirs: BTreeSet<PathBuf> = state.paths(game_dir).iter().filter_map(|p| p.parent().map(Path::to_path_buf)).collect();
+    dirs.insert(game_dir.join("mods"));
+    dirs.insert(state::path(base_dir).parent().expect("the state lies in the state directory").to_path_buf());
+    dirs.insert(staging::dir(base_dir, instance.staging_dir.as_deref()));
+    let mut garbage: Vec<Garbage> = dirs
+        .iter()
+        .flat_map(|dir| files_ending(dir, "part"))
+        .filter(|p| stale(p))
+        .map(|p| Garbage::of(p, "partial download"))
+        .collect();
+    if let Ok(cache_dir) = cache::dir() {
+        garbage.extend(files_ending(&cache_dir, "tmp").into_iter().filter(|p| stale(p)).map(|p| Garbage::of(p, "partial cache entry")));
+    }
+    Ok(garbage)
+}
+
+/// What the prefetch store holds that its index no longer lists, and the
+/// copy of the pack a dry run pulled.
+fn orphaned_staging(base_dir: &Path) -> Result<Vec<Garbage>> {
+    let mut garbage = Vec::new();
+    let dry_run = base_dir.join(log::STATE_DIR).join("dry-run");
+    if dry_run.is_dir() && stale(&dry_run) {
+        garbage.push(Garbage::of(dry_run, "dry run copy of the pack"));
+    }
+    let dir = prefetch::dir(base_dir);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(garbage);
+    };
+    let store = Store::load(base_dir)?;
+    let referenced: BTreeSet<&str> = store.files.values().map(|p| p.file.as_str()).collect();
+    for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        // the index and the pack a prefetch pulled are the store's own
+        if path.is_file() && name != "index.json" && !referenced.contains(name.as_str()) && stale(&path) {
+            garbage.push(Garbage::of(path, "orphaned prefetched file"));
+        }
+    }
+    Ok(garbage)
+}
+
+/// Backups beyond what the instance keeps, its settings may have lowered
+/// the number since they were taken.
+fn expired_backups(base_dir: &Path, instance: &InstanceSettings) -> Result<Vec<Garbage>> {
+    let keep = instance.backup.as_ref().map_or(backup::DEFAULT_KEEP, |b| b.keep);
+    let world_keep = instance.world_backup.as_ref().map(|w| w.keep);
+    let mut garbage = Vec::new();
+    for (kind, keep) in [(backup::MODS, Some(keep)), (backup::WORLD, world_keep)] {
+        let (Some(keep), archives) = (keep, backup::list(base_dir, kind)?) else {
+            continue;
+        };
+        let expired = archives.len().saturating_sub(keep);
+        garbage.extend(archives.into_iter().take(expired).map(|p| Garbage::of(p, "expired backup")));
+    }
+    Ok(garbage)
+}
+
+fn expired_quarantine(base_dir: &Path, days: u64) -> Result<Vec<Garbage>> {
+    let mut garbage = Vec::new();
+    for (path, sidecar) in quarantine::list(base_dir)? {
+        // without a sidecar the file's own age tells
+        let quarantined_at = match sidecar {
+            Some(sidecar) => Some(sidecar.quarantined_at),
+            None => fs::metadata(&path).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from),
+        };
+        let expired = quarantined_at.is_some_and(|at| Utc::now() - at > settings::days(days));
+        if expired {
+            let sidecar = quarantine::sidecar_path(&path);
+            garbage.push(Garbage::of(path, "expired quarantined file"));
+            if sidecar.is_file() {
+                garbage.push(Garbage::of(sidecar, "sidecar of a quarantined file"));
+            }
+        }
+    }
+    Ok(garbage)
+}
+
+/// Run logs and indexes of exported bundles beyond the newest `keep`.
+fn old_history(base_dir: &Path, keep: usize) -> Result<Vec<Garbage>> {
+    let runs = log::list_runs(base_dir)?;
+    let expired = runs.len().saturating_sub(keep);
+    let mut garbage: Vec<Garbage> = runs.into_iter().take(expired).map(|p| Garbage::of(p, "old run log")).collect();
+    // bundle indexes are named after the version, their age orders them
+    let mut bundles: Vec<(std::time::SystemTime, PathBuf)> = files_ending(&bundle::history_dir(base_dir), "json")
+        .into_iter()
+        .filter_map(|p| Some((fs::metadata(&p).and_then(|m| m.modified()).ok()?, p)))
+        .collect();
+    bundles.sort();
+    let expired = bundles.len().saturating_sub(keep);
+    garbage.extend(bundles.into_iter().take(expired).map(|(_, p)| Garbage::of(p, "old bundle index")));
+    Ok(garbage)
+}
+
+/// Deletes what syncs left behind and nothing refers to anymore, partial
+/// downloads, orphaned staging files, expired backups and quarantined
+/// files and history beyond what is kept, reporting the space reclaimed.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+
+    let mut garbage = partial_downloads(&base_dir, &instance, &game_dir)?;
+    garbage.extend(orphaned_staging(&base_dir)?);
+    garbage.extend(expired_backups(&base_dir, &instance)?);
+    garbage.extend(expired_quarantine(&base_dir, config.quarantine_days)?);
+    garbage.extend(old_history(&base_dir, config.keep)?);
+
+    let (mut deleted, mut reclaimed) = (0, 0);
+    for item in garbage.iter() {
+        let shown = item.path.strip_prefix(&base_dir).unwrap_or(&item.path).display();
+        if config.dry_run {
+            println!("[INFO] {}", t!("gc-would-delete", what = item.what, path = shown, size = human_size(item.bytes)));
+        } else if let Err(e) = item.delete() {
+            println!("[WARN] {:#}", e);
+            continue;
+        } else {
+            println!("[INFO] {}", t!("gc-deleted", what = item.what, path = shown, size = human_size(item.bytes)));
+        }
+        deleted += 1;
+        reclaimed += item.bytes;
+    }
+    match (garbage.is_empty(), config.dry_run) {
+        (true, _) => println!("[INFO] {}", t!("gc-nothing")),
+        (false, true) => println!("[INFO] {}", t!("gc-would-reclaim", size = human_size(reclaimed), count = deleted)),
+        (false, false) => println!("[INFO] {}", t!("gc-reclaimed", size = human_size(reclaimed), count = deleted)),
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::ftb;
+use crate::sync::manifest;
+use crate::sync::report::human_size;
+use crate::sync::settings::{InstanceSettings, Side};
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: PathBuf,
+    pub mods_file: String,
+    /// Id of the pack on FTB.
+    pub pack: u64,
+    /// Name or id of the version to import, the newest release by default.
+    pub version: Option<String>,
+    pub side: Side,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["server"], &["pack", "version"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?;
+        let pack = parsed.value("pack").ok_or_else(|| anyhow!("expected --pack with the id of the FTB pack"))?;
+        let pack = pack.parse().map_err(|_| anyhow!("--pack expects the numeric id of the FTB pack, got {}", pack))?;
+
+        Ok(Config {
+            base_dir: PathBuf::from(base_dir),
+            mods_file: "modlist.json".to_string(),
+            pack,
+            version: parsed.value("version").map(str::to_string),
+            side: match parsed.has("server") {
+                true => Side::Server,
+                false => Side::Client,
+            },
+        })
+    }
+}
+
+/// Makes an instance of a Feed The Beast pack version: a modlist entry for
+/// every file FTB mirrors from CurseForge, pinned by the lockfile, and the
+/// files FTB hosts itself as overrides.
+pub fn run(config: Config) -> Result<()> {
+    let modlist = config.base_dir.join(&config.mods_file);
+    if modlist.exists() {
+        return Err(anyhow!("{} is an instance already, it has a {}", config.base_dir.display(), config.mods_file));
+    }
+    let pack = ftb::pack(config.pack)?;
+    let picked = pack.pick(config.version.as_deref())?;
+    println!("[INFO] {}", t!("import-ftb-importing", pack = pack.name, version = picked.name));
+    let import = ftb::convert(&pack.name, ftb::version(config.pack, picked.id)?);
+
+    fs::create_dir_all(&config.base_dir)?;
+    let mut instance = InstanceSettings::load(&config.base_dir)?;
+    instance.side = config.side;
+    instance.save(&config.base_dir)?;
+
+    let mut failed = Vec::new();
+    for file in import.overrides.iter() {
+        if let Err(e) = ftb::download_override(&config.base_dir, file) {
+            println!("[WARN] {:#}", e);
+            failed.push(file.key());
+        }
+    }
+    let mut lock = import.lock;
+    lock.update_overrides(&config.base_dir)?;
+    manifest::save(&modlist, &import.manifest)?;
+    lock.save(&config.base_dir)?;
+
+    let pack_header = &import.manifest.pack;
+    println!(
+        "[INFO] {}",
+        t!(
+            "import-ftb-imported",
+            entries = import.manifest.mods.len(),
+            overrides = import.overrides.len() - failed.len(),
+            size = human_size(import.overrides.iter().filter(|f| !failed.contains(&f.key())).map(|f| f.size).sum()),
+            minecraft = pack_header.minecraft.as_deref().unwrap_or("unknown"),
+            loader = pack_header.loader.as_deref().unwrap_or("no loader")
+        )
+    );
+    if import.manifest.pack.opt_in_tags.iter().any(|t| t == ftb::OPTIONAL_TAG) {
+        println!("[INFO] {}", t!("import-ftb-optional", tag = ftb::OPTIONAL_TAG));
+    }
+    if !failed.is_empty() {
+        println!("[WARN] {}", t!("import-ftb-failed", count = failed.len(), files = failed.join(", ")));
+    }
+    println!("[INFO] {}", t!("import-ftb-next", dir = config.base_dir.display()));
+    Ok(())
+}
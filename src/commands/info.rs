@@ -0,0 +1,125 @@
+use crate::cli::Args;
+use crate::sync::curse_files::{self, CurseFile};
+use crate::sync::{self, settings};
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+/// How many matching files to show.
+const MAX_FILES: usize = 10;
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub query: String,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &[])?;
+        if parsed.positional.len() < 2 {
+            return Err(anyhow!("expected the path to the modpack and a project id, name or filename"));
+        }
+
+        Ok(Config {
+            base_dir: parsed.positional[0].clone(),
+            mods_file: "modlist.json".to_string(),
+            query: parsed.positional[1..].join(" "),
+        })
+    }
+}
+
+/// Shows live project metadata for one mod, the files available for the
+/// pack's game version and loader, and the file the modlist pins.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let effective = sync::load_effective(&base_dir, &config.mods_file)?;
+    let api_key = settings::api_key()?;
+
+    let query = config.query.to_lowercase();
+    let entry = effective.mods.iter().find(|m| {
+        m.project_id() == Some(config.query.as_str())
+            || m.name.to_lowercase() == query
+            || m.filename.to_lowercase() == query
+    });
+    let project_id = match entry.and_then(|m| m.project_id()) {
+        Some(id) => id.to_string(),
+        None if config.query.chars().all(|c| c.is_ascii_digit()) => config.query.clone(),
+        None => return Err(anyhow!("{} is not in the modlist and is not a project id", config.query)),
+    };
+
+    let project = curse_files::project(&project_id, &api_key)?;
+    let authors: Vec<&str> = project.authors.iter().map(|a| a.name.as_str()).collect();
+    println!("{} ({}), project {}", project.name, project.slug, project.id);
+    println!("  by {}, {} downloads", authors.join(", "), project.download_count as u64);
+    if !project.summary.is_empty() {
+        println!("  {}", project.summary);
+    }
+
+    let minecraft = effective.pack.minecraft.as_deref();
+    let loader = effective.pack.loader.as_deref();
+    let pinned = entry.map(|m| m.filename.as_str());
+    match pinned {
+        Some(filename) => println!("  modlist pins {}", filename),
+        None => println!("  not in the modlist"),
+    }
+
+    println!(
+        "Files for minecraft {} / {}:",
+        minecraft.unwrap_or("any"),
+        loader.unwrap_or("any loader")
+    );
+    let mut shown = 0;
+    let mut pinned_found = pinned.is_none();
+    let mut dependencies = None;
+    for file in CurseFile::of(&project_id, &api_key)? {
+        let file = file?;
+        let is_pinned = pinned == Some(file.file_name.as_str());
+        if is_pinned {
+            pinned_found = true;
+            dependencies = Some(file.dependencies.clone());
+        }
+
+        if shown < MAX_FILES && (is_pinned || file.supports(minecraft, loader)) {
+            // without a pin the newest matching file is the interesting one
+            if dependencies.is_none() {
+                dependencies = Some(file.dependencies.clone());
+            }
+            shown += 1;
+            let kind = match file.release_type {
+                1 => "release",
+                2 => "beta",
+                _ => "alpha",
+            };
+            println!(
+                "  {} {:<48} {:<8} {} {}",
+                if is_pinned { "*" } else { " " },
+                file.file_name,
+                kind,
+                file.file_date.get(..10).unwrap_or(&file.file_date),
+                file.id
+            );
+        }
+
+        if shown >= MAX_FILES && pinned_found {
+            break;
+        }
+    }
+    if shown == 0 {
+        println!("  no files found for this game version and loader");
+    }
+
+    let dependencies = dependencies.unwrap_or_default();
+    if !dependencies.is_empty() {
+        println!("Dependencies:");
+        for d in dependencies.iter() {
+            let listed = effective.mods.iter().any(|m| m.project_id() == Some(d.mod_id.to_string().as_str()));
+            println!(
+                "  {:<12} project {:<10} {}",
+                d.relation(),
+                d.mod_id,
+                if listed { "in modlist" } else { "not in modlist" }
+            );
+        }
+    }
+    Ok(())
+}
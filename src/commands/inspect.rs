@@ -0,0 +1,70 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::backup;
+use crate::sync::report::human_size;
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: String,
+    /// Backup to look into, as `restore-backup --list` names it.
+    pub snapshot: String,
+    /// Files or directories to extract, relative to the game directory.
+    pub extract: Vec<String>,
+    /// Where extracted files go, a directory named after the backup in the
+    /// current directory by default.
+    pub to: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["snapshot", "extract", "to"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let snapshot = parsed
+            .value("snapshot")
+            .ok_or_else(|| anyhow!("expected --snapshot with the backup to inspect, see `restore-backup --list`"))?
+            .to_string();
+
+        Ok(Config {
+            base_dir,
+            snapshot,
+            extract: parsed.values("extract").into_iter().map(str::to_string).collect(),
+            to: parsed.value("to").map(PathBuf::from),
+        })
+    }
+}
+
+/// Lists the files a backup holds, or copies some of them out with
+/// `--extract`, without restoring anything into the instance.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let archive = backup::find(&base_dir, &config.snapshot)?;
+    let name = archive.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+    if config.extract.is_empty() && config.to.is_none() {
+        let files = backup::contents(&archive)?;
+        for file in files.iter() {
+            println!("  {:<64} {:>10}", file.path, human_size(file.size));
+        }
+        println!(
+            "[INFO] {}",
+            t!("inspect-holds", backup = name, count = files.len(), size = human_size(files.iter().map(|f| f.size).sum()))
+        );
+        return Ok(());
+    }
+
+    let to = config.to.unwrap_or_else(|| PathBuf::from(name.split('.').next().unwrap_or(&name)));
+    let extracted = backup::extract(&archive, &config.extract, &to)?;
+    if extracted.is_empty() {
+        return Err(anyhow!("{} holds none of {}", name, config.extract.join(", ")));
+    }
+    for path in extracted.iter() {
+        println!("  {}", path.display());
+    }
+    println!("[INFO] {}", t!("inspect-extracted", count = extracted.len(), backup = name, dir = to.display()));
+    Ok(())
+}
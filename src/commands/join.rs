@@ -0,0 +1,148 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::http::{self, RequestExt};
+use crate::sync::manifest::{self, Manifest, PackHeader};
+use crate::sync::settings::{self, InstanceSettings, Side};
+use crate::sync::peers::{self, Advertised};
+use crate::sync::{extends, log_to_file, ping};
+use anyhow::{anyhow, Result};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Word of a MOTD that advertises the pack, followed by its url, e.g.
+/// `modpack-sync:https://packs.example.com/skyblock/manifest.json`.
+const MOTD_PREFIX: &str = "modpack-sync:";
+
+pub struct Config {
+    /// Address of the server, `host` or `host:port`.
+    pub address: String,
+    pub base_dir: Option<String>,
+    pub mods_file: String,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &[])?;
+        let address = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected the address of the server to join"))?
+            .clone();
+
+        Ok(Config {
+            address,
+            base_dir: parsed.positional.get(1).cloned(),
+            mods_file: "modlist.json".to_string(),
+        })
+    }
+}
+
+/// The pack advertised at `site`, a website or a `modpack-sync serve`.
+fn well_known(site: &str) -> Result<Option<Advertised>> {
+    let url = format!("{}{}", site, peers::WELL_KNOWN_PATH);
+    let response = http::api().get(&url).send_captured()?;
+    if !response.status().is_success() {
+        let _ = log_to_file(&format!("[INFO] {} answered {}", url, response.status()));
+        return Ok(None);
+    }
+    Ok(Some(http::json(response)?))
+}
+
+fn from_status(status: &serde_json::Value) -> Option<Advertised> {
+    if let Some(advertised) = status.get("modpack_sync").and_then(|a| serde_json::from_value(a.clone()).ok()) {
+        return Some(advertised);
+    }
+    let motd = ping::motd(status);
+    let url = motd.split_whitespace().find_map(|w| w.strip_prefix(MOTD_PREFIX))?.to_string();
+    Some(match url.ends_with(".json") {
+        true => Advertised { manifest: Some(url), ..Default::default() },
+        false => Advertised { source: Some(url), ..Default::default() },
+    })
+}
+
+/// Finds the pack the server at `address` advertises, on the website of its
+/// host first, then on a `modpack-sync serve` on its default port and then
+/// in the entry it shows in the server list.
+fn discover(address: &str) -> Result<Advertised> {
+    let (host, _) = ping::split(address)?;
+    for site in [format!("https://{}", host), format!("http://{}:{}", host, peers::DEFAULT_PORT)] {
+        match well_known(&site) {
+            Ok(Some(advertised)) if advertised.has_pack() => return Ok(advertised),
+            Ok(_) => {}
+            Err(e) => {
+                let _ = log_to_file(&format!("[INFO] no pack advertised at {}: {:#}", site, e));
+            }
+        }
+    }
+    let status = ping::status(address)?;
+    from_status(&status).filter(Advertised::has_pack).ok_or_else(|| {
+        anyhow!(
+            "{} advertises no pack, ask its owner to serve .well-known/modpack-sync.json or put {}<url> in the MOTD",
+            address,
+            MOTD_PREFIX
+        )
+    })
+}
+
+/// Sets up a new client instance for the pack a server advertises, so
+/// joining it takes one command and a sync. The instance extends the
+/// advertised manifest or syncs from the advertised published pack.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = match &config.base_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => env::current_dir()?.join(ping::split(&config.address)?.0),
+    };
+    let modlist = base_dir.join(&config.mods_file);
+    if modlist.exists() {
+        return Err(anyhow!("{} is set up already, it has a {}", base_dir.display(), config.mods_file));
+    }
+    let advertised = discover(&config.address)?;
+
+    if let Some(location) = &advertised.manifest {
+        let pack = extends::load(location, &base_dir)?.manifest;
+        println!(
+            "[INFO] {}",
+            t!(
+                "join-plays",
+                address = config.address,
+                pack = pack.pack.name,
+                version = pack.pack.version,
+                minecraft = pack.pack.minecraft.as_deref().unwrap_or("any"),
+                loader = pack.pack.loader.as_deref().unwrap_or("any loader"),
+                entries = pack.mods.len()
+            )
+        );
+    }
+    if let Some(source) = &advertised.source {
+        println!("[INFO] {}", t!("join-source", address = config.address, source = source));
+    }
+
+    fs::create_dir_all(&base_dir)?;
+    let mut instance = InstanceSettings::load(&base_dir)?;
+    instance.side = Side::Client;
+    instance.source = advertised.source.clone();
+    instance.channel = advertised.channel.clone();
+    instance.peers = advertised.peers.clone();
+    instance.save(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&base_dir.to_string_lossy()));
+    fs::create_dir_all(game_dir.join("mods"))?;
+
+    if let Some(location) = advertised.manifest {
+        // name and version are left to the advertised pack
+        let manifest = Manifest {
+            format_version: manifest::FORMAT_VERSION,
+            extends: Some(location),
+            pack: PackHeader::default(),
+            remove: Vec::new(),
+            mods: Vec::new(),
+        };
+        manifest::save(&modlist, &manifest)?;
+    }
+
+    if settings::api_key().is_err() {
+        println!("[WARN] {}", t!("join-no-api-key"));
+    }
+    println!("[INFO] {}", t!("join-done", dir = base_dir.display(), address = config.address));
+    Ok(())
+}
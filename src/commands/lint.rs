@@ -0,0 +1,244 @@
+use super::update;
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::curse_files;
+use crate::sync::lock::Lockfile;
+use crate::sync::manifest::{self, Mod};
+use crate::sync::modrinth;
+use crate::sync::settings::{self, InstanceSettings};
+use crate::sync::state;
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    /// Apply the corrections that are unambiguous and write the modlist.
+    pub fix: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["fix"], &[])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            fix: parsed.has("fix"),
+        })
+    }
+}
+
+/// A correction of an entry.
+enum Fix {
+    Url(String),
+    Filename(String),
+    ModrinthProject(String),
+    /// Install into the directory the project's files belong in.
+    Route(&'static str),
+}
+
+impl Fix {
+    fn apply(&self, m: &mut Mod) {
+        match self {
+            Fix::Url(url) => m.url = Some(url.clone()),
+            Fix::Filename(filename) => m.filename = filename.clone(),
+            Fix::ModrinthProject(project_id) => {
+                if let Some(pin) = m.modrinth.as_mut() {
+                    pin.project_id = project_id.clone();
+                }
+            }
+            Fix::Route(dir) => m.route(dir),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Fix::Url(url) => format!("set the url to {}", url),
+            Fix::Filename(filename) => format!("set the filename to {}", filename),
+            Fix::ModrinthProject(project_id) => format!("set the modrinth project to {}", project_id),
+            Fix::Route("saves") => "make it a world, extracted into saves/".to_string(),
+            Fix::Route("mods") => "install it into the mods directory".to_string(),
+            Fix::Route(dir) => format!("set the target_dir to {}", dir),
+        }
+    }
+}
+
+/// A problem with an entry of the modlist.
+struct Finding {
+    /// Position of the entry in the manifest's mods.
+    index: usize,
+    problem: String,
+    fix: Option<Fix>,
+}
+
+/// Checks the modlist for filenames their project has no file of, slugs in
+/// place of project ids, mods listed under two providers, files older than
+/// the lockfile pins and entries installed outside the directory their
+/// project's kind of content belongs in, such as resource packs in mods/. With `--fix` the corrections that have only one
+/// answer are written to the modlist. Fails while problems are left.
+pub fn run(config: Config) -> Result<()> {
+    let (path, mut manifest) = update::load(&config.base_dir, &config.mods_file)?;
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+    let lock = Lockfile::load(&base_dir)?.unwrap_or_default();
+    let api_key = settings::api_key()?;
+    let filter = curse_files::Filter::new(manifest.pack.minecraft.as_deref(), manifest.pack.loader.as_deref());
+
+    let mut findings = Vec::new();
+    for (index, m) in manifest.mods.iter().enumerate() {
+        let locked = m
+            .install_path(&game_dir, &mods_dir)
+            .ok()
+            .and_then(|p| lock.files.get(&state::key(&game_dir, &p)))
+            .map(|f| f.source.as_str());
+        let found = match (m.project_id(), &m.modrinth) {
+            (Some(project_id), _) => curseforge(m, project_id, locked, &filter, &api_key),
+            (None, Some(pin)) => on_modrinth(m, pin),
+            (None, None) => Ok(Vec::new()),
+        };
+        match found {
+            Ok(found) => findings.extend(found.into_iter().map(|(problem, fix)| Finding { index, problem, fix })),
+            Err(e) => println!("[WARN] {}", t!("lint-lookup-failed", name = m.name, error = format!("{:#}", e))),
+        }
+    }
+    findings.extend(duplicates(&manifest.mods));
+
+    let mut fixed = 0;
+    for finding in findings.iter() {
+        let m = &mut manifest.mods[finding.index];
+        println!("[WARN] {}: {}", m.name, finding.problem);
+        match (&finding.fix, config.fix) {
+            (Some(fix), true) => {
+                println!("       {}", t!("lint-fixed", fix = fix.describe()));
+                fix.apply(m);
+                fixed += 1;
+            }
+            (Some(fix), false) => println!("       {}", t!("lint-would-fix", fix = fix.describe())),
+            (None, _) => {}
+        }
+    }
+    if fixed > 0 {
+        manifest::save(&path, &manifest)?;
+        println!("[INFO] {}", t!("lint-fixed-count", count = fixed, modlist = path.display()));
+    }
+    let left = findings.len() - fixed;
+    if left == 0 {
+        println!("[INFO] {}", t!("lint-clean", count = manifest.mods.len()));
+        return Ok(());
+    }
+    let fixable = findings.iter().filter(|f| f.fix.is_some()).count() - fixed;
+    match fixable {
+        0 => Err(anyhow!("{} problems found in {}", left, path.display())),
+        n => Err(anyhow!("{} problems found in {}, {} can be fixed with --fix", left, path.display(), n)),
+    }
+}
+
+/// Problems of a CurseForge entry against its project, and the lockfile's
+/// `locked` file of it. Files are looked for among those of the pack's
+/// `filter` first.
+fn curseforge(m: &Mod, project_id: &str, locked: Option<&str>, filter: &curse_files::Filter, api_key: &str) -> Result<Vec<(String, Option<Fix>)>> {
+    let mut found = Vec::new();
+    let mut project_id = project_id.to_string();
+    if !project_id.chars().all(|c| c.is_ascii_digit()) {
+        let (projects, _) = curse_files::search(&project_id, None, None, curse_files::Page::FIRST, api_key)?;
+        let Some(project) = projects.iter().find(|p| p.slug == project_id) else {
+            found.push((format!("its url names {}, which is not the slug of a CurseForge project", project_id), None));
+            return Ok(found);
+        };
+        found.push((
+            format!("its url names the slug {} instead of the project id", project_id),
+            Some(Fix::Url(format!("https://www.curseforge.com/projects/{}", project.id))),
+        ));
+        project_id = project.id.to_string();
+    }
+
+    let belongs = curse_files::project(&project_id, api_key)?.class_id.and_then(curse_files::class_dir);
+    if let Some(dir) = belongs.filter(|dir| m.misrouted(dir)) {
+        let target = m.target_dir.as_deref().unwrap_or("mods");
+        found.push((format!("its project's files belong in {}/, it installs into {}/", dir, target), Some(Fix::Route(dir))));
+    }
+
+    // the lockfile records which file the pack was published with
+    let pinned = locked
+        .and_then(|s| s.strip_prefix("curseforge:"))
+        .and_then(|s| s.split_once(':'))
+        .filter(|(id, _)| *id == project_id)
+        .and_then(|(_, file_id)| file_id.parse::<u64>().ok());
+    let pinned_name = |file_id: u64| curse_files::file(&project_id, file_id, api_key).map(|f| f.file_name);
+    match curse_files::find_by_name(&project_id, &m.filename, filter, api_key)? {
+        None => {
+            let fix = pinned.map(pinned_name).transpose()?.map(Fix::Filename);
+            found.push((format!("project {} has no file named {}", project_id, m.filename), fix));
+        }
+        Some(file) => {
+            if let Some(file_id) = pinned.filter(|&id| id > file.id) {
+                let name = pinned_name(file_id)?;
+                found.push((format!("{} is older than {}, which the lockfile pins", m.filename, name), Some(Fix::Filename(name))));
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Problems of a Modrinth entry against its pinned version.
+fn on_modrinth(m: &Mod, pin: &modrinth::Pin) -> Result<Vec<(String, Option<Fix>)>> {
+    let mut found = Vec::new();
+    let version = modrinth::version(&pin.version_id)?;
+    if version.project_id != pin.project_id {
+        found.push((
+            format!("modrinth version {} is of project {}, not {}", pin.version_id, version.project_id, pin.project_id),
+            Some(Fix::ModrinthProject(version.project_id.clone())),
+        ));
+    }
+    if !version.files.iter().any(|f| f.filename == m.filename) {
+        let only = match version.files.iter().find(|f| f.primary) {
+            Some(primary) => Some(primary),
+            None if version.files.len() == 1 => version.files.first(),
+            None => None,
+        };
+        found.push((
+            format!("modrinth version {} has no file named {}", pin.version_id, m.filename),
+            only.map(|f| Fix::Filename(f.filename.clone())),
+        ));
+    }
+    Ok(found)
+}
+
+fn provider(m: &Mod) -> &'static str {
+    match (m.project_id(), &m.modrinth, &m.maven, m.local_path()) {
+        (Some(_), _, _, _) => "curseforge",
+        (None, Some(_), _, _) => "modrinth",
+        (None, None, Some(_), _) => "maven",
+        (None, None, None, Some(_)) => "a local file",
+        (None, None, None, None) => "no provider",
+    }
+}
+
+/// Entries named alike that come from different providers, one of them is
+/// likely left over from switching. Entries with conditions are left out,
+/// variants of a pack list alternatives on purpose.
+fn duplicates(mods: &[Mod]) -> Vec<Finding> {
+    let key = |m: &Mod| -> String { m.name.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase() };
+    let mut found = Vec::new();
+    for (index, m) in mods.iter().enumerate().filter(|(_, m)| m.when.is_none()) {
+        let earlier = mods[..index]
+            .iter()
+            .find(|other| other.when.is_none() && key(other) == key(m) && provider(other) != provider(m));
+        if let Some(other) = earlier {
+            found.push(Finding {
+                index,
+                problem: format!("listed from {} and, as {}, from {}", provider(m), other.name, provider(other)),
+                fix: None,
+            });
+        }
+    }
+    found
+}
@@ -0,0 +1,280 @@
+use crate::cli::Args;
+use crate::sync::launcher::Launcher;
+use crate::sync::manifest::Kind;
+use crate::sync::settings::InstanceSettings;
+use crate::sync::{self, hash};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Table,
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Sort {
+    /// The order of the modlist.
+    Modlist,
+    Name,
+    Size,
+    Source,
+}
+
+/// Fields rows can be filtered by with `--filter field=value`.
+const FILTER_FIELDS: &[&str] = &["name", "version", "loader", "side", "source", "dir", "installed"];
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub format: Format,
+    pub sort: Sort,
+    /// Field and value pairs every listed entry matches.
+    pub filters: Vec<(String, String)>,
+    /// Csv lists the installed mods alone, with the columns of the
+    /// inventory, unless all entries are asked for. Then it has the columns
+    /// of the effective modlist after those.
+    pub all: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["all"], &["format", "sort", "filter"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let format = match parsed.value("format").unwrap_or("table") {
+            "table" => Format::Table,
+            "csv" => Format::Csv,
+            "json" => Format::Json,
+            other => return Err(anyhow!("unknown format {}, expected table, csv or json", other)),
+        };
+        let sort = match parsed.value("sort").unwrap_or("modlist") {
+            "modlist" => Sort::Modlist,
+            "name" => Sort::Name,
+            "size" => Sort::Size,
+            "source" => Sort::Source,
+            other => return Err(anyhow!("unknown sort {}, expected modlist, name, size or source", other)),
+        };
+        let mut filters = Vec::new();
+        for filter in parsed.values("filter") {
+            let (field, value) = filter
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected --filter field=value, got {}", filter))?;
+            if !FILTER_FIELDS.contains(&field) {
+                return Err(anyhow!("unknown filter field {}, expected one of {}", field, FILTER_FIELDS.join(", ")));
+            }
+            filters.push((field.to_string(), value.to_lowercase()));
+        }
+        let inventory = format == Format::Csv && !parsed.has("all");
+        if inventory && filters.iter().any(|(field, value)| field == "installed" && matches!(value.as_str(), "no" | "false")) {
+            return Err(anyhow!("csv lists the installed mods alone, pass --all to list the entries that are not installed"));
+        }
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            format,
+            sort,
+            filters,
+            all: parsed.has("all"),
+        })
+    }
+}
+
+/// One entry of the effective modlist.
+#[derive(Serialize)]
+struct Row {
+    name: String,
+    version: String,
+    project_id: String,
+    file_id: String,
+    sha256: String,
+    size: u64,
+    source: String,
+    /// The loader the entry is for, the pack's unless its condition names one.
+    loader: String,
+    /// `client`, `server` or `both`.
+    side: String,
+    /// Directory relative to the game directory the entry installs into.
+    dir: String,
+    installed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    /// Note and reason together, for the table.
+    #[serde(skip)]
+    remarks: Option<String>,
+}
+
+impl Row {
+    fn field(&self, name: &str) -> String {
+        match name {
+            "name" => self.name.clone(),
+            "version" => self.version.clone(),
+            "loader" => self.loader.clone(),
+            "side" => self.side.clone(),
+            "source" => self.source.clone(),
+            "dir" => self.dir.clone(),
+            "installed" => if self.installed { "yes" } else { "no" }.to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Names match by substring, every other field in full.
+    fn matches(&self, field: &str, value: &str) -> bool {
+        let actual = self.field(field).to_lowercase();
+        match field {
+            "name" => actual.contains(value),
+            "installed" => actual == value.replace("true", "yes").replace("false", "no"),
+            _ => actual == value,
+        }
+    }
+}
+
+/// Lists the entries of the effective modlist, after overlays and conditions
+/// are applied, with their identifiers, hash and size once installed. Csv
+/// keeps to the installed ones unless `all` asks for every entry.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+    let effective = sync::load_effective(&base_dir, &config.mods_file)?;
+    let metadata = Launcher::detect(&base_dir).metadata(&base_dir, &mods_dir)?;
+
+    let pack_loader = effective.pack.loader.as_deref().map(|l| l.split_once('-').map_or(l, |(name, _)| name));
+
+    let mut entries = Vec::new();
+    for m in effective.mods.iter() {
+        entries.push((m, m.install_path(&game_dir, &mods_dir)?));
+    }
+    let mut cache = hash::Cache::load(&base_dir);
+    let paths: Vec<PathBuf> = entries.iter().filter(|(_, p)| p.is_file()).map(|(_, p)| p.clone()).collect();
+    let mut hashes = cache.hash_all(&paths)?;
+    let _ = cache.save();
+
+    let mut rows = Vec::new();
+    for (m, path) in entries {
+        let installed = path.is_file();
+        let project_id = m.project_id().unwrap_or_default().to_string();
+        let file_id = metadata
+            .get(&project_id)
+            .filter(|meta| meta.filename == m.filename)
+            .and_then(|meta| meta.file_id())
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+        rows.push(Row {
+            name: m.name.clone(),
+            version: m.version.clone(),
+            source: source(m.url.as_deref()),
+            project_id,
+            file_id,
+            sha256: hashes.remove(&path).unwrap_or_default(),
+            size: if installed { fs::metadata(&path)?.len() } else { 0 },
+            loader: m
+                .when
+                .as_ref()
+                .and_then(|w| w.loader.as_deref())
+                .or(pack_loader)
+                .unwrap_or_default()
+                .to_string(),
+            side: m.when.as_ref().and_then(|w| w.side.clone()).unwrap_or_else(|| "both".to_string()),
+            dir: m.target_dir.clone().unwrap_or_else(|| if m.kind == Kind::World { "saves" } else { "mods" }.to_string()),
+            installed,
+            note: m.note.clone(),
+            reason: m.reason.clone(),
+            remarks: m.remarks(),
+        });
+    }
+    let inventory = config.format == Format::Csv && !config.all;
+    rows.retain(|r| (r.installed || !inventory) && config.filters.iter().all(|(field, value)| r.matches(field, value)));
+    match config.sort {
+        Sort::Modlist => {}
+        Sort::Name => rows.sort_by_key(|r| r.name.to_lowercase()),
+        Sort::Size => rows.sort_by_key(|r| std::cmp::Reverse(r.size)),
+        Sort::Source => rows.sort_by(|a, b| a.source.cmp(&b.source)),
+    }
+
+    match config.format {
+        Format::Csv => print_csv(&rows, config.all),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+        Format::Table => print_table(&rows, &mods_dir),
+    }
+    Ok(())
+}
+
+fn source(url: Option<&str>) -> String {
+    match url {
+        Some(u) if u.contains("curseforge.com") => "curseforge".to_string(),
+        Some(u) => u.to_string(),
+        None => "manual".to_string(),
+    }
+}
+
+/// Columns of the inventory of installed mods csv lists by default.
+const INSTALLED_COLUMNS: usize = 7;
+
+fn print_csv(rows: &[Row], all: bool) {
+    let header = "name,version,project_id,file_id,sha256,size,source,loader,side,dir,installed,note,reason";
+    let columns = if all { usize::MAX } else { INSTALLED_COLUMNS };
+    println!("{}", header.split(',').take(columns).collect::<Vec<_>>().join(","));
+    for r in rows {
+        let fields = [
+            r.name.as_str(),
+            r.version.as_str(),
+            r.project_id.as_str(),
+            r.file_id.as_str(),
+            r.sha256.as_str(),
+            &r.size.to_string(),
+            r.source.as_str(),
+            r.loader.as_str(),
+            r.side.as_str(),
+            r.dir.as_str(),
+            if r.installed { "true" } else { "false" },
+            r.note.as_deref().unwrap_or_default(),
+            r.reason.as_deref().unwrap_or_default(),
+        ];
+        let line: Vec<String> = fields.iter().take(columns).map(|f| csv_field(f)).collect();
+        println!("{}", line.join(","));
+    }
+}
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn print_table(rows: &[Row], mods_dir: &Path) {
+    let size = |r: &Row| match r.installed {
+        true => sync::report::human_size(r.size),
+        false => "missing".to_string(),
+    };
+    if sync::log::plain() {
+        for r in rows {
+            match &r.remarks {
+                Some(remarks) => println!("{}, version {}, project {}, {}, {}", r.name, r.version, r.project_id, size(r), remarks),
+                None => println!("{}, version {}, project {}, {}", r.name, r.version, r.project_id, size(r)),
+            }
+        }
+    } else {
+        println!("{:<36} {:<20} {:<10} {:>10}", "NAME", "VERSION", "PROJECT", "SIZE");
+        for r in rows {
+            println!("{:<36} {:<20} {:<10} {:>10}", r.name, r.version, r.project_id, size(r));
+            if let Some(remarks) = &r.remarks {
+                println!("  {}", remarks);
+            }
+        }
+    }
+    let installed = rows.iter().filter(|r| r.installed).count();
+    println!("[INFO] {} entries, {} of them installed in {}", rows.len(), installed, mods_dir.display());
+}
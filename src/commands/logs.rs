@@ -0,0 +1,63 @@
+use crate::cli::Args;
+use crate::sync::log;
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Config {
+    pub base_dir: String,
+    pub last: bool,
+    pub failed: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["last", "failed"], &[])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+
+        Ok(Config {
+            base_dir,
+            last: parsed.has("last"),
+            failed: parsed.has("failed"),
+        })
+    }
+}
+
+/// Lists previous runs with their outcome, or prints the most recent one
+/// with `--last`. `--failed` restricts both to runs that logged errors.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let mut runs = log::list_runs(&base_dir)?;
+    if config.failed {
+        runs.retain(|r| failed(r));
+    }
+
+    if config.last {
+        let last = runs.last().ok_or_else(|| anyhow!("no matching runs logged for {}", base_dir.display()))?;
+        print!("{}", fs::read_to_string(last)?);
+        return Ok(());
+    }
+
+    if runs.is_empty() {
+        println!("[INFO] no matching runs logged for {}", base_dir.display());
+    }
+    for run in runs.iter() {
+        let status = if failed(run) { "FAILED" } else { "ok" };
+        match log::plain() {
+            true => println!("{}: {}", status, run.display()),
+            false => println!("{:<8} {}", status, run.display()),
+        }
+    }
+    Ok(())
+}
+
+/// A run failed when it logged any error, even if it made it to the end.
+fn failed(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .map(|c| c.contains("[ERR!]") || !c.contains("Run finished successfully"))
+        .unwrap_or(true)
+}
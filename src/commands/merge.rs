@@ -0,0 +1,63 @@
+use crate::cli::Args;
+use crate::sync::log_to_file;
+use crate::sync::manifest::{self, Strategy};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+pub struct Config {
+    pub inputs: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub strategy: Strategy,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["o", "out", "strategy"])?;
+        if parsed.positional.len() < 2 {
+            return Err(anyhow!("expected at least two modlists to merge"));
+        }
+
+        let output = parsed
+            .value("out")
+            .or(parsed.value("o"))
+            .ok_or_else(|| anyhow!("expected -o <file> for the merged modlist"))?;
+
+        Ok(Config {
+            inputs: parsed.positional.iter().map(PathBuf::from).collect(),
+            output: PathBuf::from(output),
+            strategy: Strategy::parse(parsed.value("strategy").unwrap_or("fail"))?,
+        })
+    }
+}
+
+/// Merges the given modlists left to right. The pack header of the first
+/// input is kept; later inputs only contribute entries.
+pub fn run(config: Config) -> Result<()> {
+    let mut inputs = config.inputs.iter();
+    let first = inputs.next().expect("expected at least one input");
+    let mut merged = load(first)?;
+
+    for path in inputs {
+        let other = load(path)?;
+        for c in manifest::merge_into(&mut merged, other, config.strategy)? {
+            println!("[WARN] conflict on {}: kept {}, dropped {}", c.key, c.kept, c.dropped);
+            let _ = log_to_file(&format!(
+                "[WARN] merge conflict on {}: kept {}, dropped {}",
+                c.key, c.kept, c.dropped
+            ));
+        }
+    }
+
+    manifest::save(&config.output, &merged)?;
+    println!(
+        "[INFO] Wrote {} entries to {}",
+        merged.mods.len(),
+        config.output.display()
+    );
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<manifest::Manifest> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    Ok(manifest::load(path, dir)?.manifest)
+}
@@ -0,0 +1,59 @@
+use crate::cli::Args;
+use crate::sync::log_to_file;
+use crate::sync::manifest;
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &[])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+        })
+    }
+}
+
+/// Upgrades a legacy bare-array modlist to the current manifest format in
+/// place, keeping a copy of the original next to it.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let path = base_dir.join(&config.mods_file);
+    let loaded = manifest::load(&path, &base_dir)?;
+
+    if !loaded.legacy {
+        println!("[INFO] {} is already at format version {}", path.display(), manifest::FORMAT_VERSION);
+        return Ok(());
+    }
+
+    let backup = path.with_extension("json.bak");
+    fs::copy(&path, &backup)?;
+    manifest::save(&path, &loaded.manifest)?;
+
+    let _ = log_to_file(&format!(
+        "[INFO] Migrated {} to format version {} ({} entries), original kept at {}",
+        path.display(),
+        manifest::FORMAT_VERSION,
+        loaded.manifest.mods.len(),
+        backup.display()
+    ));
+    println!(
+        "[INFO] Migrated {} entries to format version {}, original kept at {}",
+        loaded.manifest.mods.len(),
+        manifest::FORMAT_VERSION,
+        backup.display()
+    );
+    Ok(())
+}
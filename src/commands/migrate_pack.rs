@@ -0,0 +1,189 @@
+use super::update;
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::curse_files;
+use crate::sync::manifest::{self, Manifest};
+use crate::sync::settings;
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    /// Minecraft version to move the pack to.
+    pub minecraft: Option<String>,
+    /// Loader to move the pack to, e.g. from forge to neoforge.
+    pub loader: Option<String>,
+    /// Where the candidate manifest is written, next to the modlist by
+    /// default.
+    pub out: Option<PathBuf>,
+    /// Search for alternatives to entries without a file for the target.
+    pub suggest: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["suggest"], &["to-mc", "to-loader", "out"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let minecraft = parsed.value("to-mc").map(str::to_string);
+        let loader = parsed.value("to-loader").map(str::to_lowercase);
+        if minecraft.is_none() && loader.is_none() {
+            return Err(anyhow!("expected --to-mc or --to-loader with what to move the pack to"));
+        }
+        if let Some(loader) = loader.as_deref().filter(|l| curse_files::loader_type(l).is_none()) {
+            return Err(anyhow!("unknown loader {}, expected fabric, quilt, forge or neoforge", loader));
+        }
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            minecraft,
+            loader,
+            out: parsed.value("out").map(PathBuf::from),
+            suggest: parsed.has("suggest"),
+        })
+    }
+}
+
+/// What moving a pack to another game version or loader came to.
+pub struct Migration {
+    /// The pack with every entry that could be resolved moved to a file for
+    /// the target, the others left out.
+    pub candidate: Manifest,
+    /// (name, current file, file for the target) of the resolved entries.
+    pub resolved: Vec<(String, String, String)>,
+    /// Positions in the current manifest of the entries whose project has
+    /// no file for the target.
+    pub stranded: Vec<usize>,
+    /// Entries that aren't from CurseForge or couldn't be looked up, kept as
+    /// they are to be checked by hand.
+    pub by_hand: Vec<String>,
+}
+
+/// Re-resolves every CurseForge entry of `manifest` to the newest file of
+/// its project tagged for `minecraft` and `loader`. Version ranges are not
+/// applied, version numbers of mods usually start over with a new game
+/// version.
+pub fn migrate(manifest: &Manifest, minecraft: Option<&str>, loader: Option<&str>, api_key: &str) -> Result<Migration> {
+    let mut migration = Migration {
+        candidate: Manifest { mods: Vec::new(), ..manifest.clone() },
+        resolved: Vec::new(),
+        stranded: Vec::new(),
+        by_hand: Vec::new(),
+    };
+    for (index, m) in manifest.mods.iter().enumerate() {
+        let Some(project_id) = m.project_id() else {
+            migration.by_hand.push(m.name.clone());
+            migration.candidate.mods.push(m.clone());
+            continue;
+        };
+        match update::newest(project_id, None, minecraft, loader, chrono::Duration::zero(), None, api_key) {
+            Ok(Some((file, found))) => {
+                let mut moved = m.clone();
+                moved.version = found.unwrap_or_else(|| m.version.clone());
+                moved.filename = file.file_name.clone();
+                migration.resolved.push((m.name.clone(), m.filename.clone(), file.file_name));
+                migration.candidate.mods.push(moved);
+            }
+            Ok(None) => migration.stranded.push(index),
+            Err(e) => {
+                println!("[WARN] {}", t!("migrate-pack-lookup-failed", name = m.name, error = format!("{:#}", e)));
+                migration.by_hand.push(m.name.clone());
+                migration.candidate.mods.push(m.clone());
+            }
+        }
+    }
+    Ok(migration)
+}
+
+/// Prints what a migration to `target` found for each entry and writes its
+/// candidate manifest to `out`. With `old_loader`, only the loader changed,
+/// so entries without a file for the target only exist for that loader.
+pub fn report(
+    manifest: &Manifest,
+    migration: &Migration,
+    target: &str,
+    old_loader: Option<&str>,
+    out: &Path,
+    suggest: bool,
+    api_key: &str,
+) -> Result<()> {
+    for (name, from, to) in migration.resolved.iter() {
+        match from == to {
+            true => println!("[INFO] {}", t!("migrate-pack-unchanged", name = name, file = from)),
+            false => println!("[INFO] {}", t!("migrate-pack-moved", name = name, from = from, to = to)),
+        }
+    }
+    for name in migration.by_hand.iter() {
+        println!("[WARN] {}", t!("migrate-pack-by-hand", name = name));
+    }
+    for m in migration.stranded.iter().map(|&i| &manifest.mods[i]) {
+        match old_loader {
+            Some(old) => println!("[WARN] {}", t!("migrate-pack-loader-only", name = m.name, loader = old)),
+            None => println!("[WARN] {}", t!("migrate-pack-stranded", name = m.name, target = target)),
+        }
+    }
+    if suggest {
+        // alternatives are searched for the target, among the current entries
+        let target = Manifest { pack: migration.candidate.pack.clone(), ..manifest.clone() };
+        update::suggest(&target, &migration.stranded, api_key);
+    }
+    manifest::save(out, &migration.candidate)?;
+    println!(
+        "[INFO] {}",
+        t!(
+            "migrate-pack-summary",
+            resolved = migration.resolved.len(),
+            entries = manifest.mods.len(),
+            stranded = migration.stranded.len(),
+            by_hand = migration.by_hand.len()
+        )
+    );
+    println!("[INFO] {}", t!("migrate-pack-written", path = out.display()));
+    Ok(())
+}
+
+/// Works out the pack for another Minecraft version or loader: which
+/// entries have a file for it and which don't, written as a candidate
+/// manifest next to the current one, which is left untouched.
+pub fn run(config: Config) -> Result<()> {
+    let (path, manifest) = update::load(&config.base_dir, &config.mods_file)?;
+    let api_key = settings::api_key()?;
+    let current = manifest.pack.loader.as_deref().map(|l| l.split('-').next().unwrap_or(l).to_lowercase());
+    let minecraft = config.minecraft.clone().or_else(|| manifest.pack.minecraft.clone());
+    let loader = config.loader.clone().or_else(|| current.clone());
+    if minecraft == manifest.pack.minecraft && loader == current {
+        return Err(anyhow!("the pack is for {} already", target(minecraft.as_deref(), loader.as_deref())));
+    }
+    let target = target(minecraft.as_deref(), loader.as_deref());
+
+    let mut migration = migrate(&manifest, minecraft.as_deref(), loader.as_deref(), &api_key)?;
+    migration.candidate.pack.minecraft = minecraft.clone();
+    // a loader build is made for one loader and game version
+    if migration.candidate.pack.loader != loader {
+        if let Some(built) = manifest.pack.loader.as_deref().filter(|l| l.contains('-')) {
+            println!("[WARN] {}", t!("migrate-pack-loader-version", version = built, target = target));
+        }
+        migration.candidate.pack.loader = loader.clone();
+    }
+
+    let out = config.out.unwrap_or_else(|| {
+        let suffix: Vec<&str> = [config.loader.as_deref(), config.minecraft.as_deref()].into_iter().flatten().collect();
+        path.with_file_name(format!("modlist-{}.json", suffix.join("-")))
+    });
+    let old_loader = current.as_deref().filter(|_| loader != current && minecraft == manifest.pack.minecraft);
+    report(&manifest, &migration, &target, old_loader, &out, config.suggest, &api_key)
+}
+
+fn target(minecraft: Option<&str>, loader: Option<&str>) -> String {
+    match (loader, minecraft) {
+        (Some(loader), Some(minecraft)) => format!("{} on minecraft {}", loader, minecraft),
+        (Some(loader), None) => loader.to_string(),
+        (None, Some(minecraft)) => format!("minecraft {}", minecraft),
+        (None, None) => "any version".to_string(),
+    }
+}
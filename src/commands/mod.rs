@@ -0,0 +1,45 @@
+pub mod migrate;
+pub mod fmt;
+pub mod merge;
+pub mod resolve;
+pub mod setup;
+pub mod doctor;
+pub mod completions;
+pub mod self_update;
+pub mod logs;
+pub mod list;
+pub mod search;
+pub mod info;
+pub mod deps;
+pub mod restore_backup;
+pub mod publish;
+pub mod export;
+pub mod plan;
+pub mod apply;
+pub mod update;
+pub mod outdated;
+pub mod verify;
+pub mod remove;
+pub mod daemon;
+pub mod analyze;
+pub mod quarantine;
+pub mod preview;
+pub mod join;
+pub mod create;
+pub mod prefetch;
+pub mod serve;
+pub mod migrate_pack;
+pub mod blame;
+pub mod bisect;
+pub mod lint;
+pub mod control;
+pub mod bench;
+pub mod fleet;
+pub mod retry;
+pub mod gc;
+pub mod stats;
+pub mod import_ftb;
+pub mod inspect;
+pub mod diff;
+pub mod encrypt_secret;
+pub mod support_bundle;
@@ -0,0 +1,143 @@
+use super::update;
+use crate::cli::Args;
+use crate::sync::curse_files::{self, ProjectDetails};
+use crate::sync::manifest::Manifest;
+use crate::sync::{log, settings};
+use crate::sync::version::Change;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    /// Largest kind of update to list.
+    pub only: Change,
+    /// Months without a new file after which a project looks abandoned.
+    pub stale_months: u32,
+    /// Search for alternatives to entries without a file for the pack.
+    pub suggest: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["suggest"], &["only", "stale-months"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            only: parsed.value("only").map_or(Ok(Change::Major), Change::parse)?,
+            stale_months: match parsed.value("stale-months") {
+                Some(months) => months.parse().map_err(|_| anyhow!("--stale-months expects a number, got {}", months))?,
+                None => 12,
+            },
+            suggest: parsed.has("suggest"),
+        })
+    }
+}
+
+/// Lists the entries with a newer file available and whether the update
+/// is a patch, minor or major one, without changing the modlist. Projects
+/// that look abandoned are pointed out so replacements can be planned.
+pub fn run(config: Config) -> Result<()> {
+    let (_, manifest) = update::load(&config.base_dir, &config.mods_file)?;
+    let api_key = settings::api_key()?;
+    let (available, stranded) = update::available(&manifest, &update::policy(&config.base_dir)?, None, &api_key)?;
+
+    let listed: Vec<_> = available.iter().filter(|u| u.change <= config.only).collect();
+    for u in listed.iter() {
+        match log::plain() {
+            true => println!("{}, {} update from {} to {}", u.name, u.change.as_str(), u.from, u.file.file_name),
+            false => println!("{:<30} {:<6} {} -> {}", u.name, u.change.as_str(), u.from, u.file.file_name),
+        }
+    }
+    println!("[INFO] {} of {} entries have updates", listed.len(), manifest.mods.len());
+    report_expired(&manifest);
+    report_abandoned(&manifest, config.stale_months, &api_key);
+    if config.suggest {
+        update::suggest(&manifest, &stranded, &api_key);
+    }
+    Ok(())
+}
+
+/// Warns about the holds of entries that are over, a temporary downgrade
+/// forgotten about otherwise stays for good.
+fn report_expired(manifest: &Manifest) {
+    let today = Utc::now().date_naive();
+    for m in manifest.mods.iter() {
+        if let Some(until) = m.hold_expired(today) {
+            println!(
+                "[WARN] the hold of {} on {} ended {}{}, run update to move it on or extend hold_until",
+                m.name,
+                m.filename,
+                until,
+                m.reason.as_ref().map(|r| format!(" ({})", r)).unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Warns about the CurseForge projects of the modlist that have no file for
+/// the pack's Minecraft version and loader or no new file in `stale_months`.
+fn report_abandoned(manifest: &Manifest, stale_months: u32, api_key: &str) {
+    let minecraft = manifest.pack.minecraft.as_deref();
+    let loader = manifest.pack.loader.as_deref().map(|l| l.split('-').next().unwrap_or(l));
+    let ids: Vec<u64> = manifest.mods.iter().filter_map(|m| m.project_id()?.parse().ok()).collect();
+    // looked up together, one at a time when CurseForge refuses the batch
+    let mut projects: HashMap<String, ProjectDetails> = match curse_files::projects(&ids, api_key) {
+        Ok(projects) => projects.into_iter().map(|p| (p.id.to_string(), p)).collect(),
+        Err(_) => HashMap::new(),
+    };
+    let mut abandoned = 0;
+    for m in manifest.mods.iter() {
+        let Some(project_id) = m.project_id() else {
+            continue;
+        };
+        let project = match projects.remove(project_id).map_or_else(|| curse_files::project(project_id, api_key), Ok) {
+            Ok(project) => project,
+            Err(e) => {
+                println!("[WARN] could not look up {}: {:#}", m.name, e);
+                continue;
+            }
+        };
+        let signals = signals(&project, minecraft, loader, stale_months);
+        if signals.is_empty() {
+            continue;
+        }
+        abandoned += 1;
+        println!(
+            "[WARN] {} looks abandoned, {} ({} downloads)",
+            m.name,
+            signals.join(", "),
+            project.download_count as u64
+        );
+    }
+    if abandoned > 0 {
+        println!("[INFO] {} entries may need a replacement", abandoned);
+    }
+}
+
+fn signals(project: &ProjectDetails, minecraft: Option<&str>, loader: Option<&str>, stale_months: u32) -> Vec<String> {
+    let mut signals = Vec::new();
+    if let Some(minecraft) = minecraft {
+        let loader_type = loader.and_then(curse_files::loader_type);
+        let has_file = project.latest_files_indexes.iter().any(|f| {
+            f.game_version == minecraft && (loader_type.is_none() || f.mod_loader.is_none() || f.mod_loader == loader_type)
+        });
+        if !has_file {
+            signals.push(format!("no file for {}{}", minecraft, loader.map(|l| format!(" {}", l)).unwrap_or_default()));
+        }
+    }
+    if let Ok(released) = DateTime::parse_from_rfc3339(&project.date_released) {
+        let months = (Utc::now() - released.with_timezone(&Utc)).num_days() / 30;
+        if months > i64::from(stale_months) {
+            signals.push(format!("last file released {} months ago on {}", months, released.format("%Y-%m-%d")));
+        }
+    }
+    signals
+}
@@ -0,0 +1,80 @@
+use crate::cli::Args;
+use crate::sync::plan::{Action, PlanFile};
+use crate::sync::{self, Mode};
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: String,
+    /// File the plan is written to.
+    pub out: PathBuf,
+    pub trace: bool,
+    /// Warning codes that fail the plan, passed on to the sync.
+    pub deny: Vec<String>,
+    pub strict: bool,
+    /// CurseForge projects trusted although they changed hands.
+    pub acknowledged: Vec<String>,
+    /// Modlist to plan instead of the instance's, `-` for stdin.
+    pub modlist: Option<String>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["trace-plan", "strict"], &["out", "deny", "acknowledge-owner", "modlist"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let out = parsed.value("out").unwrap_or("plan.json");
+
+        Ok(Config {
+            base_dir,
+            out: PathBuf::from(out),
+            trace: parsed.has("trace-plan"),
+            deny: parsed.values("deny").into_iter().map(str::to_string).collect(),
+            strict: parsed.has("strict"),
+            acknowledged: parsed.values("acknowledge-owner").into_iter().map(str::to_string).collect(),
+            modlist: parsed.value("modlist").map(str::to_string),
+        })
+    }
+}
+
+/// Works out the changes a sync would make and writes them to a plan file
+/// for review, without touching the instance. `apply` carries it out later.
+pub fn run(config: Config) -> Result<()> {
+    let mut args = vec!["sync".to_string(), config.base_dir.clone()];
+    if config.trace {
+        args.push("--trace-plan".to_string());
+    }
+    for codes in config.deny.iter() {
+        args.extend(["--deny".to_string(), codes.clone()]);
+    }
+    if config.strict {
+        args.push("--strict".to_string());
+    }
+    for project_id in config.acknowledged.iter() {
+        args.extend(["--acknowledge-owner".to_string(), project_id.clone()]);
+    }
+    if let Some(modlist) = &config.modlist {
+        args.extend(["--modlist".to_string(), modlist.clone()]);
+    }
+    let mut sync_config = sync::Config::build(&args)?;
+    sync_config.mode = Mode::Plan(config.out.clone());
+    sync::run(sync_config)?;
+
+    let saved = PlanFile::load(&config.out)?;
+    let mut changes = 0;
+    for line in saved.plan.actions.iter().filter_map(Action::describe) {
+        println!("  {}", line);
+        changes += 1;
+    }
+    println!(
+        "[INFO] wrote a plan with {} changes to {}, apply it with `modpack-sync apply {} {}`",
+        changes,
+        config.out.display(),
+        config.out.display(),
+        config.base_dir
+    );
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use crate::sync::{self, Mode};
+use anyhow::Result;
+
+pub struct Config {
+    pub sync: sync::Config,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let mut sync = sync::Config::build(args)?;
+        sync.mode = Mode::Prefetch;
+        Ok(Config { sync })
+    }
+}
+
+/// Downloads the files the next sync would install without installing
+/// them, pulling the instance's source into a copy first, so applying the
+/// update in the maintenance window or at the next launch is quick.
+pub fn run(config: Config) -> Result<()> {
+    sync::run(config.sync)
+}
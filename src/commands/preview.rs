@@ -0,0 +1,159 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::manifest::Mod;
+use crate::sync::report::human_size;
+use crate::sync::{self, curse_files, extends, hosts, log, settings};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+pub struct Config {
+    /// Url or path of the pack's manifest.
+    pub location: String,
+    /// Instance to compare the pack with.
+    pub base_dir: Option<String>,
+    pub mods_file: String,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &[])?;
+        let location = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected the url or path of a pack's manifest"))?
+            .clone();
+
+        Ok(Config {
+            location,
+            base_dir: parsed.positional.get(1).cloned(),
+            mods_file: "modlist.json".to_string(),
+        })
+    }
+}
+
+/// Sizes of local entries and, with an API key, CurseForge entries, looked up
+/// a few at a time. Entries that can't be looked up are left out.
+fn sizes(mods: &[Mod], filter: &curse_files::Filter, api_key: Option<&str>) -> HashMap<usize, u64> {
+    let next = AtomicUsize::new(0);
+    let found = Mutex::new(HashMap::new());
+    thread::scope(|s| {
+        for _ in 0..hosts::DEFAULT_JOBS.min(mods.len()) {
+            s.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(m) = mods.get(i) else {
+                        break;
+                    };
+                    let size = match (m.local_path(), m.project_id(), api_key) {
+                        (Some(path), _, _) => fs::metadata(path).ok().map(|meta| meta.len()),
+                        (None, Some(project_id), Some(api_key)) => curse_files::find_by_name(project_id, &m.filename, filter, api_key)
+                            .ok()
+                            .flatten()
+                            .map(|file| file.file_length)
+                            .filter(|len| *len > 0),
+                        _ => None,
+                    };
+                    if let Some(size) = size {
+                        found.lock().expect("sizes lock poisoned").insert(i, size);
+                    }
+                }
+            });
+        }
+    });
+    found.into_inner().expect("sizes lock poisoned")
+}
+
+fn source(m: &Mod) -> &'static str {
+    match () {
+        _ if m.maven.is_some() => "maven",
+        _ if m.local_path().is_some() => "local",
+        _ if m.manual => "manual",
+        _ if m.project_id().is_some() => "curseforge",
+        _ => "none",
+    }
+}
+
+/// Shows what a pack published anywhere contains, its game version, loader
+/// and download size, and how it differs from an instance, without making
+/// it the instance's source.
+pub fn run(config: Config) -> Result<()> {
+    // relative paths of the manifest and its parents start where it is
+    let base_dir = match Path::new(&config.location).parent() {
+        Some(dir) if !config.location.contains("://") && !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => env::current_dir()?,
+    };
+    let pack = extends::load(&config.location, &base_dir)?.manifest;
+    let api_key = settings::api_key().ok();
+    let filter = curse_files::Filter::new(pack.pack.minecraft.as_deref(), pack.pack.loader.as_deref());
+    let sizes = sizes(&pack.mods, &filter, api_key.as_deref());
+
+    println!(
+        "[INFO] {}",
+        t!(
+            "preview-pack",
+            pack = pack.pack.name,
+            version = pack.pack.version,
+            minecraft = pack.pack.minecraft.as_deref().unwrap_or("any"),
+            loader = pack.pack.loader.as_deref().unwrap_or("any loader")
+        )
+    );
+    let total: u64 = sizes.values().sum();
+    let unknown = pack.mods.len() - sizes.len();
+    match unknown {
+        0 => println!("[INFO] {}", t!("preview-size", count = pack.mods.len(), size = human_size(total))),
+        _ => println!(
+            "[INFO] {}",
+            t!("preview-size-unknown", count = pack.mods.len(), size = human_size(total), unknown = unknown)
+        ),
+    }
+    if api_key.is_none() {
+        println!("[WARN] {}", t!("preview-no-api-key"));
+    }
+
+    let width = pack.mods.iter().map(|m| m.name.len()).max().unwrap_or(0).min(40);
+    for (i, m) in pack.mods.iter().enumerate() {
+        let size = sizes.get(&i).map_or("?".to_string(), |s| human_size(*s));
+        let side = m.when.as_ref().and_then(|w| w.side.as_deref()).unwrap_or("both");
+        match log::plain() {
+            true => println!("{} {} {} {} {}", m.name, m.version, source(m), side, size),
+            false => println!("  {:<width$}  {:<16} {:<10} {:<6} {:>10}", m.name, m.version, source(m), side, size, width = width),
+        }
+    }
+
+    if let Some(instance) = &config.base_dir {
+        compare(&pack.mods, &PathBuf::from(instance), &config.mods_file)?;
+    }
+    Ok(())
+}
+
+fn remarks(m: &Mod) -> String {
+    m.remarks().map(|r| format!(" ({})", r)).unwrap_or_default()
+}
+
+/// Prints the entries `pack` adds, drops and changes against the instance.
+fn compare(pack: &[Mod], base_dir: &Path, mods_file: &str) -> Result<()> {
+    let current = sync::load_effective(base_dir, mods_file)?;
+    println!(
+        "[INFO] {}",
+        t!("preview-compared", pack = current.pack.name, version = current.pack.version, dir = base_dir.display())
+    );
+    let mut same = 0;
+    for m in pack {
+        match current.mods.iter().find(|c| c.key() == m.key()) {
+            None => println!("  + {} {}{}", m.name, m.version, remarks(m)),
+            Some(c) if c.filename != m.filename => println!("  ~ {} {} -> {}{}", m.name, c.version, m.version, remarks(m)),
+            Some(_) => same += 1,
+        }
+    }
+    for c in current.mods.iter().filter(|c| !pack.iter().any(|m| m.key() == c.key())) {
+        println!("  - {} {}{}", c.name, c.version, remarks(c));
+    }
+    println!("[INFO] {}", t!("preview-same", count = same));
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use crate::cli::Args;
+use crate::sync::lock::{self, Lockfile};
+use crate::sync::manifest::{self, Manifest};
+use crate::sync::redistribution::{self, Verdict};
+use crate::sync::settings::{self, InstanceSettings};
+use crate::sync::{self, dist};
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Bump {
+    Major,
+    Minor,
+    Patch,
+    None,
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    /// Backend to publish to, the instance's `publish_to` when unset.
+    pub to: Option<String>,
+    pub bump: Bump,
+    /// Release channel to publish to instead of the main one.
+    pub channel: Option<String>,
+    /// Push jars whose authors forbid redistribution anyway, for packs
+    /// that were given permission.
+    pub redistribute_restricted: bool,
+    /// Percentage of clients the version is rolled out to.
+    pub rollout: Option<u8>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["no-bump", "redistribute-restricted"], &["to", "bump", "channel", "rollout"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let bump = match (parsed.has("no-bump"), parsed.value("bump").unwrap_or("patch")) {
+            (true, _) => Bump::None,
+            (false, "major") => Bump::Major,
+            (false, "minor") => Bump::Minor,
+            (false, "patch") => Bump::Patch,
+            (false, other) => return Err(anyhow!("unknown bump {}, expected major, minor or patch", other)),
+        };
+        let rollout = match parsed.value("rollout") {
+            Some(percent) => match percent.trim_end_matches('%').parse::<u8>() {
+                Ok(100) => None,
+                Ok(percent @ 1..=99) => Some(percent),
+                _ => return Err(anyhow!("--rollout expects a percentage from 1 to 100, got {}", percent)),
+            },
+            None => None,
+        };
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            to: parsed.value("to").map(str::to_string),
+            bump,
+            channel: parsed.value("channel").map(str::to_string),
+            redistribute_restricted: parsed.has("redistribute-restricted"),
+            rollout,
+        })
+    }
+}
+
+/// Increments one component of a `major.minor.patch` version, resetting the
+/// ones after it.
+pub fn bump_version(version: &str, bump: Bump) -> Result<String> {
+    let parts: Vec<u64> = version
+        .trim()
+        .split('.')
+        .map(|p| p.parse::<u64>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| anyhow!("pack version {} is not numeric, pass --no-bump or fix it", version))?;
+    let mut parts: Vec<u64> = parts.into_iter().chain([0, 0, 0]).take(3).collect();
+    match bump {
+        Bump::Major => parts = vec![parts[0] + 1, 0, 0],
+        Bump::Minor => parts = vec![parts[0], parts[1] + 1, 0],
+        Bump::Patch => parts[2] += 1,
+        Bump::None => return Ok(version.to_string()),
+    }
+    Ok(parts.iter().map(u64::to_string).collect::<Vec<_>>().join("."))
+}
+
+/// Problems that would break instances syncing the pack.
+fn validate(base_dir: &Path, mods_file: &str, manifest: &Manifest) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Err(e) = sync::load_effective(base_dir, mods_file) {
+        problems.push(format!("modlist does not resolve: {:#}", e));
+    }
+    if manifest.pack.name.trim().is_empty() {
+        problems.push("pack has no name".to_string());
+    }
+
+    let mut filenames = HashSet::new();
+    for m in manifest.mods.iter() {
+        // the pack also lands on file systems that ignore case
+        if !filenames.insert(m.filename.to_lowercase()) {
+            problems.push(format!("{} is listed more than once", m.filename));
+        }
+        if let Some(path) = m.local_path() {
+            if !path.is_file() {
+                problems.push(format!("{}: local file {} does not exist", m.filename, path.display()));
+            }
+        } else if m.manual {
+            if m.sha256.is_none() && m.project_id().is_none() {
+                problems.push(format!("{} is manual but has no sha256 or url to verify it against", m.filename));
+            }
+        } else if m.maven.is_none() && m.project_id().is_none() && !m.filename.ends_with(".disabled") {
+            problems.push(format!("{} has no url to download it from", m.filename));
+        }
+    }
+    problems
+}
+
+/// Checks the jars the pack ships itself may be redistributed, warning
+/// about the ones nothing says about and returning the ones their authors
+/// forbid to be shared.
+fn restricted_jars(manifest: &Manifest) -> Result<Vec<String>> {
+    let api_key = settings::api_key().ok();
+    let mut restricted = Vec::new();
+    for m in manifest.mods.iter() {
+        let Some(path) = m.local_path().filter(|p| p.is_file()) else {
+            continue;
+        };
+        match redistribution::check(&path, api_key.as_deref())? {
+            Verdict::Allowed { license } => println!("[INFO] {} may be redistributed under {}", m.filename, license),
+            Verdict::Forbidden { reason } => restricted.push(format!("{}: {}", m.filename, reason)),
+            Verdict::Unknown { license: Some(license) } => println!(
+                "[WARN] {} is licensed as \"{}\", make sure it may be redistributed before publishing it",
+                m.filename, license
+            ),
+            Verdict::Unknown { license: None } => println!(
+                "[WARN] {} declares no license and no provider lists one, make sure it may be redistributed",
+                m.filename
+            ),
+        }
+    }
+    Ok(restricted)
+}
+
+/// Validates the pack, bumps its version and publishes it so instances
+/// with it as their `source` pick it up on their next sync.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let to = config
+        .to
+        .or(instance.publish_to)
+        .ok_or_else(|| anyhow!("no backend to publish to, pass --to or set publish_to in the instance settings"))?;
+    let mut backend = dist::Backend::parse(&to)?;
+    if let Some(channel) = &config.channel {
+        backend = backend.channel(channel);
+    }
+
+    let path = base_dir.join(&config.mods_file);
+    let original = fs::read_to_string(&path)?;
+    let loaded = manifest::parse(&original, &base_dir)?;
+    if loaded.legacy {
+        return Err(anyhow!("{} uses the legacy format, run `modpack-sync migrate` first", path.display()));
+    }
+    let mut manifest = loaded.manifest;
+
+    let problems = validate(&base_dir, &config.mods_file, &manifest);
+    for problem in problems.iter() {
+        eprintln!("[ERR!] {}", problem);
+    }
+    if !problems.is_empty() {
+        return Err(anyhow!("refusing to publish a pack with {} problems", problems.len()));
+    }
+
+    let restricted = restricted_jars(&manifest)?;
+    if !restricted.is_empty() {
+        println!("[WARN] {} jars of the pack may not be redistributed:", restricted.len());
+        for jar in restricted.iter() {
+            println!("[WARN]   {}", jar);
+        }
+        if !config.redistribute_restricted {
+            return Err(anyhow!(
+                "refusing to push jars whose authors forbid redistribution, link them from their provider or pass --redistribute-restricted once you have permission"
+            ));
+        }
+    }
+
+    let version = bump_version(&manifest.pack.version, config.bump)?;
+    if version != manifest.pack.version {
+        println!("[INFO] bumping pack version {} -> {}", manifest.pack.version, version);
+        manifest.pack.version = version.clone();
+        manifest::save(&path, &manifest)?;
+    }
+
+    // both are published as they are on disk, and put back as they were
+    // when publishing fails
+    let lock_path = lock::path(&base_dir);
+    let original_lock = fs::read(&lock_path).ok();
+    let mut lock = Lockfile::load(&base_dir)?.unwrap_or_default();
+    lock.update_overrides(&base_dir)?;
+    lock.save(&base_dir)?;
+
+    match dist::publish(&base_dir, &config.mods_file, &backend, config.rollout) {
+        Ok(sent) => {
+            let channel = config.channel.map(|c| format!(" channel {}", c)).unwrap_or_default();
+            println!("[INFO] published {} {} ({} files) to {}{}", manifest.pack.name, version, sent, to, channel);
+            if let Some(percent) = config.rollout {
+                println!(
+                    "[INFO] rolled out to {}% of clients, widen it with `modpack-sync publish --no-bump --rollout <percent>`",
+                    percent
+                );
+            }
+            Ok(())
+        }
+        Err(e) => {
+            // an unpublished version would be skipped by the next publish
+            fs::write(&path, original)?;
+            match original_lock {
+                Some(contents) => fs::write(&lock_path, contents)?,
+                None => fs::remove_file(&lock_path)?,
+            }
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::{log, quarantine};
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    List,
+    Clear,
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub action: Action,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &[])?;
+        let (action, base_dir) = match parsed.positional.as_slice() {
+            [action, base_dir, ..] => (action.as_str(), base_dir.clone()),
+            _ => return Err(anyhow!("usage: modpack-sync quarantine <list|clear> <instance dir>")),
+        };
+        let action = match action {
+            "list" => Action::List,
+            "clear" => Action::Clear,
+            other => return Err(anyhow!("unknown quarantine action {}, expected list or clear", other)),
+        };
+
+        Ok(Config { base_dir, action })
+    }
+}
+
+/// Lists the downloads syncs rejected and why, or deletes them.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    match config.action {
+        Action::List => {
+            let files = quarantine::list(&base_dir)?;
+            for (path, sidecar) in files.iter() {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                match sidecar {
+                    Some(s) if log::plain() => println!(
+                        "{}, from {}, quarantined {}: {}",
+                        s.original,
+                        s.source,
+                        s.quarantined_at.format("%Y-%m-%d %H:%M"),
+                        s.reason
+                    ),
+                    Some(s) => {
+                        println!("{}", name);
+                        println!("  was      {}", s.original);
+                        println!("  from     {}", s.source);
+                        println!("  when     {}", s.quarantined_at.format("%Y-%m-%d %H:%M UTC"));
+                        println!("  reason   {}", s.reason);
+                    }
+                    None => println!("{} {}", name, t!("quarantine-no-record")),
+                }
+            }
+            println!("[INFO] {}", t!("quarantine-listed", count = files.len(), dir = quarantine::dir(&base_dir).display()));
+        }
+        Action::Clear => {
+            let cleared = quarantine::clear(&base_dir)?;
+            println!("[INFO] {}", t!("quarantine-cleared", count = cleared));
+        }
+    }
+    Ok(())
+}
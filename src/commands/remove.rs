@@ -0,0 +1,123 @@
+use crate::cli::Args;
+use crate::prompt;
+use crate::sync::launcher::Launcher;
+use crate::sync::manifest::{self, Mod};
+use crate::sync::settings::{self, InstanceSettings};
+use crate::sync::{self, deps};
+use anyhow::{anyhow, Result};
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    /// Names, project ids or file names of the entries to remove.
+    pub mods: Vec<String>,
+    pub yes: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["yes"], &[])?;
+        let (base_dir, mods) = parsed
+            .positional
+            .split_first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?;
+        if mods.is_empty() {
+            return Err(anyhow!("expected the names of the mods to remove"));
+        }
+
+        Ok(Config {
+            base_dir: base_dir.clone(),
+            mods_file: "modlist.json".to_string(),
+            mods: mods.to_vec(),
+            yes: parsed.has("yes"),
+        })
+    }
+}
+
+fn matches(m: &Mod, wanted: &str) -> bool {
+    m.key() == wanted || m.filename == wanted || m.name.eq_ignore_ascii_case(wanted)
+}
+
+/// Removes entries from the modlist. Libraries that were only there as
+/// their dependencies are offered for removal too, and dependents left
+/// behind are warned about. Inherited entries are dropped through the
+/// manifest's `remove` list.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let path = base_dir.join(&config.mods_file);
+    let loaded = manifest::load(&path, &base_dir)?;
+    if loaded.legacy {
+        return Err(anyhow!(
+            "{} uses the legacy format, run `modpack-sync migrate` first",
+            path.display()
+        ));
+    }
+    let effective = sync::load_effective(&base_dir, &config.mods_file)?;
+
+    let mut removed: Vec<&Mod> = Vec::new();
+    for wanted in config.mods.iter() {
+        let m = effective
+            .mods
+            .iter()
+            .chain(effective.skipped.iter())
+            .find(|m| matches(m, wanted))
+            .ok_or_else(|| anyhow!("{} is not in the modlist", wanted))?;
+        removed.push(m);
+    }
+
+    let instance = InstanceSettings::load(&base_dir)?;
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+    let graph = settings::api_key().and_then(|key| {
+        let metadata = Launcher::detect(&base_dir).metadata(&base_dir, &mods_dir)?;
+        deps::build(&effective.mods, &metadata, &key)
+    });
+    match &graph {
+        Ok(graph) => {
+            if !graph.unresolved.is_empty() {
+                println!("[WARN] could not look up {} entries, their dependencies are not checked", graph.unresolved.len());
+            }
+            let ids: Vec<&str> = removed.iter().filter_map(|m| m.project_id()).collect();
+            for id in ids.iter() {
+                for dependent in graph.dependents(id).iter().filter(|d| !ids.contains(&d.project_id.as_str())) {
+                    println!("[WARN] {} requires {} and stays in the pack", dependent.name, name_of(&removed, id));
+                }
+            }
+            let libraries = graph.only_needed_by(&ids);
+            if !libraries.is_empty() {
+                let names: Vec<&str> = libraries.iter().map(|l| l.name.as_str()).collect();
+                println!("[INFO] only needed by the removed mods: {}", names.join(", "));
+                let remove = config.yes
+                    || (io::stdin().is_terminal() && prompt::confirm("Remove these libraries too?", true)?);
+                if remove {
+                    let is_library = |m: &&Mod| libraries.iter().any(|l| m.project_id() == Some(l.project_id.as_str()));
+                    removed.extend(effective.mods.iter().filter(is_library));
+                } else if !io::stdin().is_terminal() {
+                    println!("       pass --yes to remove them as well");
+                }
+            }
+        }
+        Err(e) => println!("[WARN] could not build the dependency graph, dependencies are not checked: {:#}", e),
+    }
+
+    let mut manifest = loaded.manifest;
+    for m in removed.iter() {
+        let before = manifest.mods.len();
+        manifest.mods.retain(|own| own.key() != m.key());
+        if manifest.mods.len() == before && !manifest.remove.iter().any(|r| r == m.key()) {
+            // inherited from a parent layer
+            manifest.remove.push(m.key().to_string());
+        }
+        println!("[INFO] removed {}", m.name);
+    }
+    manifest::save(&path, &manifest)?;
+    Ok(())
+}
+
+fn name_of<'a>(removed: &[&'a Mod], project_id: &'a str) -> &'a str {
+    removed
+        .iter()
+        .find(|m| m.project_id() == Some(project_id))
+        .map_or(project_id, |m| m.name.as_str())
+}
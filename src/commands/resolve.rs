@@ -0,0 +1,112 @@
+use super::update;
+use crate::cli::Args;
+use crate::sync::settings;
+use crate::sync::{self, manifest};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub out: Option<PathBuf>,
+    /// Resolve CurseForge entries to the files their projects had then.
+    pub as_of: Option<DateTime<Utc>>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["out", "as-of"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            out: parsed.value("out").map(PathBuf::from),
+            as_of: parsed.value("as-of").map(update::as_of).transpose()?,
+        })
+    }
+}
+
+/// Prints the effective mod list after all `extends` layers are applied,
+/// optionally writing it out as a standalone manifest. With `--as-of` each
+/// CurseForge entry gets the newest file, within its version range, that
+/// was published before then, reconstructing the pack as it was.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let mut effective = sync::load_effective(&base_dir, &config.mods_file)?;
+    if let Some(as_of) = config.as_of {
+        rewind(&mut effective, as_of)?;
+    }
+
+    println!("[INFO] layers: {}", effective.layers.join(" -> "));
+    for m in effective.mods.iter() {
+        match sync::log::plain() {
+            true => println!("{}, version {}, file {}", m.name, m.version, m.filename),
+            false => println!("{:<40} {:<20} {}", m.name, m.version, m.filename),
+        }
+    }
+    println!(
+        "[INFO] {} effective entries, {} skipped by conditions",
+        effective.mods.len(),
+        effective.skipped.len()
+    );
+
+    if let Some(out) = config.out {
+        let flattened = manifest::Manifest {
+            format_version: manifest::FORMAT_VERSION,
+            extends: None,
+            pack: effective.pack,
+            remove: Vec::new(),
+            mods: effective.mods,
+        };
+        manifest::save(&out, &flattened)?;
+        println!("[INFO] Wrote flattened modlist to {}", out.display());
+    }
+    Ok(())
+}
+
+/// Moves the CurseForge entries of `effective` to the files they would
+/// have resolved to at `as_of`. Entries of other providers stay as listed.
+fn rewind(effective: &mut sync::Effective, as_of: DateTime<Utc>) -> Result<()> {
+    let api_key = settings::api_key()?;
+    let (minecraft, loader) = update::target(&effective.pack);
+    let (minecraft, loader) = (minecraft.map(str::to_string), loader.map(str::to_string));
+    let mut kept = 0;
+    for m in effective.mods.iter_mut().filter(|m| !m.manual) {
+        let Some(project_id) = m.project_id().map(str::to_string) else {
+            kept += 1;
+            continue;
+        };
+        let range = m.range()?;
+        let found = update::newest(
+            &project_id,
+            range.as_ref(),
+            minecraft.as_deref(),
+            loader.as_deref(),
+            chrono::Duration::zero(),
+            Some(as_of),
+            &api_key,
+        )?;
+        match found {
+            Some((file, version)) => {
+                if file.file_name != m.filename {
+                    println!("[INFO] {}: {} -> {}", m.name, m.filename, file.file_name);
+                    m.filename = file.file_name;
+                    if let Some(version) = version {
+                        m.version = version;
+                    }
+                }
+            }
+            None => println!("[WARN] {} had no file for the pack before {}", m.name, as_of.to_rfc3339()),
+        }
+    }
+    if kept > 0 {
+        println!("[INFO] {} entries are not on CurseForge and keep their listed files", kept);
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use crate::cli::Args;
+use crate::sync::backup;
+use crate::sync::settings::InstanceSettings;
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+pub struct Config {
+    pub base_dir: String,
+    /// File name of the backup to restore, the newest when unset.
+    pub name: Option<String>,
+    pub list: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["list"], &[])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+
+        Ok(Config {
+            base_dir,
+            name: parsed.positional.get(1).cloned(),
+            list: parsed.has("list"),
+        })
+    }
+}
+
+/// Restores the managed files of an instance from a backup taken with
+/// `sync --backup`, or lists the available backups with `--list`.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let backups = backup::list(&base_dir, backup::MODS)?;
+
+    if config.list {
+        if backups.is_empty() {
+            println!("[INFO] no backups in {}", backup::dir(&base_dir).display());
+        }
+        for b in backups.iter() {
+            println!("{}", b.file_name().unwrap_or_default().to_string_lossy());
+        }
+        return Ok(());
+    }
+
+    let archive = match &config.name {
+        Some(name) => backups
+            .iter()
+            .find(|b| b.file_name().is_some_and(|n| n.to_string_lossy() == *name))
+            .ok_or_else(|| anyhow!("no backup named {}, see `restore-backup --list`", name))?,
+        None => backups
+            .last()
+            .ok_or_else(|| anyhow!("no backups in {}", backup::dir(&base_dir).display()))?,
+    };
+
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = instance.game_dir(&config.base_dir);
+    let mods_dir = instance.mods_dir(&config.base_dir);
+    let restored = backup::restore(&base_dir, Path::new(&game_dir), Path::new(&mods_dir), archive)?;
+    println!("[INFO] restored {} files from {}", restored, archive.display());
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use crate::sync::log::console;
+use crate::sync::state::State;
+use crate::sync::{self, Mode};
+use anyhow::Result;
+use chrono::Local;
+use std::path::Path;
+
+pub struct Config {
+    pub sync: sync::Config,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let mut sync = sync::Config::build(args)?;
+        sync.mode = Mode::Retry;
+        Ok(Config { sync })
+    }
+}
+
+/// Installs again only the entries that still failed after the retries of
+/// an earlier run, from the pack as it was pulled then, without planning
+/// the rest of the pack.
+pub fn run(config: Config) -> Result<()> {
+    let state = State::load(Path::new(&config.sync.base_dir))?;
+    for (filename, queued) in state.retry.iter() {
+        let since = queued.since.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+        console!("[INFO] retrying {}, failed {} times since {}: {}", filename, queued.attempts, since, queued.error);
+    }
+    sync::run(config.sync)
+}
@@ -0,0 +1,90 @@
+use crate::cli::Args;
+use crate::sync::{curse_files, modrinth, settings};
+use anyhow::{anyhow, Result};
+
+pub struct Config {
+    pub query: String,
+    pub minecraft: Option<String>,
+    pub loader: Option<String>,
+    pub curseforge: bool,
+    pub modrinth: bool,
+    /// Page of the CurseForge results to show, the first being 1.
+    pub page: u32,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["mc", "loader", "provider", "page"])?;
+        if parsed.positional.is_empty() {
+            return Err(anyhow!("expected a search query"));
+        }
+
+        let (curseforge, modrinth) = match parsed.value("provider").unwrap_or("all") {
+            "all" => (true, true),
+            "curseforge" => (true, false),
+            "modrinth" => (false, true),
+            other => return Err(anyhow!("unknown provider {}, expected curseforge, modrinth or all", other)),
+        };
+
+        let page = match parsed.value("page") {
+            Some(page) => page.parse().ok().filter(|p| *p > 0).ok_or_else(|| anyhow!("--page expects a number from 1, got {}", page))?,
+            None => 1,
+        };
+
+        Ok(Config {
+            query: parsed.positional.join(" "),
+            minecraft: parsed.value("mc").map(str::to_string),
+            loader: parsed.value("loader").map(str::to_string),
+            curseforge,
+            modrinth,
+            page,
+        })
+    }
+}
+
+/// Searches the configured providers and prints matching projects with the
+/// identifier to put in a modlist entry.
+pub fn run(config: Config) -> Result<()> {
+    let minecraft = config.minecraft.as_deref();
+    let loader = config.loader.as_deref();
+
+    if config.curseforge {
+        match settings::api_key() {
+            Ok(key) => {
+                let page = curse_files::Page { index: config.page - 1, ..curse_files::Page::FIRST };
+                let (projects, pagination) = curse_files::search(&config.query, minecraft, loader, page, &key)?;
+                let pages = pagination.total_count.div_ceil(u64::from(page.size)).max(1);
+                println!("CurseForge ({} results, page {} of {})", pagination.total_count, config.page, pages);
+                for p in projects {
+                    let latest = p
+                        .latest_files_indexes
+                        .iter()
+                        .find(|f| minecraft.is_none_or(|mc| f.game_version == mc))
+                        .map(|f| f.filename.as_str())
+                        .unwrap_or("-");
+                    println!(
+                        "  {:<36} {:>12} downloads  latest {:<40} slug {} url https://www.curseforge.com/projects/{}",
+                        p.name, p.download_count as u64, latest, p.slug, p.id
+                    );
+                }
+            }
+            Err(e) => println!("[WARN] skipping CurseForge: {:#}", e),
+        }
+    }
+
+    if config.modrinth {
+        let hits = modrinth::search(&config.query, minecraft, loader)?;
+        println!("Modrinth ({} results)", hits.len());
+        for h in hits {
+            println!(
+                "  {:<36} {:>12} downloads  latest {:<40} slug {} ({})",
+                h.title,
+                h.downloads,
+                h.latest_version.as_deref().unwrap_or("-"),
+                h.slug,
+                h.project_id
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use crate::cli::Args;
+use crate::release::{self, CURRENT_VERSION};
+use crate::sync::hash;
+use crate::sync::http::RequestExt;
+use anyhow::{anyhow, Result};
+use reqwest::header::USER_AGENT;
+use std::env;
+use std::fs;
+
+pub struct Config {
+    pub check_only: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["check"], &[])?;
+        Ok(Config { check_only: parsed.has("check") })
+    }
+}
+
+/// Replaces the running binary with the latest GitHub release after checking
+/// it against the published sha256 checksum.
+pub fn run(config: Config) -> Result<()> {
+    let client = release::client()?;
+    let latest = release::latest(&client)?;
+
+    if !latest.is_newer() {
+        println!("[INFO] modpack-sync {} is up to date", CURRENT_VERSION);
+        return Ok(());
+    }
+
+    println!("[INFO] modpack-sync {} is available (running {})", latest.version(), CURRENT_VERSION);
+    if config.check_only {
+        return Ok(());
+    }
+
+    let binary = latest.binary()?;
+    let checksum = latest.checksum_for(binary)?;
+    let agent = format!("modpack-sync/{}", CURRENT_VERSION);
+
+    let expected = client
+        .get(&checksum.browser_download_url)
+        .header(USER_AGENT, &agent)
+        .send_captured()?
+        .error_for_status()?
+        .text()?;
+    let expected = expected
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow!("checksum file {} is empty", checksum.name))?
+        .to_lowercase();
+
+    let bytes = client
+        .get(&binary.browser_download_url)
+        .header(USER_AGENT, &agent)
+        .send_captured()?
+        .error_for_status()?
+        .bytes()?;
+    let actual = hash::sha256_bytes(&bytes);
+    if actual != expected {
+        return Err(anyhow!(
+            "checksum mismatch for {}: expected {}, got {}",
+            binary.name,
+            expected,
+            actual
+        ));
+    }
+
+    let current = env::current_exe()?;
+    let staged = current.with_extension("new");
+    fs::write(&staged, &bytes)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    // Windows refuses to overwrite a running executable but allows renaming it.
+    let old = current.with_extension("old");
+    let _ = fs::remove_file(&old);
+    fs::rename(&current, &old)?;
+    if let Err(e) = fs::rename(&staged, &current) {
+        let _ = fs::rename(&old, &current);
+        return Err(anyhow!("failed to replace {}: {}", current.display(), e));
+    }
+    let _ = fs::remove_file(&old);
+
+    println!("[INFO] Updated modpack-sync to {}", latest.version());
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::cli::Args;
+use crate::sync::control::Access;
+use crate::sync::log::console;
+use crate::sync::peers::{self, Advertised, Served};
+use crate::sync::settings::InstanceSettings;
+use anyhow::{anyhow, Context, Result};
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: String,
+    /// Address to listen on, all interfaces by default.
+    pub bind: String,
+    pub port: u16,
+    /// Whether the reports of clients are recorded.
+    pub fleet: bool,
+    /// Url players reach this instance at, advertised as a peer.
+    pub url: Option<String>,
+    /// Url of the manifest advertised to players joining.
+    pub manifest: Option<String>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["fleet"], &["port", "bind", "url", "manifest"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let port = match parsed.value("port") {
+            Some(port) => port.parse().map_err(|_| anyhow!("expected a port number for --port, got {}", port))?,
+            None => peers::DEFAULT_PORT,
+        };
+
+        Ok(Config {
+            base_dir,
+            bind: parsed.value("bind").unwrap_or("0.0.0.0").to_string(),
+            port,
+            fleet: parsed.has("fleet"),
+            url: parsed.value("url").map(str::to_string),
+            manifest: parsed.value("manifest").map(str::to_string),
+        })
+    }
+}
+
+/// Shares the files the instance's lockfile pins with peers, so players of
+/// a busy server fetch a new version of the pack from it and each other
+/// rather than all at once from CurseForge. Players list it under `peers`
+/// in their `modpack-sync.toml`, files peers don't have come from their
+/// providers as before. The pack's statistics are served alongside for
+/// community websites and badges. With `--fleet` clients that opt in
+/// report the version they synced and the state of their instance. The
+/// pack `modpack-sync join` finds is the instance's source and channel, or
+/// the `--manifest` url, with `--url` as its peer.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let shared = peers::shared(&base_dir, &game_dir).len();
+    if shared == 0 {
+        console!("[WARN] {} has no files pinned by a lockfile, sync it first", config.base_dir);
+    }
+    let listener = TcpListener::bind((config.bind.as_str(), config.port))
+        .with_context(|| format!("failed to listen on {}:{}", config.bind, config.port))?;
+    let fleet = match config.fleet {
+        true => Some(instance.fleet_token().filter(|t| !t.is_empty()).ok_or_else(|| {
+            anyhow!("serve --fleet needs the token clients report with, set fleet_token in the modpack-sync.toml or MODPACK_SYNC_FLEET_TOKEN")
+        })?),
+        false => None,
+    };
+    let access = Access::load(&instance.daemon.clone().unwrap_or_default()).with_context(|| format!("invalid daemon settings of {}", config.base_dir))?;
+    let advertised = Advertised {
+        manifest: config.manifest.clone(),
+        source: instance.source.clone(),
+        channel: instance.channel.clone(),
+        peers: config.url.iter().cloned().collect(),
+    };
+    if !advertised.has_pack() {
+        console!("[WARN] {} has no source to advertise, players can't join it by address without --manifest", config.base_dir);
+    }
+    console!("[INFO] sharing {} files of {} on port {}", shared, config.base_dir, config.port);
+    console!("       players add peers = [\"http://<this host>:{}\"] to their modpack-sync.toml to fetch them", config.port);
+    console!("       websites read the pack's statistics from http://<this host>:{}/stats.json and badges from /badge/<field>", config.port);
+    if config.fleet {
+        console!("       clients set fleet.report_to = \"http://<this host>:{}\" and fleet.token to report their syncs, list them with `modpack-sync fleet`", config.port);
+    }
+    if access.has_tokens() {
+        console!("       the daemon settings have tokens, players set peer_token to one of them");
+    }
+    console!("       `modpack-sync join` reads the pack from http://<this host>:{}{}", config.port, peers::WELL_KNOWN_PATH);
+    peers::serve(listener, base_dir, game_dir, Served { access, fleet, advertised })
+}
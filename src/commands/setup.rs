@@ -0,0 +1,109 @@
+use crate::cli::Args;
+use crate::prompt;
+use crate::sync::manifest::{self, Manifest, PackHeader};
+use crate::sync::secrets;
+use crate::sync::settings::{self, InstanceSettings, Side, UserSettings};
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: Option<String>,
+    pub mods_file: String,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &[])?;
+        Ok(Config {
+            base_dir: parsed.positional.first().cloned(),
+            mods_file: "modlist.json".to_string(),
+        })
+    }
+}
+
+/// Interactive first-run setup: picks the instance directory, stores the API
+/// key in the user settings, records whether this is a client or server
+/// instance and creates an empty modlist if there is none yet.
+pub fn run(config: Config) -> Result<()> {
+    println!("modpack-sync setup, press enter to accept the value in brackets.");
+
+    let cwd = std::env::current_dir()?.to_string_lossy().to_string();
+    let default_dir = config.base_dir.unwrap_or(cwd);
+    let base_dir = PathBuf::from(prompt::ask("Instance directory", Some(&default_dir))?);
+    if !base_dir.is_dir() {
+        if !prompt::confirm(&format!("{} does not exist, create it?", base_dir.display()), true)? {
+            return Err(anyhow!("setup cancelled, no instance directory"));
+        }
+        fs::create_dir_all(&base_dir)?;
+    }
+
+    let mut user = UserSettings::load()?;
+    let existing = settings::api_key().ok();
+    let question = match existing {
+        Some(_) => "CurseForge API key (empty keeps the current one)",
+        None => "CurseForge API key",
+    };
+    let key = prompt::ask(question, None)?;
+    if !key.is_empty() {
+        // hosts without a keyring keep the key encrypted with the passphrase
+        match secrets::passphrase()? {
+            Some(passphrase) => {
+                user.api_key = Some(secrets::encrypt(&key, &passphrase)?);
+                user.save()?;
+                println!("[INFO] API key stored encrypted with MODPACK_SYNC_PASSPHRASE in {}", UserSettings::path()?.display());
+            }
+            None => {
+                user.api_key = Some(key);
+                user.save()?;
+                println!("[INFO] API key stored in {}", UserSettings::path()?.display());
+            }
+        }
+    } else if existing.is_none() {
+        println!("[WARN] no API key configured, syncing will fail until CURSE_API_KEY is set or setup is run again");
+    }
+
+    let mut instance = InstanceSettings::load(&base_dir)?;
+    let current = match instance.side {
+        Side::Client => "client",
+        Side::Server => "server",
+    };
+    instance.side = Side::parse(&prompt::ask("Is this a client or a server instance", Some(current))?)?;
+    instance.save(&base_dir)?;
+
+    let game_dir = PathBuf::from(instance.game_dir(&base_dir.to_string_lossy()));
+    fs::create_dir_all(game_dir.join("mods"))?;
+
+    let modlist = base_dir.join(&config.mods_file);
+    if !modlist.exists() {
+        let name = base_dir
+            .canonicalize()?
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "modpack".to_string());
+        let minecraft = prompt::ask("Minecraft version (optional)", None)?;
+        let loader = prompt::ask("Mod loader (optional)", None)?;
+        let manifest = Manifest {
+            format_version: manifest::FORMAT_VERSION,
+            extends: None,
+            pack: PackHeader {
+                name,
+                version: "0.1.0".to_string(),
+                minecraft: Some(minecraft).filter(|v| !v.is_empty()),
+                loader: Some(loader).filter(|v| !v.is_empty()),
+                launch: None,
+                extensions: Default::default(),
+                variables: Default::default(),
+                opt_in_tags: Vec::new(),
+                stamp: None,
+            },
+            remove: Vec::new(),
+            mods: Vec::new(),
+        };
+        manifest::save(&modlist, &manifest)?;
+        println!("[INFO] Created empty modlist at {}", modlist.display());
+    }
+
+    println!("[INFO] Setup complete, run `modpack-sync {}` to sync", base_dir.display());
+    Ok(())
+}
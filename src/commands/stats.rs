@@ -0,0 +1,95 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::report::human_size;
+use crate::sync::usage::{self, Usage};
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub format: Format,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["format"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let format = match parsed.value("format").unwrap_or("text") {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            other => return Err(anyhow!("unknown format {}, expected text or json", other)),
+        };
+
+        Ok(Config { base_dir, format })
+    }
+}
+
+fn percent(rate: Option<f64>) -> String {
+    rate.map_or_else(|| "-".to_string(), |r| format!("{:.0}%", r))
+}
+
+/// Shows the usage statistics syncs kept for the instance: what was
+/// downloaded, what the caches, peers and incremental syncs spared and how
+/// long syncs take.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let usage = Usage::load(&base_dir)?;
+    if config.format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&usage)?);
+        return Ok(());
+    }
+    let (Some(since), Some(last_sync)) = (usage.since, usage.last_sync) else {
+        println!("[INFO] {}", t!("stats-empty", path = usage::path(&base_dir).display()));
+        return Ok(());
+    };
+    let format = |at: chrono::DateTime<chrono::Utc>| at.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string();
+    println!(
+        "[INFO] {}",
+        t!("stats-syncs", count = usage.syncs, since = format(since), failed = usage.failed, last = format(last_sync))
+    );
+    println!(
+        "[INFO] {}",
+        t!(
+            "stats-downloads",
+            count = usage.downloads,
+            hosts = human_size(usage.downloaded_bytes),
+            peers = human_size(usage.peer_bytes),
+            copied = human_size(usage.copied_bytes)
+        )
+    );
+    println!("[INFO] {}", t!("stats-kept", count = usage.kept));
+    println!(
+        "[INFO] {}",
+        t!(
+            "stats-cache",
+            rate = percent(usage.cache_hit_rate()),
+            hits = usage.cache_hits,
+            revalidated = usage.cache_revalidated,
+            misses = usage.cache_misses,
+            saved = human_size(usage.cache_saved_bytes)
+        )
+    );
+    println!("[INFO] {}", t!("stats-hash-cache", rate = percent(usage.hash_cache_hit_rate())));
+    if let Some(average) = usage.average_seconds() {
+        println!(
+            "[INFO] {}",
+            t!(
+                "stats-duration",
+                seconds = format!("{:.1}", average),
+                downloading = format!("{:.1}", usage.download_seconds / usage.syncs as f64)
+            )
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,302 @@
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::sync::capture::REDACTED;
+use crate::sync::launcher::Launcher;
+use crate::sync::log;
+use crate::sync::report::human_size;
+use crate::sync::settings::{self, InstanceSettings, UserSettings, INSTANCE_FILE};
+use crate::sync::conditions::Tags;
+use crate::sync::state::{self, Queued, State};
+use crate::sync::control::Access;
+use crate::sync::{auth, failure, lock, secrets};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+
+/// Keys of settings whose values are secrets wherever they are.
+const SECRET_KEYS: [&str; 4] = ["api_key", "password", "token", "secret"];
+/// Words secrets follow in logs and reports whether they are known or not,
+/// and whether the rest of the line is the secret.
+const SECRET_MARKERS: [(&str, bool); 7] = [
+    ("authorization:", true),
+    ("x-api-key:", true),
+    ("bearer ", false),
+    ("api_key=", false),
+    ("token=", false),
+    ("password=", false),
+    ("secret=", false),
+];
+/// Run logs bundled by default.
+const DEFAULT_LOGS: usize = 5;
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    /// Where the bundle is written, `modpack-sync-support-<time>.zip` in the
+    /// current directory by default.
+    pub out: Option<PathBuf>,
+    /// Number of the most recent run logs to bundle.
+    pub logs: usize,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &[], &["out", "logs"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let logs = match parsed.value("logs") {
+            Some(logs) => logs.parse().map_err(|_| anyhow!("expected a number of --logs, got {}", logs))?,
+            None => DEFAULT_LOGS,
+        };
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            out: parsed.value("out").map(PathBuf::from),
+            logs,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Environment {
+    tool_version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    time: DateTime<Utc>,
+    launcher: &'static str,
+    side: &'static str,
+    base_dir: String,
+    game_dir: String,
+    mods_dir: String,
+    /// Variables of the tool that are set, without their values.
+    variables: Vec<String>,
+}
+
+/// What the state records, without the digests and times of every file.
+#[derive(Serialize)]
+struct StateSummary {
+    files: usize,
+    bytes: u64,
+    /// Installed files by the provider they came from.
+    providers: BTreeMap<String, usize>,
+    /// Files whose installs predate sizes in the state, or that have none.
+    without_size: usize,
+    deep_verified_at: Option<DateTime<Utc>>,
+    bisecting: bool,
+    tags: Tags,
+    retry: BTreeMap<String, Queued>,
+}
+
+/// Collects what a bug report needs into one zip: the recent run logs, the
+/// last failure report, the modlist and lockfile, the settings with their
+/// secrets redacted, a summary of the state and the environment.
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    if !base_dir.is_dir() {
+        return Err(anyhow!("modpack directory {} does not exist", config.base_dir));
+    }
+    let out = config
+        .out
+        .unwrap_or_else(|| PathBuf::from(format!("modpack-sync-support-{}.zip", Local::now().format("%Y%m%d-%H%M%S"))));
+
+    // the secrets of the settings are scrubbed from everything bundled
+    let mut known: Vec<String> = Vec::new();
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    let instance_file = base_dir.join(INSTANCE_FILE);
+    if instance_file.exists() {
+        entries.push((INSTANCE_FILE.to_string(), sanitized(&instance_file, &mut known)?.into_bytes()));
+    }
+    let user_file = UserSettings::path()?;
+    if user_file.exists() {
+        entries.push(("user-config.toml".to_string(), sanitized(&user_file, &mut known)?.into_bytes()));
+    }
+    // values stored encrypted are scrubbed in plaintext too, when they can
+    // be decrypted
+    let instance = InstanceSettings::load(&base_dir).unwrap_or_default();
+    let _ = UserSettings::load();
+    known.extend(secrets::opened());
+    known.extend(settings::api_key().ok());
+    // and those read from the environment or the keyring
+    known.extend(auth::secrets());
+    if let Some(access) = instance.daemon.as_ref().and_then(|d| Access::load(d).ok()) {
+        known.extend(access.secrets().map(str::to_string));
+    }
+    known.extend(instance.panel.as_ref().and_then(|p| p.api_key().ok()));
+    known.extend(instance.fleet.as_ref().and_then(|f| f.token()));
+    known.extend(instance.fleet_token());
+    known.extend(instance.peer_token());
+    let email = instance.notify.as_ref().and_then(|n| n.email.as_ref());
+    known.extend(email.and_then(|e| e.password().ok().flatten()));
+    known.retain(|s| s.len() >= 6 && s != REDACTED);
+
+    let runs = log::list_runs(&base_dir)?;
+    for path in runs.iter().skip(runs.len().saturating_sub(config.logs)) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        entries.push((format!("logs/{}", name), scrub(&fs::read(path)?, &known)));
+    }
+    let bundled = [
+        (failure::path(&base_dir), "last-failure.json".to_string()),
+        (base_dir.join(&config.mods_file), config.mods_file.clone()),
+        (lock::path(&base_dir), lock::LOCK_FILE.to_string()),
+    ];
+    for (path, name) in bundled.iter().filter(|(path, _)| path.is_file()) {
+        entries.push((name.clone(), scrub(&fs::read(path)?, &known)));
+    }
+    if state::path(&base_dir).exists() {
+        let summary = summarize(&State::load(&base_dir)?);
+        entries.push(("state-summary.json".to_string(), serde_json::to_vec_pretty(&summary)?));
+    }
+    let environment = Environment {
+        tool_version: env!("CARGO_PKG_VERSION"),
+        os: env::consts::OS,
+        arch: env::consts::ARCH,
+        time: Utc::now(),
+        launcher: Launcher::detect(&base_dir).name(),
+        side: match instance.side {
+            settings::Side::Client => "client",
+            settings::Side::Server => "server",
+        },
+        base_dir: config.base_dir.clone(),
+        game_dir: instance.game_dir(&config.base_dir),
+        mods_dir: instance.mods_dir(&config.base_dir),
+        variables: env::vars_os()
+            .map(|(name, _)| name.to_string_lossy().to_string())
+            .filter(|name| name.starts_with("MODPACK_SYNC_") || name == "CURSE_API_KEY")
+            .collect(),
+    };
+    entries.push(("environment.json".to_string(), serde_json::to_vec_pretty(&environment)?));
+
+    write_zip(&out, &entries)?;
+    for (name, contents) in entries.iter() {
+        println!("  {:<48} {:>10}", name, human_size(contents.len() as u64));
+    }
+    println!("[INFO] {}", t!("support-bundle-written", count = entries.len(), path = out.display()));
+    println!("[INFO] {}", t!("support-bundle-redacted"));
+    Ok(())
+}
+
+/// The settings file at `path` with the value of every secret, and every
+/// encrypted value, replaced by [`REDACTED`]. The plaintext secrets are
+/// added to `secrets`.
+fn sanitized(path: &Path, secrets: &mut Vec<String>) -> Result<String> {
+    let contents = fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut value: toml::Value = toml::from_str(&contents).with_context(|| format!("failed to parse {}", path.display()))?;
+    redact(&mut value, false, "", secrets);
+    Ok(toml::to_string_pretty(&value)?)
+}
+
+fn redact(value: &mut toml::Value, secret: bool, path: &str, secrets: &mut Vec<String>) {
+    match value {
+        toml::Value::String(s) if secret || s.starts_with("enc:") || s.starts_with("age:") => {
+            secrets.push(std::mem::replace(s, REDACTED.to_string()));
+        }
+        toml::Value::Array(values) => values.iter_mut().for_each(|v| redact(v, secret, path, secrets)),
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                // webhook urls carry their token, and the headers of http
+                // notifications their credentials
+                let notify = path.starts_with("notify") && (key == "url" || key == "headers");
+                let secret = secret || notify || SECRET_KEYS.contains(&key.as_str());
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                redact(value, secret, &path, secrets);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `contents` with every one of `secrets`, and whatever follows one of the
+/// [`SECRET_MARKERS`], replaced by [`REDACTED`].
+fn scrub(contents: &[u8], secrets: &[String]) -> Vec<u8> {
+    let Ok(text) = std::str::from_utf8(contents) else {
+        return contents.to_vec();
+    };
+    let text = secrets.iter().fold(text.to_string(), |text, secret| text.replace(secret.as_str(), REDACTED));
+    let lower = text.to_ascii_lowercase();
+    let mut scrubbed = String::with_capacity(text.len());
+    let mut at = 0;
+    while let Some((start, (marker, line))) = SECRET_MARKERS
+        .iter()
+        .filter_map(|m| lower[at..].find(m.0).map(|i| (at + i, *m)))
+        .min_by_key(|(i, _)| *i)
+    {
+        let rest = &text[start + marker.len()..];
+        let value = start + marker.len() + (rest.len() - rest.trim_start_matches(' ').len());
+        let end = match line {
+            true => text[value..].find(['\r', '\n']),
+            false => text[value..].find(|c: char| c.is_whitespace() || "\"',&;)".contains(c)),
+        };
+        let end = end.map_or(text.len(), |i| value + i);
+        scrubbed.push_str(&text[at..value]);
+        if end > value {
+            scrubbed.push_str(REDACTED);
+        }
+        at = end;
+    }
+    scrubbed.push_str(&text[at..]);
+    scrubbed.into_bytes()
+}
+
+fn summarize(state: &State) -> StateSummary {
+    let mut providers = BTreeMap::new();
+    for managed in state.files.values() {
+        *providers.entry(managed.source.clone()).or_insert(0) += 1;
+    }
+    StateSummary {
+        files: state.files.len(),
+        bytes: state.files.values().filter_map(|m| m.size).sum(),
+        providers,
+        without_size: state.files.values().filter(|m| m.size.is_none()).count(),
+        deep_verified_at: state.deep_verified_at,
+        bisecting: state.bisect.is_some(),
+        tags: state.tags.clone(),
+        retry: state.retry.clone(),
+    }
+}
+
+fn write_zip(out: &Path, entries: &[(String, Vec<u8>)]) -> Result<()> {
+    if let Some(parent) = out.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let file = fs::File::create(out).with_context(|| format!("failed to create {}", out.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, contents) in entries.iter() {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(contents)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_scrubbed_known_or_not() {
+        let log = "[INFO] asked with hunter22\nAuthorization: Basic dXNlcjpwYXNz\n\
+                   GET https://example.com/a?token=abc123&page=2 Bearer eyJhbGc.x\nnothing=here";
+        let scrubbed = String::from_utf8(scrub(log.as_bytes(), &["hunter22".to_string()])).unwrap();
+        assert_eq!(
+            scrubbed,
+            format!(
+                "[INFO] asked with {r}\nAuthorization: {r}\nGET https://example.com/a?token={r}&page=2 Bearer {r}\nnothing=here",
+                r = REDACTED
+            )
+        );
+    }
+}
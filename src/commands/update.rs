@@ -0,0 +1,290 @@
+use crate::cli::Args;
+use crate::sync::alternatives;
+use crate::sync::curse_files::{CurseFile, ModFile};
+use crate::sync::manifest::{self, Manifest, PackHeader};
+use crate::sync::settings::{self, UpdateSettings};
+use crate::sync::version::{self, Change, Requirement};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub dry_run: bool,
+    /// Largest kind of update to apply, the rest is only listed. Minor
+    /// unless asked, majors wait for someone to look at them.
+    pub only: Change,
+    /// Search for alternatives to entries without a file for the pack.
+    pub suggest: bool,
+    /// Only consider files published before this moment.
+    pub as_of: Option<DateTime<Utc>>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["dry-run", "suggest"], &["only", "as-of"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            dry_run: parsed.has("dry-run"),
+            only: parsed.value("only").map_or(Ok(Change::Minor), Change::parse)?,
+            suggest: parsed.has("suggest"),
+            as_of: parsed.value("as-of").map(as_of).transpose()?,
+        })
+    }
+}
+
+/// A newer file for an entry of the modlist.
+pub struct Available {
+    /// Position of the entry in the manifest's mods.
+    pub index: usize,
+    pub name: String,
+    pub from: String,
+    pub file: ModFile,
+    pub change: Change,
+}
+
+/// Loads the instance's own modlist, refusing the legacy format.
+pub fn load(base_dir: &str, mods_file: &str) -> Result<(PathBuf, Manifest)> {
+    let base_dir = PathBuf::from(base_dir);
+    let path = base_dir.join(mods_file);
+    let loaded = manifest::load(&path, &base_dir)?;
+    if loaded.legacy {
+        return Err(anyhow!(
+            "{} uses the legacy format, run `modpack-sync migrate` first",
+            path.display()
+        ));
+    }
+    Ok((path, loaded.manifest))
+}
+
+/// The moment `--as-of` names, a date meaning its start in UTC or an
+/// RFC 3339 timestamp.
+pub fn as_of(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|d| d.with_timezone(&Utc))
+        .map_err(|_| anyhow!("{} is not a date, expected e.g. 2024-06-01 or 2024-06-01T12:00:00Z", value))
+}
+
+/// The pack's Minecraft version and loader name.
+pub fn target(pack: &PackHeader) -> (Option<&str>, Option<&str>) {
+    let loader = pack.loader.as_deref().map(|l| l.split('-').next().unwrap_or(l));
+    (pack.minecraft.as_deref(), loader)
+}
+
+/// The instance's update policy, none when it has no settings.
+pub fn policy(base_dir: &str) -> Result<UpdateSettings> {
+    Ok(settings::InstanceSettings::load(&PathBuf::from(base_dir))?.updates.unwrap_or_default())
+}
+
+/// Newer CurseForge files tagged for the pack's Minecraft version and
+/// loader that are as old as `policy` asks, pinned and held entries left
+/// out. Entries with a version range only move within it, and with `as_of`
+/// to files published before it. Also hands back the positions of the
+/// entries whose project has no file for the pack at all.
+pub fn available(
+    manifest: &Manifest,
+    policy: &UpdateSettings,
+    as_of: Option<DateTime<Utc>>,
+    api_key: &str,
+) -> Result<(Vec<Available>, Vec<usize>)> {
+    let (minecraft, loader) = target(&manifest.pack);
+    let mut found = Vec::new();
+    let mut stranded = Vec::new();
+    let today = Utc::now().date_naive();
+    for (index, m) in manifest.mods.iter().enumerate().filter(|(_, m)| !m.manual) {
+        if m.held(today) {
+            println!(
+                "[INFO] keeping {} on {} until {}{}",
+                m.name,
+                m.filename,
+                m.hold_until.expect("held entries have a hold"),
+                m.reason.as_ref().map(|r| format!(", {}", r)).unwrap_or_default()
+            );
+            continue;
+        }
+        if m.pinned {
+            println!("[INFO] keeping {} on {}, it is pinned", m.name, m.filename);
+            continue;
+        }
+        let range = m.range()?;
+        let Some(project_id) = m.project_id() else {
+            if let Some(range) = range {
+                println!("[WARN] {} has version range {} but no CurseForge project to resolve it against", m.name, range);
+            }
+            continue;
+        };
+        let min_age = settings::hours(policy.min_age_hours(&m.name, project_id));
+        let Some((file, version)) = newest(project_id, range.as_ref(), minecraft, loader, min_age, as_of, api_key)? else {
+            match range {
+                Some(range) => println!("[WARN] no file of {} satisfies {}", m.name, range),
+                None => stranded.push(index),
+            }
+            continue;
+        };
+        if file.file_name == m.filename {
+            continue;
+        }
+        let change = match (version::of_file(&m.filename, minecraft), &version) {
+            // betas and alphas are as risky as a major release
+            _ if file.release_type != 1 => Change::Major,
+            (Some(current), Some(version)) => version::change(&current, version),
+            _ => Change::Major,
+        };
+        found.push(Available {
+            index,
+            name: m.name.clone(),
+            from: m.filename.clone(),
+            file,
+            change,
+        });
+    }
+    Ok((found, stranded))
+}
+
+/// Prints projects named like the entries in `stranded` that do have files
+/// for the pack, such as forks and ports of mods that were left behind.
+pub fn suggest(manifest: &Manifest, stranded: &[usize], api_key: &str) {
+    let (minecraft, loader) = target(&manifest.pack);
+    let target = [minecraft, loader].iter().flatten().copied().collect::<Vec<_>>().join(" ");
+    for m in stranded.iter().map(|&i| &manifest.mods[i]) {
+        println!("[WARN] {} has no file for {}", m.name, target);
+        let alternatives = alternatives::find(&m.name, m.project_id(), minecraft, loader, api_key);
+        if alternatives.is_empty() {
+            println!("[INFO]   no alternatives found");
+        }
+        for alternative in alternatives {
+            println!("[INFO]   alternative: {}", alternative);
+        }
+    }
+}
+
+/// Moves entries to the newest CurseForge file of their project, within
+/// their version range if they have one. Updates bigger than `--only`
+/// are listed but held back, with `--dry-run` the modlist is left
+/// untouched. `--as-of` picks the newest file as it was then, which also
+/// moves entries back to older files.
+pub fn run(config: Config) -> Result<()> {
+    let (path, mut manifest) = load(&config.base_dir, &config.mods_file)?;
+    let api_key = settings::api_key()?;
+
+    let (available, stranded) = available(&manifest, &policy(&config.base_dir)?, config.as_of, &api_key)?;
+    let mut updated = 0;
+    let mut held = 0;
+    for update in available {
+        if update.change > config.only {
+            println!(
+                "[INFO] holding back {} update of {}: {} -> {}{}",
+                update.change.as_str(),
+                update.name,
+                update.from,
+                update.file.file_name,
+                manifest.mods[update.index].remarks().map(|r| format!(" ({})", r)).unwrap_or_default()
+            );
+            held += 1;
+            continue;
+        }
+        println!(
+            "[INFO] {} ({}): {} -> {}",
+            update.name,
+            update.change.as_str(),
+            update.from,
+            update.file.file_name
+        );
+        // a reason for the file it was on may not hold for the new one
+        if let Some(reason) = &manifest.mods[update.index].reason {
+            println!("[WARN]   it was on {} because: {}", update.from, reason);
+        }
+        let entry = &mut manifest.mods[update.index];
+        if let Some(until) = entry.hold_expired(Utc::now().date_naive()) {
+            println!("[INFO]   its hold ended {}, removing it with its reason", until);
+            entry.hold_until = None;
+            entry.reason = None;
+        }
+        entry.filename = update.file.file_name;
+        updated += 1;
+    }
+
+    if config.suggest {
+        suggest(&manifest, &stranded, &api_key);
+    }
+    if held > 0 {
+        println!("[INFO] {} updates held back, pass a larger --only to apply them", held);
+    }
+    if updated == 0 {
+        println!("[INFO] no updates to apply");
+    } else if config.dry_run {
+        println!("[INFO] {} entries would be updated, run without --dry-run to write them", updated);
+    } else {
+        manifest::save(&path, &manifest)?;
+        println!("[INFO] updated {} entries in {}", updated, path.display());
+    }
+    Ok(())
+}
+
+/// The newest file of a project satisfying `range` by the version in its
+/// file name, or simply the latest upload for entries without one, passing
+/// over files uploaded less than `min_age` ago or, with `as_of`, after it.
+pub fn newest(
+    project_id: &str,
+    range: Option<&Requirement>,
+    minecraft: Option<&str>,
+    loader: Option<&str>,
+    min_age: chrono::Duration,
+    as_of: Option<DateTime<Utc>>,
+    api_key: &str,
+) -> Result<Option<(ModFile, Option<String>)>> {
+    let mut best: Option<(ModFile, String)> = None;
+    for file in CurseFile::fresh(project_id, api_key)? {
+        let file = file?;
+        if !file.supports(minecraft, loader) {
+            continue;
+        }
+        let date = DateTime::parse_from_rfc3339(&file.file_date).ok().map(|d| d.with_timezone(&Utc));
+        // files without a date can't be shown to predate the cutoff
+        if as_of.is_some_and(|as_of| date.is_none_or(|d| d >= as_of)) {
+            continue;
+        }
+        // nor be shown to be old enough
+        let age = match date {
+            Some(date) => Utc::now() - date,
+            None if min_age.is_zero() => chrono::Duration::zero(),
+            None => {
+                println!("[INFO] passing over {}, its upload date {} can't be read", file.file_name, file.file_date);
+                continue;
+            }
+        };
+        if age < min_age {
+            println!(
+                "[INFO] passing over {}, it was uploaded {} hours ago and needs to be {} hours old",
+                file.file_name,
+                age.num_hours(),
+                min_age.num_hours()
+            );
+            continue;
+        }
+        let found = version::of_file(&file.file_name, minecraft);
+        let Some(range) = range else {
+            // files come newest first
+            return Ok(Some((file, found)));
+        };
+        let Some(found) = found.filter(|v| range.matches(v)) else {
+            continue;
+        };
+        if best.as_ref().is_none_or(|(_, v)| version::compare(&found, v) == Ordering::Greater) {
+            best = Some((file, found));
+        }
+    }
+    Ok(best.map(|(file, found)| (file, Some(found))))
+}
@@ -0,0 +1,379 @@
+use super::update;
+use crate::cli::Args;
+use crate::prompt;
+use crate::sync::golden::{self, Golden};
+use crate::sync::hash::Algorithm;
+use crate::sync::manifest::{self, Kind, Mod};
+use crate::sync::settings::{self, InstanceSettings};
+use crate::sync::state::State;
+use crate::sync::{self, curse_files, hash, jar, modrinth, overrides, state, version};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+
+/// How thoroughly the instance is checked.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Depth {
+    /// Presence and size of the recorded files, for launch hooks.
+    Quick,
+    /// Every entry, its hash where unchanged files are remembered and its
+    /// version.
+    Full,
+    /// [`Depth::Full`] hashing every file again.
+    Deep,
+}
+
+pub struct Config {
+    pub base_dir: String,
+    pub mods_file: String,
+    pub depth: Depth,
+    /// Look the jars up on Modrinth and CurseForge by their hashes.
+    pub identify: bool,
+    /// Where to write the hashes of every file once the instance verified.
+    pub export: Option<PathBuf>,
+    /// Hash set of a golden instance to check this one against from now on.
+    pub import: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config> {
+        let parsed = Args::parse(&args[1..], &["quick", "deep", "identify"], &["export-hashes", "import-hashes"])?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let depth = match (parsed.has("quick"), parsed.has("deep")) {
+            (true, true) => return Err(anyhow!("--quick and --deep exclude each other")),
+            (true, false) => Depth::Quick,
+            (false, true) => Depth::Deep,
+            (false, false) => Depth::Full,
+        };
+        if depth == Depth::Quick && parsed.has("identify") {
+            return Err(anyhow!("--identify asks Modrinth and CurseForge about every jar, it is not quick"));
+        }
+        let export = parsed.value("export-hashes").map(PathBuf::from);
+        let import = parsed.value("import-hashes").map(PathBuf::from);
+        if depth == Depth::Quick && (export.is_some() || import.is_some()) {
+            return Err(anyhow!("the hashes of a golden instance are checked by hashing every file, it is not quick"));
+        }
+        // what goes out is read from disk, not from the cache
+        let depth = match export {
+            Some(_) => Depth::Deep,
+            None => depth,
+        };
+        Ok(Config {
+            base_dir,
+            mods_file: "modlist.json".to_string(),
+            depth,
+            identify: parsed.has("identify"),
+            export,
+            import,
+        })
+    }
+}
+
+/// Checks that every file the state records is present with the size it
+/// was installed with, without reading the modlist or any file. Overrides
+/// are left out, players edit them.
+fn quick(game_dir: &Path, state: &State) -> Result<()> {
+    let mut problems = 0;
+    let mut checked = 0;
+    for (path, managed) in state.entries(game_dir).filter(|(_, m)| m.source != overrides::SOURCE) {
+        checked += 1;
+        if !managed.files.is_empty() {
+            if let Some(missing) = managed.files.iter().find(|f| !game_dir.join(f).is_file()) {
+                println!("[ERR!] {} is missing, it was unpacked from {}", missing, path.display());
+                problems += 1;
+            }
+            continue;
+        }
+        match fs::metadata(&path) {
+            Ok(meta) if managed.size.is_some_and(|size| size != meta.len()) => {
+                println!("[ERR!] {} changed size since it was installed", path.display());
+                problems += 1;
+            }
+            Ok(_) => {}
+            Err(_) => {
+                println!("[ERR!] {} is missing", path.display());
+                problems += 1;
+            }
+        }
+    }
+    if problems > 0 {
+        return Err(anyhow!("{} problem(s) found, run `modpack-sync` to repair the instance", problems));
+    }
+    println!("[INFO] {} files present", checked);
+    Ok(())
+}
+
+/// Checks every entry of the effective modlist against the instance:
+/// missing files, files changed since they were installed and files whose
+/// version falls outside the entry's range. Fails when anything is off.
+/// The files of unmanaged directories are listed apart, they are never
+/// problems. `--quick` only looks at what the state records, unless the
+/// instance is due for a deep check.
+/// Whether a deep check every `hours` is due at `now` after the last one
+/// at `last`. Periods too long to count never come due.
+fn deep_due(hours: Option<u64>, last: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    let Some(every) = hours.map(|hours| i64::try_from(hours).ok().and_then(Duration::try_hours)) else {
+        return false;
+    };
+    last.is_none_or(|at| every.is_some_and(|every| now - at >= every))
+}
+
+pub fn run(config: Config) -> Result<()> {
+    let base_dir = PathBuf::from(&config.base_dir);
+    let instance = InstanceSettings::load(&base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&config.base_dir));
+    let mods_dir = PathBuf::from(instance.mods_dir(&config.base_dir));
+    let mut state = State::load(&base_dir)?;
+    let mut depth = config.depth;
+    if depth == Depth::Quick {
+        let every = instance.verify.as_ref().and_then(|v| v.deep_every_hours);
+        if !deep_due(every, state.deep_verified_at, Utc::now()) {
+            return quick(&game_dir, &state);
+        }
+        println!("[INFO] the last deep verification is too old, hashing every file");
+        depth = Depth::Deep;
+    }
+    if let Some(file) = &config.import {
+        let imported = Golden::load(file)?;
+        imported.save(&golden::path(&base_dir))?;
+        println!(
+            "[INFO] imported the hashes of {} files{} from {}, this and later verifications check against them",
+            imported.files.len(),
+            imported.pack.as_ref().map(|p| format!(" of {}", p)).unwrap_or_default(),
+            file.display()
+        );
+    }
+    let effective = sync::load_effective(&base_dir, &config.mods_file)?;
+    let mut cache = hash::Cache::load(&base_dir);
+    let unmanaged = instance.unmanaged.clone().unwrap_or_default();
+    // the installed files are hashed together, on every core at once
+    let installed: Vec<PathBuf> = effective
+        .mods
+        .iter()
+        .filter(|m| m.kind != Kind::World && !m.unpack)
+        .filter_map(|m| m.install_path(&game_dir, &mods_dir).ok())
+        .filter(|path| !overrides::inside_any(&state::key(&game_dir, path), &unmanaged))
+        .filter(|path| path.is_file() && state.get(&game_dir, path).is_some())
+        .collect();
+    let hashes = match depth {
+        Depth::Deep => cache.rehash_all(&installed)?,
+        _ => cache.hash_all(&installed)?,
+    };
+
+    let mut problems = 0;
+    for m in effective.mods.iter() {
+        let path = m.install_path(&game_dir, &mods_dir)?;
+        if overrides::inside_any(&state::key(&game_dir, &path), &unmanaged) {
+            println!("[WARN] {} installs into an unmanaged directory, it is not synced", m.name);
+            continue;
+        }
+        // worlds change as they are played, they are the players' anyway
+        if m.kind == Kind::World {
+            continue;
+        }
+        if m.unpack {
+            let extracted = state.get(&game_dir, &path).map(|managed| &managed.files);
+            if extracted.is_none_or(|files| files.iter().any(|f| !game_dir.join(f).is_file())) {
+                println!("[ERR!] {} is not unpacked at {}", m.name, path.display());
+                problems += 1;
+            }
+            continue;
+        }
+        if !path.is_file() {
+            println!("[ERR!] {} is missing, expected {}", m.name, path.display());
+            problems += 1;
+            continue;
+        }
+        if let Some(managed) = state.get(&game_dir, &path) {
+            if hashes.get(&path) != Some(&managed.sha256) {
+                println!("[ERR!] {} was modified since it was installed", path.display());
+                problems += 1;
+            }
+        }
+        let Some(range) = m.range()? else {
+            continue;
+        };
+        let installed = jar::fabric_meta(&path)
+            .ok()
+            .flatten()
+            .map(|meta| meta.version)
+            .filter(|v| !v.is_empty())
+            .or_else(|| version::of_file(&m.filename, effective.pack.minecraft.as_deref()));
+        match installed {
+            Some(installed) if !range.matches(&installed) => {
+                println!("[ERR!] {} {} does not satisfy {}", m.name, installed, range);
+                problems += 1;
+            }
+            Some(_) => {}
+            None => println!("[WARN] could not tell which version of {} is installed to check it against {}", m.name, range),
+        }
+    }
+    let _ = cache.save();
+
+    for dir in unmanaged.iter() {
+        let mut files = Vec::new();
+        if game_dir.join(dir).is_dir() {
+            overrides::files(&game_dir.join(dir), &mut files)?;
+        }
+        files.sort();
+        println!("[INFO] unmanaged {}: {} file(s)", dir, files.len());
+        for file in files {
+            println!("       {}", state::key(&game_dir, &file));
+        }
+    }
+
+    if config.identify {
+        identify(&config, &game_dir, &mods_dir, &effective.mods)?;
+    }
+
+    let golden = golden::path(&base_dir);
+    let pack = Some(format!("{} {}", effective.pack.name, effective.pack.version));
+    let current = match golden.exists() || config.export.is_some() {
+        true => Some(Golden::of(&game_dir, &mods_dir, &state, &mut cache, pack)?),
+        false => None,
+    };
+    let _ = cache.save();
+    if let (true, Some(current)) = (golden.exists(), &current) {
+        let differences = Golden::load(&golden)?.differences(current);
+        for difference in differences.iter() {
+            println!("[ERR!] {}", difference);
+        }
+        match differences.len() {
+            0 => println!("[INFO] the {} files match the golden instance byte for byte", current.files.len()),
+            n => problems += n,
+        }
+    }
+
+    if problems > 0 {
+        return Err(anyhow!("{} problem(s) found", problems));
+    }
+    if depth == Depth::Deep {
+        state.deep_verified_at = Some(Utc::now());
+        state.save(&base_dir)?;
+    }
+    if let (Some(out), Some(current)) = (&config.export, &current) {
+        current.save(out)?;
+        println!("[INFO] wrote the hashes of {} files to {}, import them elsewhere with `verify --import-hashes`", current.files.len(), out.display());
+    }
+    println!("[INFO] {} entries verified", effective.mods.len());
+    Ok(())
+}
+
+/// Looks the jars of the mods directory up on Modrinth by their sha512, and
+/// on CurseForge by their fingerprint when Modrinth doesn't know them. Jars
+/// no entry installs are named, and CurseForge entries whose file is on
+/// Modrinth too are offered to switch to it, which syncs without an API key.
+fn identify(config: &Config, game_dir: &Path, mods_dir: &Path, mods: &[Mod]) -> Result<()> {
+    let api_key = settings::api_key().ok();
+    let installs: HashMap<PathBuf, &Mod> = mods
+        .iter()
+        .filter_map(|m| Some((m.install_path(game_dir, mods_dir).ok()?, m)))
+        .collect();
+    let mut jars: Vec<PathBuf> = match fs::read_dir(mods_dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "jar"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    jars.sort();
+
+    let mut switches = Vec::new();
+    for path in jars.iter() {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let bytes = fs::read(path)?;
+        let found = match modrinth::version_of(&Algorithm::Sha512.bytes(&bytes)) {
+            Ok(found) => found,
+            Err(e) => {
+                println!("[WARN] could not look {} up on Modrinth: {:#}", name, e);
+                continue;
+            }
+        };
+        match (installs.get(path), found) {
+            (Some(m), Some(found)) => {
+                let curseforge = m.maven.is_none() && m.modrinth.is_none() && m.local_path().is_none() && m.project_id().is_some();
+                if curseforge {
+                    println!("[INFO] {} is on Modrinth too, version {} of {}", m.name, found.version_number, modrinth::page(&found.project_id));
+                    let pin = modrinth::Pin { project_id: found.project_id, version_id: found.id };
+                    switches.push((m.key().to_string(), m.name.clone(), pin));
+                }
+            }
+            (Some(_), None) => {}
+            (None, Some(found)) => {
+                let title = modrinth::project(&found.project_id).map_or_else(|_| found.project_id.clone(), |p| p.title);
+                println!(
+                    "[INFO] {} is not in the modlist, it is {} {} from {}",
+                    name,
+                    title,
+                    found.version_number,
+                    modrinth::page(&found.project_id)
+                );
+            }
+            (None, None) => {
+                let matched = api_key
+                    .as_deref()
+                    .and_then(|key| curse_files::match_fingerprints(&[curse_files::fingerprint(&bytes)], key).ok())
+                    .and_then(|matches| matches.into_iter().next());
+                match matched {
+                    Some(found) => println!(
+                        "[INFO] {} is not in the modlist, it is {}from https://www.curseforge.com/projects/{}",
+                        name,
+                        found.file.map(|f| format!("file {} ", f.id)).unwrap_or_default(),
+                        found.id
+                    ),
+                    None => println!("[WARN] {} is not in the modlist and neither Modrinth nor CurseForge know it", name),
+                }
+            }
+        }
+    }
+
+    if switches.is_empty() {
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        println!("[INFO] run `verify --identify` in a terminal to switch {} entries to Modrinth", switches.len());
+        return Ok(());
+    }
+    let (path, mut manifest) = update::load(&config.base_dir, &config.mods_file)?;
+    let mut switched = 0;
+    for (key, name, pin) in switches {
+        let Some(entry) = manifest.mods.iter_mut().find(|m| m.key() == key) else {
+            println!("[INFO] {} comes from a parent modlist, switch it there", name);
+            continue;
+        };
+        if prompt::confirm(&format!("Download {} from Modrinth instead of CurseForge?", name), true)? {
+            entry.modrinth = Some(pin);
+            entry.url = None;
+            switched += 1;
+        }
+    }
+    if switched > 0 {
+        manifest::save(&path, &manifest)?;
+        println!("[INFO] switched {} entries to Modrinth in {}", switched, path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deep_checks_come_due_without_overflowing() {
+        let now = Utc::now();
+        let day_ago = Some(now - Duration::hours(24));
+        assert!(!deep_due(None, day_ago, now));
+        assert!(deep_due(Some(24), day_ago, now));
+        assert!(!deep_due(Some(25), day_ago, now));
+        assert!(deep_due(Some(u64::MAX), None, now));
+        assert!(!deep_due(Some(u64::MAX), day_ago, now));
+        assert!(!deep_due(Some(i64::MAX as u64), day_ago, now));
+    }
+}
@@ -0,0 +1,230 @@
+//! Translations of the messages players see. Catalogs use a subset of the
+//! Fluent syntax, one `key = text` message per line with `{ $name }`
+//! placeholders and indented lines continuing the message above. Messages
+//! about a number pick their wording by its plural category:
+//!
+//! ```text
+//! gc-removed = { $count ->
+//!         [one] removed one file
+//!        *[other] removed { $count } files
+//!     }
+//! ```
+//!
+//! Locales shipped with the tool live in `locales/`, community translations
+//! can be dropped into `<config dir>/locales/<locale>.ftl` and take
+//! precedence.
+
+use crate::sync::settings::UserSettings;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::sync::OnceLock;
+
+/// Catalogs built into the binary, English being the fallback of all others.
+const BUILTIN: &[(&str, &str)] = &[("en", include_str!("../locales/en.ftl"))];
+const FALLBACK: &str = "en";
+
+/// The catalogs looked up, most specific first, with their language.
+static CATALOGS: OnceLock<Vec<(String, HashMap<String, String>)>> = OnceLock::new();
+
+/// Parses a catalog, skipping comments and lines it doesn't understand.
+fn parse(source: &str) -> HashMap<String, String> {
+    let mut messages: HashMap<String, String> = HashMap::new();
+    let mut current: Option<String> = None;
+    for line in source.lines() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        if line.starts_with([' ', '\t']) && !line.trim().is_empty() {
+            if let Some(text) = current.as_ref().and_then(|key| messages.get_mut(key)) {
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(line.trim());
+            }
+            continue;
+        }
+        current = None;
+        if let Some((key, text)) = line.split_once('=') {
+            let key = key.trim().to_string();
+            messages.insert(key.clone(), text.trim().to_string());
+            current = Some(key);
+        }
+    }
+    messages
+}
+
+/// The locales to look messages up in, most specific first: `de-AT`, `de`
+/// then English.
+fn locales() -> Vec<String> {
+    let wanted = env::var("MODPACK_SYNC_LANG")
+        .ok()
+        .or_else(|| UserSettings::load().ok().and_then(|s| s.language))
+        .or_else(|| {
+            ["LC_ALL", "LC_MESSAGES", "LANG"]
+                .iter()
+                .filter_map(|v| env::var(v).ok())
+                .find(|v| !v.is_empty())
+        })
+        .unwrap_or_default();
+    // `de_AT.UTF-8@euro` as POSIX locales name it
+    let tag = wanted.split(['.', '@']).next().unwrap_or_default().replace('_', "-");
+    let mut locales = Vec::new();
+    if !tag.is_empty() && tag != "C" && tag != "POSIX" {
+        if let Some((language, _)) = tag.split_once('-') {
+            locales.push(tag.clone());
+            locales.push(language.to_string());
+        } else {
+            locales.push(tag);
+        }
+    }
+    locales.push(FALLBACK.to_string());
+    locales.dedup();
+    locales
+}
+
+fn load() -> Vec<(String, HashMap<String, String>)> {
+    let community = UserSettings::dir().ok().map(|dir| dir.join("locales"));
+    let mut catalogs = Vec::new();
+    for locale in locales() {
+        let language = locale.split('-').next().unwrap_or_default().to_lowercase();
+        if let Some(contents) = community.as_ref().and_then(|dir| fs::read_to_string(dir.join(format!("{}.ftl", locale))).ok()) {
+            catalogs.push((language.clone(), parse(&contents)));
+        }
+        if let Some((_, contents)) = BUILTIN.iter().find(|(name, _)| name.eq_ignore_ascii_case(&locale)) {
+            catalogs.push((language, parse(contents)));
+        }
+    }
+    catalogs
+}
+
+/// The CLDR plural category of `n` in `language`, for the languages whose
+/// rules differ from English.
+fn plural(language: &str, n: f64) -> &'static str {
+    if n.fract() != 0.0 {
+        return "other";
+    }
+    let n = n.abs() as u64;
+    let (ones, tens) = (n % 10, n % 100);
+    match language {
+        "ja" | "ko" | "zh" | "vi" | "th" | "id" => "other",
+        "fr" | "pt" if n < 2 => "one",
+        "ru" | "uk" | "be" => match (ones, tens) {
+            (1, t) if t != 11 => "one",
+            (2..=4, t) if !(12..=14).contains(&t) => "few",
+            _ => "many",
+        },
+        "pl" => match (n, ones, tens) {
+            (1, _, _) => "one",
+            (_, 2..=4, t) if !(12..=14).contains(&t) => "few",
+            _ => "many",
+        },
+        "cs" | "sk" => match n {
+            1 => "one",
+            2..=4 => "few",
+            _ => "other",
+        },
+        _ if n == 1 => "one",
+        _ => "other",
+    }
+}
+
+/// Offset of the `}` closing the `{` that `text` starts with.
+fn closing(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn value(args: &[(&str, &dyn Display)], placeholder: &str) -> Option<String> {
+    let name = placeholder.strip_prefix('$')?;
+    args.iter().find(|(n, _)| *n == name).map(|(_, value)| value.to_string())
+}
+
+/// The variant of a select expression on `selector`, one `[key] text` per
+/// line with the default marked `*`, whose key is the selector's value or
+/// its plural category.
+fn select(selector: &str, variants: &str, language: &str, args: &[(&str, &dyn Display)]) -> String {
+    let value = value(args, selector).unwrap_or_default();
+    let category = value.parse::<f64>().ok().map(|n| plural(language, n));
+    let mut exact = None;
+    let mut categorized = None;
+    let mut default = None;
+    for line in variants.lines().map(str::trim) {
+        let (is_default, line) = match line.strip_prefix('*') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let Some((key, text)) = line.strip_prefix('[').and_then(|l| l.split_once(']')) else {
+            continue;
+        };
+        let key = key.trim();
+        if key == value {
+            exact.get_or_insert(text.trim());
+        }
+        if Some(key) == category {
+            categorized.get_or_insert(text.trim());
+        }
+        if is_default {
+            default = Some(text.trim());
+        }
+    }
+    exact.or(categorized).or(default).map_or(String::new(), |text| format(text, language, args))
+}
+
+/// `text` with its placeholders and select expressions filled in.
+fn format(text: &str, language: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = closing(&rest[start..]) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let inner = rest[start + 1..start + end].trim();
+        match inner.split_once("->") {
+            Some((selector, variants)) => out.push_str(&select(selector.trim(), variants, language, args)),
+            None => match value(args, inner) {
+                Some(value) => out.push_str(&value),
+                None => out.push_str(&rest[start..start + end + 1]),
+            },
+        }
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The message `key` in the player's language with its placeholders filled
+/// in. Falls back to English, and to the key itself for unknown messages.
+pub fn tr(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let catalogs = CATALOGS.get_or_init(load);
+    match catalogs.iter().find_map(|(language, c)| Some((language, c.get(key)?))) {
+        Some((language, text)) => format(text, language, args),
+        None => key.to_string(),
+    }
+}
+
+/// [`tr`] taking the placeholders as `name = value` pairs, e.g.
+/// `t!("sync-updated", from = old, to = new)`.
+macro_rules! t {
+    ($key:literal) => {
+        $crate::i18n::tr($key, &[])
+    };
+    ($key:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::tr($key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+])
+    };
+}
+pub(crate) use t;
@@ -1,14 +1,262 @@
+mod cli;
+mod commands;
+mod i18n;
+mod prompt;
+mod release;
 mod sync;
 
 use std::env;
+use std::path::Path;
 
 use sync::Config;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    // `--plain` applies to every command, so it is taken out before their
+    // flags are parsed
+    let plain = args.iter().any(|a| a == "--plain");
+    args.retain(|a| a != "--plain");
+    if plain || env::var_os("MODPACK_SYNC_PLAIN").is_some() || env::var("TERM").is_ok_and(|t| t == "dumb") {
+        sync::log::set_plain();
+    }
+    // so are `--debug-http <dir>`, recording the requests of the run, and
+    // `--replay <dir>`, answering them from such a recording
+    let record = take_option(&mut args, "--debug-http");
+    let replay = take_option(&mut args, "--replay");
+    let captured = match (record, replay) {
+        (_, Some(dir)) => sync::capture::replay_from(Path::new(&dir)),
+        (Some(dir), None) => sync::capture::record_to(Path::new(&dir)),
+        (None, None) => Ok(()),
+    };
+    if let Err(e) = captured {
+        eprintln!("[ERR!] {:#}", e);
+        std::process::exit(1);
+    }
+    // recordings have the key redacted, any key replays them
+    if sync::capture::replaying() && sync::settings::api_key().is_err() {
+        env::set_var("CURSE_API_KEY", sync::capture::REDACTED);
+    }
 
-    let config: Config = Config::build(&args).expect("expected a valid config");
-    println!("[INFO] Starting new run of modpack-sync...");
-    sync::run(config).expect("expected to install mods successfully");
-    println!("[INFO] modpack-sync finished successfully...");
+    sync::audit::set_command(&args);
+
+    match args.get(1).map(String::as_str) {
+        Some("migrate") => {
+            let config = commands::migrate::Config::build(&args[1..]).expect("expected a valid config");
+            commands::migrate::run(config).expect("expected to migrate modlist successfully");
+        }
+        Some("migrate-pack") => {
+            let config = commands::migrate_pack::Config::build(&args[1..]).expect("expected a valid config");
+            commands::migrate_pack::run(config).expect("expected to migrate the pack successfully");
+        }
+        Some("fmt") => {
+            let config = commands::fmt::Config::build(&args[1..]).expect("expected a valid config");
+            commands::fmt::run(config).expect("expected to format modlist successfully");
+        }
+        Some("merge") => {
+            let config = commands::merge::Config::build(&args[1..]).expect("expected a valid config");
+            commands::merge::run(config).expect("expected to merge modlists successfully");
+        }
+        Some("resolve") => {
+            let config = commands::resolve::Config::build(&args[1..]).expect("expected a valid config");
+            commands::resolve::run(config).expect("expected to resolve modlist successfully");
+        }
+        Some("setup") => {
+            let config = commands::setup::Config::build(&args[1..]).expect("expected a valid config");
+            commands::setup::run(config).expect("expected setup to complete successfully");
+        }
+        Some("doctor") => {
+            let config = commands::doctor::Config::build(&args[1..]).expect("expected a valid config");
+            commands::doctor::run(config).expect("expected all doctor checks to pass");
+        }
+        Some("list") => {
+            let config = commands::list::Config::build(&args[1..]).expect("expected a valid config");
+            commands::list::run(config).expect("expected to list mods successfully");
+        }
+        Some("search") => {
+            let config = commands::search::Config::build(&args[1..]).expect("expected a valid config");
+            commands::search::run(config).expect("expected to search successfully");
+        }
+        Some("info") => {
+            let config = commands::info::Config::build(&args[1..]).expect("expected a valid config");
+            commands::info::run(config).expect("expected to show mod info successfully");
+        }
+        Some("deps") => {
+            let config = commands::deps::Config::build(&args[1..]).expect("expected a valid config");
+            commands::deps::run(config).expect("expected to build the dependency graph successfully");
+        }
+        Some("analyze") => {
+            let config = commands::analyze::Config::build(&args[1..]).expect("expected a valid config");
+            commands::analyze::run(config).expect("expected to analyze the pack successfully");
+        }
+        Some("blame") => {
+            let config = commands::blame::Config::build(&args[1..]).expect("expected a valid config");
+            commands::blame::run(config).expect("expected to read the crash report successfully");
+        }
+        Some("bisect") => {
+            let config = commands::bisect::Config::build(&args[1..]).expect("expected a valid config");
+            commands::bisect::run(config).expect("expected to bisect the mods successfully");
+        }
+        Some("bench") => {
+            let config = commands::bench::Config::build(&args[1..]).expect("expected a valid config");
+            commands::bench::run(config).expect("expected to benchmark the instance successfully");
+        }
+        Some("lint") => {
+            let config = commands::lint::Config::build(&args[1..]).expect("expected a valid config");
+            commands::lint::run(config).expect("expected to lint the modlist successfully");
+        }
+        Some("control") => {
+            let config = commands::control::Config::build(&args[1..]).expect("expected a valid config");
+            commands::control::run(config).expect("expected the daemon to carry out the request");
+        }
+        Some("publish") => {
+            let config = commands::publish::Config::build(&args[1..]).expect("expected a valid config");
+            commands::publish::run(config).expect("expected to publish the pack successfully");
+        }
+        Some("export") => {
+            let config = commands::export::Config::build(&args[1..]).expect("expected a valid config");
+            commands::export::run(config).expect("expected to export the pack successfully");
+        }
+        Some("create") => {
+            let config = commands::create::Config::build(&args[1..]).expect("expected a valid config");
+            commands::create::run(config).expect("expected to create the instance successfully");
+        }
+        Some("join") => {
+            let config = commands::join::Config::build(&args[1..]).expect("expected a valid config");
+            commands::join::run(config).expect("expected to join the server successfully");
+        }
+        Some("preview") => {
+            let config = commands::preview::Config::build(&args[1..]).expect("expected a valid config");
+            commands::preview::run(config).expect("expected to preview the pack successfully");
+        }
+        Some("plan") => {
+            let config = commands::plan::Config::build(&args[1..]).expect("expected a valid config");
+            commands::plan::run(config).expect("expected to write the plan successfully");
+        }
+        Some("prefetch") => {
+            let config = commands::prefetch::Config::build(&args[1..]).expect("expected a valid config");
+            commands::prefetch::run(config).expect("expected to prefetch the pending files successfully");
+        }
+        Some("serve") => {
+            let config = commands::serve::Config::build(&args[1..]).expect("expected a valid config");
+            commands::serve::run(config).expect("expected to serve the pack's files successfully");
+        }
+        Some("retry") => {
+            let config = commands::retry::Config::build(&args[1..]).expect("expected a valid config");
+            commands::retry::run(config).expect("expected to retry the failed entries successfully");
+        }
+        Some("gc") => {
+            let config = commands::gc::Config::build(&args[1..]).expect("expected a valid config");
+            commands::gc::run(config).expect("expected to clean up the instance successfully");
+        }
+        Some("stats") => {
+            let config = commands::stats::Config::build(&args[1..]).expect("expected a valid config");
+            commands::stats::run(config).expect("expected to show the usage statistics successfully");
+        }
+        Some("import-ftb") => {
+            let config = commands::import_ftb::Config::build(&args[1..]).expect("expected a valid config");
+            commands::import_ftb::run(config).expect("expected to import the FTB pack successfully");
+        }
+        Some("inspect") => {
+            let config = commands::inspect::Config::build(&args[1..]).expect("expected a valid config");
+            commands::inspect::run(config).expect("expected to inspect the backup successfully");
+        }
+        Some("diff") => {
+            let config = commands::diff::Config::build(&args[1..]).expect("expected a valid config");
+            commands::diff::run(config).expect("expected to compare against the backup successfully");
+        }
+        Some("encrypt-secret") => {
+            let config = commands::encrypt_secret::Config::build(&args[1..]).expect("expected a valid config");
+            commands::encrypt_secret::run(config).expect("expected to encrypt the secret successfully");
+        }
+        Some("support-bundle") => {
+            let config = commands::support_bundle::Config::build(&args[1..]).expect("expected a valid config");
+            commands::support_bundle::run(config).expect("expected to write the support bundle successfully");
+        }
+        Some("fleet") => {
+            let config = commands::fleet::Config::build(&args[1..]).expect("expected a valid config");
+            commands::fleet::run(config).expect("expected to list the fleet successfully");
+        }
+        Some("apply") => {
+            let config = commands::apply::Config::build(&args[1..]).expect("expected a valid config");
+            commands::apply::run(config).expect("expected to apply the plan successfully");
+        }
+        Some("update") => {
+            let config = commands::update::Config::build(&args[1..]).expect("expected a valid config");
+            commands::update::run(config).expect("expected to update the modlist successfully");
+        }
+        Some("outdated") => {
+            let config = commands::outdated::Config::build(&args[1..]).expect("expected a valid config");
+            commands::outdated::run(config).expect("expected to check for updates successfully");
+        }
+        Some("verify") => {
+            let config = commands::verify::Config::build(&args[1..]).expect("expected a valid config");
+            commands::verify::run(config).expect("expected the instance to match its modlist");
+        }
+        Some("remove") => {
+            let config = commands::remove::Config::build(&args[1..]).expect("expected a valid config");
+            commands::remove::run(config).expect("expected to remove the mods successfully");
+        }
+        Some("daemon") => {
+            let config = commands::daemon::Config::build(&args[1..]).expect("expected a valid config");
+            commands::daemon::run(config).expect("expected the daemon to run successfully");
+        }
+        Some("quarantine") => {
+            let config = commands::quarantine::Config::build(&args[1..]).expect("expected a valid config");
+            commands::quarantine::run(config).expect("expected to manage the quarantine successfully");
+        }
+        Some("restore-backup") => {
+            let config = commands::restore_backup::Config::build(&args[1..]).expect("expected a valid config");
+            commands::restore_backup::run(config).expect("expected to restore the backup successfully");
+        }
+        Some("logs") => {
+            let config = commands::logs::Config::build(&args[1..]).expect("expected a valid config");
+            commands::logs::run(config).expect("expected to read logs successfully");
+        }
+        Some("completions") => {
+            let config = commands::completions::Config::build(&args[1..]).expect("expected a valid config");
+            commands::completions::run(config).expect("expected to print completions successfully");
+        }
+        Some("self-update") => {
+            let config = commands::self_update::Config::build(&args[1..]).expect("expected a valid config");
+            commands::self_update::run(config).expect("expected to update modpack-sync successfully");
+        }
+        Some("help") | Some("--help") | Some("-h") | None => cli::print_help(),
+        Some("sync") => run_sync(&args[1..]),
+        _ => run_sync(&args),
+    }
+}
+
+fn run_sync(args: &[String]) {
+    let config: Config = Config::build(args).expect("expected a valid config");
+    sync::log::console!("[INFO] {}", i18n::t!("sync-starting"));
+    let base_dir = config.base_dir.clone();
+    if let Err(e) = sync::run(config) {
+        eprintln!("[ERR!] {}", i18n::t!("sync-failed", error = format!("{:#}", e)));
+        eprintln!(
+            "       {}",
+            i18n::t!("sync-failure-report", report = sync::failure::path(std::path::Path::new(&base_dir)).display())
+        );
+        std::process::exit(1);
+    }
+    release::notify_if_outdated();
+    sync::log::console!("[INFO] {}", i18n::t!("sync-finished"));
+}
+
+/// Takes `name <value>` or `name=value` out of `args`, the last one given.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    let mut value = None;
+    let mut i = 0;
+    while i < args.len() {
+        if let Some(v) = args[i].strip_prefix(&prefix) {
+            value = Some(v.to_string());
+            args.remove(i);
+        } else if args[i] == name && i + 1 < args.len() {
+            value = Some(args.remove(i + 1));
+            args.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    value
 }
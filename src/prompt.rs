@@ -0,0 +1,45 @@
+use crate::i18n::t;
+use anyhow::{anyhow, Result};
+use std::io::{self, BufRead, Write};
+
+/// Asks a question on stdin, returning `default` for an empty answer.
+pub fn ask(question: &str, default: Option<&str>) -> Result<String> {
+    match default {
+        Some(d) if !d.is_empty() => print!("{} [{}]: ", question, d),
+        _ => print!("{}: ", question),
+    }
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(anyhow!("no input available for: {}", question));
+    }
+
+    let answer = line.trim();
+    if answer.is_empty() {
+        return Ok(default.unwrap_or_default().to_string());
+    }
+    Ok(answer.to_string())
+}
+
+/// Asks a yes or no question in the words of the locale, the English ones
+/// and the first letters of both understood as well.
+pub fn confirm(question: &str, default: bool) -> Result<bool> {
+    let (yes, no) = (t!("prompt-yes").to_lowercase(), t!("prompt-no").to_lowercase());
+    let initial = |word: &str| word.chars().next().map(String::from).unwrap_or_default();
+    let words = |word: &str, english: &str| [word.to_string(), initial(word), english.to_string(), initial(english)];
+    let (yeses, noes) = (words(&yes, "yes"), words(&no, "no"));
+    let hint = match default {
+        true => format!("{}/{}", initial(&yes).to_uppercase(), initial(&no)),
+        false => format!("{}/{}", initial(&yes), initial(&no).to_uppercase()),
+    };
+    loop {
+        let answer = ask(&format!("{} ({})", question, hint), None)?.to_lowercase();
+        match answer.as_str() {
+            "" => return Ok(default),
+            a if yeses.iter().any(|w| w == a) => return Ok(true),
+            a if noes.iter().any(|w| w == a) => return Ok(false),
+            _ => println!("{}", t!("prompt-answer-either", yes = yes, no = no)),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::sync::http::RequestExt;
+use crate::sync::settings::UserSettings;
+use crate::sync::{log_to_file, version};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use reqwest::header::{ACCEPT, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+const LATEST_URL: &str = "https://api.github.com/repos/dev-pengu/modpack-sync/releases/latest";
+
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    pub fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    pub fn is_newer(&self) -> bool {
+        version::compare(self.version(), CURRENT_VERSION) == Ordering::Greater
+    }
+
+    /// The binary built for this platform, named like
+    /// `modpack-sync-linux-x86_64` or `modpack-sync-windows-x86_64.exe`.
+    pub fn binary(&self) -> Result<&Asset> {
+        let platform = format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH);
+        self.assets
+            .iter()
+            .find(|a| a.name.contains(&platform) && !a.name.ends_with(".sha256"))
+            .ok_or_else(|| anyhow!("release {} has no binary for {}", self.tag_name, platform))
+    }
+
+    /// The published checksum file for an asset, `<asset>.sha256`.
+    pub fn checksum_for(&self, asset: &Asset) -> Result<&Asset> {
+        let name = format!("{}.sha256", asset.name);
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| anyhow!("release {} has no checksum for {}", self.tag_name, asset.name))
+    }
+}
+
+pub fn client() -> Result<reqwest::blocking::Client> {
+    Ok(crate::sync::http::bind(reqwest::blocking::Client::builder())
+        .timeout(Duration::from_secs(30))
+        .build()?)
+}
+
+pub fn latest(client: &reqwest::blocking::Client) -> Result<Release> {
+    let release = client
+        .get(LATEST_URL)
+        .header(USER_AGENT, format!("modpack-sync/{}", CURRENT_VERSION))
+        .header(ACCEPT, "application/vnd.github+json")
+        .send_captured()?
+        .error_for_status()?
+        .json::<Release>()?;
+    Ok(release)
+}
+
+/// Result of the last release check, cached so fleets of machines only ask
+/// GitHub once a day.
+#[derive(Serialize, Deserialize)]
+struct CheckState {
+    checked_at: DateTime<Utc>,
+    latest: String,
+}
+
+/// Prints and logs a one line notice when a newer release exists. Opt out
+/// with `check_updates = false` in the user settings or by setting
+/// MODPACK_SYNC_NO_UPDATE_CHECK. Never fails the run.
+pub fn notify_if_outdated() {
+    if env::var_os("MODPACK_SYNC_NO_UPDATE_CHECK").is_some() || crate::sync::capture::replaying() {
+        return;
+    }
+    let settings = UserSettings::load().unwrap_or_default();
+    if settings.check_updates == Some(false) {
+        return;
+    }
+
+    let Some(latest) = cached_latest() else {
+        return;
+    };
+    if version::compare(&latest, CURRENT_VERSION) == Ordering::Greater {
+        let notice = format!(
+            "[INFO] modpack-sync {} is available (running {}), run `modpack-sync self-update`",
+            latest, CURRENT_VERSION
+        );
+        crate::sync::log::console!("{}", notice);
+        let _ = log_to_file(&notice);
+    }
+}
+
+fn cached_latest() -> Option<String> {
+    let path = UserSettings::dir().ok()?.join("update-check.json");
+    let cached: Option<CheckState> = fs::read_to_string(&path)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok());
+
+    if let Some(state) = &cached {
+        if Utc::now() - state.checked_at < chrono::Duration::days(1) {
+            return Some(state.latest.clone());
+        }
+    }
+
+    let client = crate::sync::http::bind(reqwest::blocking::Client::builder())
+        .timeout(Duration::from_secs(3))
+        .build()
+        .ok()?;
+    let latest = match latest(&client) {
+        Ok(release) => release.version().to_string(),
+        // keep quiet on network trouble, the cached answer is still useful
+        Err(_) => return cached.map(|s| s.latest),
+    };
+
+    let state = CheckState {
+        checked_at: Utc::now(),
+        latest: latest.clone(),
+    };
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(contents) = serde_json::to_string(&state) {
+        let _ = fs::write(&path, contents);
+    }
+    Some(latest)
+}
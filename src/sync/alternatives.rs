@@ -0,0 +1,30 @@
+use super::{curse_files, modrinth};
+
+/// Search results kept of each provider.
+const PER_PROVIDER: usize = 3;
+
+/// Projects named like `name` that have files for the pack's `minecraft`
+/// version and `loader`, such as forks and ports of a mod that was left
+/// behind, leaving out its `own` CurseForge project. Failed searches
+/// simply suggest nothing.
+pub fn find(name: &str, own: Option<&str>, minecraft: Option<&str>, loader: Option<&str>, api_key: &str) -> Vec<String> {
+    let own = own.unwrap_or_default();
+    let mut found = Vec::new();
+    if let Ok((projects, _)) = curse_files::search(name, minecraft, loader, curse_files::Page::FIRST, api_key) {
+        found.extend(
+            projects
+                .iter()
+                .filter(|p| p.id.to_string() != own)
+                .take(PER_PROVIDER)
+                .map(|p| format!("{} on CurseForge, project {}, {} downloads", p.name, p.id, p.download_count as u64)),
+        );
+    }
+    if let Ok(hits) = modrinth::search(name, minecraft, loader) {
+        found.extend(
+            hits.iter()
+                .take(PER_PROVIDER)
+                .map(|h| format!("{} on Modrinth, slug {}, {} downloads", h.title, h.slug, h.downloads)),
+        );
+    }
+    found
+}
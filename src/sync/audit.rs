@@ -0,0 +1,88 @@
+use super::hash;
+use super::log::STATE_DIR;
+use super::log_to_file;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Lines are only ever appended, so it may be made append-only with
+/// `chattr +a` where several admins share a server. Unlike the run logs it
+/// is never pruned.
+pub fn path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("audit.log")
+}
+
+static COMMAND: OnceLock<String> = OnceLock::new();
+
+/// Records the command line changes of this run are made by.
+pub fn set_command(args: &[String]) {
+    let _ = COMMAND.set(args.get(1..).unwrap_or_default().join(" "));
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Installed,
+    Deleted,
+    /// A file failing its checks was moved out of the instance.
+    Quarantined,
+    ManifestWritten,
+    /// A backup was put back, `after` being the hash of its archive.
+    RolledBack,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    at: DateTime<Utc>,
+    action: Action,
+    /// Relative to the instance when inside it.
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<&'a str>,
+    command: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    /// Who ran the command through sudo.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sudo_user: Option<String>,
+}
+
+/// Appends a change of `path` with the sha256 of the file `before` and
+/// `after` it to the audit log of the instance. Failing to record is
+/// logged, the change is made either way.
+pub fn record(base_dir: &Path, action: Action, path: &Path, before: Option<&str>, after: Option<&str>) {
+    let entry = Entry {
+        at: Utc::now(),
+        action,
+        path: path.strip_prefix(base_dir).unwrap_or(path).to_string_lossy().to_string(),
+        before,
+        after,
+        command: COMMAND.get().map_or("", String::as_str),
+        user: env::var("USER").or_else(|_| env::var("USERNAME")).ok(),
+        sudo_user: env::var("SUDO_USER").ok(),
+    };
+    if let Err(e) = append(base_dir, &entry) {
+        let _ = log_to_file(&format!("[WARN] failed to write the audit log of {}: {:#}", base_dir.display(), e));
+    }
+}
+
+/// The sha256 of the file at `path` as it is now, for the `before` of a
+/// change about to be made.
+pub fn hash(path: &Path) -> Option<String> {
+    hash::sha256_file(path).ok()
+}
+
+fn append(base_dir: &Path, entry: &Entry) -> Result<()> {
+    let path = path(base_dir);
+    fs::create_dir_all(path.parent().expect("the audit log lies in the state directory"))?;
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use super::hosts;
+use super::log_to_file;
+use super::settings::{self, UserSettings};
+use reqwest::blocking::RequestBuilder;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// What is sent to a host the user settings hold credentials for.
+enum Credential {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+/// Variable the secret of `host` is read from before the keyring, e.g.
+/// `MODPACK_SYNC_AUTH_NEXUS_EXAMPLE_COM` for `nexus.example.com`.
+fn env_var(host: &str) -> String {
+    let host: String = host
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    format!("MODPACK_SYNC_AUTH_{}", host)
+}
+
+/// Credentials of every configured host, their secrets looked up once a
+/// run. Hosts without a secret get none and are warned about.
+fn credentials() -> &'static HashMap<String, Credential> {
+    static CREDENTIALS: OnceLock<HashMap<String, Credential>> = OnceLock::new();
+    CREDENTIALS.get_or_init(|| {
+        let hosts = UserSettings::load().map(|s| s.hosts).unwrap_or_default();
+        let mut credentials = HashMap::new();
+        for (host, auth) in hosts {
+            let Some(secret) = settings::secret(&env_var(&host), &host) else {
+                let _ = log_to_file(&format!(
+                    "[WARN] no secret for {}, set {} or store it in the keyring for account {}",
+                    host,
+                    env_var(&host),
+                    host
+                ));
+                continue;
+            };
+            let credential = match auth.username {
+                Some(username) => Credential::Basic { username, password: secret },
+                None => Credential::Bearer(secret),
+            };
+            credentials.insert(host.to_lowercase(), credential);
+        }
+        credentials
+    })
+}
+
+/// The secrets of the configured hosts, to keep them out of what is shared.
+pub fn secrets() -> Vec<String> {
+    credentials()
+        .values()
+        .map(|credential| match credential {
+            Credential::Basic { password, .. } => password.clone(),
+            Credential::Bearer(token) => token.clone(),
+        })
+        .collect()
+}
+
+/// `request` to `url` with the credentials of its host, as it is when the
+/// host has none.
+pub fn apply(request: RequestBuilder, url: &str) -> RequestBuilder {
+    match credentials().get(&hosts::of(url).to_lowercase()) {
+        Some(Credential::Basic { username, password }) => request.basic_auth(username, Some(password)),
+        Some(Credential::Bearer(token)) => request.bearer_auth(token),
+        None => request,
+    }
+}
@@ -0,0 +1,284 @@
+use super::audit;
+use super::hash;
+use super::links;
+use super::log::STATE_DIR;
+use super::state::{self, State};
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::path::{Component, Path, PathBuf};
+
+/// Default number of backups kept per instance.
+pub const DEFAULT_KEEP: usize = 5;
+const EXTENSION: &str = ".tar.zst";
+/// File name prefix of backups of managed files.
+pub const MODS: &str = "mods";
+/// File name prefix of world backups.
+pub const WORLD: &str = "world";
+/// Worlds above this size are compressed at the fastest level.
+const LARGE_WORLD: u64 = 1024 * 1024 * 1024;
+/// Archive entry holding the instance state at the time of the backup.
+const STATE_ENTRY: &str = "state.json";
+/// Archive directory holding the backed up files, relative to the game dir.
+const FILES_ENTRY: &str = "files";
+
+pub fn dir(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("backups")
+}
+
+/// Backups of an instance of the given kind, oldest first.
+pub fn list(base_dir: &Path, kind: &str) -> Result<Vec<PathBuf>> {
+    let prefix = format!("{}-", kind);
+    let dir = dir(base_dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups: Vec<PathBuf> = fs::read_dir(&dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            let name = p.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with(&prefix) && name.ends_with(EXTENSION)
+        })
+        .collect();
+    backups.sort();
+    Ok(backups)
+}
+
+/// Streams `files`, given as (path on disk, name in the archive), into a
+/// zstd compressed tarball at `dest`.
+pub fn write_archive(dest: &Path, files: &[(PathBuf, String)]) -> Result<()> {
+    let mut tar = archive(dest, 0)?;
+    for (path, name) in files {
+        tar.append_path_with_name(path, name)
+            .with_context(|| format!("failed to archive {}", path.display()))?;
+    }
+    tar.into_inner()?;
+    Ok(())
+}
+
+/// A zstd compressed tarball being written to `dest`, `level` 0 being
+/// zstd's default.
+pub fn archive(dest: &Path, level: i32) -> Result<tar::Builder<zstd::stream::AutoFinishEncoder<'static, File>>> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let out = File::create(dest).with_context(|| format!("failed to create {}", dest.display()))?;
+    let encoder = zstd::Encoder::new(out, level)?.auto_finish();
+    Ok(tar::Builder::new(encoder))
+}
+
+pub fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| match e.metadata() {
+            Ok(m) if m.is_dir() => dir_size(&e.path()),
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Archives a world directory, keeping the newest `keep` world backups.
+/// Large worlds trade compression ratio for speed. Returns the archive with
+/// the size of the world it holds.
+pub fn world(base_dir: &Path, world_dir: &Path, keep: usize) -> Result<(PathBuf, u64)> {
+    if !world_dir.is_dir() {
+        return Err(anyhow!("world directory {} does not exist", world_dir.display()));
+    }
+    let size = dir_size(world_dir);
+    let level = if size > LARGE_WORLD { 1 } else { 0 };
+
+    let name = format!("{}-{}{}", WORLD, Local::now().format("%Y%m%d-%H%M%S"), EXTENSION);
+    let dest = dir(base_dir).join(name);
+    let mut tar = archive(&dest, level)?;
+    let root = world_dir.file_name().map_or("world".into(), |n| n.to_string_lossy());
+    tar.append_dir_all(root.as_ref(), world_dir)
+        .with_context(|| format!("failed to archive {}", world_dir.display()))?;
+    tar.into_inner()?;
+    prune(list(base_dir, WORLD)?, keep);
+    Ok((dest, size))
+}
+
+/// Deletes all but the newest `keep` of `archives`, which are oldest first.
+pub fn prune(mut archives: Vec<PathBuf>, keep: usize) {
+    while archives.len() > keep {
+        let _ = fs::remove_file(archives.remove(0));
+    }
+}
+
+/// The files a backup of the instance takes: every managed file and the
+/// top-level jars of the mods directory.
+pub fn snapshot_paths(base_dir: &Path, game_dir: &Path, mods_dir: &Path) -> Result<Vec<PathBuf>> {
+    let state = State::load(base_dir)?;
+    let mut paths = state.paths(game_dir);
+    if mods_dir.is_dir() {
+        for entry in fs::read_dir(mods_dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|e| e == "jar") && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Snapshots every managed file and top-level jar of the mods directory along
+/// with the instance state, keeping the newest `keep` backups.
+pub fn create(base_dir: &Path, game_dir: &Path, mods_dir: &Path, keep: usize) -> Result<PathBuf> {
+    let paths = snapshot_paths(base_dir, game_dir, mods_dir)?;
+    let mut files: Vec<(PathBuf, String)> = paths
+        .into_iter()
+        .filter(|p| p.is_file())
+        .filter_map(|p| {
+            let relative = p.strip_prefix(game_dir).ok()?.to_string_lossy().replace('\\', "/");
+            Some((p.clone(), format!("{}/{}", FILES_ENTRY, relative)))
+        })
+        .collect();
+    let state_path = state::path(base_dir);
+    if state_path.exists() {
+        files.push((state_path, STATE_ENTRY.to_string()));
+    }
+
+    let name = format!("{}-{}{}", MODS, Local::now().format("%Y%m%d-%H%M%S"), EXTENSION);
+    let dest = dir(base_dir).join(name);
+    write_archive(&dest, &files)?;
+    prune(list(base_dir, MODS)?, keep);
+    Ok(dest)
+}
+
+/// The backup of managed files `id` names: its file name, with or without
+/// the extension, or the time it was taken as in its name.
+pub fn find(base_dir: &Path, id: &str) -> Result<PathBuf> {
+    let id = id.trim_end_matches(EXTENSION);
+    list(base_dir, MODS)?
+        .into_iter()
+        .find(|b| {
+            let name = b.file_name().unwrap_or_default().to_string_lossy();
+            let stem = name.trim_end_matches(EXTENSION);
+            stem == id || stem.strip_prefix(&format!("{}-", MODS)) == Some(id)
+        })
+        .ok_or_else(|| anyhow!("no backup {}, see `restore-backup --list`", id))
+}
+
+/// A file kept in a backup.
+pub struct Snapshotted {
+    /// Path relative to the game directory, with `/` separators.
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Opens `archive` for reading its entries one after the other.
+fn open(archive: &Path) -> Result<tar::Archive<zstd::Decoder<'static, std::io::BufReader<File>>>> {
+    let file = File::open(archive).with_context(|| format!("failed to open {}", archive.display()))?;
+    Ok(tar::Archive::new(zstd::Decoder::new(file)?))
+}
+
+/// Path of a backup entry relative to the game directory, `None` for the
+/// state kept next to the files.
+fn relative(name: &Path) -> Result<Option<PathBuf>> {
+    if name == Path::new(STATE_ENTRY) {
+        return Ok(None);
+    }
+    let relative = name
+        .strip_prefix(FILES_ENTRY)
+        .map_err(|_| anyhow!("unexpected entry {} in backup", name.display()))?;
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err(anyhow!("backup entry {} escapes the instance", name.display()));
+    }
+    Ok(Some(relative.to_path_buf()))
+}
+
+/// The files `archive` holds with their size and sha256, read without
+/// restoring anything.
+pub fn contents(archive: &Path) -> Result<Vec<Snapshotted>> {
+    let mut tar = open(archive)?;
+    let mut files = Vec::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let Some(relative) = relative(&entry.path()?)? else {
+            continue;
+        };
+        let mut hasher = Sha256::new();
+        let size = std::io::copy(&mut entry, &mut hasher)?;
+        files.push(Snapshotted {
+            path: relative.to_string_lossy().replace('\\', "/"),
+            size,
+            sha256: hash::to_hex(&hasher.finalize()),
+        });
+    }
+    Ok(files)
+}
+
+/// Writes the files of `archive` whose path is or lies under one of
+/// `wanted`, every file when it is empty, into `to` at their path in the
+/// game directory. The instance is left alone. Returns what was extracted.
+pub fn extract(archive: &Path, wanted: &[String], to: &Path) -> Result<Vec<PathBuf>> {
+    let mut tar = open(archive)?;
+    let mut extracted = Vec::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let Some(relative) = relative(&entry.path()?)? else {
+            continue;
+        };
+        let matches = |w: &String| relative.starts_with(w.trim_end_matches('/'));
+        if !wanted.is_empty() && !wanted.iter().any(matches) {
+            continue;
+        }
+        let dest = to.join(&relative);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&dest).with_context(|| format!("failed to extract {}", dest.display()))?;
+        extracted.push(dest);
+    }
+    Ok(extracted)
+}
+
+/// Puts the files and state of `archive` back. Jars in the mods directory
+/// that were not part of the backup are removed, they came from later syncs.
+pub fn restore(base_dir: &Path, game_dir: &Path, mods_dir: &Path, archive: &Path) -> Result<usize> {
+    let mut tar = open(archive)?;
+
+    let mut restored = Vec::new();
+    let mut had_state = false;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let dest = match relative(&entry.path()?)? {
+            None => {
+                had_state = true;
+                state::path(base_dir)
+            }
+            Some(relative) => {
+                let dest = game_dir.join(relative);
+                restored.push(dest.clone());
+                dest
+            }
+        };
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&dest).with_context(|| format!("failed to restore {}", dest.display()))?;
+    }
+    if !had_state {
+        let _ = fs::remove_file(state::path(base_dir));
+    }
+
+    if mods_dir.is_dir() {
+        for entry in fs::read_dir(mods_dir)? {
+            let path = entry?.path();
+            let jar = path.is_file() && path.extension().is_some_and(|e| e == "jar");
+            if jar && !restored.contains(&path) && links::inside(base_dir, &path) {
+                let before = audit::hash(&path);
+                fs::remove_file(&path)?;
+                audit::record(base_dir, audit::Action::Deleted, &path, before.as_deref(), None);
+            }
+        }
+    }
+    audit::record(base_dir, audit::Action::RolledBack, archive, None, audit::hash(archive).as_deref());
+    Ok(restored.len())
+}
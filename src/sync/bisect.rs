@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Suffix launchers give jars they don't load, e.g. Prism's disabled mods.
+pub const DISABLED: &str = ".disabled";
+
+/// A search for the mod behind a crash in progress, kept in the state file
+/// across launches. Files are keyed like the state's, relative to the game
+/// directory.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Bisect {
+    pub started_at: DateTime<Utc>,
+    /// Jars one of which is behind the crash, narrowed down with each answer.
+    pub suspects: Vec<String>,
+    /// The half of the suspects loaded for the current launch, the others are
+    /// disabled.
+    pub testing: Vec<String>,
+    /// Launches answered so far.
+    #[serde(default)]
+    pub rounds: u32,
+}
+
+impl Bisect {
+    pub fn new(suspects: Vec<String>) -> Bisect {
+        let mut bisect = Bisect { started_at: Utc::now(), suspects, testing: Vec::new(), rounds: 0 };
+        bisect.split();
+        bisect
+    }
+
+    /// Loads the first half of the suspects for the next launch.
+    fn split(&mut self) {
+        self.testing = self.suspects[..self.suspects.len().div_ceil(2)].to_vec();
+    }
+
+    /// Narrows the suspects down by whether the launch crashed, to the half
+    /// that was loaded when it did and to the other half when it didn't.
+    /// Hands back the culprit once a single suspect is left.
+    pub fn answer(&mut self, crashed: bool) -> Option<String> {
+        self.rounds += 1;
+        match crashed {
+            true => self.suspects = std::mem::take(&mut self.testing),
+            false => self.suspects.retain(|s| !self.testing.contains(s)),
+        }
+        match self.suspects.as_slice() {
+            [culprit] => Some(culprit.clone()),
+            _ => {
+                self.split();
+                None
+            }
+        }
+    }
+
+    /// Suspects disabled for the current launch.
+    pub fn disabled(&self) -> impl Iterator<Item = &String> {
+        self.suspects.iter().filter(|s| !self.testing.contains(s))
+    }
+
+    /// Launches left at most before the culprit is found.
+    pub fn remaining(&self) -> u32 {
+        self.suspects.len().next_power_of_two().trailing_zeros()
+    }
+}
+
+fn disabled_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(DISABLED);
+    PathBuf::from(name)
+}
+
+/// Renames the jar at `key` so the launcher skips it.
+pub fn disable(game_dir: &Path, key: &str) -> Result<()> {
+    let path = game_dir.join(key);
+    match path.is_file() {
+        true => fs::rename(&path, disabled_path(&path)).with_context(|| format!("failed to disable {}", path.display())),
+        false => Ok(()),
+    }
+}
+
+/// Puts back a jar [`disable`] renamed.
+pub fn enable(game_dir: &Path, key: &str) -> Result<()> {
+    let path = game_dir.join(key);
+    let disabled = disabled_path(&path);
+    match disabled.is_file() {
+        true => fs::rename(&disabled, &path).with_context(|| format!("failed to enable {}", path.display())),
+        false => Ok(()),
+    }
+}
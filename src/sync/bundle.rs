@@ -0,0 +1,483 @@
+use super::backup;
+use super::extends;
+use super::hash;
+use super::links;
+use super::lock::{self, Lockfile, LOCK_FILE};
+use super::manifest::{self, Manifest, PackHeader, FRAGMENTS_DIR};
+use super::overrides::{self, OVERRIDES_DIR};
+use super::plan::MANUAL_SOURCE;
+use super::redistribution::{self, Verdict};
+use super::stamp;
+use super::state::{Managed, State};
+use super::log::STATE_DIR;
+use super::log_to_file;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Archive entry describing the bundle, written first.
+const INDEX_ENTRY: &str = "bundle.json";
+/// Archive directory holding the pack's files, relative to the game dir.
+const FILES_ENTRY: &str = "files";
+
+/// What a bundle holds besides the files themselves.
+#[derive(Serialize, Deserialize)]
+struct Index {
+    pack: PackHeader,
+    /// Version of the pack a differential bundle updates, it holds only the
+    /// files that changed since.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    /// Records of every file of the pack, keyed by their path in the game
+    /// directory, as the state keeps them.
+    files: BTreeMap<String, Managed>,
+    /// sha256 of every override and fragment, by its name in the archive.
+    #[serde(default)]
+    pack_files: BTreeMap<String, String>,
+    /// Files and overrides the version of `from` had and this one dropped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    removed: Vec<String>,
+    #[serde(default)]
+    restricted: Vec<Restricted>,
+}
+
+/// Where the index of every exported bundle is kept, for differential
+/// bundles against it later.
+pub fn history_dir(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("bundles")
+}
+
+fn history_path(base_dir: &Path, version: &str) -> PathBuf {
+    let name: String = version
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || ".-_".contains(c) { c } else { '_' })
+        .collect();
+    history_dir(base_dir).join(format!("{}.json", name))
+}
+
+/// Reads the index at the start of a bundle.
+fn read_index(bundle: &Path) -> Result<Index> {
+    let file = File::open(bundle).with_context(|| format!("failed to open {}", bundle.display()))?;
+    let mut tar = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut entry = tar
+        .entries()?
+        .next()
+        .ok_or_else(|| anyhow!("{} is an empty archive, not a bundle", bundle.display()))??;
+    if entry.path()?.as_ref() != Path::new(INDEX_ENTRY) {
+        return Err(anyhow!("{} is not a bundle, it starts without a {}", bundle.display(), INDEX_ENTRY));
+    }
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents)?;
+    serde_json::from_str(&contents).with_context(|| format!("{} of {} is corrupt", INDEX_ENTRY, bundle.display()))
+}
+
+/// The index of the bundle `from` names: a bundle file, or the version of
+/// a bundle exported from this instance before.
+fn base_index(base_dir: &Path, from: &str) -> Result<Index> {
+    if Path::new(from).is_file() {
+        return read_index(Path::new(from));
+    }
+    let path = history_path(base_dir, from);
+    let contents = fs::read_to_string(&path).map_err(|_| {
+        anyhow!("no bundle of version {} was exported from {}, pass the bundle file instead", from, base_dir.display())
+    })?;
+    serde_json::from_str(&contents).with_context(|| format!("{} is corrupt", path.display()))
+}
+
+/// Whether `name`, a path the index of a bundle names, stays below the
+/// directory it is joined to.
+fn relative(name: &str) -> bool {
+    let path = Path::new(name);
+    path.components().next().is_some() && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Refuses an index naming any path outside the instance, it comes from the
+/// bundle and nothing in it is trusted.
+fn check_paths(index: &Index) -> Result<()> {
+    let names = index
+        .files
+        .iter()
+        .flat_map(|(key, managed)| std::iter::once(key).chain(managed.files.iter()))
+        .chain(index.pack_files.keys())
+        .chain(index.removed.iter())
+        .chain(index.restricted.iter().map(|r| &r.path));
+    for name in names {
+        if !relative(name) {
+            return Err(anyhow!("{} of the bundle names {}, which escapes the instance", INDEX_ENTRY, name));
+        }
+    }
+    Ok(())
+}
+
+/// A file of the pack left out of a bundle, to be placed by hand.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Restricted {
+    /// Where it goes, relative to the game directory.
+    pub path: String,
+    pub reason: String,
+    /// Page it can be downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+pub struct Exported {
+    pub version: String,
+    /// Files and overrides written to the bundle.
+    pub files: usize,
+    pub overrides: usize,
+    /// Files and overrides left out of a differential bundle as unchanged.
+    pub unchanged: usize,
+    /// Deletions a differential bundle carries.
+    pub removed: usize,
+    pub restricted: Vec<Restricted>,
+}
+
+pub struct Applied {
+    pub pack: PackHeader,
+    pub files: usize,
+    pub restricted: Vec<Restricted>,
+    /// Files of a differential bundle that should have been there from the
+    /// version it updates and aren't.
+    pub missing: Vec<String>,
+}
+
+fn project_url(managed: &Managed) -> Option<String> {
+    managed
+        .project_id
+        .as_ref()
+        .map(|id| format!("https://www.curseforge.com/projects/{}", id))
+}
+
+/// Writes the installed pack of an instance to one zstd compressed tarball
+/// at `out` for installing on machines without internet: the flattened
+/// modlist, lockfile, overrides and fragments, and every installed file its
+/// author allows to be passed on. Files that may not be redistributed, see
+/// [`redistribution::check`], and manually placed ones are listed for
+/// handling by hand instead. With `from`, a bundle file or the version of a
+/// bundle exported before, only what changed since is written along with
+/// what was deleted. `to` is the version the instance must have installed.
+pub fn export(
+    base_dir: &Path,
+    game_dir: &Path,
+    mods_file: &str,
+    out: &Path,
+    api_key: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Exported> {
+    let state = State::load(base_dir)?;
+    if state.files.is_empty() {
+        return Err(anyhow!("{} has nothing installed, sync it before bundling it", base_dir.display()));
+    }
+    let modlist = base_dir.join(mods_file);
+    let loaded = manifest::load(&modlist, base_dir)?;
+    // parents may be out of reach where the bundle is installed
+    let flattened = Manifest {
+        extends: None,
+        remove: Vec::new(),
+        ..extends::flatten(loaded.manifest, &modlist.to_string_lossy(), base_dir)?.manifest
+    };
+    let version = flattened.pack.version.clone();
+    if let Some(to) = to.filter(|to| *to != version) {
+        return Err(anyhow!("{} has version {} of the pack installed, sync it to {} first", base_dir.display(), version, to));
+    }
+    let base = from.map(|from| base_index(base_dir, from)).transpose()?;
+    if let Some(base) = base.as_ref().filter(|b| b.pack.version == version) {
+        return Err(anyhow!("the bundle to update has version {} as well, there is nothing to update", base.pack.version));
+    }
+
+    let mut files: Vec<(PathBuf, String)> = Vec::new();
+    let mut index = Index {
+        pack: flattened.pack.clone(),
+        from: base.as_ref().map(|b| b.pack.version.clone()),
+        files: BTreeMap::new(),
+        pack_files: BTreeMap::new(),
+        removed: Vec::new(),
+        restricted: Vec::new(),
+    };
+    let (mut unchanged, mut unchanged_files) = (0, 0);
+    for (key, managed) in state.files.iter().filter(|(_, m)| m.source != overrides::SOURCE) {
+        let path = game_dir.join(key);
+        let restricted = |reason: String| Restricted { path: key.clone(), reason, url: project_url(managed) };
+        // instances extracting them do so from the mod bundling them
+        if managed.parent.is_some() {
+            continue;
+        }
+        if managed.source == MANUAL_SOURCE {
+            index.restricted.push(restricted("it is placed by hand".to_string()));
+            continue;
+        }
+        // the base bundle had the same file, so it was allowed in already
+        if base.as_ref().and_then(|b| b.files.get(key)).is_some_and(|m| m.sha256 == managed.sha256) {
+            index.files.insert(key.clone(), managed.clone());
+            unchanged_files += 1;
+            continue;
+        }
+        // archives of unpacked entries are not kept, their contents are
+        if !managed.files.is_empty() {
+            files.extend(managed.files.iter().map(|f| (game_dir.join(f), format!("{}/{}", FILES_ENTRY, f))));
+            index.files.insert(key.clone(), managed.clone());
+            continue;
+        }
+        if !path.is_file() {
+            index.restricted.push(restricted("it is missing from the instance".to_string()));
+            continue;
+        }
+        if let Verdict::Forbidden { reason } = redistribution::check(&path, api_key)? {
+            let _ = log_to_file(&format!("[INFO] leaving {} out of the bundle, {}", key, reason));
+            index.restricted.push(restricted(reason));
+            continue;
+        }
+        files.push((path, format!("{}/{}", FILES_ENTRY, key)));
+        index.files.insert(key.clone(), managed.clone());
+    }
+
+    let mut found = Vec::new();
+    let overrides_dir = base_dir.join(OVERRIDES_DIR);
+    if overrides_dir.is_dir() {
+        overrides::files(&overrides_dir, &mut found)?;
+    }
+    let fragments_dir = base_dir.join(FRAGMENTS_DIR);
+    if fragments_dir.is_dir() {
+        overrides::files(&fragments_dir, &mut found)?;
+    }
+    let mut overrides = 0;
+    for file in found {
+        let name = overrides::key(base_dir, &file);
+        let sha256 = hash::sha256_file(&file)?;
+        if base.as_ref().and_then(|b| b.pack_files.get(&name)) == Some(&sha256) {
+            unchanged += 1;
+        } else {
+            overrides += 1;
+            files.push((file, name.clone()));
+        }
+        index.pack_files.insert(name, sha256);
+    }
+    if let Some(base) = &base {
+        let restricted = |key: &String| index.restricted.iter().any(|r| r.path == *key);
+        index.removed = base
+            .files
+            .keys()
+            .filter(|key| !index.files.contains_key(*key) && !restricted(key))
+            .chain(base.pack_files.keys().filter(|name| !index.pack_files.contains_key(*name)))
+            .cloned()
+            .collect();
+    }
+    let lockfile = lock::path(base_dir);
+    if lockfile.is_file() {
+        files.push((lockfile, LOCK_FILE.to_string()));
+    }
+
+    let mut tar = backup::archive(out, 0)?;
+    let described = serde_json::to_string_pretty(&index)?;
+    for (name, contents) in [(INDEX_ENTRY, described.clone()), (mods_file, manifest::to_string(&flattened)?)] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(chrono::Utc::now().timestamp().max(0) as u64);
+        tar.append_data(&mut header, name, contents.as_bytes())?;
+    }
+    for (path, name) in files.iter() {
+        tar.append_path_with_name(path, name)
+            .with_context(|| format!("failed to bundle {}", path.display()))?;
+    }
+    tar.into_inner()?;
+    fs::create_dir_all(history_dir(base_dir))?;
+    fs::write(history_path(base_dir, &version), described)?;
+    Ok(Exported {
+        version,
+        files: index.files.len() - unchanged_files,
+        overrides,
+        unchanged: unchanged + unchanged_files,
+        removed: index.removed.len(),
+        restricted: index.restricted,
+    })
+}
+
+/// Installs a bundle written by [`export`] into an instance without going
+/// online: the modlist, lockfile and fragments replace the instance's, the
+/// files are put in place and recorded as installed, and the overrides are
+/// applied as a sync would. Files the instance had from an earlier version
+/// of the pack are removed. A differential bundle only applies to the
+/// version it updates. Syncing later with internet finds everything up to
+/// date.
+pub fn apply(
+    bundle: &Path,
+    base_dir: &Path,
+    game_dir: &Path,
+    mods_file: &str,
+    protected: &[String],
+    unmanaged: &[String],
+) -> Result<Applied> {
+    let file = File::open(bundle).with_context(|| format!("failed to open {}", bundle.display()))?;
+    let mut tar = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut index: Option<Index> = None;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_path_buf();
+        if name.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(anyhow!("bundle entry {} escapes the instance", name.display()));
+        }
+        // links and devices could point the files installed after them
+        // anywhere
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            return Err(anyhow!("bundle entry {} is not a file or directory", name.display()));
+        }
+        // the index comes first, nothing is touched before it is read
+        if index.is_none() {
+            if name != Path::new(INDEX_ENTRY) {
+                return Err(anyhow!("{} is not a bundle, it starts without a {}", bundle.display(), INDEX_ENTRY));
+            }
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+            let read: Index = serde_json::from_str(&contents).with_context(|| format!("{} of the bundle is corrupt", INDEX_ENTRY))?;
+            check_paths(&read)?;
+            match &read.from {
+                // a differential bundle only holds what changed since its base
+                Some(from) => {
+                    let installed = manifest::load(&base_dir.join(mods_file), base_dir).ok().map(|l| l.manifest.pack.version);
+                    if installed.as_deref() != Some(from.as_str()) {
+                        return Err(anyhow!(
+                            "{} updates version {} of the pack, {} has {}, apply the full bundle instead",
+                            bundle.display(),
+                            from,
+                            base_dir.display(),
+                            installed.as_deref().unwrap_or("none")
+                        ));
+                    }
+                    for name in read.removed.iter().filter(|n| n.starts_with(OVERRIDES_DIR) || n.starts_with(FRAGMENTS_DIR)) {
+                        let path = base_dir.join(name);
+                        if links::inside(base_dir, &path) {
+                            let _ = fs::remove_file(path);
+                        }
+                    }
+                }
+                None => {
+                    for dir in [OVERRIDES_DIR, FRAGMENTS_DIR] {
+                        if base_dir.join(dir).is_dir() {
+                            fs::remove_dir_all(base_dir.join(dir))?;
+                        }
+                    }
+                }
+            }
+            index = Some(read);
+            continue;
+        }
+        let (root, dest) = match name.strip_prefix(FILES_ENTRY) {
+            std::result::Result::Ok(relative) => (game_dir, game_dir.join(relative)),
+            Err(_) if name.starts_with(OVERRIDES_DIR) || name.starts_with(FRAGMENTS_DIR) => (base_dir, base_dir.join(&name)),
+            Err(_) if name == Path::new(mods_file) || name == Path::new(LOCK_FILE) => (base_dir, base_dir.join(&name)),
+            Err(_) => return Err(anyhow!("unexpected entry {} in bundle", name.display())),
+        };
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // nor may a link already in the instance lead them out of it
+        if !links::inside(root, &dest) || links::is_link(&dest) {
+            return Err(anyhow!("bundle entry {} would be installed outside the instance", name.display()));
+        }
+        entry.unpack(&dest).with_context(|| format!("failed to install {}", dest.display()))?;
+    }
+    let index = index.ok_or_else(|| anyhow!("{} is an empty archive, not a bundle", bundle.display()))?;
+
+    let mut state = State::load(base_dir)?;
+    let stale: Vec<(String, Managed)> = state
+        .files
+        .iter()
+        .filter(|(key, m)| m.source != overrides::SOURCE && !index.files.contains_key(*key))
+        .filter(|(key, _)| !index.restricted.iter().any(|r| r.path == **key))
+        .map(|(key, m)| (key.clone(), m.clone()))
+        .collect();
+    for (key, managed) in stale {
+        let path = game_dir.join(&key);
+        if !links::inside(base_dir, &path) {
+            continue;
+        }
+        let _ = fs::remove_file(&path);
+        for extracted in managed.files.iter() {
+            let _ = fs::remove_file(game_dir.join(extracted));
+        }
+        state.forget(game_dir, &path);
+    }
+    for (key, managed) in index.files.iter() {
+        state.files.insert(key.clone(), Managed {
+            installed_at: chrono::Utc::now(),
+            pack_version: index.pack.version.clone(),
+            ..managed.clone()
+        });
+    }
+    let lock = Lockfile::load(base_dir)?;
+    let overridden = overrides::apply(base_dir, game_dir, &mut state, lock.as_ref(), protected, unmanaged, &index.pack.version);
+    state.save(base_dir)?;
+    overridden?;
+    if let Err(e) = stamp::write(game_dir, &index.pack) {
+        let _ = log_to_file(&format!("[WARN] failed to stamp the pack version into the instance: {:#}", e));
+    }
+    let missing = match index.from {
+        Some(_) => index
+            .files
+            .iter()
+            .filter(|(key, managed)| match managed.files.is_empty() {
+                true => !game_dir.join(key).is_file(),
+                false => managed.files.iter().any(|f| !game_dir.join(f).is_file()),
+            })
+            .map(|(key, _)| key.clone())
+            .collect(),
+        None => Vec::new(),
+    };
+    Ok(Applied { pack: index.pack, files: index.files.len(), restricted: index.restricted, missing })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn managed() -> Managed {
+        Managed {
+            sha256: String::new(),
+            source: "curseforge".to_string(),
+            project_id: None,
+            file_id: None,
+            installed_at: chrono::Utc::now(),
+            pack_version: "1.0.0".to_string(),
+            size: None,
+            files: Vec::new(),
+            tags: Vec::new(),
+            parent: None,
+            filename: None,
+        }
+    }
+
+    fn index(files: &[&str], removed: &[&str]) -> Index {
+        Index {
+            pack: PackHeader::default(),
+            from: None,
+            files: files.iter().map(|f| (f.to_string(), managed())).collect(),
+            pack_files: BTreeMap::new(),
+            removed: removed.iter().map(|r| r.to_string()).collect(),
+            restricted: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn relative_names_stay_inside() {
+        assert!(relative("mods/a.jar"));
+        assert!(relative("overrides/config/a.toml"));
+        assert!(!relative(""));
+        assert!(!relative("/etc/passwd"));
+        assert!(!relative("overrides/../../.ssh/authorized_keys"));
+        assert!(!relative("./mods/a.jar"));
+    }
+
+    #[test]
+    fn escaping_index_paths_are_refused() {
+        assert!(check_paths(&index(&["mods/a.jar"], &["overrides/config/old.toml"])).is_ok());
+        assert!(check_paths(&index(&["../outside.jar"], &[])).is_err());
+        assert!(check_paths(&index(&[], &["overrides/../../.ssh/authorized_keys"])).is_err());
+        assert!(check_paths(&index(&[], &["fragments/../../x"])).is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use super::hash;
+use super::log_to_file;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+const INDEX_FILE: &str = "index.json";
+/// Held by whoever updates the index, across the runs sharing the cache.
+const INDEX_LOCK: &str = "index.lock";
+/// Entries untouched for this long are deleted when the index is written.
+const EXPIRE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// zstd level, fast to write while still shrinking JSON tenfold.
+const LEVEL: i32 = 3;
+/// Downloaded files shared by every instance of the user, named by their
+/// sha256, so the same file is downloaded and kept once.
+const FILES_DIR: &str = "files";
+/// Digests of the shared files by the file of a provider they are, named by
+/// the sha256 of what identifies it.
+const SOURCES_DIR: &str = "sources";
+
+/// Tells apart the temporary files of the threads of one run.
+static TEMPORARY: AtomicUsize = AtomicUsize::new(0);
+/// Lookups of this process since `take_counts`, see [`Counts`].
+static HITS: AtomicU64 = AtomicU64::new(0);
+static REVALIDATED: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static SAVED: AtomicU64 = AtomicU64::new(0);
+/// Whether this process already deleted the shared files left unused.
+static PRUNED: AtomicBool = AtomicBool::new(false);
+
+/// How the cache did since it was last asked.
+#[derive(Clone, Copy, Default)]
+pub struct Counts {
+    /// Responses served from the cache without asking the server.
+    pub hits: u64,
+    /// Responses the server said didn't change, served from the cache.
+    pub revalidated: u64,
+    /// Responses fetched and stored.
+    pub misses: u64,
+    /// Bytes the hits and revalidations spared transferring.
+    pub saved: u64,
+}
+
+/// The lookups counted since the last call, for the usage statistics.
+pub fn take_counts() -> Counts {
+    Counts {
+        hits: HITS.swap(0, Ordering::Relaxed),
+        revalidated: REVALIDATED.swap(0, Ordering::Relaxed),
+        misses: MISSES.swap(0, Ordering::Relaxed),
+        saved: SAVED.swap(0, Ordering::Relaxed),
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Entry {
+    /// Compressed data, relative to the cache directory.
+    file: String,
+    fetched_at: DateTime<Utc>,
+    /// Size before compression.
+    size: u64,
+    #[serde(default, skip_serializing_if = "Validators::is_empty")]
+    validators: Validators,
+}
+
+/// What the server said identifies the version of a response, sent back to
+/// ask whether it changed.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Per-user directory for cached API responses and manifests.
+pub fn dir() -> Result<PathBuf> {
+    let dir = if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+        PathBuf::from(dir)
+    } else if let Some(dir) = env::var_os("LOCALAPPDATA") {
+        PathBuf::from(dir)
+    } else if let Some(home) = env::var_os("HOME") {
+        PathBuf::from(home).join(".cache")
+    } else {
+        return Err(anyhow!("could not determine the user cache directory"));
+    };
+    Ok(dir.join("modpack-sync"))
+}
+
+fn load_index() -> Result<BTreeMap<String, Entry>> {
+    let path = dir()?.join(INDEX_FILE);
+    match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).with_context(|| format!("the cache index {} is corrupt", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(anyhow!("failed to read the cache index {}: {}", path.display(), e)),
+    }
+}
+
+/// Locks `path`, created when missing, until the file returned is dropped.
+/// Other runs and threads locking it wait meanwhile.
+fn lock(path: &Path) -> Result<File> {
+    let file = File::options().create(true).truncate(false).write(true).open(path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Writes `data` to `path` through a temporary file beside it, so readers
+/// see the previous contents or all of the new ones.
+fn place(path: &Path, data: &[u8]) -> Result<()> {
+    let temporary = path.with_extension(format!("{}-{}.tmp", std::process::id(), TEMPORARY.fetch_add(1, Ordering::Relaxed)));
+    let written = fs::write(&temporary, data).and_then(|()| fs::rename(&temporary, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    Ok(written?)
+}
+
+/// Changes the index while holding its lock, dropping expired entries and
+/// their data. Being replaced at once, it is read without the lock.
+fn update_index(change: impl FnOnce(&mut BTreeMap<String, Entry>)) -> Result<()> {
+    let dir = dir()?;
+    let _lock = lock(&dir.join(INDEX_LOCK))?;
+    let mut index = match load_index() {
+        Ok(index) => index,
+        // kept aside to look into, the cache starts over rather than
+        // staying unusable
+        Err(e) if dir.join(INDEX_FILE).exists() => {
+            let aside = dir.join(format!("{}.corrupt", INDEX_FILE));
+            let _ = log_to_file(&format!("[WARN] {:#}, starting it over and keeping it as {}", e, aside.display()));
+            fs::rename(dir.join(INDEX_FILE), &aside)?;
+            BTreeMap::new()
+        }
+        Err(e) => return Err(e),
+    };
+    change(&mut index);
+    let now = Utc::now();
+    index.retain(|_, entry| {
+        let fresh = (now - entry.fetched_at).to_std().is_ok_and(|age| age < EXPIRE);
+        if !fresh {
+            let _ = fs::remove_file(dir.join(&entry.file));
+            let _ = fs::remove_file(dir.join(entry_lock(&entry.file)));
+        }
+        fresh
+    });
+    place(&dir.join(INDEX_FILE), serde_json::to_string(&index)?.as_bytes())
+}
+
+/// Lock file of the data file `file`, held while it is written.
+fn entry_lock(file: &str) -> String {
+    format!("{}.lock", file)
+}
+
+fn entry(key: &str) -> Option<Entry> {
+    match load_index() {
+        Ok(index) => index.get(key).cloned(),
+        Err(e) => {
+            let _ = log_to_file(&format!("[WARN] {:#}", e));
+            None
+        }
+    }
+}
+
+fn read(entry: &Entry) -> Option<Vec<u8>> {
+    let compressed = fs::read(dir().ok()?.join(&entry.file)).ok()?;
+    zstd::decode_all(compressed.as_slice()).ok()
+}
+
+/// The data cached under `key`, when it was stored less than `max_age` ago.
+pub fn get(key: &str, max_age: Duration) -> Option<Vec<u8>> {
+    let entry = entry(key)?;
+    if (Utc::now() - entry.fetched_at).to_std().map_or(true, |age| age >= max_age) {
+        return None;
+    }
+    let data = read(&entry)?;
+    HITS.fetch_add(1, Ordering::Relaxed);
+    SAVED.fetch_add(data.len() as u64, Ordering::Relaxed);
+    Some(data)
+}
+
+/// The data cached under `key` however old, with the validators to ask
+/// the server whether it is still current. None without validators.
+pub fn validated(key: &str) -> Option<(Vec<u8>, Validators)> {
+    let entry = entry(key).filter(|e| !e.validators.is_empty())?;
+    Some((read(&entry)?, entry.validators))
+}
+
+/// Marks the data under `key` as fetched now, after the server said it
+/// didn't change.
+pub fn touch(key: &str) {
+    let _ = update_index(|index| {
+        if let Some(entry) = index.get_mut(key) {
+            entry.fetched_at = Utc::now();
+            REVALIDATED.fetch_add(1, Ordering::Relaxed);
+            SAVED.fetch_add(entry.size, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Stores `data` under `key` compressed. Caching is best effort, failures
+/// only mean the next run fetches again.
+pub fn put(key: &str, data: &[u8]) {
+    let _ = try_put(key, data, Validators::default());
+}
+
+/// Stores `data` under `key` with the validators it was served with.
+pub fn put_validated(key: &str, data: &[u8], validators: Validators) {
+    let _ = try_put(key, data, validators);
+}
+
+fn try_put(key: &str, data: &[u8], validators: Validators) -> Result<()> {
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    let dir = dir()?;
+    fs::create_dir_all(&dir)?;
+    let file = format!("{}.zst", &hash::sha256_bytes(key.as_bytes())[..32]);
+    {
+        // runs storing the same entry take turns, the last one stays
+        let _lock = lock(&dir.join(entry_lock(&file)))?;
+        place(&dir.join(&file), &zstd::encode_all(data, LEVEL)?)?;
+    }
+    update_index(|index| {
+        index.insert(
+            key.to_string(),
+            Entry {
+                file,
+                fetched_at: Utc::now(),
+                size: data.len() as u64,
+                validators,
+            },
+        );
+    })
+}
+
+/// A downloaded file the instances share, as its provider identifies it.
+#[derive(Serialize, Deserialize)]
+pub struct SharedFile {
+    pub sha256: String,
+    /// Id of the CurseForge file it is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<u64>,
+}
+
+/// Where the shared file with `sha256` is kept.
+pub fn file_path(sha256: &str) -> Result<PathBuf> {
+    Ok(dir()?.join(FILES_DIR).join(sha256))
+}
+
+/// Copies the shared file with `sha256` to `dest`, handing back its size.
+/// None when no instance stored it, or it turned out different. Copied
+/// rather than linked, the permissions an instance gives its files stay its
+/// own.
+pub fn get_file(sha256: &str, dest: &Path) -> Option<u64> {
+    let path = file_path(sha256).ok()?;
+    if !path.is_file() {
+        return None;
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).ok()?;
+    }
+    let bytes = fs::copy(&path, dest).ok()?;
+    if !hash::sha256_file(dest).is_ok_and(|actual| actual.eq_ignore_ascii_case(sha256)) {
+        let _ = fs::remove_file(dest);
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+    // files still taken aren't pruned
+    let _ = File::options().write(true).open(&path).and_then(|f| f.set_modified(SystemTime::now()));
+    Some(bytes)
+}
+
+/// The shared file a provider's file identified by `source` was stored as.
+pub fn shared_file(source: &str) -> Option<SharedFile> {
+    let path = dir().ok()?.join(SOURCES_DIR).join(hash::sha256_bytes(source.as_bytes()));
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+/// Shares the downloaded file at `path` with the other instances, known
+/// by `source` as well when that identifies it. Best effort like the
+/// responses.
+pub fn put_file(path: &Path, file: &SharedFile, source: Option<&str>) {
+    let _ = try_put_file(path, file, source);
+}
+
+fn try_put_file(path: &Path, file: &SharedFile, source: Option<&str>) -> Result<()> {
+    let dir = dir()?;
+    let files = dir.join(FILES_DIR);
+    let sources = dir.join(SOURCES_DIR);
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&sources)?;
+    if !PRUNED.swap(true, Ordering::Relaxed) {
+        prune(&files);
+        prune(&sources);
+    }
+    let shared = files.join(&file.sha256);
+    if !shared.is_file() {
+        let temporary = shared.with_extension(format!("{}-{}.tmp", std::process::id(), TEMPORARY.fetch_add(1, Ordering::Relaxed)));
+        let copied = fs::copy(path, &temporary).and_then(|_| fs::rename(&temporary, &shared));
+        if copied.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        copied?;
+    }
+    if let Some(source) = source {
+        place(&sources.join(hash::sha256_bytes(source.as_bytes())), &serde_json::to_vec(file)?)?;
+    }
+    Ok(())
+}
+
+/// Deletes the shared files, and what identifies them, unused for as long
+/// as responses are kept.
+fn prune(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let unused = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age >= EXPIRE));
+        if unused {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
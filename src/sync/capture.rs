@@ -0,0 +1,412 @@
+use super::log_to_file;
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::{Client, Request, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{ResponseBuilderExt, StatusCode, Url};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+
+/// Headers whose values are replaced in recorded exchanges.
+const SECRET_HEADERS: [&str; 5] = ["x-api-token", "x-api-key", "authorization", "cookie", "set-cookie"];
+/// Query parameters whose values are replaced in recorded urls.
+const SECRET_PARAMS: [&str; 6] = ["key", "api_key", "apikey", "token", "access_token", "signature"];
+/// Secrets shorter than this are only redacted from their header, replacing
+/// them everywhere would garble the record.
+const MIN_SECRET_LEN: usize = 8;
+/// Text bodies up to this size are kept in the exchange, larger ones and
+/// files go next to it as `<exchange>.body`.
+const MAX_INLINE_BODY: usize = 1024 * 1024;
+/// What secrets are replaced with, also the API key of a replayed run.
+pub const REDACTED: &str = "<redacted>";
+
+/// Values of secret headers seen so far, also redacted from urls and bodies.
+#[derive(Default)]
+struct Secrets(Mutex<Vec<String>>);
+
+impl Secrets {
+    fn learn(&self, headers: &HeaderMap) {
+        let mut secrets = self.0.lock().expect("secrets lock poisoned");
+        for name in SECRET_HEADERS {
+            for value in headers.get_all(name) {
+                let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+                if value.len() >= MIN_SECRET_LEN && !secrets.contains(&value) {
+                    secrets.push(value);
+                }
+            }
+        }
+    }
+
+    fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in self.0.lock().expect("secrets lock poisoned").iter() {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+        text
+    }
+
+    fn url(&self, url: &Url) -> String {
+        let mut url = url.clone();
+        if url.query().is_some() {
+            let pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .map(|(k, v)| match SECRET_PARAMS.contains(&k.to_lowercase().as_str()) {
+                    true => (k.to_string(), REDACTED.to_string()),
+                    false => (k.to_string(), v.to_string()),
+                })
+                .collect();
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+        self.redact(url.as_str())
+    }
+
+    fn headers(&self, headers: &HeaderMap) -> Value {
+        let mut out = serde_json::Map::new();
+        for (name, value) in headers.iter() {
+            let value = match SECRET_HEADERS.contains(&name.as_str()) {
+                true => REDACTED.to_string(),
+                false => self.redact(&String::from_utf8_lossy(value.as_bytes())),
+            };
+            out.insert(name.to_string(), Value::String(value));
+        }
+        Value::Object(out)
+    }
+}
+
+/// `request` built to be sent or answered, the secrets of its headers
+/// learned by `secrets`.
+fn build(request: RequestBuilder, secrets: &Secrets) -> reqwest::Result<(Client, Request)> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    secrets.learn(request.headers());
+    Ok((client, request))
+}
+
+/// Where `--debug-http` records the exchanges of a run, one JSON file each.
+struct Recorder {
+    dir: PathBuf,
+    next: AtomicUsize,
+    /// Recorded exchanges waiting for their body, by the thread that sent
+    /// them and the url of the response, in the order they were sent.
+    /// Bodies are read on the thread the response came to.
+    pending: Mutex<HashMap<(ThreadId, String), VecDeque<PathBuf>>>,
+    secrets: Secrets,
+}
+
+static RECORDER: OnceLock<Recorder> = OnceLock::new();
+
+/// Records every request sent with [`super::http::RequestExt::send_captured`]
+/// and its response into `dir` from now on, with keys and tokens redacted,
+/// for bug reports and for [`replay_from`].
+pub fn record_to(dir: &Path) -> Result<()> {
+    let _ = RECORDER.set(Recorder::new(dir)?);
+    Ok(())
+}
+
+impl Recorder {
+    fn new(dir: &Path) -> Result<Recorder> {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(Recorder {
+            dir: dir.to_path_buf(),
+            next: AtomicUsize::new(1),
+            pending: Mutex::new(HashMap::new()),
+            secrets: Secrets::default(),
+        })
+    }
+
+    fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let (client, request) = build(request, &self.secrets)?;
+        let mut exchange = json!({
+            "request": {
+                "method": request.method().as_str(),
+                "url": self.secrets.url(request.url()),
+                "headers": self.secrets.headers(request.headers()),
+            },
+        });
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        let host = request.url().host_str().unwrap_or("local").to_string();
+        let path = self.dir.join(format!("{:04}-{}.json", id, host));
+        let url = request.url().clone();
+        let result = client.execute(request);
+        match &result {
+            Ok(response) => {
+                exchange["response"] = json!({
+                    "status": response.status().as_u16(),
+                    "url": self.secrets.url(response.url()),
+                    "headers": self.secrets.headers(response.headers()),
+                });
+                self.pending
+                    .lock()
+                    .expect("recorder lock poisoned")
+                    .entry((thread::current().id(), response.url().to_string()))
+                    .or_default()
+                    .push_back(path.clone());
+            }
+            Err(e) => {
+                let error = e.to_string().replace(url.as_str(), &self.secrets.url(&url));
+                exchange["error"] = Value::String(self.secrets.redact(&error));
+            }
+        }
+        write(&path, &exchange);
+        result
+    }
+
+    fn body(&self, url: &str, body: &[u8]) {
+        let path = {
+            let mut pending = self.pending.lock().expect("recorder lock poisoned");
+            let key = (thread::current().id(), url.to_string());
+            let path = pending.get_mut(&key).and_then(VecDeque::pop_front);
+            if pending.get(&key).is_some_and(VecDeque::is_empty) {
+                pending.remove(&key);
+            }
+            path
+        };
+        let Some(path) = path else {
+            return;
+        };
+        let Some(mut exchange) = fs::read(&path).ok().and_then(|b| serde_json::from_slice::<Value>(&b).ok()) else {
+            return;
+        };
+        let response = &mut exchange["response"];
+        // the body is recorded decoded, as it is replayed
+        if let Some(headers) = response["headers"].as_object_mut() {
+            headers.remove("content-encoding");
+            headers.remove("content-length");
+        }
+        match std::str::from_utf8(body) {
+            Ok(text) if body.len() <= MAX_INLINE_BODY => response["body"] = Value::String(self.secrets.redact(text)),
+            _ => {
+                let file = path.with_extension("body");
+                if let Err(e) = fs::write(&file, body) {
+                    let _ = log_to_file(&format!("[WARN] failed to record {}: {}", file.display(), e));
+                }
+                let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                response["body_file"] = Value::String(name);
+            }
+        }
+        write(&path, &exchange);
+    }
+}
+
+fn write(path: &Path, exchange: &Value) {
+    if let Err(e) = serde_json::to_vec_pretty(exchange).map_err(io::Error::from).and_then(|b| fs::write(path, b)) {
+        let _ = log_to_file(&format!("[WARN] failed to record {}: {}", path.display(), e));
+    }
+}
+
+struct Recorded {
+    method: String,
+    url: String,
+    exchange: Value,
+    replayed: bool,
+}
+
+/// Answers requests from a recording instead of the network for `--replay`.
+struct Replayer {
+    dir: PathBuf,
+    exchanges: Mutex<Vec<Recorded>>,
+    secrets: Secrets,
+}
+
+static REPLAYER: OnceLock<Replayer> = OnceLock::new();
+
+/// Answers every request from now on with the exchanges [`record_to`] wrote
+/// into `dir`, without touching the network. Requests the recording lacks
+/// are answered with [`NOT_RECORDED`].
+pub fn replay_from(dir: &Path) -> Result<()> {
+    let _ = REPLAYER.set(Replayer::load(dir)?);
+    Ok(())
+}
+
+/// Status requests a replayed recording lacks are answered with, along with
+/// a `x-modpack-sync-replay: not recorded` header.
+pub const NOT_RECORDED: StatusCode = StatusCode::BAD_GATEWAY;
+
+/// Whether requests are answered from a recording.
+pub fn replaying() -> bool {
+    REPLAYER.get().is_some()
+}
+
+impl Replayer {
+    fn load(dir: &Path) -> Result<Replayer> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .with_context(|| format!("failed to read the recording {}", dir.display()))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .collect();
+        files.sort();
+        let mut exchanges = Vec::new();
+        for file in files {
+            let contents = fs::read(&file).with_context(|| format!("failed to read {}", file.display()))?;
+            let exchange: Value =
+                serde_json::from_slice(&contents).with_context(|| format!("{} is not a recorded exchange", file.display()))?;
+            let request = &exchange["request"];
+            let (Some(method), Some(url)) = (request["method"].as_str(), request["url"].as_str()) else {
+                return Err(anyhow!("{} is not a recorded exchange", file.display()));
+            };
+            exchanges.push(Recorded {
+                method: method.to_string(),
+                url: url.to_string(),
+                exchange: exchange.clone(),
+                replayed: false,
+            });
+        }
+        let _ = log_to_file(&format!("[INFO] replaying {} recorded exchanges from {}", exchanges.len(), dir.display()));
+        Ok(Replayer {
+            dir: dir.to_path_buf(),
+            exchanges: Mutex::new(exchanges),
+            secrets: Secrets::default(),
+        })
+    }
+
+    fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let (_, request) = build(request, &self.secrets)?;
+        let method = request.method().as_str().to_string();
+        let url = self.secrets.url(request.url());
+        // repeated requests get the recorded answers in order, the last one
+        // once they run out
+        let exchange = {
+            let mut exchanges = self.exchanges.lock().expect("replay lock poisoned");
+            let matching = |e: &Recorded| e.method == method && e.url == url;
+            let index = exchanges
+                .iter()
+                .position(|e| !e.replayed && matching(e))
+                .or_else(|| exchanges.iter().rposition(matching));
+            index.map(|i| {
+                exchanges[i].replayed = true;
+                exchanges[i].exchange.clone()
+            })
+        };
+        let response = exchange.as_ref().map(|e| &e["response"]).filter(|r| r.is_object());
+        let Some(response) = response else {
+            let error = match &exchange {
+                Some(e) => format!("failed when recorded: {}", e["error"].as_str().unwrap_or_default()),
+                None => "not in the recording".to_string(),
+            };
+            let _ = log_to_file(&format!("[ERR!] replaying {} {}: {}", method, url, error));
+            return Ok(not_recorded(request.url(), &error));
+        };
+
+        let replayed_url = response["url"].as_str().and_then(|u| Url::parse(u).ok()).unwrap_or_else(|| request.url().clone());
+        let mut builder = http::Response::builder()
+            .status(response["status"].as_u64().unwrap_or(200) as u16)
+            .url(replayed_url);
+        if let Some(headers) = response["headers"].as_object() {
+            for (name, value) in headers {
+                if let (Ok(name), Ok(value)) = (HeaderName::from_str(name), HeaderValue::from_str(value.as_str().unwrap_or_default())) {
+                    builder = builder.header(name, value);
+                }
+            }
+        }
+        let body = match (response["body"].as_str(), response["body_file"].as_str()) {
+            (Some(body), _) => body.as_bytes().to_vec(),
+            (None, Some(file)) => fs::read(self.dir.join(file)).unwrap_or_else(|e| {
+                let _ = log_to_file(&format!("[ERR!] failed to read the recorded body {}: {}", file, e));
+                Vec::new()
+            }),
+            (None, None) => Vec::new(),
+        };
+        match builder.body(body) {
+            Ok(response) => {
+                let _ = log_to_file(&format!("[INFO]  replayed {} {}", method, url));
+                Ok(Response::from(response))
+            }
+            Err(e) => {
+                let _ = log_to_file(&format!("[ERR!] replaying {} {}: the recorded response is invalid: {}", method, url, e));
+                Ok(not_recorded(request.url(), "the recorded response is invalid"))
+            }
+        }
+    }
+}
+
+/// The answer to a request to `url` the recording can't answer, saying why.
+fn not_recorded(url: &Url, why: &str) -> Response {
+    let response = http::Response::builder()
+        .status(NOT_RECORDED)
+        .url(url.clone())
+        .header("x-modpack-sync-replay", "not recorded")
+        .body(format!("{} is not answered by the recording: {}", url, why))
+        .expect("the answer is valid");
+    Response::from(response)
+}
+
+/// Sends `request`, recording or replaying it when asked to.
+pub fn send(request: RequestBuilder) -> reqwest::Result<Response> {
+    if let Some(replayer) = REPLAYER.get() {
+        return replayer.send(request);
+    }
+    match RECORDER.get() {
+        Some(recorder) => recorder.send(request),
+        None => request.send(),
+    }
+}
+
+/// Adds the decoded body of a response to its recorded exchange.
+pub fn body(url: &str, body: &[u8]) {
+    if let Some(recorder) = RECORDER.get() {
+        recorder.body(url, body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    const BODY: &str = r#"{"data":[1,2]}"#;
+
+    /// A server answering `count` requests on a local port with [`BODY`],
+    /// handing back its url.
+    fn serve(count: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                let _ = super::super::httpd::read_head(&stream);
+                let head = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close";
+                write!(stream, "{}\r\nContent-Length: {}\r\n\r\n{}", head, BODY.len(), BODY).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn recordings_replay_without_the_network() {
+        let dir = std::env::temp_dir().join(format!("modpack-sync-capture-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let server = serve(2);
+        let client = Client::new();
+        let recorder = Recorder::new(&dir).unwrap();
+        // the same url twice, each exchange gets its own body
+        for _ in 0..2 {
+            let request = client.get(format!("{}/v1/mods?key=abcdefgh1234", server)).header("x-api-key", "key-of-the-test");
+            let response = recorder.send(request).unwrap();
+            let url = response.url().to_string();
+            recorder.body(&url, &response.bytes().unwrap());
+        }
+        for name in ["0001-127.0.0.1.json", "0002-127.0.0.1.json"] {
+            let recorded = fs::read_to_string(dir.join(name)).unwrap();
+            assert!(!recorded.contains("key-of-the-test") && !recorded.contains("abcdefgh1234"));
+            let exchange: Value = serde_json::from_str(&recorded).unwrap();
+            assert_eq!(exchange["response"]["body"], BODY);
+        }
+
+        // the server answered all it will, the answers come from the files
+        let replayer = Replayer::load(&dir).unwrap();
+        let request = client.get(format!("{}/v1/mods?key=another-key-5678", server)).header("x-api-key", "another-key");
+        let replayed = replayer.send(request).unwrap();
+        assert_eq!(replayed.status(), StatusCode::OK);
+        assert_eq!(replayed.text().unwrap(), BODY);
+        let missing = replayer.send(client.get(format!("{}/v1/files", server))).unwrap();
+        assert_eq!(missing.status(), NOT_RECORDED);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
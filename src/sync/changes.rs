@@ -0,0 +1,144 @@
+use super::manifest::Mod;
+use super::plan::{Action, Source};
+use super::{state, Run};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// What the last sync that changed mods did, overwritten by each of them,
+/// for tools telling players what changed since the last restart.
+pub const CHANGES_FILE: &str = "changes.json";
+/// Every such sync, newest first.
+pub const CHANGELOG_FILE: &str = "CHANGELOG.md";
+const CHANGELOG_TITLE: &str = "# Changelog\n\n";
+
+/// A mod a sync added, updated or removed.
+#[derive(Serialize)]
+pub struct Change {
+    pub name: String,
+    pub file: String,
+    /// File the update replaced.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    /// Page of the project, or the repository of a Maven artifact.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// Note and reason of the entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remarks: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Changes {
+    pub pack: Option<String>,
+    pub version: Option<String>,
+    pub applied_at: DateTime<Utc>,
+    pub added: Vec<Change>,
+    pub updated: Vec<Change>,
+    pub removed: Vec<Change>,
+}
+
+impl Changes {
+    /// The mods `run` installed and removed, named after their entries.
+    pub fn of(run: &Run, mods: &[Mod], game_dir: &Path, mods_dir: &Path) -> Changes {
+        let mut changes = Changes {
+            pack: run.pack.as_ref().map(|p| p.name.clone()),
+            version: run.pack.as_ref().map(|p| p.version.clone()),
+            applied_at: Utc::now(),
+            added: Vec::new(),
+            updated: Vec::new(),
+            removed: Vec::new(),
+        };
+        let sources: Vec<(&Path, &Source)> = run
+            .plan
+            .iter()
+            .flat_map(|plan| plan.actions.iter())
+            .filter_map(|action| match action {
+                Action::Install { source, dest, .. } => Some((dest.as_path(), source)),
+                _ => None,
+            })
+            .collect();
+        for installed in run.installed.iter() {
+            let path = game_dir.join(&installed.path);
+            let entry = mods.iter().find(|m| m.install_path(game_dir, mods_dir).is_ok_and(|p| p == path));
+            let name = entry.map_or_else(|| installed.filename.clone(), |m| m.name.clone());
+            let source = sources.iter().find(|(dest, _)| state::key(game_dir, dest) == installed.path);
+            let link = source.and_then(|(_, source)| match source {
+                Source::Curseforge { .. } => Some(source.url()),
+                Source::Maven(coordinate) => Some(coordinate.repo.clone()),
+                Source::Modrinth(_) => Some(source.url()),
+                Source::Local { .. } => None,
+            });
+            let change = Change {
+                name,
+                file: installed.filename.clone(),
+                previous: installed.replaced.clone(),
+                link,
+                remarks: entry.and_then(Mod::remarks),
+            };
+            match change.previous.is_some() {
+                true => changes.updated.push(change),
+                false => changes.added.push(change),
+            }
+        }
+        // files an update replaced are not gone from the pack
+        for removed in run.removed.iter().filter(|r| !changes.updated.iter().any(|u| u.previous.as_ref() == Some(*r))) {
+            changes.removed.push(Change {
+                name: removed.trim_end_matches(".jar").to_string(),
+                file: removed.clone(),
+                previous: None,
+                link: None,
+                remarks: None,
+            });
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    /// Writes `changes.json` into the game directory and adds the changes on
+    /// top of its `CHANGELOG.md`.
+    pub fn write(&self, game_dir: &Path) -> Result<()> {
+        let path = game_dir.join(CHANGES_FILE);
+        fs::write(&path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("failed to write {}", path.display()))?;
+
+        let path = game_dir.join(CHANGELOG_FILE);
+        let previous = fs::read_to_string(&path).unwrap_or_default();
+        let previous = previous.strip_prefix(CHANGELOG_TITLE).unwrap_or(&previous);
+        fs::write(&path, format!("{}{}{}", CHANGELOG_TITLE, self.markdown(), previous))
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    fn markdown(&self) -> String {
+        let mut out = String::new();
+        let title = [self.pack.as_deref(), self.version.as_deref()].into_iter().flatten().collect::<Vec<_>>().join(" ");
+        let applied_at = self.applied_at.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+        let _ = writeln!(out, "## {} ({})\n", title, applied_at);
+        let named = |c: &Change| match &c.link {
+            Some(link) => format!("[{}]({})", c.name, link),
+            None => c.name.clone(),
+        };
+        for (heading, changes) in [("Added", &self.added), ("Updated", &self.updated), ("Removed", &self.removed)] {
+            if changes.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "### {}\n", heading);
+            for c in changes.iter() {
+                let remarks = c.remarks.as_ref().map(|r| format!(" ({})", r)).unwrap_or_default();
+                let _ = match &c.previous {
+                    Some(previous) => writeln!(out, "- {}: `{}` -> `{}`{}", named(c), previous, c.file, remarks),
+                    None => writeln!(out, "- {}: `{}`{}", named(c), c.file, remarks),
+                };
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
@@ -0,0 +1,145 @@
+use super::manifest::{Mod, PackHeader};
+use super::settings::Side;
+use super::version::Requirement;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Restricts an entry to certain pack variants. Every field that is set must
+/// match; `loader`, `os` and `side` accept comma separated alternatives.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Condition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minecraft: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    /// `client` or `server`, e.g. to keep client only mods off servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side: Option<String>,
+}
+
+impl Condition {
+    /// Whether an entry with this condition belongs on `side`, regardless of
+    /// the other fields.
+    pub fn allows_side(&self, side: Side) -> bool {
+        self.side.as_deref().is_none_or(|wanted| one_of(wanted, side.name()))
+    }
+}
+
+/// The values conditions are evaluated against.
+pub struct Target {
+    pub minecraft: Option<String>,
+    pub loader: Option<String>,
+    pub os: String,
+    pub side: Side,
+}
+
+impl Target {
+    pub fn of(pack: &PackHeader, side: Side) -> Target {
+        Target {
+            minecraft: pack.minecraft.clone(),
+            loader: pack.loader.clone(),
+            os: std::env::consts::OS.to_string(),
+            side,
+        }
+    }
+}
+
+impl Condition {
+    pub fn evaluate(&self, target: &Target) -> Result<bool> {
+        if let Some(wanted) = &self.minecraft {
+            let Some(minecraft) = &target.minecraft else {
+                return Err(anyhow!("condition on minecraft {} but the pack header has no minecraft version", wanted));
+            };
+            if !Requirement::parse(wanted)?.matches(minecraft) {
+                return Ok(false);
+            }
+        }
+
+        if let Some(wanted) = &self.loader {
+            let Some(loader) = &target.loader else {
+                return Err(anyhow!("condition on loader {} but the pack header has no loader", wanted));
+            };
+            if !one_of(wanted, loader) {
+                return Ok(false);
+            }
+        }
+
+        if let Some(wanted) = &self.os {
+            if !one_of(wanted, &target.os) {
+                return Ok(false);
+            }
+        }
+
+        Ok(self.allows_side(target.side))
+    }
+}
+
+fn one_of(alternatives: &str, value: &str) -> bool {
+    alternatives
+        .split(',')
+        .map(str::trim)
+        .any(|a| a.eq_ignore_ascii_case(value))
+}
+
+/// Tags of entries turned on with `--with` and off with `--without`,
+/// remembered in the state of the instance for later syncs.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Tags {
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub with: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub without: BTreeSet<String>,
+}
+
+impl Tags {
+    pub fn is_empty(&self) -> bool {
+        self.with.is_empty() && self.without.is_empty()
+    }
+
+    /// Turns tags on and off, the latest choice for a tag replacing the one
+    /// remembered.
+    pub fn pick(&mut self, with: &[String], without: &[String]) {
+        for tag in with {
+            self.without.remove(tag);
+            self.with.insert(tag.clone());
+        }
+        for tag in without {
+            self.with.remove(tag);
+            self.without.insert(tag.clone());
+        }
+    }
+
+    /// The first of `tags` that is turned off. Tags are on unless turned off,
+    /// those in `opt_in` are off unless turned on.
+    pub fn off<'a>(&self, tags: &'a [String], opt_in: &[String]) -> Option<&'a str> {
+        tags.iter()
+            .find(|t| self.without.contains(*t) || (opt_in.contains(t) && !self.with.contains(*t)))
+            .map(String::as_str)
+    }
+}
+
+/// Splits entries into those that apply to the target and those skipped by
+/// their condition.
+pub fn partition(mods: Vec<Mod>, target: &Target) -> Result<(Vec<Mod>, Vec<Mod>)> {
+    let mut applied = Vec::new();
+    let mut skipped = Vec::new();
+
+    for m in mods {
+        let keep = match &m.when {
+            Some(condition) => condition
+                .evaluate(target)
+                .map_err(|e| anyhow!("{}: {}", m.filename, e))?,
+            None => true,
+        };
+        if keep {
+            applied.push(m);
+        } else {
+            skipped.push(m);
+        }
+    }
+
+    Ok((applied, skipped))
+}
@@ -0,0 +1,76 @@
+use super::curse_files::ModFile;
+use super::jar::{self, FabricMeta};
+use super::version::Requirement;
+use std::path::Path;
+
+/// Two mods of the pack declared incompatible by one of them.
+pub struct Conflict {
+    pub mod_name: String,
+    pub other: String,
+    pub source: &'static str,
+}
+
+/// Incompatibility relations of a CurseForge file against the projects of
+/// the pack, `pack` being (project id, name) pairs.
+pub fn from_curseforge(name: &str, file: &ModFile, pack: &[(String, String)]) -> Vec<Conflict> {
+    file.dependencies
+        .iter()
+        .filter(|d| d.relation_type == 5)
+        .filter_map(|d| pack.iter().find(|(id, _)| *id == d.mod_id.to_string()))
+        .map(|(_, other)| Conflict {
+            mod_name: name.to_string(),
+            other: other.clone(),
+            source: "curseforge",
+        })
+        .collect()
+}
+
+/// `breaks` and `conflicts` declarations between the Fabric jars at `paths`.
+/// Jars that can't be read are ignored.
+pub fn from_jars(paths: &[&Path]) -> Vec<Conflict> {
+    let metas: Vec<FabricMeta> = paths
+        .iter()
+        .filter_map(|p| jar::fabric_meta(p).ok().flatten())
+        .collect();
+
+    let mut found = Vec::new();
+    for meta in metas.iter() {
+        let declared = meta
+            .breaks
+            .iter()
+            .map(|d| (d, "fabric breaks"))
+            .chain(meta.conflicts.iter().map(|d| (d, "fabric conflicts")));
+        for ((id, range), source) in declared {
+            let Some(other) = metas.iter().find(|m| &m.id == id && m.id != meta.id) else {
+                continue;
+            };
+            if in_range(range, &other.version) {
+                found.push(Conflict {
+                    mod_name: meta.id.clone(),
+                    other: other.id.clone(),
+                    source,
+                });
+            }
+        }
+    }
+    found
+}
+
+/// Whether a Fabric version predicate, a string or a list of alternatives,
+/// covers `version`. Predicates we can't interpret are assumed to match.
+fn in_range(range: &serde_json::Value, version: &str) -> bool {
+    match range {
+        serde_json::Value::String(pred) => {
+            let pred = pred.trim();
+            if pred == "*" || pred.is_empty() {
+                return true;
+            }
+            match Requirement::parse(&pred.split_whitespace().collect::<Vec<_>>().join(",")) {
+                Ok(req) => req.matches(version),
+                Err(_) => true,
+            }
+        }
+        serde_json::Value::Array(alternatives) => alternatives.iter().any(|a| in_range(a, version)),
+        _ => true,
+    }
+}
@@ -0,0 +1,420 @@
+use super::log::STATE_DIR;
+use super::log_to_file;
+use super::settings::{self, DaemonSettings, Scope};
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+/// Socket a running daemon listens on in the state directory of every
+/// instance it watches, in a directory only its owner may enter.
+#[cfg(unix)]
+pub fn path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("control").join("daemon.sock")
+}
+
+/// Named pipe a running daemon listens on for every instance it watches,
+/// named after the instance's directory.
+#[cfg(windows)]
+pub fn path(base_dir: &Path) -> PathBuf {
+    let dir = std::fs::canonicalize(base_dir).unwrap_or_else(|_| base_dir.to_path_buf());
+    let id = super::hash::sha256_bytes(dir.to_string_lossy().to_lowercase().as_bytes());
+    PathBuf::from(format!(r"\\.\pipe\modpack-sync-{}", &id[..16]))
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    /// Check for a new pack version right away and apply it, whatever the
+    /// windows and players online.
+    TriggerSync,
+    Status,
+    /// Stop checking until resumed.
+    Pause,
+    Resume,
+    /// Read the instance settings again, e.g. after changing the windows.
+    ReloadConfig,
+    /// Restore the newest backup of the managed files and pause, so the
+    /// update isn't applied again right away.
+    Rollback,
+}
+
+impl Command {
+    pub const ALL: [Command; 6] = [
+        Command::TriggerSync,
+        Command::Status,
+        Command::Pause,
+        Command::Resume,
+        Command::ReloadConfig,
+        Command::Rollback,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::TriggerSync => "trigger-sync",
+            Command::Status => "get-status",
+            Command::Pause => "pause",
+            Command::Resume => "resume",
+            Command::ReloadConfig => "reload-config",
+            Command::Rollback => "rollback",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Command> {
+        match name {
+            // the short form scripts are likely to try first
+            "status" => Some(Command::Status),
+            name => Command::ALL.into_iter().find(|c| c.name() == name),
+        }
+    }
+}
+
+/// Which instances a request is about.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Target {
+    /// The instance of the socket the request came in on.
+    Own,
+    All,
+    /// An instance by directory or directory name.
+    Named(String),
+}
+
+/// A line a client sent: a command, optionally followed by the instance it
+/// is about or `all`.
+pub struct Request {
+    pub command: Command,
+    pub target: Target,
+}
+
+impl Request {
+    pub fn parse(line: &str) -> Result<Request> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or_else(|| anyhow!("empty request"))?;
+        let command = Command::parse(name).ok_or_else(|| {
+            let names: Vec<&str> = Command::ALL.iter().map(|c| c.name()).collect();
+            anyhow!("unknown command {}, expected one of {}", name, names.join(", "))
+        })?;
+        let target = match words.next() {
+            None => Target::Own,
+            Some("all") => Target::All,
+            Some(instance) => Target::Named(instance.to_string()),
+        };
+        if words.next().is_some() {
+            return Err(anyhow!("expected a command and at most one instance"));
+        }
+        Ok(Request { command, target })
+    }
+}
+
+/// Who a request came from.
+#[derive(Clone)]
+pub enum Caller {
+    /// The owner of the control socket, allowed everything.
+    Owner,
+    /// A client of the dashboard or of `serve`, with the token it sent.
+    Web(Option<String>),
+}
+
+/// A request as the daemon gets it, with the instance of the socket it came
+/// in on and where its answer goes.
+pub struct Incoming {
+    pub request: Request,
+    /// Position of the socket's instance among the daemon's.
+    pub instance: usize,
+    pub caller: Caller,
+    pub reply: mpsc::Sender<Value>,
+}
+
+/// What clients of the dashboard may do with an instance, from its daemon
+/// settings.
+pub struct Access {
+    /// Secrets of the tokens with their scopes.
+    tokens: Vec<(String, Scope)>,
+    /// Scope of requests without a token.
+    anonymous: Option<Scope>,
+}
+
+impl Access {
+    pub fn load(settings: &DaemonSettings) -> Result<Access> {
+        let mut tokens = Vec::new();
+        for token in settings.tokens.iter() {
+            let env: String = token
+                .name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+                .collect();
+            let env = format!("MODPACK_SYNC_TOKEN_{}", env);
+            let secret = match &token.token {
+                Some(secret) => secret.clone(),
+                None => settings::secret(&env, &format!("token:{}", token.name))
+                    .ok_or_else(|| anyhow!("no secret for the token {}, set token, {} or the keyring entry token:{}", token.name, env, token.name))?,
+            };
+            tokens.push((secret, token.scope));
+        }
+        // nobody operates an instance without a token, not even where the
+        // instance has none
+        let anonymous = settings.public_status.then_some(Scope::Read);
+        Ok(Access { tokens, anonymous })
+    }
+
+    /// The secrets of the tokens.
+    pub fn secrets(&self) -> impl Iterator<Item = &str> {
+        self.tokens.iter().map(|(secret, _)| secret.as_str())
+    }
+
+    /// Whether any token is configured, without one only a public status
+    /// can be seen.
+    pub fn has_tokens(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// What `caller` may do, nothing when its token is not one of these.
+    pub fn scope(&self, caller: &Caller) -> Option<Scope> {
+        match caller {
+            Caller::Owner => Some(Scope::Operator),
+            Caller::Web(None) => self.anonymous,
+            // a wrong token is refused even where the status is public, so
+            // mistyped ones are noticed
+            Caller::Web(Some(token)) => self.tokens.iter().find(|(secret, _)| same(secret, token)).map(|(_, scope)| *scope),
+        }
+    }
+}
+
+/// Compares secrets in a time that doesn't tell how much of them matched.
+pub fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Answers the requests a client of the control socket sends on `input`,
+/// one per line, on `out`.
+fn converse(input: impl Read, mut out: impl Write, instance: usize, requests: &mpsc::Sender<Incoming>) -> Result<()> {
+    for line in BufReader::new(input).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let answer = match Request::parse(&line) {
+            Ok(request) => {
+                let (reply, answer) = mpsc::channel();
+                requests
+                    .send(Incoming { request, instance, caller: Caller::Owner, reply })
+                    .map_err(|_| anyhow!("the daemon stopped"))?;
+                answer.recv().unwrap_or_else(|_| failure("the daemon stopped"))
+            }
+            Err(e) => failure(&format!("{:#}", e)),
+        };
+        let _ = log_to_file(&format!("[INFO] control: {} -> {}", line.trim(), answer));
+        writeln!(out, "{}", answer)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Listens on the control socket of the instance at `instance` of the
+/// daemon's, passing the requests to `requests`. Answers are one JSON line
+/// each, `ok` telling whether the request was carried out. The socket is
+/// only accessible to its owner: it is bound inside a directory only they
+/// may enter, so nobody reaches it before its own permissions are set.
+#[cfg(unix)]
+pub fn listen(base_dir: &Path, instance: usize, requests: mpsc::Sender<Incoming>) -> Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = path(base_dir);
+    let dir = path.parent().expect("the socket is in a directory");
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    // made by an older version, or with other permissions by someone else
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+        .with_context(|| format!("failed to restrict {} to its owner", dir.display()))?;
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(anyhow!("a daemon is watching {} already, its socket is {}", base_dir.display(), path.display()));
+        }
+        // left behind by a daemon that was killed
+        std::fs::remove_file(&path).with_context(|| format!("failed to remove the stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(&path).with_context(|| format!("failed to listen on {}", path.display()))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let requests = requests.clone();
+            thread::spawn(move || -> Result<()> { converse(stream.try_clone()?, stream, instance, &requests) });
+        }
+    });
+    Ok(path)
+}
+
+/// Listens on the control pipe of the instance at `instance` of the
+/// daemon's, passing the requests to `requests`. Answers are one JSON line
+/// each, `ok` telling whether the request was carried out. Clients of other
+/// machines are refused, and the pipe's default security only lets its
+/// owner, administrators and the system write to it.
+#[cfg(windows)]
+pub fn listen(base_dir: &Path, instance: usize, requests: mpsc::Sender<Incoming>) -> Result<PathBuf> {
+    let path = path(base_dir);
+    let name: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // only the first instance of a pipe may be created as such, so a
+    // second daemon of the instance fails here
+    let first = pipe::create(&name, true)
+        .map_err(|e| anyhow!("a daemon is watching {} already, or its pipe {} can't be made: {}", base_dir.display(), path.display(), e))?;
+    thread::spawn(move || {
+        let mut next = Ok(first);
+        loop {
+            let server = match next.and_then(|server| pipe::connect(&server).map(|()| server)) {
+                Ok(server) => std::fs::File::from(server),
+                Err(e) => {
+                    let _ = log_to_file(&format!("[WARN] the control pipe failed: {}", e));
+                    return;
+                }
+            };
+            let requests = requests.clone();
+            thread::spawn(move || -> Result<()> { converse(server.try_clone()?, server, instance, &requests) });
+            next = pipe::create(&name, false);
+        }
+    });
+    Ok(path)
+}
+
+#[cfg(windows)]
+use std::os::windows::ffi::OsStrExt;
+
+/// The named pipes of the control pipe.
+#[cfg(windows)]
+mod pipe {
+    use std::io;
+    use std::os::windows::io::{FromRawHandle, OwnedHandle};
+    use windows_sys::Win32::Foundation::{ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX};
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES,
+        PIPE_WAIT,
+    };
+
+    /// A new instance of the pipe `name`, a nul-terminated wide string.
+    pub fn create(name: &[u16], first: bool) -> io::Result<OwnedHandle> {
+        let open_mode = PIPE_ACCESS_DUPLEX | if first { FILE_FLAG_FIRST_PIPE_INSTANCE } else { 0 };
+        let pipe_mode = PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS;
+        // SAFETY: `name` is nul-terminated and outlives the call, no
+        // security attributes are passed
+        let handle = unsafe { CreateNamedPipeW(name.as_ptr(), open_mode, pipe_mode, PIPE_UNLIMITED_INSTANCES, 4096, 4096, 0, std::ptr::null()) };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle was just created and is owned by nothing else
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle) })
+    }
+
+    /// Waits for a client to open the pipe instance `pipe`.
+    pub fn connect(pipe: &OwnedHandle) -> io::Result<()> {
+        use std::os::windows::io::AsRawHandle;
+        // SAFETY: the handle is a pipe instance opened without overlapped I/O
+        if unsafe { ConnectNamedPipe(pipe.as_raw_handle(), std::ptr::null_mut()) } != 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        // a client that opened it between its creation and the call
+        match error.raw_os_error() == Some(ERROR_PIPE_CONNECTED as i32) {
+            true => Ok(()),
+            false => Err(error),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn listen(_base_dir: &Path, _instance: usize, _requests: mpsc::Sender<Incoming>) -> Result<PathBuf> {
+    Err(anyhow!("the control socket is only available on Unix and Windows systems"))
+}
+
+/// The answer to a request that could not be carried out.
+pub fn failure(error: &str) -> Value {
+    json!({ "ok": false, "error": error })
+}
+
+/// The answer to a request its caller may not make.
+pub fn denied() -> Value {
+    json!({ "ok": false, "denied": true, "error": "a token with the scope for this is needed" })
+}
+
+/// Sends `line` to the daemon watching the instance at `base_dir`, handing
+/// back its answer.
+#[cfg(any(unix, windows))]
+pub fn send(base_dir: &Path, line: &str) -> Result<Value> {
+    let path = path(base_dir);
+    #[cfg(unix)]
+    let stream = std::os::unix::net::UnixStream::connect(&path);
+    #[cfg(windows)]
+    let stream = std::fs::OpenOptions::new().read(true).write(true).open(&path);
+    let mut stream = stream.with_context(|| format!("no daemon is watching {}, {} can't be reached", base_dir.display(), path.display()))?;
+    writeln!(stream, "{}", line)?;
+    let mut answer = String::new();
+    BufReader::new(stream).read_line(&mut answer)?;
+    serde_json::from_str(&answer).with_context(|| format!("the daemon answered with {}", answer.trim()))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn send(_base_dir: &Path, _line: &str) -> Result<Value> {
+    Err(anyhow!("the control socket is only available on Unix and Windows systems"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::settings::TokenSettings;
+
+    fn access(tokens: &[(&str, Scope)], public_status: bool) -> Access {
+        let settings = DaemonSettings {
+            tokens: tokens
+                .iter()
+                .map(|(secret, scope)| TokenSettings { name: secret.to_string(), scope: *scope, token: Some(secret.to_string()) })
+                .collect(),
+            public_status,
+            ..DaemonSettings::default()
+        };
+        Access::load(&settings).unwrap()
+    }
+
+    fn web(token: Option<&str>) -> Caller {
+        Caller::Web(token.map(str::to_string))
+    }
+
+    #[test]
+    fn anonymous_callers_never_operate() {
+        assert_eq!(access(&[], false).scope(&web(None)), None);
+        assert_eq!(access(&[], true).scope(&web(None)), Some(Scope::Read));
+        assert_eq!(access(&[("secret", Scope::Operator)], false).scope(&web(None)), None);
+        assert_eq!(access(&[("secret", Scope::Operator)], true).scope(&web(None)), Some(Scope::Read));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_socket_is_its_owner_s() {
+        use std::os::unix::fs::PermissionsExt;
+        let base_dir = std::env::temp_dir().join(format!("modpack-sync-control-{}", std::process::id()));
+        let (requests, incoming) = mpsc::channel();
+        let socket = listen(&base_dir, 0, requests).unwrap();
+        let mode = std::fs::metadata(socket.parent().unwrap()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        thread::spawn(move || {
+            let Incoming { request, reply, .. } = incoming.recv().unwrap();
+            assert_eq!(request.command, Command::Status);
+            reply.send(json!({ "ok": true })).unwrap();
+        });
+        assert_eq!(send(&base_dir, "status").unwrap()["ok"], true);
+        assert!(listen(&base_dir, 0, mpsc::channel().0).is_err());
+        std::fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn tokens_grant_their_scope() {
+        let access = access(&[("reader", Scope::Read), ("operator", Scope::Operator)], true);
+        assert_eq!(access.scope(&web(Some("reader"))), Some(Scope::Read));
+        assert_eq!(access.scope(&web(Some("operator"))), Some(Scope::Operator));
+        assert_eq!(access.scope(&web(Some("operato"))), None);
+        assert_eq!(access.scope(&web(Some("wrong"))), None);
+        assert_eq!(access.scope(&Caller::Owner), Some(Scope::Operator));
+    }
+}
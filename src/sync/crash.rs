@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Packages of the game, the loaders and the libraries they ship. Their
+/// frames are in every trace and say nothing about which mod is to blame.
+const PLATFORM: &[&str] = &[
+    "java.", "javax.", "jdk.", "sun.", "com.sun.", "net.minecraft.", "com.mojang.", "net.minecraftforge.",
+    "net.neoforged.", "net.fabricmc.", "org.quiltmc.", "cpw.mods.", "org.spongepowered.", "org.lwjgl.",
+    "io.netty.", "com.google.", "org.apache.", "it.unimi.", "org.slf4j.", "oshi.",
+];
+
+/// What a crash report points at.
+#[derive(Default)]
+pub struct Clues {
+    /// Mod ids the report names: suspected mods, `-- MOD <id> --` sections
+    /// and mixins `from mod <id>`.
+    pub mod_ids: BTreeSet<String>,
+    /// File names of jars the report names, in stack frames or as `Mod File`.
+    pub jars: BTreeSet<String>,
+    /// Classes of the stack frames outside the game and loaders, as jar
+    /// entry names like `com/example/Mod.class`.
+    pub classes: BTreeSet<String>,
+}
+
+/// The newest crash report of a game directory, a `crash-reports/crash-*.txt`
+/// or a JVM `hs_err_pid*.log` left in the game directory.
+pub fn latest(game_dir: &Path) -> Option<PathBuf> {
+    let mut reports = Vec::new();
+    for (dir, prefix, extension) in [
+        (game_dir.join("crash-reports"), "crash-", ".txt"),
+        (game_dir.to_path_buf(), "hs_err_pid", ".log"),
+    ] {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with(prefix) && name.ends_with(extension) {
+                let modified = entry.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+                reports.push((modified, entry.path()));
+            }
+        }
+    }
+    reports.into_iter().max().map(|(_, path)| path)
+}
+
+/// Reads the clues out of a crash report of the game or a JVM `hs_err` log.
+pub fn read(path: &Path) -> Result<Clues> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(parse(&String::from_utf8_lossy(&bytes)))
+}
+
+fn parse(report: &str) -> Clues {
+    let mut clues = Clues::default();
+    let mut suspected = false;
+    for line in report.lines() {
+        let trimmed = line.trim();
+        if suspected {
+            // the list is indented under its header, issue tracker lines further
+            match trimmed.is_empty() || !line.starts_with(char::is_whitespace) {
+                true => suspected = false,
+                false => clues.mod_ids.extend(suspect(trimmed)),
+            }
+        }
+        if let Some(rest) = trimmed.strip_prefix("Suspected Mods:").or_else(|| trimmed.strip_prefix("Suspected Mod:")) {
+            suspected = true;
+            clues.mod_ids.extend(suspect(rest.trim()));
+        }
+        if let Some(id) = trimmed.strip_prefix("-- MOD ").and_then(|r| r.strip_suffix(" --")) {
+            clues.mod_ids.insert(id.trim().to_string());
+        }
+        if let Some((_, rest)) = trimmed.split_once(" from mod ") {
+            clues.mod_ids.extend(rest.split_whitespace().next().map(|id| id.trim_end_matches([',', ')']).to_string()));
+        }
+        if let Some(file) = trimmed.strip_prefix("Mod File: ") {
+            let name = file.trim().rsplit(['/', '\\']).next().unwrap_or_default();
+            if name.ends_with(".jar") {
+                clues.jars.insert(name.to_string());
+            }
+        }
+        let Some(class) = frame(trimmed) else {
+            continue;
+        };
+        clues.classes.insert(class);
+        // Forge and NeoForge name the jar of each frame, at a.B.c(B.java:1) ~[some-mod.jar%23123!/:?]
+        if let Some((_, jar)) = trimmed.starts_with("at ").then(|| trimmed.rsplit_once('[')).flatten() {
+            let name = jar.split([':', '%', '!']).next().unwrap_or_default();
+            if name.ends_with(".jar") {
+                clues.jars.insert(name.to_string());
+            }
+        }
+    }
+    clues
+}
+
+/// The mod id of a suspected mod, listed as `Name (id), Version: 1.0`.
+fn suspect(line: &str) -> Option<String> {
+    let named = line.split_once(", Version").map_or(line, |(named, _)| named);
+    let (_, id) = named.strip_suffix(')')?.rsplit_once('(')?;
+    Some(id.trim().to_string()).filter(|id| !id.is_empty())
+}
+
+/// The class of a stack frame outside the platform, from a trace's
+/// `at a.b.C.method(C.java:1)` or the `j  a.b.C.method()V+1` of an `hs_err`.
+fn frame(line: &str) -> Option<String> {
+    let symbol = match line.strip_prefix("at ") {
+        Some(rest) => rest,
+        None if line.starts_with("j ") || line.starts_with("J ") => line.split_whitespace().find(|t| t.contains('('))?,
+        None => return None,
+    };
+    let (method, _) = symbol.split_once('(')?;
+    // modules come first in newer traces, at TRANSFORMER/mod@1.0/a.b.C.m(
+    let method = method.rsplit('/').next().unwrap_or(method);
+    let (class, _) = method.rsplit_once('.')?;
+    if PLATFORM.iter().any(|p| class.starts_with(p)) {
+        return None;
+    }
+    Some(format!("{}.class", class.replace('.', "/")))
+}
@@ -1,12 +1,189 @@
-use reqwest::Result;
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT};
+use super::cache;
+use super::http::{self, RequestExt};
+use super::trace;
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Base of CurseForge's API.
+const API: &str = "https://www.curseforge.com/api/v1";
+
+/// Requests CurseForge allows, as the headers of an answer tell.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimit {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// Seconds until the allowance is renewed.
+    pub reset: Option<u64>,
+    /// Seconds to wait before asking again, when throttled.
+    pub retry_after: Option<u64>,
+}
+
+impl RateLimit {
+    /// The limits `headers` report, none when they report none.
+    fn of(headers: &HeaderMap) -> Option<RateLimit> {
+        let number = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse().ok());
+        let limit = RateLimit {
+            limit: number("x-ratelimit-limit"),
+            remaining: number("x-ratelimit-remaining"),
+            reset: number("x-ratelimit-reset"),
+            retry_after: number(RETRY_AFTER.as_str()),
+        };
+        (limit != RateLimit::default()).then_some(limit)
+    }
+}
+
+impl std::fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        match (self.remaining, self.limit) {
+            (Some(remaining), Some(limit)) => parts.push(format!("{} of {} requests left", remaining, limit)),
+            (Some(remaining), None) => parts.push(format!("{} requests left", remaining)),
+            (None, Some(limit)) => parts.push(format!("{} requests allowed", limit)),
+            (None, None) => {}
+        }
+        if let Some(reset) = self.reset {
+            parts.push(format!("renewed in {}s", reset));
+        }
+        if let Some(after) = self.retry_after {
+            parts.push(format!("retry after {}s", after));
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// Limits of the last CurseForge answer that reported any.
+static LAST_RATE_LIMIT: Mutex<Option<RateLimit>> = Mutex::new(None);
+
+/// The limits CurseForge reported last in this run, none when it never did.
+pub fn rate_limit() -> Option<RateLimit> {
+    *LAST_RATE_LIMIT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ErrorBody {
+    #[serde(default)]
+    error_code: Option<i64>,
+    #[serde(default)]
+    error_message: Option<String>,
+}
+
+/// A request CurseForge answered with an error status.
+#[derive(Debug)]
+pub struct ApiError {
+    pub url: String,
+    pub status: StatusCode,
+    /// Code and message of the error body, when it has them.
+    pub code: Option<i64>,
+    pub message: Option<String>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+impl ApiError {
+    /// The error `response` stands for, reading its body.
+    pub fn of(response: Response) -> ApiError {
+        let url = response.url().to_string();
+        let status = response.status();
+        let rate_limit = RateLimit::of(response.headers());
+        let body = http::decoded(response).ok().and_then(|b| serde_json::from_slice::<ErrorBody>(&b).ok());
+        ApiError {
+            url,
+            status,
+            code: body.as_ref().and_then(|b| b.error_code),
+            message: body.and_then(|b| b.error_message).filter(|m| !m.trim().is_empty()),
+            rate_limit,
+        }
+    }
+
+    /// Whether CurseForge turned the request down for asking too often.
+    pub fn throttled(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS
+    }
+
+    /// Whether the API key was rejected.
+    pub fn unauthorized(&self) -> bool {
+        matches!(self.status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CurseForge answered {} to {}", self.status, self.url)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        if let Some(code) = self.code {
+            write!(f, " (error {})", code)?;
+        }
+        match self.rate_limit {
+            Some(limit) if self.throttled() => write!(f, ", {}", limit),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+fn headers(api_key: &str) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+    headers.insert("X-Api-Token", HeaderValue::from_str(api_key).context("the CurseForge API key is not a valid header")?);
+    Ok(headers)
+}
+
+/// Sends a request to the API, keeping the limits it reports and turning
+/// error statuses into an [`ApiError`].
+fn send(request: RequestBuilder, api_key: &str) -> Result<Response> {
+    let response = request.headers(headers(api_key)?).send_captured()?;
+    if let Some(limit) = RateLimit::of(response.headers()) {
+        *LAST_RATE_LIMIT.lock().unwrap_or_else(|e| e.into_inner()) = Some(limit);
+    }
+    // only asked for conditionally, the caller has the cached answer
+    if !response.status().is_success() && response.status() != StatusCode::NOT_MODIFIED {
+        return Err(ApiError::of(response).into());
+    }
+    Ok(response)
+}
+
+fn get<T: DeserializeOwned>(path: &str, api_key: &str) -> Result<T> {
+    http::json(send(http::api().get(format!("{}{}", API, path)), api_key)?)
+}
+
+fn post<T: DeserializeOwned>(path: &str, body: &Value, api_key: &str) -> Result<T> {
+    http::json(send(http::api().post(format!("{}{}", API, path)).json(body), api_key)?)
+}
+
+/// Which page of a listing to ask for, the first being 0.
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    pub index: u32,
+    pub size: u32,
+}
+
+impl Page {
+    /// The first 20 results.
+    pub const FIRST: Page = Page { index: 0, size: 20 };
+}
+
+/// Size of the listing a page was taken from, as the API reports it.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Pagination {
+    pub total_count: u64,
+}
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct ApiResponse {
     data: Vec<ModFile>,
-    pagination: PaginationMeta,
+    pagination: Pagination,
 }
 
 #[derive(Deserialize, Debug)]
@@ -14,37 +191,515 @@ struct ApiResponse {
 pub struct ModFile {
     pub id: u64,
     pub file_name: String,
+    #[serde(default)]
+    pub file_date: String,
+    /// 1 release, 2 beta, 3 alpha.
+    #[serde(default)]
+    pub release_type: u8,
+    /// Game versions and loader names the file is tagged with.
+    #[serde(default)]
+    pub game_versions: Vec<String>,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    /// Size in bytes, 0 when the API leaves it out.
+    #[serde(default)]
+    pub file_length: u64,
+    #[serde(default)]
+    pub hashes: Vec<FileHash>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FileHash {
+    pub value: String,
+    /// 1 sha1, 2 md5.
+    pub algo: u8,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Dependency {
+    pub mod_id: u64,
+    pub relation_type: u8,
+}
+
+impl Dependency {
+    pub fn relation(&self) -> &'static str {
+        match self.relation_type {
+            1 => "embedded",
+            2 => "optional",
+            3 => "required",
+            4 => "tool",
+            5 => "incompatible",
+            6 => "include",
+            _ => "unknown",
+        }
+    }
+}
+
+impl ModFile {
+    /// Whether the file is tagged for the given game version and loader,
+    /// either of which may be left unconstrained.
+    pub fn supports(&self, minecraft: Option<&str>, loader: Option<&str>) -> bool {
+        let tagged = |wanted: &str| self.game_versions.iter().any(|v| v.eq_ignore_ascii_case(wanted));
+        minecraft.is_none_or(tagged) && loader.is_none_or(tagged)
+    }
+
+    /// Loaders the file is tagged for, often none for older files.
+    pub fn loaders(&self) -> Vec<&str> {
+        self.game_versions
+            .iter()
+            .map(String::as_str)
+            .filter(|v| loader_type(v).is_some())
+            .collect()
+    }
+
+    pub fn sha1(&self) -> Option<&str> {
+        self.hashes.iter().find(|h| h.algo == 1).map(|h| h.value.as_str())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ChangelogResponse {
+    data: String,
+}
+
+/// Minecraft's game id on CurseForge.
+const MINECRAFT_GAME_ID: u32 = 432;
+/// The "Mods" class of the Minecraft game.
+const MODS_CLASS_ID: u32 = 6;
+
+/// Directory, relative to the game directory with `mods` standing for the
+/// mods directory, files of a CurseForge class belong in. `None` for
+/// classes without one, such as data packs, which go into a world.
+pub fn class_dir(class_id: u32) -> Option<&'static str> {
+    match class_id {
+        MODS_CLASS_ID => Some("mods"),
+        12 => Some("resourcepacks"),
+        17 => Some("saves"),
+        6552 => Some("shaderpacks"),
+        _ => None,
+    }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct PaginationMeta {
-    total_count: u64,
+pub struct Project {
+    pub id: u64,
+    pub name: String,
+    pub slug: String,
+    #[serde(default)]
+    pub download_count: f64,
+    #[serde(default)]
+    pub latest_files_indexes: Vec<FileIndex>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileIndex {
+    pub game_version: String,
+    pub filename: String,
+    /// CurseForge's id of the loader, see [`loader_type`].
+    #[serde(default)]
+    pub mod_loader: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectDetails {
+    pub id: u64,
+    pub name: String,
+    pub slug: String,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub download_count: f64,
+    #[serde(default)]
+    pub authors: Vec<Author>,
+    #[serde(default)]
+    pub categories: Vec<Category>,
+    /// When the newest file was released, RFC 3339.
+    #[serde(default)]
+    pub date_released: String,
+    /// The newest file of every game version and loader the project has
+    /// files for.
+    #[serde(default)]
+    pub latest_files_indexes: Vec<FileIndex>,
+    /// False when the author disabled downloads through third party tools.
+    #[serde(default)]
+    pub allow_mod_distribution: Option<bool>,
+    /// Kind of content, mods, resource packs, worlds or shaders, see
+    /// [`class_dir`].
+    #[serde(default)]
+    pub class_id: Option<u32>,
+}
+
+impl ProjectDetails {
+    /// Libraries and APIs only exist to be depended upon.
+    pub fn is_library(&self) -> bool {
+        self.categories.iter().any(|c| c.name.contains("Library") || c.name.contains("API"))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Category {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Author {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProjectResponse {
+    data: ProjectDetails,
+}
+
+pub fn project(project_id: &str, api_key: &str) -> Result<ProjectDetails> {
+    Ok(get::<ProjectResponse>(&format!("/mods/{}", project_id), api_key)?.data)
 }
 
+#[derive(Deserialize, Debug)]
+struct ProjectsResponse {
+    data: Vec<ProjectDetails>,
+}
+
+/// Several projects in one request, those CurseForge doesn't know left out.
+pub fn projects(project_ids: &[u64], api_key: &str) -> Result<Vec<ProjectDetails>> {
+    Ok(post::<ProjectsResponse>("/mods", &json!({ "modIds": project_ids }), api_key)?.data)
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    data: Vec<Project>,
+    #[serde(default)]
+    pagination: Pagination,
+}
+
+/// CurseForge's fingerprint of a file: 32 bit MurmurHash2 with seed 1 over
+/// its bytes, leaving out tabs, line breaks and spaces.
+pub fn fingerprint(bytes: &[u8]) -> u32 {
+    const M: u32 = 0x5bd1_e995;
+    let data: Vec<u8> = bytes.iter().copied().filter(|b| !matches!(b, 9 | 10 | 13 | 32)).collect();
+    let mut h = 1 ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in chunks.by_ref() {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M) ^ k;
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, b) in rest.iter().enumerate() {
+            h ^= (*b as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^ (h >> 15)
+}
+
+/// A file CurseForge knows by its fingerprint.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FingerprintMatch {
+    /// The project the file was uploaded to.
+    pub id: u64,
+    pub file: Option<ModFile>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FingerprintMatches {
+    #[serde(default)]
+    exact_matches: Vec<FingerprintMatch>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FingerprintResponse {
+    data: FingerprintMatches,
+}
+
+/// The files CurseForge knows among those with the [`fingerprint`]s
+/// `fingerprints`, in one request.
+pub fn match_fingerprints(fingerprints: &[u32], api_key: &str) -> Result<Vec<FingerprintMatch>> {
+    Ok(post::<FingerprintResponse>("/fingerprints", &json!({ "fingerprints": fingerprints }), api_key)?.data.exact_matches)
+}
+
+/// The project a file with the [`fingerprint`] `fingerprint` was uploaded
+/// to, `None` when CurseForge doesn't know the file.
+pub fn project_of_fingerprint(fingerprint: u32, api_key: &str) -> Result<Option<u64>> {
+    Ok(match_fingerprints(&[fingerprint], api_key)?.first().map(|m| m.id))
+}
+
+/// CurseForge's numeric id for a mod loader name.
+pub fn loader_type(loader: &str) -> Option<u32> {
+    match loader.to_lowercase().as_str() {
+        "forge" => Some(1),
+        "liteloader" => Some(3),
+        "fabric" => Some(4),
+        "quilt" => Some(5),
+        "neoforge" => Some(6),
+        _ => None,
+    }
+}
+
+/// Searches Minecraft mods, most downloaded first, returning the `page`
+/// asked for with how many results there are in all.
+pub fn search(
+    query: &str,
+    minecraft: Option<&str>,
+    loader: Option<&str>,
+    page: Page,
+    api_key: &str,
+) -> Result<(Vec<Project>, Pagination)> {
+    let mut url = reqwest::Url::parse(&format!("{}/mods/search", API)).expect("static url is valid");
+    url.query_pairs_mut()
+        .append_pair("gameId", &MINECRAFT_GAME_ID.to_string())
+        .append_pair("classId", &MODS_CLASS_ID.to_string())
+        .append_pair("searchFilter", query)
+        .append_pair("sortField", "6")
+        .append_pair("sortOrder", "desc")
+        .append_pair("index", &(page.index * page.size).to_string())
+        .append_pair("pageSize", &page.size.to_string());
+    if let Some(minecraft) = minecraft {
+        url.query_pairs_mut().append_pair("gameVersion", minecraft);
+    }
+    if let Some(loader) = loader.and_then(loader_type) {
+        url.query_pairs_mut().append_pair("modLoaderType", &loader.to_string());
+    }
+
+    let response = http::json::<SearchResponse>(send(http::api().get(url), api_key)?)?;
+    Ok((response.data, response.pagination))
+}
+
+#[derive(Deserialize, Debug)]
+struct FileResponse {
+    data: ModFile,
+}
+
+pub fn file(project_id: &str, file_id: u64, api_key: &str) -> Result<ModFile> {
+    Ok(get::<FileResponse>(&format!("/mods/{}/files/{}", project_id, file_id), api_key)?.data)
+}
+
+#[derive(Deserialize, Debug)]
+struct FilesResponse {
+    data: Vec<ModFile>,
+}
+
+/// Several files of any projects in one request, those CurseForge doesn't
+/// know left out.
+pub fn files(file_ids: &[u64], api_key: &str) -> Result<Vec<ModFile>> {
+    Ok(post::<FilesResponse>("/mods/files", &json!({ "fileIds": file_ids }), api_key)?.data)
+}
+
+/// One page of a project's files, newest first, as CurseForge lists them
+/// right now.
+pub fn files_page(project_id: &str, page: Page, api_key: &str) -> Result<(Vec<ModFile>, Pagination)> {
+    let response: ApiResponse = http::json(send(http::api().get(files_url(project_id, page, &Filter::default())), api_key)?)?;
+    Ok((response.data, response.pagination))
+}
+
+fn files_url(project_id: &str, page: Page, filter: &Filter) -> String {
+    format!(
+        "{}/mods/{}/files?pageIndex={}&pageSize={}&sort=dateCreated&sortDescending=true&removeAlphas=false{}",
+        API,
+        project_id,
+        page.index,
+        page.size,
+        filter.query()
+    )
+}
+
+/// Where the file's bytes are served from, asking with the API key.
+pub fn download_url(project_id: &str, file_id: u64) -> String {
+    format!("{}/mods/{}/files/{}/download", API, project_id, file_id)
+}
+
+/// The API key headers a download from [`download_url`] is sent with.
+pub fn download_headers(api_key: &str) -> Result<HeaderMap> {
+    let mut headers = headers(api_key)?;
+    headers.remove(ACCEPT);
+    Ok(headers)
+}
+
+/// Files of a project looked through for one by name, newest first,
+/// before giving up on it.
+pub const SEARCH_WINDOW: usize = 500;
+
+/// The game version and loader a pack's files are looked for, either may
+/// be left open.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub minecraft: Option<String>,
+    /// Name of the loader, without its version.
+    pub loader: Option<String>,
+}
+
+impl Filter {
+    /// The filter of a pack's `minecraft` version and `loader`, which may
+    /// carry its version as in `forge-47.2.0`.
+    pub fn new(minecraft: Option<&str>, loader: Option<&str>) -> Filter {
+        Filter {
+            minecraft: minecraft.map(str::to_string),
+            loader: loader.map(|l| l.split('-').next().unwrap_or(l).to_string()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.minecraft.is_none() && self.loader.is_none()
+    }
+
+    /// Query parameters restricting a listing to the filter.
+    fn query(&self) -> String {
+        let mut query = String::new();
+        if let Some(minecraft) = &self.minecraft {
+            query.push_str(&format!("&gameVersion={}", minecraft));
+        }
+        if let Some(loader) = self.loader.as_deref().and_then(loader_type) {
+            query.push_str(&format!("&modLoaderType={}", loader));
+        }
+        query
+    }
+}
+
+enum Searched {
+    Found(ModFile),
+    /// The listing ended without it.
+    Missing,
+    /// There are more files than [`SEARCH_WINDOW`].
+    Beyond,
+}
+
+/// Pages through a project's newest files until one with the given name
+/// turns up, first among those CurseForge tags for the `filter`, then among
+/// all, asking CurseForge again when a cached listing doesn't have it.
+/// Fails when the project has more than [`SEARCH_WINDOW`] files and the
+/// newest don't have it.
+pub fn find_by_name(project_id: &str, filename: &str, filter: &Filter, api_key: &str) -> Result<Option<ModFile>> {
+    let mut filters = vec![Filter::default()];
+    if !filter.is_empty() {
+        filters.insert(0, filter.clone());
+    }
+    let mut searched = Searched::Missing;
+    for filter in filters {
+        searched = search_listing(CurseFile::of(project_id, api_key)?.filtered(&filter), filename)?;
+        if let Searched::Found(file) = searched {
+            return Ok(Some(file));
+        }
+    }
+    match searched {
+        Searched::Beyond => Err(anyhow!(
+            "{} is not among the newest {} files of project {}, check the entry's filename",
+            filename,
+            SEARCH_WINDOW,
+            project_id
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// Looks through `listing` for `filename`, again through a fresh listing
+/// when it came from the cache.
+fn search_listing(mut listing: CurseFile, filename: &str) -> Result<Searched> {
+    let searched = search_files(&mut listing, filename)?;
+    if !matches!(searched, Searched::Found(_)) && listing.cached {
+        trace!("{}: not in the cached files of project {}, asking again", filename, listing.project_id);
+        let mut fresh = CurseFile::fresh(&listing.project_id, &listing.api_key)?.filtered(&listing.filter);
+        return search_files(&mut fresh, filename);
+    }
+    Ok(searched)
+}
+
+fn search_files(listing: &mut CurseFile, filename: &str) -> Result<Searched> {
+    let mut searched = 0;
+    while let Some(file) = listing.next() {
+        let file = file?;
+        searched += 1;
+        if file.file_name == filename {
+            trace!(
+                "{}: found file {} of project {} ({} bytes) after {} files of the {} listing{}",
+                filename,
+                file.id,
+                listing.project_id,
+                file.file_length,
+                searched,
+                if listing.cached { "cached" } else { "fetched" },
+                if listing.filter.is_empty() { "" } else { " filtered" }
+            );
+            return Ok(Searched::Found(file));
+        }
+        if searched >= SEARCH_WINDOW {
+            trace!("{}: not among the newest {} of the {} files of project {}", filename, searched, listing.total, listing.project_id);
+            return Ok(match listing.total > searched as u64 {
+                true => Searched::Beyond,
+                false => Searched::Missing,
+            });
+        }
+    }
+    trace!("{}: not among the {} files of project {}, cached={}", filename, searched, listing.project_id, listing.cached);
+    Ok(Searched::Missing)
+}
+
+/// The changelog of a single file, as the HTML snippet the author wrote.
+pub fn changelog(project_id: &str, file_id: u64, api_key: &str) -> Result<String> {
+    Ok(get::<ChangelogResponse>(&format!("/mods/{}/files/{}/changelog", project_id, file_id), api_key)?.data)
+}
+
+/// How long cached file listings are used before asking again. Syncs look
+/// for files by name, falling back to a fresh listing when one is missing.
+const LISTING_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
 pub struct CurseFile {
     project_id: String,
     api_key: String,
-    client: reqwest::blocking::Client,
+    /// Age up to which cached pages are used, none to always fetch.
+    max_age: Option<Duration>,
+    /// Whether any page came from the cache.
+    cached: bool,
     page: u32,
     per_page: u32,
     files: <Vec<ModFile> as IntoIterator>::IntoIter,
     total: u64,
+    filter: Filter,
 }
 
 impl CurseFile {
+    /// The project's files, newest first, from the cache when it listed
+    /// them recently.
     pub fn of(project_id: &str, api_key: &str) -> Result<Self> {
+        Self::listing(project_id, api_key, Some(LISTING_AGE))
+    }
+
+    /// The project's files as CurseForge lists them right now.
+    pub fn fresh(project_id: &str, api_key: &str) -> Result<Self> {
+        Self::listing(project_id, api_key, None)
+    }
+
+    fn listing(project_id: &str, api_key: &str, max_age: Option<Duration>) -> Result<Self> {
         Ok(CurseFile {
             project_id: project_id.to_owned(),
             api_key: api_key.to_owned(),
-            client: reqwest::blocking::Client::new(),
+            max_age,
+            cached: false,
             files: vec![].into_iter(),
             page: 0,
             per_page: 50,
             total: 0,
+            filter: Filter::default(),
         })
     }
 
+    /// Only the files CurseForge tags for the `filter`'s game version and
+    /// loader.
+    pub fn filtered(mut self, filter: &Filter) -> Self {
+        self.filter = filter.clone();
+        self
+    }
+
     fn try_next(&mut self) -> Result<Option<ModFile>> {
         if let Some(dep) = self.files.next() {
             return Ok(Some(dep));
@@ -54,27 +709,51 @@ impl CurseFile {
             return Ok(None);
         }
 
-        let url = format!("https://www.curseforge.com/api/v1/mods/{}/files?pageIndex={}&pageSize={}&sort=dateCreated&sortDescending=true&removeAlphas=false", 
-            self.project_id, 
-            self.page, 
-            self.per_page);
+        let url = files_url(&self.project_id, Page { index: self.page, size: self.per_page }, &self.filter);
 
-        let mut headers = HeaderMap::new();
-        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
-        headers.insert("X-Api-Token", HeaderValue::from_str(&self.api_key).unwrap());
-        
-        let response = self.client
-            .get(&url)
-            .headers(headers)
-            .send()?
-            .json::<ApiResponse>()?;
+        let body = match self.max_age.and_then(|age| cache::get(&url, age)) {
+            Some(body) => {
+                self.cached = true;
+                body
+            }
+            None => self.fetch(&url)?,
+        };
+        let response: ApiResponse = serde_json::from_slice(&body).with_context(|| format!("unexpected response from {}", url))?;
         
         self.page += 1;
         self.files = response.data.into_iter();
         self.total = response.pagination.total_count;
         Ok(self.files.next())
     }
-    
+
+    /// Fetches a page, asking only whether it changed when the cache has it
+    /// with validators. Unchanged it isn't transferred again.
+    fn fetch(&self, url: &str) -> Result<Vec<u8>> {
+        let cached = cache::validated(url);
+        let mut request = http::api().get(url);
+        if let Some((_, validators)) = &cached {
+            if let Some(etag) = &validators.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(modified) = &validators.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, modified);
+            }
+        }
+        let response = send(request, &self.api_key)?;
+        if let (StatusCode::NOT_MODIFIED, Some((body, _))) = (response.status(), cached) {
+            trace!("{}: page {} of the files of project {} is unchanged", url, self.page, self.project_id);
+            cache::touch(url);
+            return Ok(body);
+        }
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Err(anyhow!("{} answered 304 to a request that wasn't conditional", url));
+        }
+        let header = |name: HeaderName| response.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let validators = cache::Validators { etag: header(ETAG), last_modified: header(LAST_MODIFIED) };
+        let body = http::decoded(response)?;
+        cache::put_validated(url, &body, validators);
+        Ok(body)
+    }
 }
 
 impl Iterator for CurseFile {
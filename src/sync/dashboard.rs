@@ -0,0 +1,354 @@
+use super::control::{Caller, Command, Incoming, Request, Target};
+use super::httpd::{self, Refused};
+use super::report::{escape, STYLE};
+use crate::i18n::t;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+
+/// Seconds between reloads of the page.
+const REFRESH_SECS: u32 = 30;
+/// Cookie the login form sets to the token entered, percent-encoded.
+const TOKEN_COOKIE: &str = "modpack_sync_token";
+/// Bytes of a posted form read, the login form sends a token and no more.
+const MAX_FORM_BYTES: u64 = 4096;
+/// Clients answered at once, the page is for a few admins.
+const MAX_CONNECTIONS: usize = 16;
+
+/// Serves a page listing the daemon's instances with buttons to sync, pause
+/// and roll them back, carried out as control requests through `requests`.
+/// `/status.json` answers as `control get-status all` does. Requests carry
+/// the token of the daemon settings as `Authorization: Bearer` or, from a
+/// browser, in the cookie `/login` sets, so it never ends up in a url. It
+/// is only answered by its address or `localhost`, and forms posted to it
+/// must come from its own page.
+pub fn serve(listener: TcpListener, requests: mpsc::Sender<Incoming>) {
+    let bound = listener.local_addr().ok();
+    thread::spawn(move || httpd::serve(listener, "dashboard", MAX_CONNECTIONS, move |stream| handle(stream, &requests, bound)));
+}
+
+/// Whether `host`, the Host header of a request, names the dashboard by
+/// an address rather than a name another site could point at it, so pages
+/// of other sites can't reach it by rebinding their name.
+fn addressed(host: &str, bound: Option<SocketAddr>) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    if name.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    match (name.parse::<IpAddr>(), bound) {
+        (Ok(ip), Some(bound)) => bound.ip().is_unspecified() || ip == bound.ip(),
+        (Ok(_), None) => true,
+        (Err(_), _) => false,
+    }
+}
+
+fn handle(stream: TcpStream, requests: &mpsc::Sender<Incoming>, bound: Option<SocketAddr>) -> Result<()> {
+    let (head, reader) = httpd::read_head(&stream)?;
+    let mut out = stream;
+    let head = match head {
+        Ok(head) => head,
+        Err(Refused::TooLarge) => return respond(&mut out, "431 Request Header Fields Too Large", "text/plain", "request too large"),
+        Err(Refused::BadRequest) => return respond(&mut out, "400 Bad Request", "text/plain", "bad request"),
+    };
+    let host = head.header("host").map(str::to_string);
+    if !host.as_deref().is_some_and(|h| addressed(h, bound)) {
+        return respond(&mut out, "421 Misdirected Request", "text/plain", "open the dashboard by its address or localhost");
+    }
+    let (origin, bearer) = (head.header("origin"), head.bearer());
+    let cookie = head.header("cookie").and_then(cookie_token);
+    let (method, path) = (head.method.as_str(), head.path.as_str());
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    let caller = Caller::Web(bearer.clone().or(cookie));
+    match (method, path) {
+        ("GET", "/") => {
+            let status = ask(requests, Command::Status, Target::All, 0, &caller)?;
+            if status["denied"] == Value::Bool(true) {
+                return denied(&mut out);
+            }
+            respond(&mut out, "200 OK", "text/html; charset=utf-8", &page(&status, None))
+        }
+        ("GET", "/login") => respond(&mut out, "200 OK", "text/html; charset=utf-8", &login(None)),
+        ("GET", "/status.json") => {
+            let status = ask(requests, Command::Status, Target::All, 0, &caller)?;
+            if status["denied"] == Value::Bool(true) {
+                return denied(&mut out);
+            }
+            respond(&mut out, "200 OK", "application/json", &status.to_string())
+        }
+        ("POST", path) => {
+            // forms of other sites must not drive the daemon, browsers send
+            // where a form came from, other clients their token
+            let foreign = match (origin, &bearer) {
+                (Some(o), _) => Some(o.split("://").nth(1).unwrap_or(o).to_string()) != host,
+                (None, bearer) => bearer.is_none(),
+            };
+            if foreign {
+                return respond(&mut out, "403 Forbidden", "text/plain", "requests from other sites are refused");
+            }
+            if path == "/login" {
+                let length = head.content_length();
+                if length > MAX_FORM_BYTES {
+                    return respond(&mut out, "413 Payload Too Large", "text/plain", "the form is too large");
+                }
+                let body = String::from_utf8_lossy(&httpd::body(reader, length)?).to_string();
+                let Some(token) = form_value(&body, "token").filter(|t| !t.is_empty()) else {
+                    return respond(&mut out, "400 Bad Request", "text/html; charset=utf-8", &login(None));
+                };
+                let status = ask(requests, Command::Status, Target::All, 0, &Caller::Web(Some(token.clone())))?;
+                if status["denied"] == Value::Bool(true) {
+                    let body = login(Some(&t!("dashboard-login-wrong")));
+                    return respond(&mut out, "401 Unauthorized", "text/html; charset=utf-8", &body);
+                }
+                let cookie = format!(
+                    "Set-Cookie: {}={}; Path=/; HttpOnly; SameSite=Strict\r\nLocation: /\r\n",
+                    TOKEN_COOKIE,
+                    encode(&token)
+                );
+                return respond_with(&mut out, "303 See Other", &cookie, "text/plain", "");
+            }
+            let Some((index, command)) = action(path) else {
+                return respond(&mut out, "404 Not Found", "text/plain", "not found");
+            };
+            let answer = ask(requests, command, Target::Own, index, &caller)?;
+            if answer["denied"] == Value::Bool(true) {
+                return denied(&mut out);
+            }
+            let notice = match answer["ok"].as_bool() {
+                Some(true) => t!("dashboard-done", action = command.name()),
+                _ => answer["error"].as_str().unwrap_or_default().to_string(),
+            };
+            let status = ask(requests, Command::Status, Target::All, 0, &caller)?;
+            respond(&mut out, "200 OK", "text/html; charset=utf-8", &page(&status, Some(&notice)))
+        }
+        _ => respond(&mut out, "404 Not Found", "text/plain", "not found"),
+    }
+}
+
+/// The instance and command of `/instances/<index>/<command>`.
+fn action(path: &str) -> Option<(usize, Command)> {
+    let rest = path.strip_prefix("/instances/")?;
+    let (index, command) = rest.split_once('/')?;
+    let command = Command::parse(command).filter(|c| *c != Command::Status && *c != Command::ReloadConfig)?;
+    Some((index.parse().ok()?, command))
+}
+
+fn ask(requests: &mpsc::Sender<Incoming>, command: Command, target: Target, instance: usize, caller: &Caller) -> Result<Value> {
+    let (reply, answer) = mpsc::channel();
+    let request = Request { command, target };
+    requests
+        .send(Incoming { request, instance, caller: caller.clone(), reply })
+        .map_err(|_| anyhow!("the daemon stopped"))?;
+    answer.recv().map_err(|_| anyhow!("the daemon stopped"))
+}
+
+fn respond(out: &mut TcpStream, status: &str, content_type: &str, body: &str) -> Result<()> {
+    respond_with(out, status, "", content_type, body)
+}
+
+/// Refuses a request without the scope it needs, offering browsers the
+/// login form.
+fn denied(out: &mut TcpStream) -> Result<()> {
+    let body = login(Some(&t!("dashboard-login-needed")));
+    respond_with(out, "401 Unauthorized", "WWW-Authenticate: Bearer\r\n", "text/html; charset=utf-8", &body)
+}
+
+/// The token of the dashboard's cookie in the Cookie header `value`.
+fn cookie_token(value: &str) -> Option<String> {
+    let prefix = format!("{}=", TOKEN_COOKIE);
+    value.split(';').find_map(|c| c.trim().strip_prefix(&prefix)).and_then(decode)
+}
+
+/// The value of the field `name` of a form posted urlencoded.
+fn form_value(body: &str, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    body.trim().split('&').find_map(|f| f.strip_prefix(&prefix)).and_then(|v| decode(&v.replace('+', " ")))
+}
+
+/// Percent-decodes `value`, nothing when it is not valid UTF-8 once
+/// decoded or has a broken escape.
+fn decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Percent-encodes everything in `value` but letters, digits and `-._~`,
+/// for a cookie value.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b if b.is_ascii_alphanumeric() || b"-._~".contains(&b) => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The form a browser enters its token into, with why it is asked for.
+fn login(notice: Option<&str>) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{t}</title><style>{s}.notice{{background:#eef6ee;padding:.5rem}}</style></head><body><h1>{t}</h1>",
+        t = escape(&t!("dashboard-title")),
+        s = STYLE
+    );
+    if let Some(notice) = notice {
+        let _ = write!(out, "<p class=\"notice\">{}</p>", escape(notice));
+    }
+    let _ = writeln!(
+        out,
+        "<form method=\"post\" action=\"/login\"><input type=\"password\" name=\"token\" autocomplete=\"current-password\"> <button>{}</button></form></body></html>",
+        escape(&t!("dashboard-login"))
+    );
+    out
+}
+
+fn respond_with(out: &mut TcpStream, status: &str, headers: &str, content_type: &str, body: &str) -> Result<()> {
+    write!(
+        out,
+        "HTTP/1.1 {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        headers,
+        content_type,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+fn page(status: &Value, notice: Option<&str>) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"{r}\">\
+         <title>{t}</title><style>{s}form{{display:inline}}.notice{{background:#eef6ee;padding:.5rem}}</style></head><body><h1>{t}</h1>",
+        r = REFRESH_SECS,
+        t = escape(&t!("dashboard-title")),
+        s = STYLE
+    );
+    if let Some(notice) = notice {
+        let _ = write!(out, "<p class=\"notice\">{}</p>", escape(notice));
+    }
+    let _ = write!(
+        out,
+        "<table><tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr>",
+        escape(&t!("dashboard-instance")),
+        escape(&t!("dashboard-pack")),
+        escape(&t!("dashboard-pending")),
+        escape(&t!("dashboard-state")),
+        escape(&t!("dashboard-last-check")),
+        escape(&t!("dashboard-actions"))
+    );
+    for instance in status["instances"].as_array().into_iter().flatten() {
+        let index = instance["index"].as_u64().unwrap_or_default();
+        let dir = instance["dir"].as_str().unwrap_or_default();
+        let state = match (instance["queued"].as_str(), instance["running"].as_bool(), instance["paused"].as_bool()) {
+            (Some(queued), _, _) => t!("dashboard-queued", action = queued),
+            (_, Some(true), _) => t!("dashboard-checking"),
+            (_, _, Some(true)) => t!("dashboard-paused"),
+            _ => t!("dashboard-next-check", minutes = instance["next_check_seconds"].as_u64().unwrap_or(0).div_ceil(60)),
+        };
+        let last = match (instance["last_check"].as_str(), instance["last_error"].as_str()) {
+            (Some(at), Some(error)) => format!(
+                "<span class=\"failed\">{}</span>",
+                escape(&t!("dashboard-failed", at = at.get(..16).unwrap_or(at).replace('T', " "), error = error))
+            ),
+            (Some(at), None) => escape(&t!("dashboard-fine", at = at.get(..16).unwrap_or(at).replace('T', " "))),
+            (None, _) => escape(&t!("dashboard-never")),
+        };
+        let _ = write!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>",
+            escape(dir),
+            escape(instance["pack_version"].as_str().unwrap_or("-")),
+            escape(instance["pending_version"].as_str().unwrap_or("-")),
+            escape(&state),
+            last
+        );
+        if instance["scope"] == "operator" {
+            let _ = write!(out, "{}", button(index, Command::TriggerSync, &t!("dashboard-sync"), None));
+            let _ = match instance["paused"].as_bool() {
+                Some(true) => write!(out, "{}", button(index, Command::Resume, &t!("dashboard-resume"), None)),
+                _ => write!(out, "{}", button(index, Command::Pause, &t!("dashboard-pause"), None)),
+            };
+            if instance["backups"].as_u64().unwrap_or(0) > 0 {
+                let confirm = t!("dashboard-rollback-confirm", instance = dir);
+                let _ = write!(out, "{}", button(index, Command::Rollback, &t!("dashboard-rollback"), Some(&confirm)));
+            }
+        }
+        out.push_str("</td></tr>");
+    }
+    out.push_str("</table></body></html>\n");
+    out
+}
+
+fn button(index: u64, command: Command, label: &str, confirm: Option<&str>) -> String {
+    let confirm = confirm
+        .map(|c| format!(" onsubmit=\"return confirm(&quot;{}&quot;)\"", escape(&c.replace('\\', "\\\\").replace('"', "\\\""))))
+        .unwrap_or_default();
+    format!(
+        "<form method=\"post\" action=\"/instances/{}/{}\"{}><button>{}</button></form> ",
+        index,
+        command.name(),
+        confirm,
+        escape(label)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_come_from_the_cookie_decoded() {
+        assert_eq!(cookie_token("theme=dark; modpack_sync_token=a%2Bb%3D"), Some("a+b=".to_string()));
+        assert_eq!(cookie_token("modpack_sync_token=plain"), Some("plain".to_string()));
+        assert_eq!(cookie_token("other=x"), None);
+        assert_eq!(cookie_token("modpack_sync_token=%zz"), None);
+    }
+
+    #[test]
+    fn cookie_values_round_trip() {
+        let token = "s3cr3t/+=; \"x\"";
+        assert_eq!(cookie_token(&format!("{}={}", TOKEN_COOKIE, encode(token))), Some(token.to_string()));
+        assert!(!encode(token).contains([';', ' ', '"']));
+    }
+
+    #[test]
+    fn form_fields_are_decoded() {
+        assert_eq!(form_value("token=a+b%26c", "token"), Some("a b&c".to_string()));
+        assert_eq!(form_value("other=1&token=x", "token"), Some("x".to_string()));
+        assert_eq!(form_value("other=1", "token"), None);
+    }
+
+    #[test]
+    fn only_addresses_and_localhost_are_answered() {
+        let bound: SocketAddr = "127.0.0.1:8991".parse().unwrap();
+        assert!(addressed("127.0.0.1:8991", Some(bound)));
+        assert!(addressed("localhost:8991", Some(bound)));
+        assert!(!addressed("evil.example:8991", Some(bound)));
+        assert!(!addressed("10.0.0.1:8991", Some(bound)));
+    }
+}
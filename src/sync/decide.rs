@@ -0,0 +1,150 @@
+use super::log::log_to_file;
+use super::settings::DecisionSettings;
+use crate::i18n::t;
+use crate::prompt;
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal};
+use std::sync::Mutex;
+
+/// A situation a sync can't settle on its own.
+#[derive(Clone, Copy)]
+pub enum Kind {
+    /// A managed file was changed locally and the pack replaces or deletes it.
+    Modified,
+    /// A file no entry references is another copy of a mod an entry installs.
+    Duplicate,
+    /// The file an entry resolved to is built for another loader than the
+    /// pack's.
+    Loader,
+    /// The slug or authors of a CurseForge project changed since a file was
+    /// last installed from it. Never goes ahead without being told to.
+    Owner,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Modified => "modified",
+            Kind::Duplicate => "duplicate",
+            Kind::Loader => "loader",
+            Kind::Owner => "owner",
+        }
+    }
+
+    /// The answer going ahead with the change, then the one leaving things
+    /// as they are.
+    fn answers(self) -> [&'static str; 2] {
+        match self {
+            Kind::Modified => ["replace", "keep"],
+            Kind::Duplicate => ["remove", "keep"],
+            Kind::Loader => ["install", "skip"],
+            Kind::Owner => ["trust", "refuse"],
+        }
+    }
+
+    fn default(self, settings: &DecisionSettings) -> &str {
+        match self {
+            Kind::Modified => &settings.modified,
+            Kind::Duplicate => &settings.duplicate,
+            Kind::Loader => &settings.loader,
+            Kind::Owner => "refuse",
+        }
+    }
+
+    /// Whether `answer` goes ahead, `None` when it isn't one of the kind's.
+    fn parse(self, answer: &str) -> Option<bool> {
+        let [yes, no] = self.answers();
+        match answer.to_lowercase().as_str() {
+            a if a == yes || a == &yes[..1] => Some(true),
+            a if a == no || a == &no[..1] => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Checks the configured defaults are answers of their situation.
+pub fn check(settings: &DecisionSettings) -> Result<()> {
+    for kind in [Kind::Modified, Kind::Duplicate, Kind::Loader] {
+        let [yes, no] = kind.answers();
+        if kind.parse(kind.default(settings)).is_none() {
+            return Err(anyhow!(
+                "decisions.{} must be {} or {}, not {}",
+                kind.name(),
+                yes,
+                no,
+                kind.default(settings)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Settles situations by the answer remembered for the file, by asking when
+/// there is a terminal to ask on, or else by the configured default.
+/// Answers given with `always` are remembered in the instance state.
+pub struct Decider<'a> {
+    settings: &'a DecisionSettings,
+    interactive: bool,
+    remembered: Mutex<BTreeMap<String, String>>,
+    /// Keeps the questions of several download threads apart.
+    asking: Mutex<()>,
+}
+
+impl Decider<'_> {
+    pub fn new(settings: &DecisionSettings, remembered: BTreeMap<String, String>) -> Decider<'_> {
+        Decider {
+            settings,
+            interactive: settings.interactive && io::stdin().is_terminal(),
+            remembered: Mutex::new(remembered),
+            asking: Mutex::new(()),
+        }
+    }
+
+    /// Whether to go ahead with the change `question` asks about, for the
+    /// file `subject`.
+    pub fn decide(&self, kind: Kind, subject: &str, question: &str) -> bool {
+        let key = format!("{}:{}", kind.name(), subject);
+        let remembered = self.remembered.lock().expect("decisions lock poisoned").get(&key).cloned();
+        if let Some(answer) = remembered.and_then(|a| kind.parse(&a)) {
+            let _ = log_to_file(&format!("[INFO]  {} {}", question, kind.answers()[!answer as usize]));
+            return answer;
+        }
+        let default = kind.default(self.settings);
+        if self.interactive {
+            let _asking = self.asking.lock().expect("prompt lock poisoned");
+            if let Some(answer) = self.ask(kind, &key, question, default) {
+                return answer;
+            }
+        }
+        let _ = log_to_file(&format!("[INFO]  {} {} by default", question, default));
+        kind.parse(default).unwrap_or(false)
+    }
+
+    /// Asks until the answer is one of the kind's, `None` without input.
+    fn ask(&self, kind: Kind, key: &str, question: &str, default: &str) -> Option<bool> {
+        let [yes, no] = kind.answers();
+        loop {
+            let answer = prompt::ask(&t!("prompt-remember", question = question, yes = yes, no = no), Some(default)).ok()?;
+            let (always, answer) = match answer.strip_prefix("always ") {
+                Some(answer) => (true, answer.trim()),
+                None => (false, answer.as_str()),
+            };
+            match kind.parse(answer) {
+                Some(decided) => {
+                    if always {
+                        let word = kind.answers()[!decided as usize].to_string();
+                        self.remembered.lock().expect("decisions lock poisoned").insert(key.to_string(), word);
+                    }
+                    return Some(decided);
+                }
+                None => println!("{}", t!("prompt-answer-either", yes = yes, no = no)),
+            }
+        }
+    }
+
+    /// The remembered answers, including the ones given this run.
+    pub fn remembered(self) -> BTreeMap<String, String> {
+        self.remembered.into_inner().expect("decisions lock poisoned")
+    }
+}
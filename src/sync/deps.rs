@@ -0,0 +1,205 @@
+use super::curse_files::{self, ModFile};
+use super::index::ModMeta;
+use super::jar;
+use super::manifest::Mod;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+#[derive(Serialize)]
+pub struct Node {
+    pub project_id: String,
+    pub name: String,
+    pub filename: String,
+    pub library: bool,
+    /// Download size of the pinned file, 0 when CurseForge leaves it out.
+    pub size: u64,
+}
+
+#[derive(Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub relation: &'static str,
+}
+
+/// Dependency relations between the entries of a pack as declared by the
+/// provider for the exact files the modlist pins.
+#[derive(Serialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    /// Entries whose file could not be looked up.
+    pub unresolved: Vec<String>,
+    /// Required projects no entry provides that a jar of the pack bundles.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bundled: Vec<Bundled>,
+}
+
+#[derive(Serialize)]
+pub struct Bundled {
+    pub project_id: String,
+    /// File name of the entry whose jar carries it.
+    pub bundled_in: String,
+}
+
+impl Graph {
+    fn has(&self, project_id: &str) -> bool {
+        self.nodes.iter().any(|n| n.project_id == project_id)
+    }
+
+    /// Required dependencies that no entry provides or bundles, as
+    /// (dependent, missing).
+    pub fn missing(&self) -> Vec<(&str, &str)> {
+        self.edges
+            .iter()
+            .filter(|e| e.relation == "required" && !self.has(&e.to) && !self.bundled.iter().any(|b| b.project_id == e.to))
+            .map(|e| (e.from.as_str(), e.to.as_str()))
+            .collect()
+    }
+
+    /// Pairs of entries where the first declares the second incompatible.
+    pub fn incompatible(&self) -> Vec<(&str, &str)> {
+        self.edges
+            .iter()
+            .filter(|e| e.relation == "incompatible" && self.has(&e.to))
+            .map(|e| (e.from.as_str(), e.to.as_str()))
+            .collect()
+    }
+
+    /// Entries requiring `project_id`.
+    pub fn dependents(&self, project_id: &str) -> Vec<&Node> {
+        self.nodes
+            .iter()
+            .filter(|n| {
+                self.edges
+                    .iter()
+                    .any(|e| e.relation == "required" && e.from == n.project_id && e.to == project_id)
+            })
+            .collect()
+    }
+
+    /// Libraries that are only in the pack for the entries in `removed`,
+    /// directly or through other such libraries.
+    pub fn only_needed_by(&self, removed: &[&str]) -> Vec<&Node> {
+        let mut gone: HashSet<&str> = removed.iter().copied().collect();
+        let mut found = Vec::new();
+        loop {
+            let next = self.nodes.iter().find(|n| {
+                let mut users = self
+                    .edges
+                    .iter()
+                    .filter(|e| (e.relation == "required" || e.relation == "optional") && e.to == n.project_id)
+                    .map(|e| e.from.as_str())
+                    .peekable();
+                n.library && !gone.contains(n.project_id.as_str()) && users.peek().is_some() && users.all(|u| gone.contains(u))
+            });
+            match next {
+                Some(n) => {
+                    gone.insert(&n.project_id);
+                    found.push(n);
+                }
+                None => return found,
+            }
+        }
+    }
+
+    /// Finds the missing required projects among the mods bundled in the
+    /// `jars` of the entries, by file name, matching the project's slug
+    /// against their mod ids. Projects that can't be looked up stay missing.
+    pub fn find_bundled(&mut self, jars: &[(String, PathBuf)], api_key: &str) {
+        let normalize = |id: &str| -> String { id.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase() };
+        let carried: Vec<(String, &str)> = jars
+            .iter()
+            .flat_map(|(filename, path)| {
+                let ids = jar::bundled_ids(path).unwrap_or_default();
+                ids.into_iter().map(move |id| (normalize(&id), filename.as_str()))
+            })
+            .collect();
+        if carried.is_empty() {
+            return;
+        }
+        let mut missing: Vec<String> = self.missing().into_iter().map(|(_, to)| to.to_string()).collect();
+        missing.sort();
+        missing.dedup();
+        for project_id in missing {
+            let Ok(project) = curse_files::project(&project_id, api_key) else {
+                continue;
+            };
+            if let Some((_, filename)) = carried.iter().find(|(id, _)| *id == normalize(&project.slug)) {
+                self.bundled.push(Bundled { project_id, bundled_in: filename.to_string() });
+            }
+        }
+    }
+
+    /// Library entries nothing in the pack depends on.
+    pub fn orphans(&self) -> Vec<&Node> {
+        let used: HashSet<&str> = self
+            .edges
+            .iter()
+            .filter(|e| e.relation == "required" || e.relation == "optional")
+            .map(|e| e.to.as_str())
+            .collect();
+        self.nodes
+            .iter()
+            .filter(|n| n.library && !used.contains(n.project_id.as_str()))
+            .collect()
+    }
+}
+
+/// Looks up the pinned file of every CurseForge entry, using the file id from
+/// the launcher's metadata when known to avoid paging through the history.
+pub fn build(mods: &[Mod], metadata: &HashMap<String, ModMeta>, api_key: &str) -> Result<Graph> {
+    let mut graph = Graph {
+        nodes: Vec::new(),
+        edges: Vec::new(),
+        unresolved: Vec::new(),
+        bundled: Vec::new(),
+    };
+
+    for m in mods.iter() {
+        let Some(project_id) = m.project_id() else {
+            continue;
+        };
+
+        let file = match lookup(project_id, m, metadata, api_key) {
+            Ok(Some(file)) => file,
+            _ => {
+                graph.unresolved.push(m.filename.clone());
+                continue;
+            }
+        };
+        let library = curse_files::project(project_id, api_key)
+            .map(|p| p.is_library())
+            .unwrap_or(false);
+
+        graph.nodes.push(Node {
+            project_id: project_id.to_string(),
+            name: m.name.clone(),
+            filename: m.filename.clone(),
+            library,
+            size: file.file_length,
+        });
+        for d in file.dependencies.iter() {
+            graph.edges.push(Edge {
+                from: project_id.to_string(),
+                to: d.mod_id.to_string(),
+                relation: d.relation(),
+            });
+        }
+    }
+
+    Ok(graph)
+}
+
+fn lookup(project_id: &str, m: &Mod, metadata: &HashMap<String, ModMeta>, api_key: &str) -> Result<Option<ModFile>> {
+    let file_id = metadata
+        .get(project_id)
+        .filter(|meta| meta.filename == m.filename)
+        .and_then(|meta| meta.file_id());
+    match file_id {
+        Some(id) => Ok(Some(curse_files::file(project_id, id, api_key)?)),
+        None => Ok(curse_files::find_by_name(project_id, &m.filename, &curse_files::Filter::default(), api_key)?),
+    }
+}
@@ -0,0 +1,567 @@
+use super::auth;
+use super::hash;
+use super::http::{self, RequestExt};
+use super::lock::{Lockfile, LOCK_FILE};
+use super::log::STATE_DIR;
+use super::log_to_file;
+use super::manifest;
+use super::overrides::OVERRIDES_DIR;
+use super::s3;
+use super::version;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Index of the published pack.
+pub const INDEX_FILE: &str = "pack.json";
+/// Directory of the published pack holding jars of local entries.
+const JARS_DIR: &str = "mods";
+
+/// Where a pack is published to and synced from.
+pub enum Backend {
+    S3(s3::Location),
+    /// An `rsync` destination such as `user@host:/srv/www/pack/`, usually a
+    /// directory served over HTTP.
+    Rsync(String),
+    /// `sftp://user@host/path`, for hosts that only offer sftp.
+    Sftp { host: String, path: String },
+    /// `git+<remote>` or `git+<remote>#<branch>`, the pack is committed and
+    /// pushed to the remote.
+    Git { remote: String, branch: Option<String> },
+    /// An `http(s)://` directory the pack was uploaded to, read only.
+    Http(String),
+}
+
+impl Backend {
+    pub fn parse(url: &str) -> Result<Backend> {
+        if url.starts_with("s3://") {
+            return Ok(Backend::S3(s3::Location::parse(url)?));
+        }
+        if let Some(target) = url.strip_prefix("rsync:") {
+            return Ok(Backend::Rsync(target.to_string()));
+        }
+        if let Some(rest) = url.strip_prefix("sftp://") {
+            let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+            return Ok(Backend::Sftp {
+                host: host.to_string(),
+                path: format!("/{}", path),
+            });
+        }
+        if let Some(remote) = url.strip_prefix("git+") {
+            let (remote, branch) = match remote.rsplit_once('#') {
+                Some((remote, branch)) => (remote, Some(branch.to_string())),
+                None => (remote, None),
+            };
+            return Ok(Backend::Git {
+                remote: remote.to_string(),
+                branch,
+            });
+        }
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return Ok(Backend::Http(url.trim_end_matches('/').to_string()));
+        }
+        Err(anyhow!(
+            "unsupported distribution backend {}, expected s3://, rsync:, sftp://, git+ or http(s):// url",
+            url
+        ))
+    }
+
+    /// The same backend narrowed to a release channel: a subdirectory named
+    /// after the channel, or a branch for git.
+    pub fn channel(self, channel: &str) -> Backend {
+        match self {
+            Backend::S3(location) => Backend::S3(s3::Location {
+                prefix: format!("{}{}/", location.prefix, channel),
+                ..location
+            }),
+            Backend::Rsync(target) => Backend::Rsync(format!("{}/{}/", target.trim_end_matches('/'), channel)),
+            Backend::Sftp { host, path } => Backend::Sftp {
+                host,
+                path: format!("{}/{}", path.trim_end_matches('/'), channel),
+            },
+            Backend::Git { remote, .. } => Backend::Git {
+                remote,
+                branch: Some(channel.to_string()),
+            },
+            Backend::Http(url) => Backend::Http(format!("{}/{}", url, channel)),
+        }
+    }
+}
+
+/// Table of contents of a published pack, read by clients to tell whether
+/// there is anything new before downloading the rest.
+#[derive(Serialize, Deserialize)]
+pub struct Index {
+    pub name: String,
+    pub version: String,
+    pub files: Vec<String>,
+    /// Percentage of clients the version is rolled out to, the rest stays on
+    /// what it pulled before until it is raised. All of them when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<u8>,
+}
+
+/// What an instance last pulled from its source.
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Pulled {
+    channel: Option<String>,
+    version: String,
+}
+
+pub enum Outcome {
+    UpToDate(String),
+    Updated { from: Option<String>, to: String },
+}
+
+fn run_tool(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let status = command
+        .status()
+        .with_context(|| format!("failed to run {}, is it installed?", program))?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
+/// Copies the pack into a fresh directory so external tools can mirror it.
+fn stage(dir: &Path, pack: &[(PathBuf, String)]) -> Result<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    for (path, name) in pack {
+        let dest = dir.join(name);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(path, &dest).with_context(|| format!("failed to stage {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn files(dir: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files(&path, found)?;
+        } else {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn relative(root: &Path, file: &Path) -> String {
+    file.strip_prefix(root)
+        .unwrap_or(file)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Everything that makes up the published pack as (file on disk, name in
+/// the pack): the manifest, lockfile, overrides, and the jars of `file://`
+/// entries which are the maintainer's own to redistribute. Mods from
+/// providers are never uploaded.
+pub fn pack_files(base_dir: &Path, mods_file: &str) -> Result<Vec<(PathBuf, String)>> {
+    let modlist = base_dir.join(mods_file);
+    let loaded = manifest::load(&modlist, base_dir)?;
+    let mut pack = vec![(modlist, mods_file.to_string())];
+
+    let lock = base_dir.join(LOCK_FILE);
+    if lock.exists() {
+        pack.push((lock, LOCK_FILE.to_string()));
+    }
+
+    let overrides = base_dir.join(OVERRIDES_DIR);
+    if overrides.is_dir() {
+        let mut found = Vec::new();
+        files(&overrides, &mut found)?;
+        found.sort();
+        for file in found {
+            let name = format!("{}/{}", OVERRIDES_DIR, relative(&overrides, &file));
+            pack.push((file, name));
+        }
+    }
+
+    for m in loaded.manifest.mods.iter() {
+        if let Some(path) = m.local_path() {
+            pack.push((path, format!("{}/{}", JARS_DIR, m.filename)));
+        }
+    }
+    Ok(pack)
+}
+
+/// Uploads the pack of an instance, returning the number of files sent.
+/// Files removed from the pack are also removed from rsync and git targets.
+pub fn publish(base_dir: &Path, mods_file: &str, backend: &Backend, rollout: Option<u8>) -> Result<usize> {
+    let mut pack = pack_files(base_dir, mods_file)?;
+    let header = manifest::load(&base_dir.join(mods_file), base_dir)?.manifest.pack;
+    let version = header.version.clone();
+    let index = Index {
+        name: header.name,
+        version: header.version,
+        files: pack.iter().map(|(_, name)| name.clone()).collect(),
+        rollout,
+    };
+    let index_path = base_dir.join(STATE_DIR).join(INDEX_FILE);
+    fs::create_dir_all(base_dir.join(STATE_DIR))?;
+    fs::write(&index_path, serde_json::to_string_pretty(&index)?)?;
+    pack.push((index_path, INDEX_FILE.to_string()));
+
+    let staging = base_dir.join(STATE_DIR).join("publish");
+    match backend {
+        Backend::S3(location) => {
+            let client = s3::Client::from_env()?;
+            for (path, name) in pack.iter() {
+                let body = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+                client.put(&location.bucket, &location.key(name), body)?;
+                println!("[INFO] uploaded {}", name);
+            }
+        }
+        Backend::Rsync(target) => {
+            stage(&staging, &pack)?;
+            let source = format!("{}/", staging.display());
+            run_tool(Command::new("rsync").args(["-rtz", "--delete", &source, target]))?;
+        }
+        Backend::Sftp { host, path } => {
+            stage(&staging, &pack)?;
+            let staged = staging.to_string_lossy();
+            let (path, staged) = (sftp_quoted(path)?, sftp_literal(&staged)?);
+            // the staged files are put by the one glob left unescaped
+            let batch = format!("-mkdir {}\ncd {}\nput -r {}/*\"\n", path, path, staged.trim_end_matches('"'));
+            let batch_file = base_dir.join(STATE_DIR).join("publish.sftp");
+            fs::write(&batch_file, batch)?;
+            let result = run_tool(Command::new("sftp").arg("-b").arg(&batch_file).arg(host));
+            let _ = fs::remove_file(&batch_file);
+            result?;
+        }
+        Backend::Git { remote, branch } => {
+            let checkout = base_dir.join(STATE_DIR).join("publish-git");
+            // a fresh clone each time so leftovers of failed publishes never leak in
+            if checkout.exists() {
+                fs::remove_dir_all(&checkout)?;
+            }
+            run_tool(Command::new("git").arg("clone").arg("--quiet").arg(remote).arg(&checkout))?;
+            let git = |args: &[&str]| run_tool(Command::new("git").arg("-C").arg(&checkout).args(args));
+            if let Some(branch) = branch {
+                git(&["checkout", "--quiet", "-B", branch])?;
+                let remote_branch = format!("refs/remotes/origin/{}", branch);
+                if git(&["show-ref", "--verify", "--quiet", &remote_branch]).is_ok() {
+                    git(&["reset", "--quiet", "--hard", &remote_branch])?;
+                }
+            }
+            // everything but the repository itself is replaced by the pack
+            for entry in fs::read_dir(&checkout)? {
+                let path = entry?.path();
+                if path.file_name().is_some_and(|n| n == ".git") {
+                    continue;
+                }
+                if path.is_dir() {
+                    fs::remove_dir_all(&path)?;
+                } else {
+                    fs::remove_file(&path)?;
+                }
+            }
+            for (path, name) in pack.iter() {
+                let dest = checkout.join(name);
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(path, &dest)?;
+            }
+            git(&["add", "-A"])?;
+            // republishing the same files leaves nothing to commit
+            if git(&["diff", "--cached", "--quiet"]).is_err() {
+                git(&["commit", "--quiet", "-m", &format!("Publish pack version {}", version)])?;
+            }
+            let refspec = match branch {
+                Some(branch) => format!("HEAD:{}", branch),
+                None => "HEAD".to_string(),
+            };
+            git(&["push", "--quiet", "origin", &refspec])?;
+        }
+        Backend::Http(url) => {
+            return Err(anyhow!("{} is read only, publish with rsync: or sftp:// to the directory it serves", url));
+        }
+    }
+    Ok(pack.len())
+}
+
+/// `path` quoted for an sftp batch file, which splits arguments at spaces
+/// and takes backslashes and quotes inside double quotes escaped. A line
+/// break would end the command and start another, so paths holding one are
+/// refused.
+pub fn sftp_quoted(path: &str) -> Result<String> {
+    if path.contains(['\n', '\r']) {
+        return Err(anyhow!("{:?} holds a line break, which can't be passed to sftp", path));
+    }
+    Ok(format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\"")))
+}
+
+/// `path` quoted as by [`sftp_quoted`] for the arguments sftp expands as
+/// globs, the local files of `put` and the remote ones of `ls` and `rm`,
+/// with the glob characters escaped so it names the one file.
+pub fn sftp_literal(path: &str) -> Result<String> {
+    let escaped: String = path
+        .chars()
+        .flat_map(|c| match c {
+            '*' | '?' | '[' | ']' | '\\' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect();
+    sftp_quoted(&escaped)
+}
+
+/// Jars of local entries downloaded with the pack.
+fn jars_dir(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("dist").join(JARS_DIR)
+}
+
+/// Read access to a published pack.
+enum Reader {
+    S3 { client: s3::Client, location: s3::Location },
+    Http { client: reqwest::blocking::Client, url: String },
+    Dir(PathBuf),
+}
+
+impl Reader {
+    fn open(base_dir: &Path, backend: &Backend) -> Result<Reader> {
+        match backend {
+            Backend::S3(location) => Ok(Reader::S3 {
+                client: s3::Client::from_env()?,
+                location: location.clone(),
+            }),
+            Backend::Http(url) => Ok(Reader::Http {
+                client: http::files().clone(),
+                url: url.clone(),
+            }),
+            Backend::Git { remote, branch } => {
+                let checkout = base_dir.join(STATE_DIR).join("source-git");
+                if checkout.exists() {
+                    fs::remove_dir_all(&checkout)?;
+                }
+                let mut clone = Command::new("git");
+                clone.args(["clone", "--quiet", "--depth", "1"]);
+                if let Some(branch) = branch {
+                    clone.args(["--branch", branch]);
+                }
+                run_tool(clone.arg(remote).arg(&checkout))?;
+                Ok(Reader::Dir(checkout))
+            }
+            Backend::Rsync(_) | Backend::Sftp { .. } => Err(anyhow!(
+                "instances can't sync from rsync or sftp targets, use the http(s):// url they are served at"
+            )),
+        }
+    }
+
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match self {
+            Reader::S3 { client, location } => client.get(&location.bucket, &location.key(name)),
+            Reader::Http { client, url } => {
+                let url = reqwest::Url::parse(&format!("{}/", url))?.join(name)?;
+                let response = auth::apply(client.get(url.clone()), url.as_str()).send_captured().with_context(|| format!("request to {} failed", url))?;
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                if !response.status().is_success() {
+                    return Err(anyhow!("{} returned {}", url, response.status()));
+                }
+                Ok(Some(response.bytes()?.to_vec()))
+            }
+            Reader::Dir(dir) => match fs::read(dir.join(name)) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+
+    /// Files of a pack without an index, only S3 can list them.
+    fn list(&self) -> Result<Vec<String>> {
+        match self {
+            Reader::S3 { client, location } => Ok(client
+                .list(&location.bucket, &location.prefix)?
+                .into_iter()
+                .map(|k| k.strip_prefix(&location.prefix).unwrap_or(&k).to_string())
+                .collect()),
+            _ => Err(anyhow!("published pack has no {}, publish it again with this version", INDEX_FILE)),
+        }
+    }
+}
+
+fn pulled_path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("source.json")
+}
+
+/// Identifier of the instance among the clients of a pack, made up once and
+/// kept so the instance stays inside or outside of a rollout.
+pub fn client_id(base_dir: &Path) -> String {
+    // copies a prefetch or dry run pulls into share the instance's id
+    let base_dir = base_dir.ancestors().find(|a| a.file_name() == Some(STATE_DIR.as_ref())).and_then(Path::parent).unwrap_or(base_dir);
+    let path = base_dir.join(STATE_DIR).join("client-id");
+    if let Some(id) = fs::read_to_string(&path).ok().map(|id| id.trim().to_string()).filter(|id| !id.is_empty()) {
+        return id;
+    }
+    let seed = format!("{}:{}:{:?}", base_dir.display(), std::process::id(), std::time::SystemTime::now());
+    let id = hash::sha256_bytes(seed.as_bytes())[..32].to_string();
+    let _ = fs::create_dir_all(base_dir.join(STATE_DIR));
+    let _ = fs::write(&path, &id);
+    id
+}
+
+/// Whether the instance waits for a wider rollout of the published version,
+/// having pulled another one before. Instances are picked by a hash of
+/// their id and the version, so raising the percentage only adds clients
+/// and each version is tried first by different players.
+fn held_back(base_dir: &Path, index: &Index, previous: Option<&Pulled>) -> bool {
+    let Some(percent) = index.rollout.filter(|p| *p < 100) else {
+        return false;
+    };
+    if previous.is_none_or(|p| p.version == index.version) {
+        return false;
+    }
+    let digest = hash::sha256_bytes(format!("{}:{}", client_id(base_dir), index.version).as_bytes());
+    let bucket = u64::from_str_radix(&digest[..8], 16).expect("digests are hex") % 100;
+    if bucket < percent as u64 {
+        return false;
+    }
+    let _ = log_to_file(&format!("[INFO] pack {} is rolled out to {}% of clients, not this one yet", index.version, percent));
+    true
+}
+
+/// Version of the published pack of `channel` when it is not the one the
+/// instance pulled last, reading nothing but the index.
+pub fn pending(base_dir: &Path, backend: &Backend, channel: Option<&str>) -> Result<Option<String>> {
+    let reader = Reader::open(base_dir, backend)?;
+    let index: Index = match reader.read(INDEX_FILE)? {
+        Some(bytes) => serde_json::from_slice(&bytes).with_context(|| format!("published {} is corrupt", INDEX_FILE))?,
+        None => return Err(anyhow!("the published pack has no {}, publish it again to watch it", INDEX_FILE)),
+    };
+    let previous: Option<Pulled> = fs::read_to_string(pulled_path(base_dir))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok());
+    let same = Pulled { channel: channel.map(str::to_string), version: index.version.clone() };
+    if held_back(base_dir, &index, previous.as_ref()) {
+        return Ok(None);
+    }
+    Ok((previous.as_ref() != Some(&same)).then_some(index.version))
+}
+
+/// Brings the instance's manifest, lockfile and overrides up to date with
+/// the published pack of `channel`. Nothing beyond the index is downloaded
+/// when the instance already has the published version. Local entries are
+/// pointed at their downloaded jars since the maintainer's paths don't
+/// exist here.
+pub fn pull(base_dir: &Path, mods_file: &str, backend: &Backend, channel: Option<&str>) -> Result<Outcome> {
+    let reader = Reader::open(base_dir, backend)?;
+    let modlist = base_dir.join(mods_file);
+    let local_version = manifest::load(&modlist, base_dir).ok().map(|l| l.manifest.pack.version);
+    let previous: Option<Pulled> = fs::read_to_string(pulled_path(base_dir))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok());
+
+    let index: Option<Index> = match reader.read(INDEX_FILE)? {
+        Some(bytes) => Some(serde_json::from_slice(&bytes).with_context(|| format!("published {} is corrupt", INDEX_FILE))?),
+        None => None,
+    };
+    if let (Some(index), Some(previous), true) = (&index, &previous, modlist.exists()) {
+        let same = Pulled { channel: channel.map(str::to_string), version: index.version.clone() };
+        if *previous == same {
+            return Ok(Outcome::UpToDate(index.version.clone()));
+        }
+        if held_back(base_dir, index, Some(previous)) {
+            return Ok(Outcome::UpToDate(previous.version.clone()));
+        }
+    }
+    let names = match &index {
+        Some(index) => index.files.clone(),
+        None => reader.list()?,
+    };
+    if !names.iter().any(|n| n == mods_file) {
+        return Err(anyhow!("the published pack has no {}", mods_file));
+    }
+
+    // the lockfile comes first, its hashes tell which overrides are unchanged
+    let lock: Option<Lockfile> = match names.iter().any(|n| n == LOCK_FILE) {
+        true => match reader.read(LOCK_FILE)? {
+            Some(body) => {
+                let mut published: Lockfile = serde_json::from_slice(&body).with_context(|| format!("published {} is corrupt", LOCK_FILE))?;
+                // the owners this instance trusted stay, a publish doesn't
+                // vouch for a project that changed hands
+                if let Some(local) = Lockfile::load(base_dir).ok().flatten() {
+                    published.owners.extend(local.owners);
+                }
+                published.save(base_dir)?;
+                Some(published)
+            }
+            None => None,
+        },
+        false => None,
+    };
+    let overrides = base_dir.join(OVERRIDES_DIR);
+    let mut unchanged = HashSet::new();
+    if overrides.is_dir() {
+        let mut found = Vec::new();
+        files(&overrides, &mut found)?;
+        for file in found {
+            let key = relative(&overrides, &file);
+            let published = lock.as_ref().and_then(|l| l.overrides.get(&key));
+            if published.is_some_and(|h| hash::sha256_file(&file).is_ok_and(|local| local == *h)) {
+                unchanged.insert(format!("{}/{}", OVERRIDES_DIR, key));
+            } else {
+                fs::remove_file(&file)?;
+            }
+        }
+    }
+    let jars = jars_dir(base_dir);
+    for name in names.iter().filter(|n| *n != LOCK_FILE && !unchanged.contains(*n)) {
+        let dest = match name.split_once('/') {
+            Some((JARS_DIR, file)) => jars.join(file),
+            Some((OVERRIDES_DIR, _)) => base_dir.join(name),
+            None if name == mods_file => base_dir.join(name),
+            _ => continue,
+        };
+        if Path::new(name).components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+            return Err(anyhow!("published file {} escapes the instance", name));
+        }
+        let Some(body) = reader.read(name)? else {
+            continue;
+        };
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&dest, body).with_context(|| format!("failed to write {}", dest.display()))?;
+        let _ = log_to_file(&format!("[INFO]  fetched {}", name));
+    }
+
+    let mut loaded = manifest::load(&modlist, base_dir)?;
+    let mut rewritten = false;
+    for m in loaded.manifest.mods.iter_mut() {
+        let Ok(jar) = jars.join(&m.filename).canonicalize() else {
+            continue;
+        };
+        if m.local_path().is_some_and(|p| !p.exists()) {
+            m.url = reqwest::Url::from_file_path(&jar).ok().map(|u| u.to_string());
+            rewritten = true;
+        }
+    }
+    if rewritten {
+        manifest::save(&modlist, &loaded.manifest)?;
+    }
+
+    let version = loaded.manifest.pack.version;
+    if let Some(local) = &local_version {
+        if version::compare(&version, local) == Ordering::Less {
+            let _ = log_to_file(&format!("[WARN] published pack {} is older than {}, rolling back", version, local));
+        }
+    }
+    let pulled = Pulled { channel: channel.map(str::to_string), version: version.clone() };
+    fs::write(pulled_path(base_dir), serde_json::to_string_pretty(&pulled)?)?;
+    Ok(Outcome::Updated { from: local_version, to: version })
+}
@@ -0,0 +1,314 @@
+use super::http::{self, RequestExt};
+use super::settings::DnsSettings;
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use reqwest::blocking::Client;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+/// How long a DNS server or DoH endpoint gets to answer.
+const TIMEOUT: Duration = Duration::from_secs(5);
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+
+/// Where host names are looked up.
+pub enum Upstream {
+    /// The system's resolver.
+    System,
+    /// A DNS server asked over UDP.
+    Server(SocketAddr),
+    /// A DNS-over-HTTPS endpoint, asked with RFC 8484 GET requests.
+    Doh(String),
+}
+
+impl Upstream {
+    /// The upstream `settings` choose, DoH over a plain server.
+    pub fn of(settings: &DnsSettings) -> Result<Upstream> {
+        if let Some(url) = &settings.doh {
+            reqwest::Url::parse(url).with_context(|| format!("{} is not a DNS-over-HTTPS url", url))?;
+            return Ok(Upstream::Doh(url.clone()));
+        }
+        match &settings.server {
+            Some(server) => {
+                let addr = server
+                    .parse::<SocketAddr>()
+                    .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+                    .map_err(|_| anyhow!("{} is not the address of a DNS server, e.g. 1.1.1.1 or 9.9.9.9:53", server))?;
+                Ok(Upstream::Server(addr))
+            }
+            None => Ok(Upstream::System),
+        }
+    }
+
+    /// Addresses of `host`, port 0. IP literals are taken as they are.
+    pub fn lookup(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, 0)]);
+        }
+        let asked = match self {
+            Upstream::System => return (host, 0).to_socket_addrs().map(Iterator::collect),
+            Upstream::Server(server) => [TYPE_A, TYPE_AAAA]
+                .iter()
+                .map(|&t| udp(*server, host, t).and_then(|(m, id)| answers(&m, id)))
+                .collect::<Result<Vec<_>>>(),
+            Upstream::Doh(url) => [TYPE_A, TYPE_AAAA]
+                .iter()
+                .map(|&t| doh(url, host, t).and_then(|(m, id)| answers(&m, id)))
+                .collect::<Result<Vec<_>>>(),
+        };
+        let addrs: Vec<SocketAddr> = asked
+            .map_err(|e| io::Error::other(format!("failed to look up {}: {:#}", host, e)))?
+            .into_iter()
+            .flatten()
+            .map(|ip| SocketAddr::new(ip, 0))
+            .collect();
+        // names only the local network knows, like localhost
+        if addrs.is_empty() {
+            return (host, 0).to_socket_addrs().map(Iterator::collect);
+        }
+        Ok(addrs)
+    }
+
+    /// Target host and port of the SRV record `name`, the one of the lowest
+    /// priority and highest weight, none where it has none. The system's
+    /// resolver is asked through the first nameserver of `/etc/resolv.conf`,
+    /// where there is none no record is looked up.
+    pub fn srv(&self, name: &str) -> Result<Option<(String, u16)>> {
+        let message = match self {
+            Upstream::System => match system_server() {
+                Some(server) => udp(server, name, TYPE_SRV)?,
+                None => return Ok(None),
+            },
+            Upstream::Server(server) => udp(*server, name, TYPE_SRV)?,
+            Upstream::Doh(url) => doh(url, name, TYPE_SRV)?,
+        };
+        let (message, id) = message;
+        srv_target(&message, id)
+    }
+}
+
+/// The SRV record among the answers of `message` to query `id` of the
+/// lowest priority and highest weight, as its target and port.
+fn srv_target(message: &[u8], id: u16) -> Result<Option<(String, u16)>> {
+    let mut targets = Vec::new();
+    for (kind, at, data) in records(message, id)? {
+        if kind != TYPE_SRV || data.len() < 7 {
+            continue;
+        }
+        let (priority, weight, port) = (u16_at(data, 0)?, u16_at(data, 2)?, u16_at(data, 4)?);
+        let target = read_name(message, at + 6)?;
+        // "." says the service is not available there
+        if !target.is_empty() {
+            targets.push((priority, std::cmp::Reverse(weight), target, port));
+        }
+    }
+    Ok(targets.into_iter().min().map(|(_, _, target, port)| (target, port)))
+}
+
+/// The first nameserver the system's resolver is configured with.
+fn system_server() -> Option<SocketAddr> {
+    let conf = std::fs::read_to_string("/etc/resolv.conf").ok()?;
+    conf.lines()
+        .filter_map(|l| l.trim().strip_prefix("nameserver"))
+        .find_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+}
+
+/// A query for the `kind` records of `host`, recursion desired.
+fn query(id: u16, host: &str, kind: u16) -> Result<Vec<u8>> {
+    let mut message = Vec::with_capacity(host.len() + 18);
+    message.extend(id.to_be_bytes());
+    message.extend([0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(anyhow!("{} is not a valid host name", host));
+        }
+        message.push(label.len() as u8);
+        message.extend(label.as_bytes());
+    }
+    message.push(0);
+    message.extend(kind.to_be_bytes());
+    message.extend(1u16.to_be_bytes());
+    Ok(message)
+}
+
+fn u16_at(message: &[u8], at: usize) -> Result<u16> {
+    message
+        .get(at..at + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| anyhow!("truncated DNS answer"))
+}
+
+/// Position after the name at `at`, which may end in a pointer.
+fn skip_name(message: &[u8], mut at: usize) -> Result<usize> {
+    loop {
+        let length = *message.get(at).ok_or_else(|| anyhow!("truncated DNS answer"))?;
+        match length {
+            0 => return Ok(at + 1),
+            l if l & 0xc0 == 0xc0 => return Ok(at + 2),
+            l => at += 1 + l as usize,
+        }
+    }
+}
+
+/// The name at `at`, following its pointers, without the final dot.
+fn read_name(message: &[u8], mut at: usize) -> Result<String> {
+    let mut labels = Vec::new();
+    // pointers only lead back, a loop of them can't be longer than this
+    for _ in 0..message.len() {
+        let length = *message.get(at).ok_or_else(|| anyhow!("truncated DNS answer"))?;
+        match length {
+            0 => return Ok(labels.join(".")),
+            l if l & 0xc0 == 0xc0 => at = (u16_at(message, at)? & 0x3fff) as usize,
+            l => {
+                let label = message.get(at + 1..at + 1 + l as usize).ok_or_else(|| anyhow!("truncated DNS answer"))?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                at += 1 + l as usize;
+            }
+        }
+    }
+    Err(anyhow!("DNS answer with a loop of names"))
+}
+
+/// The kind, position of the data and data of the answers of the response
+/// `message` to query `id`.
+fn records(message: &[u8], id: u16) -> Result<Vec<(u16, usize, &[u8])>> {
+    if u16_at(message, 0)? != id {
+        return Err(anyhow!("DNS answer to another query"));
+    }
+    let flags = u16_at(message, 2)?;
+    match flags & 0x000f {
+        0 => {}
+        // the name doesn't exist
+        3 => return Ok(Vec::new()),
+        rcode => return Err(anyhow!("DNS server answered with error code {}", rcode)),
+    }
+    let (questions, count) = (u16_at(message, 4)?, u16_at(message, 6)?);
+    let mut at = 12;
+    for _ in 0..questions {
+        at = skip_name(message, at)? + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..count {
+        at = skip_name(message, at)?;
+        let kind = u16_at(message, at)?;
+        let length = u16_at(message, at + 8)? as usize;
+        let data = message.get(at + 10..at + 10 + length).ok_or_else(|| anyhow!("truncated DNS answer"))?;
+        records.push((kind, at + 10, data));
+        at += 10 + length;
+    }
+    Ok(records)
+}
+
+/// The addresses among the answers of the response `message` to query
+/// `id`. Other records, such as the CNAMEs leading to them, are skipped.
+fn answers(message: &[u8], id: u16) -> Result<Vec<IpAddr>> {
+    let mut addrs = Vec::new();
+    for (kind, _, data) in records(message, id)? {
+        match (kind, data.len()) {
+            (TYPE_A, 4) => addrs.push(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3]))),
+            (TYPE_AAAA, 16) => {
+                let octets: [u8; 16] = data.try_into().expect("length checked");
+                addrs.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            }
+            _ => {}
+        }
+    }
+    Ok(addrs)
+}
+
+fn id() -> u16 {
+    // unpredictable enough to tell answers apart, ids are not a defence
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos() as u16)
+}
+
+/// The response of `server` to a query for the `kind` records of `host`,
+/// with the id it was asked with.
+fn udp(server: SocketAddr, host: &str, kind: u16) -> Result<(Vec<u8>, u16)> {
+    let id = id();
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.send_to(&query(id, host, kind)?, server)?;
+    let mut buffer = [0u8; 1232];
+    loop {
+        let (length, from) = socket.recv_from(&mut buffer).with_context(|| format!("{} did not answer", server))?;
+        if from == server {
+            return Ok((buffer[..length].to_vec(), id));
+        }
+    }
+}
+
+/// The answer of the DoH endpoint `url` to a query for the `kind` records
+/// of `host`, with the id it was asked with.
+fn doh(url: &str, host: &str, kind: u16) -> Result<(Vec<u8>, u16)> {
+    // the endpoint's own host is looked up by the system
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    let id = id();
+    let encoded = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(query(id, host, kind)?);
+    let url = format!("{}{}dns={}", url, if url.contains('?') { '&' } else { '?' }, encoded);
+    // a blocking client can't run on the thread of the client asking
+    let body = thread::scope(|s| {
+        s.spawn(|| -> Result<Vec<u8>> {
+            let client = CLIENT.get_or_init(|| http::bind(Client::builder()).timeout(TIMEOUT).build().expect("the http client settings are valid"));
+            let response = client.get(&url).header(ACCEPT, "application/dns-message").send_captured()?.error_for_status()?;
+            let dns = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .is_some_and(|c| c.as_bytes().starts_with(b"application/dns-message"));
+            if !dns {
+                return Err(anyhow!("{} did not answer with a DNS message", url));
+            }
+            Ok(response.bytes()?.to_vec())
+        })
+        .join()
+        .map_err(|_| anyhow!("DNS-over-HTTPS lookup panicked"))?
+    })?;
+    Ok((body, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An answer to query 7 for `_minecraft._tcp.example.com` with SRV
+    /// records of a priority, weight, port and a target under example.com.
+    fn answer(srv: &[(u16, u16, u16, &str)]) -> Vec<u8> {
+        let mut message = query(7, "_minecraft._tcp.example.com", TYPE_SRV).unwrap();
+        message[2] = 0x81;
+        message[6..8].copy_from_slice(&(srv.len() as u16).to_be_bytes());
+        for (priority, weight, port, label) in srv {
+            // the name asked, then the target as a label and a pointer to
+            // example.com in the question
+            message.extend([0xc0, 12]);
+            message.extend(TYPE_SRV.to_be_bytes());
+            message.extend([0, 1, 0, 0, 0, 60]);
+            message.extend((6 + 1 + label.len() as u16 + 2).to_be_bytes());
+            message.extend(priority.to_be_bytes());
+            message.extend(weight.to_be_bytes());
+            message.extend(port.to_be_bytes());
+            message.push(label.len() as u8);
+            message.extend(label.as_bytes());
+            message.extend([0xc0, 28]);
+        }
+        message
+    }
+
+    #[test]
+    fn srv_records_name_their_best_target() {
+        let message = answer(&[(10, 5, 25570, "backup"), (1, 5, 25566, "mc"), (1, 9, 25567, "play")]);
+        assert_eq!(srv_target(&message, 7).unwrap(), Some(("play.example.com".to_string(), 25567)));
+        assert_eq!(srv_target(&answer(&[]), 7).unwrap(), None);
+        assert!(srv_target(&message, 8).is_err());
+    }
+}
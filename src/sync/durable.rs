@@ -0,0 +1,151 @@
+use super::hash;
+use super::log::STATE_DIR;
+use super::plan::{Action, Plan};
+use super::staging;
+use super::state::{self, State};
+use super::unpack;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns on flushing written files to disk for the rest of the run, for
+/// servers that must come back from a power loss with a working instance.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Flushes the file at `path` and the directory listing it to disk, when
+/// durability was asked for.
+pub fn flush(path: &Path) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.sync_all();
+    }
+    flush_dir(path);
+}
+
+/// Flushes the directory listing `path`, so a file created, renamed or
+/// deleted in it stays so.
+pub fn flush_dir(path: &Path) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    // directories can't be opened on every platform, there is nothing to do there
+    if let Some(Ok(dir)) = path.parent().map(fs::File::open) {
+        let _ = dir.sync_all();
+    }
+}
+
+/// Replaces the file at `path` with `contents` at once: readers and a
+/// crash see either the old contents or the new, never part of them.
+pub fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    let written = staging::part(None, path);
+    let mut file = fs::File::create(&written).with_context(|| format!("failed to write {}", written.display()))?;
+    file.write_all(contents)?;
+    if ENABLED.load(Ordering::Relaxed) {
+        file.sync_all()?;
+    }
+    drop(file);
+    fs::rename(&written, path).with_context(|| format!("failed to write {}", path.display()))?;
+    flush_dir(path);
+    Ok(())
+}
+
+pub fn path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("journal.json")
+}
+
+/// The installs a sync is about to make, written before the first of them
+/// and deleted once the state records them. Still being there on the next
+/// run means the sync stopped half way.
+#[derive(Serialize, Deserialize)]
+pub struct Journal {
+    pub started_at: DateTime<Utc>,
+    /// Files to be installed, relative to the game directory.
+    pub installs: Vec<String>,
+    /// Recorded files to be deleted.
+    pub removals: Vec<String>,
+}
+
+impl Journal {
+    /// Records the installs of `plan` before they are made.
+    pub fn begin(base_dir: &Path, game_dir: &Path, plan: &Plan) -> Result<()> {
+        let journal = Journal {
+            started_at: Utc::now(),
+            installs: plan
+                .actions
+                .iter()
+                .filter_map(|a| match a {
+                    Action::Install { dest, world: false, .. } => Some(state::key(game_dir, dest)),
+                    _ => None,
+                })
+                .collect(),
+            removals: plan
+                .actions
+                .iter()
+                .filter_map(|a| match a {
+                    Action::Remove { path } => Some(state::key(game_dir, path)),
+                    _ => None,
+                })
+                .collect(),
+        };
+        let path = path(base_dir);
+        fs::create_dir_all(path.parent().expect("the journal lies in the state directory"))?;
+        write(&path, &serde_json::to_vec_pretty(&journal)?)
+    }
+
+    /// Drops the journal once the state records what the sync did.
+    pub fn finish(base_dir: &Path) {
+        let path = path(base_dir);
+        if fs::remove_file(&path).is_ok() {
+            flush_dir(&path);
+        }
+    }
+
+    /// Deletes what an interrupted sync left of the installs it journaled:
+    /// files the saved state doesn't record as they are on disk, and
+    /// downloads that never completed. The sync about to run installs them
+    /// again. Removals it made are forgotten by `state`, those of unpacked
+    /// archives finished. Hands back the start of the interrupted sync and
+    /// the number of files deleted, nothing when the last sync finished.
+    /// The journal stays until `state` is saved.
+    pub fn recover(base_dir: &Path, game_dir: &Path, staging_dir: &Path, state: &mut State) -> Result<Option<(DateTime<Utc>, usize)>> {
+        let path = path(base_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let journal: Journal =
+            serde_json::from_slice(&contents).with_context(|| format!("{} is corrupt, delete it to start over", path.display()))?;
+        let mut deleted = 0;
+        for key in journal.installs.iter() {
+            let file = game_dir.join(key);
+            let _ = fs::remove_file(staging::part(Some(staging_dir), &file));
+            let recorded = state.files.get(key).is_some_and(|m| hash::sha256_file(&file).is_ok_and(|h| h == m.sha256));
+            if file.is_file() && !recorded && fs::remove_file(&file).is_ok() {
+                flush_dir(&file);
+                deleted += 1;
+            }
+        }
+        for key in journal.removals.iter() {
+            let Some(managed) = state.files.get(key) else {
+                continue;
+            };
+            match managed.files.is_empty() {
+                true if game_dir.join(key).exists() => continue,
+                true => {}
+                false => unpack::remove(game_dir, &managed.files),
+            }
+            state.files.remove(key);
+        }
+        Ok(Some((journal.started_at, deleted)))
+    }
+}
@@ -0,0 +1,271 @@
+use super::log::console;
+use super::{hosts, report};
+use crate::i18n::t;
+use serde::{Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Something that happened during a sync, for subscribers to measure.
+/// Serializes tagged with `event`, durations in seconds.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Planning the effective modlist against the instance started.
+    ResolutionStarted { mods: usize },
+    ResolutionFinished {
+        actions: usize,
+        #[serde(serialize_with = "seconds")]
+        elapsed: Duration,
+    },
+    /// A file was fetched, `provider` being curseforge, maven or local.
+    Downloaded {
+        filename: &'a str,
+        provider: &'a str,
+        host: &'a str,
+        bytes: u64,
+        #[serde(serialize_with = "seconds")]
+        elapsed: Duration,
+    },
+    DownloadFailed { filename: &'a str, host: &'a str, error: &'a str },
+    Removed { filename: &'a str },
+    /// A file's hash was taken from the hash cache.
+    CacheHit { path: &'a Path },
+    /// A file had to be read to hash it.
+    CacheMiss { path: &'a Path },
+    /// A request failed and is tried again.
+    Retry { url: &'a str, attempt: u32, error: &'a str },
+    RunFinished {
+        #[serde(serialize_with = "seconds")]
+        elapsed: Duration,
+        ok: bool,
+    },
+}
+
+fn seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Receives every event emitted while it is subscribed.
+pub trait Subscriber: Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+/// The subscribers of one run. Each run of a process has its own, so runs
+/// side by side only see the events of their own.
+#[derive(Default)]
+pub struct Bus {
+    subscribers: Mutex<Vec<(u64, Arc<dyn Subscriber>)>>,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Bus>>> = const { RefCell::new(None) };
+}
+
+/// The bus of the run on this thread, for the threads it starts to
+/// [`enter`].
+pub fn current() -> Option<Arc<Bus>> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+/// Keeps `bus` the one of this thread until dropped.
+pub struct Entered(Option<Arc<Bus>>);
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.with(|c| *c.borrow_mut() = self.0.take());
+    }
+}
+
+/// Makes `bus` the one events of this thread are emitted on and
+/// subscribers subscribe to.
+#[must_use = "the thread leaves the bus when this is dropped"]
+pub fn enter(bus: Option<Arc<Bus>>) -> Entered {
+    Entered(CURRENT.with(|c| c.replace(bus)))
+}
+
+/// Keeps a subscriber subscribed until it is dropped.
+pub struct Subscription(Option<(Arc<Bus>, u64)>);
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some((bus, id)) = &self.0 {
+            bus.subscribers
+                .lock()
+                .expect("subscribers lock poisoned")
+                .retain(|(i, _)| i != id);
+        }
+    }
+}
+
+/// Subscribes to the bus of this thread, outside of a run there are no
+/// events to receive.
+#[must_use = "the subscriber is removed when the subscription is dropped"]
+pub fn subscribe(subscriber: Arc<dyn Subscriber>) -> Subscription {
+    let Some(bus) = current() else {
+        return Subscription(None);
+    };
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    bus.subscribers.lock().expect("subscribers lock poisoned").push((id, subscriber));
+    Subscription(Some((bus, id)))
+}
+
+pub fn emit(event: Event) {
+    let Some(bus) = current() else {
+        return;
+    };
+    // subscribers run without the lock so they may emit themselves
+    let subscribers: Vec<Arc<dyn Subscriber>> = bus
+        .subscribers
+        .lock()
+        .expect("subscribers lock poisoned")
+        .iter()
+        .map(|(_, s)| s.clone())
+        .collect();
+    for subscriber in subscribers {
+        subscriber.event(&event);
+    }
+}
+
+/// Prints every finished download and removal as a line of its own, what
+/// `--plain` shows instead of only the summary.
+pub struct Printer;
+
+impl Subscriber for Printer {
+    fn event(&self, event: &Event) {
+        match event {
+            Event::ResolutionFinished { actions, .. } => console!("[INFO] {}", t!("progress-planned", actions = actions)),
+            Event::Downloaded { filename, host, bytes, .. } => console!(
+                "[INFO] {}",
+                t!("progress-downloaded", file = filename, size = report::human_size(*bytes), host = host)
+            ),
+            Event::DownloadFailed { filename, error, .. } => {
+                console!("[WARN] {}", t!("progress-failed", file = filename, error = error))
+            }
+            Event::Removed { filename } => console!("[INFO] {}", t!("progress-removed", file = filename)),
+            Event::Retry { url, attempt, error } => {
+                console!("[WARN] {}", t!("progress-retry", url = url, attempt = attempt, error = error))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Totals of one run, what the summary and the reports show.
+#[derive(Serialize, Default, Clone)]
+pub struct Stats {
+    pub seconds: f64,
+    pub resolve_seconds: f64,
+    pub download_seconds: f64,
+    pub downloads: usize,
+    pub bytes: u64,
+    /// Bytes of `bytes` fetched from peers rather than the file's host.
+    pub peer_bytes: u64,
+    pub download_failures: usize,
+    pub removed: usize,
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+    pub retries: usize,
+    pub ok: bool,
+    pub hosts: BTreeMap<String, HostStats>,
+}
+
+/// Totals of the downloads from one host.
+#[derive(Serialize, Default, Clone)]
+pub struct HostStats {
+    pub downloads: usize,
+    pub bytes: u64,
+    pub seconds: f64,
+    pub failures: usize,
+    pub retries: usize,
+}
+
+impl HostStats {
+    /// Bytes per second while downloading from the host.
+    pub fn throughput(&self) -> f64 {
+        match self.seconds > 0.0 {
+            true => self.bytes as f64 / self.seconds,
+            false => 0.0,
+        }
+    }
+}
+
+/// Subscriber adding events up into [`Stats`].
+#[derive(Default)]
+pub struct Collector(Mutex<Stats>);
+
+impl Collector {
+    pub fn stats(&self) -> Stats {
+        self.0.lock().expect("stats lock poisoned").clone()
+    }
+}
+
+impl Subscriber for Collector {
+    fn event(&self, event: &Event) {
+        let mut stats = self.0.lock().expect("stats lock poisoned");
+        match event {
+            Event::ResolutionStarted { .. } => {}
+            Event::ResolutionFinished { elapsed, .. } => stats.resolve_seconds += elapsed.as_secs_f64(),
+            Event::Downloaded { provider, host, bytes, elapsed, .. } => {
+                stats.downloads += 1;
+                stats.bytes += bytes;
+                if *provider == "peer" {
+                    stats.peer_bytes += bytes;
+                }
+                stats.download_seconds += elapsed.as_secs_f64();
+                let host = stats.hosts.entry(host.to_string()).or_default();
+                host.downloads += 1;
+                host.bytes += bytes;
+                host.seconds += elapsed.as_secs_f64();
+            }
+            Event::DownloadFailed { host, .. } => {
+                stats.download_failures += 1;
+                stats.hosts.entry(host.to_string()).or_default().failures += 1;
+            }
+            Event::Removed { .. } => stats.removed += 1,
+            Event::CacheHit { .. } => stats.cache_hits += 1,
+            Event::CacheMiss { .. } => stats.cache_misses += 1,
+            Event::Retry { url, .. } => {
+                stats.retries += 1;
+                stats.hosts.entry(hosts::of(url)).or_default().retries += 1;
+            }
+            Event::RunFinished { elapsed, ok } => {
+                stats.seconds = elapsed.as_secs_f64();
+                stats.ok = *ok;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_side_by_side_keep_their_events() {
+        let run = || {
+            let _bus = enter(Some(Arc::new(Bus::default())));
+            let collector = Arc::new(Collector::default());
+            let _subscription = subscribe(collector.clone());
+            let bus = current();
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    let _bus = enter(bus);
+                    emit(Event::Removed { filename: "a.jar" });
+                });
+            });
+            emit(Event::Removed { filename: "b.jar" });
+            collector.stats().removed
+        };
+        let (a, b) = std::thread::scope(|s| {
+            let (a, b) = (s.spawn(run), s.spawn(run));
+            (a.join().unwrap(), b.join().unwrap())
+        });
+        assert_eq!((a, b), (2, 2));
+    }
+}
@@ -0,0 +1,120 @@
+use super::log::log_to_file;
+use super::manifest::{self, Manifest, Strategy};
+use super::cache;
+use super::http::{self, RequestExt};
+use super::warnings;
+use crate::i18n::t;
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Parent chains deeper than this are almost certainly a mistake.
+const MAX_DEPTH: usize = 8;
+
+pub struct Flattened {
+    pub manifest: Manifest,
+    /// Sources of every layer, root parent first and the manifest itself last.
+    pub layers: Vec<String>,
+}
+
+/// Fetches the manifest at `location`, a url or a path, with its parents
+/// flattened into it.
+pub fn load(location: &str, base_dir: &Path) -> Result<Flattened> {
+    let manifest = fetch(location, base_dir).with_context(|| format!("failed to load manifest {}", location))?;
+    flatten(manifest, location, base_dir)
+}
+
+/// Resolves the `extends` chain of a manifest into a single list. Layers are
+/// applied parent first: a child's entries replace the parent's entry for the
+/// same project, and its `remove` list drops inherited entries.
+pub fn flatten(manifest: Manifest, source: &str, base_dir: &Path) -> Result<Flattened> {
+    let mut chain = vec![(source.to_string(), manifest)];
+
+    while let Some(parent) = chain.last().and_then(|(_, m)| m.extends.clone()) {
+        let location = locate(&parent, &chain.last().expect("chain is never empty").0, base_dir);
+        if chain.iter().any(|(s, _)| *s == location) {
+            return Err(anyhow!("manifest {} extends itself through {}", source, location));
+        }
+        if chain.len() > MAX_DEPTH {
+            return Err(anyhow!("manifest {} has more than {} parent layers", source, MAX_DEPTH));
+        }
+        let parent_manifest = fetch(&location, base_dir)
+            .with_context(|| format!("failed to load parent manifest {}", location))?;
+        chain.push((location, parent_manifest));
+    }
+
+    chain.reverse();
+    let mut layers = Vec::new();
+    let mut iter = chain.into_iter();
+    let (root_source, mut effective) = iter.next().expect("chain is never empty");
+    layers.push(root_source);
+
+    for (layer_source, layer) in iter {
+        effective
+            .mods
+            .retain(|m| !layer.remove.iter().any(|r| r == m.key() || *r == m.filename));
+
+        // a layer without a name or version, such as the modlist of a
+        // joined server, goes by its parent's
+        let pack = &layer.pack;
+        if !pack.name.is_empty() {
+            effective.pack.name = pack.name.clone();
+        }
+        if !pack.version.is_empty() {
+            effective.pack.version = pack.version.clone();
+        }
+        effective.pack.minecraft = pack.minecraft.clone().or(effective.pack.minecraft.take());
+        effective.pack.loader = pack.loader.clone().or(effective.pack.loader.take());
+        effective.pack.launch = pack.launch.clone().or(effective.pack.launch.take());
+        effective.pack.stamp = pack.stamp.clone().or(effective.pack.stamp.take());
+        effective.pack.extensions.extend(pack.extensions.clone());
+        effective.pack.variables.extend(pack.variables.clone());
+
+        manifest::merge_into(&mut effective, layer, Strategy::KeepLast)?;
+        layers.push(layer_source);
+    }
+
+    effective.extends = None;
+    effective.remove = Vec::new();
+    Ok(Flattened { manifest: effective, layers })
+}
+
+/// Relative parents are looked up next to the manifest that references them.
+fn locate(reference: &str, child: &str, base_dir: &Path) -> String {
+    if is_url(reference) || Path::new(reference).is_absolute() {
+        return reference.to_string();
+    }
+    if is_url(child) {
+        let dir = child.rsplit_once('/').map(|(d, _)| d).unwrap_or(child);
+        return format!("{}/{}", dir, reference);
+    }
+    let dir = Path::new(child).parent().map(PathBuf::from).unwrap_or_else(|| base_dir.to_path_buf());
+    dir.join(reference).to_string_lossy().to_string()
+}
+
+fn fetch(location: &str, base_dir: &Path) -> Result<Manifest> {
+    if !is_url(location) {
+        return Ok(manifest::load(Path::new(location), base_dir)?.manifest);
+    }
+
+    // the last copy fetched stands in while the host is unreachable
+    let body = match http::api().get(location).send_captured().and_then(|r| r.error_for_status()) {
+        Ok(response) => {
+            let body = http::decoded(response)?;
+            cache::put(location, &body);
+            body
+        }
+        Err(e) => {
+            let body = cache::get(location, Duration::MAX).ok_or(e)?;
+            let _ = log_to_file(&format!("[WARN] failed to fetch {}, using the cached copy", location));
+            warnings::warn(warnings::Code::ParentCached, &t!("sync-parent-cached", location = location));
+            body
+        }
+    };
+    let body = String::from_utf8(body).with_context(|| format!("{} is not valid UTF-8", location))?;
+    Ok(manifest::parse(&body, base_dir)?.manifest)
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("https://") || location.starts_with("http://")
+}
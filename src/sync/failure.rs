@@ -0,0 +1,74 @@
+use super::log::STATE_DIR;
+use super::manifest::PackHeader;
+use super::plan::Plan;
+use super::{Failure, Run};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    /// The run stopped early.
+    Fatal,
+    /// The run completed but some entries could not be synced.
+    Partial,
+}
+
+#[derive(Serialize)]
+struct Environment {
+    os: &'static str,
+    arch: &'static str,
+    tool_version: &'static str,
+}
+
+/// Snapshot of a failed run meant to be attached to bug reports.
+#[derive(Serialize)]
+struct Report<'a> {
+    time: DateTime<Utc>,
+    kind: Kind,
+    /// The error followed by each of its causes.
+    error_chain: Vec<String>,
+    failures: &'a [Failure],
+    pack: Option<&'a PackHeader>,
+    plan: Option<&'a Plan>,
+    environment: Environment,
+}
+
+pub fn path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("last-failure.json")
+}
+
+/// Writes `last-failure.json`. `error` is set for fatal failures, partial
+/// ones are described by the failed entries of the run.
+pub fn write(base_dir: &Path, error: Option<&anyhow::Error>, run: &Run) -> Result<()> {
+    let report = Report {
+        time: Utc::now(),
+        kind: if error.is_some() { Kind::Fatal } else { Kind::Partial },
+        error_chain: error
+            .map(|e| e.chain().map(|c| c.to_string()).collect())
+            .unwrap_or_default(),
+        failures: &run.failures,
+        pack: run.pack.as_ref(),
+        plan: run.plan.as_ref(),
+        environment: Environment {
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            tool_version: env!("CARGO_PKG_VERSION"),
+        },
+    };
+
+    let path = path(base_dir);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    Ok(())
+}
+
+/// Removes the report of an earlier failure once a run succeeds.
+pub fn clear(base_dir: &Path) {
+    let _ = fs::remove_file(path(base_dir));
+}
@@ -0,0 +1,127 @@
+use super::http::{self, RequestExt};
+use super::log::STATE_DIR;
+use super::overrides;
+use super::settings::FleetSettings;
+use super::state::State;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Path of a `serve` instance clients post their reports to.
+pub const REPORT_PATH: &str = "/report";
+/// Bytes a report may take, more is not a report.
+pub const MAX_REPORT_BYTES: u64 = 16 * 1024;
+/// Clients kept, the ones that reported longest ago make room for new ones.
+const MAX_CLIENTS: usize = 5000;
+/// Reports taken from one address a minute, a sync sends one.
+const REPORTS_PER_MINUTE: usize = 10;
+
+/// What a client tells the maintainer about itself after a sync.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Report {
+    /// Identifier the instance made up for itself.
+    pub client_id: String,
+    /// Name of the player or server, when the instance gives one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Whether the sync finished without failed mods.
+    pub synced: bool,
+    /// Managed files missing or of another size than installed.
+    pub problems: usize,
+    pub tool_version: String,
+    pub reported_at: DateTime<Utc>,
+}
+
+pub fn path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("fleet.json")
+}
+
+/// The last report of every client of a `serve` instance, by client id.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Fleet {
+    pub clients: BTreeMap<String, Report>,
+}
+
+impl Fleet {
+    pub fn load(base_dir: &Path) -> Result<Fleet> {
+        let path = path(base_dir);
+        if !path.exists() {
+            return Ok(Fleet::default());
+        }
+        let contents = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("{} is corrupt", path.display()))
+    }
+
+    fn save(&self, base_dir: &Path) -> Result<()> {
+        let path = path(base_dir);
+        fs::create_dir_all(path.parent().expect("the fleet lies in the state directory"))?;
+        fs::write(&path, serde_json::to_string_pretty(self)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Keeps `report` as the latest of its client. Reports arrive on several
+/// connections at once, they are recorded one after the other.
+pub fn record(base_dir: &Path, report: Report) -> Result<()> {
+    static RECORDING: Mutex<()> = Mutex::new(());
+    let _recording = RECORDING.lock().unwrap_or_else(|e| e.into_inner());
+    let mut fleet = Fleet::load(base_dir)?;
+    fleet.clients.insert(report.client_id.clone(), report);
+    while fleet.clients.len() > MAX_CLIENTS {
+        let oldest = fleet.clients.values().min_by_key(|r| r.reported_at).map(|r| r.client_id.clone());
+        match oldest {
+            Some(id) => fleet.clients.remove(&id),
+            None => break,
+        };
+    }
+    fleet.save(base_dir)
+}
+
+/// Whether `peer` sent more than [`REPORTS_PER_MINUTE`] reports in the
+/// last minute, counting this one.
+pub fn throttled(peer: IpAddr) -> bool {
+    static SENT: Mutex<Option<HashMap<IpAddr, (Instant, usize)>>> = Mutex::new(None);
+    let mut sent = SENT.lock().unwrap_or_else(|e| e.into_inner());
+    let sent = sent.get_or_insert_with(HashMap::new);
+    let minute = Duration::from_secs(60);
+    sent.retain(|_, (since, _)| since.elapsed() < minute);
+    let (_, count) = sent.entry(peer).or_insert((Instant::now(), 0));
+    *count += 1;
+    *count > REPORTS_PER_MINUTE
+}
+
+/// Managed files that are missing or changed size since they were
+/// installed, as `verify --quick` counts them.
+pub fn problems(game_dir: &Path, state: &State) -> usize {
+    state
+        .entries(game_dir)
+        .filter(|(_, m)| m.source != overrides::SOURCE)
+        .filter(|(path, managed)| match managed.files.is_empty() {
+            true => fs::metadata(path).map_or(true, |meta| managed.size.is_some_and(|size| size != meta.len())),
+            false => managed.files.iter().any(|f| !game_dir.join(f).is_file()),
+        })
+        .count()
+}
+
+/// Posts `report` to the `serve` instance the settings name.
+pub fn send(settings: &FleetSettings, report: &Report) -> Result<()> {
+    let url = format!("{}{}", settings.report_to.trim_end_matches('/'), REPORT_PATH);
+    let mut request = http::api().post(&url).json(report).timeout(Duration::from_secs(10));
+    if let Some(token) = settings.token() {
+        request = request.bearer_auth(token);
+    }
+    request
+        .send_captured()
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("failed to report to {}", url))?;
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use super::conditions::Condition;
+use super::hash::{Algorithm, Multihash};
+use super::http::{self, RequestExt};
+use super::lock::{LockedFile, Lockfile};
+use super::manifest::{self, Manifest, Mod, PackHeader};
+use super::overrides::OVERRIDES_DIR;
+use anyhow::{anyhow, Context, Result};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+const API: &str = "https://api.feed-the-beast.com/v1/modpacks/public/modpack";
+/// Tag given to the files FTB marks optional, turned on with `--with`.
+pub const OPTIONAL_TAG: &str = "optional";
+
+#[derive(Deserialize)]
+pub struct Pack {
+    pub name: String,
+    #[serde(default)]
+    pub versions: Vec<VersionHeader>,
+}
+
+#[derive(Deserialize)]
+pub struct VersionHeader {
+    pub id: u64,
+    pub name: String,
+    /// `Release`, `Beta` or `Alpha`.
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(default)]
+    pub updated: i64,
+}
+
+#[derive(Deserialize)]
+pub struct Version {
+    pub name: String,
+    #[serde(default)]
+    pub targets: Vec<Target>,
+    #[serde(default)]
+    pub files: Vec<File>,
+}
+
+/// The game, loader or Java version a pack version is for.
+#[derive(Deserialize)]
+pub struct Target {
+    pub name: String,
+    pub version: String,
+    /// `game`, `modloader` or `runtime`.
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Deserialize)]
+pub struct File {
+    pub name: String,
+    /// Directory relative to the game directory, e.g. `./mods/`.
+    pub path: String,
+    #[serde(default)]
+    pub version: String,
+    pub url: String,
+    pub sha1: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub clientonly: bool,
+    #[serde(default)]
+    pub serveronly: bool,
+    #[serde(default)]
+    pub optional: bool,
+    /// Where CurseForge has the same file, for files FTB mirrors.
+    #[serde(default)]
+    pub curseforge: Option<CurseforgeFile>,
+}
+
+#[derive(Deserialize)]
+pub struct CurseforgeFile {
+    pub project: u64,
+    pub file: u64,
+}
+
+impl File {
+    /// Directory of the file relative to the game directory, without the
+    /// leading `./` and trailing `/` FTB writes.
+    pub fn dir(&self) -> &str {
+        self.path.trim_start_matches("./").trim_matches('/')
+    }
+
+    /// Path of the file relative to the game directory.
+    pub fn key(&self) -> String {
+        match self.dir() {
+            "" => self.name.clone(),
+            dir => format!("{}/{}", dir, self.name),
+        }
+    }
+}
+
+/// FTB answers errors with a status and message of its own, often with a
+/// success code.
+#[derive(Deserialize)]
+struct Status {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    message: String,
+}
+
+fn get<T: for<'de> Deserialize<'de>>(url: &str) -> Result<T> {
+    let response = http::api().get(url).send_captured().with_context(|| format!("failed to reach FTB at {}", url))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(anyhow!("FTB has no modpack at {}", url));
+    }
+    let body = http::decoded(response.error_for_status()?)?;
+    if let Some(status) = serde_json::from_slice::<Status>(&body).ok().filter(|s| s.status == "error") {
+        return Err(anyhow!("FTB answered {} for {}", status.message, url));
+    }
+    serde_json::from_slice(&body).with_context(|| format!("unexpected response from {}", url))
+}
+
+pub fn pack(pack_id: u64) -> Result<Pack> {
+    get(&format!("{}/{}", API, pack_id))
+}
+
+pub fn version(pack_id: u64, version_id: u64) -> Result<Version> {
+    get(&format!("{}/{}/{}", API, pack_id, version_id))
+}
+
+impl Pack {
+    /// The version named or numbered `wanted`, the newest release without
+    /// one, or the newest version of packs without releases.
+    pub fn pick(&self, wanted: Option<&str>) -> Result<&VersionHeader> {
+        if let Some(wanted) = wanted {
+            return self
+                .versions
+                .iter()
+                .find(|v| v.name == wanted || v.id.to_string() == wanted)
+                .ok_or_else(|| anyhow!("{} has no version {}", self.name, wanted));
+        }
+        let newest = |release: bool| {
+            self.versions
+                .iter()
+                .filter(|v| !release || v.kind.eq_ignore_ascii_case("release"))
+                .max_by_key(|v| (v.updated, v.id))
+        };
+        newest(true).or_else(|| newest(false)).ok_or_else(|| anyhow!("{} has no versions", self.name))
+    }
+}
+
+/// The pack version as this tool keeps a pack: the manifest, with an entry
+/// for every file FTB mirrors from CurseForge, and the lockfile pinning
+/// them. Files FTB hosts itself, mostly configs, become overrides.
+pub struct Import {
+    pub manifest: Manifest,
+    pub lock: Lockfile,
+    /// The files FTB hosts itself.
+    pub overrides: Vec<File>,
+}
+
+pub fn convert(name: &str, version: Version) -> Import {
+    let target = |kind: &str| version.targets.iter().find(|t| t.kind == kind);
+    let minecraft = target("game").map(|t| t.version.clone());
+    let loader = target("modloader").map(|t| format!("{}-{}", t.name.to_lowercase(), t.version));
+    let mut import = Import {
+        manifest: Manifest {
+            format_version: manifest::FORMAT_VERSION,
+            extends: None,
+            pack: PackHeader { name: name.to_string(), version: version.name.clone(), minecraft, loader, ..Default::default() },
+            remove: Vec::new(),
+            mods: Vec::new(),
+        },
+        lock: Lockfile::default(),
+        overrides: Vec::new(),
+    };
+    for file in version.files {
+        let Some(curseforge) = &file.curseforge else {
+            import.overrides.push(file);
+            continue;
+        };
+        let side = match (file.clientonly, file.serveronly) {
+            (true, false) => Some("client"),
+            (false, true) => Some("server"),
+            _ => None,
+        };
+        let mut entry = Mod {
+            filename: file.name.clone(),
+            name: Path::new(&file.name).file_stem().map_or(file.name.clone(), |s| s.to_string_lossy().to_string()),
+            url: Some(format!("https://www.curseforge.com/projects/{}", curseforge.project)),
+            version: file.version.clone(),
+            when: side.map(|side| Condition { side: Some(side.to_string()), ..Default::default() }),
+            tags: match file.optional {
+                true => vec![OPTIONAL_TAG.to_string()],
+                false => Vec::new(),
+            },
+            ..Default::default()
+        };
+        // files in the game directory itself are routed to it as `.`
+        entry.route(match file.dir() {
+            "" => ".",
+            dir => dir,
+        });
+        import.lock.files.insert(
+            file.key(),
+            LockedFile {
+                source: format!("curseforge:{}:{}", curseforge.project, curseforge.file),
+                hashes: Multihash::from([(Algorithm::Sha1, file.sha1.to_lowercase())]),
+            },
+        );
+        import.manifest.mods.push(entry);
+    }
+    if import.manifest.mods.iter().any(|m| m.tags.iter().any(|t| t == OPTIONAL_TAG)) {
+        import.manifest.pack.opt_in_tags.push(OPTIONAL_TAG.to_string());
+    }
+    import
+}
+
+/// Downloads a file FTB hosts itself into the overrides of the instance,
+/// checked against the sha1 FTB published for it.
+pub fn download_override(base_dir: &Path, file: &File) -> Result<()> {
+    let dest = base_dir.join(OVERRIDES_DIR).join(manifest::contained(&file.key(), &file.name)?);
+    let response = http::files()
+        .get(&file.url)
+        .send_captured()
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("failed to download {} from {}", file.name, file.url))?;
+    let body = http::decoded(response)?;
+    let sha1 = Algorithm::Sha1.bytes(&body);
+    if !sha1.eq_ignore_ascii_case(&file.sha1) {
+        return Err(anyhow!("sha1 of {} is {}, FTB says {}", file.name, sha1, file.sha1));
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&dest, body).with_context(|| format!("failed to write {}", dest.display()))
+}
@@ -0,0 +1,77 @@
+use super::hash::Cache;
+use super::log::STATE_DIR;
+use super::state::{self, State};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where an imported hash set is kept, checked by every `verify` after.
+pub fn path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("golden.json")
+}
+
+/// The hashes of every file of an instance that verified, exported so other
+/// machines can confirm theirs match it byte for byte, e.g. the clients of
+/// a tournament.
+#[derive(Serialize, Deserialize)]
+pub struct Golden {
+    pub created_at: DateTime<Utc>,
+    /// Name and version of the pack the instance ran.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack: Option<String>,
+    /// sha256 of the files, by path relative to the game directory.
+    pub files: BTreeMap<String, String>,
+}
+
+impl Golden {
+    /// Hashes the files the state records, overrides and unpacked files
+    /// included, and every file of the mods directory, so a jar dropped in
+    /// beside the pack's shows up as well.
+    pub fn of(game_dir: &Path, mods_dir: &Path, state: &State, cache: &mut Cache, pack: Option<String>) -> Result<Golden> {
+        let mut paths = state.paths(game_dir);
+        if let Ok(entries) = fs::read_dir(mods_dir) {
+            paths.extend(entries.filter_map(|e| e.ok().map(|e| e.path())));
+        }
+        paths.retain(|p| p.is_file());
+        paths.sort();
+        paths.dedup();
+        let hashes = cache.rehash_all(&paths)?;
+        Ok(Golden {
+            created_at: Utc::now(),
+            pack,
+            files: hashes.into_iter().map(|(path, sha256)| (state::key(game_dir, &path), sha256)).collect(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Golden> {
+        let contents = fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("{} is not an exported hash set", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// How `current` differs from the hash set, one line per file missing,
+    /// changed or not in it.
+    pub fn differences(&self, current: &Golden) -> Vec<String> {
+        let mut found = Vec::new();
+        for (key, sha256) in self.files.iter() {
+            match current.files.get(key) {
+                None => found.push(format!("{} is missing", key)),
+                Some(other) if other != sha256 => found.push(format!("{} differs from the golden instance's", key)),
+                Some(_) => {}
+            }
+        }
+        for key in current.files.keys().filter(|k| !self.files.contains_key(*k)) {
+            found.push(format!("{} is not on the golden instance", key));
+        }
+        found
+    }
+}
@@ -0,0 +1,205 @@
+use super::events::{self, Event};
+use super::log::STATE_DIR;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+const CACHE_FILE: &str = "hashes.json";
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn sha256_bytes(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+pub fn sha1_file(path: &Path) -> Result<String> {
+    Algorithm::Sha1.file(path)
+}
+
+/// Digests files are checked with. Providers each publish their own, sha1
+/// on CurseForge, sha512 on Modrinth and whatever a Maven repository has, so
+/// a file may be known by any of them.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// The digests of one file by algorithm, lowercase hex.
+pub type Multihash = BTreeMap<Algorithm, String>;
+
+fn digest_file<D: Digest + io::Write>(path: &Path) -> Result<String> {
+    let mut hasher = D::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+impl Algorithm {
+    /// Strongest first.
+    pub const ALL: [Algorithm; 3] = [Algorithm::Sha512, Algorithm::Sha256, Algorithm::Sha1];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    pub fn bytes(self, bytes: &[u8]) -> String {
+        match self {
+            Algorithm::Sha1 => to_hex(&sha1::Sha1::digest(bytes)),
+            Algorithm::Sha256 => to_hex(&Sha256::digest(bytes)),
+            Algorithm::Sha512 => to_hex(&Sha512::digest(bytes)),
+        }
+    }
+
+    pub fn file(self, path: &Path) -> Result<String> {
+        match self {
+            Algorithm::Sha1 => digest_file::<sha1::Sha1>(path),
+            Algorithm::Sha256 => digest_file::<Sha256>(path),
+            Algorithm::Sha512 => digest_file::<Sha512>(path),
+        }
+    }
+}
+
+/// Checks `path` against every digest `expected` has, returning them and
+/// its sha256. `from` says where the digests came from in the error.
+pub fn check(path: &Path, expected: &Multihash, from: &str) -> Result<Multihash> {
+    let mut hashes = Multihash::new();
+    for (&algorithm, digest) in expected {
+        let actual = algorithm.file(path)?;
+        if !actual.eq_ignore_ascii_case(digest) {
+            return Err(anyhow!("{} of {} is {}, {} says {}", algorithm.name(), path.display(), actual, from, digest));
+        }
+        hashes.insert(algorithm, actual);
+    }
+    if let std::collections::btree_map::Entry::Vacant(sha256) = hashes.entry(Algorithm::Sha256) {
+        sha256.insert(sha256_file(path)?);
+    }
+    Ok(hashes)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Cached {
+    size: u64,
+    mtime: u128,
+    sha256: String,
+}
+
+/// Hashes of files in an instance remembered by path, size and modification
+/// time so unchanged files are not read again on the next run.
+pub struct Cache {
+    path: PathBuf,
+    entries: HashMap<String, Cached>,
+}
+
+fn stat(path: &Path) -> Result<(u64, u128)> {
+    let meta = fs::metadata(path)?;
+    let mtime = meta.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    Ok((meta.len(), mtime))
+}
+
+impl Cache {
+    /// Loads the cache of an instance, starting empty when it is missing or
+    /// unreadable.
+    pub fn load(base_dir: &Path) -> Cache {
+        let path = base_dir.join(STATE_DIR).join(CACHE_FILE);
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        Cache { path, entries }
+    }
+
+    /// Writes the cache back, forgetting files that no longer exist.
+    pub fn save(&mut self) -> Result<()> {
+        self.entries.retain(|p, _| Path::new(p).is_file());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string(&self.entries)?)?;
+        Ok(())
+    }
+
+    /// How many of `paths` have no cached hash or one of an older version.
+    pub fn stale(&self, paths: &[PathBuf]) -> usize {
+        paths
+            .iter()
+            .filter(|path| match (stat(path), self.entries.get(&path.to_string_lossy().to_string())) {
+                (Ok((size, mtime)), Some(c)) => c.size != size || c.mtime != mtime,
+                _ => true,
+            })
+            .count()
+    }
+
+    /// [`Cache::hash_all`] reading every file again, whatever is cached.
+    pub fn rehash_all(&mut self, paths: &[PathBuf]) -> Result<HashMap<PathBuf, String>> {
+        for path in paths {
+            self.entries.remove(&path.to_string_lossy().to_string());
+        }
+        self.hash_all(paths)
+    }
+
+    /// Hashes `paths`, reading files whose cached entry is stale on all
+    /// available cores.
+    pub fn hash_all(&mut self, paths: &[PathBuf]) -> Result<HashMap<PathBuf, String>> {
+        let mut hashes = HashMap::new();
+        let mut stale = Vec::new();
+        for path in paths {
+            let (size, mtime) = stat(path)?;
+            match self.entries.get(&path.to_string_lossy().to_string()) {
+                Some(c) if c.size == size && c.mtime == mtime => {
+                    events::emit(Event::CacheHit { path });
+                    hashes.insert(path.clone(), c.sha256.clone());
+                }
+                _ => {
+                    events::emit(Event::CacheMiss { path });
+                    stale.push((path, size, mtime));
+                }
+            }
+        }
+
+        let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(stale.len());
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::new());
+        thread::scope(|s| {
+            for _ in 0..workers {
+                s.spawn(|| {
+                    while let Some((path, size, mtime)) = stale.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let hashed = sha256_file(path).map_err(|e| anyhow!("failed to hash {}: {}", path.display(), e));
+                        results.lock().expect("hash results lock poisoned").push((*path, *size, *mtime, hashed));
+                    }
+                });
+            }
+        });
+
+        for (path, size, mtime, hashed) in results.into_inner().expect("hash results lock poisoned") {
+            let sha256 = hashed?;
+            self.entries.insert(
+                path.to_string_lossy().to_string(),
+                Cached { size, mtime, sha256: sha256.clone() },
+            );
+            hashes.insert(path.clone(), sha256);
+        }
+        Ok(hashes)
+    }
+}
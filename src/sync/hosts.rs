@@ -0,0 +1,171 @@
+use super::events::{self, Event, Subscriber};
+use super::http::RequestExt;
+use super::log::log_to_file;
+use super::warnings;
+use crate::i18n::t;
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Downloads running at once unless `--jobs` says otherwise.
+pub const DEFAULT_JOBS: usize = 4;
+/// Attempts of a request before its failure counts.
+const ATTEMPTS: u32 = 3;
+/// Longest pause a server's `Retry-After` is followed for, longer ones
+/// fail the request rather than stall the sync.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+/// Host of a url, the url itself when it has none.
+pub fn of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Sends the request `request` builds, trying again after timeouts,
+/// throttling and server errors with a growing pause, or as long as the
+/// server's `Retry-After` asks.
+pub fn send_retrying(
+    request: impl Fn() -> reqwest::blocking::RequestBuilder,
+    url: &str,
+) -> reqwest::Result<reqwest::blocking::Response> {
+    let mut attempt = 1;
+    loop {
+        let result = request().send_captured();
+        let (error, retry_after) = match &result {
+            Ok(resp) if resp.status().is_server_error() || resp.status().as_u16() == 429 => {
+                (Some(format!("the server answered {}", resp.status())), retry_after(resp))
+            }
+            Ok(_) => (None, None),
+            Err(e) => (Some(e.to_string()), None),
+        };
+        let pause = retry_after.unwrap_or(Duration::from_secs(1 << attempt));
+        match error {
+            Some(error) if attempt < ATTEMPTS && pause <= MAX_RETRY_AFTER => {
+                let _ = log_to_file(&format!("[WARN]  attempt {} of {} failed, {}, trying again in {}s", attempt, url, error, pause.as_secs()));
+                events::emit(Event::Retry { url, attempt, error: &error });
+                thread::sleep(pause);
+                attempt += 1;
+            }
+            _ => return result,
+        }
+    }
+}
+
+/// The pause `Retry-After` asks for, in seconds or until a date.
+fn retry_after(response: &reqwest::blocking::Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+struct Host {
+    limit: usize,
+    active: usize,
+    /// Downloads that succeeded since the limit last changed.
+    succeeded: usize,
+    /// Whether the host was reported as struggling already.
+    warned: bool,
+}
+
+/// Caps the downloads running against each host. Every host starts at the
+/// full job count, is halved whenever a request to it fails or is throttled
+/// and grows back by one after each round of successful downloads.
+pub struct Limiter {
+    jobs: usize,
+    hosts: Mutex<HashMap<String, Host>>,
+    freed: Condvar,
+}
+
+/// A running download, giving its place back when dropped.
+pub struct Slot<'a> {
+    limiter: &'a Limiter,
+    host: String,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut hosts = self.limiter.hosts.lock().expect("hosts lock poisoned");
+        if let Some(host) = hosts.get_mut(&self.host) {
+            host.active -= 1;
+        }
+        self.limiter.freed.notify_all();
+    }
+}
+
+impl Limiter {
+    pub fn new(jobs: usize) -> Limiter {
+        Limiter {
+            jobs: jobs.max(1),
+            hosts: Mutex::new(HashMap::new()),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Waits until another download from `host` may start.
+    pub fn acquire(&self, host: &str) -> Slot<'_> {
+        let mut hosts = self.hosts.lock().expect("hosts lock poisoned");
+        loop {
+            let state = hosts.entry(host.to_string()).or_insert(Host {
+                limit: self.jobs,
+                active: 0,
+                succeeded: 0,
+                warned: false,
+            });
+            if state.active < state.limit {
+                state.active += 1;
+                break;
+            }
+            hosts = self.freed.wait(hosts).expect("hosts lock poisoned");
+        }
+        Slot {
+            limiter: self,
+            host: host.to_string(),
+        }
+    }
+
+    fn back_off(&self, host: &str) {
+        let mut hosts = self.hosts.lock().expect("hosts lock poisoned");
+        let Some(state) = hosts.get_mut(host) else {
+            return;
+        };
+        state.succeeded = 0;
+        if state.limit > 1 {
+            state.limit /= 2;
+            let _ = log_to_file(&format!("[WARN] {} is struggling, downloading {} files at once from it", host, state.limit));
+            if state.limit == 1 && !state.warned {
+                state.warned = true;
+                warnings::warn(warnings::Code::HostStruggled, &t!("sync-host-throttled", host = host));
+            }
+        }
+    }
+
+    fn succeeded(&self, host: &str) {
+        let mut hosts = self.hosts.lock().expect("hosts lock poisoned");
+        let Some(state) = hosts.get_mut(host) else {
+            return;
+        };
+        state.succeeded += 1;
+        if state.limit < self.jobs && state.succeeded >= state.limit {
+            state.limit += 1;
+            state.succeeded = 0;
+            self.freed.notify_all();
+        }
+    }
+}
+
+impl Subscriber for Limiter {
+    fn event(&self, event: &Event) {
+        match event {
+            Event::Retry { url, .. } => self.back_off(&of(url)),
+            Event::DownloadFailed { host, .. } => self.back_off(host),
+            Event::Downloaded { host, .. } => self.succeeded(host),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use super::capture;
+use super::dns::Upstream;
+use super::hosts;
+use super::log_to_file;
+use super::settings::{IpMode, UserSettings};
+use anyhow::{anyhow, Context, Result};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long resolved addresses are reused.
+const DNS_TTL: Duration = Duration::from_secs(300);
+/// Hosts CurseForge redirects downloads to.
+pub const CURSEFORGE_CDN: [&str; 2] = ["https://edge.forgecdn.net/", "https://mediafilez.forgecdn.net/"];
+/// MiB of downloaded files held in memory at once unless the instance says
+/// otherwise.
+pub const DEFAULT_BUFFER_LIMIT_MB: u64 = 256;
+/// Bytes counted for a file of unknown size until it is read.
+const UNKNOWN_LENGTH: u64 = 16 * 1024 * 1024;
+/// MiB from which a file is downloaded over several connections at once
+/// unless the instance says otherwise.
+pub const DEFAULT_SPLIT_MB: u64 = 128;
+/// Connections a large file is downloaded over unless the instance says
+/// otherwise.
+pub const DEFAULT_CONNECTIONS: usize = 4;
+/// Hosts [`warm_up`] connects to at once.
+const WARM_UP_THREADS: usize = 8;
+
+/// Sending requests so `--debug-http` records them and `--replay` answers
+/// them.
+pub trait RequestExt {
+    /// Sends the request, recording it with its response or answering it
+    /// from the recording being replayed.
+    fn send_captured(self) -> reqwest::Result<Response>;
+}
+
+impl RequestExt for RequestBuilder {
+    fn send_captured(self) -> reqwest::Result<Response> {
+        capture::send(self)
+    }
+}
+
+/// Resolver remembering addresses for [`DNS_TTL`], so a host is looked up
+/// once for all the downloads of a run rather than once per file. Hosts
+/// are looked up where the user settings say, the system by default, and
+/// their addresses ordered by the family they prefer.
+struct CachingResolver {
+    upstream: Upstream,
+    ip: Option<IpMode>,
+    cache: Mutex<HashMap<String, (Instant, Vec<SocketAddr>)>>,
+}
+
+impl CachingResolver {
+    fn cached(&self, host: &str) -> Option<Vec<SocketAddr>> {
+        let cached = self.cache.lock().expect("dns cache lock poisoned").get(host).cloned();
+        cached.filter(|(at, _)| at.elapsed() < DNS_TTL).map(|(_, addrs)| addrs)
+    }
+
+    fn lookup(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        if let Some(addrs) = self.cached(host) {
+            return Ok(addrs);
+        }
+        let mut addrs = self.upstream.lookup(host)?;
+        if let Some(ip) = self.ip {
+            addrs = ip.order(addrs);
+            if addrs.is_empty() {
+                return Err(io::Error::other(format!("{} has no address of the family the settings allow", host)));
+            }
+        }
+        self.cache
+            .lock()
+            .expect("dns cache lock poisoned")
+            .insert(host.to_string(), (Instant::now(), addrs.clone()));
+        Ok(addrs)
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        if let Some(addrs) = self.cached(name.as_str()) {
+            return Box::pin(async move { Ok(Box::new(addrs.into_iter()) as Addrs) });
+        }
+        // a lookup blocks, so it is left to the runtime's blocking threads
+        // rather than holding up the connections of other requests; the
+        // clients all share the one resolver
+        let resolver = resolver();
+        Box::pin(async move {
+            let addrs = tokio::task::spawn_blocking(move || resolver.lookup(name.as_str()))
+                .await
+                .map_err(io::Error::other)??;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn resolver() -> Arc<CachingResolver> {
+    static RESOLVER: OnceLock<Arc<CachingResolver>> = OnceLock::new();
+    RESOLVER
+        .get_or_init(|| {
+            let settings = user().dns.clone().unwrap_or_default();
+            let upstream = Upstream::of(&settings).unwrap_or_else(|e| {
+                let _ = log_to_file(&format!("[WARN] looking hosts up with the system resolver: {:#}", e));
+                Upstream::System
+            });
+            Arc::new(CachingResolver {
+                upstream,
+                ip: user().network.as_ref().and_then(|n| n.ip),
+                cache: Mutex::default(),
+            })
+        })
+        .clone()
+}
+
+/// Target host and port of the SRV record `name`, looked up where the
+/// clients look their hosts up.
+pub fn srv(name: &str) -> Result<Option<(String, u16)>> {
+    resolver().upstream.srv(name)
+}
+
+/// The user settings, read once for the clients of a run.
+fn user() -> &'static UserSettings {
+    static SETTINGS: OnceLock<UserSettings> = OnceLock::new();
+    SETTINGS.get_or_init(|| UserSettings::load().unwrap_or_default())
+}
+
+/// `builder` connecting from the local address or network interface the
+/// user settings name. Every client of the tool is built with it.
+pub fn bind(builder: ClientBuilder) -> ClientBuilder {
+    // replayed runs answer requests from the recording, one sent past it
+    // goes to a proxy that isn't there rather than to the network
+    let builder = match capture::replaying() {
+        true => builder.proxy(reqwest::Proxy::all("http://127.0.0.1:9").expect("the proxy url is valid")),
+        false => builder,
+    };
+    let Some(bind) = user().network.as_ref().and_then(|n| n.bind.as_deref()) else {
+        return builder;
+    };
+    if let Ok(addr) = bind.parse::<IpAddr>() {
+        return builder.local_address(addr);
+    }
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    let builder = builder.interface(bind);
+    #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+    let _ = log_to_file(&format!("[WARN] binding to interface {} needs Linux, give its address instead", bind));
+    builder
+}
+
+/// Client for API and metadata calls. It asks for compressed responses,
+/// decoded by [`json`], and speaks HTTP/2 with hosts offering it so the
+/// calls of parallel downloads share one connection.
+pub fn api() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| build("gzip, deflate, zstd"))
+}
+
+/// Client for downloading files. Jars and archives are compressed already,
+/// so servers are asked to send them as they are stored.
+pub fn files() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| build("identity"))
+}
+
+fn build(encoding: &'static str) -> Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static(encoding));
+    bind(Client::builder())
+        .default_headers(headers)
+        .http2_adaptive_window(true)
+        .dns_resolver(resolver())
+        .build()
+        .expect("the http client settings are valid")
+}
+
+/// The body of an [`api`] response with its content encoding undone.
+pub fn decoded(response: Response) -> Result<Vec<u8>> {
+    let encoding = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|e| e.to_str().ok())
+        .map(|e| e.trim().to_lowercase());
+    let url = response.url().clone();
+    let body = response.bytes()?;
+    let mut decoded = Vec::new();
+    match encoding.as_deref() {
+        None | Some("identity") => {
+            capture::body(url.as_str(), &body);
+            return Ok(body.to_vec());
+        }
+        Some("gzip") | Some("x-gzip") => GzDecoder::new(body.as_ref()).read_to_end(&mut decoded).map(|_| ()),
+        Some("deflate") => ZlibDecoder::new(body.as_ref()).read_to_end(&mut decoded).map(|_| ()),
+        Some("zstd") => zstd::stream::copy_decode(body.as_ref(), &mut decoded),
+        Some(other) => return Err(anyhow!("{} answered with unsupported content encoding {}", url, other)),
+    }
+    .with_context(|| format!("failed to decode the response of {}", url))?;
+    capture::body(url.as_str(), &decoded);
+    Ok(decoded)
+}
+
+/// Bytes downloaded files may take up in memory at once, across threads.
+struct Budget {
+    limit: u64,
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+static BUDGET: OnceLock<Budget> = OnceLock::new();
+
+fn budget() -> &'static Budget {
+    BUDGET.get_or_init(|| Budget {
+        limit: DEFAULT_BUFFER_LIMIT_MB * 1024 * 1024,
+        used: Mutex::new(0),
+        freed: Condvar::new(),
+    })
+}
+
+/// Limits the memory downloaded files take up at once to `mb` MiB, for the
+/// rest of the run, so small machines don't run out while several large
+/// jars download. Has no effect once a file was downloaded.
+pub fn limit_buffers(mb: u64) {
+    let _ = BUDGET.set(Budget {
+        limit: mb.max(1) * 1024 * 1024,
+        used: Mutex::new(0),
+        freed: Condvar::new(),
+    });
+}
+
+/// Memory counted against the [`Budget`], given back when dropped.
+struct Reservation {
+    bytes: u64,
+}
+
+impl Reservation {
+    /// Waits until `bytes` fit in the budget. A file larger than all of it
+    /// waits until nothing else is held, then goes alone.
+    fn take(bytes: u64) -> Reservation {
+        let budget = budget();
+        let mut used = budget.used.lock().expect("download budget lock poisoned");
+        while *used > 0 && *used + bytes > budget.limit {
+            used = budget.freed.wait(used).expect("download budget lock poisoned");
+        }
+        *used += bytes;
+        Reservation { bytes }
+    }
+
+    /// Counts `bytes` more without waiting, the file turned out larger.
+    fn grow(&mut self, bytes: u64) {
+        *budget().used.lock().expect("download budget lock poisoned") += bytes;
+        self.bytes += bytes;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let budget = budget();
+        *budget.used.lock().expect("download budget lock poisoned") -= self.bytes;
+        budget.freed.notify_all();
+    }
+}
+
+/// A downloaded file in memory, counted against the limit of
+/// [`limit_buffers`] until it is dropped.
+pub struct Buffered {
+    bytes: Vec<u8>,
+    _reservation: Reservation,
+}
+
+impl std::ops::Deref for Buffered {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsRef<[u8]> for Buffered {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// The body of a file download like [`decoded`], read once the memory it
+/// takes fits in the limit of [`limit_buffers`].
+pub fn buffered(response: Response) -> Result<Buffered> {
+    let mut reservation = Reservation::take(response.content_length().unwrap_or(UNKNOWN_LENGTH));
+    let bytes = decoded(response)?;
+    if let Some(more) = (bytes.len() as u64).checked_sub(reservation.bytes).filter(|&m| m > 0) {
+        reservation.grow(more);
+    }
+    Ok(Buffered { bytes, _reservation: reservation })
+}
+
+/// From which size and over how many connections files are split.
+struct Splitting {
+    min_bytes: u64,
+    connections: usize,
+}
+
+static SPLITTING: OnceLock<Splitting> = OnceLock::new();
+
+fn splitting() -> &'static Splitting {
+    SPLITTING.get_or_init(|| Splitting { min_bytes: DEFAULT_SPLIT_MB * 1024 * 1024, connections: DEFAULT_CONNECTIONS })
+}
+
+/// Downloads files of at least `min_mb` MiB over `connections` connections
+/// at once for the rest of the run, 1 turning it off. Has no effect once a
+/// file was downloaded.
+pub fn split_downloads(min_mb: u64, connections: usize) {
+    let _ = SPLITTING.set(Splitting { min_bytes: min_mb.saturating_mul(1024 * 1024), connections: connections.max(1) });
+}
+
+/// Downloads the file of `size` bytes at `url` to `dest` in ranges over
+/// several connections when it is large enough, written to the file as
+/// they arrive rather than held in memory. Hands back `None` to download it
+/// in one piece when it is small or the server doesn't answer ranges. The
+/// first range follows the redirects, the others go to where it ended up.
+pub fn split(url: &str, headers: &HeaderMap, size: Option<u64>, dest: &Path) -> Result<Option<u64>> {
+    let splitting = splitting();
+    let Some(size) = size.filter(|&s| splitting.connections > 1 && s >= splitting.min_bytes.max(1)) else {
+        return Ok(None);
+    };
+    let chunk = size.div_ceil(splitting.connections as u64);
+    let ranges: Vec<(u64, u64)> = (0..size).step_by(chunk as usize).map(|start| (start, (start + chunk).min(size) - 1)).collect();
+    let range = |client: &Client, url: &str, (start, end): (u64, u64)| {
+        hosts::send_retrying(|| client.get(url).headers(headers.clone()).header(RANGE, format!("bytes={}-{}", start, end)), url)
+    };
+    let client = files();
+    let first = range(client, url, ranges[0]).with_context(|| format!("request to {} failed", url))?;
+    if first.status() != StatusCode::PARTIAL_CONTENT {
+        let _ = log_to_file(&format!("[INFO]  {} answered {} to a range, downloading it in one piece", url, first.status()));
+        return Ok(None);
+    }
+    let landed = first.url().to_string();
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::File::create(dest)?.set_len(size)?;
+    let write = |mut response: Response, (start, end): (u64, u64)| -> Result<()> {
+        let answered = response.headers().get(CONTENT_RANGE).and_then(|r| r.to_str().ok()).unwrap_or_default().to_string();
+        if response.status() != StatusCode::PARTIAL_CONTENT || !answered.starts_with(&format!("bytes {}-{}/", start, end)) {
+            return Err(anyhow!("{} answered {} {} to bytes {}-{}", landed, response.status(), answered, start, end));
+        }
+        let mut file = OpenOptions::new().write(true).open(dest)?;
+        file.seek(SeekFrom::Start(start))?;
+        let written = io::copy(&mut response, &mut file)?;
+        if written != end + 1 - start {
+            return Err(anyhow!("got {} of the {} bytes {}-{} of {}, the transfer was cut short", written, end + 1 - start, start, end, landed));
+        }
+        Ok(())
+    };
+    let (landed, write) = (&landed, &write);
+    let filled = thread::scope(|scope| {
+        let rest: Vec<_> = ranges[1..]
+            .iter()
+            .map(|&r| scope.spawn(move || write(range(client, landed, r).with_context(|| format!("request to {} failed", landed))?, r)))
+            .collect();
+        let mut result = write(first, ranges[0]);
+        for handle in rest {
+            let written = handle.join().unwrap_or_else(|_| Err(anyhow!("a download thread panicked")));
+            result = result.and(written);
+        }
+        result
+    });
+    if let Err(e) = filled {
+        // the file has its full size with holes where ranges are missing,
+        // it must not pass for downloaded
+        let _ = fs::remove_file(dest);
+        return Err(e);
+    }
+    let _ = log_to_file(&format!("[INFO]  downloaded {} bytes of {} over {} connections", size, landed, ranges.len()));
+    Ok(Some(size))
+}
+
+/// Parses an [`api`] response as JSON.
+pub fn json<T: DeserializeOwned>(response: Response) -> Result<T> {
+    let url = response.url().clone();
+    serde_json::from_slice(&decoded(response)?).with_context(|| format!("unexpected response from {}", url))
+}
+
+/// Resolves the hosts of `urls` and opens a connection to each ahead of a
+/// batch of downloads, [`WARM_UP_THREADS`] at a time. Failures are left for
+/// the downloads to report.
+pub fn warm_up(urls: &[String]) {
+    if capture::replaying() {
+        return;
+    }
+    let started = Instant::now();
+    let next = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for _ in 0..WARM_UP_THREADS.min(urls.len()) {
+            s.spawn(|| {
+                while let Some(url) = urls.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    warm(url);
+                }
+            });
+        }
+    });
+    let _ = log_to_file(&format!(
+        "[INFO] connected to {} hosts in {:.1}s",
+        urls.len(),
+        started.elapsed().as_secs_f64()
+    ));
+}
+
+fn warm(url: &str) {
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return;
+    };
+    let Some(host) = parsed.host_str() else {
+        return;
+    };
+    if let Err(e) = resolver().lookup(host) {
+        let _ = log_to_file(&format!("[WARN] failed to resolve {}: {}", host, e));
+        return;
+    }
+    for client in [api(), files()] {
+        let _ = client.head(parsed.clone()).timeout(Duration::from_secs(10)).send();
+    }
+}
@@ -0,0 +1,149 @@
+use super::log_to_file;
+use anyhow::Result;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Bytes of the request line and headers read of a client.
+const MAX_HEAD_BYTES: u64 = 8192;
+/// How long a client may take to send its request, or to take the next
+/// part of an answer, before it is dropped.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The request line and headers of a request.
+pub struct Head {
+    pub method: String,
+    /// Path asked for, with its query.
+    pub path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    /// The value of the header `name`, the first one sent.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// The token of an `Authorization: Bearer` header.
+    pub fn bearer(&self) -> Option<String> {
+        self.header("authorization")?.strip_prefix("Bearer ").map(|t| t.trim().to_string())
+    }
+
+    /// The length of the body sent along, none without one.
+    pub fn content_length(&self) -> u64 {
+        self.header("content-length").and_then(|l| l.trim().parse().ok()).unwrap_or(0)
+    }
+}
+
+/// Why the head of a request could not be read.
+pub enum Refused {
+    /// Its request line was not one.
+    BadRequest,
+    /// It went over [`MAX_HEAD_BYTES`].
+    TooLarge,
+}
+
+/// Reads the head of the request on `stream`, handing back what of the
+/// stream follows it for the body.
+pub fn read_head(stream: &TcpStream) -> Result<(std::result::Result<Head, Refused>, BufReader<TcpStream>)> {
+    let mut head = BufReader::new(stream.try_clone()?).take(MAX_HEAD_BYTES);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    let mut headers = Vec::new();
+    let mut header = String::new();
+    while head.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        header.clear();
+    }
+    let limit_reached = head.limit() == 0;
+    let reader = head.into_inner();
+    if limit_reached {
+        return Ok((Err(Refused::TooLarge), reader));
+    }
+    let parsed = match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [method, path, _] => Ok(Head { method: method.to_string(), path: path.to_string(), headers }),
+        _ => Err(Refused::BadRequest),
+    };
+    Ok((parsed, reader))
+}
+
+/// Answers the clients of `listener` with `handle`, each on a thread of
+/// its own, until the listener fails. Clients that go quiet are dropped,
+/// and those beyond `max_connections` at once are asked to come back later
+/// without a thread of their own. `name` tells what is served in the log.
+pub fn serve<F>(listener: TcpListener, name: &'static str, max_connections: usize, handle: F)
+where
+    F: Fn(TcpStream) -> Result<()> + Send + Sync + 'static,
+{
+    let active = Arc::new(AtomicUsize::new(0));
+    let handle = Arc::new(handle);
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = log_to_file(&format!("[WARN] failed to accept a client of the {}: {}", name, e));
+                continue;
+            }
+        };
+        let _ = stream.set_read_timeout(Some(IDLE_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(IDLE_TIMEOUT));
+        if active.load(Ordering::SeqCst) >= max_connections {
+            let _ = write!(stream, "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            continue;
+        }
+        active.fetch_add(1, Ordering::SeqCst);
+        let (active, handle) = (active.clone(), handle.clone());
+        thread::spawn(move || {
+            if let Err(e) = handle(stream) {
+                let _ = log_to_file(&format!("[WARN] failed to serve the {}: {:#}", name, e));
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+/// Reads at most `length` bytes of the body following a head.
+pub fn body(reader: BufReader<TcpStream>, length: u64) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    reader.take(length).read_to_end(&mut body)?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The head `request` reads as, sent over a local connection.
+    fn read(request: &[u8]) -> std::result::Result<Head, Refused> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(request).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        read_head(&server).unwrap().0
+    }
+
+    #[test]
+    fn heads_are_parsed() {
+        let Ok(head) = read(b"GET /files/ab HTTP/1.1\r\nHost: a\r\nAuthorization: Bearer t0k\r\nContent-Length: 12\r\n\r\n") else {
+            panic!("expected the head to be read");
+        };
+        assert_eq!((head.method.as_str(), head.path.as_str()), ("GET", "/files/ab"));
+        assert_eq!(head.header("HOST"), Some("a"));
+        assert_eq!(head.bearer().as_deref(), Some("t0k"));
+        assert_eq!(head.content_length(), 12);
+    }
+
+    #[test]
+    fn oversized_and_broken_heads_are_refused() {
+        let mut request = b"GET / HTTP/1.1\r\n".to_vec();
+        request.extend(std::iter::repeat_n(b"X-Filler: aaaaaaaaaaaaaaaa\r\n".as_slice(), 400).flatten());
+        request.extend(b"\r\n");
+        assert!(matches!(read(&request), Err(Refused::TooLarge)));
+        assert!(matches!(read(b"nonsense\r\n\r\n"), Err(Refused::BadRequest)));
+    }
+}
@@ -0,0 +1,76 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Prism/packwiz metadata file from `mods/.index`.
+#[derive(Deserialize)]
+pub struct ModMeta {
+    pub filename: String,
+    update: Update,
+}
+
+impl ModMeta {
+    /// Metadata of a CurseForge file known from another source than packwiz.
+    pub fn curseforge(project_id: u64, file_id: Option<u64>, filename: &str) -> ModMeta {
+        ModMeta {
+            filename: filename.to_string(),
+            update: Update {
+                curseforge: CurseForge { project_id, file_id },
+            },
+        }
+    }
+
+    pub fn file_id(&self) -> Option<u64> {
+        self.update.curseforge.file_id
+    }
+}
+
+#[derive(Deserialize)]
+struct Update {
+    curseforge: CurseForge,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CurseForge {
+    project_id: u64,
+    #[serde(default)]
+    file_id: Option<u64>,
+}
+
+/// Loads every metadata file in `dir` keyed by CurseForge project id.
+pub fn load_mod_metadata(dir: impl AsRef<Path>) -> io::Result<HashMap<String, ModMeta>> {
+    let mut mods = HashMap::new();
+
+    let dir = dir.as_ref();
+
+    if !dir.exists() {
+        return Ok(mods);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if !path.extension().is_some_and(|e| e.eq_ignore_ascii_case("toml")) {
+            continue;
+        }
+
+        let contents = fs::read_to_string(&path)?;
+
+        let meta: ModMeta = match toml::from_str(&contents) {
+            Ok(m) => m,
+            Err(e) => {
+                eprintln!("Failed to parse {}: {e}", path.display());
+                continue;
+            }
+        };
+
+        let project_id = meta.update.curseforge.project_id;
+        mods.insert(project_id.to_string(), meta);
+    }
+
+    Ok(mods)
+}
@@ -0,0 +1,362 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek};
+use std::path::Path;
+
+/// The parts of a Fabric `fabric.mod.json` the tool cares about.
+#[derive(Deserialize)]
+pub struct FabricMeta {
+    pub id: String,
+    #[serde(default)]
+    pub version: String,
+    /// `client`, `server` or `*` for mods that load on both.
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// Mods that crash the game when installed alongside this one.
+    #[serde(default)]
+    pub breaks: HashMap<String, serde_json::Value>,
+    /// Mods that load but are known to misbehave with this one.
+    #[serde(default)]
+    pub conflicts: HashMap<String, serde_json::Value>,
+}
+
+/// Reads the Fabric metadata embedded in a jar, `None` for jars of other
+/// loaders.
+pub fn fabric_meta(path: &Path) -> Result<Option<FabricMeta>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).with_context(|| format!("{} is not a valid jar", path.display()))?;
+    let mut entry = match archive.by_name("fabric.mod.json") {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents)?;
+    let meta = serde_json::from_str(&contents)
+        .with_context(|| format!("invalid fabric.mod.json in {}", path.display()))?;
+    Ok(Some(meta))
+}
+
+/// The license a jar declares, from `fabric.mod.json` or the `license` of a
+/// Forge or NeoForge `mods.toml`. `None` when it declares none.
+pub fn license(path: &Path) -> Result<Option<String>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).with_context(|| format!("{} is not a valid jar", path.display()))?;
+    for name in ["fabric.mod.json", "quilt.mod.json", "META-INF/neoforge.mods.toml", "META-INF/mods.toml"] {
+        let mut entry = match archive.by_name(name) {
+            Ok(entry) => entry,
+            Err(zip::result::ZipError::FileNotFound) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents)?;
+        let license = match name.ends_with(".toml") {
+            true => toml::from_str::<toml::Value>(&contents)
+                .ok()
+                .and_then(|t| t.get("license").and_then(|l| l.as_str()).map(str::to_string)),
+            // a string or a list of them, Quilt nests it under quilt_loader.metadata
+            false => serde_json::from_str::<serde_json::Value>(&contents).ok().and_then(|meta| {
+                let license = meta.get("license").or_else(|| meta.pointer("/quilt_loader/metadata/license"))?;
+                match license {
+                    serde_json::Value::String(s) => Some(s.clone()),
+                    serde_json::Value::Array(list) => {
+                        let names: Vec<&str> = list.iter().filter_map(|l| l.as_str()).collect();
+                        Some(names.join(" OR ")).filter(|s| !s.is_empty())
+                    }
+                    _ => None,
+                }
+            }),
+        };
+        if let Some(license) = license.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()) {
+            return Ok(Some(license));
+        }
+    }
+    Ok(None)
+}
+
+/// Ids of the SPDX license list and of its exceptions.
+const SPDX_LICENSES: &str = include_str!("../../spdx/licenses.txt");
+const SPDX_EXCEPTIONS: &str = include_str!("../../spdx/exceptions.txt");
+
+/// Whether `id` is one of `list`, compared without regard to case as SPDX
+/// ids are.
+fn listed(list: &str, id: &str) -> bool {
+    list.lines().filter(|l| !l.starts_with('#')).any(|l| l.eq_ignore_ascii_case(id))
+}
+
+/// Whether a declared license is an SPDX expression such as `MIT` or
+/// `LGPL-3.0-only OR MIT`, rather than free text like `All rights reserved`.
+/// Its licenses must be on the SPDX license list or `LicenseRef-` ones, its
+/// exceptions on the exception list, joined by AND, OR and WITH.
+pub fn is_spdx_expression(license: &str) -> bool {
+    let spaced = license.replace('(', " ( ").replace(')', " ) ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    let mut at = 0;
+    spdx_or(&tokens, &mut at) && at == tokens.len()
+}
+
+/// `and-expression (OR and-expression)*` from `tokens[*at]` on.
+fn spdx_or(tokens: &[&str], at: &mut usize) -> bool {
+    if !spdx_and(tokens, at) {
+        return false;
+    }
+    while tokens.get(*at) == Some(&"OR") {
+        *at += 1;
+        if !spdx_and(tokens, at) {
+            return false;
+        }
+    }
+    true
+}
+
+/// `term (AND term)*`.
+fn spdx_and(tokens: &[&str], at: &mut usize) -> bool {
+    if !spdx_term(tokens, at) {
+        return false;
+    }
+    while tokens.get(*at) == Some(&"AND") {
+        *at += 1;
+        if !spdx_term(tokens, at) {
+            return false;
+        }
+    }
+    true
+}
+
+/// A parenthesized expression or `license[+] [WITH exception]`.
+fn spdx_term(tokens: &[&str], at: &mut usize) -> bool {
+    let Some(token) = tokens.get(*at) else {
+        return false;
+    };
+    *at += 1;
+    if *token == "(" {
+        let inner = spdx_or(tokens, at) && tokens.get(*at) == Some(&")");
+        *at += 1;
+        return inner;
+    }
+    let id = token.strip_suffix('+').unwrap_or(token);
+    let reference = id.split_once(':').map_or(id, |(document, id)| match document.starts_with("DocumentRef-") {
+        true => id,
+        false => "",
+    });
+    let known = match reference.strip_prefix("LicenseRef-") {
+        Some(name) => !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-')),
+        None => reference == id && listed(SPDX_LICENSES, id),
+    };
+    if !known {
+        return false;
+    }
+    if tokens.get(*at) == Some(&"WITH") {
+        *at += 2;
+        return tokens.get(*at - 1).is_some_and(|exception| listed(SPDX_EXCEPTIONS, exception));
+    }
+    true
+}
+
+/// Mod ids a jar declares, from `fabric.mod.json` and `quilt.mod.json` with
+/// the ids they provide, the `[[mods]]` of a Forge or NeoForge `mods.toml`
+/// or the `mcmod.info` of older Forge. Empty for plain libraries.
+pub fn mod_ids(path: &Path) -> Result<Vec<String>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).with_context(|| format!("{} is not a valid jar", path.display()))?;
+    declared(&mut archive)
+}
+
+/// Mod ids of the jars bundled inside a jar, Fabric and Quilt `jars` and
+/// the Jar-in-Jar of Forge and NeoForge, at any depth. Libraries bundled
+/// this way are loaded as mods of their own.
+pub fn bundled_ids(path: &Path) -> Result<Vec<String>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).with_context(|| format!("{} is not a valid jar", path.display()))?;
+    let mut ids = Vec::new();
+    nested_ids(&mut archive, 0, &mut ids)?;
+    ids.sort();
+    ids.dedup();
+    Ok(ids)
+}
+
+/// How deep bundled jars are looked into, real packs nest two levels.
+const MAX_NESTING: usize = 4;
+
+/// The jars bundled inside a jar at any depth, as their file names with
+/// their contents. Jars of the same name are kept once.
+pub fn bundled_jars(path: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).with_context(|| format!("{} is not a valid jar", path.display()))?;
+    let mut jars = Vec::new();
+    nested_jars(&mut archive, 0, &mut jars)?;
+    Ok(jars)
+}
+
+fn nested_jars<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, depth: usize, jars: &mut Vec<(String, Vec<u8>)>) -> Result<()> {
+    if depth >= MAX_NESTING {
+        return Ok(());
+    }
+    for name in nested(archive)? {
+        let mut bytes = Vec::new();
+        match archive.by_name(&name) {
+            Ok(mut entry) => entry.read_to_end(&mut bytes)?,
+            Err(zip::result::ZipError::FileNotFound) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let Ok(mut inner) = zip::ZipArchive::new(Cursor::new(bytes.clone())) else {
+            continue;
+        };
+        nested_jars(&mut inner, depth + 1, jars)?;
+        let file_name = name.rsplit('/').next().unwrap_or(&name).to_string();
+        if !file_name.is_empty() && !jars.iter().any(|(n, _)| *n == file_name) {
+            jars.push((file_name, bytes));
+        }
+    }
+    Ok(())
+}
+
+fn nested_ids<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, depth: usize, ids: &mut Vec<String>) -> Result<()> {
+    if depth >= MAX_NESTING {
+        return Ok(());
+    }
+    for name in nested(archive)? {
+        let mut bytes = Vec::new();
+        match archive.by_name(&name) {
+            Ok(mut entry) => entry.read_to_end(&mut bytes)?,
+            // metadata naming a jar that isn't there is the bundler's mistake
+            Err(zip::result::ZipError::FileNotFound) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let Ok(mut inner) = zip::ZipArchive::new(Cursor::new(bytes)) else {
+            continue;
+        };
+        ids.extend(declared(&mut inner)?);
+        nested_ids(&mut inner, depth + 1, ids)?;
+    }
+    Ok(())
+}
+
+/// Entry names of the jars an archive bundles.
+fn nested<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for (metadata, pointer) in [
+        ("fabric.mod.json", "/jars"),
+        ("quilt.mod.json", "/quilt_loader/jars"),
+        ("META-INF/jarjar/metadata.json", "/jars"),
+    ] {
+        let Some(contents) = read(archive, metadata)? else {
+            continue;
+        };
+        let meta = serde_json::from_str::<serde_json::Value>(&contents).ok();
+        let jars = meta.as_ref().and_then(|m| m.pointer(pointer)).and_then(|j| j.as_array());
+        for jar in jars.into_iter().flatten() {
+            // Quilt lists paths, Fabric objects with a `file`, Jar-in-Jar with a `path`
+            let name = jar.as_str().or_else(|| jar.get("file").or_else(|| jar.get("path")).and_then(|f| f.as_str()));
+            names.extend(name.map(str::to_string));
+        }
+    }
+    Ok(names)
+}
+
+fn read<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, name: &str) -> Result<Option<String>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents)?;
+    Ok(Some(contents))
+}
+
+fn declared<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for name in ["fabric.mod.json", "quilt.mod.json", "META-INF/neoforge.mods.toml", "META-INF/mods.toml", "mcmod.info"] {
+        let Some(contents) = read(archive, name)? else {
+            continue;
+        };
+        match name {
+            "META-INF/neoforge.mods.toml" | "META-INF/mods.toml" => {
+                let mods = toml::from_str::<toml::Value>(&contents).ok();
+                let mods = mods.as_ref().and_then(|t| t.get("mods")).and_then(|m| m.as_array());
+                for m in mods.into_iter().flatten() {
+                    ids.extend(m.get("modId").and_then(|id| id.as_str()).map(str::to_string));
+                }
+            }
+            // Forge before 1.13, a list of mods or one under `modList`
+            "mcmod.info" => {
+                let meta = serde_json::from_str::<serde_json::Value>(&contents).ok();
+                let mods = meta.as_ref().and_then(|m| m.as_array().or_else(|| m.get("modList")?.as_array()));
+                for m in mods.into_iter().flatten() {
+                    ids.extend(m.get("modid").and_then(|id| id.as_str()).map(str::to_string));
+                }
+            }
+            // the ids a mod provides stand for it as well
+            _ => {
+                let meta = serde_json::from_str::<serde_json::Value>(&contents).ok();
+                let Some(meta) = meta.as_ref().map(|m| m.get("quilt_loader").unwrap_or(m)) else {
+                    continue;
+                };
+                ids.extend(meta.get("id").and_then(|id| id.as_str()).map(str::to_string));
+                let provides = meta.get("provides").and_then(|p| p.as_array());
+                for provided in provides.into_iter().flatten() {
+                    let id = provided.as_str().or_else(|| provided.get("id")?.as_str());
+                    ids.extend(id.map(str::to_string));
+                }
+            }
+        }
+    }
+    ids.sort();
+    ids.dedup();
+    Ok(ids)
+}
+
+/// Classes a jar holds, as entry names like `com/example/Mod.class`.
+pub fn classes(path: &Path) -> Result<HashSet<String>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let archive = zip::ZipArchive::new(file).with_context(|| format!("{} is not a valid jar", path.display()))?;
+    Ok(archive
+        .file_names()
+        .filter_map(|n| n.ok())
+        .filter(|n| n.ends_with(".class"))
+        .map(|n| n.to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn ids_of(files: &[(&str, &str)]) -> Vec<String> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        let mut archive = zip::ZipArchive::new(zip.finish().unwrap()).unwrap();
+        declared(&mut archive).unwrap()
+    }
+
+    #[test]
+    fn mod_ids_of_every_loader_are_read() {
+        assert_eq!(ids_of(&[("fabric.mod.json", r#"{"id": "sodium", "provides": ["rubidium"]}"#)]), ["rubidium", "sodium"]);
+        assert_eq!(
+            ids_of(&[("quilt.mod.json", r#"{"quilt_loader": {"id": "qsl", "provides": [{"id": "quilted_fabric_api"}]}}"#)]),
+            ["qsl", "quilted_fabric_api"]
+        );
+        assert_eq!(ids_of(&[("META-INF/neoforge.mods.toml", "[[mods]]\nmodId = \"jei\"\n")]), ["jei"]);
+        assert_eq!(ids_of(&[("META-INF/mods.toml", "[[mods]]\nmodId = \"jei\"\n[[mods]]\nmodId = \"jei_api\"\n")]), ["jei", "jei_api"]);
+        assert_eq!(ids_of(&[("mcmod.info", r#"[{"modid": "journeymap"}]"#)]), ["journeymap"]);
+        assert_eq!(ids_of(&[("mcmod.info", r#"{"modListVersion": 2, "modList": [{"modid": "ic2"}]}"#)]), ["ic2"]);
+        assert!(ids_of(&[("assets/readme.txt", "")]).is_empty());
+    }
+
+    #[test]
+    fn spdx_expressions_are_told_from_free_text() {
+        for license in ["MIT", "mit", "LGPL-3.0-only OR MIT", "(MIT AND Apache-2.0) OR GPL-3.0+", "GPL-2.0-only WITH Classpath-exception-2.0", "LicenseRef-Custom"] {
+            assert!(is_spdx_expression(license), "{}", license);
+        }
+        for license in ["All rights reserved", "ARR", "Custom", "MIT AND", "(MIT", "MIT)", "MIT WITH Nothing", "MIT OR Whatever-1.0", ""] {
+            assert!(!is_spdx_expression(license), "{}", license);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use super::launcher::Launcher;
+use super::manifest::PackHeader;
+use super::version;
+use anyhow::{anyhow, Context, Result};
+use std::cmp::Ordering;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Java releases Minecraft moved to, newest first.
+const MINECRAFT_JAVA: &[(&str, u32)] = &[("1.20.5", 21), ("1.18", 17), ("1.17", 16)];
+
+/// Major Java version the pack needs: the one declared in its launch
+/// settings, otherwise the one its Minecraft version requires.
+pub fn required(pack: &PackHeader) -> Option<u32> {
+    if let Some(java) = pack.launch.as_ref().and_then(|l| l.java) {
+        return Some(java);
+    }
+    let minecraft = pack.minecraft.as_deref()?;
+    let java = MINECRAFT_JAVA
+        .iter()
+        .find(|(since, _)| version::compare(minecraft, since) != Ordering::Less)
+        .map_or(8, |(_, java)| *java);
+    Some(java)
+}
+
+pub struct Found {
+    pub path: PathBuf,
+    pub major: u32,
+}
+
+impl Found {
+    /// Where the java came from, for messages.
+    pub fn describe(&self) -> String {
+        match self.path.components().count() {
+            1 => "the java on PATH".to_string(),
+            _ => self.path.display().to_string(),
+        }
+    }
+}
+
+/// The java the instance runs with: the launcher's configured runtime,
+/// otherwise `JAVA_HOME` or the one on `PATH`.
+pub fn find(base_dir: &Path, launcher: Launcher) -> Result<Found> {
+    let path = launcher
+        .java_path(base_dir)
+        .or_else(|| env::var_os("JAVA_HOME").map(|home| PathBuf::from(home).join("bin").join("java")))
+        .filter(|p| p.is_file() || p.with_extension("exe").is_file())
+        .unwrap_or_else(|| PathBuf::from("java"));
+    let output = Command::new(&path)
+        .arg("-version")
+        .output()
+        .with_context(|| format!("failed to run {}, is java installed?", path.display()))?;
+    // java -version prints to stderr, e.g. `openjdk version "17.0.8" 2023-07-18`
+    let text = String::from_utf8_lossy(&output.stderr).to_string() + &String::from_utf8_lossy(&output.stdout);
+    let quoted = text
+        .split('"')
+        .nth(1)
+        .ok_or_else(|| anyhow!("could not read the version of {}", path.display()))?;
+    let mut parts = quoted.split(['.', '_', '-', '+']);
+    let major = match parts.next() {
+        Some("1") => parts.next(),
+        first => first,
+    }
+    .and_then(|p| p.parse::<u32>().ok())
+    .ok_or_else(|| anyhow!("could not parse java version {}", quoted))?;
+    Ok(Found { path, major })
+}
+
+/// What is wrong with the instance's java for the pack.
+pub enum Problem {
+    /// No java could be run.
+    Missing(String),
+    /// Older than the pack needs.
+    TooOld(String),
+    /// Newer than packs on Java 8 usually start on.
+    TooNew(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing(message) | Problem::TooOld(message) | Problem::TooNew(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Problem with the instance's java for the pack, `None` when it fits or
+/// nothing is known to check against.
+pub fn check(base_dir: &Path, pack: &PackHeader) -> Option<Problem> {
+    let launcher = Launcher::detect(base_dir);
+    if launcher.manages_java() {
+        return None;
+    }
+    let required = required(pack)?;
+    match find(base_dir, launcher) {
+        Err(e) => Some(Problem::Missing(format!("{:#}, the pack needs Java {}", e, required))),
+        Ok(found) if found.major < required => Some(Problem::TooOld(format!(
+            "{} is Java {} but the pack needs Java {}",
+            found.describe(),
+            found.major,
+            required
+        ))),
+        // old forge crashes on anything newer than the java it was built for
+        Ok(found) if required == 8 && found.major > 8 => Some(Problem::TooNew(format!(
+            "{} is Java {}, packs for this Minecraft version usually only start on Java 8",
+            found.describe(),
+            found.major
+        ))),
+        Ok(_) => None,
+    }
+}
@@ -0,0 +1,550 @@
+use super::index::{self, ModMeta};
+use super::manifest::{self, LaunchSettings, PackHeader};
+use super::plan;
+use super::Installed;
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prism's per instance settings, next to the `.minecraft` directory.
+const PRISM_INSTANCE: &str = "instance.cfg";
+/// Prism's list of the game and loader versions of an instance.
+const PRISM_COMPONENTS: &str = "mmc-pack.json";
+/// The vanilla launcher's profiles, in the directory it is installed in.
+const VANILLA_PROFILES: &str = "launcher_profiles.json";
+/// JVM argument file forge server scripts read, used for every server.
+pub const SERVER_JVM_ARGS: &str = "user_jvm_args.txt";
+const MANAGED_BEGIN: &str = "# modpack-sync begin, replaced on every sync";
+const MANAGED_END: &str = "# modpack-sync end";
+
+/// CurseForge game id of Minecraft.
+const MINECRAFT_GAME_ID: u64 = 432;
+
+/// Launcher an instance was created by, which decides where the game lives
+/// and which bookkeeping of installed mods has to be kept consistent.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Launcher {
+    /// Prism/MultiMC, the game lives in `.minecraft` with packwiz metadata in
+    /// `mods/.index`.
+    Prism,
+    /// CurseForge App, installed addons are listed in `minecraftinstance.json`.
+    CurseForge,
+    /// ATLauncher, mods are listed under `launcher.mods` of `instance.json`.
+    Atlauncher,
+    /// GDLauncher, mods are listed in the instance's `config.json`.
+    Gdlauncher,
+    /// The vanilla launcher, the game lives where the instance's profile
+    /// says, with packwiz metadata in `mods/.index`.
+    Vanilla,
+}
+
+/// Where a launcher lists the mods of an instance, as JSON pointers into
+/// its instance file.
+struct Registry {
+    file: &'static str,
+    list: &'static str,
+    project_id: &'static str,
+    file_id: &'static str,
+    filename: &'static str,
+    /// JVM arguments of the instance as a single string.
+    jvm_args: &'static str,
+    /// Maximum heap in megabytes.
+    max_memory: &'static str,
+    /// Minimum heap in megabytes, passed as `-Xms` where unsupported.
+    min_memory: Option<&'static str>,
+    /// Path of the java the instance runs with, when configurable.
+    java_path: Option<&'static str>,
+    /// Entry for a newly installed CurseForge file.
+    entry: fn(&Entry) -> Value,
+}
+
+struct Entry<'a> {
+    project_id: u64,
+    file_id: u64,
+    filename: &'a str,
+    name: &'a str,
+    bytes: u64,
+    now: String,
+}
+
+const CURSEFORGE: Registry = Registry {
+    file: "minecraftinstance.json",
+    list: "/installedAddons",
+    project_id: "/addonID",
+    file_id: "/installedFile/id",
+    filename: "/installedFile/fileNameOnDisk",
+    jvm_args: "/javaArgsOverride",
+    max_memory: "/allocatedMemory",
+    min_memory: None,
+    java_path: None,
+    entry: |e| {
+        json!({
+            "addonID": e.project_id,
+            "gameID": MINECRAFT_GAME_ID,
+            "name": e.name,
+            "installedFile": {
+                "id": e.file_id,
+                "displayName": e.filename,
+                "fileName": e.filename,
+                "fileNameOnDisk": e.filename,
+                "fileLength": e.bytes,
+                "projectId": e.project_id,
+            },
+            "dateInstalled": e.now,
+            "dateUpdated": e.now,
+        })
+    },
+};
+
+const ATLAUNCHER: Registry = Registry {
+    file: "instance.json",
+    list: "/launcher/mods",
+    project_id: "/curseForgeProjectId",
+    file_id: "/curseForgeFileId",
+    filename: "/file",
+    jvm_args: "/launcher/javaArguments",
+    max_memory: "/launcher/maximumMemory",
+    min_memory: Some("/launcher/initialMemory"),
+    java_path: Some("/launcher/javaPath"),
+    entry: |e| {
+        json!({
+            "name": e.name,
+            "version": e.filename,
+            "file": e.filename,
+            "type": "mods",
+            "description": "",
+            "disabled": false,
+            "userAdded": true,
+            "wasSelected": true,
+            "skipped": false,
+            "curseForgeProjectId": e.project_id,
+            "curseForgeFileId": e.file_id,
+        })
+    },
+};
+
+const GDLAUNCHER: Registry = Registry {
+    file: "config.json",
+    list: "/mods",
+    project_id: "/projectID",
+    file_id: "/fileID",
+    filename: "/fileName",
+    jvm_args: "/javaArgs",
+    max_memory: "/javaMemory",
+    min_memory: None,
+    java_path: None,
+    entry: |e| {
+        json!({
+            "displayName": e.name,
+            "fileName": e.filename,
+            "projectID": e.project_id,
+            "fileID": e.file_id,
+            "source": "curseforge",
+        })
+    },
+};
+
+impl Launcher {
+    pub fn detect(base_dir: &Path) -> Launcher {
+        if base_dir.join(CURSEFORGE.file).is_file() {
+            return Launcher::CurseForge;
+        }
+        let has = |file: &str, pointer: &str| read_json(&base_dir.join(file)).is_ok_and(|v| v.pointer(pointer).is_some());
+        if has(ATLAUNCHER.file, "/launcher") {
+            return Launcher::Atlauncher;
+        }
+        if has(GDLAUNCHER.file, "/loader") {
+            return Launcher::Gdlauncher;
+        }
+        if base_dir.join(VANILLA_PROFILES).is_file() {
+            return Launcher::Vanilla;
+        }
+        Launcher::Prism
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Launcher::Prism => "prism",
+            Launcher::CurseForge => "curseforge",
+            Launcher::Atlauncher => "atlauncher",
+            Launcher::Gdlauncher => "gdlauncher",
+            Launcher::Vanilla => "vanilla",
+        }
+    }
+
+    fn registry(&self) -> Option<&'static Registry> {
+        match self {
+            Launcher::Prism | Launcher::Vanilla => None,
+            Launcher::CurseForge => Some(&CURSEFORGE),
+            Launcher::Atlauncher => Some(&ATLAUNCHER),
+            Launcher::Gdlauncher => Some(&GDLAUNCHER),
+        }
+    }
+
+    /// Whether the launcher keeps the game in `.minecraft` inside the
+    /// instance rather than the instance directory itself.
+    pub fn nested_game_dir(&self) -> bool {
+        matches!(self, Launcher::Prism)
+    }
+
+    /// CurseForge files the launcher knows to be installed, keyed by project
+    /// id.
+    pub fn metadata(&self, base_dir: &Path, mods_dir: &Path) -> Result<HashMap<String, ModMeta>> {
+        let Some(registry) = self.registry() else {
+            return Ok(index::load_mod_metadata(mods_dir.join(".index"))?);
+        };
+        let instance = read_json(&base_dir.join(registry.file))?;
+        let mut mods = HashMap::new();
+        for entry in entries(&instance, registry) {
+            let project_id = entry.pointer(registry.project_id).and_then(Value::as_u64);
+            let filename = entry.pointer(registry.filename).and_then(Value::as_str);
+            if let (Some(project_id), Some(filename)) = (project_id, filename) {
+                let file_id = entry.pointer(registry.file_id).and_then(Value::as_u64);
+                mods.insert(project_id.to_string(), ModMeta::curseforge(project_id, file_id, filename));
+            }
+        }
+        Ok(mods)
+    }
+
+    /// Updates the launcher's record of installed mods after a sync so its
+    /// UI matches the mods directory. `pack` maps project ids to names and
+    /// `extensions` are those of the files the tool manages in `mods_dir`.
+    pub fn record(
+        &self,
+        base_dir: &Path,
+        mods_dir: &Path,
+        extensions: &[String],
+        installed: &[Installed],
+        pack: &[(String, String)],
+    ) -> Result<()> {
+        let Some(registry) = self.registry() else {
+            return Ok(());
+        };
+        let path = base_dir.join(registry.file);
+        let mut instance = read_json(&path)?;
+        // managed files that are gone were removed by the sync, disabled ones live elsewhere
+        let mut list: Vec<Value> = entries(&instance, registry)
+            .filter(|entry| {
+                let disabled = entry.get("disabled").and_then(Value::as_bool).unwrap_or(false);
+                match entry.pointer(registry.filename).and_then(Value::as_str) {
+                    Some(name) if plan::has_extension(Path::new(name), extensions) && !disabled => {
+                        mods_dir.join(name).is_file()
+                    }
+                    _ => true,
+                }
+            })
+            .cloned()
+            .collect();
+
+        let now = chrono::Utc::now().to_rfc3339();
+        for new in installed.iter() {
+            let (Some(project_id), Some(file_id)) = (&new.project_id, new.file_id) else {
+                continue;
+            };
+            let Ok(numeric_id) = project_id.parse::<u64>() else {
+                continue;
+            };
+            let name = pack
+                .iter()
+                .find(|(id, _)| id == project_id)
+                .map_or(new.filename.as_str(), |(_, name)| name.as_str());
+            let entry = (registry.entry)(&Entry {
+                project_id: numeric_id,
+                file_id,
+                filename: &new.filename,
+                name,
+                bytes: new.bytes,
+                now: now.clone(),
+            });
+            let existing = list
+                .iter_mut()
+                .find(|e| e.pointer(registry.project_id).and_then(Value::as_u64) == Some(numeric_id));
+            match (existing, entry) {
+                // keep what the launcher knows beyond the fields we write
+                (Some(Value::Object(existing)), Value::Object(entry)) => existing.extend(entry),
+                (_, entry) => list.push(entry),
+            }
+        }
+
+        let parent = registry.list.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let last = registry.list.rsplit('/').next().unwrap_or_default();
+        let object = instance
+            .pointer_mut(parent)
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| anyhow!("{} has no {} object", path.display(), parent))?;
+        object.insert(last.to_string(), Value::Array(list));
+        fs::write(&path, serde_json::to_string_pretty(&instance)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Arguments of the settings as passed to java, heap sizes first.
+fn java_args(launch: &LaunchSettings, with_min: bool, with_max: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    if let (true, Some(min)) = (with_min, &launch.min_memory) {
+        args.push(format!("-Xms{}", min));
+    }
+    if let (true, Some(max)) = (with_max, &launch.max_memory) {
+        args.push(format!("-Xmx{}", max));
+    }
+    args.extend(launch.jvm_args.iter().cloned());
+    args
+}
+
+/// Sets `key=value` lines of an ini style file, adding missing keys to its
+/// `[General]` section.
+fn set_ini(contents: &str, values: &[(&str, String)]) -> String {
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    let mut section_end = lines.iter().position(|l| l.trim() == "[General]").map(|i| {
+        i + 1 + lines[i + 1..].iter().take_while(|l| !l.trim_start().starts_with('[')).count()
+    });
+    for (key, value) in values {
+        let line = format!("{}={}", key, value);
+        match lines.iter().position(|l| l.split_once('=').is_some_and(|(k, _)| k.trim() == *key)) {
+            Some(i) => lines[i] = line,
+            None => match section_end.as_mut() {
+                Some(end) => {
+                    lines.insert(*end, line);
+                    *end += 1;
+                }
+                None => lines.push(line),
+            },
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn set_pointer(root: &mut Value, pointer: &str, value: Value) {
+    let mut current = root;
+    for part in pointer.trim_start_matches('/').split('/') {
+        if !current.is_object() {
+            *current = json!({});
+        }
+        current = current
+            .as_object_mut()
+            .expect("replaced by an object above")
+            .entry(part)
+            .or_insert(Value::Null);
+    }
+    *current = value;
+}
+
+/// Replaces the block of `user_jvm_args.txt` the tool manages with the
+/// pack's settings, leaving the server owner's lines alone.
+pub fn write_server_args(game_dir: &Path, launch: &LaunchSettings) -> Result<()> {
+    let path = game_dir.join(SERVER_JVM_ARGS);
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    let mut lines: Vec<&str> = Vec::new();
+    let mut inside = false;
+    for line in existing.lines() {
+        match line.trim() {
+            MANAGED_BEGIN => inside = true,
+            MANAGED_END => inside = false,
+            _ if !inside => lines.push(line),
+            _ => {}
+        }
+    }
+    let block = java_args(launch, true, true);
+    let mut contents = String::new();
+    for line in lines {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    contents.push_str(MANAGED_BEGIN);
+    contents.push('\n');
+    for arg in block {
+        contents.push_str(&arg);
+        contents.push('\n');
+    }
+    contents.push_str(MANAGED_END);
+    contents.push('\n');
+    fs::write(&path, contents).with_context(|| format!("failed to write {}", path.display()))
+}
+
+impl Launcher {
+    /// Whether the launcher picks the java runtime for the game itself.
+    pub fn manages_java(&self) -> bool {
+        matches!(self, Launcher::CurseForge)
+    }
+
+    /// The java the instance is configured to run with, `None` when it uses
+    /// the launcher's default.
+    pub fn java_path(&self, base_dir: &Path) -> Option<PathBuf> {
+        let Some(registry) = self.registry() else {
+            let contents = fs::read_to_string(base_dir.join(PRISM_INSTANCE)).ok()?;
+            let value = |key: &str| {
+                contents
+                    .lines()
+                    .filter_map(|l| l.split_once('='))
+                    .find(|(k, _)| k.trim() == key)
+                    .map(|(_, v)| v.trim().to_string())
+            };
+            return match value("OverrideJavaLocation").as_deref() {
+                Some("true") => value("JavaPath").filter(|p| !p.is_empty()).map(PathBuf::from),
+                _ => None,
+            };
+        };
+        let instance = read_json(&base_dir.join(registry.file)).ok()?;
+        let path = instance.pointer(registry.java_path?)?.as_str()?;
+        Some(PathBuf::from(path)).filter(|p| !p.as_os_str().is_empty())
+    }
+
+    /// Writes the pack's JVM settings to the launcher's instance settings.
+    pub fn apply_launch(&self, base_dir: &Path, launch: &LaunchSettings) -> Result<()> {
+        let max = launch.max_memory.as_deref().map(manifest::megabytes).transpose()?;
+        let min = launch.min_memory.as_deref().map(manifest::megabytes).transpose()?;
+        let Some(registry) = self.registry() else {
+            let path = base_dir.join(PRISM_INSTANCE);
+            let mut values = vec![
+                ("OverrideJavaArgs", "true".to_string()),
+                ("JvmArgs", launch.jvm_args.join(" ")),
+            ];
+            if max.is_some() || min.is_some() {
+                values.push(("OverrideMemory", "true".to_string()));
+            }
+            if let Some(max) = max {
+                values.push(("MaxMemAlloc", max.to_string()));
+            }
+            if let Some(min) = min {
+                values.push(("MinMemAlloc", min.to_string()));
+            }
+            let existing = fs::read_to_string(&path).unwrap_or_else(|_| "[General]\n".to_string());
+            return fs::write(&path, set_ini(&existing, &values)).with_context(|| format!("failed to write {}", path.display()));
+        };
+
+        let path = base_dir.join(registry.file);
+        let mut instance = read_json(&path)?;
+        let args = java_args(launch, registry.min_memory.is_none(), false);
+        set_pointer(&mut instance, registry.jvm_args, json!(args.join(" ")));
+        if let Some(max) = max {
+            set_pointer(&mut instance, registry.max_memory, json!(max));
+        }
+        if let (Some(pointer), Some(min)) = (registry.min_memory, min) {
+            set_pointer(&mut instance, pointer, json!(min));
+        }
+        fs::write(&path, serde_json::to_string_pretty(&instance)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Directory Prism keeps its instances in, when it is installed for the
+/// current user.
+pub fn prism_instances() -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(dir) = env::var_os("XDG_DATA_HOME") {
+        candidates.push(PathBuf::from(dir).join("PrismLauncher"));
+    }
+    if let Some(dir) = env::var_os("APPDATA") {
+        candidates.push(PathBuf::from(dir).join("PrismLauncher"));
+    }
+    if let Some(home) = env::var_os("HOME").map(PathBuf::from) {
+        candidates.push(home.join(".local/share/PrismLauncher"));
+        candidates.push(home.join(".var/app/org.prismlauncher.PrismLauncher/data/PrismLauncher"));
+        candidates.push(home.join("Library/Application Support/PrismLauncher"));
+    }
+    let data = candidates.into_iter().find(|d| d.is_dir())?;
+    // the instance folder can be moved in Prism's settings
+    let configured = fs::read_to_string(data.join("prismlauncher.cfg")).ok().and_then(|c| {
+        c.lines()
+            .filter_map(|l| l.split_once('='))
+            .find(|(k, _)| k.trim() == "InstanceDir")
+            .map(|(_, v)| v.trim().to_string())
+    });
+    Some(data.join(configured.unwrap_or_else(|| "instances".to_string())))
+}
+
+/// Prism's component id of a loader.
+fn prism_component(loader: &str) -> Option<&'static str> {
+    match loader.to_lowercase().as_str() {
+        "fabric" => Some("net.fabricmc.fabric-loader"),
+        "quilt" => Some("org.quiltmc.quilt-loader"),
+        "forge" => Some("net.minecraftforge"),
+        "neoforge" => Some("net.neoforged"),
+        _ => None,
+    }
+}
+
+/// Makes `base_dir` a Prism instance named `name` for the game and loader
+/// of `pack`, linking it into `instances` unless it is there already.
+/// Returns whether Prism still has to be told the loader version, which
+/// it needs to install the loader.
+pub fn register_prism(base_dir: &Path, name: &str, pack: &PackHeader, instances: &Path) -> Result<bool> {
+    let path = base_dir.join(PRISM_INSTANCE);
+    let existing = fs::read_to_string(&path).unwrap_or_else(|_| "[General]\n".to_string());
+    let values = [
+        ("ConfigVersion", "1.2".to_string()),
+        ("InstanceType", "OneSix".to_string()),
+        ("name", name.to_string()),
+    ];
+    fs::write(&path, set_ini(&existing, &values)).with_context(|| format!("failed to write {}", path.display()))?;
+
+    let mut components = Vec::new();
+    if let Some(minecraft) = &pack.minecraft {
+        components.push(json!({ "uid": "net.minecraft", "version": minecraft, "important": true }));
+    }
+    let mut missing_loader = false;
+    if let Some(loader) = &pack.loader {
+        let (loader, version) = loader.split_once('-').unwrap_or((loader, ""));
+        match (prism_component(loader), version) {
+            (Some(uid), version) if !version.is_empty() => components.push(json!({ "uid": uid, "version": version })),
+            _ => missing_loader = true,
+        }
+    }
+    let path = base_dir.join(PRISM_COMPONENTS);
+    let pack_json = json!({ "formatVersion": 1, "components": components });
+    fs::write(&path, serde_json::to_string_pretty(&pack_json)?).with_context(|| format!("failed to write {}", path.display()))?;
+
+    let base_dir = base_dir.canonicalize()?;
+    fs::create_dir_all(instances)?;
+    if base_dir.parent() == Some(instances.canonicalize()?.as_path()) {
+        return Ok(missing_loader);
+    }
+    let link = instances.join(base_dir.file_name().ok_or_else(|| anyhow!("{} has no name", base_dir.display()))?);
+    if link.exists() {
+        return Err(anyhow!("Prism has an instance at {} already", link.display()));
+    }
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&base_dir, &link)
+            .with_context(|| format!("failed to link the instance into {}", instances.display()))?;
+        Ok(missing_loader)
+    }
+    #[cfg(not(unix))]
+    Err(anyhow!("create the instance inside {} to have Prism list it", instances.display()))
+}
+
+/// Game directory of the vanilla launcher profile `name`, by name or id,
+/// of the launcher installed in `base_dir`. Profiles without one play in
+/// `base_dir` itself.
+pub fn profile_game_dir(base_dir: &Path, name: &str) -> Result<PathBuf> {
+    let path = base_dir.join(VANILLA_PROFILES);
+    let launcher = read_json(&path)?;
+    let profiles = launcher
+        .get("profiles")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow!("{} lists no profiles", path.display()))?;
+    let named = |profile: &Value| profile.get("name").and_then(Value::as_str) == Some(name);
+    let profile = profiles
+        .values()
+        .find(|p| named(p))
+        .or_else(|| profiles.get(name))
+        .ok_or_else(|| {
+            let mut names: Vec<&str> = profiles.iter().map(|(id, p)| p.get("name").and_then(Value::as_str).unwrap_or(id)).collect();
+            names.sort();
+            anyhow!("the launcher has no profile named {}, its profiles are {}", name, names.join(", "))
+        })?;
+    Ok(match profile.get("gameDir").and_then(Value::as_str).filter(|d| !d.is_empty()) {
+        Some(dir) => base_dir.join(dir),
+        None => base_dir.to_path_buf(),
+    })
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let contents = fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&contents).with_context(|| format!("{} is corrupt", path.display()))
+}
+
+fn entries<'a>(instance: &'a Value, registry: &Registry) -> impl Iterator<Item = &'a Value> {
+    instance.pointer(registry.list).and_then(Value::as_array).into_iter().flatten()
+}
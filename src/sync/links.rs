@@ -0,0 +1,22 @@
+use std::fs;
+use std::path::Path;
+
+/// Whether `path` itself is a symbolic link, without following it.
+pub fn is_link(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// Whether deleting `path` stays inside the instance. A link is deleted
+/// itself, so only the directory holding it is resolved, a linked directory
+/// on the way there can still lead elsewhere.
+pub fn inside(base_dir: &Path, path: &Path) -> bool {
+    path.parent().is_some_and(|dir| within(base_dir, dir))
+}
+
+/// Whether `dir`, with every link on the way resolved, is in the instance.
+pub fn within(base_dir: &Path, dir: &Path) -> bool {
+    match (base_dir.canonicalize(), dir.canonicalize()) {
+        (Ok(root), Ok(dir)) => dir.starts_with(root),
+        _ => false,
+    }
+}
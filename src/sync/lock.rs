@@ -0,0 +1,100 @@
+use super::hash::{self, Multihash};
+use super::overrides::{self, OVERRIDES_DIR};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Lockfile published next to the manifest.
+pub const LOCK_FILE: &str = "modlist.lock";
+
+/// Exact contents of a published pack beyond what the manifest says.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Lockfile {
+    /// sha256 of every override file, keyed by its path under the overrides
+    /// directory.
+    #[serde(default)]
+    pub overrides: BTreeMap<String, String>,
+    /// Overrides earlier versions shipped and this one dropped, with the
+    /// sha256 they were last published with, so instances delete them even
+    /// without a record of installing them.
+    #[serde(default)]
+    pub removed: BTreeMap<String, String>,
+    /// Slug and authors of every CurseForge project installed from, keyed by
+    /// project id, to notice projects changing hands.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub owners: BTreeMap<String, Ownership>,
+    /// Digests of every installed file whose source pins its contents,
+    /// keyed by its path in the game directory. Each file keeps the ones its
+    /// provider publishes next to its sha256, downloads of the same source
+    /// are checked against all of them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, LockedFile>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct LockedFile {
+    /// The CurseForge file or Maven artifact the digests are of.
+    pub source: String,
+    #[serde(flatten)]
+    pub hashes: Multihash,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Ownership {
+    pub slug: String,
+    pub authors: Vec<String>,
+}
+
+impl std::fmt::Display for Ownership {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} by {}", self.slug, self.authors.join(", "))
+    }
+}
+
+pub fn path(base_dir: &Path) -> PathBuf {
+    base_dir.join(LOCK_FILE)
+}
+
+impl Lockfile {
+    /// The lockfile of an instance, `None` if the pack has none.
+    pub fn load(base_dir: &Path) -> Result<Option<Lockfile>> {
+        let path = path(base_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let lock = serde_json::from_str(&contents).with_context(|| format!("{} is corrupt", path.display()))?;
+        Ok(Some(lock))
+    }
+
+    pub fn save(&self, base_dir: &Path) -> Result<()> {
+        let path = path(base_dir);
+        fs::write(&path, serde_json::to_string_pretty(self)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Hashes the overrides directory again, turning overrides that are gone
+    /// since the last publish into tombstones.
+    pub fn update_overrides(&mut self, base_dir: &Path) -> Result<()> {
+        let root = base_dir.join(OVERRIDES_DIR);
+        let mut found = Vec::new();
+        if root.is_dir() {
+            overrides::files(&root, &mut found)?;
+        }
+        let mut current = BTreeMap::new();
+        for file in found {
+            current.insert(overrides::key(&root, &file), hash::sha256_file(&file)?);
+        }
+
+        let previous = std::mem::take(&mut self.overrides);
+        for (key, sha256) in previous {
+            if !current.contains_key(&key) {
+                self.removed.insert(key, sha256);
+            }
+        }
+        self.removed.retain(|key, _| !current.contains_key(key));
+        self.overrides = current;
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use chrono::Local;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Directory inside the instance holding the tool's own files.
+pub const STATE_DIR: &str = ".modpack-sync";
+/// Number of run logs kept before the oldest are pruned.
+pub const KEEP_RUNS: usize = 20;
+
+/// Lines logged before the first run log starts, written to it then.
+const MAX_EARLY_LINES: usize = 1000;
+
+/// Where logged lines go.
+enum Sink {
+    /// No run log started yet, the lines wait for it.
+    Early(Vec<String>),
+    Run(PathBuf),
+}
+
+static CURRENT: Mutex<Sink> = Mutex::new(Sink::Early(Vec::new()));
+static CONSOLE_TO_STDERR: AtomicBool = AtomicBool::new(false);
+static TRACE: AtomicBool = AtomicBool::new(false);
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Sends console messages to stderr from now on, keeping stdout for a report
+/// other programs read.
+pub fn console_to_stderr() {
+    CONSOLE_TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+/// Keeps output to simple lines from now on: a line per finished step, no
+/// padded tables or arrows, for screen readers, dumb terminals and launcher
+/// log windows.
+pub fn set_plain() {
+    PLAIN.store(true, Ordering::Relaxed);
+}
+
+pub fn plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+pub fn write_console(message: fmt::Arguments) {
+    match CONSOLE_TO_STDERR.load(Ordering::Relaxed) {
+        true => eprintln!("{}", message),
+        false => println!("{}", message),
+    }
+}
+
+/// `println!` for progress and warnings of a sync, see [`console_to_stderr`].
+macro_rules! console {
+    ($($arg:tt)*) => {
+        $crate::sync::log::write_console(format_args!($($arg)*))
+    };
+}
+pub(crate) use console;
+
+/// Logs every planner decision with its inputs from now on, see [`trace`].
+pub fn enable_trace() {
+    TRACE.store(true, Ordering::Relaxed);
+}
+
+pub fn tracing() -> bool {
+    TRACE.load(Ordering::Relaxed)
+}
+
+/// Writes a `[TRACE]` line to the run log when `--trace-plan` asked for
+/// them, formatting the message only then.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::sync::log::tracing() {
+            let _ = $crate::sync::log::log_to_file(&format!("[TRACE] {}", format_args!($($arg)*)));
+        }
+    };
+}
+pub(crate) use trace;
+
+pub fn logs_dir(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("logs")
+}
+
+/// Starts a new run log `logs/sync-<timestamp>.log`, points `latest.log` at
+/// it and prunes old runs. Messages logged before the first run log of the
+/// process are written to it first.
+pub fn start_run(base_dir: &Path) -> Result<PathBuf> {
+    let dir = logs_dir(base_dir);
+    fs::create_dir_all(&dir)?;
+
+    // runs started within the same millisecond, by the daemon or several
+    // processes, still get a log of their own
+    let stamp = Local::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+    let (name, mut file) = (0..)
+        .map(|n| match n {
+            0 => format!("sync-{}.log", stamp),
+            n => format!("sync-{}-{}.log", stamp, n),
+        })
+        .find_map(|name| match OpenOptions::new().write(true).create_new(true).open(dir.join(&name)) {
+            Ok(file) => Some(Ok((name, file))),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => None,
+            Err(e) => Some(Err(e)),
+        })
+        .expect("some name is free")?;
+    let path = dir.join(&name);
+    let mut current = CURRENT.lock().expect("log lock poisoned");
+    if let Sink::Early(lines) = &*current {
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+    }
+    *current = Sink::Run(path.clone());
+    drop(current);
+
+    #[cfg(unix)]
+    {
+        let latest = dir.join("latest.log");
+        let _ = fs::remove_file(&latest);
+        let _ = std::os::unix::fs::symlink(&name, &latest);
+    }
+
+    let mut runs = list_runs(base_dir)?;
+    while runs.len() > KEEP_RUNS {
+        let _ = fs::remove_file(runs.remove(0));
+    }
+    Ok(path)
+}
+
+/// Run logs of an instance, oldest first.
+pub fn list_runs(base_dir: &Path) -> Result<Vec<PathBuf>> {
+    let dir = logs_dir(base_dir);
+    let mut runs = Vec::new();
+    if !dir.exists() {
+        return Ok(runs);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if name.starts_with("sync-") && name.ends_with(".log") {
+            runs.push(path);
+        }
+    }
+    runs.sort();
+    Ok(runs)
+}
+
+/// Appends a timestamped line to the current run log, kept for the first
+/// one while none was started.
+pub fn log_to_file(message: &str) -> Result<()> {
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+    let line = format!("[{}] {}", now, message);
+    let path = match &mut *CURRENT.lock().expect("log lock poisoned") {
+        Sink::Early(lines) => {
+            if lines.len() < MAX_EARLY_LINES {
+                lines.push(line);
+            }
+            return Ok(());
+        }
+        Sink::Run(path) => path.clone(),
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
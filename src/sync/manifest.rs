@@ -0,0 +1,592 @@
+use super::audit;
+use super::conditions::Condition;
+use super::hash;
+use super::log::STATE_DIR;
+use super::maven;
+use super::modrinth;
+use super::version::{self, Requirement};
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Manifest schema version written by this build of the tool.
+pub const FORMAT_VERSION: u32 = 1;
+/// Directory next to the modlist whose `.json` and `.toml` fragments are
+/// merged into it, e.g. admin tools only a server installs.
+pub const FRAGMENTS_DIR: &str = "modlist.d";
+
+/// What an entry installs.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    #[default]
+    Mod,
+    /// A world zip, extracted into `saves/` or as the world of a server on
+    /// the first install only. It is never replaced or deleted afterwards,
+    /// it is the players' from then on.
+    World,
+}
+
+impl Kind {
+    fn is_mod(&self) -> bool {
+        *self == Kind::Mod
+    }
+}
+
+/// A command an entry runs once its file is installed, e.g. a converter
+/// writing a data pack or a tool regenerating a config.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Script {
+    /// Program and arguments, run in the game directory without a shell.
+    /// `MODPACK_SYNC_FILE` names the installed file.
+    pub command: Vec<String>,
+    /// Files the command writes, relative to the game directory. They are
+    /// managed files of the entry, deleted with it, and the command runs
+    /// again when one is missing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Mod {
+    pub filename: String,
+    pub name: String,
+    pub url: Option<String>,
+    pub version: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Kind::is_mod")]
+    pub kind: Kind,
+    /// Only install the entry for matching pack variants.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+    /// Free-form labels of optional sets of entries, e.g. `shaders`, turned
+    /// on and off with `--with` and `--without`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Directory relative to the game directory to install into instead of mods/.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_dir: Option<String>,
+    /// Name to give the file on disk instead of `filename`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install_as: Option<String>,
+    /// Download the entry from a Maven repository instead of CurseForge.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maven: Option<maven::Coordinate>,
+    /// Download the entry from a Modrinth version instead of CurseForge.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modrinth: Option<modrinth::Pin>,
+    /// The author doesn't allow third party downloads, the file is placed
+    /// by hand and only verified by its hash.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub manual: bool,
+    /// sha256 the manually placed file must have, CurseForge's own hash of
+    /// the file is checked when left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// The file is a zip extracted into the entry's directory instead of
+    /// being installed as is, e.g. shader packs shipped as folders.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unpack: bool,
+    /// Url of a detached signature the downloaded file is checked against
+    /// before it is installed, of the format `signing.format` names.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Commands to run after the file is installed, only on instances that
+    /// allow scripts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_install: Vec<Script>,
+    /// Why the entry is in the pack, e.g. `required by quest chapter 3`,
+    /// shown wherever the entry is listed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Why the entry is on the file it is, e.g. `temporary downgrade due to
+    /// bug #123`, shown next to the note and when the entry is updated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Last day the entry stays on its file, e.g. for a temporary downgrade.
+    /// `update` leaves it alone until then, afterwards it moves on again and
+    /// `outdated` and syncs warn about the hold still being there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold_until: Option<NaiveDate>,
+    /// The entry stays on its file for good, `update` never moves it and
+    /// `bisect` keeps it loaded, e.g. a library the pack was tested with.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PackHeader {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minecraft: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch: Option<LaunchSettings>,
+    /// File extensions the pack manages by content directory, relative to
+    /// the game directory with `mods` standing for the mods directory.
+    /// Files with them that no entry references are removed on sync. Only
+    /// `.jar` files in the mods directory are managed by default.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extensions: BTreeMap<String, Vec<String>>,
+    /// Values for `${name}` placeholders in entries, next to the built in
+    /// `mc_version`, `loader`, `loader_version` and `pack_version`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// Tags whose entries are only installed once turned on with `--with`,
+    /// e.g. `shaders`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub opt_in_tags: Vec<String>,
+    /// Config files the pack's name and version are written into on every
+    /// sync, for mods that show them in game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stamp: Option<StampSettings>,
+}
+
+impl PackHeader {
+    /// Placeholder values the header defines, `overrides` such as the ones
+    /// given on the command line taking precedence.
+    pub fn variables(&self, overrides: &[(String, String)]) -> BTreeMap<String, String> {
+        let mut vars = BTreeMap::new();
+        vars.insert("pack_version".to_string(), self.version.clone());
+        if let Some(minecraft) = &self.minecraft {
+            vars.insert("mc_version".to_string(), minecraft.clone());
+        }
+        if let Some(loader) = &self.loader {
+            let (name, version) = loader.split_once('-').unwrap_or((loader, ""));
+            vars.insert("loader".to_string(), name.to_string());
+            if !version.is_empty() {
+                vars.insert("loader_version".to_string(), version.to_string());
+            }
+        }
+        vars.extend(self.variables.clone());
+        vars.extend(overrides.iter().cloned());
+        vars
+    }
+
+    /// Directories the tool cleans up, with the extensions of the files it
+    /// manages in each.
+    pub fn managed_dirs(&self, game_dir: &Path, mods_dir: &Path) -> Result<Vec<(PathBuf, Vec<String>)>> {
+        let mut dirs = vec![(mods_dir.to_path_buf(), vec!["jar".to_string()])];
+        for (dir, extensions) in self.extensions.iter() {
+            let extensions = extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect();
+            match dir.as_str() {
+                "mods" => dirs[0].1 = extensions,
+                _ => dirs.push((game_dir.join(contained(dir, "extensions")?), extensions)),
+            }
+        }
+        Ok(dirs)
+    }
+}
+
+/// Where a sync writes the pack's name and version besides the stamp file
+/// of the game directory.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct StampSettings {
+    /// Fill in the pack of Better Compatibility Checker's
+    /// `config/bcc-common.toml`, created when it is missing.
+    #[serde(default)]
+    pub bcc: bool,
+    /// Keys to set in other config files by path relative to the game
+    /// directory, e.g. `{"config/menu.toml": {"version": "v${pack_version}"}}`.
+    /// `${pack_name}` and the other placeholders of entries can be used.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// JVM settings the pack recommends, written to the launcher profile or the
+/// server's JVM arguments of instances that opt in.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LaunchSettings {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jvm_args: Vec<String>,
+    /// Heap sizes such as `2G` or `4096M`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_memory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory: Option<String>,
+    /// Major Java version the pack runs on, e.g. 17.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub java: Option<u32>,
+}
+
+/// Megabytes of a JVM style memory size, `6G`, `512M` or plain megabytes.
+pub fn megabytes(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let (number, factor) = match size.char_indices().last() {
+        Some((i, 'G' | 'g')) => (&size[..i], 1024),
+        Some((i, 'M' | 'm')) => (&size[..i], 1),
+        _ => (size, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(factor))
+        .ok_or_else(|| anyhow!("invalid memory size {}, expected e.g. 4G or 4096M", size))
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Manifest {
+    pub format_version: u32,
+    /// Path or url of a parent manifest whose entries are inherited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    #[serde(default)]
+    pub pack: PackHeader,
+    /// Project ids or filenames of inherited entries to drop.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+    #[serde(default)]
+    pub mods: Vec<Mod>,
+}
+
+impl Mod {
+    /// The note and reason of the entry as one line, `None` without either.
+    pub fn remarks(&self) -> Option<String> {
+        let remarks: Vec<&str> = [self.note.as_deref(), self.reason.as_deref()].into_iter().flatten().collect();
+        (!remarks.is_empty()).then(|| remarks.join("; "))
+    }
+
+    /// Whether the entry is held on its file on `today`.
+    pub fn held(&self, today: NaiveDate) -> bool {
+        self.hold_until.is_some_and(|until| today <= until)
+    }
+
+    /// The last day of a hold that is over by `today`.
+    pub fn hold_expired(&self, today: NaiveDate) -> Option<NaiveDate> {
+        self.hold_until.filter(|until| today > *until)
+    }
+
+    /// Fills `${name}` placeholders in the file name, url, target directory
+    /// and install name of the entry.
+    pub fn substitute(&mut self, vars: &BTreeMap<String, String>) -> Result<()> {
+        let filename = self.filename.clone();
+        let fill = |text: &mut String| -> Result<()> {
+            *text = substitute(text, vars).with_context(|| format!("in the entry for {}", filename))?;
+            Ok(())
+        };
+        fill(&mut self.filename)?;
+        for text in [&mut self.url, &mut self.target_dir, &mut self.install_as, &mut self.signature].into_iter().flatten() {
+            fill(text)?;
+        }
+        Ok(())
+    }
+
+    /// The requirement files of the entry must satisfy when its version is a
+    /// range such as `>=0.5.1, <0.6`, `None` for entries pinned to a file.
+    pub fn range(&self) -> Result<Option<Requirement>> {
+        if !version::is_requirement(&self.version) {
+            return Ok(None);
+        }
+        Requirement::parse(&self.version)
+            .map(Some)
+            .with_context(|| format!("invalid version range of {}", self.name))
+    }
+
+    /// CurseForge project id taken from the last segment of the url.
+    pub fn project_id(&self) -> Option<&str> {
+        self.url
+            .as_deref()
+            .filter(|u| !u.starts_with("file:"))
+            .and_then(|u| u.trim_end_matches('/').rsplit('/').next())
+            .filter(|id| !id.is_empty())
+    }
+
+    /// Path of a `file://` url, for private mods kept on a local or network
+    /// drive.
+    pub fn local_path(&self) -> Option<PathBuf> {
+        let url = self.url.as_deref().filter(|u| u.starts_with("file:"))?;
+        reqwest::Url::parse(url).ok()?.to_file_path().ok()
+    }
+
+    /// Identity used when comparing entries across manifests: the project
+    /// when known, otherwise the filename.
+    pub fn key(&self) -> &str {
+        self.project_id().unwrap_or(&self.filename)
+    }
+
+    /// Where the entry lands on disk, the directory of the world for a
+    /// world, named after its zip unless `install_as` names it. `target_dir`
+    /// and `install_as` must stay inside the game directory.
+    pub fn install_path(&self, game_dir: &Path, mods_dir: &Path) -> Result<PathBuf> {
+        let dir = match (&self.target_dir, self.kind) {
+            // the world of a server lies in the game directory itself
+            (Some(target), _) if target == "." => game_dir.to_path_buf(),
+            (Some(target), _) => game_dir.join(contained(target, &self.filename)?),
+            (None, Kind::World) => game_dir.join("saves"),
+            (None, Kind::Mod) => mods_dir.to_path_buf(),
+        };
+        let name = match &self.install_as {
+            Some(name) => {
+                let name = contained(name, &self.filename)?;
+                if name.components().count() != 1 {
+                    return Err(anyhow!("{}: install_as must be a plain file name", self.filename));
+                }
+                name
+            }
+            None if self.kind == Kind::World => {
+                PathBuf::from(self.filename.strip_suffix(".zip").unwrap_or(&self.filename))
+            }
+            None => PathBuf::from(&self.filename),
+        };
+        Ok(dir.join(name))
+    }
+
+    /// Whether the entry installs outside `dir`, a directory relative to
+    /// the game directory with `mods` standing for the mods directory.
+    /// Subdirectories of it count as inside.
+    pub fn misrouted(&self, dir: &str) -> bool {
+        let default = match self.kind {
+            Kind::World => "saves",
+            Kind::Mod => "mods",
+        };
+        let target = self.target_dir.as_deref().map_or(default, |t| t.trim_end_matches('/'));
+        target != dir && !target.starts_with(&format!("{}/", dir))
+    }
+
+    /// Installs the entry into `dir`, as for [`Mod::misrouted`]. Entries
+    /// routed to `saves` become worlds.
+    pub fn route(&mut self, dir: &str) {
+        self.kind = match dir {
+            "saves" => Kind::World,
+            _ => Kind::Mod,
+        };
+        self.target_dir = Some(dir.to_string()).filter(|d| d != "mods" && d != "saves");
+    }
+}
+
+pub fn contained(path: &str, filename: &str) -> Result<PathBuf> {
+    let path = Path::new(path);
+    let escapes = path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(anyhow!("{}: {} must be a relative path inside the instance", filename, path.display()));
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Replaces every `${name}` in `text` with its value from `vars`.
+pub fn substitute(text: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find('}').ok_or_else(|| anyhow!("unclosed placeholder in {}", text))?;
+        let name = &after[..end];
+        let value = vars
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown placeholder ${{{}}} in {}, set it in the pack's variables", name, text))?;
+        out.push_str(value);
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// How to settle two manifests listing the same project with different files.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Strategy {
+    KeepFirst,
+    KeepLast,
+    Fail,
+}
+
+impl Strategy {
+    pub fn parse(value: &str) -> Result<Strategy> {
+        match value {
+            "first" => Ok(Strategy::KeepFirst),
+            "last" => Ok(Strategy::KeepLast),
+            "fail" => Ok(Strategy::Fail),
+            other => Err(anyhow!("unknown merge strategy {}, expected first, last or fail", other)),
+        }
+    }
+}
+
+pub struct Conflict {
+    pub key: String,
+    pub kept: String,
+    pub dropped: String,
+}
+
+/// Either the current manifest object or the bare array of mods written by
+/// older versions of the tool (and by Prism's modlist export).
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ManifestFile {
+    Current(Box<Manifest>),
+    Legacy(Vec<Mod>),
+}
+
+pub struct Loaded {
+    pub manifest: Manifest,
+    pub legacy: bool,
+}
+
+impl Manifest {
+    /// Builds a manifest around legacy entries, filling the header with
+    /// defaults derived from the instance directory.
+    pub fn from_legacy(mods: Vec<Mod>, base_dir: &Path) -> Manifest {
+        let name = base_dir
+            .canonicalize()
+            .ok()
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+            .unwrap_or_else(|| "modpack".to_string());
+
+        Manifest {
+            format_version: FORMAT_VERSION,
+            extends: None,
+            remove: Vec::new(),
+            pack: PackHeader {
+                name,
+                version: "0.1.0".to_string(),
+                minecraft: None,
+                loader: None,
+                launch: None,
+                extensions: BTreeMap::new(),
+                variables: BTreeMap::new(),
+                opt_in_tags: Vec::new(),
+                stamp: None,
+            },
+            mods,
+        }
+    }
+}
+
+/// Unions the entries of `other` into `base`. Entries for the same project
+/// with differing files are resolved by `strategy`, every such case is
+/// returned so callers can report it.
+pub fn merge_into(base: &mut Manifest, other: Manifest, strategy: Strategy) -> Result<Vec<Conflict>> {
+    let mut conflicts = Vec::new();
+
+    for m in other.mods {
+        let Some(existing) = base.mods.iter_mut().find(|e| e.key() == m.key()) else {
+            base.mods.push(m);
+            continue;
+        };
+
+        if existing.filename == m.filename && existing.version == m.version {
+            continue;
+        }
+
+        let conflict = match strategy {
+            Strategy::Fail => {
+                return Err(anyhow!(
+                    "{} is listed as both {} and {}",
+                    m.key(),
+                    existing.filename,
+                    m.filename
+                ))
+            }
+            Strategy::KeepFirst => Conflict {
+                key: m.key().to_string(),
+                kept: existing.filename.clone(),
+                dropped: m.filename.clone(),
+            },
+            Strategy::KeepLast => {
+                let conflict = Conflict {
+                    key: m.key().to_string(),
+                    kept: m.filename.clone(),
+                    dropped: existing.filename.clone(),
+                };
+                *existing = m;
+                conflict
+            }
+        };
+        conflicts.push(conflict);
+    }
+
+    Ok(conflicts)
+}
+
+pub fn parse(contents: &str, base_dir: &Path) -> Result<Loaded> {
+    let file: ManifestFile =
+        serde_json::from_str(contents).context("modlist is not a valid manifest or legacy mod array")?;
+
+    match file {
+        ManifestFile::Current(manifest) => {
+            if manifest.format_version > FORMAT_VERSION {
+                return Err(anyhow!(
+                    "modlist uses format version {} but this build only understands up to {}, please update modpack-sync",
+                    manifest.format_version,
+                    FORMAT_VERSION
+                ));
+            }
+            Ok(Loaded { manifest: *manifest, legacy: false })
+        }
+        ManifestFile::Legacy(mods) => Ok(Loaded {
+            manifest: Manifest::from_legacy(mods, base_dir),
+            legacy: true,
+        }),
+    }
+}
+
+/// A file of [`FRAGMENTS_DIR`]: entries it adds to the modlist and project
+/// ids or filenames of entries it drops.
+#[derive(Deserialize)]
+pub struct Fragment {
+    #[serde(default)]
+    pub remove: Vec<String>,
+    #[serde(default)]
+    pub mods: Vec<Mod>,
+}
+
+/// The fragments of the instance in `base_dir` in filename order with their
+/// paths, none without a [`FRAGMENTS_DIR`].
+pub fn fragments(base_dir: &Path) -> Result<Vec<(PathBuf, Fragment)>> {
+    let dir = base_dir.join(FRAGMENTS_DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .collect();
+    paths.sort();
+    let mut fragments = Vec::new();
+    for path in paths {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        if !matches!(extension.as_deref(), Some("json") | Some("toml")) {
+            continue;
+        }
+        let contents = fs::read_to_string(&path).with_context(|| format!("failed to read modlist fragment {}", path.display()))?;
+        let fragment = match extension.as_deref() {
+            Some("toml") => toml::from_str(&contents).map_err(anyhow::Error::from),
+            _ => serde_json::from_str(&contents).map_err(anyhow::Error::from),
+        }
+        .with_context(|| format!("{} is not a valid modlist fragment", path.display()))?;
+        fragments.push((path, fragment));
+    }
+    Ok(fragments)
+}
+
+pub fn load(path: &Path, base_dir: &Path) -> Result<Loaded> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read modlist {}", path.display()))?;
+    parse(&contents, base_dir)
+}
+
+pub fn to_string(manifest: &Manifest) -> Result<String> {
+    let mut contents = serde_json::to_string_pretty(manifest)?;
+    contents.push('\n');
+    Ok(contents)
+}
+
+/// Writes the modlist, recording it in the audit log when it lies in an
+/// instance.
+pub fn save(path: &Path, manifest: &Manifest) -> Result<()> {
+    let contents = to_string(manifest)?;
+    let before = audit::hash(path);
+    fs::write(path, &contents).with_context(|| format!("failed to write modlist {}", path.display()))?;
+    let after = hash::sha256_bytes(contents.as_bytes());
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    if base_dir.join(STATE_DIR).is_dir() && before.as_deref() != Some(after.as_str()) {
+        audit::record(base_dir, audit::Action::ManifestWritten, path, before.as_deref(), Some(&after));
+    }
+    Ok(())
+}
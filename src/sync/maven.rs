@@ -0,0 +1,153 @@
+use super::auth;
+use super::hash::{Algorithm, Multihash};
+use super::hosts;
+use super::http;
+use super::quarantine::Rejected;
+use super::trace;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// An artifact in a Maven repository. `version` may be a `-SNAPSHOT`, or
+/// `latest`/`release` to take what the repository's metadata points at.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Coordinate {
+    pub repo: String,
+    pub group: String,
+    pub artifact: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classifier: Option<String>,
+}
+
+/// Text of the first `<tag>` element in `xml`. Maven metadata is simple
+/// enough not to need a real parser.
+fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(xml[start..end].trim())
+}
+
+fn get(client: &reqwest::blocking::Client, url: &str) -> Result<http::Buffered> {
+    let response = hosts::send_retrying(|| auth::apply(client.get(url), url), url).with_context(|| format!("request to {} failed", url))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("{} returned {}", url, response.status()));
+    }
+    http::buffered(response)
+}
+
+fn get_text(client: &reqwest::blocking::Client, url: &str) -> Result<String> {
+    Ok(String::from_utf8_lossy(&get(client, url)?).to_string())
+}
+
+impl Coordinate {
+    /// Whether the version moves with new releases, `latest`, `release` or
+    /// a `-SNAPSHOT`.
+    pub fn moving(&self) -> bool {
+        self.version == "latest" || self.version == "release" || self.version.ends_with("-SNAPSHOT")
+    }
+
+    /// Whether `path` still is the jar the coordinate resolves to, by the
+    /// strongest checksum the repository has of it.
+    pub fn is_current(&self, path: &Path) -> Result<bool> {
+        let client = http::files();
+        let url = self.resolve(client)?;
+        let (algorithm, expected) = checksum(client, &url)?;
+        Ok(algorithm.file(path)? == expected)
+    }
+
+    fn base(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            self.repo.trim_end_matches('/'),
+            self.group.replace('.', "/"),
+            self.artifact
+        )
+    }
+
+    /// The concrete version to download, looking `latest` and `release` up
+    /// in the artifact's metadata.
+    fn version(&self, client: &reqwest::blocking::Client) -> Result<String> {
+        if self.version != "latest" && self.version != "release" {
+            return Ok(self.version.clone());
+        }
+        let metadata = get_text(client, &format!("{}/maven-metadata.xml", self.base()))?;
+        element(&metadata, &self.version)
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("maven metadata of {}:{} has no {} version", self.group, self.artifact, self.version))
+    }
+
+    /// Url of the jar. Snapshots are resolved to their newest timestamped
+    /// build through the version's metadata.
+    pub fn resolve(&self, client: &reqwest::blocking::Client) -> Result<String> {
+        let version = self.version(client)?;
+        let dir = format!("{}/{}", self.base(), version);
+        let mut file_version = version.clone();
+
+        if let Some(prefix) = version.strip_suffix("-SNAPSHOT") {
+            let metadata = get_text(client, &format!("{}/maven-metadata.xml", dir))?;
+            let classifier = self.classifier.as_deref().unwrap_or("");
+            let listed = metadata.split("<snapshotVersion>").skip(1).find(|v| {
+                element(v, "extension") == Some("jar") && element(v, "classifier").unwrap_or("") == classifier
+            });
+            file_version = match listed.and_then(|v| element(v, "value")) {
+                Some(value) => value.to_string(),
+                None => {
+                    let timestamp = element(&metadata, "timestamp");
+                    let build = element(&metadata, "buildNumber");
+                    match (timestamp, build) {
+                        (Some(t), Some(b)) => format!("{}-{}-{}", prefix, t, b),
+                        // locally deployed snapshots keep the plain name
+                        _ => version.clone(),
+                    }
+                }
+            };
+        }
+
+        let classifier = self.classifier.as_ref().map(|c| format!("-{}", c)).unwrap_or_default();
+        Ok(format!("{}/{}-{}{}.jar", dir, self.artifact, file_version, classifier))
+    }
+}
+
+/// The strongest checksum file the repository has next to `url`.
+fn checksum(client: &reqwest::blocking::Client, url: &str) -> Result<(Algorithm, String)> {
+    for algorithm in Algorithm::ALL {
+        let Ok(expected) = get_text(client, &format!("{}.{}", url, algorithm.name())) else {
+            continue;
+        };
+        let expected = expected.split_whitespace().next().unwrap_or_default().to_lowercase();
+        trace!("{}: repository {} is {}", url, algorithm.name(), expected);
+        return Ok((algorithm, expected));
+    }
+    Err(anyhow!("repository has no checksum for {}", url))
+}
+
+/// Checks `bytes` against the strongest checksum file the repository has
+/// next to `url`, returning that checksum.
+fn verify(client: &reqwest::blocking::Client, url: &str, bytes: &[u8]) -> Result<Multihash> {
+    let (algorithm, expected) = checksum(client, url)?;
+    if algorithm.bytes(bytes) != expected {
+        return Err(anyhow!("{} checksum of {} does not match the repository", algorithm.name(), url));
+    }
+    Ok(Multihash::from([(algorithm, expected)]))
+}
+
+/// Downloads and verifies the artifact into `dest`, returning its size and
+/// the repository's checksum.
+pub fn download(coordinate: &Coordinate, dest: &Path) -> Result<(u64, Multihash)> {
+    let client = http::files();
+    let url = coordinate.resolve(client)?;
+    let bytes = get(client, &url)?;
+    trace!("{}:{}:{}: resolved to {}, {} bytes", coordinate.group, coordinate.artifact, coordinate.version, url, bytes.len());
+    let verified = verify(client, &url, &bytes);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(dest, &bytes).with_context(|| format!("failed to write {}", dest.display()))?;
+    // saved anyway so it can be looked at in quarantine
+    let checksum = verified.map_err(|e| anyhow!(Rejected(format!("{:#}", e))))?;
+    Ok((bytes.len() as u64, checksum))
+}
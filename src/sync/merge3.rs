@@ -0,0 +1,150 @@
+/// Largest base × side line product diffed, bigger files are reported as
+/// conflicting rather than spending seconds in the LCS table.
+const MAX_CELLS: usize = 4_000_000;
+
+/// For every line of `base`, the index of the line of `other` it is kept as
+/// in a longest common subsequence.
+fn matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let (n, m) = (base.len(), other.len());
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[at(i, j)] = if base[i] == other[j] {
+                lcs[at(i + 1, j + 1)] + 1
+            } else {
+                lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut matched = vec![None; n];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if base[i] == other[j] {
+            matched[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if lcs[at(i + 1, j)] >= lcs[at(i, j + 1)] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matched
+}
+
+/// Line based three-way merge of `local` and `upstream` edits to `base`.
+/// Returns `None` when both sides changed the same region differently.
+pub fn merge(base: &str, local: &str, upstream: &str) -> Option<String> {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let local: Vec<&str> = local.split_inclusive('\n').collect();
+    let upstream: Vec<&str> = upstream.split_inclusive('\n').collect();
+    if base.len() * local.len().max(upstream.len()) > MAX_CELLS {
+        return None;
+    }
+
+    let to_local = matches(&base, &local);
+    let to_upstream = matches(&base, &upstream);
+    let mut merged = String::new();
+    let (mut i, mut a, mut b) = (0, 0, 0);
+
+    while i < base.len() || a < local.len() || b < upstream.len() {
+        if i < base.len() && to_local[i] == Some(a) && to_upstream[i] == Some(b) {
+            merged.push_str(base[i]);
+            i += 1;
+            a += 1;
+            b += 1;
+            continue;
+        }
+
+        // the next base line both sides kept closes the differing chunk
+        let next = (i..base.len()).find(|&j| to_local[j].is_some() && to_upstream[j].is_some());
+        let (j, a_end, b_end) = match next {
+            Some(j) => (j, to_local[j].unwrap_or(a), to_upstream[j].unwrap_or(b)),
+            None => (base.len(), local.len(), upstream.len()),
+        };
+
+        let original = &base[i..j];
+        let ours = &local[a..a_end];
+        let theirs = &upstream[b..b_end];
+        let chosen = if ours == original {
+            theirs
+        } else if theirs == original || ours == theirs {
+            ours
+        } else {
+            return None;
+        };
+        chosen.iter().for_each(|line| merged.push_str(line));
+        (i, a, b) = (j, a_end, b_end);
+    }
+
+    Some(merged)
+}
+
+/// Hunks of a unified diff of `old` to `new` with `context` lines around
+/// the changes, empty when they are the same. Returns `None` for files too
+/// large to diff.
+pub fn unified(old: &str, new: &str, context: usize) -> Option<String> {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    if old.len() * new.len() > MAX_CELLS {
+        return None;
+    }
+
+    let to_new = matches(&old, &new);
+    let mut ops: Vec<(char, &str)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        match to_new.get(i) {
+            Some(Some(k)) if *k == j => {
+                ops.push((' ', old[i]));
+                i += 1;
+                j += 1;
+            }
+            Some(None) => {
+                ops.push(('-', old[i]));
+                i += 1;
+            }
+            _ => {
+                ops.push(('+', new[j]));
+                j += 1;
+            }
+        }
+    }
+
+    // changes closer than twice the context share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (at, _) in ops.iter().enumerate().filter(|(_, (tag, _))| *tag != ' ') {
+        let (start, end) = (at.saturating_sub(context), (at + context + 1).min(ops.len()));
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    let mut out = String::new();
+    for (start, end) in hunks {
+        let before = &ops[..start];
+        let lines = &ops[start..end];
+        let old_start = before.iter().filter(|(tag, _)| *tag != '+').count();
+        let new_start = before.iter().filter(|(tag, _)| *tag != '-').count();
+        let old_len = lines.iter().filter(|(tag, _)| *tag != '+').count();
+        let new_len = lines.iter().filter(|(tag, _)| *tag != '-').count();
+        // an empty side is numbered by the line before it
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + usize::from(old_len > 0),
+            old_len,
+            new_start + usize::from(new_len > 0),
+            new_len
+        ));
+        for (tag, line) in lines {
+            out.push(*tag);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    Some(out)
+}
@@ -1,304 +1,2393 @@
-mod curse_files;
-use chrono::Local;
-use serde::{Deserialize, Serialize};
-use anyhow::{anyhow, Ok, Result};
-use reqwest;
-use reqwest::header::{HeaderMap, HeaderValue};
-use std::collections::HashMap;
-use std::env;
-use std::fs::{self, create_dir_all, File, OpenOptions};
-use std::io;
-use std::io::{copy, Write};
-use std::path::Path;
+pub mod alternatives;
+pub mod audit;
+pub mod auth;
+pub mod backup;
+pub mod bisect;
+pub mod bundle;
+pub mod cache;
+pub mod changes;
+pub mod capture;
+pub mod conditions;
+pub mod conflicts;
+pub mod control;
+pub mod crash;
+pub mod curse_files;
+pub mod dashboard;
+pub mod decide;
+pub mod deps;
+pub mod dist;
+pub mod dns;
+pub mod durable;
+pub mod events;
+pub mod extends;
+pub mod failure;
+pub mod fleet;
+pub mod ftb;
+pub mod golden;
+pub mod hash;
+pub mod hosts;
+pub mod http;
+pub mod httpd;
+pub mod index;
+pub mod jar;
+pub mod java;
+pub mod launcher;
+pub mod links;
+pub mod lock;
+pub mod log;
+pub mod manifest;
+pub mod maven;
+pub mod merge3;
+pub mod modrinth;
+pub mod notify;
+pub mod overrides;
+pub mod panel;
+pub mod owners;
+pub mod peers;
+pub mod perms;
+pub mod ping;
+pub mod plan;
+pub mod policy;
+pub mod prefetch;
+pub mod quarantine;
+pub mod report;
+pub mod rcon;
+pub mod redistribution;
+pub mod restart;
+pub mod s3;
+pub mod sbom;
+pub mod scan;
+pub mod schedule;
+pub mod script;
+pub mod secrets;
+pub mod serverpack;
+pub mod settings;
+pub mod signature;
+pub mod smtp;
+pub mod staging;
+pub mod stamp;
+pub mod stats;
+pub mod state;
+pub mod storage;
+pub mod unpack;
+pub mod usage;
+pub mod version;
+pub mod warnings;
+use crate::cli::Args;
+use crate::i18n::t;
+use crate::prompt;
+use anyhow::{anyhow, Context, Ok, Result};
+use manifest::{Mod, PackHeader};
+use plan::{Action, Plan, SkipReason, Source};
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, create_dir_all, File};
+use std::io::{self, IsTerminal, Read};
+use std::io::copy;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
 
 pub struct Config {
     pub api_key: String,
     pub base_dir: String,
+    pub game_dir: String,
     pub mods_dir: String,
     pub mods_file: String,
+    /// Manifest given with `--modlist`, read from stdin for `-`, used
+    /// instead of the instance's modlist file.
+    pub modlist: Option<String>,
+    /// Placeholder values given with `--var name=value`.
+    pub vars: Vec<(String, String)>,
+    pub reports: Vec<report::Target>,
+    /// Replace managed files that were changed locally without asking.
+    pub force: bool,
+    /// Delete files past the approval thresholds without asking.
+    pub yes: bool,
+    pub approval: settings::ApprovalSettings,
+    /// Number of backups to keep when a backup is taken before applying.
+    pub backup: Option<usize>,
+    pub world_backup: Option<settings::WorldBackupSettings>,
+    /// Published pack to fetch the manifest and overrides from first.
+    pub source: Option<String>,
+    pub channel: Option<String>,
+    /// Paths, relative to the game directory, overrides never replace.
+    pub protected: Vec<String>,
+    /// Directories, relative to the game directory, left entirely to the
+    /// player.
+    pub unmanaged: Vec<String>,
+    pub side: settings::Side,
+    /// Write the pack's JVM settings to the launcher or server arguments.
+    pub apply_launch: bool,
+    pub symlinks: settings::Symlinks,
+    /// Mode and ownership of installed files.
+    pub permissions: Option<settings::PermissionSettings>,
+    /// Downloads running at once, fewer against struggling hosts.
+    pub jobs: usize,
+    pub decisions: settings::DecisionSettings,
+    pub signing: settings::SigningSettings,
+    /// Command every downloaded file is checked with before it is installed.
+    pub scan_hook: Option<String>,
+    /// Urls of `serve` instances pinned files are fetched from first.
+    pub peers: Vec<String>,
+    /// Token the peers are asked with, for those with tokens of their own.
+    pub peer_token: Option<String>,
+    /// Policies of the machine and the instance plans must keep to.
+    pub policies: Vec<policy::Policy>,
+    /// Directory downloads are written to until complete and checked.
+    pub staging_dir: PathBuf,
+    /// Where the files of the instance are put and deleted.
+    pub storage: Box<dyn storage::Storage>,
+    /// MiB downloaded files may take up in memory at once.
+    pub download_memory_mb: Option<u64>,
+    /// MiB from which files are downloaded over several connections.
+    pub split_download_mb: Option<u64>,
+    /// Connections large files are downloaded over.
+    pub download_connections: Option<usize>,
+    /// Whether installed files and the state are flushed to disk and the
+    /// installs journaled, to recover from a crash.
+    pub durable: bool,
+    /// Whether the peers are ordered by their speed before large syncs.
+    pub probe_peers: bool,
+    /// Where the outcome of syncs is reported to.
+    pub fleet: Option<settings::FleetSettings>,
+    /// Whether failed CurseForge downloads are retried from Modrinth.
+    pub fallback: bool,
+    /// Whether the jars mods bundle are extracted into the mods directory.
+    pub extract_bundled: bool,
+    /// Whether the post-install commands of entries are run.
+    pub allow_scripts: bool,
+    /// Whether entries without a file for the pack get alternatives searched
+    /// for, listed in the reports.
+    pub suggest: bool,
+    /// Warnings kept quiet.
+    pub suppress: Vec<warnings::Code>,
+    /// Warnings that fail the run, for validating packs in CI.
+    pub deny: Vec<warnings::Code>,
+    /// CurseForge projects, by id, whose change of owner the player accepted
+    /// with `--acknowledge-owner`.
+    pub acknowledged: Vec<String>,
+    /// Tags of entries to turn on and off from this sync on.
+    pub with: Vec<String>,
+    pub without: Vec<String>,
+    pub mode: Mode,
 }
 
-#[derive(Serialize, Deserialize)]
-struct Mod {
-    filename: String,
-    name: String,
-    url: Option<String>,
-    version: String,
+/// What a run does with the plan it makes.
+#[derive(Clone, PartialEq)]
+pub enum Mode {
+    /// Apply it right away.
+    Sync,
+    /// Only write it to the file for review, nothing in the instance changes.
+    Plan(PathBuf),
+    /// Apply the plan stored in the file instead, if the instance still
+    /// plans to the same changes.
+    Apply(PathBuf),
+    /// Download the files it installs ahead of time for the next sync,
+    /// nothing in the instance changes.
+    Prefetch,
+    /// Print it with diffs of the overrides it changes, nothing in the
+    /// instance changes.
+    DryRun,
+    /// Only install the entries of the retry queue, from the pack as last
+    /// pulled.
+    Retry,
+}
+
+pub(crate) use log::{console, log_to_file, trace};
+
+/// How far a sync got, kept around so a failure can be reported with context.
+#[derive(Default)]
+pub struct Run {
+    pub pack: Option<PackHeader>,
+    pub plan: Option<Plan>,
+    pub installed: Vec<Installed>,
+    pub removed: Vec<String>,
+    pub failures: Vec<Failure>,
+    /// Warnings of the run, without the suppressed ones.
+    pub warnings: Vec<warnings::Warning>,
+    /// Warnings left out for their code.
+    pub suppressed: usize,
+    /// Totals of the run's events.
+    pub stats: events::Stats,
+}
+
+#[derive(Serialize)]
+pub struct Installed {
+    /// CurseForge project and file, unset for other providers.
+    pub project_id: Option<String>,
+    pub file_id: Option<u64>,
+    pub filename: String,
+    /// File name of the version this install replaced, for updates.
+    pub replaced: Option<String>,
+    pub bytes: u64,
+    /// Provider that served the file, which differs from the entry's when
+    /// its download fell back to another.
+    pub provider: &'static str,
+    /// Where the file was installed, relative to the game directory.
+    pub path: String,
+    /// Install whose download the file was copied from when several entries
+    /// need the same file, or file another instance downloaded, it was
+    /// downloaded only once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copy_of: Option<String>,
 }
 
-#[derive(Deserialize)]
-struct ModMeta {
+#[derive(Serialize)]
+pub struct Failure {
+    pub filename: String,
+    pub error: String,
+    pub kind: FailureKind,
+    /// Projects with a file for the pack suggested in place of the entry's,
+    /// with `--suggest` when CurseForge has none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    Error,
+    /// The author disabled third party downloads of the project.
+    DistributionDenied,
+    /// A `manual` entry's file is missing or doesn't match its hash.
+    Manual,
+}
+
+impl FailureKind {
+    /// Whether the player has to download the file themselves.
+    pub fn needs_download(self) -> bool {
+        self != FailureKind::Error
+    }
+}
+
+/// Download refused because the project's author only allows downloads from
+/// the CurseForge website and app.
+#[derive(Debug)]
+struct DistributionDenied {
     filename: String,
-    update: Update
+    project_id: String,
 }
 
-#[derive(Deserialize)]
-struct Update {
-    curseforge: CurseForge,
+impl std::fmt::Display for DistributionDenied {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the author does not allow third party downloads, download {} from {} and set `manual: true` on the entry",
+            self.filename,
+            project_page(&self.project_id)
+        )
+    }
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct CurseForge {
-    project_id: u64,
+impl std::error::Error for DistributionDenied {}
+
+/// CurseForge has no file of the entry's name for the pack.
+#[derive(Debug)]
+struct NoFile(String);
+
+impl std::fmt::Display for NoFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, " -----> failed to find file id for file {}", self.0)
+    }
+}
+
+impl std::error::Error for NoFile {}
+
+/// Install of a file built for other loaders declined, which the entry's
+/// other providers serve just the same.
+#[derive(Debug)]
+struct LoaderDeclined(String);
+
+impl std::fmt::Display for LoaderDeclined {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for LoaderDeclined {}
+
+fn project_page(project_id: &str) -> String {
+    format!("https://www.curseforge.com/projects/{}", project_id)
+}
+
+/// The mod set an instance should end up with.
+pub struct Effective {
+    pub pack: PackHeader,
+    pub mods: Vec<Mod>,
+    /// Entries whose `when` condition excludes them for this pack variant.
+    pub skipped: Vec<Mod>,
+    /// Entries left out by a tag that is turned off, with the tag.
+    pub disabled: Vec<(Mod, String)>,
+    pub layers: Vec<String>,
+    pub legacy: bool,
+}
+
+/// Loads the modlist of an instance, flattens its `extends` layers, merges
+/// the fragments of its `modlist.d` and applies entry conditions and the
+/// tags last synced with.
+pub fn load_effective(base_dir: &Path, mods_file: &str) -> Result<Effective> {
+    let modlist = base_dir.join(mods_file);
+    let loaded = manifest::load(&modlist, base_dir)?;
+    let tags = state::State::load(base_dir)?.tags;
+    effective(loaded, &modlist.to_string_lossy(), base_dir, &[], &tags)
+}
+
+/// [`load_effective`] for a manifest that was read already, `source` being
+/// where it came from for resolving relative parents. `vars` override the
+/// placeholder values of the pack header.
+fn effective(
+    loaded: manifest::Loaded,
+    source: &str,
+    base_dir: &Path,
+    vars: &[(String, String)],
+    tags: &conditions::Tags,
+) -> Result<Effective> {
+    let mut flattened = extends::flatten(loaded.manifest, source, base_dir)?;
+    // fragments go on top of every layer, later files replacing the entries
+    // of earlier ones
+    for (path, fragment) in manifest::fragments(base_dir)? {
+        let layer = &mut flattened.manifest;
+        layer.mods.retain(|m| !fragment.remove.iter().any(|r| r == m.key() || *r == m.filename));
+        for m in fragment.mods {
+            match layer.mods.iter_mut().find(|e| e.key() == m.key()) {
+                Some(existing) => *existing = m,
+                None => layer.mods.push(m),
+            }
+        }
+        flattened.layers.push(path.to_string_lossy().to_string());
+    }
+    let values = flattened.manifest.pack.variables(vars);
+    for m in flattened.manifest.mods.iter_mut() {
+        m.substitute(&values)?;
+    }
+    let side = settings::InstanceSettings::load(base_dir)?.side;
+    let target = conditions::Target::of(&flattened.manifest.pack, side);
+    let (mut mods, skipped) = conditions::partition(flattened.manifest.mods, &target)?;
+    if side == settings::Side::Server {
+        // a server runs the one world server.properties names
+        let level = rcon::server_property(base_dir, "level-name");
+        for m in mods.iter_mut().filter(|m| m.kind == manifest::Kind::World) {
+            m.target_dir = Some(".".to_string());
+            m.install_as = Some(level.clone().unwrap_or_else(|| "world".to_string()));
+        }
+    }
+    let opt_in = &flattened.manifest.pack.opt_in_tags;
+    let (mods, disabled): (Vec<Mod>, Vec<Mod>) = mods.into_iter().partition(|m| tags.off(&m.tags, opt_in).is_none());
+    let disabled = disabled
+        .into_iter()
+        .map(|m| {
+            let tag = tags.off(&m.tags, opt_in).unwrap_or_default().to_string();
+            (m, tag)
+        })
+        .collect();
+
+    Ok(Effective {
+        pack: flattened.manifest.pack,
+        mods,
+        skipped,
+        disabled,
+        layers: flattened.layers,
+        legacy: loaded.legacy,
+    })
 }
 
 pub fn run(config: Config) -> Result<()> {
-    let _ = fs::remove_file(Path::new("sync.log"));
+    execute(config).1
+}
+
+/// [`run`], also handing back what the sync did for summaries.
+pub fn execute(config: Config) -> (Run, Result<()>) {
+    let mut run = Run::default();
+    let log = match log::start_run(Path::new(&config.base_dir)) {
+        std::result::Result::Ok(log) => log,
+        Err(e) => return (run, Err(e)),
+    };
     let _ = log_to_file("[INFO] Starting new run of modpack-sync...");
     let _ = log_to_file(&format!("[INFO]    mods_dir={}", &config.mods_dir));
     let _ = log_to_file(&format!("[INFO]    base_dir={}", &config.base_dir));
     let _ = log_to_file(&format!("[INFO]    mods_file={}", &config.mods_file));
-    return sync_mods(
-        &config.mods_dir,
-        &config.base_dir,
-        &config.mods_file,
-        &config.api_key,
+    let started = Instant::now();
+    let _bus = events::enter(Some(Arc::new(events::Bus::default())));
+    let collector = Arc::new(events::Collector::default());
+    let subscription = events::subscribe(collector.clone());
+    let printer = log::plain().then(|| events::subscribe(Arc::new(events::Printer)));
+    warnings::start(&config.suppress);
+    let result = sync_mods(&config, &mut run);
+    events::emit(events::Event::RunFinished {
+        elapsed: started.elapsed(),
+        ok: result.is_ok() && run.failures.is_empty(),
+    });
+    drop(subscription);
+    drop(printer);
+    run.stats = collector.stats();
+    log_hosts(&run.stats);
+    (run.warnings, run.suppressed) = warnings::finish();
+    let denied: Vec<String> = warnings::Code::ALL
+        .into_iter()
+        .filter(|c| config.deny.contains(c))
+        .filter_map(|c| match run.warnings.iter().filter(|w| w.code == c).count() {
+            0 => None,
+            1 => Some(c.to_string()),
+            n => Some(format!("{} ({} times)", c, n)),
+        })
+        .collect();
+    let result = match result {
+        std::result::Result::Ok(()) if !denied.is_empty() => Err(anyhow!("denied warnings were raised: {}", denied.join(", "))),
+        result => result,
+    };
+    if log::tracing() {
+        console!("[INFO] {}", t!("sync-traced", log = log.display()));
+    }
+    let base_dir = Path::new(&config.base_dir);
+    for target in config.reports.iter() {
+        if let Err(e) = report::write(target, &run, &config.api_key) {
+            let _ = log_to_file(&format!("[ERR!] failed to write report {}: {:#}", target.path.display(), e));
+        }
+    }
+    if let Some(settings) = config.fleet.as_ref().filter(|_| matches!(config.mode, Mode::Sync | Mode::Apply(_) | Mode::Retry)) {
+        report_to_fleet(settings, &config, &run, result.is_ok() && run.failures.is_empty());
+    }
+    if matches!(config.mode, Mode::Sync | Mode::Apply(_) | Mode::Prefetch | Mode::Retry) {
+        usage::record(base_dir, &run, result.is_ok() && run.failures.is_empty());
+    }
+    match &result {
+        std::result::Result::Ok(()) if run.failures.is_empty() => {
+            failure::clear(base_dir);
+            let _ = log_to_file("[INFO] Run finished successfully");
+            if run.plan.is_some() {
+                console!("[INFO] {}", summary(&run.stats));
+            }
+        }
+        std::result::Result::Ok(()) => {
+            let _ = failure::write(base_dir, None, &run);
+            let _ = log_to_file(&format!("[ERR!] Run finished with {} failed mods", run.failures.len()));
+            let (manual, failed): (Vec<&Failure>, Vec<&Failure>) =
+                run.failures.iter().partition(|f| f.kind.needs_download());
+            for f in manual.iter() {
+                console!("[WARN] {}", t!("sync-manual-download", file = f.filename, error = f.error));
+            }
+            if !failed.is_empty() {
+                console!(
+                    "[WARN] {}",
+                    t!("sync-failed-mods", count = failed.len(), report = failure::path(base_dir).display())
+                );
+            }
+        }
+        Err(e) => {
+            let _ = failure::write(base_dir, Some(e), &run);
+            let _ = log_to_file(&format!("[ERR!] Run failed: {:#}", e));
+        }
+    }
+    (run, result)
+}
+
+/// Tells the maintainer's `serve` instance which version the instance is
+/// on and whether it is intact. A report that doesn't arrive is only
+/// logged, it never fails the sync.
+fn report_to_fleet(settings: &settings::FleetSettings, config: &Config, run: &Run, synced: bool) {
+    let base_dir = Path::new(&config.base_dir);
+    let problems = state::State::load(base_dir).map_or(0, |state| fleet::problems(Path::new(&config.game_dir), &state));
+    let report = fleet::Report {
+        client_id: dist::client_id(base_dir),
+        name: settings.name.clone(),
+        pack: run.pack.as_ref().map(|p| p.name.clone()),
+        version: run.pack.as_ref().map(|p| p.version.clone()),
+        synced,
+        problems,
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        reported_at: chrono::Utc::now(),
+    };
+    match fleet::send(settings, &report) {
+        std::result::Result::Ok(()) => {
+            let _ = log_to_file(&format!("[INFO] Reported the sync to {}", settings.report_to));
+        }
+        Err(e) => {
+            let _ = log_to_file(&format!("[WARN] {:#}", e));
+        }
+    }
+}
+
+/// One line summing up an applied run for the console.
+fn summary(stats: &events::Stats) -> String {
+    let mut line = t!(
+        "sync-summary",
+        seconds = format!("{:.1}", stats.seconds),
+        downloads = stats.downloads,
+        size = report::human_size(stats.bytes),
+        removed = stats.removed
     );
+    if stats.retries > 0 {
+        line.push_str(&t!("sync-summary-retries", retries = stats.retries));
+    }
+    line
 }
 
-fn log_to_file(message: &str) -> Result<()> {
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("sync.log")?;
+/// Logs how each download host did, warning about the ones that failed or
+/// throttled.
+fn log_hosts(stats: &events::Stats) {
+    for (name, host) in stats.hosts.iter() {
+        let _ = log_to_file(&format!(
+            "[INFO] {}: {} files, {} at {}/s, {} failed, {} retried",
+            name,
+            host.downloads,
+            report::human_size(host.bytes),
+            report::human_size(host.throughput() as u64),
+            host.failures,
+            host.retries
+        ));
+        if host.failures > 0 || host.retries > 0 {
+            warnings::warn(
+                warnings::Code::HostStruggled,
+                &t!("sync-host-struggled", host = name, failures = host.failures, retries = host.retries),
+            );
+        }
+    }
+}
 
-    let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+fn sync_mods(config: &Config, run: &mut Run) -> Result<()> {
+    let base_dir = Path::new(&config.base_dir);
+    if config.durable {
+        durable::enable();
+    }
+    if let Some(mb) = config.download_memory_mb {
+        http::limit_buffers(mb);
+    }
+    if config.split_download_mb.is_some() || config.download_connections.is_some() {
+        http::split_downloads(
+            config.split_download_mb.unwrap_or(http::DEFAULT_SPLIT_MB),
+            config.download_connections.unwrap_or(http::DEFAULT_CONNECTIONS),
+        );
+    }
+    let mods_dir = Path::new(&config.mods_dir);
+    let _ = stage_dir(config.storage.as_ref(), &config.mods_dir);
+    // reviewed plans are made from and applied to the modlist as it is
+    // so is a modlist given on the command line
+    let pulls = matches!(config.mode, Mode::Sync | Mode::Prefetch | Mode::DryRun) && config.modlist.is_none();
+    let source = config.source.as_ref().filter(|_| pulls);
+    // a prefetch or dry run pulls into a copy, the instance's manifest
+    // changes when the sync applying the update pulls it
+    let pack_dir = match config.mode {
+        Mode::Prefetch if source.is_some() => prefetch::dir(base_dir).join("pack"),
+        Mode::DryRun if source.is_some() => base_dir.join(log::STATE_DIR).join("dry-run"),
+        _ => base_dir.to_path_buf(),
+    };
+    if let Some(source) = source {
+        let _ = log_to_file(&format!("[INFO] Fetching the published pack from {}", source));
+        let mut backend = dist::Backend::parse(source)?;
+        if let Some(channel) = &config.channel {
+            backend = backend.channel(channel);
+        }
+        fs::create_dir_all(&pack_dir)?;
+        match dist::pull(&pack_dir, &config.mods_file, &backend, config.channel.as_deref())? {
+            dist::Outcome::UpToDate(version) => {
+                let _ = log_to_file(&format!("[INFO] pack {} is up to date", version));
+            }
+            dist::Outcome::Updated { to, .. } if config.mode == Mode::Prefetch => {
+                let _ = log_to_file(&format!("[INFO] prefetching the update of the pack to {}", to));
+            }
+            dist::Outcome::Updated { to, .. } if config.mode == Mode::DryRun => {
+                console!("[INFO] {}", t!("sync-dry-run-update", to = to));
+            }
+            dist::Outcome::Updated { from, to } => {
+                let from = from.unwrap_or_else(|| "nothing".to_string());
+                console!("[INFO] {}", t!("sync-pack-updated", from = from, to = to));
+                let _ = log_to_file(&format!("[INFO] updated the pack {} -> {}", from, to));
+            }
+        }
+    }
+    let mut tags = state::State::load(base_dir)?.tags;
+    tags.pick(&config.with, &config.without);
+    let effective = match &config.modlist {
+        Some(contents) => {
+            let source = base_dir.join("-").to_string_lossy().to_string();
+            effective(manifest::parse(contents, base_dir)?, &source, base_dir, &config.vars, &tags)?
+        }
+        None => {
+            let modlist = pack_dir.join(&config.mods_file);
+            let loaded = manifest::load(&modlist, &pack_dir)?;
+            effective(loaded, &modlist.to_string_lossy(), base_dir, &config.vars, &tags)?
+        }
+    };
+    if effective.legacy {
+        let _ = log_to_file("[WARN] modlist uses the legacy bare-array format, run `modpack-sync migrate` to upgrade it");
+    }
+    if effective.layers.len() > 1 {
+        let _ = log_to_file(&format!("[INFO]    layers={}", effective.layers.join(" -> ")));
+    }
+    for m in effective.skipped.iter() {
+        let _ = log_to_file(&format!("[INFO] Skipping mod not meant for this pack variant: {}", &m.filename));
+    }
+    for (m, tag) in effective.disabled.iter() {
+        let _ = log_to_file(&format!("[INFO] Skipping mod tagged {}, which is turned off: {}", tag, &m.filename));
+    }
+    run.pack = Some(effective.pack.clone());
+    let game_dir = Path::new(&config.game_dir);
+    let unmanaged = |path: &Path| overrides::inside_any(&state::key(game_dir, path), &config.unmanaged);
+    let (mods, inside): (Vec<Mod>, Vec<Mod>) = effective
+        .mods
+        .into_iter()
+        .partition(|m| m.install_path(game_dir, mods_dir).map_or(true, |p| !unmanaged(&p)));
+    for m in inside.iter() {
+        let _ = log_to_file(&format!("[WARN] Skipping mod installed into an unmanaged directory: {}", &m.filename));
+        warnings::warn(warnings::Code::Unmanaged, &t!("sync-unmanaged-entry", file = &m.filename));
+    }
+    let today = chrono::Utc::now().date_naive();
+    for m in mods.iter() {
+        if let Some(until) = m.hold_expired(today) {
+            let _ = log_to_file(&format!("[WARN] The hold of {} on {} ended {}", m.name, m.filename, until));
+            warnings::warn(warnings::Code::HoldExpired, &t!("sync-hold-expired", name = &m.name, until = until.to_string()));
+        }
+    }
 
-    writeln!(file, "[{}] {}", now, message)?;
+    let launcher = launcher::Launcher::detect(base_dir);
+    let metadata = launcher.metadata(base_dir, mods_dir)?;
+    let mut state = state::State::load(base_dir)?;
+    if state.bisect.is_some() {
+        return Err(anyhow!("a bisect is disabling mods of the instance, finish it or run `modpack-sync bisect reset` first"));
+    }
+    if matches!(config.mode, Mode::Sync | Mode::Apply(_) | Mode::Retry) {
+        if let Some((started_at, deleted)) = durable::Journal::recover(base_dir, game_dir, &config.staging_dir, &mut state)? {
+            let _ = log_to_file(&format!("[WARN] the sync started {} was interrupted, deleted {} files it left behind", started_at, deleted));
+            console!("[WARN] {}", t!("sync-interrupted", started = started_at.format("%Y-%m-%d %H:%M").to_string(), count = deleted));
+            state.save(base_dir)?;
+            durable::Journal::finish(base_dir);
+        }
+    }
+    let mods = match config.mode {
+        Mode::Retry => {
+            state.retry.retain(|filename, _| mods.iter().any(|m| &m.filename == filename));
+            if state.retry.is_empty() {
+                console!("[INFO] {}", t!("sync-retry-empty"));
+                return state.save(base_dir);
+            }
+            let _ = log_to_file(&format!("[INFO] Retrying {} entries that failed before", state.retry.len()));
+            mods.into_iter().filter(|m| state.retry.contains_key(&m.filename)).collect()
+        }
+        _ => mods,
+    };
+    if metadata.is_empty() && state.files.is_empty() && config.mode == Mode::Sync {
+        console!("{}", t!("sync-fresh-start"));
+        let manual: Vec<PathBuf> = mods
+            .iter()
+            .filter(|m| m.manual)
+            .filter_map(|m| m.install_path(Path::new(&config.game_dir), mods_dir).ok())
+            .collect();
+        let mut stale = fresh_start_files(base_dir, mods_dir, &manual, config.symlinks).unwrap_or_default();
+        stale.retain(|p| !unmanaged(p));
+        approve_removals(&stale, &state, config)?;
+        for path in stale {
+            let before = audit::hash(&path);
+            if config.storage.remove_file(&path).is_ok() {
+                audit::record(base_dir, audit::Action::Deleted, &path, before.as_deref(), None);
+            }
+        }
+    }
+
+    let mut managed_dirs = effective.pack.managed_dirs(game_dir, mods_dir)?;
+    managed_dirs.retain(|(dir, _)| !unmanaged(dir));
+    let resolving = Instant::now();
+    events::emit(events::Event::ResolutionStarted { mods: mods.len() });
+    let moved = plan::moved_artifacts(&mods, game_dir, mods_dir)?;
+    let lookups = plan::Lookups { metadata: &metadata, moved: &moved };
+    let mut plan = plan::build(&mods, game_dir, mods_dir, &managed_dirs, &lookups, &state, config.symlinks)?;
+    // files recorded before their directory was declared unmanaged stay,
+    // they are the player's now
+    plan.actions.retain(|action| match action {
+        Action::Remove { path } if unmanaged(path) => {
+            let _ = log_to_file(&format!("[INFO] No longer managing {}, it is in an unmanaged directory", path.display()));
+            state.forget(game_dir, path);
+            false
+        }
+        // the rest of the pack isn't planned, its files stay
+        Action::Remove { .. } => config.mode != Mode::Retry,
+        _ => true,
+    });
+    events::emit(events::Event::ResolutionFinished {
+        actions: plan.actions.len(),
+        elapsed: resolving.elapsed(),
+    });
+    for action in plan.actions.iter() {
+        if let Action::Skip { filename, reason: SkipReason::MissingUrl } = action {
+            warnings::warn(warnings::Code::MissingUrl, &t!("sync-missing-url", file = filename));
+        }
+    }
+    if !config.policies.is_empty() {
+        let lock = lock::Lockfile::load(base_dir).ok().flatten().unwrap_or_default();
+        let filter = curse_files::Filter::new(effective.pack.minecraft.as_deref(), effective.pack.loader.as_deref());
+        let overrides = overrides::shipped(base_dir)?;
+        let pack = policy::Pack { mods: &mods, overrides: &overrides, lock: &lock, game_dir, mods_dir };
+        for policy in config.policies.iter() {
+            let violations = policy.check(&plan, &pack, &filter, &config.api_key);
+            for violation in violations.iter() {
+                let _ = log_to_file(&format!("[ERR!] {} breaks the policy {}: {}", violation.filename, policy.location, violation.reason));
+                console!("[WARN] {}", t!("sync-policy-violation", file = &violation.filename, reason = &violation.reason));
+            }
+            let entries: HashSet<&str> = violations.iter().map(|v| v.filename.as_str()).collect();
+            if !entries.is_empty() {
+                return Err(anyhow!("{} entries break the policy {}, nothing was changed", entries.len(), policy.location));
+            }
+        }
+    }
+    let plan = match &config.mode {
+        Mode::Sync | Mode::Retry => plan,
+        Mode::Plan(out) => {
+            review_owners(&plan, base_dir, config, &state)?;
+            let mut file = plan::PlanFile::new(base_dir, config.planned_modlist(), run.pack.clone(), plan)?;
+            file.acknowledged = config.acknowledged.clone();
+            file.save(out)?;
+            let _ = log_to_file(&format!("[INFO] Wrote the plan to {}", out.display()));
+            return Ok(());
+        }
+        Mode::Prefetch => return prefetch_plan(&plan, &mods, config, run),
+        Mode::DryRun => return dry_run(&plan, &pack_dir, config, &state),
+        Mode::Apply(file) => {
+            let _ = log_to_file(&format!("[INFO] Applying the plan in {}", file.display()));
+            plan::PlanFile::load(file)?.resolve(base_dir, config.planned_modlist(), plan)?
+        }
+    };
+    let removals: Vec<PathBuf> = plan
+        .actions
+        .iter()
+        .filter_map(|a| match a {
+            Action::Remove { path } => Some(path.clone()),
+            _ => None,
+        })
+        .collect();
+    // files of entries whose tag was turned off are removed as asked
+    let opt_in = &effective.pack.opt_in_tags;
+    let (untagged, removals): (Vec<PathBuf>, Vec<PathBuf>) = removals.into_iter().partition(|path| {
+        let tag = state.get(game_dir, path).and_then(|managed| tags.off(&managed.tags, opt_in));
+        if let Some(tag) = tag {
+            let _ = log_to_file(&format!("[INFO] Removing {}, tag {} is turned off", path.display(), tag));
+        }
+        tag.is_some()
+    });
+    if !untagged.is_empty() {
+        console!("[INFO] {}", t!("sync-tags-removed", count = untagged.len()));
+    }
+    approve_removals(&removals, &state, config)?;
+    let changes = plan
+        .actions
+        .iter()
+        .any(|a| matches!(a, Action::Install { .. } | Action::Remove { .. }));
+    if let (Some(world), true) = (&config.world_backup, changes) {
+        let world_dir = base_dir.join(&world.dir);
+        console!("[INFO] {}", t!("sync-world-backup", world = world_dir.display()));
+        let (archive, size) = backup::world(base_dir, &world_dir, world.keep)?;
+        let _ = log_to_file(&format!(
+            "[INFO] Backed up world ({}) to {}",
+            report::human_size(size),
+            archive.display()
+        ));
+    }
+    if let (Some(keep), true) = (config.backup, changes) {
+        let archive = backup::create(base_dir, game_dir, mods_dir, keep)?;
+        let _ = log_to_file(&format!("[INFO] Backed up managed files to {}", archive.display()));
+    }
+    let pack: Vec<(String, String)> = mods
+        .iter()
+        .filter_map(|m| m.project_id().map(|id| (id.to_string(), m.name.clone())))
+        .collect();
+    let duplicates = plan::duplicates(&plan, &mods, game_dir, mods_dir);
+    let signatures: HashMap<PathBuf, String> = mods
+        .iter()
+        .filter_map(|m| Some((m.install_path(game_dir, mods_dir).ok()?, m.signature.clone()?)))
+        .collect();
+    if config.durable && changes {
+        durable::Journal::begin(base_dir, game_dir, &plan)?;
+    }
+    apply(&plan, &pack, &duplicates, &signatures, config, &mut state, run);
+    run.plan = Some(plan);
+    for m in mods.iter().filter(|m| !m.tags.is_empty()) {
+        let recorded = m.install_path(game_dir, mods_dir).ok().and_then(|p| state.files.get_mut(&state::key(game_dir, &p)));
+        if let Some(managed) = recorded {
+            managed.tags = m.tags.clone();
+        }
+    }
+    state.tags = tags;
+    extract_bundled(config, &mut state);
+    let extensions = managed_dirs.first().map(|(_, e)| e.as_slice()).unwrap_or_default();
+    if let Err(e) = launcher.record(base_dir, mods_dir, extensions, &run.installed, &pack) {
+        let _ = log_to_file(&format!("[WARN] failed to update the {} instance: {:#}", launcher.name(), e));
+        warnings::warn(warnings::Code::LauncherOutdated, &t!("sync-launcher-outdated", error = format!("{:#}", e)));
+    }
+    let pack_version = run.pack.as_ref().map(|p| p.version.clone()).unwrap_or_default();
+    let lock = lock::Lockfile::load(base_dir)?;
+    let overridden = match config.mode {
+        Mode::Retry => Ok(()),
+        _ => overrides::apply(base_dir, game_dir, &mut state, lock.as_ref(), &config.protected, &config.unmanaged, &pack_version),
+    };
+    let failed = run.failures.iter().filter(|f| f.kind == FailureKind::Error);
+    state.requeue(failed.map(|f| (f.filename.as_str(), f.error.as_str())));
+    state.save(base_dir)?;
+    durable::Journal::finish(base_dir);
+    overridden?;
+    config.storage.finish(base_dir, game_dir, &state)?;
+    let changes = changes::Changes::of(run, &mods, game_dir, mods_dir);
+    if !changes.is_empty() {
+        if let Err(e) = changes.write(game_dir) {
+            let _ = log_to_file(&format!("[WARN] failed to write the changes of the run: {:#}", e));
+        }
+    }
+    if let Some(permissions) = &config.permissions {
+        let applied = perms::Permissions::resolve(permissions).and_then(|p| p.apply(game_dir, &state.paths(game_dir)));
+        if let Err(e) = applied {
+            let _ = log_to_file(&format!("[WARN] failed to set the permissions of installed files: {:#}", e));
+            warnings::warn(warnings::Code::Permissions, &t!("sync-permissions-failed", error = format!("{:#}", e)));
+        }
+    }
+    let launch = run.pack.as_ref().and_then(|p| p.launch.as_ref());
+    if let (true, Some(launch)) = (config.apply_launch, launch) {
+        let applied = match config.side {
+            settings::Side::Server => launcher::write_server_args(game_dir, launch),
+            settings::Side::Client => launcher.apply_launch(base_dir, launch),
+        };
+        match applied {
+            std::result::Result::Ok(()) => {
+                let _ = log_to_file("[INFO] Applied the pack's launch settings");
+            }
+            Err(e) => {
+                let _ = log_to_file(&format!("[WARN] failed to apply the pack's launch settings: {:#}", e));
+                warnings::warn(warnings::Code::Launch, &t!("sync-launch-failed", error = format!("{:#}", e)));
+            }
+        }
+    }
+    if let Some(pack) = run.pack.as_ref() {
+        if let Err(e) = stamp::write(game_dir, pack) {
+            let _ = log_to_file(&format!("[WARN] failed to stamp the pack version into the instance: {:#}", e));
+            warnings::warn(warnings::Code::Stamp, &t!("sync-stamp-failed", error = format!("{:#}", e)));
+        }
+    }
+
+    let installed: Vec<_> = mods
+        .iter()
+        .filter_map(|m| m.install_path(game_dir, mods_dir).ok())
+        .filter(|p| p.is_file())
+        .collect();
+    let paths: Vec<&Path> = installed.iter().map(|p| p.as_path()).collect();
+    for conflict in conflicts::from_jars(&paths) {
+        warn_conflict(&conflict);
+    }
+    if let Some(problem) = run.pack.as_ref().and_then(|p| java::check(base_dir, p)) {
+        let _ = log_to_file(&format!("[WARN] {}", problem));
+        warnings::warn(warnings::Code::Java, &format!("{}, {}", problem, t!("sync-java-hint")));
+    }
     Ok(())
 }
 
-fn sync_mods(mods_dir: &String, path: &String, mods_file: &String, api_key: &String) -> Result<()> {
-    let _ = stage_dir(&mods_dir);
-    let contents = fs::read_to_string(format!("{}/{}", path, mods_file))
-        .expect("Should have been able to read the file");
-    let mods: Vec<Mod> = serde_json::from_str(contents.as_str())
-        .expect("Should have received correctly formatted json file");
+fn warn_conflict(conflict: &conflicts::Conflict) {
+    let _ = log_to_file(&format!(
+        "[WARN] {} is declared incompatible with {} ({})",
+        conflict.mod_name, conflict.other, conflict.source
+    ));
+    warnings::warn(
+        warnings::Code::Incompatible,
+        &t!("sync-incompatible", name = conflict.mod_name, other = conflict.other, source = conflict.source),
+    );
+}
 
-    let mods_path = Path::new(&mods_dir);
-    let metadata = load_mod_metadata(format!("{}/.index", &mods_dir))?;
-    if metadata.is_empty() {
-        println!("No mod metadata found, will now clean directory and start fresh.")
-        println!("    Please check for updates for Prism to generate metadata")
-        let _ = clean_all_mods(&mods_dir);
+/// Prints the changes of `plan` and the overrides of `pack_dir` would
+/// make, with unified diffs of the text files among the overrides.
+fn dry_run(plan: &Plan, pack_dir: &Path, config: &Config, state: &state::State) -> Result<()> {
+    let base_dir = Path::new(&config.base_dir);
+    let game_dir = Path::new(&config.game_dir);
+    let lock = lock::Lockfile::load(pack_dir)?;
+    let files: Vec<String> = plan.actions.iter().filter_map(Action::describe).collect();
+    let overridden = overrides::preview(pack_dir, base_dir, game_dir, state, lock.as_ref(), &config.protected, &config.unmanaged)?;
+    for line in files.iter() {
+        console!("  {}", line);
     }
+    for change in overridden.iter() {
+        console!("{}", change.show().trim_end());
+    }
+    console!("[INFO] {}", t!("sync-dry-run", files = files.len(), overrides = overridden.len()));
+    Ok(())
+}
 
-    for m in mods.iter() {
-        if m.filename.ends_with(".disabled") {
-            let _ = log_to_file(&format!("[INFO] Skipping disabled mod: {}", &m.filename));
-            continue;
+/// Downloads the files `plan` installs into the prefetch store without
+/// touching the instance, so the sync applying it later only moves them
+/// into place. Files already waiting are kept, those of superseded updates
+/// dropped. Local copies are quick and Maven versions that move may move
+/// again before the sync, both are left to it.
+fn prefetch_plan(plan: &Plan, mods: &[Mod], config: &Config, run: &mut Run) -> Result<()> {
+    let base_dir = Path::new(&config.base_dir);
+    let mut store = prefetch::Store::load(base_dir)?;
+    let installs: Vec<(&Source, &str)> = plan
+        .actions
+        .iter()
+        .filter_map(|a| match a {
+            Action::Install { source, filename, .. } => Some((source, filename.as_str())),
+            _ => None,
+        })
+        .filter(|(source, _)| match source {
+            Source::Curseforge { .. } => true,
+            Source::Maven(_) | Source::Modrinth(_) => source.pinned(None).is_some(),
+            Source::Local { .. } => false,
+        })
+        .collect();
+    store.retain(&installs.iter().map(|(source, filename)| download_key(source, filename)).collect());
+    let mut missing: Vec<(&Source, &str, PathBuf)> = Vec::new();
+    for (source, filename) in installs.iter() {
+        let key = download_key(source, filename);
+        if !store.files.contains_key(&key) && !missing.iter().any(|(s, f, _)| download_key(s, f) == key) {
+            missing.push((source, filename, store.path(&key, filename)));
         }
+    }
+    let none = None;
+    let downloads: Vec<Download> = missing
+        .iter()
+        .map(|(source, filename, dest)| Download {
+            source,
+            filename,
+            dest,
+            part: staging::part(Some(&config.staging_dir), dest),
+            replaces: &none,
+            unpack: false,
+            world: false,
+            locked: None,
+            fallback: None,
+            file: None,
+        })
+        .collect();
+    let pack: Vec<(String, String)> = mods
+        .iter()
+        .filter_map(|m| m.project_id().map(|id| (id.to_string(), m.name.clone())))
+        .collect();
+    let filter = run.pack.as_ref().map_or_else(curse_files::Filter::default, |p| curse_files::Filter::new(p.minecraft.as_deref(), p.loader.as_deref()));
+    let shortcuts = Shortcuts { prefetched: None, peers: &config.peers, peer_token: config.peer_token.as_deref() };
+    let fetched = fetch_all(&downloads, &pack, &config.api_key, config.jobs, &filter, &shortcuts);
+    for (download, fetched) in downloads.iter().zip(fetched) {
+        // the digests are checked again when the sync installs the file
+        let checked = fetched.and_then(|fetched| {
+            hash::check(&download.part, &fetched.hashes, fetched.provider)?;
+            staging::place(&download.part, download.dest)?;
+            Ok(fetched)
+        });
+        match checked {
+            std::result::Result::Ok(fetched) => {
+                let _ = log_to_file(&format!("[INFO]  prefetched {}", download.filename));
+                store.insert(download.key(), prefetch::Prefetched {
+                    file: download.dest.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                    project_id: fetched.project_id,
+                    file_id: fetched.file_id,
+                    bytes: fetched.bytes,
+                    size: fetched.size,
+                    hashes: fetched.hashes,
+                    fetched_at: chrono::Utc::now(),
+                });
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&download.part);
+                let _ = log_to_file(&format!("[ERR!]  failed to prefetch {}: {:?}", download.filename, e));
+                run.failures.push(Failure {
+                    filename: download.filename.to_string(),
+                    error: format!("{:#}", e),
+                    kind: FailureKind::Error,
+                    alternatives: Vec::new(),
+                });
+            }
+        }
+    }
+    store.save()?;
+    console!(
+        "[INFO] {}",
+        t!("sync-prefetched", count = store.files.len(), size = report::human_size(store.size()))
+    );
+    Ok(())
+}
 
-        match &m.url {
-            Some(value) => {
-                let url_parts = value.split("/");
-                let project_id = url_parts
-                    .last()
-                    .expect("expected project_id to not be empty");
-                if let Some(meta) = metadata.get(project_id) {
-                    // Previous mod meta found for mod
-                    if meta.filename != m.filename {
-                        // the mod file is different, delete the file and download a new one
-                        let old_mod_path = Path::new(&mods_dir).join(&meta.filename);
-                        let _ = log_to_file(&format!("[INFO]  Attempting to remove existing file: {}", &old_mod_path.to_string_lossy().to_string()));
-                        let _ = fs::remove_file(&old_mod_path);
-
-                        let file_id = get_file_id(project_id, &m.filename, &api_key);
-                        if file_id.is_err() {
-                            let _ = log_to_file(&format!("[ERR!]  couldn't find file for {}. file may have been removed!", &m.filename));
+/// Executes a plan, recording what happened in `run`. Failures of individual
+/// entries are logged and recorded but do not stop the remaining actions.
+/// Files are downloaded after the other actions ran, `config.jobs` at once.
+/// `pack` lists the (project id, name) of every entry to check the
+/// incompatibilities of new files against, `duplicates` the files removed
+/// as another copy of an entry's mod and `signatures` the signature urls
+/// of the destinations of signed entries. Installed and removed files are
+/// recorded in `state`, as are the answers to remember.
+fn apply(
+    plan: &Plan,
+    pack: &[(String, String)],
+    duplicates: &HashMap<PathBuf, String>,
+    signatures: &HashMap<PathBuf, String>,
+    config: &Config,
+    state: &mut state::State,
+    run: &mut Run,
+) {
+    let api_key = config.api_key.as_str();
+    let base_dir = Path::new(&config.base_dir);
+    let game_dir = Path::new(&config.game_dir);
+    let pack_version = run.pack.as_ref().map(|p| p.version.clone()).unwrap_or_default();
+    let filter = run.pack.as_ref().map_or_else(curse_files::Filter::default, |p| curse_files::Filter::new(p.minecraft.as_deref(), p.loader.as_deref()));
+    let decider = decide::Decider::new(&config.decisions, std::mem::take(&mut state.decisions));
+    let mut lock = lock::Lockfile::load(base_dir).ok().flatten().unwrap_or_default();
+    let mut locked = false;
+    let mut downloads = Vec::new();
+    for action in plan.actions.iter() {
+        match action {
+            Action::Keep { filename, .. } => {
+                let _ = log_to_file(&format!("[INFO] Skipping already up to date mod: {}", filename));
+            }
+            Action::Skip { filename, reason: SkipReason::Disabled } => {
+                let _ = log_to_file(&format!("[INFO] Skipping disabled mod: {}", filename));
+            }
+            Action::Skip { filename, reason: SkipReason::MissingUrl } => {
+                let _ = log_to_file(&format!("[WARN] Skipping file: {} missing url! Check your modlist.json file!", filename));
+            }
+            Action::Verify { filename, dest, project_id, sha256 } => {
+                match verify_manual(filename, dest, project_id.as_deref(), sha256.as_deref(), &filter, api_key) {
+                    std::result::Result::Ok(sha256) => {
+                        let _ = log_to_file(&format!("[INFO] Verified manually placed mod: {}", filename));
+                        state.record(game_dir, dest, state::Managed {
+                            sha256,
+                            source: plan::MANUAL_SOURCE.to_string(),
+                            project_id: project_id.clone(),
+                            file_id: None,
+                            installed_at: chrono::Utc::now(),
+                            pack_version: pack_version.clone(),
+                            size: fs::metadata(dest).ok().map(|meta| meta.len()),
+                            files: Vec::new(),
+                            tags: Vec::new(),
+                            parent: None,
+                            filename: None,
+                        });
+                    }
+                    Err(e) => {
+                        let _ = log_to_file(&format!("[WARN] {}: {:#}", filename, e));
+                        run.failures.push(Failure {
+                            filename: filename.clone(),
+                            error: format!("{:#}", e),
+                            kind: FailureKind::Manual,
+                            alternatives: Vec::new(),
+                        });
+                    }
+                }
+            }
+            Action::Install { source, filename, dest, replaces, unpack, world, fallback } => {
+                let modified = replaces
+                    .iter()
+                    .chain([dest])
+                    .find(|p| locally_modified(state, game_dir, p) && !damaged(state, game_dir, p));
+                if let Some(path) = modified {
+                    if !may_replace(&decider, game_dir, path, config.force) {
+                        run.failures.push(Failure {
+                            filename: filename.clone(),
+                            error: format!("{} was modified locally, pass --force to replace it", path.display()),
+                            kind: FailureKind::Error,
+                            alternatives: Vec::new(),
+                        });
+                        continue;
+                    }
+                }
+                let mut file = None;
+                if let Source::Curseforge { project_id } = source {
+                    let project = curse_files::project(project_id, api_key)
+                        .inspect_err(|e| {
+                            let _ = log_to_file(&format!("[WARN]  could not look up project {}: {:#}", project_id, e));
+                        })
+                        .ok();
+                    let belongs = project.as_ref().and_then(|p| p.class_id).and_then(curse_files::class_dir);
+                    let inside = |dir: &str| match dir {
+                        "mods" => dest.starts_with(&config.mods_dir),
+                        dir => dest.starts_with(game_dir.join(dir)),
+                    };
+                    // servers keep their world outside saves/
+                    if let Some(dir) = belongs.filter(|dir| !inside(dir) && !*world) {
+                        let _ = log_to_file(&format!("[WARN]  {} is not installed into {}, its project's files belong there", filename, dir));
+                        warnings::warn(warnings::Code::ContentType, &t!("sync-content-type", file = filename, dir = dir));
+                    }
+                    match owners::check(&mut lock, project_id, project.as_ref(), &config.acknowledged, &decider) {
+                        std::result::Result::Ok(changed) => locked |= changed,
+                        Err(e) => {
+                            run.failures.push(Failure {
+                                filename: filename.clone(),
+                                error: format!("{:#}", e),
+                                kind: FailureKind::Error,
+                                alternatives: Vec::new(),
+                            });
                             continue;
                         }
-                        let download_res = download_file(project_id, file_id.unwrap(), &m.filename, mods_dir.clone(), &api_key);
-                        if download_res.is_err() {
-                            let _ = log_to_file(&format!("[ERR!]  failed to download file: {}", &m.filename));
-                            let _ = log_to_file(&format!("[ERR!]  {:?}", download_res.err()));
+                    }
+                    // asked here, a download thread waiting for the answer
+                    // would hold its host's slot; a file that can't be found
+                    // yet is looked for again by its download
+                    if let std::result::Result::Ok(found) = find_file(project_id, filename, &filter, api_key) {
+                        if let Some((built_for, loader)) = other_loader(&found, &filter) {
+                            let question = t!("decide-loader", file = filename, built_for = &built_for, loader = loader);
+                            if !decider.decide(decide::Kind::Loader, filename, &question) {
+                                let _ = log_to_file(&format!("[INFO]  Skipping {}, it is built for {} while the pack uses {}", filename, built_for, loader));
+                                warnings::warn(warnings::Code::LoaderSkipped, &t!("sync-loader-skipped", file = filename, built_for = &built_for, loader = loader));
+                                continue;
+                            }
                         }
-                    } else {
-                        // the mod file is the same, skip the file and log it
-                        let _ = log_to_file(&format!("[INFO] Skipping already up to date mod: {}", &m.filename));
+                        file = Some(found);
                     }
-                } else {
-                    let file_id = get_file_id(project_id, &m.filename, &api_key);
-                    if file_id.is_err() {
-                        let _ = log_to_file(&format!("[ERR!]  couldn't find file for {}. file may have been removed!", &m.filename));
+                }
+                let locked = lock.files.get(&state::key(game_dir, dest)).filter(|r| locks(source, file.as_ref().map(|f| f.id), r)).cloned();
+                let fallback = fallback.as_ref().filter(|_| config.fallback);
+                let part = staging::part(Some(&config.staging_dir), dest);
+                downloads.push(Download { source, filename, dest, part, replaces, unpack: *unpack, world: *world, locked, fallback, file });
+            }
+            // run once the files they are for are installed
+            Action::Script { .. } => {}
+            Action::Remove { path } => {
+                let file_name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+                if locally_modified(state, game_dir, path) && !may_replace(&decider, game_dir, path, config.force) {
+                    run.failures.push(Failure {
+                        filename: file_name.to_string(),
+                        error: format!("{} was modified locally, pass --force to delete it", path.display()),
+                        kind: FailureKind::Error,
+                        alternatives: Vec::new(),
+                    });
+                    continue;
+                }
+                if !links::inside(base_dir, path) {
+                    let _ = log_to_file(&format!("[WARN]  Not deleting {}, a link leads it outside the instance", path.display()));
+                    warnings::warn(warnings::Code::LinkOutside, &t!("sync-link-outside", path = path.display()));
+                    continue;
+                }
+                if let Some(entry) = duplicates.get(path) {
+                    let question = t!("decide-duplicate", path = path.display(), entry = entry);
+                    if !decider.decide(decide::Kind::Duplicate, &state::key(game_dir, path), &question) {
+                        let _ = log_to_file(&format!("[INFO]  Keeping duplicate {} of {}", path.display(), entry));
                         continue;
                     }
-                    let download_res = download_file(project_id, file_id.unwrap(), &m.filename, mods_dir.clone(), &api_key);
-                    if download_res.is_err() {
-                        let _ = log_to_file(&format!("[ERR!]  failed to download file: {}", &m.filename));
-                        let _ = log_to_file(&format!("[ERR!]  {:?}", download_res.err()));
+                }
+                let _ = log_to_file(&format!("[INFO]  Deleting removed mod: {}", file_name));
+                let extracted = state.get(game_dir, path).map(|m| m.files.clone()).unwrap_or_default();
+                let before = state.get(game_dir, path).map(|m| m.sha256.clone()).or_else(|| audit::hash(path));
+                let removed = match extracted.is_empty() {
+                    true => config.storage.remove_file(path).is_ok(),
+                    false => {
+                        unpack::remove(game_dir, &extracted);
+                        true
                     }
+                };
+                if removed {
+                    durable::flush_dir(path);
+                    audit::record(base_dir, audit::Action::Deleted, path, before.as_deref(), None);
+                    locked |= lock.files.remove(&state::key(game_dir, path)).is_some();
+                    events::emit(events::Event::Removed { filename: &file_name });
+                    state.forget(game_dir, path);
+                    run.removed.push(file_name.to_string());
                 }
             }
-            None => {
-                let _ = log_to_file(&format!("[WARN] Skipping file: {} missing url! Check your modlist.json file!", &m.filename));
+        }
+    }
+
+    let prefetched = match prefetch::Store::load(base_dir) {
+        std::result::Result::Ok(store) => Some(Mutex::new(store)),
+        Err(e) => {
+            let _ = log_to_file(&format!("[WARN] ignoring the prefetched files: {:#}", e));
+            None
+        }
+    };
+    let waiting = prefetched.as_ref().map_or(0, |s| s.lock().expect("prefetch store lock poisoned").files.len());
+    let peers = rank_peers(config, &downloads);
+    let shortcuts = Shortcuts { prefetched, peers: &peers, peer_token: config.peer_token.as_deref() };
+    let fetched = fetch_all(&downloads, pack, api_key, config.jobs, &filter, &shortcuts);
+    if let Some(store) = shortcuts.prefetched.map(|s| s.into_inner().expect("prefetch store lock poisoned")) {
+        if store.files.len() != waiting {
+            if let Err(e) = store.save() {
+                let _ = log_to_file(&format!("[WARN] failed to record the installed prefetched files: {:#}", e));
             }
         }
     }
+    for (download, fetched) in downloads.iter().zip(fetched) {
+        let Download { filename, dest, replaces, unpack, world, .. } = download;
+        let previous = state.get(game_dir, dest).map(|m| m.files.clone()).unwrap_or_default();
+        let key = state::key(game_dir, dest);
+        let served = fetched.as_ref().ok().and_then(|f| f.served.clone());
+        let source = served.as_ref().unwrap_or(download.source);
+        // every check runs on the download, the installed file is only
+        // replaced once it passed them all
+        let part = download.part.as_path();
+        let fetched = fetched.and_then(|fetched| {
+            let mut hashes = hash::check(part, &fetched.hashes, fetched.provider).map_err(|e| anyhow!(quarantine::Rejected(format!("{:#}", e))))?;
+            // a lockfile of another provider's digests still verifies the file
+            let pinned = source.pinned(fetched.file_id);
+            if let Some(recorded) = lock.files.get(&key).filter(|r| pinned.as_ref() == Some(&r.source)) {
+                hashes.extend(hash::check(part, &recorded.hashes, lock::LOCK_FILE).map_err(|e| anyhow!(quarantine::Rejected(format!("{:#}", e))))?);
+            }
+            check_archive(part, dest)?;
+            for policy in config.policies.iter() {
+                policy.check_download(part, source)?;
+            }
+            if let Some(url) = signatures.get(*dest) {
+                signature::verify(part, url, base_dir, &config.signing).map_err(|e| anyhow!(quarantine::Rejected(format!("{:#}", e))))?;
+                let _ = log_to_file(&format!("[INFO]  verified the signature of {}", filename));
+            }
+            if let Some(hook) = &config.scan_hook {
+                scan::check(hook, part)?;
+            }
+            if let Some(sha256) = hashes.get(&hash::Algorithm::Sha256).filter(|_| !world) {
+                // a file served by a fallback isn't the one the source names
+                let shared = cache::SharedFile { sha256: sha256.clone(), file_id: fetched.file_id };
+                cache::put_file(part, &shared, download.shared_key().filter(|_| served.is_none()).as_deref());
+            }
+            if !world {
+                config
+                    .storage
+                    .place(part, dest)
+                    .with_context(|| format!("failed to move {} to {} of {}", part.display(), dest.display(), config.storage.name()))?;
+            }
+            let files = match (unpack, world) {
+                (_, true) => {
+                    let dir = dest.parent().unwrap_or(game_dir);
+                    let extracted = unpack::install_world(part, dir, &staging::part(Some(&config.staging_dir), dir))?;
+                    let _ = fs::remove_file(part);
+                    let _ = log_to_file(&format!("[INFO]  extracted {} files of the world {}", extracted, filename));
+                    Vec::new()
+                }
+                (true, false) => unpack_archive(dest, game_dir, &previous)?,
+                (false, false) => Vec::new(),
+            };
+            Ok((fetched, hashes, pinned, files))
+        });
+        match fetched {
+            std::result::Result::Ok((fetched, hashes, pinned, files)) => {
+                durable::flush(dest);
+                for file in files.iter() {
+                    durable::flush(&game_dir.join(file));
+                }
+                let sha256 = hashes.get(&hash::Algorithm::Sha256).cloned().unwrap_or_default();
+                let locked_file = pinned.map(|source| lock::LockedFile { source, hashes });
+                if lock.files.get(&key) != locked_file.as_ref() {
+                    match locked_file {
+                        Some(file) => lock.files.insert(key.clone(), file),
+                        None => lock.files.remove(&key),
+                    };
+                    locked = true;
+                }
+                let before = state.get(game_dir, dest).map(|m| m.sha256.clone());
+                if let Some(old_mod_path) = replaces {
+                    // only once its replacement is in place
+                    if old_mod_path != *dest && links::inside(base_dir, old_mod_path) {
+                        let _ = log_to_file(&format!("[INFO]  Removing replaced file: {}", old_mod_path.to_string_lossy()));
+                        let removed = audit::hash(old_mod_path);
+                        if config.storage.remove_file(old_mod_path).is_ok() {
+                            audit::record(base_dir, audit::Action::Deleted, old_mod_path, removed.as_deref(), None);
+                        }
+                    }
+                    state.forget(game_dir, old_mod_path);
+                }
+                if *world {
+                    // recorded apart from the managed files, nothing deletes it
+                    let dir = dest.parent().unwrap_or(game_dir);
+                    audit::record(base_dir, audit::Action::Installed, dir, None, Some(&sha256));
+                    state.worlds.insert(state::key(game_dir, dir), chrono::Utc::now());
+                } else {
+                    audit::record(base_dir, audit::Action::Installed, dest, before.as_deref(), Some(&sha256));
+                    state.record(game_dir, dest, state::Managed {
+                        sha256,
+                        source: fetched.provider.to_string(),
+                        project_id: fetched.project_id.clone(),
+                        file_id: fetched.file_id,
+                        installed_at: chrono::Utc::now(),
+                        pack_version: pack_version.clone(),
+                        size: fetched.size.or_else(|| fs::metadata(dest).ok().filter(|_| files.is_empty()).map(|meta| meta.len())),
+                        files,
+                        tags: Vec::new(),
+                        parent: None,
+                        filename: Some(filename.to_string()),
+                    });
+                }
+                run.installed.push(Installed {
+                    project_id: fetched.project_id,
+                    file_id: fetched.file_id,
+                    filename: filename.to_string(),
+                    replaced: replaces
+                        .as_ref()
+                        .and_then(|p| p.file_name())
+                        .map(|n| n.to_string_lossy().to_string()),
+                    bytes: fetched.bytes,
+                    provider: fetched.provider,
+                    path: key,
+                    copy_of: fetched.copy_of.map(|p| state::key(game_dir, &p)),
+                })
+            }
+            Err(e) => {
+                let e = match e.downcast_ref::<quarantine::Rejected>() {
+                    Some(_) if part.is_file() => match quarantine::put(base_dir, part, dest, &format!("{:#}", e), &source.url()) {
+                        std::result::Result::Ok(to) => e.context(format!("quarantined in {}", to.display())),
+                        Err(moving) => {
+                            let _ = fs::remove_file(part);
+                            e.context(format!("deleted it, quarantining failed: {:#}", moving))
+                        }
+                    },
+                    _ => {
+                        let _ = fs::remove_file(part);
+                        e
+                    }
+                };
+                let _ = log_to_file(&format!("[ERR!]  failed to download file: {}", filename));
+                let _ = log_to_file(&format!("[ERR!]  {:?}", e));
+                let kind = match e.downcast_ref::<DistributionDenied>() {
+                    Some(_) => FailureKind::DistributionDenied,
+                    None => FailureKind::Error,
+                };
+                let alternatives = match (config.suggest, download.source, e.downcast_ref::<NoFile>()) {
+                    (true, Source::Curseforge { project_id }, Some(_)) => {
+                        let name = pack.iter().find(|(id, _)| id == project_id).map_or(*filename, |(_, n)| n.as_str());
+                        alternatives::find(name, Some(project_id), filter.minecraft.as_deref(), filter.loader.as_deref(), api_key)
+                    }
+                    _ => Vec::new(),
+                };
+                run.failures.push(Failure { filename: filename.to_string(), error: format!("{:#}", e), kind, alternatives });
+            }
+        }
+    }
+    for action in plan.actions.iter() {
+        let Action::Script { filename, dest, command, outputs } = action else {
+            continue;
+        };
+        if !dest.exists() || run.failures.iter().any(|f| f.filename == *filename) {
+            let _ = log_to_file(&format!("[WARN]  Not running `{}`, {} wasn't installed", command.join(" "), filename));
+            continue;
+        }
+        if !config.allow_scripts {
+            let _ = log_to_file(&format!("[WARN]  Not running `{}` for {}, scripts aren't allowed", command.join(" "), filename));
+            warnings::warn(warnings::Code::ScriptNotAllowed, &t!("sync-script-not-allowed", command = command.join(" "), file = filename));
+            continue;
+        }
+        let _ = log_to_file(&format!("[INFO]  Running `{}` for {}", command.join(" "), filename));
+        if let Err(e) = script::run(command, dest, game_dir) {
+            let _ = log_to_file(&format!("[ERR!]  the post-install command of {} failed: {:#}", filename, e));
+            run.failures.push(Failure {
+                filename: filename.clone(),
+                error: format!("the post-install command failed: {:#}", e),
+                kind: FailureKind::Error,
+                alternatives: Vec::new(),
+            });
+            continue;
+        }
+        let parent = state::key(game_dir, dest);
+        for output in outputs {
+            let std::result::Result::Ok(sha256) = hash::sha256_file(output) else {
+                let _ = log_to_file(&format!("[WARN]  `{}` did not write {}", command.join(" "), output.display()));
+                continue;
+            };
+            let before = state.get(game_dir, output).map(|m| m.sha256.clone());
+            audit::record(base_dir, audit::Action::Installed, output, before.as_deref(), Some(&sha256));
+            state.record(game_dir, output, state::Managed {
+                sha256,
+                source: plan::SCRIPT_SOURCE.to_string(),
+                project_id: None,
+                file_id: None,
+                installed_at: chrono::Utc::now(),
+                pack_version: pack_version.clone(),
+                size: fs::metadata(output).ok().map(|meta| meta.len()),
+                files: Vec::new(),
+                tags: Vec::new(),
+                parent: Some(parent.clone()),
+                filename: None,
+            });
+        }
+    }
+    state.decisions = decider.remembered();
+    if locked {
+        if let Err(e) = lock.save(base_dir) {
+            let _ = log_to_file(&format!("[WARN] failed to record project owners and file digests: {:#}", e));
+        }
+    }
+}
+
+/// An install of the plan waiting for its file.
+struct Download<'a> {
+    source: &'a Source,
+    filename: &'a str,
+    dest: &'a Path,
+    /// Where the file is written until it is complete and moved to `dest`.
+    part: PathBuf,
+    replaces: &'a Option<PathBuf>,
+    unpack: bool,
+    world: bool,
+    /// The lockfile's digests of the file, which let peers serve it.
+    locked: Option<lock::LockedFile>,
+    /// Modrinth version fetched instead when the source fails.
+    fallback: Option<&'a modrinth::Pin>,
+    /// The CurseForge file, when it was looked up and its loader asked about
+    /// ahead of the downloads.
+    file: Option<curse_files::ModFile>,
+}
+
+impl Download<'_> {
+    /// Identifies the file the download fetches, equal for entries that
+    /// need the same file in different places.
+    fn key(&self) -> String {
+        download_key(self.source, self.filename)
+    }
+
+    /// Identifies the file among those the instances share, for sources
+    /// that always name the same file: moving maven versions and local
+    /// files change under theirs.
+    fn shared_key(&self) -> Option<String> {
+        match self.source {
+            Source::Local { .. } => None,
+            Source::Maven(coordinate) if coordinate.moving() => None,
+            _ => Some(self.key()),
+        }
+    }
+}
 
-    clean_unused_mods(mods_path, &mods)?;
-    return Ok(());
+fn download_key(source: &Source, filename: &str) -> String {
+    format!("{}:{}", serde_json::to_string(source).unwrap_or_default(), filename)
 }
 
-fn load_mod_metadata(dir: impl AsRef<Path>) -> io::Result<HashMap<String, ModMeta>> {
-    let mut mods = HashMap::new();
+/// Whether the lockfile's `recorded` file is the one `source` installs, for
+/// CurseForge the file `file_id` it was found to resolve to. Where that is
+/// not known yet, it isn't taken to be any.
+fn locks(source: &Source, file_id: Option<u64>, recorded: &lock::LockedFile) -> bool {
+    source.pinned(file_id).as_ref() == Some(&recorded.source)
+}
 
-    let dir = dir.as_ref();
+/// Where files are taken from before their providers.
+struct Shortcuts<'a> {
+    /// Files `prefetch` downloaded for the sync.
+    prefetched: Option<Mutex<prefetch::Store>>,
+    /// Urls of peers serving the files the lockfile pins.
+    peers: &'a [String],
+    peer_token: Option<&'a str>,
+}
 
-    if !dir.exists() {
-        return std::result::Result::Ok(mods);
+/// Fetches the files of `downloads` on up to `jobs` threads, fewer against
+/// hosts that fail or throttle, returning the results in order. A file
+/// needed by several downloads is fetched once and copied to the others.
+/// Prefetched files are moved into place instead and pinned files fetched
+/// from peers that have them. Downloads with a fallback that fail for
+/// another reason than their file are fetched from it.
+fn fetch_all(
+    downloads: &[Download],
+    pack: &[(String, String)],
+    api_key: &str,
+    jobs: usize,
+    filter: &curse_files::Filter,
+    shortcuts: &Shortcuts,
+) -> Vec<Result<Fetched>> {
+    let mut origins: Vec<String> = downloads.iter().flat_map(|d| d.source.origins()).collect();
+    origins.sort();
+    origins.dedup();
+    http::warm_up(&origins);
+    // indices of the downloads of each distinct file, the first one fetches it
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut by_key: HashMap<String, usize> = HashMap::new();
+    for (index, download) in downloads.iter().enumerate() {
+        match by_key.entry(download.key()) {
+            Entry::Occupied(group) => groups[*group.get()].push(index),
+            Entry::Vacant(group) => {
+                group.insert(groups.len());
+                groups.push(vec![index]);
+            }
+        }
     }
+    let limiter = Arc::new(hosts::Limiter::new(jobs));
+    let _subscription = events::subscribe(limiter.clone());
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<Fetched>>>> = Mutex::new(downloads.iter().map(|_| None).collect());
+    let bus = events::current();
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, groups.len().max(1)) {
+            s.spawn(|| {
+                let _bus = events::enter(bus.clone());
+                while let Some(group) = groups.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let download = &downloads[group[0]];
+                    let from = |source: &Source| {
+                        let host = source.host();
+                        let slot = limiter.acquire(&host);
+                        let started = Instant::now();
+                        let fetched = fetch(source, download.filename, &download.part, download.file.as_ref(), pack, api_key, filter);
+                        drop(slot);
+                        match &fetched {
+                            std::result::Result::Ok(fetched) => events::emit(events::Event::Downloaded {
+                                filename: download.filename,
+                                provider: fetched.provider,
+                                host: &host,
+                                bytes: fetched.bytes,
+                                elapsed: started.elapsed(),
+                            }),
+                            Err(e) => events::emit(events::Event::DownloadFailed {
+                                filename: download.filename,
+                                host: &host,
+                                error: &format!("{:#}", e),
+                            }),
+                        }
+                        fetched
+                    };
+                    let stored = shortcuts.prefetched.as_ref().and_then(|store| {
+                        store.lock().expect("prefetch store lock poisoned").take(&download.key(), &download.part)
+                    });
+                    let shared = match (&stored, &download.locked) {
+                        (Some(_), _) => None,
+                        (None, Some(locked)) if !shortcuts.peers.is_empty() => {
+                            from_store(download).or_else(|| from_peers(download, locked, shortcuts.peers, shortcuts.peer_token))
+                        }
+                        (None, _) => from_store(download),
+                    };
+                    let fetched = match (stored, shared) {
+                        (_, Some(shared)) => std::result::Result::Ok(shared),
+                        (Some(stored), None) => {
+                            let _ = log_to_file(&format!("[INFO]  installing prefetched {}", download.filename));
+                            std::result::Result::Ok(Fetched {
+                                provider: download.source.provider(),
+                                project_id: stored.project_id,
+                                file_id: stored.file_id,
+                                bytes: stored.bytes,
+                                size: stored.size,
+                                copy_of: None,
+                                hashes: stored.hashes,
+                                served: None,
+                            })
+                        }
+                        (None, None) => match (from(download.source), download.fallback) {
+                            (Err(e), Some(pin)) if falls_back(&e) => {
+                                let _ = log_to_file(&format!(
+                                    "[WARN]  {} failed for {}, downloading it from modrinth: {:#}",
+                                    download.source.provider(),
+                                    download.filename,
+                                    e
+                                ));
+                                let source = Source::Modrinth(pin.clone());
+                                from(&source).map(|fetched| Fetched { served: Some(source), ..fetched })
+                            }
+                            (fetched, _) => fetched,
+                        },
+                    };
+                    // downloads stay where they were written, to be checked
+                    // before they are put in place, rejected ones to be
+                    // quarantined from there
+                    if let (true, Err(e)) = (download.part.is_file(), &fetched) {
+                        if e.downcast_ref::<quarantine::Rejected>().is_none() {
+                            let _ = fs::remove_file(&download.part);
+                        }
+                    }
+                    let copies: Vec<(usize, Result<Fetched>)> = group[1..]
+                        .iter()
+                        .map(|&index| (index, copy_download(download, &fetched, &downloads[index])))
+                        .collect();
+                    let mut results = results.lock().expect("download results lock poisoned");
+                    results[group[0]] = Some(fetched);
+                    for (index, copied) in copies {
+                        results[index] = Some(copied);
+                    }
+                }
+            });
+        }
+    });
+    results
+        .into_inner()
+        .expect("download results lock poisoned")
+        .into_iter()
+        .map(|r| r.expect("every download has a result"))
+        .collect()
+}
 
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
+/// Whether a failed download is worth trying from another provider: not when
+/// the file itself was rejected or declined, the other would serve the same.
+fn falls_back(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<quarantine::Rejected>().is_none() && e.downcast_ref::<LoaderDeclined>().is_none()
+}
 
-        if path.extension().and_then(|e| e.to_str()) != Some("toml") {
-            continue;
+/// Copies the file `download` fetched to `dest`, failing like the download
+/// when it failed.
+fn copy_download(download: &Download, fetched: &Result<Fetched>, copy: &Download) -> Result<Fetched> {
+    let fetched = fetched
+        .as_ref()
+        .map_err(|e| anyhow!("failed to download {}: {:#}", download.filename, e))?;
+    if let Some(parent) = copy.part.parent() {
+        create_dir_all(parent)?;
+    }
+    let bytes = fs::copy(&download.part, &copy.part)
+        .with_context(|| format!("failed to copy {} to {}", download.part.display(), copy.part.display()))?;
+    let _ = log_to_file(&format!("[INFO]  copied {} to {}, it was downloaded once", download.dest.display(), copy.dest.display()));
+    Ok(Fetched {
+        provider: fetched.provider,
+        project_id: fetched.project_id.clone(),
+        file_id: fetched.file_id,
+        bytes,
+        size: fetched.size,
+        copy_of: Some(download.dest.to_path_buf()),
+        hashes: fetched.hashes.clone(),
+        served: fetched.served.clone(),
+    })
+}
+
+/// The peers of the instance in the order downloads try them: the fastest
+/// first when they are probed and enough pinned files are to be fetched,
+/// measured with the first of them.
+fn rank_peers(config: &Config, downloads: &[Download]) -> Vec<String> {
+    let pinned: Vec<&String> = downloads
+        .iter()
+        .filter_map(|d| d.locked.as_ref()?.hashes.get(&hash::Algorithm::Sha256))
+        .collect();
+    if !config.probe_peers || config.peers.len() < 2 || pinned.len() < peers::PROBE_MIN_DOWNLOADS {
+        return config.peers.clone();
+    }
+    let ranked = peers::rank(&config.peers, config.peer_token.as_deref(), pinned[0]);
+    let _ = log_to_file(&format!("[INFO] trying peers in the order {}", ranked.join(", ")));
+    ranked
+}
+
+/// Fetches the file of `download` from the first of `peers` that has the
+/// file `locked` records, as the provider would have served it.
+fn from_peers(download: &Download, locked: &lock::LockedFile, peers: &[String], token: Option<&str>) -> Option<Fetched> {
+    let sha256 = locked.hashes.get(&hash::Algorithm::Sha256)?;
+    let started = Instant::now();
+    let (bytes, peer) = peers::fetch(peers, token, sha256, &download.part)?;
+    let _ = log_to_file(&format!("[INFO]  fetched {} from peer {}", download.filename, peer));
+    events::emit(events::Event::Downloaded {
+        filename: download.filename,
+        provider: "peer",
+        host: &hosts::of(&peer),
+        bytes,
+        elapsed: started.elapsed(),
+    });
+    let (project_id, file_id) = match download.source {
+        Source::Curseforge { project_id } => (Some(project_id.clone()), locked.source.rsplit(':').next().and_then(|id| id.parse().ok())),
+        _ => (None, None),
+    };
+    Some(Fetched {
+        provider: download.source.provider(),
+        project_id,
+        file_id,
+        bytes,
+        size: Some(bytes),
+        copy_of: None,
+        hashes: locked.hashes.clone(),
+        served: None,
+    })
+}
+
+/// Takes the file of `download` from the files the instances share, found
+/// by the sha256 the lockfile pins or the one it was shared with.
+fn from_store(download: &Download) -> Option<Fetched> {
+    let locked = download.locked.as_ref().and_then(|l| Some((l.hashes.get(&hash::Algorithm::Sha256)?.clone(), l)));
+    let (sha256, file_id) = match locked {
+        Some((sha256, locked)) => (sha256, locked.source.strip_prefix("curseforge:").and_then(|s| s.rsplit(':').next()).and_then(|id| id.parse().ok())),
+        None => {
+            let shared = cache::shared_file(&download.shared_key()?)?;
+            (shared.sha256, shared.file_id)
+        }
+    };
+    let bytes = cache::get_file(&sha256, &download.part)?;
+    let _ = log_to_file(&format!("[INFO]  took {} from the files shared by the instances", download.filename));
+    let project_id = match download.source {
+        Source::Curseforge { project_id } => Some(project_id.clone()),
+        _ => None,
+    };
+    Some(Fetched {
+        provider: download.source.provider(),
+        project_id,
+        file_id,
+        bytes,
+        size: Some(bytes),
+        copy_of: cache::file_path(&sha256).ok(),
+        hashes: hash::Multihash::from([(hash::Algorithm::Sha256, sha256)]),
+        served: None,
+    })
+}
+
+/// What was downloaded for an install.
+struct Fetched {
+    provider: &'static str,
+    project_id: Option<String>,
+    file_id: Option<u64>,
+    bytes: u64,
+    /// Size the provider reported, when it reports one.
+    size: Option<u64>,
+    /// Destination of the download, or file the instances share, the file
+    /// was copied from.
+    copy_of: Option<PathBuf>,
+    /// Digests the provider published for the file.
+    hashes: hash::Multihash,
+    /// Source the file came from when it isn't the download's, after
+    /// falling back.
+    served: Option<Source>,
+}
+
+/// The loaders `file` is built for and the pack's, when it is tagged only
+/// for others than the pack's loader in `filter`.
+fn other_loader<'a>(file: &curse_files::ModFile, filter: &'a curse_files::Filter) -> Option<(String, &'a str)> {
+    let loaders = file.loaders();
+    let loader = filter.loader.as_deref()?;
+    (!loaders.is_empty() && !loaders.iter().any(|t| t.eq_ignore_ascii_case(loader))).then(|| (loaders.join(", "), loader))
+}
+
+/// Downloads an install's file. CurseForge files are `file` when it was
+/// looked up ahead, otherwise looked for among those tagged for the pack's
+/// game version and loader in `filter` first; one tagged only for other
+/// loaders is then refused, nobody was asked about it.
+fn fetch(
+    source: &Source,
+    filename: &str,
+    dest: &Path,
+    file: Option<&curse_files::ModFile>,
+    pack: &[(String, String)],
+    api_key: &str,
+    filter: &curse_files::Filter,
+) -> Result<Fetched> {
+    match source {
+        Source::Curseforge { project_id } => {
+            let found;
+            let file = match file {
+                Some(file) => file,
+                None => {
+                    found = find_file(project_id, filename, filter, api_key).inspect_err(|_| {
+                        let _ = log_to_file(&format!("[ERR!]  couldn't find file for {}. file may have been removed!", filename));
+                    })?;
+                    if let Some((built_for, loader)) = other_loader(&found, filter) {
+                        return Err(anyhow!(LoaderDeclined(format!(
+                            "not installed, the file is built for {} while the pack uses {}",
+                            built_for, loader
+                        ))));
+                    }
+                    &found
+                }
+            };
+            let name = pack.iter().find(|(id, _)| id == project_id).map_or(filename, |(_, n)| n.as_str());
+            for conflict in conflicts::from_curseforge(name, file, pack) {
+                warn_conflict(&conflict);
+            }
+            let size = Some(file.file_length).filter(|&l| l > 0);
+            let bytes = download_file(project_id, file.id, size, dest, api_key).map_err(|e| {
+                // the download endpoint doesn't say why, the project does
+                match curse_files::project(project_id, api_key) {
+                    std::result::Result::Ok(p) if p.allow_mod_distribution == Some(false) => anyhow!(DistributionDenied {
+                        filename: filename.to_string(),
+                        project_id: project_id.clone(),
+                    }),
+                    _ => e,
+                }
+            })?;
+            Ok(Fetched {
+                provider: "curseforge",
+                project_id: Some(project_id.clone()),
+                file_id: Some(file.id),
+                bytes,
+                size,
+                copy_of: None,
+                hashes: file.sha1().map(|sha1| hash::Multihash::from([(hash::Algorithm::Sha1, sha1.to_lowercase())])).unwrap_or_default(),
+                served: None,
+            })
+        }
+        Source::Maven(coordinate) => {
+            let _ = log_to_file(&format!("[INFO] resolving {}:{}:{}", coordinate.group, coordinate.artifact, coordinate.version));
+            let (bytes, hashes) = maven::download(coordinate, dest)?;
+            let _ = log_to_file(&format!("[INFO]  successfully downloaded {}", dest.display()));
+            Ok(Fetched {
+                provider: "maven",
+                project_id: None,
+                file_id: None,
+                bytes,
+                size: None,
+                copy_of: None,
+                hashes,
+                served: None,
+            })
         }
+        Source::Modrinth(pin) => {
+            let _ = log_to_file(&format!("[INFO] resolving modrinth version {} of {}", pin.version_id, pin.project_id));
+            let (bytes, size, hashes) = modrinth::download(pin, filename, dest)?;
+            let _ = log_to_file(&format!("[INFO]  successfully downloaded {}", dest.display()));
+            Ok(Fetched {
+                provider: "modrinth",
+                project_id: None,
+                file_id: None,
+                bytes,
+                size,
+                copy_of: None,
+                hashes,
+                served: None,
+            })
+        }
+        Source::Local { path } => {
+            let bytes = copy_local(path, dest)?;
+            let _ = log_to_file(&format!("[INFO]  successfully copied {} to {}", path.display(), dest.display()));
+            Ok(Fetched {
+                provider: "local",
+                project_id: None,
+                file_id: None,
+                bytes,
+                size: None,
+                copy_of: None,
+                hashes: hash::Multihash::new(),
+                served: None,
+            })
+        }
+    }
+}
+
+/// Copies a file from a local or network path, checking the copy is intact
+/// since network drives are not always reliable.
+fn copy_local(source: &Path, dest: &Path) -> Result<u64> {
+    let expected = hash::sha256_file(source).map_err(|e| anyhow!("failed to read {}: {}", source.display(), e))?;
+    if let Some(parent) = dest.parent() {
+        create_dir_all(parent)?;
+    }
+    let bytes = fs::copy(source, dest)?;
+    if hash::sha256_file(dest)? != expected {
+        return Err(anyhow!(quarantine::Rejected(format!("copy of {} does not match the original", source.display()))));
+    }
+    Ok(bytes)
+}
 
-        let contents = fs::read_to_string(&path)?;
+/// Rejects jars and zips that can't be opened, damaged or not archives at
+/// all, `file` being the download of the one installed at `dest`.
+fn check_archive(file: &Path, dest: &Path) -> Result<()> {
+    let archive = dest.extension().is_some_and(|e| e.eq_ignore_ascii_case("jar") || e.eq_ignore_ascii_case("zip"));
+    if !archive {
+        return Ok(());
+    }
+    let opened = File::open(file).map_err(anyhow::Error::from).and_then(|f| Ok(zip::ZipArchive::new(f)?));
+    match opened {
+        std::result::Result::Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!(quarantine::Rejected(format!("{} is not a valid archive: {:#}", file.display(), e)))),
+    }
+}
 
-        let meta: ModMeta = match toml::from_str(&contents) {
-            std::result::Result::Ok(m) => m,
+/// Copies the jars the installed mods bundle into the mods directory and
+/// records them as children of their mod, so they go with it. Children no
+/// longer bundled, or all of them with the setting off, are deleted. Files
+/// the player placed under the same name are left alone.
+fn extract_bundled(config: &Config, state: &mut state::State) {
+    let base_dir = Path::new(&config.base_dir);
+    let game_dir = Path::new(&config.game_dir);
+    let mods_dir = Path::new(&config.mods_dir);
+    let jar = ["jar".to_string()];
+    let parents: Vec<(String, PathBuf, String)> = state
+        .entries(game_dir)
+        .filter(|(path, m)| m.parent.is_none() && m.files.is_empty() && path.parent() == Some(mods_dir) && plan::has_extension(path, &jar))
+        .map(|(path, m)| (state::key(game_dir, &path), path, m.pack_version.clone()))
+        .collect();
+    let mut wanted: HashSet<String> = HashSet::new();
+    for (parent, path, pack_version) in parents.iter().filter(|_| config.extract_bundled) {
+        let bundled = match jar::bundled_jars(path) {
+            std::result::Result::Ok(bundled) => bundled,
             Err(e) => {
-                eprintln!("Failed to parse {}: {e}", path.display());
+                let _ = log_to_file(&format!("[WARN] failed to read the jars bundled in {}: {:#}", path.display(), e));
+                // what was extracted from it before stays until it can be read
+                wanted.extend(state.files.iter().filter(|(_, m)| m.parent.as_ref() == Some(parent)).map(|(key, _)| key.clone()));
                 continue;
             }
         };
+        for (name, bytes) in bundled {
+            let dest = mods_dir.join(&name);
+            let key = state::key(game_dir, &dest);
+            let sha256 = hash::sha256_bytes(&bytes);
+            let recorded = state.files.get(&key);
+            if recorded.is_some_and(|m| m.parent.as_deref() != Some(parent.as_str())) || (recorded.is_none() && dest.exists()) {
+                let _ = log_to_file(&format!("[WARN] Not extracting {} from {}, {} is there already", name, parent, dest.display()));
+                continue;
+            }
+            wanted.insert(key.clone());
+            if recorded.is_some_and(|m| m.sha256 == sha256) && dest.is_file() {
+                continue;
+            }
+            let before = audit::hash(&dest);
+            if let Err(e) = fs::write(&dest, &bytes) {
+                let _ = log_to_file(&format!("[WARN] failed to extract {} from {}: {}", name, parent, e));
+                continue;
+            }
+            let _ = log_to_file(&format!("[INFO]  Extracted {} bundled in {}", name, parent));
+            audit::record(base_dir, audit::Action::Installed, &dest, before.as_deref(), Some(&sha256));
+            state.files.insert(key, state::Managed {
+                sha256,
+                source: plan::BUNDLED_SOURCE.to_string(),
+                project_id: None,
+                file_id: None,
+                installed_at: chrono::Utc::now(),
+                pack_version: pack_version.clone(),
+                size: Some(bytes.len() as u64),
+                files: Vec::new(),
+                tags: Vec::new(),
+                parent: Some(parent.clone()),
+                filename: None,
+            });
+        }
+    }
+    let stale: Vec<String> = state
+        .files
+        .iter()
+        .filter(|(key, m)| m.source == plan::BUNDLED_SOURCE && !wanted.contains(*key))
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in stale {
+        let path = game_dir.join(&key);
+        let before = audit::hash(&path);
+        match fs::remove_file(&path) {
+            std::result::Result::Ok(()) => audit::record(base_dir, audit::Action::Deleted, &path, before.as_deref(), None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                let _ = log_to_file(&format!("[WARN] failed to delete {}: {}", path.display(), e));
+                continue;
+            }
+        }
+        let _ = log_to_file(&format!("[INFO]  Deleted {}, its mod no longer bundles it", key));
+        state.files.remove(&key);
+    }
+}
 
-        let project_id = meta.update.curseforge.project_id.clone();
-        mods.insert(project_id.to_string(), meta);
+/// Extracts a downloaded archive next to where it was saved and deletes
+/// it, after removing what an earlier extraction of the entry left behind.
+/// Returns the extracted files relative to the game directory.
+fn unpack_archive(archive: &Path, game_dir: &Path, previous: &[String]) -> Result<Vec<String>> {
+    unpack::remove(game_dir, previous);
+    let dir = archive.parent().unwrap_or(game_dir);
+    let extracted = unpack::extract(archive, dir);
+    let _ = fs::remove_file(archive);
+    let files: Vec<String> = extracted?.iter().map(|f| state::key(game_dir, f)).collect();
+    let _ = log_to_file(&format!("[INFO]  extracted {} files from {}", files.len(), archive.display()));
+    Ok(files)
+}
+
+/// Checks a manually placed file against the entry's sha256, or the sha1
+/// CurseForge lists for it, returning its sha256.
+fn verify_manual(
+    filename: &str,
+    dest: &Path,
+    project_id: Option<&str>,
+    sha256: Option<&str>,
+    filter: &curse_files::Filter,
+    api_key: &str,
+) -> Result<String> {
+    if !dest.is_file() {
+        let from = project_id.map_or_else(|| "its author".to_string(), project_page);
+        return Err(anyhow!("download {} from {} and place it at {}", filename, from, dest.display()));
     }
+    let actual = hash::sha256_file(dest)?;
+    match (sha256, project_id) {
+        (Some(expected), _) if !expected.eq_ignore_ascii_case(&actual) => Err(anyhow!(
+            "{} does not match the entry's sha256, download it again",
+            dest.display()
+        )),
+        (Some(_), _) => Ok(actual),
+        (None, Some(project_id)) => {
+            let file = find_file(project_id, filename, filter, api_key)?;
+            let expected = file
+                .sha1()
+                .ok_or_else(|| anyhow!("CurseForge lists no hash for {}, add its sha256 to the entry", filename))?;
+            if !expected.eq_ignore_ascii_case(&hash::sha1_file(dest)?) {
+                return Err(anyhow!("{} does not match the file on CurseForge, download it again", dest.display()));
+            }
+            Ok(actual)
+        }
+        (None, None) => Err(anyhow!("manual entry has neither a sha256 nor a CurseForge url to verify it against")),
+    }
+}
 
-    return std::result::Result::Ok(mods);
+/// Whether `path` was installed by the tool and its contents changed since.
+fn locally_modified(state: &state::State, game_dir: &Path, path: &Path) -> bool {
+    match state.get(game_dir, path) {
+        Some(managed) if path.is_file() => hash::sha256_file(path).is_ok_and(|h| h != managed.sha256),
+        _ => false,
+    }
+}
+
+/// Whether `path` was installed by the tool and since lost its size, which
+/// is damage rather than an edit worth keeping.
+fn damaged(state: &state::State, game_dir: &Path, path: &Path) -> bool {
+    state.get(game_dir, path).is_some_and(|managed| managed.damaged(path))
+}
+
+/// Whether a locally modified file may be replaced or deleted, always with
+/// `--force` and otherwise as `decider` settles it.
+fn may_replace(decider: &decide::Decider, game_dir: &Path, path: &Path, force: bool) -> bool {
+    if force {
+        let _ = log_to_file(&format!("[WARN]  Replacing locally modified file: {}", path.display()));
+        return true;
+    }
+    let question = t!("decide-modified", path = path.display());
+    let replace = decider.decide(decide::Kind::Modified, &state::key(game_dir, path), &question);
+    if !replace {
+        let _ = log_to_file(&format!("[WARN]  Leaving locally modified file alone: {}", path.display()));
+    }
+    replace
 }
 
-fn get_file_id(project_id: &str, filename: &String, api_key: &String) -> Result<u64> {
+fn find_file(project_id: &str, filename: &str, filter: &curse_files::Filter, api_key: &str) -> Result<curse_files::ModFile> {
     let _ = log_to_file(&format!("[INFO] attempting to find file {}", filename));
-    for f in curse_files::CurseFile::of(&project_id, &api_key)? {
-        let file = f?;
-        if file.file_name.as_str() == filename.as_str() {
-            let _ = log_to_file(&format!("[INFO]  matching file found, will now attempt to download mod file"));
-            return Ok(file.id);
-        }
+    if let Some(file) = curse_files::find_by_name(project_id, filename, filter, api_key)? {
+        let _ = log_to_file("[INFO]  matching file found, will now attempt to download mod file");
+        return Ok(file);
     }
 
-    return Err(anyhow!(
-        " -----> failed to find file id for file {}",
-        filename
-    ));
+    Err(anyhow!(NoFile(filename.to_string())))
 }
 
 fn download_file(
     project_id: &str,
     file_id: u64,
-    filename: &str,
-    dir: String,
-    api_key: &String,
-) -> Result<()> {
-    let client = reqwest::blocking::Client::new();
-    let mut headers = HeaderMap::new();
-    headers.insert("X-Api-Token", HeaderValue::from_str(&api_key)?);
-    headers.insert(
-        "Accept-Encoding",
-        HeaderValue::from_str("gzip, deflate, br, zstd")?,
-    );
-
-    let url = format!(
-        "https://www.curseforge.com/api/v1/mods/{}/files/{}/download",
-        project_id, file_id
-    );
+    expected: Option<u64>,
+    dest: &Path,
+    api_key: &str,
+) -> Result<u64> {
+    let client = http::files();
+    let headers = curse_files::download_headers(api_key)?;
+    let url = curse_files::download_url(project_id, file_id);
+    if let Some(bytes) = http::split(&url, &headers, expected, dest)? {
+        let _ = log_to_file(&format!("[INFO]  successfully downloaded {}", dest.display()));
+        return Ok(bytes);
+    }
 
-    let resp = client
-        .get(&url)
-        .headers(headers)
-        .send();
+    let resp = hosts::send_retrying(|| client.get(&url).headers(headers.clone()), &url);
     if resp.is_err() {
         return Err(anyhow!("request to get file {} failed", file_id));
     }
-    let out = File::create(format!("{}/{}", dir, filename));
-    if out.is_err() {
-        return Err(anyhow!("failed to create jar file"));
+    let resp = resp?;
+    if !resp.status().is_success() {
+        let e = curse_files::ApiError::of(resp);
+        return Err(anyhow!(e).context(format!("download of file {} failed", file_id)));
     }
-    let content = resp?.bytes();
+    let content = http::buffered(resp);
     if content.is_err() {
         return Err(anyhow!("no file content to write"));
     }
-    copy(&mut content?.as_ref(), &mut out?)?;
+    let content = content?;
+    let checked = check_download(&content, expected);
+    if let Some(parent) = dest.parent() {
+        create_dir_all(parent)?;
+    }
+    let out = File::create(dest);
+    if out.is_err() {
+        return Err(anyhow!("failed to create jar file"));
+    }
+    let bytes = copy(&mut content.as_ref(), &mut out?)?;
+    // saved anyway so it can be looked at in quarantine
+    checked.map_err(|e| anyhow!(quarantine::Rejected(format!("{:#}", e))))?;
+
+    let _ = log_to_file(&format!("[INFO]  successfully downloaded {}", dest.display()));
+    Ok(bytes)
+}
 
-    let _ = log_to_file(&format!("[INFO]  successfully downloaded {}", filename));
-    return Ok(());
+/// Catches error pages and truncated transfers served in place of a jar,
+/// which happen even when the request itself succeeds.
+fn check_download(content: &[u8], expected: Option<u64>) -> Result<()> {
+    let start = String::from_utf8_lossy(&content[..content.len().min(256)]).trim_start().to_lowercase();
+    if start.starts_with("<!doctype") || start.starts_with("<html") {
+        return Err(anyhow!("got an HTML page instead of the file, the download may be blocked by a captcha or proxy"));
+    }
+    match expected {
+        Some(size) if content.len() as u64 != size => Err(anyhow!(
+            "downloaded {} bytes but CurseForge reports {}, the transfer was cut short or altered",
+            content.len(),
+            size
+        )),
+        _ => Ok(()),
+    }
 }
 
-fn stage_dir(dir: &str) -> Result<()> {
+fn stage_dir(storage: &dyn storage::Storage, dir: &str) -> Result<()> {
     if !Path::new(dir).exists() {
-        create_dir_all(dir)?;
+        storage.create_dir_all(Path::new(dir))?;
     }
-    return Ok(());
+    Ok(())
 }
 
-fn clean_all_mods(dir: impl AsRef<Path>) -> io::Result<()> {
+/// Files emptying the mods directory for a fresh start deletes, all but
+/// `keep`, the files placed by hand which can't be downloaded again. A mods
+/// directory linked from outside the instance is left alone, as are linked
+/// files unless `symlinks` follows them.
+fn fresh_start_files(base_dir: &Path, dir: &Path, keep: &[PathBuf], symlinks: settings::Symlinks) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if !links::within(base_dir, dir) {
+        return std::result::Result::Ok(files);
+    }
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
+        if symlinks == settings::Symlinks::Skip && links::is_link(&path) {
+            continue;
+        }
 
-        if path.is_file() {
-            fs::remove_file(path)?;
+        if path.is_file() && !keep.contains(&path) {
+            files.push(path);
         }
     }
 
-    return std::result::Result::Ok(());
+    files.sort();
+    std::result::Result::Ok(files)
 }
 
-fn clean_unused_mods(mods_dir: &Path, mods: &[Mod]) -> Result<()> {
-    use std::collections::HashSet;
-    let valid_filenames: HashSet<&str> = mods.iter().map(|m| m.filename.as_str()).collect();
-
-    for entry in fs::read_dir(mods_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if !path.is_file() {
-            continue;
-        }
+/// Asks before deleting more files than the approval threshold allows or
+/// any file the tool did not install, so a bad modlist edit can't quietly
+/// empty the mods directory. Without a terminal only `--yes` allows it.
+/// A plan is only written, its removals are approved when it is applied,
+/// and a fresh start has no files of the tool to tell the others apart.
+fn approve_removals(paths: &[PathBuf], state: &state::State, config: &Config) -> Result<()> {
+    if matches!(config.mode, Mode::Plan(_)) || state.files.is_empty() {
+        return Ok(());
+    }
+    let game_dir = Path::new(&config.game_dir);
+    let unmanaged = paths.iter().filter(|p| state.get(game_dir, p).is_none()).count();
+    let too_many = paths.len() > config.approval.max_removals;
+    if config.yes || !(too_many || (config.approval.unmanaged && unmanaged > 0)) {
+        return Ok(());
+    }
 
-        let file_name = match path.file_name().and_then(|n| n.to_str()) {
-            Some(n) => n,
-            None => continue,
+    console!("[WARN] {}", t!("sync-removals", count = paths.len(), unmanaged = unmanaged));
+    for path in paths.iter() {
+        let note = match state.get(game_dir, path) {
+            None => format!(" {}", t!("sync-removal-unmanaged")),
+            Some(_) => String::new(),
         };
+        console!("         {}{}", path.display(), note);
+    }
+    let _ = log_to_file(&format!("[WARN] sync wants to delete {} files, {} unmanaged", paths.len(), unmanaged));
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!("refusing to delete {} files without confirmation, check the modlist or pass --yes", paths.len()));
+    }
+    if !prompt::confirm(&t!("sync-removals-confirm"), false)? {
+        return Err(anyhow!("cancelled, nothing was changed"));
+    }
+    Ok(())
+}
 
-        if !file_name.ends_with(".jar") {
-            continue;
-        }
-
-        if !valid_filenames.contains(file_name) {
-            let _ = log_to_file(&format!("[INFO]  Deleting removed mod: {}", file_name));
-            let _ = fs::remove_file(&path);
+/// Fails a plan installing from CurseForge projects that changed hands
+/// since the lockfile recorded them, or whose owner can't be looked up, as
+/// applying it would. Nothing is recorded, that is left to the apply.
+fn review_owners(plan: &Plan, base_dir: &Path, config: &Config, state: &state::State) -> Result<()> {
+    let projects: Vec<(&String, &String)> = plan
+        .actions
+        .iter()
+        .filter_map(|action| match action {
+            Action::Install { source: Source::Curseforge { project_id }, filename, .. } => Some((project_id, filename)),
+            _ => None,
+        })
+        .collect();
+    if projects.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<u64> = projects.iter().filter_map(|(id, _)| id.parse().ok()).collect();
+    let details = curse_files::projects(&ids, &config.api_key).unwrap_or_else(|e| {
+        let _ = log_to_file(&format!("[WARN] could not look up the projects of the plan: {:#}", e));
+        Vec::new()
+    });
+    let mut lock = lock::Lockfile::load(base_dir)?.unwrap_or_default();
+    let decider = decide::Decider::new(&config.decisions, state.decisions.clone());
+    let mut held = 0;
+    for (project_id, filename) in projects {
+        let project = details.iter().find(|p| p.id.to_string() == *project_id);
+        if let Err(e) = owners::check(&mut lock, project_id, project, &config.acknowledged, &decider) {
+            let _ = log_to_file(&format!("[ERR!] {}: {:#}", filename, e));
+            console!("[ERR!] {}: {:#}", filename, e);
+            held += 1;
         }
     }
-
-    Ok(())
+    match held {
+        0 => Ok(()),
+        n => Err(anyhow!("{} entries install from projects whose owner changed or can't be checked, no plan was written", n)),
+    }
 }
 
+/// Flags of `sync` that take no value.
+pub const SWITCHES: &[&str] = &["force", "backup", "yes", "trace-plan", "strict", "dry-run", "allow-scripts", "suggest"];
+/// Flags of `sync` that take a value.
+pub const OPTIONS: &[&str] = &[
+    "report",
+    "channel",
+    "modlist",
+    "var",
+    "jobs",
+    "acknowledge-owner",
+    "with",
+    "without",
+    "deny",
+    "target",
+    "profile-name",
+];
+
 impl Config {
     pub fn build(args: &[String]) -> Result<Config> {
-        if args.len() < 2 {
-            return Err(anyhow!("expected argument containing path to modpack"));
+        let parsed = Args::parse(&args[1..], SWITCHES, OPTIONS)?;
+        let base_dir = parsed
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("expected argument containing path to modpack"))?
+            .clone();
+        let reports = parsed
+            .values("report")
+            .into_iter()
+            .map(report::Target::parse)
+            .collect::<Result<Vec<_>>>()?;
+        let vars = parsed
+            .values("var")
+            .into_iter()
+            .map(|v| {
+                v.split_once('=')
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .ok_or_else(|| anyhow!("expected --var <name>=<value>, got {}", v))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if reports.iter().any(|r| r.to_stdout()) {
+            log::console_to_stderr();
+        }
+        if parsed.has("trace-plan") {
+            log::enable_trace();
         }
+        let modlist = match parsed.value("modlist") {
+            Some("-") => {
+                let mut contents = String::new();
+                io::stdin().read_to_string(&mut contents).context("failed to read the modlist from stdin")?;
+                Some(contents)
+            }
+            Some(path) => Some(fs::read_to_string(path).with_context(|| format!("failed to read modlist {}", path))?),
+            None => None,
+        };
 
-        let base_dir = args[1].clone();
-        let api_key = env::var("CURSE_API_KEY").unwrap();
+        if !Path::new(&base_dir).is_dir() {
+            return Err(anyhow!("modpack directory {} does not exist, run `modpack-sync setup` to create one", base_dir));
+        }
+        let api_key = settings::api_key()?;
+        let mut instance = settings::InstanceSettings::load(Path::new(&base_dir))?;
+        let vanilla = launcher::Launcher::detect(Path::new(&base_dir)) == launcher::Launcher::Vanilla;
+        if let Some(name) = parsed.value("profile-name") {
+            if !vanilla {
+                return Err(anyhow!("--profile-name needs {} to be a vanilla launcher installation with a launcher_profiles.json", base_dir));
+            }
+            if instance.profile.as_deref() != Some(name) {
+                launcher::profile_game_dir(Path::new(&base_dir), name)?;
+                instance.profile = Some(name.to_string());
+                instance.save(Path::new(&base_dir))?;
+                console!("[INFO] {}", t!("sync-profile-chosen", profile = name));
+            }
+        }
+        // a profile gone from the launcher fails rather than syncing into
+        // the launcher's own directory
+        if let (Some(name), settings::Side::Client, true) = (&instance.profile, instance.side, vanilla) {
+            launcher::profile_game_dir(Path::new(&base_dir), name)?;
+        }
+
+        let suppress = instance
+            .suppress
+            .iter()
+            .map(|c| warnings::Code::parse(c).ok_or_else(|| anyhow!("unknown warning code {} in suppress, expected {}", c, warnings::Code::range())))
+            .collect::<Result<Vec<_>>>()?;
+        // denying a code overrides suppressing it, strict fails on every
+        // warning that is shown
+        let mut deny = parsed
+            .values("deny")
+            .into_iter()
+            .flat_map(|v| v.split(','))
+            .map(|c| warnings::Code::parse(c).ok_or_else(|| anyhow!("unknown warning code {} for --deny, expected {}", c, warnings::Code::range())))
+            .collect::<Result<Vec<_>>>()?;
+        if parsed.has("strict") {
+            deny.extend(warnings::Code::ALL.into_iter().filter(|c| !suppress.contains(c)));
+        }
+        let suppress = suppress.into_iter().filter(|c| !deny.contains(c)).collect();
 
         let mods_file = "modlist.json".to_string();
-        let mods_dir = format!("{}/.minecraft/mods", base_dir);
+        let game_dir = instance.game_dir(&base_dir);
+        let world_backup = match (&instance.world_backup, instance.side) {
+            (Some(world), settings::Side::Server) => Some(world.clone()),
+            (Some(_), settings::Side::Client) => {
+                console!("[WARN] {}", t!("sync-world-backup-client"));
+                None
+            }
+            (None, _) => None,
+        };
+        let backup = match &instance.backup {
+            Some(settings) if settings.enabled || parsed.has("backup") => Some(settings.keep),
+            _ if parsed.has("backup") => Some(backup::DEFAULT_KEEP),
+            _ => None,
+        };
+        let mods_dir = instance.mods_dir(&base_dir);
+        let staging_dir = staging::dir(Path::new(&base_dir), instance.staging_dir.as_deref());
+        let jobs = match parsed.value("jobs") {
+            Some(jobs) => jobs
+                .parse::<usize>()
+                .ok()
+                .filter(|j| *j > 0)
+                .ok_or_else(|| anyhow!("expected a positive number of --jobs, got {}", jobs))?,
+            None => hosts::DEFAULT_JOBS,
+        };
+        let decisions = instance.decisions.clone().unwrap_or_default();
+        decide::check(&decisions)?;
+        let mut policies = Vec::new();
+        if let Some(location) = settings::UserSettings::load()?.policy {
+            policies.push(policy::load(&location, &settings::UserSettings::dir()?)?);
+        }
+        if let Some(location) = &instance.policy {
+            policies.push(policy::load(location, Path::new(&base_dir))?);
+        }
 
         Ok(Config {
             api_key,
             base_dir,
+            game_dir,
             mods_dir,
             mods_file,
+            modlist,
+            vars,
+            reports,
+            force: parsed.has("force"),
+            yes: parsed.has("yes"),
+            approval: instance.approval.clone().unwrap_or_default(),
+            backup,
+            world_backup,
+            source: instance.source.clone(),
+            channel: parsed.value("channel").map(str::to_string).or(instance.channel.clone()),
+            protected: instance
+                .protected
+                .clone()
+                .unwrap_or_else(|| overrides::DEFAULT_PROTECTED.iter().map(|p| p.to_string()).collect()),
+            unmanaged: instance.unmanaged.clone().unwrap_or_default(),
+            side: instance.side,
+            apply_launch: instance.apply_launch.unwrap_or(false),
+            symlinks: instance.symlinks.unwrap_or_default(),
+            permissions: instance.permissions.clone(),
+            jobs,
+            decisions,
+            signing: instance.signing.clone().unwrap_or_default(),
+            scan_hook: instance.scan_hook.clone(),
+            peers: instance.peers.clone(),
+            peer_token: instance.peer_token(),
+            policies,
+            probe_peers: instance.probe_peers,
+            fleet: instance.fleet.clone(),
+            staging_dir,
+            durable: instance.durable,
+            download_memory_mb: instance.download_memory_mb,
+            split_download_mb: instance.split_download_mb,
+            download_connections: instance.download_connections,
+            storage: storage::open(parsed.value("target"), instance.panel.as_ref())?,
+            fallback: instance.provider_fallback,
+            extract_bundled: instance.extract_bundled,
+            allow_scripts: instance.allow_scripts || parsed.has("allow-scripts"),
+            suggest: parsed.has("suggest"),
+            suppress,
+            deny,
+            acknowledged: parsed.values("acknowledge-owner").into_iter().map(str::to_string).collect(),
+            with: parsed.values("with").into_iter().map(str::to_string).collect(),
+            without: parsed.values("without").into_iter().map(str::to_string).collect(),
+            mode: match parsed.has("dry-run") {
+                true => Mode::DryRun,
+                false => Mode::Sync,
+            },
         })
     }
+
+    /// The modlist a plan of the sync is made from.
+    fn planned_modlist(&self) -> plan::Modlist<'_> {
+        match &self.modlist {
+            Some(contents) => plan::Modlist::Given(contents),
+            None => plan::Modlist::File(&self.mods_file),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curseforge_files_are_locked_by_their_file() {
+        let source = Source::Curseforge { project_id: "238222".to_string() };
+        let recorded = lock::LockedFile { source: "curseforge:238222:5101366".to_string(), hashes: hash::Multihash::default() };
+        assert!(locks(&source, Some(5101366), &recorded));
+        assert!(!locks(&source, Some(5101367), &recorded));
+        assert!(!locks(&source, None, &recorded));
+        assert!(!locks(&Source::Curseforge { project_id: "238223".to_string() }, Some(5101366), &recorded));
+    }
 }
@@ -0,0 +1,262 @@
+use super::hash::{Algorithm, Multihash};
+use super::hosts;
+use super::http::{self, RequestExt};
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const API: &str = "https://api.modrinth.com/v2";
+
+/// Modrinth asks every client to identify itself.
+fn agent() -> String {
+    format!("dev-pengu/modpack-sync/{}", env!("CARGO_PKG_VERSION"))
+}
+
+#[derive(Deserialize)]
+pub struct SearchHit {
+    pub project_id: String,
+    pub slug: String,
+    pub title: String,
+    #[serde(default)]
+    pub downloads: u64,
+    #[serde(default)]
+    pub latest_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Deserialize)]
+pub struct License {
+    /// SPDX identifier, `LicenseRef-All-Rights-Reserved` and the like for
+    /// licenses without one.
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// A version of a Modrinth project an entry is pinned to.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Pin {
+    pub project_id: String,
+    pub version_id: String,
+}
+
+#[derive(Deserialize)]
+struct FileHashes {
+    sha1: String,
+    sha512: String,
+}
+
+#[derive(Deserialize)]
+pub struct VersionFile {
+    pub url: String,
+    pub filename: String,
+    #[serde(default)]
+    pub primary: bool,
+    #[serde(default)]
+    pub size: u64,
+    hashes: FileHashes,
+}
+
+impl VersionFile {
+    /// Digests Modrinth published for the file.
+    pub fn hashes(&self) -> Multihash {
+        Multihash::from([
+            (Algorithm::Sha512, self.hashes.sha512.to_lowercase()),
+            (Algorithm::Sha1, self.hashes.sha1.to_lowercase()),
+        ])
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Version {
+    pub id: String,
+    pub project_id: String,
+    #[serde(default)]
+    pub version_number: String,
+    /// When the version was released, RFC 3339.
+    #[serde(default)]
+    pub date_published: String,
+    #[serde(default)]
+    pub files: Vec<VersionFile>,
+}
+
+impl Version {
+    /// The file named `filename`, the primary one when none is.
+    pub fn file(&self, filename: &str) -> Option<&VersionFile> {
+        self.files
+            .iter()
+            .find(|f| f.filename == filename)
+            .or_else(|| self.files.iter().find(|f| f.primary))
+            .or(self.files.first())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Project {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub slug: String,
+    #[serde(default)]
+    pub title: String,
+    pub license: License,
+    /// Id of the team of its members.
+    #[serde(default)]
+    pub team: String,
+}
+
+#[derive(Deserialize)]
+pub struct User {
+    pub username: String,
+}
+
+#[derive(Deserialize)]
+pub struct Member {
+    pub team_id: String,
+    pub user: User,
+}
+
+pub fn page(project_id: &str) -> String {
+    format!("https://modrinth.com/project/{}", project_id)
+}
+
+/// The version a file with the sha512 `sha512` was uploaded as, `None` when
+/// Modrinth doesn't know the file.
+pub fn version_of(sha512: &str) -> Result<Option<Version>> {
+    let response = http::api()
+        .get(format!("{}/version_file/{}?algorithm=sha512", API, sha512))
+        .header(USER_AGENT, agent())
+        .send_captured()?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(http::json::<Version>(response.error_for_status()?)?))
+}
+
+pub fn version(version_id: &str) -> Result<Version> {
+    http::json::<Version>(
+        http::api()
+            .get(format!("{}/version/{}", API, version_id))
+            .header(USER_AGENT, agent())
+            .send_captured()?
+            .error_for_status()?,
+    )
+}
+
+pub fn project(project_id: &str) -> Result<Project> {
+    http::json::<Project>(
+        http::api()
+            .get(format!("{}/project/{}", API, project_id))
+            .header(USER_AGENT, agent())
+            .send_captured()?
+            .error_for_status()?,
+    )
+}
+
+/// Several versions in one request, those Modrinth doesn't know left out.
+pub fn versions(version_ids: &[String]) -> Result<Vec<Version>> {
+    http::json::<Vec<Version>>(
+        http::api()
+            .get(format!("{}/versions", API))
+            .query(&[("ids", serde_json::to_string(version_ids)?)])
+            .header(USER_AGENT, agent())
+            .send_captured()?
+            .error_for_status()?,
+    )
+}
+
+/// Several projects, by id or slug, in one request, those Modrinth doesn't
+/// know left out.
+pub fn projects(project_ids: &[String]) -> Result<Vec<Project>> {
+    http::json::<Vec<Project>>(
+        http::api()
+            .get(format!("{}/projects", API))
+            .query(&[("ids", serde_json::to_string(project_ids)?)])
+            .header(USER_AGENT, agent())
+            .send_captured()?
+            .error_for_status()?,
+    )
+}
+
+/// The members of several teams in one request, a list for every team
+/// Modrinth knows.
+pub fn teams(team_ids: &[String]) -> Result<Vec<Vec<Member>>> {
+    http::json::<Vec<Vec<Member>>>(
+        http::api()
+            .get(format!("{}/teams", API))
+            .query(&[("ids", serde_json::to_string(team_ids)?)])
+            .header(USER_AGENT, agent())
+            .send_captured()?
+            .error_for_status()?,
+    )
+}
+
+/// Downloads the file `filename` of the pinned version to `dest`, handing
+/// back its size and the digests Modrinth published for it. They are
+/// checked by the caller.
+pub fn download(pin: &Pin, filename: &str, dest: &Path) -> Result<(u64, Option<u64>, Multihash)> {
+    let version = version(&pin.version_id)?;
+    let file = version
+        .file(filename)
+        .ok_or_else(|| anyhow!("version {} of {} has no files", version.version_number, pin.project_id))?;
+    let size = Some(file.size).filter(|&s| s > 0);
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_str(&agent())?);
+    if let Some(bytes) = http::split(&file.url, &headers, size, dest)? {
+        return Ok((bytes, size, file.hashes()));
+    }
+    let client = http::files();
+    let response = hosts::send_retrying(|| client.get(&file.url).header(USER_AGENT, agent()), &file.url)
+        .with_context(|| format!("request to {} failed", file.url))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("{} returned {}", file.url, response.status()));
+    }
+    let bytes = http::buffered(response)?;
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(dest, &bytes).with_context(|| format!("failed to write {}", dest.display()))?;
+    Ok((bytes.len() as u64, size, file.hashes()))
+}
+
+/// The license of the project a file with the sha512 `sha512` was uploaded
+/// to, `None` when Modrinth doesn't know the file.
+pub fn license_of(sha512: &str) -> Result<Option<License>> {
+    let Some(version) = version_of(sha512)? else {
+        return Ok(None);
+    };
+    Ok(Some(project(&version.project_id)?.license))
+}
+
+/// Searches mods, filtering by game version and loader when given.
+pub fn search(query: &str, minecraft: Option<&str>, loader: Option<&str>) -> Result<Vec<SearchHit>> {
+    let mut facets = vec![r#"["project_type:mod"]"#.to_string()];
+    if let Some(minecraft) = minecraft {
+        facets.push(format!(r#"["versions:{}"]"#, minecraft));
+    }
+    if let Some(loader) = loader {
+        facets.push(format!(r#"["categories:{}"]"#, loader.to_lowercase()));
+    }
+
+    let mut url = reqwest::Url::parse(&format!("{}/search", API))?;
+    url.query_pairs_mut()
+        .append_pair("query", query)
+        .append_pair("limit", "20")
+        .append_pair("index", "relevance")
+        .append_pair("facets", &format!("[{}]", facets.join(",")));
+
+    let response = http::json::<SearchResponse>(
+        http::api()
+            .get(url)
+            .header(USER_AGENT, agent())
+            .send_captured()?
+            .error_for_status()?,
+    )?;
+    Ok(response.hits)
+}
@@ -0,0 +1,331 @@
+use super::http::{self, RequestExt};
+use super::settings::{EmailSettings, HttpSettings, NotifySettings, WebhookSettings};
+use super::smtp::{self, Message, Security, Server};
+use super::Run;
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const DEFAULT_TITLE: &str = "[modpack-sync] {{pack}} {{version}} {{event}} on {{instance}}";
+const DEFAULT_TEMPLATE: &str = "{{#if error}}The update failed: {{error}}\n\n{{/if}}\
+{{#if installed}}Installed:\n{{installed}}\n\n{{/if}}\
+{{#if removed}}Removed:\n{{removed}}\n\n{{/if}}\
+{{#if failures}}Failed:\n{{failures}}\n{{/if}}";
+/// Longest message Discord accepts.
+const DISCORD_LIMIT: usize = 2000;
+/// How long a webhook or endpoint has to accept a notification.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// A pack update was applied.
+    Updated,
+    /// Applying an update failed or left files behind.
+    Failed,
+}
+
+impl Event {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Event::Updated => "updated",
+            Event::Failed => "failed",
+        }
+    }
+}
+
+/// What a daemon reports after applying an update, the same for every
+/// notification backend.
+#[derive(Serialize)]
+pub struct Notification {
+    pub event: Event,
+    /// The instance's directory.
+    pub instance: String,
+    pub pack: Option<String>,
+    pub version: Option<String>,
+    pub installed: Vec<String>,
+    pub removed: Vec<String>,
+    /// `file: error` for every file that didn't install.
+    pub failures: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl Notification {
+    pub fn from_run(instance: &str, run: &Run, result: &Result<()>) -> Notification {
+        let error = result.as_ref().err().map(|e| format!("{:#}", e));
+        let failures: Vec<String> = run
+            .failures
+            .iter()
+            .map(|f| format!("{}: {}", f.filename, f.error))
+            .collect();
+        Notification {
+            event: match error.is_none() && failures.is_empty() {
+                true => Event::Updated,
+                false => Event::Failed,
+            },
+            instance: instance.to_string(),
+            pack: run.pack.as_ref().map(|p| p.name.clone()),
+            version: run.pack.as_ref().map(|p| p.version.clone()),
+            installed: run.installed.iter().map(|i| i.filename.clone()).collect(),
+            removed: run.removed.clone(),
+            failures,
+            error,
+        }
+    }
+
+    /// Values of the template placeholders, lists with one item a line.
+    fn values(&self) -> BTreeMap<&'static str, String> {
+        let list = |items: &[String]| items.iter().map(|i| format!("- {}", i)).collect::<Vec<_>>().join("\n");
+        BTreeMap::from([
+            ("event", self.event.as_str().to_string()),
+            ("instance", self.instance.clone()),
+            ("pack", self.pack.clone().unwrap_or_default()),
+            ("version", self.version.clone().unwrap_or_default()),
+            ("installed", list(&self.installed)),
+            ("installed_count", self.installed.len().to_string()),
+            ("removed", list(&self.removed)),
+            ("removed_count", self.removed.len().to_string()),
+            ("failures", list(&self.failures)),
+            ("failures_count", self.failures.len().to_string()),
+            ("error", self.error.clone().unwrap_or_default()),
+        ])
+    }
+
+    /// An empty notification, to check templates against.
+    fn sample() -> Notification {
+        Notification {
+            event: Event::Updated,
+            instance: String::new(),
+            pack: None,
+            version: None,
+            installed: Vec::new(),
+            removed: Vec::new(),
+            failures: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+/// Fills `{{name}}` placeholders with their value and keeps the text of
+/// `{{#if name}}...{{/if}}` sections only when the value isn't empty.
+pub fn render(template: &str, values: &BTreeMap<&'static str, String>) -> Result<String> {
+    let mut out = String::new();
+    // whether each enclosing section is shown
+    let mut sections: Vec<bool> = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let shown = sections.iter().all(|s| *s);
+        if shown {
+            out.push_str(&rest[..start]);
+        }
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| anyhow!("unclosed {{{{ in template"))?;
+        let tag = rest[start + 2..start + end].trim();
+        rest = &rest[start + end + 2..];
+
+        let lookup = |name: &str| {
+            values
+                .get(name)
+                .ok_or_else(|| anyhow!("unknown placeholder {{{{{}}}}} in template", name))
+        };
+        if let Some(name) = tag.strip_prefix("#if ") {
+            sections.push(!lookup(name.trim())?.is_empty());
+        } else if tag == "/if" {
+            sections.pop().ok_or_else(|| anyhow!("{{{{/if}}}} without {{{{#if}}}} in template"))?;
+        } else {
+            let value = lookup(tag)?;
+            if shown {
+                out.push_str(value);
+            }
+        }
+    }
+    if !sections.is_empty() {
+        return Err(anyhow!("unclosed {{{{#if}}}} in template"));
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// A place notifications are delivered to.
+pub trait Notifier: Send + Sync {
+    /// Name of the backend for messages.
+    fn name(&self) -> &'static str;
+    fn send(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Title and body templates of a backend.
+struct Templates {
+    title: String,
+    body: String,
+}
+
+impl Templates {
+    fn new(settings: &NotifySettings, body: Option<&String>) -> Result<Templates> {
+        let templates = Templates {
+            title: settings.title.clone().unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+            body: body
+                .or(settings.template.as_ref())
+                .cloned()
+                .unwrap_or_else(|| DEFAULT_TEMPLATE.to_string()),
+        };
+        // templates are checked up front rather than at the first update
+        let values = Notification::sample().values();
+        render(&templates.title, &values).context("invalid notification title")?;
+        render(&templates.body, &values).context("invalid notification template")?;
+        Ok(templates)
+    }
+
+    fn render(&self, notification: &Notification) -> Result<(String, String)> {
+        let values = notification.values();
+        let body = render(&self.body, &values)?;
+        let body = match body.trim() {
+            "" => "No files changed.".to_string(),
+            body => body.to_string(),
+        };
+        Ok((render(&self.title, &values)?, body))
+    }
+}
+
+struct Email {
+    settings: EmailSettings,
+    templates: Templates,
+}
+
+impl Notifier for Email {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn send(&self, notification: &Notification) -> Result<()> {
+        let settings = &self.settings;
+        let password = settings.password()?;
+        let server = Server {
+            host: &settings.server,
+            port: settings.port.unwrap_or(match settings.security {
+                Security::Tls => 465,
+                _ => 587,
+            }),
+            security: settings.security,
+            login: settings.username.as_deref().zip(password.as_deref()),
+        };
+        let (subject, body) = self.templates.render(notification)?;
+        smtp::send(
+            &server,
+            &Message {
+                from: &settings.from,
+                to: &settings.to,
+                subject: &subject,
+                body: &body,
+            },
+        )
+    }
+}
+
+/// Discord and Slack take the message as one JSON field, only their
+/// names and markup differ.
+struct Webhook {
+    settings: WebhookSettings,
+    templates: Templates,
+    slack: bool,
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        match self.slack {
+            true => "slack",
+            false => "discord",
+        }
+    }
+
+    fn send(&self, notification: &Notification) -> Result<()> {
+        let (title, body) = self.templates.render(notification)?;
+        let payload = match self.slack {
+            true => json!({ "text": format!("*{}*\n{}", title, body) }),
+            false => {
+                let content: String = format!("**{}**\n{}", title, body).chars().take(DISCORD_LIMIT).collect();
+                json!({ "content": content })
+            }
+        };
+        http::api()
+            .post(&self.settings.url)
+            .timeout(TIMEOUT)
+            .json(&payload)
+            .send_captured()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+struct Http {
+    settings: HttpSettings,
+    templates: Option<Templates>,
+}
+
+impl Notifier for Http {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn send(&self, notification: &Notification) -> Result<()> {
+        let mut request = http::api().post(&self.settings.url).timeout(TIMEOUT);
+        request = match &self.templates {
+            Some(templates) => request
+                .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+                .body(templates.render(notification)?.1),
+            None => request.json(notification),
+        };
+        for (name, value) in self.settings.headers.iter() {
+            request = request.header(name, value);
+        }
+        request.send_captured()?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// The configured backends, failing on invalid templates.
+pub fn notifiers(settings: &NotifySettings) -> Result<Vec<Box<dyn Notifier>>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let Some(email) = &settings.email {
+        notifiers.push(Box::new(Email {
+            settings: email.clone(),
+            templates: Templates::new(settings, email.template.as_ref())?,
+        }));
+    }
+    for (webhook, slack) in [(&settings.discord, false), (&settings.slack, true)] {
+        if let Some(webhook) = webhook {
+            notifiers.push(Box::new(Webhook {
+                settings: webhook.clone(),
+                templates: Templates::new(settings, webhook.template.as_ref())?,
+                slack,
+            }));
+        }
+    }
+    if let Some(http) = &settings.http {
+        let templates = match &http.template {
+            Some(template) => Some(Templates::new(settings, Some(template))?),
+            None => None,
+        };
+        notifiers.push(Box::new(Http {
+            settings: http.clone(),
+            templates,
+        }));
+    }
+    Ok(notifiers)
+}
+
+/// Sends the notification with every backend, failing after trying all of
+/// them if any failed.
+pub fn send(notifiers: &[Box<dyn Notifier>], notification: &Notification) -> Result<()> {
+    let failed: Vec<String> = notifiers
+        .iter()
+        .filter_map(|n| n.send(notification).err().map(|e| format!("{}: {:#}", n.name(), e)))
+        .collect();
+    if !failed.is_empty() {
+        return Err(anyhow!("failed to send notifications, {}", failed.join("; ")));
+    }
+    Ok(())
+}
@@ -0,0 +1,373 @@
+use super::hash;
+use super::links;
+use super::lock::Lockfile;
+use super::log::STATE_DIR;
+use super::log_to_file;
+use super::manifest;
+use super::merge3;
+use super::state::{Managed, State};
+use super::warnings;
+use crate::i18n::t;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory next to the modlist whose contents are copied into the game
+/// directory, e.g. `overrides/config/jei.toml`.
+pub const OVERRIDES_DIR: &str = "overrides";
+/// `Managed::source` of files placed from the overrides directory.
+pub const SOURCE: &str = "overrides";
+/// Files the player owns once they exist, the pack only provides their
+/// initial contents.
+pub const DEFAULT_PROTECTED: &[&str] = &["options.txt", "servers.dat"];
+
+/// Whether `pattern` matches the whole of `key`, `*` matches any run of
+/// characters and `?` any single one.
+fn wildcard(pattern: &[u8], key: &[u8]) -> bool {
+    match (pattern.split_first(), key.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => wildcard(rest, key) || (!key.is_empty() && wildcard(pattern, &key[1..])),
+        (Some((b'?', rest)), Some((_, key_rest))) => wildcard(rest, key_rest),
+        (Some((p, rest)), Some((k, key_rest))) if p == k => wildcard(rest, key_rest),
+        _ => false,
+    }
+}
+
+pub fn matches_any(key: &str, protected: &[String]) -> bool {
+    protected.iter().any(|pattern| wildcard(pattern.as_bytes(), key.as_bytes()))
+}
+
+/// Whether `key` is one of `dirs` or lies inside one of them, both relative
+/// to the game directory.
+pub fn inside_any(key: &str, dirs: &[String]) -> bool {
+    dirs.iter().any(|dir| {
+        let dir = dir.replace('\\', "/");
+        let dir = dir.trim_matches('/');
+        key == dir || key.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Copies of the override files as last installed, the base of three-way
+/// merges when both the pack and the player changed a file.
+fn base_copy(base_dir: &Path, key: &str) -> PathBuf {
+    base_dir.join(STATE_DIR).join("base").join(key)
+}
+
+pub fn files(dir: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files(&path, found)?;
+        } else {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub fn key(root: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(root).unwrap_or(file);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// What bringing one override up to date does to the game directory.
+enum Outcome {
+    /// The file already has the pack's contents.
+    Current,
+    /// The player edited it and the pack didn't change it.
+    Edited,
+    Write(Vec<u8>),
+    /// Upstream changes merged into the player's edits.
+    Merged(Vec<u8>),
+    /// Both changed it in ways that don't merge, the pack's version goes
+    /// next to it with the previous one as base.
+    Conflict(Option<Vec<u8>>),
+}
+
+fn outcome(base_dir: &Path, key: &str, upstream: &[u8], local: Option<&[u8]>, recorded: Option<&str>) -> Outcome {
+    let upstream_hash = hash::sha256_bytes(upstream);
+    let local_hash = local.map(hash::sha256_bytes);
+    match (local, recorded) {
+        _ if local_hash.as_deref() == Some(upstream_hash.as_str()) => Outcome::Current,
+        (None, _) => Outcome::Write(upstream.to_vec()),
+        (Some(_), Some(recorded)) if recorded == upstream_hash => Outcome::Edited,
+        (Some(_), Some(recorded)) if local_hash.as_deref() == Some(recorded) => Outcome::Write(upstream.to_vec()),
+        (Some(local), recorded) => {
+            let base = recorded.and_then(|_| fs::read(base_copy(base_dir, key)).ok());
+            let merged = match (base.as_deref().map(std::str::from_utf8), std::str::from_utf8(local), std::str::from_utf8(upstream)) {
+                (Some(Ok(base)), Ok(local), Ok(upstream)) => merge3::merge(base, local, upstream),
+                _ => None,
+            };
+            match merged {
+                Some(merged) => Outcome::Merged(merged.into_bytes()),
+                None => Outcome::Conflict(base),
+            }
+        }
+    }
+}
+
+/// Overrides the pack dropped that the state records, with the hash they
+/// were installed with.
+fn dropped(game_dir: &Path, state: &State, present: &[String], unmanaged: &[String]) -> Vec<(PathBuf, String)> {
+    state
+        .entries(game_dir)
+        .filter(|(_, m)| m.source == SOURCE)
+        .map(|(path, m)| (path, m.sha256.clone()))
+        .filter(|(path, _)| !present.contains(&key(game_dir, path)) && !inside_any(&key(game_dir, path), unmanaged))
+        .collect()
+}
+
+/// Whether a dropped override is deleted, it isn't once the player changed
+/// it.
+fn deletes(base_dir: &Path, game_dir: &Path, path: &Path, published: &str, protected: &[String]) -> bool {
+    let unchanged = hash::sha256_file(path).map_or(true, |h| h == published);
+    unchanged && !matches_any(&key(game_dir, path), protected) && links::inside(base_dir, path)
+}
+
+/// Files the lockfile's tombstones name that are still in the game
+/// directory, with the hash they were published with.
+fn tombstoned<'a>(
+    base_dir: &Path,
+    game_dir: &Path,
+    lock: Option<&'a Lockfile>,
+    present: &[String],
+    protected: &[String],
+    unmanaged: &[String],
+) -> Vec<(&'a String, PathBuf, &'a String)> {
+    lock.map(|l| &l.removed)
+        .into_iter()
+        .flatten()
+        // the lockfile comes with the pack, a key leaving the game
+        // directory is not followed
+        .filter_map(|(key, published)| match manifest::contained(key, "a tombstone of the lockfile") {
+            Ok(relative) => Some((key, game_dir.join(relative), published)),
+            Err(e) => {
+                let _ = log_to_file(&format!("[WARN] {:#}, the file is left alone", e));
+                None
+            }
+        })
+        .filter(|(key, path, _)| {
+            !(present.contains(key) || matches_any(key, protected) || inside_any(key, unmanaged) || !path.is_file() || !links::inside(base_dir, path))
+        })
+        .collect()
+}
+
+/// Keys of all the override files of `pack_dir`.
+pub fn shipped(pack_dir: &Path) -> Result<Vec<String>> {
+    let root = pack_dir.join(OVERRIDES_DIR);
+    let mut found = Vec::new();
+    if root.is_dir() {
+        files(&root, &mut found)?;
+    }
+    Ok(found.iter().map(|file| key(&root, file)).collect())
+}
+
+struct Upstream {
+    /// Override files with their keys, without those that are not installed
+    /// at all: inside `unmanaged` or `protected` ones that exist.
+    installed: Vec<(PathBuf, String)>,
+    /// Keys of all the pack's overrides.
+    present: Vec<String>,
+}
+
+/// The override files of `pack_dir`.
+fn upstream(pack_dir: &Path, game_dir: &Path, protected: &[String], unmanaged: &[String]) -> Result<Upstream> {
+    let root = pack_dir.join(OVERRIDES_DIR);
+    let mut upstream_files = Vec::new();
+    if root.is_dir() {
+        files(&root, &mut upstream_files)?;
+    }
+    upstream_files.sort();
+
+    let mut present = Vec::new();
+    let mut installed = Vec::new();
+    for source in upstream_files {
+        let key = key(&root, &source);
+        present.push(key.clone());
+        if inside_any(&key, unmanaged) {
+            let _ = log_to_file(&format!("[WARN]  Not installing override {} into an unmanaged directory", key));
+            warnings::warn(warnings::Code::Unmanaged, &t!("sync-unmanaged-override", file = key));
+            continue;
+        }
+        if matches_any(&key, protected) && game_dir.join(&key).exists() {
+            let _ = log_to_file(&format!("[INFO]  Keeping player owned {}", key));
+            continue;
+        }
+        installed.push((source, key));
+    }
+    Ok(Upstream { installed, present })
+}
+
+/// Brings the override files of the game directory up to date. Files the
+/// player edited are merged with upstream changes when both are text,
+/// otherwise the player's version is kept and the pack's is written next to
+/// it as `.new` with the previous pack version as `.orig`. Overrides the
+/// pack dropped are deleted unless edited, and `protected` paths are never
+/// touched once they exist. Nothing inside the `unmanaged` directories is
+/// written or deleted.
+pub fn apply(
+    base_dir: &Path,
+    game_dir: &Path,
+    state: &mut State,
+    lock: Option<&Lockfile>,
+    protected: &[String],
+    unmanaged: &[String],
+    pack_version: &str,
+) -> Result<()> {
+    let Upstream { installed, present } = upstream(base_dir, game_dir, protected, unmanaged)?;
+    for (source, key) in installed.iter() {
+        let dest = game_dir.join(key);
+        let upstream = fs::read(source).with_context(|| format!("failed to read {}", source.display()))?;
+        let upstream_hash = hash::sha256_bytes(&upstream);
+        let local = fs::read(&dest).ok();
+        let recorded = state.get(game_dir, &dest).map(|m| m.sha256.clone());
+
+        let contents = match outcome(base_dir, key, &upstream, local.as_deref(), recorded.as_deref()) {
+            Outcome::Current => None,
+            Outcome::Edited => continue,
+            Outcome::Write(contents) => Some(contents),
+            Outcome::Merged(merged) => {
+                let _ = log_to_file(&format!("[INFO]  Merged upstream changes into edited {}", key));
+                Some(merged)
+            }
+            Outcome::Conflict(base) => {
+                let _ = log_to_file(&format!("[WARN]  {} was edited locally and upstream, wrote {}.new", key, key));
+                warnings::warn(warnings::Code::OverrideConflict, &t!("sync-override-conflict", file = key));
+                fs::write(with_suffix(&dest, ".new"), &upstream)?;
+                if let Some(base) = base {
+                    fs::write(with_suffix(&dest, ".orig"), base)?;
+                }
+                None
+            }
+        };
+
+        if let Some(contents) = contents {
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&dest, contents).with_context(|| format!("failed to write {}", dest.display()))?;
+            let _ = log_to_file(&format!("[INFO]  Installed override {}", key));
+        }
+        let copy = base_copy(base_dir, key);
+        if let Some(parent) = copy.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&copy, &upstream)?;
+        state.record(game_dir, &dest, Managed {
+            sha256: upstream_hash,
+            source: SOURCE.to_string(),
+            project_id: None,
+            file_id: None,
+            installed_at: chrono::Utc::now(),
+            pack_version: pack_version.to_string(),
+            size: None,
+            files: Vec::new(),
+            tags: Vec::new(),
+            parent: None,
+            filename: None,
+        });
+    }
+
+    // overrides dropped by the pack go too, unless the player changed them
+    for (path, recorded) in dropped(game_dir, state, &present, unmanaged) {
+        if deletes(base_dir, game_dir, &path, &recorded, protected) {
+            let _ = fs::remove_file(&path);
+            let _ = log_to_file(&format!("[INFO]  Deleting removed override: {}", path.display()));
+        }
+        let _ = fs::remove_file(base_copy(base_dir, &key(game_dir, &path)));
+        state.forget(game_dir, &path);
+    }
+
+    // tombstones catch the dropped overrides of instances without a record
+    // of them, e.g. set up from a copy of the pack
+    for (key, path, published) in tombstoned(base_dir, game_dir, lock, &present, protected, unmanaged) {
+        if hash::sha256_file(&path).is_ok_and(|h| h == *published) {
+            fs::remove_file(&path)?;
+            let _ = log_to_file(&format!("[INFO]  Deleting removed override: {}", path.display()));
+        } else {
+            let _ = log_to_file(&format!("[INFO]  Keeping edited {} the pack no longer ships", key));
+        }
+    }
+    Ok(())
+}
+
+/// A change [`apply`] would make to an override.
+pub enum Change {
+    Write { key: String, old: Option<Vec<u8>>, new: Vec<u8> },
+    Merge { key: String, old: Vec<u8>, new: Vec<u8> },
+    Conflict { key: String },
+    Delete { key: String },
+}
+
+impl Change {
+    /// A unified diff of the change for text files, a line saying what
+    /// happens to others.
+    pub fn show(&self) -> String {
+        let diff = |key: &str, old: Option<&[u8]>, new: &[u8]| {
+            let text = (std::str::from_utf8(old.unwrap_or_default()), std::str::from_utf8(new));
+            let hunks = match text {
+                (Ok(old), Ok(new)) => merge3::unified(old, new, 3),
+                _ => None,
+            };
+            let from = old.map_or("/dev/null".to_string(), |_| format!("a/{}", key));
+            match hunks {
+                Some(hunks) => format!("--- {}\n+++ b/{}\n{}", from, key, hunks),
+                None => format!("{}: binary or too large to diff, would be {}\n", key, if old.is_some() { "replaced" } else { "written" }),
+            }
+        };
+        match self {
+            Change::Write { key, old, new } => diff(key, old.as_deref(), new),
+            Change::Merge { key, old, new } => format!("{} (merged with the local edits)\n{}", key, diff(key, Some(old), new)),
+            Change::Conflict { key } => format!("{}: edited locally and upstream, the pack's version would be written to {}.new\n", key, key),
+            Change::Delete { key } => format!("{}: dropped by the pack, would be deleted\n", key),
+        }
+    }
+}
+
+/// The changes [`apply`] would make with the overrides of `pack_dir`,
+/// writing nothing.
+pub fn preview(
+    pack_dir: &Path,
+    base_dir: &Path,
+    game_dir: &Path,
+    state: &State,
+    lock: Option<&Lockfile>,
+    protected: &[String],
+    unmanaged: &[String],
+) -> Result<Vec<Change>> {
+    let Upstream { installed, present } = upstream(pack_dir, game_dir, protected, unmanaged)?;
+    let mut changes = Vec::new();
+    for (source, key) in installed {
+        let dest = game_dir.join(&key);
+        let upstream = fs::read(&source).with_context(|| format!("failed to read {}", source.display()))?;
+        let local = fs::read(&dest).ok();
+        let recorded = state.get(game_dir, &dest).map(|m| m.sha256.clone());
+        match outcome(base_dir, &key, &upstream, local.as_deref(), recorded.as_deref()) {
+            Outcome::Current | Outcome::Edited => {}
+            Outcome::Write(new) => changes.push(Change::Write { key, old: local, new }),
+            Outcome::Merged(new) => changes.push(Change::Merge { key, old: local.unwrap_or_default(), new }),
+            Outcome::Conflict(_) => changes.push(Change::Conflict { key }),
+        }
+    }
+    for (path, recorded) in dropped(game_dir, state, &present, unmanaged) {
+        if deletes(base_dir, game_dir, &path, &recorded, protected) {
+            changes.push(Change::Delete { key: key(game_dir, &path) });
+        }
+    }
+    for (key, path, published) in tombstoned(base_dir, game_dir, lock, &present, protected, unmanaged) {
+        if hash::sha256_file(&path).is_ok_and(|h| h == *published) {
+            changes.push(Change::Delete { key: key.clone() });
+        }
+    }
+    Ok(changes)
+}
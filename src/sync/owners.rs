@@ -0,0 +1,99 @@
+use super::curse_files::ProjectDetails;
+use super::decide::{self, Decider};
+use super::lock::{Lockfile, Ownership};
+use super::log::log_to_file;
+use super::warnings;
+use crate::i18n::t;
+use anyhow::{anyhow, Result};
+
+/// Checks the owner recorded in the instance's lockfile against CurseForge
+/// before installing from a project. Projects that were taken over have
+/// shipped malware, so a new slug or author has to be accepted.
+///
+/// Records the project's slug and authors the first time, and errors when
+/// they changed since unless the player acknowledged it with
+/// `--acknowledge-owner` or answers the question to trust it. A project that
+/// couldn't be looked up, `None`, is held back as well unless acknowledged,
+/// its owner can't be told. Returns whether `lock` changed.
+pub fn check(
+    lock: &mut Lockfile,
+    project_id: &str,
+    project: Option<&ProjectDetails>,
+    acknowledged: &[String],
+    decider: &Decider,
+) -> Result<bool> {
+    let acknowledged = acknowledged.iter().any(|a| a == project_id);
+    let Some(project) = project else {
+        if acknowledged {
+            let _ = log_to_file(&format!("[WARN]  installing from project {} without checking its owner, it was acknowledged", project_id));
+            return Ok(false);
+        }
+        return Err(anyhow!(
+            "could not look up the owner of project {}, try again later or pass --acknowledge-owner {} to install from it anyway",
+            project_id,
+            project_id
+        ));
+    };
+    let current = Ownership {
+        slug: project.slug.clone(),
+        authors: project.authors.iter().map(|a| a.name.clone()).collect(),
+    };
+    let Some(recorded) = lock.owners.get(project_id) else {
+        lock.owners.insert(project_id.to_string(), current);
+        return Ok(true);
+    };
+    if *recorded == current {
+        return Ok(false);
+    }
+    let _ = log_to_file(&format!("[WARN]  project {} changed from {} to {}", project_id, recorded, current));
+    warnings::warn(warnings::Code::OwnerChanged, &t!("owner-changed", project = project_id, from = recorded, to = &current));
+    let trusted = acknowledged || {
+        let question = t!("decide-owner", project = project_id);
+        decider.decide(decide::Kind::Owner, &format!("{}:{}", project_id, current), &question)
+    };
+    if !trusted {
+        return Err(anyhow!(
+            "project {} changed from {} to {}, review it and pass --acknowledge-owner {} to install from it",
+            project_id,
+            recorded,
+            current,
+            project_id
+        ));
+    }
+    lock.owners.insert(project_id.to_string(), current);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::settings::DecisionSettings;
+
+    fn project(slug: &str, author: &str) -> ProjectDetails {
+        serde_json::from_value(serde_json::json!({
+            "id": 1, "name": slug, "slug": slug, "authors": [{ "name": author }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn unknown_owners_are_held_back() {
+        let settings = DecisionSettings { interactive: false, ..DecisionSettings::default() };
+        let decider = Decider::new(&settings, Default::default());
+        let mut lock = Lockfile::default();
+        assert!(check(&mut lock, "1", None, &[], &decider).is_err());
+        assert!(!check(&mut lock, "1", None, &["1".to_string()], &decider).unwrap());
+    }
+
+    #[test]
+    fn changed_owners_need_acknowledging() {
+        let settings = DecisionSettings { interactive: false, ..DecisionSettings::default() };
+        let decider = Decider::new(&settings, Default::default());
+        let mut lock = Lockfile::default();
+        assert!(check(&mut lock, "1", Some(&project("jei", "mezz")), &[], &decider).unwrap());
+        assert!(!check(&mut lock, "1", Some(&project("jei", "mezz")), &[], &decider).unwrap());
+        assert!(check(&mut lock, "1", Some(&project("jei", "someone")), &[], &decider).is_err());
+        assert!(check(&mut lock, "1", Some(&project("jei", "someone")), &["1".to_string()], &decider).unwrap());
+        assert_eq!(lock.owners["1"].authors, vec!["someone".to_string()]);
+    }
+}
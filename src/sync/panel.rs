@@ -0,0 +1,197 @@
+use super::http::{self, RequestExt};
+use super::log_to_file;
+use super::settings::PanelSettings;
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::RequestBuilder;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the server may take to stop or start.
+const POWER_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Deserialize)]
+struct List {
+    data: Vec<Item<ListedFile>>,
+}
+
+#[derive(Deserialize)]
+struct Item<T> {
+    attributes: T,
+}
+
+#[derive(Deserialize)]
+struct ListedFile {
+    name: String,
+    size: u64,
+    is_file: bool,
+}
+
+#[derive(Deserialize)]
+struct SignedUrl {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct Resources {
+    current_state: String,
+}
+
+/// The client API of a server on a Pterodactyl or Pelican panel. Paths are
+/// those of the server's directory, `/` being its root.
+pub struct Panel {
+    /// `<panel>/api/client/servers/<server>`.
+    base: String,
+    key: String,
+    pub power: bool,
+}
+
+impl Panel {
+    pub fn connect(settings: &PanelSettings) -> Result<Panel> {
+        Ok(Panel {
+            base: format!("{}/api/client/servers/{}", settings.url.trim_end_matches('/'), settings.server),
+            key: settings.api_key()?,
+            power: settings.power,
+        })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        http::api()
+            .request(method, format!("{}{}", self.base, path))
+            .bearer_auth(&self.key)
+            .header(ACCEPT, "application/json")
+    }
+
+    /// Files of the server's directory `dir` with their size, none when it
+    /// doesn't exist.
+    pub fn list(&self, dir: &str) -> Result<Vec<(String, u64)>> {
+        let response = self
+            .request(Method::GET, "/files/list")
+            .query(&[("directory", dir)])
+            .send_captured()
+            .with_context(|| format!("failed to list {} on the panel", dir))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let list: List = http::json(response.error_for_status()?)?;
+        Ok(list.data.into_iter().map(|i| i.attributes).filter(|f| f.is_file).map(|f| (f.name, f.size)).collect())
+    }
+
+    /// Uploads the local `file` to `path`, replacing what is there. Files
+    /// go to the node through a signed url of the upload endpoint, as the
+    /// panel's file manager sends them, which keeps them byte for byte.
+    pub fn upload(&self, path: &str, file: &Path) -> Result<()> {
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let contents = fs::read(file).with_context(|| format!("failed to read {}", file.display()))?;
+        let signed: Item<SignedUrl> = http::json(
+            self.request(Method::GET, "/files/upload")
+                .send_captured()
+                .and_then(|r| r.error_for_status())
+                .with_context(|| format!("failed to ask the panel to upload {}", path))?,
+        )?;
+        let (content_type, body) = multipart(name, &contents);
+        http::files()
+            .post(&signed.attributes.url)
+            .query(&[("directory", if dir.is_empty() { "/" } else { dir })])
+            .header(CONTENT_TYPE, content_type)
+            .body(body)
+            .send_captured()
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("failed to upload {} to the panel", path))?;
+        Ok(())
+    }
+
+    /// Creates the directory `name` in `root`, which may exist already.
+    pub fn create_folder(&self, root: &str, name: &str) {
+        let created = self
+            .request(Method::POST, "/files/create-folder")
+            .json(&json!({ "root": root, "name": name }))
+            .send_captured();
+        if let Err(e) = created {
+            let _ = log_to_file(&format!("[WARN] failed to create {} in {} on the panel: {}", name, root, e));
+        }
+    }
+
+    pub fn delete(&self, paths: &[String]) -> Result<()> {
+        let files: Vec<&str> = paths.iter().map(|p| p.trim_start_matches('/')).collect();
+        self.request(Method::POST, "/files/delete")
+            .json(&json!({ "root": "/", "files": files }))
+            .send_captured()
+            .and_then(|r| r.error_for_status())
+            .context("failed to delete files on the panel")?;
+        Ok(())
+    }
+
+    /// Whether the server's directory `dir` holds a `server.properties`.
+    pub fn is_game_dir(&self, dir: &str) -> Result<bool> {
+        Ok(self.list(dir)?.iter().any(|(name, _)| name == "server.properties"))
+    }
+
+    /// Sends the power `signal`, `start`, `stop` or `restart`, and waits
+    /// for the server to be `until`.
+    pub fn power(&self, signal: &str, until: &str) -> Result<()> {
+        self.request(Method::POST, "/power")
+            .json(&json!({ "signal": signal }))
+            .send_captured()
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("failed to {} the server on the panel", signal))?;
+        let started = Instant::now();
+        loop {
+            let resources: Item<Resources> = http::json(self.request(Method::GET, "/resources").send_captured()?.error_for_status()?)?;
+            if resources.attributes.current_state == until {
+                let _ = log_to_file(&format!("[INFO] the server is {} after {}s", until, started.elapsed().as_secs()));
+                return Ok(());
+            }
+            if started.elapsed() >= POWER_TIMEOUT {
+                return Err(anyhow!(
+                    "the server is still {} {} seconds after asking the panel to {} it",
+                    resources.attributes.current_state,
+                    POWER_TIMEOUT.as_secs(),
+                    signal
+                ));
+            }
+            thread::sleep(Duration::from_secs(2));
+        }
+    }
+}
+
+/// The content type and body of a form uploading `contents` as the file
+/// `name`, as the upload endpoint of the node takes it.
+fn multipart(name: &str, contents: &[u8]) -> (String, Vec<u8>) {
+    let mut nonce = [0u8; 12];
+    let _ = getrandom::getrandom(&mut nonce);
+    let boundary = format!("modpack-sync-{}", nonce.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    let name = name.replace(['"', '\r', '\n'], "_");
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+        boundary, name
+    )
+    .into_bytes();
+    body.extend_from_slice(contents);
+    body.extend(format!("\r\n--{}--\r\n", boundary).into_bytes());
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uploads_are_sent_as_they_are() {
+        let contents = [0x50, 0x4b, 0x03, 0x04, 0x00, 0xff, b'\r', b'\n'];
+        let (content_type, body) = multipart("a\"b.jar", &contents);
+        let boundary = content_type.strip_prefix("multipart/form-data; boundary=").unwrap();
+        let head = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"a_b.jar\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+            boundary
+        );
+        assert!(body.starts_with(head.as_bytes()));
+        assert_eq!(&body[head.len()..head.len() + contents.len()], &contents);
+        assert!(body.ends_with(format!("\r\n--{}--\r\n", boundary).as_bytes()));
+    }
+}
@@ -0,0 +1,334 @@
+use super::hash::{self, Algorithm};
+use super::http::{self, RequestExt};
+use super::httpd::{self, Refused};
+use super::lock::Lockfile;
+use super::control::{self, Access, Caller};
+use super::fleet;
+use super::log_to_file;
+use super::stats;
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::RANGE;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Path files are served under, followed by their sha256.
+const FILES_PATH: &str = "/files/";
+/// Path the statistics of the pack are served at.
+const STATS_PATH: &str = "/stats.json";
+/// Path the shields.io badges of the statistics are served under, followed
+/// by the field.
+const BADGE_PATH: &str = "/badge/";
+/// Path the pack a server advertises is served at, see [`Advertised`].
+pub const WELL_KNOWN_PATH: &str = "/.well-known/modpack-sync.json";
+/// Port `serve` listens on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 8990;
+/// Downloads served at once, more are asked to come back later so a game
+/// server keeps bandwidth for its players.
+const MAX_CONNECTIONS: usize = 32;
+/// Files a sync needs from peers before they are worth probing.
+pub const PROBE_MIN_DOWNLOADS: usize = 8;
+/// Bytes asked of each peer to measure it.
+const PROBE_BYTES: u64 = 256 * 1024;
+
+/// What a server advertises about its pack, served at [`WELL_KNOWN_PATH`]
+/// or under `modpack_sync` in the status a server list ping answers with.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Advertised {
+    /// Url of the pack's manifest, the joined instance extends it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<String>,
+    /// Published pack the joined instance syncs from, as `source` in
+    /// `modpack-sync.toml`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// Urls of `modpack-sync serve` instances sharing the pack's files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub peers: Vec<String>,
+}
+
+impl Advertised {
+    /// Whether it names a pack to join.
+    pub fn has_pack(&self) -> bool {
+        self.manifest.is_some() || self.source.is_some()
+    }
+}
+
+/// A request for `url` of a peer, with `token` where one is set.
+fn get(url: &str, token: Option<&str>) -> reqwest::blocking::RequestBuilder {
+    let request = http::files().get(url);
+    match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// Downloads the file with `sha256` from the first of `peers` that has it
+/// intact, handing back its size and the peer it came from. Peers that are
+/// down, don't have it or send something else are skipped.
+pub fn fetch(peers: &[String], token: Option<&str>, sha256: &str, dest: &Path) -> Option<(u64, String)> {
+    for peer in peers {
+        let url = format!("{}{}{}", peer.trim_end_matches('/'), FILES_PATH, sha256);
+        let bytes = get(&url, token)
+            .send_captured()
+            .and_then(|r| r.error_for_status())
+            .map_err(anyhow::Error::from)
+            .and_then(http::buffered);
+        match bytes {
+            Ok(bytes) if hash::sha256_bytes(&bytes) == sha256 => {
+                let written = dest
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(dest, &bytes));
+                match written {
+                    Ok(()) => return Some((bytes.len() as u64, peer.clone())),
+                    Err(e) => {
+                        let _ = log_to_file(&format!("[WARN]  failed to write {}: {}", dest.display(), e));
+                        return None;
+                    }
+                }
+            }
+            Ok(_) => {
+                let _ = log_to_file(&format!("[WARN]  peer {} sent another file for {}", peer, sha256));
+            }
+            Err(e) => {
+                let _ = log_to_file(&format!("[INFO]  peer {} does not have {}: {}", peer, sha256, e));
+            }
+        }
+    }
+    None
+}
+
+/// Orders `peers` by how fast they sent the start of the file with `sha256`,
+/// the fastest first, for the downloads of this sync. Peers that fail to
+/// send it come last, in the order they were configured in.
+pub fn rank(peers: &[String], token: Option<&str>, sha256: &str) -> Vec<String> {
+    let measured: Vec<Option<f64>> = thread::scope(|s| {
+        let probes: Vec<_> = peers.iter().map(|peer| s.spawn(move || probe(peer, token, sha256))).collect();
+        probes.into_iter().map(|p| p.join().ok().flatten()).collect()
+    });
+    let mut ranked: Vec<(&String, Option<f64>)> = peers.iter().zip(measured).collect();
+    ranked.sort_by(|(_, a), (_, b)| b.unwrap_or(-1.0).total_cmp(&a.unwrap_or(-1.0)));
+    ranked.into_iter().map(|(peer, _)| peer.clone()).collect()
+}
+
+/// Bytes per second `peer` sent the first [`PROBE_BYTES`] of a file at,
+/// the time it took to answer included.
+fn probe(peer: &str, token: Option<&str>, sha256: &str) -> Option<f64> {
+    let url = format!("{}{}{}", peer.trim_end_matches('/'), FILES_PATH, sha256);
+    let started = Instant::now();
+    let received = get(&url, token)
+        .header(RANGE, format!("bytes=0-{}", PROBE_BYTES - 1))
+        .timeout(Duration::from_secs(10))
+        .send_captured()
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())
+        .and_then(|r| {
+            let answered = started.elapsed();
+            let mut bytes = Vec::new();
+            r.take(PROBE_BYTES).read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+            Ok((answered, bytes.len()))
+        });
+    match received {
+        Ok((answered, bytes)) => {
+            let elapsed = started.elapsed().as_secs_f64().max(0.001);
+            let _ = log_to_file(&format!(
+                "[INFO] peer {} answered in {}ms, {:.0} KiB/s",
+                peer,
+                answered.as_millis(),
+                bytes as f64 / 1024.0 / elapsed
+            ));
+            Some(bytes as f64 / elapsed)
+        }
+        Err(e) => {
+            let _ = log_to_file(&format!("[WARN] peer {} failed its probe: {}", peer, e));
+            None
+        }
+    }
+}
+
+/// Files of an instance peers may fetch, by sha256: the installed files
+/// the lockfile pins.
+pub fn shared(base_dir: &Path, game_dir: &Path) -> HashMap<String, PathBuf> {
+    let lock = Lockfile::load(base_dir).ok().flatten().unwrap_or_default();
+    lock.files
+        .iter()
+        .filter_map(|(key, file)| Some((file.hashes.get(&Algorithm::Sha256)?.clone(), game_dir.join(key))))
+        .filter(|(_, path)| path.is_file())
+        .collect()
+}
+
+/// What `serve` answers with besides the files.
+pub struct Served {
+    pub access: Access,
+    /// Token clients report their syncs with, none to not record them.
+    pub fleet: Option<String>,
+    /// The pack advertised to everyone at [`WELL_KNOWN_PATH`].
+    pub advertised: Advertised,
+}
+
+/// Serves the pinned files of the instance to peers until the process is
+/// stopped. The lockfile is read for every request, so files of a new
+/// version of the pack are served as soon as it is synced. Where `access`
+/// has tokens, the files and statistics are only served to requests with
+/// one, the statistics and the advertised pack to everyone with a public
+/// status. With a `fleet` token the reports clients post after their syncs
+/// with it are recorded.
+pub fn serve(listener: TcpListener, base_dir: PathBuf, game_dir: PathBuf, served: Served) -> Result<()> {
+    httpd::serve(listener, "peers", MAX_CONNECTIONS, move |stream| handle(stream, &base_dir, &game_dir, &served));
+    Err(anyhow!("stopped accepting peers"))
+}
+
+fn handle(stream: TcpStream, base_dir: &Path, game_dir: &Path, served: &Served) -> Result<()> {
+    let Served { access, fleet, advertised } = served;
+    let fleet = fleet.as_deref();
+    let address = stream.peer_addr().ok();
+    let peer = address.map(|a| a.to_string()).unwrap_or_default();
+    let (head, reader) = httpd::read_head(&stream)?;
+    let mut out = stream;
+    let head = match head {
+        Ok(head) => head,
+        Err(Refused::TooLarge) => return respond(&mut out, "431 Request Header Fields Too Large"),
+        Err(Refused::BadRequest) => return respond(&mut out, "400 Bad Request"),
+    };
+    // only a range, as peers probing it ask for, changes the answer, and
+    // the length and token of a posted report
+    let range = head.header("range").and_then(|r| r.strip_prefix("bytes=")).and_then(|r| r.split_once('-')).and_then(|(start, end)| {
+        Some((start.parse::<u64>().ok()?, end.parse::<u64>().ok()))
+    });
+    let bearer = head.bearer();
+    let path = match (head.method.as_str(), head.path.as_str()) {
+        ("GET", path) => path.to_string(),
+        ("POST", path) if fleet.is_some() && path == fleet::REPORT_PATH => {
+            if !may_report(bearer.as_deref(), fleet) {
+                return respond(&mut out, "401 Unauthorized");
+            }
+            if address.is_some_and(|a| fleet::throttled(a.ip())) {
+                return respond(&mut out, "429 Too Many Requests");
+            }
+            let length = head.content_length();
+            if length == 0 || length > fleet::MAX_REPORT_BYTES {
+                return respond(&mut out, "413 Payload Too Large");
+            }
+            let body = httpd::body(reader, length)?;
+            let Ok(report) = serde_json::from_slice::<fleet::Report>(&body) else {
+                return respond(&mut out, "400 Bad Request");
+            };
+            let _ = log_to_file(&format!("[INFO] {} reported {} on {}", peer, report.client_id, report.version.as_deref().unwrap_or("no version")));
+            fleet::record(base_dir, report)?;
+            return respond(&mut out, "204 No Content");
+        }
+        _ => return respond(&mut out, "400 Bad Request"),
+    };
+    // a server without tokens shares its files with everyone, as before
+    // it had any
+    let stats = path == STATS_PATH || path.starts_with(BADGE_PATH) || path == WELL_KNOWN_PATH;
+    let allowed = match access.scope(&Caller::Web(bearer.clone())) {
+        Some(_) => stats || bearer.is_some(),
+        None => false,
+    };
+    if access.has_tokens() && !allowed {
+        let _ = log_to_file(&format!("[WARN] refused {} to {} without a token", path, peer));
+        return respond(&mut out, "401 Unauthorized");
+    }
+    if path == WELL_KNOWN_PATH {
+        return respond_json(&mut out, &serde_json::to_value(advertised)?);
+    }
+    if stats {
+        return respond_stats(&mut out, base_dir, &path);
+    }
+    let file = path
+        .strip_prefix(FILES_PATH)
+        .and_then(|sha256| shared(base_dir, game_dir).remove(&sha256.to_lowercase()));
+    let Some(file) = file else {
+        return respond(&mut out, "404 Not Found");
+    };
+    let mut contents = fs::File::open(&file).with_context(|| format!("failed to open {}", file.display()))?;
+    let length = contents.metadata()?.len();
+    match range.filter(|(start, _)| *start < length) {
+        Some((start, end)) => {
+            let end = end.unwrap_or(length - 1).min(length - 1);
+            let Some(count) = end.checked_sub(start).and_then(|c| c.checked_add(1)) else {
+                return respond(&mut out, "416 Range Not Satisfiable");
+            };
+            write!(
+                out,
+                "HTTP/1.1 206 Partial Content\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                start,
+                end,
+                length,
+                count
+            )?;
+            contents.seek(SeekFrom::Start(start))?;
+            io::copy(&mut contents.take(count), &mut out)?;
+            return Ok(());
+        }
+        None => write!(
+            out,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            length
+        )?,
+    }
+    io::copy(&mut contents, &mut out)?;
+    let _ = log_to_file(&format!("[INFO] sent {} to {}", file.display(), peer));
+    Ok(())
+}
+
+/// Whether a client that sent `bearer` may report to a fleet taking
+/// reports with `token`.
+fn may_report(bearer: Option<&str>, token: Option<&str>) -> bool {
+    // an empty token would let anyone report
+    bearer.zip(token).is_some_and(|(sent, token)| !token.is_empty() && control::same(sent, token))
+}
+
+/// Answers with the statistics of the pack, read again for every request
+/// like the lockfile they come from.
+fn respond_stats(out: &mut TcpStream, base_dir: &Path, path: &str) -> Result<()> {
+    let stats = stats::build(base_dir, "modlist.json")?;
+    let document = match path.strip_prefix(BADGE_PATH) {
+        Some(field) => match stats::badge(&stats, field) {
+            Ok(badge) => badge,
+            Err(_) => return respond(out, "404 Not Found"),
+        },
+        None => serde_json::to_value(&stats)?,
+    };
+    respond_json(out, &document)
+}
+
+/// Answers with `document`, to the websites of anyone.
+fn respond_json(out: &mut TcpStream, document: &serde_json::Value) -> Result<()> {
+    let body = serde_json::to_vec(document)?;
+    write!(
+        out,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    out.write_all(&body)?;
+    Ok(())
+}
+
+fn respond(out: &mut TcpStream, status: &str) -> Result<()> {
+    write!(out, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_fleet_token_reports() {
+        assert!(may_report(Some("s3cret"), Some("s3cret")));
+        assert!(!may_report(Some("s3cre"), Some("s3cret")));
+        assert!(!may_report(None, Some("s3cret")));
+        assert!(!may_report(Some(""), Some("")));
+        assert!(!may_report(Some("s3cret"), None));
+    }
+}
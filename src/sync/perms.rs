@@ -0,0 +1,80 @@
+use super::settings::PermissionSettings;
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Resolved mode and ownership to give installed files.
+pub struct Permissions {
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+impl Permissions {
+    pub fn resolve(settings: &PermissionSettings) -> Result<Permissions> {
+        let mode = match &settings.mode {
+            Some(mode) => Some(
+                u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                    .ok()
+                    .filter(|m| *m <= 0o7777)
+                    .ok_or_else(|| anyhow!("invalid file mode {}, expected octal such as 0644", mode))?,
+            ),
+            None => None,
+        };
+        let uid = settings.owner.as_deref().map(|o| id("/etc/passwd", o, "user")).transpose()?;
+        let gid = settings.group.as_deref().map(|g| id("/etc/group", g, "group")).transpose()?;
+        Ok(Permissions { mode, uid, gid })
+    }
+
+    /// Applies the settings to `files` and the directories holding them up
+    /// to `game_dir`. Directories get the file mode with search permission
+    /// wherever it allows reading.
+    pub fn apply(&self, game_dir: &Path, files: &[PathBuf]) -> Result<()> {
+        let mut dirs = BTreeSet::new();
+        for file in files.iter().filter(|f| f.is_file()) {
+            self.set(file, self.mode)?;
+            dirs.extend(file.ancestors().skip(1).take_while(|d| d.starts_with(game_dir) && *d != game_dir).map(Path::to_path_buf));
+        }
+        let dir_mode = self.mode.map(|m| m | ((m & 0o444) >> 2));
+        for dir in dirs {
+            self.set(&dir, dir_mode)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn set(&self, path: &Path, mode: Option<u32>) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))
+                .with_context(|| format!("failed to set the mode of {}", path.display()))?;
+        }
+        if self.uid.is_some() || self.gid.is_some() {
+            std::os::unix::fs::chown(path, self.uid, self.gid)
+                .with_context(|| format!("failed to change the owner of {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Windows has neither modes nor numeric owners.
+    #[cfg(not(unix))]
+    fn set(&self, _path: &Path, _mode: Option<u32>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Numeric id of a user or group, looked up by name in `/etc/passwd` or
+/// `/etc/group` unless it already is one.
+fn id(database: &str, name: &str, kind: &str) -> Result<u32> {
+    if let Ok(id) = name.parse::<u32>() {
+        return Ok(id);
+    }
+    let contents = fs::read_to_string(database).with_context(|| format!("failed to read {}", database))?;
+    contents
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&name))
+        .and_then(|fields| fields.get(2).and_then(|id| id.parse().ok()))
+        .ok_or_else(|| anyhow!("no {} named {} in {}", kind, name, database))
+}
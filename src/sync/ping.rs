@@ -0,0 +1,148 @@
+use super::http;
+use super::log_to_file;
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Port Minecraft servers listen on unless the address names another.
+pub const DEFAULT_PORT: u16 = 25565;
+/// Largest status response accepted, a server with a big icon and player
+/// sample stays well below it.
+const MAX_RESPONSE: usize = 1024 * 1024;
+
+/// Host and port of a server address such as `play.example.com:25566`.
+pub fn split(address: &str) -> Result<(String, u16)> {
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+            let port = port.parse().with_context(|| format!("invalid port in server address {}", address))?;
+            Ok((host.trim_matches(['[', ']']).to_string(), port))
+        }
+        _ => Ok((address.trim_matches(['[', ']']).to_string(), DEFAULT_PORT)),
+    }
+}
+
+/// Host and port the server at `address` is reached at. An address
+/// without a port follows the `_minecraft._tcp` SRV record of its host, as
+/// the game does.
+pub fn resolve(address: &str) -> Result<(String, u16)> {
+    let (host, port) = split(address)?;
+    if port != DEFAULT_PORT || address.ends_with(&format!(":{}", DEFAULT_PORT)) || host.parse::<IpAddr>().is_ok() {
+        return Ok((host, port));
+    }
+    match http::srv(&format!("_minecraft._tcp.{}", host)) {
+        Ok(Some(target)) => Ok(target),
+        Ok(None) => Ok((host, port)),
+        Err(e) => {
+            let _ = log_to_file(&format!("[INFO] no SRV record of {}: {:#}", host, e));
+            Ok((host, port))
+        }
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(input: &mut impl Read) -> Result<i32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let mut byte = [0u8];
+        input.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u32) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(anyhow!("the server sent a malformed number"))
+}
+
+fn packet(id: i32, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_varint(&mut body, id);
+    body.extend_from_slice(payload);
+    let mut packet = Vec::new();
+    write_varint(&mut packet, body.len() as i32);
+    packet.extend(body);
+    packet
+}
+
+/// Asks the server at `address` for the status its server list entry shows,
+/// the JSON document of the Server List Ping with its version, players and
+/// `description` (the MOTD).
+pub fn status(address: &str) -> Result<Value> {
+    let (host, port) = resolve(address)?;
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()
+        .with_context(|| format!("invalid server address {}", address))?
+        .next()
+        .ok_or_else(|| anyhow!("server address {} does not resolve", address))?;
+    let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(10))
+        .with_context(|| format!("failed to connect to {}", address))?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    // handshake into the status state, any protocol version will do for it
+    let mut handshake = Vec::new();
+    write_varint(&mut handshake, -1);
+    write_varint(&mut handshake, host.len() as i32);
+    handshake.extend_from_slice(host.as_bytes());
+    handshake.extend_from_slice(&port.to_be_bytes());
+    write_varint(&mut handshake, 1);
+    stream.write_all(&packet(0, &handshake))?;
+    stream.write_all(&packet(0, &[]))?;
+
+    let length = read_varint(&mut stream).with_context(|| format!("{} did not answer the status request", address))?;
+    if length <= 0 || length as usize > MAX_RESPONSE {
+        return Err(anyhow!("{} sent a status of {} bytes", address, length));
+    }
+    let mut body = vec![0u8; length as usize];
+    stream.read_exact(&mut body)?;
+    let mut body = body.as_slice();
+    if read_varint(&mut body)? != 0 {
+        return Err(anyhow!("{} answered the status request with another packet", address));
+    }
+    let json_length = read_varint(&mut body)?;
+    let json = body
+        .get(..json_length.max(0) as usize)
+        .ok_or_else(|| anyhow!("{} sent a truncated status", address))?;
+    serde_json::from_slice(json).with_context(|| format!("{} sent a malformed status", address))
+}
+
+/// Plain text of the MOTD in a status, without the formatting of its text
+/// components and `§` codes.
+pub fn motd(status: &Value) -> String {
+    fn text(component: &Value, out: &mut String) {
+        match component {
+            Value::String(s) => out.push_str(s),
+            Value::Array(parts) => parts.iter().for_each(|p| text(p, out)),
+            Value::Object(_) => {
+                text(&component["text"], out);
+                text(&component["extra"], out);
+            }
+            _ => {}
+        }
+    }
+    let mut raw = String::new();
+    text(&status["description"], &mut raw);
+    let mut plain = String::new();
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '§' => {
+                chars.next();
+            }
+            c => plain.push(c),
+        }
+    }
+    plain
+}
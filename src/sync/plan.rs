@@ -0,0 +1,882 @@
+use super::hash;
+use super::hosts;
+use super::http;
+use super::index::ModMeta;
+use super::jar;
+use super::links;
+use super::log;
+use super::{log_to_file, trace};
+use super::lock;
+use super::manifest::{self, Kind, Mod, PackHeader};
+use super::maven;
+use super::modrinth;
+use super::overrides;
+use super::settings::Symlinks;
+use super::state::{self, State};
+use super::version;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Input naming a modlist given with `--modlist` rather than read from the
+/// instance.
+const MODLIST_INPUT: &str = "--modlist";
+
+/// `source` recorded in the state for manually placed files.
+pub const MANUAL_SOURCE: &str = "manual";
+/// `source` recorded for jars extracted from the ones a mod bundles.
+pub const BUNDLED_SOURCE: &str = "bundled";
+/// `source` recorded for files a post-install command wrote.
+pub const SCRIPT_SOURCE: &str = "script";
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    Disabled,
+    MissingUrl,
+}
+
+/// Where an installed file is downloaded from.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum Source {
+    Curseforge { project_id: String },
+    Maven(maven::Coordinate),
+    Modrinth(modrinth::Pin),
+    Local { path: PathBuf },
+}
+
+impl Source {
+    /// Provider recorded in the state for files from the source.
+    pub fn provider(&self) -> &'static str {
+        match self {
+            Source::Curseforge { .. } => "curseforge",
+            Source::Maven(_) => "maven",
+            Source::Modrinth(_) => "modrinth",
+            Source::Local { .. } => "local",
+        }
+    }
+
+    /// Host the file is downloaded from, `local` for copies of local files.
+    pub fn host(&self) -> String {
+        match self {
+            Source::Curseforge { .. } => "www.curseforge.com".to_string(),
+            Source::Maven(coordinate) => hosts::of(&coordinate.repo),
+            Source::Modrinth(_) => "cdn.modrinth.com".to_string(),
+            Source::Local { .. } => "local".to_string(),
+        }
+    }
+
+    /// Where the file comes from, for people.
+    pub fn url(&self) -> String {
+        match self {
+            Source::Curseforge { project_id } => format!("https://www.curseforge.com/projects/{}", project_id),
+            Source::Maven(c) => format!("{}:{}:{} from {}", c.group, c.artifact, c.version, c.repo),
+            Source::Modrinth(pin) => modrinth::page(&pin.project_id),
+            Source::Local { path } => path.display().to_string(),
+        }
+    }
+
+    /// What the downloaded contents are pinned by, `file_id` being the
+    /// CurseForge file the project resolved to. `None` for local copies and
+    /// Maven versions moving with new releases.
+    pub fn pinned(&self, file_id: Option<u64>) -> Option<String> {
+        match self {
+            Source::Curseforge { project_id } => file_id.map(|id| format!("curseforge:{}:{}", project_id, id)),
+            Source::Maven(c) if c.moving() => None,
+            Source::Maven(c) => Some(format!("maven:{}:{}:{}:{}", c.group, c.artifact, c.version, c.classifier.as_deref().unwrap_or_default())),
+            Source::Modrinth(pin) => Some(format!("modrinth:{}:{}", pin.project_id, pin.version_id)),
+            Source::Local { .. } => None,
+        }
+    }
+
+    /// Urls worth connecting to before downloading the file, none for
+    /// local copies.
+    pub fn origins(&self) -> Vec<String> {
+        match self {
+            Source::Curseforge { .. } => ["https://www.curseforge.com/"]
+                .into_iter()
+                .chain(http::CURSEFORGE_CDN)
+                .map(str::to_string)
+                .collect(),
+            Source::Maven(coordinate) => vec![coordinate.repo.clone()],
+            Source::Modrinth(_) => vec!["https://api.modrinth.com/".to_string(), "https://cdn.modrinth.com/".to_string()],
+            Source::Local { .. } => Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// The entry is already installed at its destination.
+    Keep { filename: String },
+    /// Download the entry's file to `dest`, then remove the file it replaces.
+    Install {
+        source: Source,
+        filename: String,
+        dest: PathBuf,
+        replaces: Option<PathBuf>,
+        /// Extract the downloaded zip next to `dest` instead of keeping it.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        unpack: bool,
+        /// The zip is a world extracted into the directory of `dest`, which
+        /// is never touched again once installed.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        world: bool,
+        /// Modrinth version to download instead when CurseForge keeps
+        /// failing, for entries that declare both.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fallback: Option<modrinth::Pin>,
+    },
+    /// Check the hash of a file placed by hand, the entry can't be
+    /// downloaded.
+    Verify {
+        filename: String,
+        dest: PathBuf,
+        project_id: Option<String>,
+        sha256: Option<String>,
+    },
+    /// The entry is left alone.
+    Skip { filename: String, reason: SkipReason },
+    /// A file in the mods directory, or one the tool installed elsewhere, no
+    /// longer referenced by the manifest.
+    Remove { path: PathBuf },
+    /// Run a post-install command of the entry installed at `dest`, after
+    /// the installs, recording the `outputs` it writes.
+    Script {
+        filename: String,
+        dest: PathBuf,
+        command: Vec<String>,
+        outputs: Vec<PathBuf>,
+    },
+}
+
+impl Action {
+    /// The change as a line for review, `None` for entries left as they are.
+    pub fn describe(&self) -> Option<String> {
+        Some(match self {
+            Action::Install { filename, replaces: Some(old), .. } => format!("install {} replacing {}", filename, old.display()),
+            Action::Install { filename, world: true, dest, .. } => {
+                format!("install the world {} into {}", filename, dest.parent().unwrap_or(dest).display())
+            }
+            Action::Install { filename, unpack: true, .. } => format!("install and unpack {}", filename),
+            Action::Install { filename, .. } => format!("install {}", filename),
+            Action::Verify { filename, .. } => format!("verify {}", filename),
+            Action::Remove { path } => format!("remove {}", path.display()),
+            Action::Script { filename, command, .. } => format!("run `{}` for {}", command.join(" "), filename),
+            Action::Keep { .. } | Action::Skip { .. } => return None,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+}
+
+impl Plan {
+    /// The plan with every path in the instance passed through `map`, to
+    /// store them relative to the instance and resolve them again.
+    fn map_paths(self, map: impl Fn(&Path) -> PathBuf) -> Plan {
+        let actions = self
+            .actions
+            .into_iter()
+            .map(|action| match action {
+                Action::Install { source, filename, dest, replaces, unpack, world, fallback } => Action::Install {
+                    source,
+                    filename,
+                    dest: map(&dest),
+                    replaces: replaces.as_deref().map(&map),
+                    unpack,
+                    world,
+                    fallback,
+                },
+                Action::Verify { filename, dest, project_id, sha256 } => Action::Verify {
+                    filename,
+                    dest: map(&dest),
+                    project_id,
+                    sha256,
+                },
+                Action::Remove { path } => Action::Remove { path: map(&path) },
+                Action::Script { filename, dest, command, outputs } => Action::Script {
+                    filename,
+                    dest: map(&dest),
+                    command,
+                    outputs: outputs.iter().map(|o| map(o)).collect(),
+                },
+                other => other,
+            })
+            .collect();
+        Plan { actions }
+    }
+
+    /// Paths in the instance the plan writes or deletes.
+    fn paths(&self) -> Vec<&Path> {
+        let mut paths = Vec::new();
+        for action in self.actions.iter() {
+            match action {
+                Action::Install { dest, replaces, .. } => {
+                    paths.push(dest.as_path());
+                    paths.extend(replaces.as_deref());
+                }
+                Action::Verify { dest, .. } => paths.push(dest.as_path()),
+                Action::Remove { path } => paths.push(path.as_path()),
+                Action::Script { outputs, .. } => paths.extend(outputs.iter().map(PathBuf::as_path)),
+                _ => {}
+            }
+        }
+        paths
+    }
+}
+
+/// A plan saved by `modpack-sync plan` to be reviewed and applied later with
+/// `modpack-sync apply`. Paths are relative to the instance directory so the
+/// plan can be made on a copy of the instance.
+#[derive(Serialize, Deserialize)]
+pub struct PlanFile {
+    pub created_at: DateTime<Utc>,
+    pub pack: Option<PackHeader>,
+    /// sha256 of the modlist, lockfile, overrides and every file the plan
+    /// touches when it was made, `None` for files that did not exist. A
+    /// modlist given with `--modlist` is recorded under that name.
+    pub inputs: BTreeMap<String, Option<String>>,
+    pub plan: Plan,
+    /// CurseForge projects whose new owner was acknowledged for the plan,
+    /// trusted when it is applied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acknowledged: Vec<String>,
+}
+
+impl PlanFile {
+    pub fn new(base_dir: &Path, modlist: Modlist, pack: Option<PackHeader>, plan: Plan) -> Result<PlanFile> {
+        let plan = plan.map_paths(|p| p.strip_prefix(base_dir).unwrap_or(p).to_path_buf());
+        let inputs = inputs(base_dir, modlist, &plan)?;
+        Ok(PlanFile {
+            created_at: Utc::now(),
+            pack,
+            inputs,
+            plan,
+            acknowledged: Vec::new(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<PlanFile> {
+        let contents = fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("{} is not a plan file", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// The stored plan with its paths resolved against `base_dir`, provided
+    /// the instance is still as it was when the plan was made: its files
+    /// hash the same and planning again comes to the same actions.
+    pub fn resolve(self, base_dir: &Path, modlist: Modlist, current: Plan) -> Result<Plan> {
+        let current = current.map_paths(|p| p.strip_prefix(base_dir).unwrap_or(p).to_path_buf());
+        let now = inputs(base_dir, modlist, &self.plan)?;
+        let changed: Vec<&String> = self
+            .inputs
+            .iter()
+            .filter(|(path, sha256)| now.get(*path) != Some(sha256))
+            .map(|(path, _)| path)
+            .collect();
+        if !changed.is_empty() {
+            let list: Vec<&str> = changed.iter().map(|p| p.as_str()).collect();
+            return Err(anyhow!("the instance changed since the plan was made: {}", list.join(", ")));
+        }
+        if serde_json::to_value(&current)? != serde_json::to_value(&self.plan)? {
+            return Err(anyhow!("the instance no longer plans to the same changes, make a new plan"));
+        }
+        Ok(self.plan.map_paths(|p| base_dir.join(p)))
+    }
+}
+
+/// The modlist a plan is made from.
+#[derive(Clone, Copy)]
+pub enum Modlist<'a> {
+    /// The file of the instance.
+    File(&'a str),
+    /// The contents given with `--modlist`.
+    Given(&'a str),
+}
+
+/// Hashes of what a plan was made from, keyed by path relative to
+/// `base_dir`. `plan` holds relative paths.
+fn inputs(base_dir: &Path, modlist: Modlist, plan: &Plan) -> Result<BTreeMap<String, Option<String>>> {
+    let mut inputs = BTreeMap::new();
+    let mut files = vec![PathBuf::from(lock::LOCK_FILE)];
+    match modlist {
+        Modlist::File(mods_file) => files.push(PathBuf::from(mods_file)),
+        Modlist::Given(contents) => {
+            inputs.insert(MODLIST_INPUT.to_string(), Some(hash::sha256_bytes(contents.as_bytes())));
+        }
+    }
+    let root = base_dir.join(overrides::OVERRIDES_DIR);
+    if root.is_dir() {
+        let mut found = Vec::new();
+        overrides::files(&root, &mut found)?;
+        files.extend(found.iter().map(|f| f.strip_prefix(base_dir).unwrap_or(f).to_path_buf()));
+    }
+    files.extend(plan.paths().into_iter().map(Path::to_path_buf));
+
+    for file in files {
+        let path = base_dir.join(&file);
+        let sha256 = match path.is_file() {
+            true => Some(hash::sha256_file(&path)?),
+            false => None,
+        };
+        inputs.insert(file.to_string_lossy().replace('\\', "/"), sha256);
+    }
+    Ok(inputs)
+}
+
+/// What is looked up ahead of building a plan, which reads the disk alone.
+pub struct Lookups<'a> {
+    /// The launcher's metadata of the files in the mods directory.
+    pub metadata: &'a HashMap<String, ModMeta>,
+    /// Entries on a moving Maven version whose artifact moved on, from
+    /// [`moved_artifacts`].
+    pub moved: &'a HashSet<String>,
+}
+
+/// File names of the entries on a moving Maven version, `latest`, `release`
+/// or a snapshot, whose artifact on disk is no longer the one the version
+/// resolves to. Those that can't be looked up are kept.
+pub fn moved_artifacts(mods: &[Mod], game_dir: &Path, mods_dir: &Path) -> Result<HashSet<String>> {
+    let mut moved = HashSet::new();
+    for m in mods.iter() {
+        let Some(coordinate) = m.maven.as_ref().filter(|c| c.moving()) else {
+            continue;
+        };
+        let dest = m.install_path(game_dir, mods_dir)?;
+        if !dest.exists() {
+            continue;
+        }
+        let current = coordinate.is_current(&dest).unwrap_or_else(|e| {
+            let _ = log_to_file(&format!("[WARN] failed to look up the current {} of {}, keeping it: {:#}", coordinate.version, m.filename, e));
+            true
+        });
+        if !current {
+            moved.insert(m.filename.clone());
+        }
+    }
+    Ok(moved)
+}
+
+/// Works out what has to change for the instance to match `mods`. Nothing is
+/// touched on disk and no network requests are made, file ids are resolved
+/// when the plan is applied and the rest is in `lookups`. Local files are
+/// hashed to tell if they changed. Unreferenced files in `managed_dirs` with
+/// the extensions listed for them are removed, symbolic links only when
+/// `symlinks` follows them.
+pub fn build(
+    mods: &[Mod],
+    game_dir: &Path,
+    mods_dir: &Path,
+    managed_dirs: &[(PathBuf, Vec<String>)],
+    lookups: &Lookups,
+    state: &State,
+    symlinks: Symlinks,
+) -> Result<Plan> {
+    let metadata = lookups.metadata;
+    let mut actions = Vec::new();
+    // compared normalized so a differently cased copy isn't seen as unused
+    let mut managed: HashSet<String> = HashSet::new();
+
+    for m in mods.iter() {
+        let dest = m.install_path(game_dir, mods_dir)?;
+        managed.insert(normalize(dest.as_os_str()));
+        trace!(
+            "{}: name={} version={} parsed version={} dest={} exists={}",
+            m.filename,
+            m.name,
+            m.version,
+            version::of_file(&m.filename, None).as_deref().unwrap_or("?"),
+            dest.display(),
+            dest.exists()
+        );
+
+        if m.filename.ends_with(".disabled") {
+            trace!("{}: skip, the file name ends with .disabled", m.filename);
+            actions.push(Action::Skip {
+                filename: m.filename.clone(),
+                reason: SkipReason::Disabled,
+            });
+            continue;
+        }
+
+        // placed by hand, verified once and then only if it changes
+        if m.manual {
+            let verified = state.get(game_dir, &dest).is_some_and(|managed| {
+                managed.source == MANUAL_SOURCE
+                    && m.sha256.as_ref().is_none_or(|sha256| sha256.eq_ignore_ascii_case(&managed.sha256))
+                    && hash::sha256_file(&dest).is_ok_and(|h| h == managed.sha256)
+            });
+            trace!(
+                "{}: manual, recorded sha256={} expected sha256={} -> {}",
+                m.filename,
+                state.get(game_dir, &dest).map_or("none", |managed| managed.sha256.as_str()),
+                m.sha256.as_deref().unwrap_or("any"),
+                if verified { "keep, verified before" } else { "verify" }
+            );
+            if verified {
+                actions.push(Action::Keep {
+                    filename: m.filename.clone(),
+                });
+            } else {
+                actions.push(Action::Verify {
+                    filename: m.filename.clone(),
+                    dest,
+                    project_id: m.project_id().map(|id| id.to_string()),
+                    sha256: m.sha256.clone(),
+                });
+            }
+            continue;
+        }
+
+        // worlds are the players' once installed, whatever became of them.
+        // A directory of the name the sync never installed is refused when
+        // installing, not taken for the world
+        if m.kind == Kind::World {
+            let installed = state.worlds.contains_key(&state::key(game_dir, &dest));
+            trace!("{}: world into {}, {}", m.filename, dest.display(), if installed { "keep, installed before" } else { "install" });
+            match entry_source(m) {
+                _ if installed => actions.push(Action::Keep {
+                    filename: m.filename.clone(),
+                }),
+                Some(source) => actions.push(Action::Install {
+                    source,
+                    filename: m.filename.clone(),
+                    dest: dest.join(&m.filename),
+                    replaces: None,
+                    unpack: true,
+                    world: true,
+                    fallback: None,
+                }),
+                None => actions.push(Action::Skip {
+                    filename: m.filename.clone(),
+                    reason: SkipReason::MissingUrl,
+                }),
+            }
+            continue;
+        }
+
+        // archives are gone once extracted, what they left behind is checked
+        if m.unpack {
+            let extracted = state.get(game_dir, &dest).is_some_and(|managed| {
+                !managed.files.is_empty() && managed.files.iter().all(|f| game_dir.join(f).is_file())
+            });
+            trace!(
+                "{}: unpack, recorded files={} all present={}",
+                m.filename,
+                state.get(game_dir, &dest).map_or(0, |managed| managed.files.len()),
+                extracted
+            );
+            let source = entry_source(m);
+            let fallback = m.modrinth.clone().filter(|_| matches!(source, Some(Source::Curseforge { .. })));
+            match source {
+                _ if extracted => actions.push(Action::Keep {
+                    filename: m.filename.clone(),
+                }),
+                Some(source) => actions.push(Action::Install {
+                    source,
+                    filename: m.filename.clone(),
+                    dest,
+                    replaces: None,
+                    unpack: true,
+                    world: false,
+                    fallback,
+                }),
+                None => actions.push(Action::Skip {
+                    filename: m.filename.clone(),
+                    reason: SkipReason::MissingUrl,
+                }),
+            }
+            continue;
+        }
+
+        // artifacts are named after their version, so one on disk is current
+        // unless the version moves, then the repository's checksum of what
+        // it resolves to tells
+        if let Some(coordinate) = &m.maven {
+            let current = dest.exists() && !lookups.moved.contains(&m.filename);
+            trace!(
+                "{}: maven {}:{}:{}, {}",
+                m.filename,
+                coordinate.group,
+                coordinate.artifact,
+                coordinate.version,
+                match (current, dest.exists()) {
+                    (true, _) => "keep, the artifact on disk is current",
+                    (false, true) => "install, the version moved on",
+                    (false, false) => "install, the artifact is missing",
+                }
+            );
+            if current {
+                actions.push(Action::Keep {
+                    filename: m.filename.clone(),
+                });
+            } else {
+                actions.push(Action::Install {
+                    source: Source::Maven(coordinate.clone()),
+                    filename: m.filename.clone(),
+                    dest,
+                    replaces: None,
+                    unpack: false,
+                    world: false,
+                    fallback: None,
+                });
+            }
+            continue;
+        }
+
+        // versions are pinned and their files named after them, entries on
+        // CurseForge too are downloaded from there
+        if let Some(pin) = m.modrinth.as_ref().filter(|_| m.project_id().is_none()) {
+            trace!(
+                "{}: modrinth {} version {}, {}",
+                m.filename,
+                pin.project_id,
+                pin.version_id,
+                if dest.exists() { "keep, the file is on disk" } else { "install, the file is missing" }
+            );
+            if dest.exists() {
+                actions.push(Action::Keep {
+                    filename: m.filename.clone(),
+                });
+            } else {
+                actions.push(Action::Install {
+                    source: Source::Modrinth(pin.clone()),
+                    filename: m.filename.clone(),
+                    dest,
+                    replaces: None,
+                    unpack: false,
+                    world: false,
+                    fallback: None,
+                });
+            }
+            continue;
+        }
+
+        // local files are compared by content, they can be rebuilt in place
+        if let Some(path) = m.local_path() {
+            let current = dest.exists() && hash::sha256_file(&dest).ok() == hash::sha256_file(&path).ok();
+            if log::tracing() {
+                trace!(
+                    "{}: local {}, sha256 on disk={} of source={} -> {}",
+                    m.filename,
+                    path.display(),
+                    hash::sha256_file(&dest).unwrap_or_else(|_| "none".to_string()),
+                    hash::sha256_file(&path).unwrap_or_else(|_| "unreadable".to_string()),
+                    if current { "keep" } else { "install" }
+                );
+            }
+            if current {
+                actions.push(Action::Keep {
+                    filename: m.filename.clone(),
+                });
+            } else {
+                actions.push(Action::Install {
+                    source: Source::Local { path },
+                    filename: m.filename.clone(),
+                    dest,
+                    replaces: None,
+                    unpack: false,
+                    world: false,
+                    fallback: None,
+                });
+            }
+            continue;
+        }
+
+        let Some(project_id) = m.project_id() else {
+            trace!("{}: skip, no url, maven coordinate or CurseForge project", m.filename);
+            actions.push(Action::Skip {
+                filename: m.filename.clone(),
+                reason: SkipReason::MissingUrl,
+            });
+            continue;
+        };
+
+        // Prism only tracks files it placed in the mods directory itself, so
+        // relocated or renamed entries are judged by their destination alone.
+        let relocated = m.target_dir.is_some() || m.install_as.is_some();
+        let meta = if relocated { None } else { metadata.get(project_id) };
+
+        let managed = state.get(game_dir, &dest).filter(|_| dest.exists());
+        trace!(
+            "{}: curseforge project {}, relocated={} launcher metadata={} recorded={}",
+            m.filename,
+            project_id,
+            relocated,
+            meta.map_or("none".to_string(), |meta| meta.filename.clone()),
+            managed.map_or("no".to_string(), |managed| format!(
+                "sha256={} file id={} size={}",
+                managed.sha256,
+                managed.file_id.map_or("?".to_string(), |id| id.to_string()),
+                managed.size.map_or("?".to_string(), |size| size.to_string())
+            ))
+        );
+        if managed.is_some_and(|managed| managed.damaged(&dest)) {
+            trace!("{}: install, the file on disk is {} bytes", m.filename, fs::metadata(&dest).map_or(0, |meta| meta.len()));
+            let _ = log_to_file(&format!("[WARN] {} does not have the size CurseForge reported, reinstalling it", m.filename));
+            actions.push(Action::Install {
+                source: Source::Curseforge {
+                    project_id: project_id.to_string(),
+                },
+                filename: m.filename.clone(),
+                dest,
+                replaces: None,
+                unpack: false,
+                world: false,
+                fallback: m.modrinth.clone(),
+            });
+            continue;
+        }
+        // relocated entries keep their destination across files, so what
+        // was recorded, or the digest the entry pins, tells them apart
+        let recorded = managed.is_some_and(|managed| installed_for(managed, m));
+        let pinned = || m.sha256.as_ref().is_some_and(|sha256| hash::sha256_file(&dest).is_ok_and(|h| h.eq_ignore_ascii_case(sha256)));
+        let up_to_date = recorded
+            || match meta {
+                Some(meta) => same_name(&meta.filename, &m.filename),
+                None => relocated && pinned(),
+            };
+
+        if up_to_date {
+            trace!(
+                "{}: keep, {}",
+                m.filename,
+                match (recorded, meta) {
+                    (true, _) => "recorded as installed",
+                    (false, Some(_)) => "the launcher metadata names the same file",
+                    (false, None) => "a relocated file is at its destination",
+                }
+            );
+            actions.push(Action::Keep {
+                filename: m.filename.clone(),
+            });
+            continue;
+        }
+
+        let replaces = meta
+            .filter(|meta| !same_name(&meta.filename, &m.filename))
+            .map(|meta| mods_dir.join(&meta.filename));
+        trace!(
+            "{}: install, {}",
+            m.filename,
+            match &replaces {
+                Some(old) => format!("replacing {} the launcher metadata names", old.display()),
+                None if meta.is_some() => "the launcher metadata names another file".to_string(),
+                None => "neither recorded nor in the launcher metadata".to_string(),
+            }
+        );
+
+        actions.push(Action::Install {
+            source: Source::Curseforge {
+                project_id: project_id.to_string(),
+            },
+            filename: m.filename.clone(),
+            dest,
+            replaces,
+            unpack: false,
+            world: false,
+            fallback: m.modrinth.clone(),
+        });
+    }
+
+    // post-install commands run after their entry is installed, and again
+    // when what they wrote is gone
+    for m in mods.iter().filter(|m| !m.post_install.is_empty()) {
+        let dest = m.install_path(game_dir, mods_dir)?;
+        let installing = actions.iter().any(|a| matches!(a, Action::Install { dest: d, .. } if *d == dest));
+        for script in m.post_install.iter() {
+            let outputs = script
+                .outputs
+                .iter()
+                .map(|o| Ok(game_dir.join(manifest::contained(o, &m.filename)?)))
+                .collect::<Result<Vec<PathBuf>>>()?;
+            managed.extend(outputs.iter().map(|o| normalize(o.as_os_str())));
+            let missing = dest.exists() && outputs.iter().any(|o| !o.is_file());
+            trace!("{}: post-install {}, installing={} outputs missing={}", m.filename, script.command.join(" "), installing, missing);
+            if installing || missing {
+                actions.push(Action::Script {
+                    filename: m.filename.clone(),
+                    dest: dest.clone(),
+                    command: script.command.clone(),
+                    outputs,
+                });
+            }
+        }
+    }
+
+    // jars extracted from a mod stay as long as the mod does
+    let children: Vec<PathBuf> = state
+        .entries(game_dir)
+        .filter(|(_, m)| m.parent.as_ref().is_some_and(|p| managed.contains(&normalize(game_dir.join(p).as_os_str()))))
+        .map(|(path, _)| path)
+        .collect();
+    for path in children {
+        managed.insert(normalize(path.as_os_str()));
+    }
+
+    let mut unused = Vec::new();
+    for (dir, extensions) in managed_dirs.iter() {
+        unused.extend(unused_files(dir, extensions, &managed, symlinks)?);
+    }
+    let installed = state.entries(game_dir).filter(|(_, m)| m.source != overrides::SOURCE);
+    for (path, recorded) in installed {
+        let present = (path.is_file() || !recorded.files.is_empty())
+            && (symlinks == Symlinks::Follow || !links::is_link(&path));
+        if !managed.contains(&normalize(path.as_os_str())) && !unused.contains(&path) && present {
+            trace!("{}: remove, recorded as installed but no entry installs it", path.display());
+            unused.push(path);
+        }
+    }
+    for path in unused {
+        actions.push(Action::Remove { path });
+    }
+
+    Ok(Plan { actions })
+}
+
+/// Where an entry is downloaded from, by what it names alone.
+pub fn entry_source(m: &Mod) -> Option<Source> {
+    match (&m.maven, m.local_path(), m.project_id()) {
+        (Some(coordinate), _, _) => Some(Source::Maven(coordinate.clone())),
+        (None, Some(path), _) => Some(Source::Local { path }),
+        (None, None, Some(project_id)) => Some(Source::Curseforge {
+            project_id: project_id.to_string(),
+        }),
+        (None, None, None) => m.modrinth.clone().map(Source::Modrinth),
+    }
+}
+
+/// Files the plan removes that are another copy of a mod an installed entry
+/// provides, itself or bundled inside it, told apart by the mod ids of the
+/// jars. Maps each to the file name of the entry.
+pub fn duplicates(plan: &Plan, mods: &[Mod], game_dir: &Path, mods_dir: &Path) -> HashMap<PathBuf, String> {
+    let jar = ["jar".to_string()];
+    let is_jar = |path: &Path| path.is_file() && has_extension(path, &jar);
+    let removed: Vec<&PathBuf> = plan
+        .actions
+        .iter()
+        .filter_map(|a| match a {
+            Action::Remove { path } if is_jar(path) => Some(path),
+            _ => None,
+        })
+        .collect();
+    let mut duplicates = HashMap::new();
+    if removed.is_empty() {
+        return duplicates;
+    }
+    let mut installed: HashMap<String, &str> = HashMap::new();
+    for m in mods.iter() {
+        let Some(path) = m.install_path(game_dir, mods_dir).ok().filter(|p| is_jar(p)) else {
+            continue;
+        };
+        let ids = jar::mod_ids(&path).unwrap_or_default().into_iter().chain(jar::bundled_ids(&path).unwrap_or_default());
+        for id in ids {
+            installed.entry(id).or_insert(m.filename.as_str());
+        }
+    }
+    for path in removed {
+        let ids = jar::mod_ids(path).unwrap_or_default();
+        if let Some(filename) = ids.iter().find_map(|id| installed.get(id)) {
+            duplicates.insert(path.clone(), filename.to_string());
+        }
+    }
+    duplicates
+}
+
+fn unused_files(dir: &Path, extensions: &[String], managed: &HashSet<String>, symlinks: Symlinks) -> Result<Vec<PathBuf>> {
+    let mut unused = Vec::new();
+    let follow = symlinks == Symlinks::Follow;
+    if !dir.exists() || (!follow && links::is_link(dir)) {
+        return Ok(unused);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if !path.is_file() || !has_extension(&path, extensions) || (!follow && links::is_link(&path)) {
+            continue;
+        }
+
+        if !managed.contains(&normalize(path.as_os_str())) {
+            trace!("{}: remove, no entry references it", path.display());
+            unused.push(path);
+        }
+    }
+
+    unused.sort();
+    Ok(unused)
+}
+
+/// Whether the file has one of `extensions`, compared case insensitively.
+pub fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy())
+        .is_some_and(|e| extensions.iter().any(|wanted| wanted.eq_ignore_ascii_case(&e)))
+}
+
+/// Windows and macOS file systems ignore case, `Mod.jar` and `mod.jar` are
+/// the same file there.
+const CASE_INSENSITIVE: bool = cfg!(any(windows, target_os = "macos"));
+
+/// Form of a path or file name to compare by, which also works for names
+/// that aren't valid UTF-8. Case is folded where the file system ignores it.
+pub fn normalize(name: &OsStr) -> String {
+    let name = name.to_string_lossy();
+    match CASE_INSENSITIVE {
+        true => name.to_lowercase(),
+        false => name.into_owned(),
+    }
+}
+
+/// Whether the recorded file is the one `m` names: of its project, its
+/// file name and the digest it pins, as far as they were recorded.
+fn installed_for(managed: &state::Managed, m: &Mod) -> bool {
+    managed.project_id.as_deref().is_none_or(|id| Some(id) == m.project_id())
+        && managed.filename.as_deref().is_none_or(|filename| same_name(filename, &m.filename))
+        && m.sha256.as_deref().is_none_or(|sha256| sha256.eq_ignore_ascii_case(&managed.sha256))
+}
+
+/// Whether two file names refer to the same file on this platform.
+pub fn same_name(a: &str, b: &str) -> bool {
+    normalize(OsStr::new(a)) == normalize(OsStr::new(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_files_must_be_the_entry_s() {
+        let managed: state::Managed = serde_json::from_value(serde_json::json!({
+            "sha256": "abcd", "source": "curseforge", "project_id": "238222", "installed_at": "2026-01-01T00:00:00Z",
+            "pack_version": "1.0.0", "filename": "jei-1.jar"
+        }))
+        .unwrap();
+        let entry = |filename: &str, url: &str, sha256: Option<&str>| Mod {
+            filename: filename.to_string(),
+            url: Some(url.to_string()),
+            install_as: Some("jei.jar".to_string()),
+            sha256: sha256.map(str::to_string),
+            ..Mod::default()
+        };
+        let project = "https://www.curseforge.com/projects/238222";
+        assert!(installed_for(&managed, &entry("jei-1.jar", project, None)));
+        assert!(installed_for(&managed, &entry("jei-1.jar", project, Some("ABCD"))));
+        assert!(!installed_for(&managed, &entry("jei-2.jar", project, None)));
+        assert!(!installed_for(&managed, &entry("jei-1.jar", project, Some("ef01"))));
+        assert!(!installed_for(&managed, &entry("jei-1.jar", "https://www.curseforge.com/projects/1", None)));
+    }
+}
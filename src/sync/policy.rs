@@ -0,0 +1,522 @@
+use super::cache;
+use super::http::{self, RequestExt};
+use super::lock::{LockedFile, Lockfile};
+use super::log::{console, log_to_file};
+use super::manifest::Mod;
+use super::plan::{self, Action, Plan, Source};
+use super::quarantine::Rejected;
+use super::{curse_files, jar, modrinth, overrides, state};
+use crate::i18n::t;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const PROVIDERS: [&str; 4] = ["curseforge", "modrinth", "maven", "local"];
+
+/// Constraints an organization puts on what its instances may install,
+/// kept apart from the packs so one file, or url, can be handed to every
+/// managed instance, e.g.
+///
+/// ```toml
+/// allowed_providers = ["curseforge", "modrinth"]
+/// banned_mods = ["238222", "*optifine*"]
+/// banned_authors = ["someone"]
+/// max_file_age_days = 730
+/// require_hashes = true
+/// allow_scripts = false
+/// licenses = ["MIT", "Apache-2.0", "LGPL-3.0-only"]
+/// ```
+///
+/// Plans breaking it are refused before anything is downloaded. Entries
+/// already installed are held to it as well as those about to be, and the
+/// override files of the pack to the rules that concern files.
+#[derive(Deserialize)]
+pub struct Policy {
+    /// Where the policy was loaded from.
+    #[serde(skip)]
+    pub location: String,
+    /// Providers files may come from, `curseforge`, `modrinth`, `maven` and
+    /// `local`, any when left out.
+    #[serde(default)]
+    pub allowed_providers: Vec<String>,
+    /// Entries that may not be installed, by project id, slug, name or file
+    /// name, which may use `*` and `?`.
+    #[serde(default)]
+    pub banned_mods: Vec<String>,
+    /// Authors, or members of Modrinth teams, whose projects may not be
+    /// installed.
+    #[serde(default)]
+    pub banned_authors: Vec<String>,
+    /// Days after their release files may still be installed.
+    #[serde(default)]
+    pub max_file_age_days: Option<u64>,
+    /// Only install files, and overrides, whose digests the lockfile or the
+    /// modlist pins.
+    #[serde(default)]
+    pub require_hashes: bool,
+    /// Whether entries may run post-install scripts, true when left out.
+    #[serde(default = "default_true")]
+    pub allow_scripts: bool,
+    /// SPDX ids of the licenses files may have, any when left out. Modrinth
+    /// lists the license of its projects, files from elsewhere are checked
+    /// for the one their jar declares once downloaded.
+    #[serde(default)]
+    pub licenses: Vec<String>,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            location: String::new(),
+            allowed_providers: Vec::new(),
+            banned_mods: Vec::new(),
+            banned_authors: Vec::new(),
+            max_file_age_days: None,
+            require_hashes: false,
+            allow_scripts: true,
+            licenses: Vec::new(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// What of the instance a plan is checked along with.
+pub struct Pack<'a> {
+    pub mods: &'a [Mod],
+    /// Keys of the override files the pack ships.
+    pub overrides: &'a [String],
+    pub lock: &'a Lockfile,
+    pub game_dir: &'a Path,
+    pub mods_dir: &'a Path,
+}
+
+/// An entry of the plan, or an override, the policy doesn't allow.
+pub struct Violation {
+    pub filename: String,
+    pub reason: String,
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("https://") || location.starts_with("http://")
+}
+
+/// Loads the policy at `location`, an https url or a path relative to
+/// `dir`.
+pub fn load(location: &str, dir: &Path) -> Result<Policy> {
+    if location.starts_with("http://") {
+        return Err(anyhow!("policy {} must be fetched over https, anyone on the way could lift it", location));
+    }
+    let contents = match is_url(location) {
+        true => fetch(location)?,
+        false => {
+            let path = dir.join(location);
+            fs::read_to_string(&path).with_context(|| format!("failed to read policy {}", path.display()))?
+        }
+    };
+    let mut policy: Policy = toml::from_str(&contents).with_context(|| format!("failed to parse policy {}", location))?;
+    if let Some(provider) = policy.allowed_providers.iter().find(|p| !PROVIDERS.contains(&p.as_str())) {
+        return Err(anyhow!(
+            "unknown provider {} in policy {}, expected {}",
+            provider,
+            location,
+            PROVIDERS.join(", ")
+        ));
+    }
+    policy.location = location.to_string();
+    Ok(policy)
+}
+
+fn fetch(location: &str) -> Result<String> {
+    // the last copy fetched stands in while the host is unreachable, a
+    // policy that can't be read must not lift its constraints
+    let body = match http::api().get(location).send_captured().and_then(|r| r.error_for_status()) {
+        Ok(response) => {
+            let body = http::decoded(response)?;
+            cache::put(location, &body);
+            body
+        }
+        Err(e) => {
+            let body = cache::get(location, Duration::MAX).ok_or(e).with_context(|| format!("failed to fetch policy {}", location))?;
+            let _ = log_to_file(&format!("[WARN] failed to fetch {}, using the cached copy", location));
+            console!("[WARN] {}", t!("sync-parent-cached", location = location));
+            body
+        }
+    };
+    String::from_utf8(body).with_context(|| format!("{} is not valid UTF-8", location))
+}
+
+/// What a batch request found by id, or why it failed.
+type Batch<T> = std::result::Result<HashMap<String, T>, String>;
+
+/// What the policy looks up about the entries of a plan, in one request per
+/// provider and kind rather than one per entry, with what looks up the rest.
+struct Lookups<'a> {
+    /// Authors of the CurseForge projects the lockfile doesn't record.
+    authors: Batch<Vec<String>>,
+    /// Members of the teams of Modrinth projects, by id and slug.
+    members: Batch<Vec<String>>,
+    /// The CurseForge files the lockfile pins, by file id.
+    files: Batch<curse_files::ModFile>,
+    /// Release dates of Modrinth versions.
+    released: Batch<String>,
+    /// Licenses of Modrinth projects, by id and slug.
+    licenses: Batch<String>,
+    filter: &'a curse_files::Filter,
+    api_key: &'a str,
+}
+
+/// An entry the plan installs or verifies, where it comes from and what the
+/// lockfile records of it.
+type Entry<'a> = (&'a Mod, Option<&'a Source>, Option<&'a LockedFile>);
+
+/// `fetch` of `ids` without duplicates, no request when there are none.
+fn batch<I: Ord, T>(ids: BTreeSet<I>, fetch: impl FnOnce(&[I]) -> Result<Vec<T>>) -> std::result::Result<Vec<T>, String> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    fetch(&ids.into_iter().collect::<Vec<_>>()).map_err(|e| format!("{:#}", e))
+}
+
+/// What `batch` found of `id`.
+fn found<'a, T>(batch: &'a Batch<T>, id: &str, missing: impl FnOnce() -> String) -> Result<&'a T> {
+    match batch {
+        Ok(found) => found.get(id).ok_or_else(|| anyhow!(missing())),
+        Err(e) => Err(anyhow!("{}", e)),
+    }
+}
+
+/// The CurseForge project an entry comes from, files placed by hand being
+/// from CurseForge.
+fn curseforge<'a>(m: &'a Mod, source: Option<&'a Source>) -> Option<&'a str> {
+    match source {
+        Some(Source::Curseforge { project_id }) => Some(project_id.as_str()),
+        Some(_) => None,
+        None => m.project_id(),
+    }
+}
+
+/// The file of `project_id` the lockfile pins.
+fn locked_file(recorded: Option<&LockedFile>, project_id: &str) -> Option<u64> {
+    let (project, file) = recorded?.source.strip_prefix("curseforge:")?.split_once(':')?;
+    (project == project_id).then(|| file.parse().ok()).flatten()
+}
+
+impl Policy {
+    /// The entries `plan` installs, verifies or keeps, and the overrides of
+    /// `pack`, that the policy doesn't allow. Their authors, release dates
+    /// and Modrinth licenses are looked up when the policy constrains them,
+    /// entries whose lookup fails are refused.
+    pub fn check(&self, plan: &Plan, pack: &Pack, filter: &curse_files::Filter, api_key: &str) -> Vec<Violation> {
+        let mut entries = Vec::new();
+        let mut violations = Vec::new();
+        let recorded = |dest: &Path| pack.lock.files.get(&state::key(pack.game_dir, dest));
+        for action in plan.actions.iter() {
+            let (filename, source, dest) = match action {
+                Action::Install { source, filename, dest, .. } => (filename, Some(Cow::Borrowed(source)), Some(dest.clone())),
+                Action::Verify { filename, dest, .. } => (filename, None, Some(dest.clone())),
+                Action::Keep { filename } => {
+                    let Some(m) = pack.mods.iter().find(|m| &m.filename == filename) else {
+                        continue;
+                    };
+                    // files placed by hand come from wherever the player got them
+                    let source = plan::entry_source(m).filter(|_| !m.manual).map(Cow::Owned);
+                    (filename, source, m.install_path(pack.game_dir, pack.mods_dir).ok())
+                }
+                Action::Script { filename, .. } if !self.allow_scripts => {
+                    violations.push(Violation {
+                        filename: filename.clone(),
+                        reason: "it runs a post-install script, the policy allows none".to_string(),
+                    });
+                    continue;
+                }
+                _ => continue,
+            };
+            if let Some(m) = pack.mods.iter().find(|m| &m.filename == filename) {
+                entries.push((m, source, dest.as_deref().and_then(recorded)));
+            }
+        }
+        let borrowed: Vec<Entry> = entries.iter().map(|(m, source, recorded)| (*m, source.as_deref(), *recorded)).collect();
+        let lookups = self.look_up(&borrowed, pack.lock, filter, api_key);
+        for (m, source, recorded) in borrowed {
+            for reason in self.reasons(m, source, recorded, pack.lock, &lookups) {
+                violations.push(Violation { filename: m.filename.clone(), reason });
+            }
+        }
+        for key in pack.overrides.iter() {
+            let name = key.rsplit('/').next().unwrap_or(key).to_lowercase();
+            let banned = self.banned_mods.iter().find(|pattern| overrides::matches_any(&name, &[pattern.to_lowercase()]));
+            if let Some(pattern) = banned {
+                violations.push(Violation { filename: key.clone(), reason: format!("the policy bans {}", pattern) });
+            }
+            if self.require_hashes && !pack.lock.overrides.contains_key(key) {
+                violations.push(Violation {
+                    filename: key.clone(),
+                    reason: "the policy requires a digest of the override in the lockfile".to_string(),
+                });
+            }
+        }
+        violations
+    }
+
+    /// Looks up what the policy constrains of `entries` ahead of checking
+    /// them.
+    fn look_up<'a>(&self, entries: &[Entry], lock: &Lockfile, filter: &'a curse_files::Filter, api_key: &'a str) -> Lookups<'a> {
+        let mut projects = BTreeSet::new();
+        let mut files = BTreeSet::new();
+        let mut versions = BTreeSet::new();
+        let mut modrinth_projects = BTreeSet::new();
+        for &(m, source, recorded) in entries {
+            if let (false, Some(project_id)) = (self.banned_authors.is_empty(), curseforge(m, source)) {
+                if !lock.owners.contains_key(project_id) {
+                    projects.extend(project_id.parse::<u64>().ok());
+                }
+            }
+            if let (false, Some(Source::Modrinth(pin))) = (self.banned_authors.is_empty() && self.licenses.is_empty(), source) {
+                modrinth_projects.insert(pin.project_id.clone());
+            }
+            match source.filter(|_| self.max_file_age_days.is_some()) {
+                Some(Source::Curseforge { project_id }) => files.extend(locked_file(recorded, project_id)),
+                Some(Source::Modrinth(pin)) => {
+                    versions.insert(pin.version_id.clone());
+                }
+                _ => {}
+            }
+        }
+        let modrinth_projects = batch(modrinth_projects, modrinth::projects);
+        let teams = modrinth_projects.as_ref().map_or_else(
+            |e| Err(e.clone()),
+            |found| {
+                let ids = found.iter().filter(|_| !self.banned_authors.is_empty()).map(|p| p.team.clone()).collect();
+                batch(ids, modrinth::teams)
+            },
+        );
+        Lookups {
+            authors: batch(projects, |ids| curse_files::projects(ids, api_key))
+                .map(|found| found.into_iter().map(|p| (p.id.to_string(), p.authors.into_iter().map(|a| a.name).collect())).collect()),
+            files: batch(files, |ids| curse_files::files(ids, api_key)).map(|found| found.into_iter().map(|f| (f.id.to_string(), f)).collect()),
+            released: batch(versions, modrinth::versions)
+                .map(|found| found.into_iter().map(|v| (v.id, v.date_published)).collect()),
+            members: teams.map(|teams| {
+                let members: HashMap<String, Vec<String>> = teams
+                    .into_iter()
+                    .filter_map(|team| Some((team.first()?.team_id.clone(), team.into_iter().map(|m| m.user.username).collect())))
+                    .collect();
+                let projects = modrinth_projects.as_deref().unwrap_or_default();
+                projects
+                    .iter()
+                    .filter_map(|p| Some((p, members.get(&p.team)?)))
+                    .flat_map(|(p, members)| [(p.id.clone(), members.clone()), (p.slug.clone(), members.clone())])
+                    .collect()
+            }),
+            licenses: modrinth_projects.as_ref().map_err(Clone::clone).map(|found| {
+                found.iter().flat_map(|p| [(p.id.clone(), p.license.id.clone()), (p.slug.clone(), p.license.id.clone())]).collect()
+            }),
+            filter,
+            api_key,
+        }
+    }
+
+    fn reasons(&self, m: &Mod, source: Option<&Source>, recorded: Option<&LockedFile>, lock: &Lockfile, lookups: &Lookups) -> Vec<String> {
+        let mut reasons = Vec::new();
+        let curseforge = curseforge(m, source);
+        if let Some(source) = source.filter(|_| !self.allowed_providers.is_empty()) {
+            if !self.allowed_providers.iter().any(|p| p == source.provider()) {
+                reasons.push(format!("it comes from {}, the policy only allows {}", source.provider(), self.allowed_providers.join(", ")));
+            }
+        }
+
+        let project_id = match source {
+            Some(Source::Modrinth(pin)) => Some(pin.project_id.as_str()),
+            _ => curseforge,
+        };
+        let slug = curseforge.and_then(|id| lock.owners.get(id)).map(|o| o.slug.as_str());
+        let names = [project_id, slug, Some(m.name.as_str()), Some(m.filename.as_str())];
+        let banned = self
+            .banned_mods
+            .iter()
+            .find(|pattern| names.iter().flatten().any(|name| overrides::matches_any(&name.to_lowercase(), &[pattern.to_lowercase()])));
+        if let Some(pattern) = banned {
+            reasons.push(format!("the policy bans {}", pattern));
+        }
+
+        if let (false, Some(project_id)) = (self.banned_authors.is_empty(), curseforge) {
+            let authors = match lock.owners.get(project_id) {
+                Some(owners) => Ok(&owners.authors),
+                None => found(&lookups.authors, project_id, || format!("CurseForge doesn't know project {}", project_id)),
+            };
+            match authors {
+                Ok(authors) => {
+                    if let Some(author) = authors.iter().find(|a| self.banned_authors.iter().any(|b| b.eq_ignore_ascii_case(a))) {
+                        reasons.push(format!("it is by {}, whose projects the policy bans", author));
+                    }
+                }
+                Err(e) => reasons.push(format!("its authors could not be looked up: {:#}", e)),
+            }
+        }
+        if let (false, Some(Source::Modrinth(pin))) = (self.banned_authors.is_empty(), source) {
+            match found(&lookups.members, &pin.project_id, || format!("Modrinth doesn't know the team of project {}", pin.project_id)) {
+                Ok(members) => {
+                    if let Some(member) = members.iter().find(|a| self.banned_authors.iter().any(|b| b.eq_ignore_ascii_case(a))) {
+                        reasons.push(format!("it is by {}, whose projects the policy bans", member));
+                    }
+                }
+                Err(e) => reasons.push(format!("its authors could not be looked up: {:#}", e)),
+            }
+        }
+
+        let pinned = recorded.is_some_and(|r| !r.hashes.is_empty());
+        if self.require_hashes && !pinned && m.sha256.is_none() {
+            reasons.push("the policy requires a digest of it in the lockfile or the modlist".to_string());
+        }
+
+        if let (Some(days), Some(source)) = (self.max_file_age_days, source) {
+            let released = match source {
+                // files the lockfile doesn't pin, or pins to another name,
+                // are looked for by name among the files of their project
+                Source::Curseforge { project_id } => {
+                    let pinned = locked_file(recorded, project_id)
+                        .and_then(|id| lookups.files.as_ref().ok()?.get(&id.to_string()))
+                        .filter(|f| f.file_name == m.filename);
+                    match pinned {
+                        Some(file) => Ok(Some(file.file_date.clone())),
+                        None => curse_files::find_by_name(project_id, &m.filename, lookups.filter, lookups.api_key)
+                            .and_then(|f| f.ok_or_else(|| anyhow!("CurseForge has no file {}", m.filename)))
+                            .map(|f| Some(f.file_date)),
+                    }
+                }
+                Source::Modrinth(pin) => {
+                    found(&lookups.released, &pin.version_id, || format!("Modrinth doesn't know version {}", pin.version_id)).map(|d| Some(d.clone()))
+                }
+                // maven and local files carry no release date
+                Source::Maven(_) | Source::Local { .. } => Ok(None),
+            };
+            let released = released.and_then(|date| {
+                date.map(|d| DateTime::parse_from_rfc3339(&d).map(|d| d.with_timezone(&Utc)).with_context(|| format!("unexpected release date {}", d)))
+                    .transpose()
+            });
+            match released {
+                Ok(Some(released)) if u64::try_from((Utc::now() - released).num_days()).is_ok_and(|age| age > days) => reasons.push(format!(
+                    "it was released {}, the policy allows files up to {} days old",
+                    released.format("%Y-%m-%d"),
+                    days
+                )),
+                Ok(_) => {}
+                Err(e) => reasons.push(format!("its release date could not be looked up: {:#}", e)),
+            }
+        }
+
+        if let (false, Some(Source::Modrinth(pin))) = (self.licenses.is_empty(), source) {
+            match found(&lookups.licenses, &pin.project_id, || format!("Modrinth doesn't know project {}", pin.project_id)) {
+                Ok(license) if self.allows_license(license) => {}
+                Ok(license) => reasons.push(format!("it is licensed {}, the policy only allows {}", license, self.licenses.join(", "))),
+                Err(e) => reasons.push(format!("its license could not be looked up: {:#}", e)),
+            }
+        }
+        reasons
+    }
+
+    /// Refuses a downloaded file whose jar declares a license the policy
+    /// doesn't allow, or none, for providers that list no license ahead.
+    pub fn check_download(&self, path: &Path, source: &Source) -> Result<()> {
+        if self.licenses.is_empty() || matches!(source, Source::Modrinth(_)) {
+            return Ok(());
+        }
+        match jar::license(path).ok().flatten() {
+            Some(license) if self.allows_license(&license) => Ok(()),
+            Some(license) => Err(anyhow!(Rejected(format!(
+                "its license {} is not allowed by the policy {}",
+                license, self.location
+            )))),
+            None => Err(anyhow!(Rejected(format!(
+                "it declares no license, the policy {} only allows {}",
+                self.location,
+                self.licenses.join(", ")
+            )))),
+        }
+    }
+
+    /// Whether `license`, an SPDX id or expression, is allowed: one of its
+    /// `OR` alternatives has only allowed licenses.
+    fn allows_license(&self, license: &str) -> bool {
+        license.split(" OR ").any(|alternative| {
+            alternative
+                .split(" AND ")
+                .map(|l| l.trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace()))
+                .all(|l| self.licenses.iter().any(|allowed| allowed.eq_ignore_ascii_case(l)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(filename: &str, name: &str) -> Mod {
+        Mod { filename: filename.to_string(), name: name.to_string(), ..Mod::default() }
+    }
+
+    fn reasons(policy: &Policy, plan: &Plan, mods: &[Mod], overrides: &[String]) -> Vec<(String, String)> {
+        let lock = Lockfile::default();
+        let pack = Pack { mods, overrides, lock: &lock, game_dir: Path::new("/game"), mods_dir: Path::new("/game/mods") };
+        let filter = curse_files::Filter::default();
+        policy.check(plan, &pack, &filter, "").into_iter().map(|v| (v.filename, v.reason)).collect()
+    }
+
+    #[test]
+    fn kept_entries_scripts_and_overrides_are_checked() {
+        let policy = Policy {
+            banned_mods: vec!["*optifine*".to_string()],
+            require_hashes: true,
+            allow_scripts: false,
+            ..Policy::default()
+        };
+        let mods = [entry("OptiFine_1.20.1.jar", "OptiFine"), entry("jei.jar", "JEI")];
+        let plan = Plan {
+            actions: vec![
+                Action::Keep { filename: "OptiFine_1.20.1.jar".to_string() },
+                Action::Script {
+                    filename: "jei.jar".to_string(),
+                    dest: "/game/mods/jei.jar".into(),
+                    command: vec!["true".to_string()],
+                    outputs: Vec::new(),
+                },
+            ],
+        };
+        let found = reasons(&policy, &plan, &mods, &["mods/optifine-extra.jar".to_string()]);
+        let of = |filename: &str| found.iter().filter(|(f, _)| f == filename).map(|(_, r)| r.as_str()).collect::<Vec<_>>();
+        assert!(of("OptiFine_1.20.1.jar").iter().any(|r| r.contains("bans")));
+        assert!(of("jei.jar").iter().any(|r| r.contains("script")));
+        let override_reasons = of("mods/optifine-extra.jar");
+        assert!(override_reasons.iter().any(|r| r.contains("bans")) && override_reasons.iter().any(|r| r.contains("digest")));
+    }
+
+    #[test]
+    fn policies_are_fetched_over_https_only() {
+        let loaded = load("http://example.com/policy.toml", Path::new("."));
+        assert!(format!("{:#}", loaded.err().unwrap()).contains("https"));
+    }
+
+    #[test]
+    fn ages_are_not_negative() {
+        assert!(toml::from_str::<Policy>("max_file_age_days = -1").is_err());
+        let policy: Policy = toml::from_str("max_file_age_days = 30").unwrap();
+        assert_eq!(policy.max_file_age_days, Some(30));
+        assert!(policy.allow_scripts);
+    }
+
+    #[test]
+    fn licenses_are_allowed_by_alternative() {
+        let policy = Policy { licenses: vec!["MIT".to_string(), "Apache-2.0".to_string()], ..Policy::default() };
+        assert!(policy.allows_license("MIT"));
+        assert!(policy.allows_license("GPL-3.0-only OR (MIT AND Apache-2.0)"));
+        assert!(!policy.allows_license("MIT AND GPL-3.0-only"));
+    }
+}
@@ -0,0 +1,105 @@
+use super::hash::{self, Multihash};
+use super::log::STATE_DIR;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const INDEX_FILE: &str = "index.json";
+
+/// A file downloaded ahead of the sync that installs it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Prefetched {
+    /// Name of the file in the store.
+    pub file: String,
+    pub project_id: Option<String>,
+    pub file_id: Option<u64>,
+    pub bytes: u64,
+    /// Size the provider reported, when it reports one.
+    pub size: Option<u64>,
+    /// Digests the provider published for the file.
+    pub hashes: Multihash,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Files of pending updates downloaded by `prefetch`, kept under
+/// `.modpack-sync/prefetch` until a sync installs them.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Store {
+    #[serde(skip)]
+    dir: PathBuf,
+    /// The files by the download they are for, see `Download::key`.
+    pub files: BTreeMap<String, Prefetched>,
+}
+
+pub fn dir(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("prefetch")
+}
+
+impl Store {
+    pub fn load(base_dir: &Path) -> Result<Store> {
+        let dir = dir(base_dir);
+        let index = dir.join(INDEX_FILE);
+        let mut store: Store = match index.is_file() {
+            true => {
+                let contents = fs::read_to_string(&index)?;
+                serde_json::from_str(&contents).with_context(|| format!("failed to parse {}", index.display()))?
+            }
+            false => Store::default(),
+        };
+        store.dir = dir;
+        Ok(store)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(INDEX_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Where the file for `key` is downloaded to, named after the key since
+    /// several sources may have files of the same name.
+    pub fn path(&self, key: &str, filename: &str) -> PathBuf {
+        let id = hash::Algorithm::Sha256.bytes(key.as_bytes());
+        self.dir.join(format!("{}-{}", &id[..16], filename))
+    }
+
+    pub fn insert(&mut self, key: String, prefetched: Prefetched) {
+        self.files.insert(key, prefetched);
+    }
+
+    /// Moves the file prefetched for `key` to `dest`, handing back what is
+    /// known about it. Files that went missing from the store are dropped.
+    pub fn take(&mut self, key: &str, dest: &Path) -> Option<Prefetched> {
+        let prefetched = self.files.remove(key)?;
+        let from = self.dir.join(&prefetched.file);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).ok()?;
+        }
+        // rename fails across file systems, a game directory may be elsewhere
+        fs::rename(&from, dest)
+            .or_else(|_| fs::copy(&from, dest).and_then(|_| fs::remove_file(&from)))
+            .ok()?;
+        Some(prefetched)
+    }
+
+    /// Drops the files of downloads not in `keys`, updates that were
+    /// superseded before they were applied.
+    pub fn retain(&mut self, keys: &HashSet<String>) {
+        let dir = &self.dir;
+        self.files.retain(|key, prefetched| {
+            let keep = keys.contains(key);
+            if !keep {
+                let _ = fs::remove_file(dir.join(&prefetched.file));
+            }
+            keep
+        });
+    }
+
+    /// Bytes the store holds.
+    pub fn size(&self) -> u64 {
+        self.files.values().map(|p| p.bytes).sum()
+    }
+}
@@ -0,0 +1,98 @@
+use super::audit;
+use super::log::STATE_DIR;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A downloaded file failed a check, it was left where it was saved for the
+/// sync to quarantine.
+#[derive(Debug)]
+pub struct Rejected(pub String);
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// Why and when a file was quarantined, kept next to it as `<file>.json`.
+#[derive(Serialize, Deserialize)]
+pub struct Sidecar {
+    pub reason: String,
+    pub quarantined_at: DateTime<Utc>,
+    /// Where the file was downloaded or copied from.
+    pub source: String,
+    /// Where it was saved, relative to the instance.
+    pub original: String,
+}
+
+pub fn dir(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("quarantine")
+}
+
+pub fn sidecar_path(file: &Path) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_os_string();
+    name.push(".json");
+    file.with_file_name(name)
+}
+
+/// Moves a rejected file, the download of the one at `dest`, into
+/// quarantine, returning where it went.
+pub fn put(base_dir: &Path, file: &Path, dest: &Path, reason: &str, source: &str) -> Result<PathBuf> {
+    let name = dest.file_name().ok_or_else(|| anyhow!("{} has no file name", dest.display()))?;
+    let now = Utc::now();
+    let dir = dir(base_dir);
+    fs::create_dir_all(&dir)?;
+    // the same file may be rejected again by later syncs
+    let to = dir.join(format!("{}-{}", now.format("%Y%m%d-%H%M%S"), name.to_string_lossy()));
+    let before = audit::hash(file);
+    // rename fails across file systems, a game directory may be elsewhere
+    fs::rename(file, &to)
+        .or_else(|_| fs::copy(file, &to).and_then(|_| fs::remove_file(file)))
+        .with_context(|| format!("failed to move {} to {}", file.display(), to.display()))?;
+    audit::record(base_dir, audit::Action::Quarantined, dest, before.as_deref(), None);
+    let sidecar = Sidecar {
+        reason: reason.to_string(),
+        quarantined_at: now,
+        source: source.to_string(),
+        original: dest.strip_prefix(base_dir).unwrap_or(dest).to_string_lossy().to_string(),
+    };
+    fs::write(sidecar_path(&to), serde_json::to_string_pretty(&sidecar)?)?;
+    Ok(to)
+}
+
+/// The quarantined files with their sidecars, oldest first. Files without a
+/// readable sidecar are listed with `None`.
+pub fn list(base_dir: &Path) -> Result<Vec<(PathBuf, Option<Sidecar>)>> {
+    let dir = dir(base_dir);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().is_some_and(|e| e == "json") {
+            continue;
+        }
+        let sidecar = fs::read_to_string(sidecar_path(&path))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok());
+        files.push((path, sidecar));
+    }
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(files)
+}
+
+/// Deletes every quarantined file with its sidecar, returning how many.
+pub fn clear(base_dir: &Path) -> Result<usize> {
+    let files = list(base_dir)?;
+    for (path, _) in files.iter() {
+        fs::remove_file(path).with_context(|| format!("failed to delete {}", path.display()))?;
+        let _ = fs::remove_file(sidecar_path(path));
+    }
+    Ok(files.len())
+}
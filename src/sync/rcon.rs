@@ -0,0 +1,122 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+
+const AUTH: i32 = 3;
+const COMMAND: i32 = 2;
+/// Largest packet the server sends, payload included.
+const MAX_PACKET: usize = 4096 + 10;
+
+/// A value of the server's `server.properties`.
+pub fn server_property(game_dir: &Path, key: &str) -> Option<String> {
+    let contents = fs::read_to_string(game_dir.join("server.properties")).ok()?;
+    contents
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .filter_map(|l| l.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim().to_string())
+}
+
+/// RCON address of the server in `game_dir`, by default its local port
+/// from `server.properties`.
+pub fn address(game_dir: &Path, address: Option<&str>) -> String {
+    address.map(str::to_string).unwrap_or_else(|| {
+        let port = server_property(game_dir, "rcon.port").unwrap_or_else(|| "25575".to_string());
+        format!("127.0.0.1:{}", port)
+    })
+}
+
+/// Connects to the server in `game_dir`, the address and password coming
+/// from `server.properties` where they are left out.
+pub fn connect_server(game_dir: &Path, address: Option<&str>, password: Option<&str>) -> Result<Rcon> {
+    let password = password
+        .map(str::to_string)
+        .or_else(|| server_property(game_dir, "rcon.password"))
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| anyhow!("no rcon password, set rcon.password in server.properties or password in modpack-sync.toml"))?;
+    Rcon::connect(&self::address(game_dir, address), &password)
+}
+
+/// A connection to a server's RCON port, the Source RCON protocol Minecraft
+/// speaks when `enable-rcon` is set.
+pub struct Rcon {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl Rcon {
+    pub fn connect(address: &str, password: &str) -> Result<Rcon> {
+        let addr = address
+            .to_socket_addrs()
+            .with_context(|| format!("invalid rcon address {}", address))?
+            .next()
+            .ok_or_else(|| anyhow!("rcon address {} does not resolve", address))?;
+        let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(10))
+            .with_context(|| format!("failed to connect to rcon at {}", address))?;
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+        let mut rcon = Rcon { stream, next_id: 1 };
+
+        let id = rcon.send(AUTH, password)?;
+        let (reply, _) = rcon.receive()?;
+        if reply == -1 || reply != id {
+            return Err(anyhow!("the server at {} rejected the rcon password", address));
+        }
+        Ok(rcon)
+    }
+
+    /// Runs a console command, returning what the server answered.
+    pub fn command(&mut self, command: &str) -> Result<String> {
+        let id = self.send(COMMAND, command)?;
+        let (reply, body) = self.receive()?;
+        if reply != id {
+            return Err(anyhow!("unexpected rcon reply to {}", command));
+        }
+        Ok(body)
+    }
+
+    /// Number of players online according to `list`, which answers with
+    /// `There are 2 of a max of 20 players online: ...`, or `2/20` before
+    /// 1.13.
+    pub fn players(&mut self) -> Result<usize> {
+        let reply = self.command("list")?;
+        reply
+            .split_whitespace()
+            .skip_while(|w| *w != "are")
+            .nth(1)
+            .and_then(|count| count.split('/').next())
+            .and_then(|count| count.parse().ok())
+            .ok_or_else(|| anyhow!("could not read the player count from `{}`", reply.trim()))
+    }
+
+    fn send(&mut self, kind: i32, body: &str) -> Result<i32> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut packet = Vec::with_capacity(body.len() + 14);
+        packet.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        self.stream.write_all(&packet).context("failed to send an rcon packet")?;
+        Ok(id)
+    }
+
+    /// Reads one packet as (request id, body).
+    fn receive(&mut self) -> Result<(i32, String)> {
+        let mut length = [0; 4];
+        self.stream.read_exact(&mut length).context("the server closed the rcon connection")?;
+        let length = i32::from_le_bytes(length) as usize;
+        if !(10..=MAX_PACKET).contains(&length) {
+            return Err(anyhow!("invalid rcon packet of {} bytes", length));
+        }
+        let mut packet = vec![0; length];
+        self.stream.read_exact(&mut packet)?;
+        let id = i32::from_le_bytes([packet[0], packet[1], packet[2], packet[3]]);
+        let body = String::from_utf8_lossy(&packet[8..length - 2]).to_string();
+        Ok((id, body))
+    }
+}
@@ -0,0 +1,70 @@
+use super::hash::Algorithm;
+use super::log_to_file;
+use super::{curse_files, jar, modrinth};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Licenses that keep every right with the author, as jars and Modrinth
+/// spell them.
+const RESERVED: [&str; 5] = ["all rights reserved", "all-rights-reserved", "arr", "proprietary", "no redistribution"];
+
+/// Whether a jar may be pushed to a mirror of the pack.
+pub enum Verdict {
+    /// Its license allows it.
+    Allowed { license: String },
+    /// The author forbids it.
+    Forbidden { reason: String },
+    /// Nothing says either way, or only free text a person has to read.
+    Unknown { license: Option<String> },
+}
+
+fn classify(license: &str) -> Verdict {
+    let lower = license.to_lowercase();
+    if RESERVED.iter().any(|r| lower == *r || (r.len() > 3 && lower.contains(r))) {
+        return Verdict::Forbidden { reason: format!("its license is {}", license) };
+    }
+    match jar::is_spdx_expression(license) && !lower.contains("licenseref-") {
+        true => Verdict::Allowed { license: license.to_string() },
+        false => Verdict::Unknown { license: Some(license.to_string()) },
+    }
+}
+
+/// Checks what the providers say about redistributing the jar at `path`:
+/// the third party distribution flag of the CurseForge project it was
+/// uploaded to, which needs `api_key`, then the license Modrinth lists and
+/// finally the one the jar declares. Failed lookups are logged and skipped.
+pub fn check(path: &Path, api_key: Option<&str>) -> Result<Verdict> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    if let Some(api_key) = api_key {
+        let project = curse_files::project_of_fingerprint(curse_files::fingerprint(&bytes), api_key)
+            .and_then(|id| id.map(|id| curse_files::project(&id.to_string(), api_key)).transpose());
+        match project {
+            Ok(Some(project)) if project.allow_mod_distribution == Some(false) => {
+                return Ok(Verdict::Forbidden {
+                    reason: format!("{} ({}) disallows third party distribution on CurseForge", project.name, project.slug),
+                });
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let _ = log_to_file(&format!("[WARN]  could not look {} up on CurseForge: {:#}", path.display(), e));
+            }
+        }
+    }
+    let listed = match modrinth::license_of(&Algorithm::Sha512.bytes(&bytes)) {
+        // custom licenses only have a LicenseRef id, their name says more
+        Ok(license) => license.filter(|l| !l.id.is_empty()).map(|l| match l.id.starts_with("LicenseRef-") && !l.name.is_empty() {
+            true => format!("{} ({})", l.name, l.id),
+            false => l.id,
+        }),
+        Err(e) => {
+            let _ = log_to_file(&format!("[WARN]  could not look {} up on Modrinth: {:#}", path.display(), e));
+            None
+        }
+    };
+    let declared = jar::license(path).ok().flatten();
+    Ok(match listed.or(declared) {
+        Some(license) => classify(&license),
+        None => Verdict::Unknown { license: None },
+    })
+}
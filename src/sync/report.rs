@@ -0,0 +1,329 @@
+use super::events::{HostStats, Stats};
+use super::manifest::PackHeader;
+use super::warnings::Warning;
+use super::{curse_files, Failure, Installed, Run};
+use crate::i18n::t;
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Html,
+    Json,
+    /// Metrics in the Prometheus text format, for node_exporter's textfile
+    /// collector.
+    Prometheus,
+}
+
+/// A report requested with `--report <format>=<path>`, `-` standing for
+/// stdout.
+pub struct Target {
+    pub format: Format,
+    pub path: PathBuf,
+}
+
+impl Target {
+    pub fn parse(value: &str) -> Result<Target> {
+        let (format, path) = value
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected --report <format>=<path>, got {}", value))?;
+        let format = match format {
+            "html" => Format::Html,
+            "json" => Format::Json,
+            "prometheus" => Format::Prometheus,
+            other => return Err(anyhow!("unknown report format {}, expected html, json or prometheus", other)),
+        };
+        Ok(Target {
+            format,
+            path: PathBuf::from(path),
+        })
+    }
+
+    pub fn to_stdout(&self) -> bool {
+        self.path.as_os_str() == "-"
+    }
+}
+
+pub fn write(target: &Target, run: &Run, api_key: &str) -> Result<()> {
+    let contents = match target.format {
+        Format::Html => html(run, api_key),
+        Format::Json => json(run)?,
+        Format::Prometheus => prometheus(run),
+    };
+    match target.to_stdout() {
+        true => io::stdout().write_all(contents.as_bytes())?,
+        false => fs::write(&target.path, contents)?,
+    }
+    Ok(())
+}
+
+/// What the run did, for programs driving the sync.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    pack: Option<&'a PackHeader>,
+    installed: &'a [Installed],
+    removed: &'a [String],
+    failures: &'a [Failure],
+    warnings: &'a [Warning],
+    /// Warnings left out for their code.
+    suppressed: usize,
+    stats: &'a Stats,
+}
+
+fn json(run: &Run) -> Result<String> {
+    let report = JsonReport {
+        pack: run.pack.as_ref(),
+        installed: &run.installed,
+        removed: &run.removed,
+        failures: &run.failures,
+        warnings: &run.warnings,
+        suppressed: run.suppressed,
+        stats: &run.stats,
+    };
+    Ok(serde_json::to_string_pretty(&report)? + "\n")
+}
+
+type HostValue = fn(&HostStats) -> f64;
+
+/// The run's totals as gauges labelled with the pack, overwritten by every
+/// run like the textfile collector expects.
+fn prometheus(run: &Run) -> String {
+    let stats = &run.stats;
+    let pack = run.pack.as_ref().map_or("", |p| p.name.as_str());
+    let labels = format!("{{pack=\"{}\"}}", label(pack));
+    let metrics: [(&str, &str, f64); 12] = [
+        ("last_run_timestamp_seconds", "When the last sync finished.", Local::now().timestamp() as f64),
+        ("last_run_success", "Whether the last sync succeeded without failed files.", stats.ok as u8 as f64),
+        ("run_duration_seconds", "Duration of the last sync.", stats.seconds),
+        ("resolve_duration_seconds", "Time the last sync spent planning.", stats.resolve_seconds),
+        ("download_duration_seconds", "Time the last sync spent downloading.", stats.download_seconds),
+        ("downloaded_files", "Files the last sync downloaded.", stats.downloads as f64),
+        ("downloaded_bytes", "Bytes the last sync downloaded.", stats.bytes as f64),
+        ("download_failures", "Files the last sync failed to download.", stats.download_failures as f64),
+        ("removed_files", "Files the last sync removed.", stats.removed as f64),
+        ("cache_hits", "Hashes the last sync took from the hash cache.", stats.cache_hits as f64),
+        ("cache_misses", "Files the last sync had to hash.", stats.cache_misses as f64),
+        ("retries", "Requests the last sync tried again.", stats.retries as f64),
+    ];
+    let mut out = String::new();
+    for (name, help, value) in metrics {
+        let _ = writeln!(out, "# HELP modpack_sync_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE modpack_sync_{} gauge", name);
+        let _ = writeln!(out, "modpack_sync_{}{} {}", name, labels, value);
+    }
+    let hosts: [(&str, &str, HostValue); 4] = [
+        ("host_downloaded_bytes", "Bytes the last sync downloaded from the host.", |h| h.bytes as f64),
+        ("host_throughput_bytes_per_second", "Download speed of the host in the last sync.", HostStats::throughput),
+        ("host_download_failures", "Files the last sync failed to download from the host.", |h| h.failures as f64),
+        ("host_retries", "Requests to the host the last sync tried again.", |h| h.retries as f64),
+    ];
+    for (name, help, value) in hosts {
+        let _ = writeln!(out, "# HELP modpack_sync_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE modpack_sync_{} gauge", name);
+        for (host, stats) in stats.hosts.iter() {
+            let labels = format!("{{pack=\"{}\",host=\"{}\"}}", label(pack), label(host));
+            let _ = writeln!(out, "modpack_sync_{}{} {}", name, labels, value(stats));
+        }
+    }
+    out
+}
+
+/// Escapes a Prometheus label value.
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+pub const STYLE: &str = "body{font-family:sans-serif;max-width:60rem;margin:2rem auto;color:#222}\
+table{border-collapse:collapse;width:100%}th,td{text-align:left;padding:.4rem;border-bottom:1px solid #ddd}\
+th{background:#f4f4f4}.failed{color:#b00020}details{margin:.5rem 0}pre{white-space:pre-wrap;background:#f8f8f8;padding:.5rem}";
+
+/// Renders a standalone page summarizing the run: updated and new mods with
+/// changelogs for updates, removals, failures and the total download size.
+fn html(run: &Run, api_key: &str) -> String {
+    let mut out = String::new();
+    let title = match &run.pack {
+        Some(pack) => format!("{} {}", pack.name, pack.version),
+        None => "modpack".to_string(),
+    };
+    // copies of a file another entry downloaded cost nothing
+    let total: u64 = run.installed.iter().filter(|i| i.copy_of.is_none()).map(|i| i.bytes).sum();
+
+    let _ = write!(
+        out,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{t}</title><style>{s}</style></head><body>",
+        t = escape(&t!("report-title", pack = title)),
+        s = STYLE
+    );
+    let _ = write!(
+        out,
+        "<h1>{}</h1><p>{}</p>",
+        escape(&title),
+        escape(&t!(
+            "report-overview",
+            date = Local::now().format("%Y-%m-%d %H:%M"),
+            installed = run.installed.len(),
+            removed = run.removed.len(),
+            failed = run.failures.len(),
+            size = human_size(total)
+        ))
+    );
+
+    if !run.installed.is_empty() {
+        let _ = write!(
+            out,
+            "<h2>{}</h2><table><tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr>",
+            t!("report-installed"),
+            t!("report-file"),
+            t!("report-previous"),
+            t!("report-provider"),
+            t!("report-size")
+        );
+        for i in run.installed.iter() {
+            let _ = write!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&i.filename),
+                escape(&i.replaced.clone().unwrap_or_else(|| t!("report-new"))),
+                i.provider,
+                human_size(i.bytes)
+            );
+        }
+        out.push_str("</table>");
+    }
+
+    let updates: Vec<_> = run.installed.iter().filter(|i| i.replaced.is_some()).collect();
+    if !updates.is_empty() {
+        let _ = write!(out, "<h2>{}</h2>", t!("report-changelogs"));
+        for i in updates {
+            let changelog = match (&i.project_id, i.file_id) {
+                (Some(project_id), Some(file_id)) => curse_files::changelog(project_id, file_id, api_key).ok(),
+                _ => None,
+            }
+            .map(|c| strip_tags(&c))
+            .unwrap_or_else(|| t!("report-changelog-unavailable"));
+            let _ = write!(
+                out,
+                "<details><summary>{}</summary><pre>{}</pre></details>",
+                escape(&i.filename),
+                escape(changelog.trim())
+            );
+        }
+    }
+
+    if !run.removed.is_empty() {
+        let _ = write!(out, "<h2>{}</h2><ul>", t!("report-removed"));
+        for r in run.removed.iter() {
+            let _ = write!(out, "<li>{}</li>", escape(r));
+        }
+        out.push_str("</ul>");
+    }
+
+    let (manual, failed): (Vec<_>, Vec<_>) = run.failures.iter().partition(|f| f.kind.needs_download());
+    if !manual.is_empty() {
+        let _ = write!(
+            out,
+            "<h2>{}</h2><table><tr><th>{}</th><th>{}</th></tr>",
+            t!("report-manual"),
+            t!("report-file"),
+            t!("report-what-to-do")
+        );
+        for f in manual {
+            let _ = write!(out, "<tr><td>{}</td><td>{}</td></tr>", escape(&f.filename), escape(&f.error));
+        }
+        out.push_str("</table>");
+    }
+
+    if !failed.is_empty() {
+        let _ = write!(
+            out,
+            "<h2 class=\"failed\">{}</h2><table><tr><th>{}</th><th>{}</th></tr>",
+            t!("report-failures"),
+            t!("report-file"),
+            t!("report-error")
+        );
+        for f in failed {
+            let _ = write!(
+                out,
+                "<tr class=\"failed\"><td>{}</td><td>{}",
+                escape(&f.filename),
+                escape(&f.error)
+            );
+            if !f.alternatives.is_empty() {
+                let _ = write!(out, "<p>{}</p><ul>", t!("report-alternatives"));
+                for alternative in f.alternatives.iter() {
+                    let _ = write!(out, "<li>{}</li>", escape(alternative));
+                }
+                out.push_str("</ul>");
+            }
+            out.push_str("</td></tr>");
+        }
+        out.push_str("</table>");
+    }
+
+    if !run.warnings.is_empty() || run.suppressed > 0 {
+        let _ = write!(
+            out,
+            "<h2>{}</h2><table><tr><th>{}</th><th>{}</th></tr>",
+            t!("report-warnings"),
+            t!("report-code"),
+            t!("report-warning")
+        );
+        for w in run.warnings.iter() {
+            let _ = write!(out, "<tr><td>{}</td><td>{}</td></tr>", w.code, escape(&w.message));
+        }
+        out.push_str("</table>");
+        if run.suppressed > 0 {
+            let _ = write!(out, "<p>{}</p>", escape(&t!("report-suppressed", count = run.suppressed)));
+        }
+    }
+
+    out.push_str("</body></html>\n");
+    out
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Changelogs are author supplied HTML, only their text is kept so nothing
+/// foreign ends up executing in the report.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.replace("<br>", "\n").replace("</p>", "\n").replace("</li>", "\n").chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
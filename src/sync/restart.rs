@@ -0,0 +1,77 @@
+use super::log::{console, log_to_file};
+use super::rcon::{self, Rcon};
+use super::settings::RestartSettings;
+use crate::i18n::t;
+use anyhow::{anyhow, Context, Result};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a server gets to shut down after `stop` before the hook runs.
+const STOP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Connects to the server's RCON with the address and password of the
+/// settings.
+pub fn connect(game_dir: &Path, settings: &RestartSettings) -> Result<Rcon> {
+    rcon::connect_server(game_dir, settings.rcon.as_deref(), settings.password.as_deref())
+}
+
+/// Runs the restart sequence after an update: the configured commands over
+/// RCON, `wait <seconds>` pausing between them, then the restart hook once
+/// the server is down.
+pub fn run(game_dir: &Path, settings: &RestartSettings) -> Result<()> {
+    let mut rcon = connect(game_dir, settings)?;
+    let mut stopped = false;
+    for command in settings.commands.iter() {
+        if let Some(seconds) = command.strip_prefix("wait ") {
+            let seconds: u64 = seconds.trim().parse().with_context(|| format!("invalid restart step {}", command))?;
+            thread::sleep(Duration::from_secs(seconds));
+            continue;
+        }
+        let reply = rcon.command(command)?;
+        let _ = log_to_file(&format!("[INFO] rcon {}: {}", command, reply.trim()));
+        if command.trim() == "stop" {
+            stopped = true;
+            break;
+        }
+    }
+
+    let Some(hook) = &settings.hook else {
+        return Ok(());
+    };
+    if stopped {
+        wait_for_stop(game_dir, settings);
+    }
+    console!("[INFO] {}", t!("sync-restarting", hook = hook));
+    let status = shell(hook)
+        .status()
+        .with_context(|| format!("failed to run the restart hook {}", hook))?;
+    if !status.success() {
+        return Err(anyhow!("restart hook {} exited with {}", hook, status));
+    }
+    Ok(())
+}
+
+/// Waits until the RCON port stops accepting connections.
+fn wait_for_stop(game_dir: &Path, settings: &RestartSettings) {
+    let started = Instant::now();
+    let address = rcon::address(game_dir, settings.rcon.as_deref());
+    while started.elapsed() < STOP_TIMEOUT && TcpStream::connect(&address).is_ok() {
+        thread::sleep(Duration::from_secs(2));
+    }
+}
+
+/// Runs `command` through the platform's shell.
+pub fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.arg("/C").arg(command);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(command);
+        c
+    }
+}
@@ -0,0 +1,281 @@
+use super::hash;
+use super::http::{self, RequestExt};
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::blocking::Response;
+use reqwest::Method;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// A bucket and key prefix given as `s3://bucket/some/prefix/`.
+#[derive(Clone)]
+pub struct Location {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl Location {
+    pub fn parse(url: &str) -> Result<Location> {
+        let rest = url
+            .strip_prefix("s3://")
+            .ok_or_else(|| anyhow!("{} is not an s3:// url", url))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(anyhow!("{} is missing a bucket name", url));
+        }
+        let prefix = prefix.trim_matches('/');
+        Ok(Location {
+            bucket: bucket.to_string(),
+            prefix: if prefix.is_empty() { String::new() } else { format!("{}/", prefix) },
+        })
+    }
+
+    pub fn key(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+}
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+/// A minimal S3 client signing requests with SigV4. Endpoints other than
+/// AWS, e.g. MinIO or R2, are addressed path-style.
+pub struct Client {
+    http: reqwest::blocking::Client,
+    credentials: Credentials,
+    region: String,
+    endpoint: Option<String>,
+}
+
+fn aws_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".aws"))
+}
+
+/// Values of `[section]` in an AWS style ini file.
+fn ini_section(path: Option<PathBuf>, section: &str) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    let Some(contents) = path.and_then(|p| fs::read_to_string(p).ok()) else {
+        return values;
+    };
+    let mut inside = false;
+    for line in contents.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            inside = name.trim() == section;
+        } else if let (true, Some((key, value))) = (inside, line.split_once('=')) {
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    values
+}
+
+fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Text of every `<tag>` element in an S3 XML response.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    xml.split(&open)
+        .skip(1)
+        .filter_map(|part| part.split_once(&close).map(|(text, _)| text))
+        .collect()
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+impl Client {
+    /// Builds a client from the standard AWS configuration: the
+    /// `AWS_ACCESS_KEY_ID` family of variables, then the profile named by
+    /// `AWS_PROFILE` in `~/.aws/credentials` and `~/.aws/config`.
+    pub fn from_env() -> Result<Client> {
+        let profile = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
+        let credentials_file = env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+            .map(PathBuf::from)
+            .or_else(|| aws_dir().map(|d| d.join("credentials")));
+        let config_file = env::var_os("AWS_CONFIG_FILE")
+            .map(PathBuf::from)
+            .or_else(|| aws_dir().map(|d| d.join("config")));
+        let stored = ini_section(credentials_file, &profile);
+        let config_section = if profile == "default" { profile.clone() } else { format!("profile {}", profile) };
+        let config = ini_section(config_file, &config_section);
+
+        let lookup = |var: &str, key: &str| {
+            env::var(var)
+                .ok()
+                .filter(|v| !v.is_empty())
+                .or_else(|| stored.get(key).cloned())
+                .or_else(|| config.get(key).cloned())
+        };
+        let credentials = Credentials {
+            access_key: lookup("AWS_ACCESS_KEY_ID", "aws_access_key_id")
+                .ok_or_else(|| anyhow!("no AWS credentials found, set AWS_ACCESS_KEY_ID or configure ~/.aws/credentials"))?,
+            secret_key: lookup("AWS_SECRET_ACCESS_KEY", "aws_secret_access_key")
+                .ok_or_else(|| anyhow!("AWS credentials are missing the secret access key"))?,
+            session_token: lookup("AWS_SESSION_TOKEN", "aws_session_token"),
+        };
+        let region = env::var("AWS_REGION")
+            .ok()
+            .or_else(|| env::var("AWS_DEFAULT_REGION").ok())
+            .or_else(|| config.get("region").cloned())
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let endpoint = env::var("AWS_ENDPOINT_URL_S3")
+            .ok()
+            .or_else(|| env::var("AWS_ENDPOINT_URL").ok())
+            .or_else(|| config.get("endpoint_url").cloned())
+            .map(|e| e.trim_end_matches('/').to_string());
+
+        Ok(Client {
+            http: http::bind(reqwest::blocking::Client::builder()).build()?,
+            credentials,
+            region,
+            endpoint,
+        })
+    }
+
+    fn send(&self, method: Method, bucket: &str, key: &str, query: &[(&str, &str)], body: Vec<u8>) -> Result<Response> {
+        let (host, path) = match &self.endpoint {
+            Some(endpoint) => {
+                let host = endpoint.split_once("://").map_or(endpoint.as_str(), |(_, h)| h);
+                (host.to_string(), format!("/{}/{}", bucket, key))
+            }
+            None => (format!("{}.s3.{}.amazonaws.com", bucket, self.region), format!("/{}", key)),
+        };
+        let scheme = match &self.endpoint {
+            Some(endpoint) if endpoint.starts_with("http://") => "http",
+            _ => "https",
+        };
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hash::sha256_bytes(&body);
+
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, false), uri_encode(v, false)))
+            .collect();
+        query.sort();
+        let canonical_query = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&");
+
+        let mut headers = vec![
+            ("host".to_string(), host.clone()),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v.trim())).collect();
+        let signed_headers = headers.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join(";");
+
+        let canonical_uri = uri_encode(&path, true);
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hash::sha256_bytes(canonical_request.as_bytes())
+        );
+        let signing_key = ["s3", "aws4_request"].iter().fold(
+            hmac(
+                &hmac(format!("AWS4{}", self.credentials.secret_key).as_bytes(), &date),
+                &self.region,
+            ),
+            |key, part| hmac(&key, part),
+        );
+        let signature = hash::to_hex(&hmac(&signing_key, &string_to_sign));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.credentials.access_key, scope, signed_headers, signature
+        );
+
+        let mut url = format!("{}://{}{}", scheme, host, canonical_uri);
+        if !canonical_query.is_empty() {
+            url = format!("{}?{}", url, canonical_query);
+        }
+        let mut request = self.http.request(method, &url).header("Authorization", authorization).body(body);
+        for (name, value) in headers.iter().filter(|(k, _)| k != "host") {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        request.send_captured().with_context(|| format!("request to s3://{}/{} failed", bucket, key))
+    }
+
+    fn check(response: Response, what: &str) -> Result<Response> {
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let body = response.text().unwrap_or_default();
+        let message = elements(&body, "Message").first().map(|m| m.to_string()).unwrap_or_default();
+        Err(anyhow!("{} failed with {} {}", what, status, message))
+    }
+
+    /// The object's contents, `None` if it does not exist.
+    pub fn get(&self, bucket: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        let response = self.send(Method::GET, bucket, key, &[], Vec::new())?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = Client::check(response, &format!("downloading s3://{}/{}", bucket, key))?;
+        Ok(Some(response.bytes()?.to_vec()))
+    }
+
+    pub fn put(&self, bucket: &str, key: &str, body: Vec<u8>) -> Result<()> {
+        let response = self.send(Method::PUT, bucket, key, &[], body)?;
+        Client::check(response, &format!("uploading s3://{}/{}", bucket, key))?;
+        Ok(())
+    }
+
+    /// Keys of every object under `prefix`.
+    pub fn list(&self, bucket: &str, prefix: &str) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let response = self.send(Method::GET, bucket, "", &query, Vec::new())?;
+            let body = Client::check(response, &format!("listing s3://{}/{}", bucket, prefix))?.text()?;
+            keys.extend(elements(&body, "Key").into_iter().map(unescape));
+            token = elements(&body, "NextContinuationToken").first().map(|t| t.to_string());
+            if token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+}
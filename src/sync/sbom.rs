@@ -0,0 +1,219 @@
+use super::hash::{self, Algorithm};
+use super::lock::Lockfile;
+use super::manifest::Mod;
+use super::settings::InstanceSettings;
+use super::{jar, state};
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    CycloneDx,
+    Spdx,
+}
+
+/// What the inventory says about one entry of the effective modlist.
+struct Component {
+    name: String,
+    version: String,
+    /// Authors of the CurseForge project or group of the Maven artifact.
+    supplier: Option<String>,
+    license: Option<String>,
+    /// Where the file is downloaded from, `None` for files placed by hand.
+    location: Option<String>,
+    /// Digests known for the installed file, none when it isn't installed.
+    hashes: hash::Multihash,
+}
+
+/// Builds a software inventory of the pack in `format`, listing every entry
+/// of the effective modlist with its version, supplier, license and the
+/// digests of the installed file.
+pub fn build(base_dir: &Path, mods_file: &str, format: Format) -> Result<Value> {
+    let instance = InstanceSettings::load(base_dir)?;
+    let base = base_dir.to_string_lossy();
+    let game_dir = PathBuf::from(instance.game_dir(&base));
+    let mods_dir = PathBuf::from(instance.mods_dir(&base));
+    let effective = super::load_effective(base_dir, mods_file)?;
+    let lock = Lockfile::load(base_dir)?.unwrap_or_default();
+
+    let mut entries = Vec::new();
+    for m in effective.mods.iter() {
+        entries.push((m, m.install_path(&game_dir, &mods_dir)?));
+    }
+    let mut cache = hash::Cache::load(base_dir);
+    let installed: Vec<PathBuf> = entries.iter().filter(|(_, p)| p.is_file()).map(|(_, p)| p.clone()).collect();
+    let mut sha256 = cache.hash_all(&installed)?;
+    let _ = cache.save();
+
+    let components: Vec<Component> = entries
+        .into_iter()
+        .map(|(m, path)| {
+            let mut hashes = hash::Multihash::new();
+            if let Some(sha256) = sha256.remove(&path) {
+                // digests of another file once at the same place don't count
+                let locked = lock.files.get(&state::key(&game_dir, &path));
+                if let Some(locked) = locked.filter(|l| l.hashes.get(&Algorithm::Sha256) == Some(&sha256)) {
+                    hashes.extend(locked.hashes.clone());
+                }
+                hashes.insert(Algorithm::Sha256, sha256);
+            }
+            Component {
+                name: m.name.clone(),
+                version: m.version.clone(),
+                supplier: supplier(m, &lock),
+                license: jar::license(&path).ok().flatten(),
+                location: location(m),
+                hashes,
+            }
+        })
+        .collect();
+
+    let created = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let id = uuid(&format!("{}:{}:{}", effective.pack.name, effective.pack.version, created));
+    Ok(match format {
+        Format::CycloneDx => cyclonedx(&effective.pack.name, &effective.pack.version, &created, &id, &components),
+        Format::Spdx => spdx(&effective.pack.name, &effective.pack.version, &created, &id, &components),
+    })
+}
+
+fn supplier(m: &Mod, lock: &Lockfile) -> Option<String> {
+    if let Some(coordinate) = &m.maven {
+        return Some(coordinate.group.clone());
+    }
+    let owner = m.project_id().and_then(|id| lock.owners.get(id))?;
+    Some(owner.authors.join(", ")).filter(|a| !a.is_empty())
+}
+
+fn location(m: &Mod) -> Option<String> {
+    if let Some(c) = &m.maven {
+        return Some(format!("{}/{}/{}/{}", c.repo.trim_end_matches('/'), c.group.replace('.', "/"), c.artifact, c.version));
+    }
+    m.url.clone().filter(|u| !u.starts_with("file:"))
+}
+
+/// A random looking but reproducible version 4 UUID derived from `seed`.
+fn uuid(seed: &str) -> String {
+    let hex = hash::sha256_bytes(seed.as_bytes());
+    format!("{}-{}-4{}-a{}-{}", &hex[..8], &hex[8..12], &hex[13..16], &hex[17..20], &hex[20..32])
+}
+
+fn cyclonedx(pack: &str, version: &str, created: &str, id: &str, components: &[Component]) -> Value {
+    let components: Vec<Value> = components
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let mut component = json!({
+                "type": "library",
+                "bom-ref": format!("mod-{}", i + 1),
+                "name": c.name,
+                "version": c.version,
+            });
+            if let Some(supplier) = &c.supplier {
+                component["supplier"] = json!({ "name": supplier });
+            }
+            if !c.hashes.is_empty() {
+                let hashes: Vec<Value> = c
+                    .hashes
+                    .iter()
+                    .map(|(algorithm, digest)| {
+                        let alg = match algorithm {
+                            Algorithm::Sha1 => "SHA-1",
+                            Algorithm::Sha256 => "SHA-256",
+                            Algorithm::Sha512 => "SHA-512",
+                        };
+                        json!({ "alg": alg, "content": digest })
+                    })
+                    .collect();
+                component["hashes"] = Value::Array(hashes);
+            }
+            if let Some(license) = &c.license {
+                component["licenses"] = match jar::is_spdx_expression(license) {
+                    true => json!([{ "expression": license }]),
+                    false => json!([{ "license": { "name": license } }]),
+                };
+            }
+            if let Some(location) = &c.location {
+                component["externalReferences"] = json!([{ "type": "distribution", "url": location }]);
+            }
+            component
+        })
+        .collect();
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", id),
+        "version": 1,
+        "metadata": {
+            "timestamp": created,
+            "tools": {
+                "components": [{ "type": "application", "name": "modpack-sync", "version": env!("CARGO_PKG_VERSION") }],
+            },
+            "component": { "type": "application", "bom-ref": "pack", "name": pack, "version": version },
+        },
+        "components": components,
+    })
+}
+
+fn spdx(pack: &str, version: &str, created: &str, id: &str, components: &[Component]) -> Value {
+    let mut packages = Vec::new();
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": "SPDXRef-Pack",
+    })];
+    packages.push(json!({
+        "name": pack,
+        "SPDXID": "SPDXRef-Pack",
+        "versionInfo": version,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+    }));
+    for (i, c) in components.iter().enumerate() {
+        let spdx_id = format!("SPDXRef-Mod-{}", i + 1);
+        let mut package = json!({
+            "name": c.name,
+            "SPDXID": spdx_id,
+            "versionInfo": c.version,
+            "supplier": c.supplier.as_ref().map_or("NOASSERTION".to_string(), |s| format!("Organization: {}", s)),
+            "downloadLocation": c.location.as_deref().unwrap_or("NOASSERTION"),
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+        });
+        if let Some(license) = &c.license {
+            match jar::is_spdx_expression(license) {
+                true => package["licenseDeclared"] = Value::String(license.clone()),
+                false => package["licenseComments"] = Value::String(format!("declared as: {}", license)),
+            }
+        }
+        if !c.hashes.is_empty() {
+            let checksums: Vec<Value> = c
+                .hashes
+                .iter()
+                .map(|(algorithm, digest)| json!({ "algorithm": algorithm.name().to_uppercase(), "checksumValue": digest }))
+                .collect();
+            package["checksums"] = Value::Array(checksums);
+        }
+        packages.push(package);
+        relationships.push(json!({
+            "spdxElementId": "SPDXRef-Pack",
+            "relationshipType": "CONTAINS",
+            "relatedSpdxElement": spdx_id,
+        }));
+    }
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{} {}", pack, version),
+        "documentNamespace": format!("https://spdx.org/spdxdocs/{}-{}-{}", pack.replace(' ', "-"), version, id),
+        "creationInfo": {
+            "created": created,
+            "creators": [format!("Tool: modpack-sync-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
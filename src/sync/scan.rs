@@ -0,0 +1,20 @@
+use super::log::log_to_file;
+use super::quarantine::Rejected;
+use super::restart;
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+
+/// Runs the scan hook on a downloaded file, rejecting it when the hook
+/// exits non-zero or can't be run.
+pub fn check(hook: &str, file: &Path) -> Result<()> {
+    let status = restart::shell(hook)
+        .env("MODPACK_SYNC_FILE", file)
+        .status()
+        .with_context(|| format!("failed to run the scan hook {}", hook))
+        .map_err(|e| anyhow!(Rejected(format!("{:#}", e))))?;
+    if !status.success() {
+        return Err(anyhow!(Rejected(format!("the scan hook exited with {}", status))));
+    }
+    let _ = log_to_file(&format!("[INFO]  the scan hook passed {}", file.display()));
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use super::settings::DaemonSettings;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Datelike, Duration, DurationRound, FixedOffset, Local, Timelike, Utc};
+
+/// How far ahead the start of the next window is looked for, a little over
+/// a week so every weekly window is found.
+const LOOKAHEAD_MINUTES: i64 = 8 * 24 * 60;
+
+/// One cron field as the set of values it allows, `None` for `*`.
+struct Field(Option<Vec<u32>>);
+
+impl Field {
+    fn parse(text: &str, min: u32, max: u32) -> Result<Field> {
+        if text == "*" {
+            return Ok(Field(None));
+        }
+        let mut values = Vec::new();
+        for part in text.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => {
+                    let step = step.parse::<u32>().ok().filter(|s| *s > 0);
+                    (range, step.ok_or_else(|| anyhow!("invalid step in {}", part))?)
+                }
+                None => (part, 1),
+            };
+            let (from, to) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((from, to)) => (from.parse()?, to.parse()?),
+                    None if step > 1 => (range.parse()?, max),
+                    None => (range.parse()?, range.parse()?),
+                },
+            };
+            if from < min || to > max || from > to {
+                return Err(anyhow!("{} is outside {}-{}", part, min, max));
+            }
+            values.extend((from..=to).step_by(step as usize));
+        }
+        Ok(Field(Some(values)))
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.0.as_ref().is_none_or(|values| values.contains(&value))
+    }
+}
+
+/// A five field cron expression, `minute hour day-of-month month
+/// day-of-week`, read as the minutes it matches.
+struct Cron {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Cron> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(anyhow!("expected five fields, e.g. `* 3-5 * * *`"));
+        };
+        Ok(Cron {
+            minute: Field::parse(minute, 0, 59)?,
+            hour: Field::parse(hour, 0, 23)?,
+            day: Field::parse(day, 1, 31)?,
+            month: Field::parse(month, 1, 12)?,
+            // both 0 and 7 are sunday
+            weekday: Field::parse(weekday, 0, 7)?,
+        })
+    }
+
+    fn matches<T: Datelike + Timelike>(&self, time: &T) -> bool {
+        let weekday = time.weekday().num_days_from_sunday();
+        let weekday = self.weekday.matches(weekday) || (weekday == 0 && self.weekday.matches(7));
+        // like cron, a restricted day of month or day of week is enough
+        let day = match (&self.day.0, &self.weekday.0) {
+            (Some(_), Some(_)) => self.day.matches(time.day()) || weekday,
+            _ => self.day.matches(time.day()) && weekday,
+        };
+        self.minute.matches(time.minute()) && self.hour.matches(time.hour()) && self.month.matches(time.month()) && day
+    }
+}
+
+enum Zone {
+    Local,
+    Fixed(FixedOffset),
+}
+
+/// When a daemon may apply updates.
+pub struct Schedule {
+    windows: Vec<Cron>,
+    zone: Zone,
+}
+
+impl Schedule {
+    pub fn parse(settings: &DaemonSettings) -> Result<Schedule> {
+        let windows = settings
+            .windows
+            .iter()
+            .map(|w| Cron::parse(w).with_context(|| format!("invalid maintenance window {}", w)))
+            .collect::<Result<Vec<_>>>()?;
+        let zone = match settings.timezone.as_deref() {
+            None | Some("local") => Zone::Local,
+            Some("utc") | Some("UTC") => Zone::Fixed(FixedOffset::east_opt(0).expect("zero is a valid offset")),
+            Some(offset) => Zone::Fixed(
+                offset
+                    .parse::<FixedOffset>()
+                    .map_err(|_| anyhow!("invalid timezone {}, expected local, utc or an offset such as +02:00", offset))?,
+            ),
+        };
+        Ok(Schedule { windows, zone })
+    }
+
+    /// Whether `now` falls in one of the windows, always true without any.
+    pub fn allows(&self, now: DateTime<Utc>) -> bool {
+        if self.windows.is_empty() {
+            return true;
+        }
+        match self.zone {
+            Zone::Local => {
+                let now = now.with_timezone(&Local);
+                self.windows.iter().any(|w| w.matches(&now))
+            }
+            Zone::Fixed(offset) => {
+                let now = now.with_timezone(&offset);
+                self.windows.iter().any(|w| w.matches(&now))
+            }
+        }
+    }
+
+    /// When the next window opens after `now`, so a daemon checking less
+    /// often than windows last still gets to apply updates in them. `None`
+    /// without windows or when none opens within [`LOOKAHEAD_MINUTES`].
+    pub fn next_opening(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.windows.is_empty() {
+            return None;
+        }
+        let minute = now.duration_trunc(Duration::minutes(1)).ok()?;
+        let mut open = self.allows(minute);
+        for i in 1..=LOOKAHEAD_MINUTES {
+            let at = minute + Duration::minutes(i);
+            let allows = self.allows(at);
+            if allows && !open {
+                return Some(at);
+            }
+            open = allows;
+        }
+        None
+    }
+}
@@ -0,0 +1,65 @@
+use super::log::log_to_file;
+use anyhow::{anyhow, Context, Result};
+use std::env;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a post-install command may run before it is killed.
+const TIMEOUT: Duration = Duration::from_secs(300);
+/// Variables of the environment passed on to post-install commands, the
+/// rest of it, API keys and tokens among them, is kept from them.
+const KEPT_ENV: &[&str] = &["PATH", "HOME", "USERPROFILE", "SYSTEMROOT", "TEMP", "TMP", "TMPDIR", "LANG", "JAVA_HOME"];
+
+/// Runs the post-install `command` of the entry installed at `file`: in the
+/// game directory, without a shell, with nothing to read, only the
+/// variables of [`KEPT_ENV`] and `MODPACK_SYNC_FILE` naming the file. Its
+/// output goes to the log. Fails when it exits non-zero or runs too long.
+pub fn run(command: &[String], file: &Path, game_dir: &Path) -> Result<()> {
+    let (program, args) = command.split_first().ok_or_else(|| anyhow!("the post-install command is empty"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(game_dir)
+        .env_clear()
+        .envs(KEPT_ENV.iter().filter_map(|name| Some((name, env::var_os(name)?))))
+        .env("MODPACK_SYNC_FILE", file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {}", program))?;
+    // read as it comes, a full pipe would stall the command
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut text = String::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_string(&mut text);
+            }
+            text
+        })
+    };
+    let stdout = read(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = read(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("{} ran longer than {} seconds and was stopped", program, TIMEOUT.as_secs()));
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+    let output = [stdout, stderr].map(|reader| reader.join().unwrap_or_default());
+    for line in output.iter().flat_map(|text| text.lines()) {
+        let _ = log_to_file(&format!("[INFO]   {}: {}", program, line));
+    }
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
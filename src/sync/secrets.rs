@@ -0,0 +1,299 @@
+use super::settings::UserSettings;
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Prefix of values encrypted with the passphrase.
+const PASSPHRASE_PREFIX: &str = "enc:";
+/// Prefix of values encrypted to an age recipient.
+const AGE_PREFIX: &str = "age:";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const ITERATIONS: usize = 200_000;
+
+/// Decrypted secrets by the settings file they are in and where in it, with
+/// the value they were stored as.
+type Opened = HashMap<(String, String), (String, String)>;
+
+/// Secrets decrypted this run, so settings written back keep them encrypted.
+static OPENED: Mutex<Option<Opened>> = Mutex::new(None);
+
+/// The passphrase from `MODPACK_SYNC_PASSPHRASE`, or the first line of the
+/// file `MODPACK_SYNC_PASSPHRASE_FILE` names, e.g. a systemd credential.
+pub fn passphrase() -> Result<Option<String>> {
+    if let Some(passphrase) = env::var("MODPACK_SYNC_PASSPHRASE").ok().filter(|p| !p.is_empty()) {
+        return Ok(Some(passphrase));
+    }
+    let Some(file) = env::var_os("MODPACK_SYNC_PASSPHRASE_FILE") else {
+        return Ok(None);
+    };
+    let contents = fs::read_to_string(&file).with_context(|| format!("failed to read the passphrase from {}", PathBuf::from(&file).display()))?;
+    Ok(contents.lines().next().map(str::to_string).filter(|p| !p.is_empty()))
+}
+
+/// The age identity secrets are decrypted with, `MODPACK_SYNC_AGE_IDENTITY`
+/// or `age-identity.txt` in the user settings directory.
+fn identity() -> Result<PathBuf> {
+    match env::var_os("MODPACK_SYNC_AGE_IDENTITY") {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(UserSettings::dir()?.join("age-identity.txt")),
+    }
+}
+
+/// PBKDF2-HMAC-SHA256 of the passphrase, one block being all of the key.
+fn key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mac = Hmac::<Sha256>::new_from_slice(passphrase.as_bytes()).map_err(|e| anyhow!("{}", e))?;
+    let mut block = mac.clone();
+    block.update(salt);
+    block.update(&1u32.to_be_bytes());
+    let mut round: [u8; 32] = block.finalize().into_bytes().into();
+    let mut key = round;
+    for _ in 1..ITERATIONS {
+        let mut next = mac.clone();
+        next.update(&round);
+        round = next.finalize().into_bytes().into();
+        key.iter_mut().zip(round.iter()).for_each(|(k, r)| *k ^= r);
+    }
+    Ok(key)
+}
+
+#[cfg(feature = "openssl")]
+fn seal_gcm(key: &[u8; 32], nonce: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, [u8; TAG_LEN])> {
+    use openssl::symm::{self, Cipher};
+    let mut tag = [0; TAG_LEN];
+    let ciphertext = symm::encrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), &[], plaintext, &mut tag)?;
+    Ok((ciphertext, tag))
+}
+
+#[cfg(feature = "openssl")]
+fn open_gcm(key: &[u8; 32], nonce: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+    use openssl::symm::{self, Cipher};
+    symm::decrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), &[], ciphertext, tag).map_err(|_| anyhow!("is the passphrase right?"))
+}
+
+#[cfg(not(feature = "openssl"))]
+fn seal_gcm(_key: &[u8; 32], _nonce: &[u8], _plaintext: &[u8]) -> Result<(Vec<u8>, [u8; TAG_LEN])> {
+    Err(anyhow!("secrets encrypted with a passphrase need modpack-sync built with the openssl feature, use age instead"))
+}
+
+#[cfg(not(feature = "openssl"))]
+fn open_gcm(_key: &[u8; 32], _nonce: &[u8], _ciphertext: &[u8], _tag: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow!("secrets encrypted with a passphrase need modpack-sync built with the openssl feature, use age instead"))
+}
+
+/// `secret` encrypted with the passphrase, as `enc:` and the base64 of the
+/// salt, nonce, AES-256-GCM ciphertext and tag.
+pub fn encrypt(secret: &str, passphrase: &str) -> Result<String> {
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    getrandom::getrandom(&mut salt).map_err(|e| anyhow!("failed to make a salt: {}", e))?;
+    getrandom::getrandom(&mut nonce).map_err(|e| anyhow!("failed to make a nonce: {}", e))?;
+    let (ciphertext, tag) = seal_gcm(&key(passphrase, &salt)?, &nonce, secret.as_bytes())?;
+    let sealed = [&salt[..], &nonce[..], &ciphertext, &tag[..]].concat();
+    Ok(format!("{}{}", PASSPHRASE_PREFIX, STANDARD.encode(sealed)))
+}
+
+/// `secret` encrypted to the age `recipient` with the `age` command.
+pub fn encrypt_age(secret: &str, recipient: &str) -> Result<String> {
+    let sealed = age(&["--encrypt", "--recipient", recipient], secret.as_bytes())?;
+    Ok(format!("{}{}", AGE_PREFIX, STANDARD.encode(sealed)))
+}
+
+fn decrypt(value: &str) -> Result<String> {
+    if let Some(encoded) = value.strip_prefix(AGE_PREFIX) {
+        let identity = identity()?;
+        if !identity.is_file() {
+            return Err(anyhow!(
+                "an age encrypted secret needs the identity {}, or set MODPACK_SYNC_AGE_IDENTITY",
+                identity.display()
+            ));
+        }
+        let sealed = STANDARD.decode(encoded.trim()).context("an age encrypted secret is not valid base64")?;
+        let identity = identity.to_string_lossy().to_string();
+        let secret = age(&["--decrypt", "--identity", &identity], &sealed)?;
+        return String::from_utf8(secret).context("an age encrypted secret is not valid UTF-8");
+    }
+    let encoded = value.strip_prefix(PASSPHRASE_PREFIX).unwrap_or(value);
+    let passphrase = passphrase()?.ok_or_else(|| anyhow!("found an encrypted secret, set MODPACK_SYNC_PASSPHRASE or MODPACK_SYNC_PASSPHRASE_FILE to decrypt it"))?;
+    let sealed = STANDARD.decode(encoded.trim()).context("an encrypted secret is not valid base64")?;
+    if sealed.len() < SALT_LEN + NONCE_LEN + TAG_LEN {
+        return Err(anyhow!("an encrypted secret is truncated"));
+    }
+    let (salt, rest) = sealed.split_at(SALT_LEN);
+    let (nonce, rest) = rest.split_at(NONCE_LEN);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+    let secret = open_gcm(&key(&passphrase, salt)?, nonce, ciphertext, tag)
+        .context("failed to decrypt a secret")?;
+    String::from_utf8(secret).context("an encrypted secret is not valid UTF-8")
+}
+
+fn age(args: &[&str], input: &[u8]) -> Result<Vec<u8>> {
+    let mut child = Command::new("age")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run age, is it installed?")?;
+    child.stdin.take().expect("stdin is piped").write_all(input)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!("age: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(output.stdout)
+}
+
+fn is_sealed(value: &str) -> bool {
+    value.starts_with(PASSPHRASE_PREFIX) || value.starts_with(AGE_PREFIX)
+}
+
+/// Whether the setting at `path` holds a secret and may be stored
+/// encrypted: keys, passwords and tokens, and the urls and headers of
+/// notifications, which carry their credentials.
+fn is_secret(path: &str) -> bool {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    let key = key.split('[').next().unwrap_or(key);
+    SECRET_KEYS.contains(&key) || path.starts_with("notify.") && (key == "url" || path.contains(".headers."))
+}
+
+/// Keys of settings that hold secrets, wherever they are.
+const SECRET_KEYS: [&str; 6] = ["api_key", "password", "token", "secret", "peer_token", "fleet_token"];
+
+/// Decrypts the encrypted secrets of settings parsed from `source`, see
+/// [`is_secret`]. Other values are taken as they are written. A secret
+/// decrypted before this run is not decrypted again while it is stored the
+/// same.
+pub fn open(value: &mut toml::Value, source: &str) -> Result<()> {
+    open_at(value, source, "")
+}
+
+fn open_at(value: &mut toml::Value, source: &str, path: &str) -> Result<()> {
+    match value {
+        toml::Value::String(s) if is_sealed(s) && is_secret(path) => {
+            let key = (source.to_string(), path.to_string());
+            let cached = OPENED
+                .lock()
+                .expect("secrets lock poisoned")
+                .as_ref()
+                .and_then(|opened| opened.get(&key))
+                .filter(|(_, sealed)| sealed == s)
+                .map(|(secret, _)| secret.clone());
+            let secret = match cached {
+                Some(secret) => secret,
+                None => decrypt(s).with_context(|| format!("failed to read {} of {}", path, source))?,
+            };
+            let sealed = std::mem::replace(s, secret.clone());
+            OPENED
+                .lock()
+                .expect("secrets lock poisoned")
+                .get_or_insert_with(HashMap::new)
+                .insert(key, (secret, sealed));
+        }
+        toml::Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                open_at(value, source, &format!("{}[{}]", path, i))?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                open_at(value, source, &join(path, key))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    }
+}
+
+/// The secrets decrypted this run, e.g. to scrub them from logs.
+pub fn opened() -> Vec<String> {
+    let opened = OPENED.lock().expect("secrets lock poisoned");
+    opened.iter().flat_map(|o| o.values().map(|(secret, _)| secret.clone())).collect()
+}
+
+/// Puts back the stored form of the secrets `open` decrypted from `source`
+/// into settings about to be written to it, where they are still what was
+/// decrypted.
+pub fn seal(value: &mut toml::Value, source: &str) {
+    let opened = OPENED.lock().expect("secrets lock poisoned");
+    if let Some(opened) = opened.as_ref() {
+        seal_at(value, opened, source, "");
+    }
+}
+
+fn seal_at(value: &mut toml::Value, opened: &Opened, source: &str, path: &str) {
+    match value {
+        toml::Value::String(s) => {
+            let key = (source.to_string(), path.to_string());
+            if let Some((_, sealed)) = opened.get(&key).filter(|(secret, _)| secret == s) {
+                *s = sealed.clone();
+            }
+        }
+        toml::Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                seal_at(value, opened, source, &format!("{}[{}]", path, i));
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                seal_at(value, opened, source, &join(path, key));
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_secrets_are_opened() {
+        assert!(is_secret("api_key"));
+        assert!(is_secret("panel.api_key"));
+        assert!(is_secret("daemon.tokens[0].token"));
+        assert!(is_secret("notify.email.password"));
+        assert!(is_secret("notify.http.headers.Authorization"));
+        assert!(is_secret("notify.discord.url"));
+        assert!(!is_secret("source"));
+        assert!(!is_secret("protected[0]"));
+        assert!(!is_secret("notify.email.from"));
+
+        // an encrypted looking value elsewhere is taken as it is, without
+        // any passphrase being asked for
+        let mut value: toml::Value = toml::from_str("source = \"age:not-a-secret\"").unwrap();
+        open(&mut value, "test.toml").unwrap();
+        assert_eq!(value["source"].as_str(), Some("age:not-a-secret"));
+    }
+
+    #[test]
+    fn secrets_opened_before_are_not_decrypted_again() {
+        let source = "cached.toml";
+        OPENED
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert((source.to_string(), "api_key".to_string()), ("plain".to_string(), "enc:sealed".to_string()));
+        let mut value: toml::Value = toml::from_str("api_key = \"enc:sealed\"").unwrap();
+        open(&mut value, source).unwrap();
+        assert_eq!(value["api_key"].as_str(), Some("plain"));
+        let mut value: toml::Value = toml::from_str("api_key = \"enc:changed\"").unwrap();
+        assert!(open(&mut value, source).is_err());
+    }
+}
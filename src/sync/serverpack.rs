@@ -0,0 +1,282 @@
+use super::extends;
+use super::http::{self, RequestExt};
+use super::jar;
+use super::launcher;
+use super::manifest::{self, Manifest, Mod, PackHeader};
+use super::overrides::{self, OVERRIDES_DIR};
+use super::settings::{InstanceSettings, Side};
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Overrides only the client reads, left out of server packs.
+pub const CLIENT_ONLY_OVERRIDES: &[&str] = &[
+    "options.txt",
+    "optionsof.txt",
+    "optionsshaders.txt",
+    "servers.dat",
+    "resourcepacks/*",
+    "shaderpacks/*",
+    "screenshots/*",
+];
+
+const EULA: &str = "# Set to true to agree to the Minecraft EULA, https://aka.ms/MinecraftEULA\neula=false\n";
+
+pub struct Exported {
+    pub mods: usize,
+    /// Entries left out because they only work on clients.
+    pub client_only: Vec<String>,
+    pub overrides: usize,
+    /// File name of the loader installer or launcher, when one was fetched.
+    pub installer: Option<String>,
+}
+
+/// Whether an entry belongs on a server: its condition allows servers and,
+/// when the client instance has the jar, its Fabric metadata doesn't mark it
+/// client only.
+fn server_side(m: &Mod, game_dir: &Path, mods_dir: &Path) -> bool {
+    if m.when.as_ref().is_some_and(|w| !w.allows_side(Side::Server)) {
+        return false;
+    }
+    let Ok(path) = m.install_path(game_dir, mods_dir) else {
+        return true;
+    };
+    match jar::fabric_meta(&path) {
+        Ok(Some(meta)) => meta.environment.as_deref() != Some("client"),
+        _ => true,
+    }
+}
+
+/// Writes a server instance of the pack into `out`: the server side of the
+/// manifest with its overrides, an EULA placeholder and start scripts.
+/// Mods are installed by syncing `out` afterwards, with `installer` the
+/// loader's server installer is downloaded too.
+pub fn export(base_dir: &Path, mods_file: &str, out: &Path, installer: bool) -> Result<Exported> {
+    let modlist = base_dir.join(mods_file);
+    let loaded = manifest::load(&modlist, base_dir)?;
+    let flattened = extends::flatten(loaded.manifest, &modlist.to_string_lossy(), base_dir)?.manifest;
+    let instance = InstanceSettings::load(base_dir)?;
+    let game_dir = instance.game_dir(&base_dir.to_string_lossy());
+    let mods_dir = instance.mods_dir(&base_dir.to_string_lossy());
+
+    let (mods, client_only): (Vec<Mod>, Vec<Mod>) = flattened
+        .mods
+        .into_iter()
+        .partition(|m| server_side(m, Path::new(&game_dir), Path::new(&mods_dir)));
+    fs::create_dir_all(out).with_context(|| format!("failed to create {}", out.display()))?;
+    let server = Manifest {
+        extends: None,
+        remove: Vec::new(),
+        mods,
+        ..flattened
+    };
+    manifest::save(&out.join(mods_file), &server)?;
+    InstanceSettings {
+        side: Side::Server,
+        ..InstanceSettings::default()
+    }
+    .save(out)?;
+
+    let root = base_dir.join(OVERRIDES_DIR);
+    let mut found = Vec::new();
+    if root.is_dir() {
+        overrides::files(&root, &mut found)?;
+    }
+    let patterns: Vec<String> = CLIENT_ONLY_OVERRIDES.iter().map(|p| p.to_string()).collect();
+    let mut copied = 0;
+    for file in found {
+        let key = overrides::key(&root, &file);
+        if overrides::matches_any(&key, &patterns) {
+            continue;
+        }
+        let dest = out.join(OVERRIDES_DIR).join(&key);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&file, &dest).with_context(|| format!("failed to copy {}", file.display()))?;
+        copied += 1;
+    }
+
+    if !out.join("eula.txt").exists() {
+        fs::write(out.join("eula.txt"), EULA)?;
+    }
+    let launch = match installer {
+        true => Some(fetch_installer(&server.pack, out)?),
+        false => None,
+    };
+    write_scripts(out, launch.as_ref())?;
+    match &server.pack.launch {
+        Some(settings) => launcher::write_server_args(out, settings)?,
+        None if !out.join(launcher::SERVER_JVM_ARGS).exists() => fs::write(out.join(launcher::SERVER_JVM_ARGS), "-Xmx4G\n")?,
+        None => {}
+    }
+
+    Ok(Exported {
+        mods: server.mods.len(),
+        client_only: client_only.into_iter().map(|m| m.filename).collect(),
+        overrides: copied,
+        installer: launch.map(|l| l.file),
+    })
+}
+
+/// How the server is started once its loader is in place.
+struct Launch {
+    file: String,
+    /// Whether `file` is an installer that sets up `run.sh`/`run.bat`.
+    installs: bool,
+}
+
+#[derive(Deserialize)]
+struct FabricLoader {
+    loader: FabricVersion,
+}
+
+#[derive(Deserialize)]
+struct FabricVersion {
+    version: String,
+    stable: bool,
+}
+
+fn get(client: &reqwest::blocking::Client, url: &str) -> Result<reqwest::blocking::Response> {
+    client
+        .get(url)
+        .send_captured()
+        .with_context(|| format!("request to {} failed", url))?
+        .error_for_status()
+        .with_context(|| format!("failed to download {}", url))
+}
+
+fn get_json<T: DeserializeOwned>(url: &str) -> Result<T> {
+    http::json(get(http::api(), url)?)
+}
+
+/// Downloads the server installer of the pack's loader, `loader` being
+/// `fabric`, `forge` or `neoforge` optionally followed by `-<version>`.
+fn fetch_installer(pack: &PackHeader, out: &Path) -> Result<Launch> {
+    let loader = pack
+        .loader
+        .as_deref()
+        .ok_or_else(|| anyhow!("the pack header has no loader to fetch the installer of"))?;
+    let minecraft = pack
+        .minecraft
+        .as_deref()
+        .ok_or_else(|| anyhow!("the pack header has no minecraft version to fetch the installer for"))?;
+    let (name, version) = match loader.split_once('-') {
+        Some((name, version)) => (name, Some(version.to_string())),
+        None => (loader, None),
+    };
+    let (url, launch) = match name.to_lowercase().as_str() {
+        "fabric" => {
+            let loader_version = match version {
+                Some(version) => version,
+                None => get_json::<Vec<FabricLoader>>(&format!("https://meta.fabricmc.net/v2/versions/loader/{}", minecraft))?
+                    .into_iter()
+                    .find(|l| l.loader.stable)
+                    .map(|l| l.loader.version)
+                    .ok_or_else(|| anyhow!("fabric has no stable loader for minecraft {}", minecraft))?,
+            };
+            let installer = get_json::<Vec<FabricVersion>>("https://meta.fabricmc.net/v2/versions/installer")?
+                .into_iter()
+                .find(|i| i.stable)
+                .map(|i| i.version)
+                .ok_or_else(|| anyhow!("fabric has no stable installer"))?;
+            let url = format!(
+                "https://meta.fabricmc.net/v2/versions/loader/{}/{}/{}/server/jar",
+                minecraft, loader_version, installer
+            );
+            (url, Launch { file: "fabric-server-launch.jar".to_string(), installs: false })
+        }
+        "forge" => {
+            #[derive(Deserialize)]
+            struct Promotions {
+                promos: HashMap<String, String>,
+            }
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    let promos = get_json::<Promotions>("https://files.minecraftforge.net/net/minecraftforge/forge/promotions_slim.json")?
+                        .promos;
+                    promos
+                        .get(&format!("{}-recommended", minecraft))
+                        .or_else(|| promos.get(&format!("{}-latest", minecraft)))
+                        .cloned()
+                        .ok_or_else(|| anyhow!("forge has no release for minecraft {}", minecraft))?
+                }
+            };
+            let full = format!("{}-{}", minecraft, version);
+            let url = format!(
+                "https://maven.minecraftforge.net/net/minecraftforge/forge/{}/forge-{}-installer.jar",
+                full, full
+            );
+            (url, Launch { file: "forge-installer.jar".to_string(), installs: true })
+        }
+        "neoforge" => {
+            #[derive(Deserialize)]
+            struct Versions {
+                versions: Vec<String>,
+            }
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    // neoforge versions drop the leading `1.` of the minecraft version
+                    let prefix = format!("{}.", minecraft.strip_prefix("1.").unwrap_or(minecraft));
+                    get_json::<Versions>("https://maven.neoforged.net/api/maven/versions/releases/net/neoforged/neoforge")?
+                        .versions
+                        .into_iter()
+                        .rfind(|v| v.starts_with(&prefix) && !v.contains("beta"))
+                        .ok_or_else(|| anyhow!("neoforge has no release for minecraft {}", minecraft))?
+                }
+            };
+            let url = format!(
+                "https://maven.neoforged.net/releases/net/neoforged/neoforge/{}/neoforge-{}-installer.jar",
+                version, version
+            );
+            (url, Launch { file: "neoforge-installer.jar".to_string(), installs: true })
+        }
+        other => return Err(anyhow!("don't know how to fetch a server installer for loader {}", other)),
+    };
+
+    let bytes = get(http::files(), &url)?.bytes()?;
+    fs::write(out.join(&launch.file), &bytes)?;
+    Ok(launch)
+}
+
+/// `start.sh` and `start.bat`, running the installer on first start when the
+/// loader needs one. JVM arguments are read from `user_jvm_args.txt`.
+fn write_scripts(out: &Path, launch: Option<&Launch>) -> Result<()> {
+    let (sh, bat) = match launch {
+        Some(Launch { file, installs: true }) => (
+            format!(
+                "#!/bin/sh\ncd \"$(dirname \"$0\")\"\n[ -f run.sh ] || java -jar {} --installServer || exit 1\nexec sh run.sh nogui\n",
+                file
+            ),
+            format!(
+                "@echo off\r\ncd /d \"%~dp0\"\r\nif not exist run.bat java -jar {} --installServer || exit /b 1\r\ncall run.bat nogui\r\n",
+                file
+            ),
+        ),
+        _ => {
+            let jar = launch.map_or("server.jar", |l| l.file.as_str());
+            (
+                format!("#!/bin/sh\ncd \"$(dirname \"$0\")\"\nexec java @{} -jar {} nogui\n", launcher::SERVER_JVM_ARGS, jar),
+                format!(
+                    "@echo off\r\ncd /d \"%~dp0\"\r\njava @{} -jar {} nogui\r\n",
+                    launcher::SERVER_JVM_ARGS,
+                    jar
+                ),
+            )
+        }
+    };
+    let script = out.join("start.sh");
+    fs::write(&script, sh)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+    }
+    fs::write(out.join("start.bat"), bat)?;
+    Ok(())
+}
@@ -0,0 +1,897 @@
+use super::launcher::{self, Launcher};
+use super::secrets;
+use super::smtp;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// File in the instance directory holding per-instance settings.
+pub const INSTANCE_FILE: &str = "modpack-sync.toml";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    #[default]
+    Client,
+    Server,
+}
+
+impl Side {
+    pub fn parse(value: &str) -> Result<Side> {
+        match value.to_lowercase().as_str() {
+            "client" => Ok(Side::Client),
+            "server" => Ok(Side::Server),
+            other => Err(anyhow!("unknown side {}, expected client or server", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Side::Client => "client",
+            Side::Server => "server",
+        }
+    }
+}
+
+/// How symbolic links in the mods and other managed directories are treated.
+/// Either way nothing is deleted through a link leading outside the instance.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Symlinks {
+    /// Linked files and directories are never cleaned up.
+    #[default]
+    Skip,
+    /// Links count as the files and directories they point to.
+    Follow,
+}
+
+/// Settings stored next to the modlist of an instance.
+#[derive(Serialize, Deserialize, Default)]
+pub struct InstanceSettings {
+    #[serde(default)]
+    pub side: Side,
+    /// Snapshot managed files before every sync that changes them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<BackupSettings>,
+    /// Archive the world of a dedicated server before its mods change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub world_backup: Option<WorldBackupSettings>,
+    /// Published pack, e.g. `s3://bucket/pack/`, to sync the modlist and
+    /// overrides from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Release channel of the source to follow, e.g. `beta`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// Profile of the vanilla launcher, by name, whose game directory the
+    /// pack is installed into when the instance is a launcher installation
+    /// with a `launcher_profiles.json`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Patterns of files the player owns, overrides only create them.
+    /// Defaults to `options.txt` and `servers.dat`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protected: Option<Vec<String>>,
+    /// Directories, relative to the game directory, the tool never looks
+    /// into or changes, e.g. `mods/local` for the player's own mods.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unmanaged: Option<Vec<String>>,
+    /// Write the pack's JVM settings to the launcher profile, or the JVM
+    /// arguments of a server, on every sync.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apply_launch: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlinks: Option<Symlinks>,
+    /// When deleting files needs confirmation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval: Option<ApprovalSettings>,
+    /// Mode and ownership given to installed files, for servers run by a
+    /// service user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<PermissionSettings>,
+    /// Default backend `modpack-sync publish` uploads the pack to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_to: Option<String>,
+    /// How `modpack-sync daemon` watches the source of the instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daemon: Option<DaemonSettings>,
+    /// How the daemon restarts a dedicated server after updating it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartSettings>,
+    /// The Pterodactyl or Pelican panel the server runs under, synced files
+    /// are uploaded to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub panel: Option<PanelSettings>,
+    /// The maintainer's `serve` instance syncs are reported to, opt-in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fleet: Option<FleetSettings>,
+    /// Token the reports of clients must carry for `serve --fleet` to
+    /// record them, or else read from `MODPACK_SYNC_FLEET_TOKEN` or the
+    /// keyring entry `fleet`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fleet_token: Option<String>,
+    /// Where the daemon reports applied updates and failures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify: Option<NotifySettings>,
+    /// What a sync does in situations it can't settle on its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decisions: Option<DecisionSettings>,
+    /// Which files `update` and `outdated` move entries to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updates: Option<UpdateSettings>,
+    /// Public keys the signatures of signed entries must be made with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing: Option<SigningSettings>,
+    /// Shell command run on every downloaded file before it is installed,
+    /// with its path in `MODPACK_SYNC_FILE`, e.g.
+    /// `clamscan --no-summary "$MODPACK_SYNC_FILE"`. Files it exits non-zero
+    /// for are quarantined.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan_hook: Option<String>,
+    /// Policy file, or url, constraining what the pack may install, such as
+    /// the providers and licenses allowed. Relative to the instance, and
+    /// kept to along with the policy of the user settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    /// Run the post-install commands of entries. They run with the rights
+    /// of whoever syncs, so only turn this on for packs that are trusted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_scripts: bool,
+    /// Urls of instances running `modpack-sync serve`, such as the server
+    /// the pack is played on, to fetch the files the lockfile pins from
+    /// before their providers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub peers: Vec<String>,
+    /// Token the peers are asked with, for those whose daemon settings have
+    /// tokens, or else read from `MODPACK_SYNC_PEER_TOKEN` or the keyring
+    /// entry `peer`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_token: Option<String>,
+    /// Measure the peers before syncs fetching many files from them and
+    /// try the fastest first, for clients far from where they are listed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub probe_peers: bool,
+    /// Directory downloads are written to until they are complete and
+    /// checked, relative to the instance, e.g. on a faster disk.
+    /// `.modpack-sync/staging` otherwise. They are copied across when it is
+    /// on another file system than the game directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub staging_dir: Option<String>,
+    /// MiB the files being downloaded may take up in memory together, 256
+    /// by default. Downloads wait for memory when several large jars would
+    /// go over it, lower it on machines with little RAM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_memory_mb: Option<u64>,
+    /// MiB from which a file is downloaded in ranges over several
+    /// connections at once, 128 by default. Helps large worlds and content
+    /// mods over links far from the CDN.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split_download_mb: Option<u64>,
+    /// Connections a large file is downloaded over, 4 by default, 1 to
+    /// download every file in one piece.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_connections: Option<usize>,
+    /// Flush installed files and the state to disk and journal the installs
+    /// of every sync, so a crash or power loss half way through is cleaned
+    /// up by the next sync instead of leaving a broken mods directory. Makes
+    /// syncs slower, meant for servers.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub durable: bool,
+    /// Download the Modrinth version of entries that declare one as well
+    /// when CurseForge keeps failing for their file.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub provider_fallback: bool,
+    /// Copy the jars installed mods bundle Jar-in-Jar into the mods
+    /// directory as files of their own, e.g. for scanners that don't look
+    /// inside jars. They are deleted with the mod bundling them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub extract_bundled: bool,
+    /// Codes of warnings to keep off the console and out of the reports,
+    /// e.g. `["W001", "W007"]`, for ones that are known and accepted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suppress: Vec<String>,
+    /// How `verify --quick` checks the instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<VerifySettings>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct VerifySettings {
+    /// Hours after which a quick verification hashes every file instead,
+    /// as `verify --deep` does. Quick checks stay quick when left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deep_every_hours: Option<u64>,
+}
+
+/// How the detached signatures of signed entries are made.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureFormat {
+    /// minisign, checked against [`SigningSettings::minisign`].
+    Minisign,
+    /// OpenPGP, checked by `gpgv` against [`SigningSettings::gpg_keyring`].
+    Openpgp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SigningSettings {
+    /// Format of the signatures, entries with one are refused until it is
+    /// set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<SignatureFormat>,
+    /// minisign public keys, the base64 line of a `.pub` file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub minisign: Vec<String>,
+    /// OpenPGP keyring `gpgv` checks signatures with, relative to the
+    /// instance directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpg_keyring: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct UpdateSettings {
+    /// Files uploaded less than this many hours ago are passed over, giving
+    /// broken or malicious uploads time to be noticed and pulled. The daemon
+    /// holds pack versions it found less than this many hours ago as well.
+    #[serde(default)]
+    pub min_age_hours: u64,
+    /// Minimum ages of single entries by name or CurseForge project id, e.g.
+    /// 0 for a mod whose fix is needed right away.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mods: BTreeMap<String, u64>,
+}
+
+impl UpdateSettings {
+    /// Hours a file of the entry must be public before it is updated to.
+    pub fn min_age_hours(&self, name: &str, project_id: &str) -> u64 {
+        self.mods
+            .get(name)
+            .or_else(|| self.mods.get(project_id))
+            .copied()
+            .unwrap_or(self.min_age_hours)
+    }
+}
+
+/// `hours` as a duration, one longer than any age when they don't fit.
+pub fn hours(hours: u64) -> chrono::Duration {
+    i64::try_from(hours).ok().and_then(chrono::Duration::try_hours).unwrap_or(chrono::Duration::MAX)
+}
+
+/// `days` as a duration, the same way as [`hours`].
+pub fn days(days: u64) -> chrono::Duration {
+    i64::try_from(days).ok().and_then(chrono::Duration::try_days).unwrap_or(chrono::Duration::MAX)
+}
+
+/// Answers taken when nobody is asked, and whether to ask on a terminal.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DecisionSettings {
+    #[serde(default = "default_true")]
+    pub interactive: bool,
+    /// A locally modified managed file the pack changes, `replace` or `keep`.
+    #[serde(default = "default_modified")]
+    pub modified: String,
+    /// A file that is another copy of a mod an entry installs, `remove` or
+    /// `keep`.
+    #[serde(default = "default_duplicate")]
+    pub duplicate: String,
+    /// A file built for another loader than the pack's, `install` or `skip`.
+    #[serde(default = "default_loader")]
+    pub loader: String,
+}
+
+impl Default for DecisionSettings {
+    fn default() -> DecisionSettings {
+        DecisionSettings {
+            interactive: true,
+            modified: default_modified(),
+            duplicate: default_duplicate(),
+            loader: default_loader(),
+        }
+    }
+}
+
+fn default_modified() -> String {
+    "keep".to_string()
+}
+
+fn default_duplicate() -> String {
+    "remove".to_string()
+}
+
+fn default_loader() -> String {
+    "install".to_string()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BackupSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Number of backups kept, the oldest are deleted first.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApprovalSettings {
+    /// Deleting more files than this in one sync needs confirmation.
+    #[serde(default = "default_max_removals")]
+    pub max_removals: usize,
+    /// Deleting files the tool did not install needs confirmation.
+    #[serde(default = "default_true")]
+    pub unmanaged: bool,
+}
+
+impl Default for ApprovalSettings {
+    fn default() -> ApprovalSettings {
+        ApprovalSettings {
+            max_removals: default_max_removals(),
+            unmanaged: true,
+        }
+    }
+}
+
+fn default_max_removals() -> usize {
+    5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PermissionSettings {
+    /// Octal mode such as `0644`, a string since TOML has no octal numbers
+    /// that survive a round trip.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// User name or uid to own the files, changing it needs root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Group name or gid, e.g. `mc`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DaemonSettings {
+    /// Minutes between checks of the source.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Cron expressions of the minutes updates may be applied in, such as
+    /// `* 4-5 * * *`. Updates are applied whenever found without any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub windows: Vec<String>,
+    /// `local`, `utc` or an offset such as `+02:00` the windows are in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub outside_window: OutsideWindow,
+    /// Hold updates of a server while more players than this are online,
+    /// 0 waits for it to be empty. Needs RCON, see [`RestartSettings`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_players: Option<usize>,
+    /// Download the files of held updates while they wait, as `prefetch`
+    /// does, so applying them only moves the files into place.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prefetch: bool,
+    /// Tokens the dashboard and `serve` take as `Authorization: Bearer`,
+    /// or from the cookie the dashboard's login sets. Without any, only
+    /// the public status can be seen, the dashboard can't operate the
+    /// instance and is only served on this machine.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<TokenSettings>,
+    /// Show the instance's status on the dashboard without a token, for a
+    /// public status page. Operating it still needs an operator token.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub public_status: bool,
+}
+
+/// A token of the dashboard and `serve`. The secret is `token`, or else read from
+/// `MODPACK_SYNC_TOKEN_<NAME>` with other characters than letters and
+/// digits replaced by `_`, or from the keyring entry `token:<name>`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TokenSettings {
+    pub name: String,
+    #[serde(default)]
+    pub scope: Scope,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// What a token of the dashboard allows, each scope allowing the ones
+/// before it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Seeing the status.
+    #[default]
+    Read,
+    /// Triggering syncs, pausing, resuming, reloading and rolling back.
+    Operator,
+}
+
+impl Scope {
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Operator => "operator",
+        }
+    }
+}
+
+/// What happens to updates found outside the maintenance windows or held
+/// for the players online. Either way they are applied once allowed.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutsideWindow {
+    /// Announce the pending update once.
+    #[default]
+    Notify,
+    /// Wait for the window without announcing anything.
+    Quiet,
+}
+
+impl Default for DaemonSettings {
+    fn default() -> DaemonSettings {
+        DaemonSettings {
+            interval: default_interval(),
+            windows: Vec::new(),
+            timezone: None,
+            outside_window: OutsideWindow::default(),
+            max_players: None,
+            prefetch: false,
+            tokens: Vec::new(),
+            public_status: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RestartSettings {
+    /// `host:port` of the server's RCON, by default the local port from
+    /// `server.properties`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rcon: Option<String>,
+    /// Falls back to `rcon.password` of `server.properties`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Console commands sent in order, `wait <seconds>` pauses between them.
+    #[serde(default = "default_restart_commands")]
+    pub commands: Vec<String>,
+    /// Shell command starting the server again once it stopped, e.g.
+    /// `systemctl restart minecraft`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hook: Option<String>,
+}
+
+/// A server of a Pterodactyl or Pelican panel, reached with the panel's
+/// client API.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PanelSettings {
+    /// Address of the panel, e.g. `https://panel.example.com`.
+    pub url: String,
+    /// Identifier of the server, as in its address on the panel.
+    pub server: String,
+    /// The game directory within the server's working directory, which
+    /// is used itself when it has a `server.properties`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    /// Client API key of an account with access to the server. Falls back to
+    /// `MODPACK_SYNC_PANEL_KEY` or the keyring, see [`secret`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Stop the server while changed files are uploaded and start it again
+    /// after.
+    #[serde(default = "default_true")]
+    pub power: bool,
+}
+
+impl PanelSettings {
+    pub fn api_key(&self) -> Result<String> {
+        self.api_key
+            .clone()
+            .or_else(|| secret("MODPACK_SYNC_PANEL_KEY", &format!("panel:{}", self.server)))
+            .ok_or_else(|| anyhow!("no API key for the panel server {}, set panel.api_key or MODPACK_SYNC_PANEL_KEY", self.server))
+    }
+}
+
+/// Reporting the pack version and the state of the instance after every
+/// sync, so the maintainer sees which players and servers are on what.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FleetSettings {
+    /// Url of a `modpack-sync serve --fleet` instance, e.g.
+    /// `http://play.example.com:8990`.
+    pub report_to: String,
+    /// Name of the player or server shown to the maintainer, only the
+    /// instance's made up id when left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Token the `serve --fleet` instance takes reports with, or else read
+    /// from `MODPACK_SYNC_FLEET_TOKEN` or the keyring entry `fleet`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl FleetSettings {
+    pub fn token(&self) -> Option<String> {
+        self.token.clone().or_else(|| secret("MODPACK_SYNC_FLEET_TOKEN", "fleet"))
+    }
+}
+
+fn default_restart_commands() -> Vec<String> {
+    ["say The server restarts in 60 seconds for a modpack update", "wait 60", "save-all", "stop"]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+/// Notification backends. Messages are rendered from templates with
+/// `{{name}}` placeholders and `{{#if name}}...{{/if}}` sections, see
+/// [`super::notify::render`].
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct NotifySettings {
+    /// Title of every message, the subject of mails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Body of every message, backends can override it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<EmailSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discord: Option<WebhookSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slack: Option<WebhookSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpSettings>,
+}
+
+/// A Discord or Slack incoming webhook.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+/// A generic endpoint the notification is POSTed to.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HttpSettings {
+    pub url: String,
+    /// Body of the request, the notification as JSON without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EmailSettings {
+    /// Host name of the SMTP server.
+    pub server: String,
+    /// Defaults to 465 for tls and 587 otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default = "default_security")]
+    pub security: smtp::Security,
+    /// Login of the SMTP server. The password is read from
+    /// `MODPACK_SYNC_SMTP_PASSWORD` or the system keyring, see [`secret`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Password of the login, for hosts without a keyring. Best stored
+    /// encrypted, see `modpack-sync encrypt-secret`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+impl EmailSettings {
+    /// The password of the login, none without one.
+    pub fn password(&self) -> Result<Option<String>> {
+        let Some(username) = &self.username else {
+            return Ok(None);
+        };
+        self.password
+            .clone()
+            .or_else(|| secret("MODPACK_SYNC_SMTP_PASSWORD", username))
+            .map(Some)
+            .ok_or_else(|| anyhow!("no password for {}, set password, MODPACK_SYNC_SMTP_PASSWORD or store it in the system keyring", username))
+    }
+}
+
+fn default_security() -> smtp::Security {
+    smtp::Security::Starttls
+}
+
+fn default_interval() -> u64 {
+    15
+}
+
+fn default_keep() -> usize {
+    super::backup::DEFAULT_KEEP
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WorldBackupSettings {
+    /// World directory relative to the instance directory.
+    #[serde(default = "default_world")]
+    pub dir: String,
+    /// Number of world backups kept, worlds are big so fewer by default.
+    #[serde(default = "default_world_keep")]
+    pub keep: usize,
+}
+
+fn default_world() -> String {
+    "world".to_string()
+}
+
+fn default_world_keep() -> usize {
+    3
+}
+
+impl InstanceSettings {
+    pub fn load(base_dir: &Path) -> Result<InstanceSettings> {
+        let path = base_dir.join(INSTANCE_FILE);
+        if !path.exists() {
+            return Ok(InstanceSettings::default());
+        }
+        read(&path)
+    }
+
+    pub fn save(&self, base_dir: &Path) -> Result<()> {
+        let path = base_dir.join(INSTANCE_FILE);
+        fs::write(&path, written(self, &path)?)?;
+        Ok(())
+    }
+
+    /// Prism clients keep the game inside `.minecraft`, vanilla launcher
+    /// profiles wherever their game directory is, dedicated servers and
+    /// other launchers' instances run from the instance directory itself.
+    pub fn game_dir(&self, base_dir: &str) -> String {
+        match (self.side, Launcher::detect(Path::new(base_dir))) {
+            (Side::Client, launcher) if launcher.nested_game_dir() => format!("{}/.minecraft", base_dir),
+            (Side::Client, Launcher::Vanilla) => match &self.profile {
+                // a missing profile fails the sync, see `launcher::profile_game_dir`
+                Some(name) => launcher::profile_game_dir(Path::new(base_dir), name)
+                    .map_or_else(|_| base_dir.to_string(), |dir| dir.to_string_lossy().to_string()),
+                None => base_dir.to_string(),
+            },
+            _ => base_dir.to_string(),
+        }
+    }
+
+    pub fn mods_dir(&self, base_dir: &str) -> String {
+        format!("{}/mods", self.game_dir(base_dir))
+    }
+
+    /// The token `serve --fleet` records the reports of clients with.
+    pub fn fleet_token(&self) -> Option<String> {
+        self.fleet_token.clone().or_else(|| secret("MODPACK_SYNC_FLEET_TOKEN", "fleet"))
+    }
+
+    /// The token the peers of the instance are asked with.
+    pub fn peer_token(&self) -> Option<String> {
+        self.peer_token.clone().or_else(|| secret("MODPACK_SYNC_PEER_TOKEN", "peer"))
+    }
+}
+
+/// Settings shared by every instance of the current user.
+#[derive(Serialize, Deserialize, Default)]
+pub struct UserSettings {
+    /// CurseForge API key, `setup` stores it encrypted when
+    /// `MODPACK_SYNC_PASSPHRASE` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Set to false to stop the daily check for new modpack-sync releases.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub check_updates: Option<bool>,
+    /// Locale of the messages, e.g. `de`, instead of the system's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Credentials for hosts serving private Maven artifacts and packs, by
+    /// host name, e.g. `[hosts."nexus.example.com"]`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, HostAuth>,
+    /// Where host names are looked up, for networks that block or poison
+    /// the DNS of CurseForge and the like.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<DnsSettings>,
+    /// How connections are made, for hosts with broken IPv6 routes and the
+    /// like.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkSettings>,
+    /// Policy file, or url, every instance of the machine keeps to, for
+    /// schools and hosts managing them centrally. Relative paths are looked
+    /// up in this directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+}
+
+/// Resolver used instead of the system's. Names the resolver doesn't know
+/// are still looked up by the system, so local hosts keep working.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct DnsSettings {
+    /// DNS server asked over UDP, e.g. `1.1.1.1` or `9.9.9.9:53`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// DNS-over-HTTPS endpoint, e.g. `https://1.1.1.1/dns-query`, taken
+    /// over `server`. Its own host is looked up by the system, unless it
+    /// is an address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doh: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct NetworkSettings {
+    /// Which addresses of a host are tried. Without it both families are
+    /// raced in the order the resolver gives, usually IPv6 first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<IpMode>,
+    /// Local address or, on Linux, network interface connections are made
+    /// from, e.g. `192.0.2.10` or `eth1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpMode {
+    /// IPv4 addresses first, IPv6 ones when those fail.
+    PreferIpv4,
+    PreferIpv6,
+    /// IPv4 addresses only.
+    Ipv4,
+    Ipv6,
+}
+
+impl IpMode {
+    /// `addrs` in the order they are to be tried, without those of the
+    /// family left out.
+    pub fn order(self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        match self {
+            IpMode::PreferIpv4 => addrs.sort_by_key(|a| a.is_ipv6()),
+            IpMode::PreferIpv6 => addrs.sort_by_key(|a| a.is_ipv4()),
+            IpMode::Ipv4 => addrs.retain(|a| a.is_ipv4()),
+            IpMode::Ipv6 => addrs.retain(|a| a.is_ipv6()),
+        }
+        addrs
+    }
+}
+
+/// How requests to a host authenticate. The secret, a password or token,
+/// is read from `MODPACK_SYNC_AUTH_<HOST>` with the host upper-cased and
+/// other characters than letters and digits replaced by `_`, or from the
+/// keyring entry for the host name as the account, see [`secret`].
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HostAuth {
+    /// Basic auth as this user, the secret is sent as a bearer token
+    /// without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+impl UserSettings {
+    pub fn path() -> Result<PathBuf> {
+        Ok(UserSettings::dir()?.join("config.toml"))
+    }
+
+    /// Per-user directory for settings and small bits of tool state.
+    pub fn dir() -> Result<PathBuf> {
+        let dir = if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+            PathBuf::from(dir)
+        } else if let Some(dir) = env::var_os("APPDATA") {
+            PathBuf::from(dir)
+        } else if let Some(home) = env::var_os("HOME") {
+            PathBuf::from(home).join(".config")
+        } else {
+            return Err(anyhow!("could not determine the user config directory"));
+        };
+        Ok(dir.join("modpack-sync"))
+    }
+
+    pub fn load() -> Result<UserSettings> {
+        let path = UserSettings::path()?;
+        if !path.exists() {
+            return Ok(UserSettings::default());
+        }
+        read(&path)
+    }
+
+    /// Writes the settings readable by the current user only, since they
+    /// hold the API key.
+    pub fn save(&self) -> Result<()> {
+        let path = UserSettings::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, written(self, &path)?)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+}
+
+/// Settings parsed from `path`, with their encrypted secrets decrypted.
+/// Keys, passwords and tokens may be stored encrypted, see `modpack-sync
+/// encrypt-secret`.
+fn read<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T> {
+    let contents = fs::read_to_string(path)?;
+    let mut value: toml::Value = toml::from_str(&contents).with_context(|| format!("failed to parse {}", path.display()))?;
+    secrets::open(&mut value, &path.to_string_lossy())?;
+    value.try_into().with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// `settings` as written to their file at `path`, the values that were read
+/// encrypted from it and are unchanged encrypted again.
+fn written<T: Serialize>(settings: &T, path: &Path) -> Result<String> {
+    let mut value = toml::Value::try_from(settings)?;
+    secrets::seal(&mut value, &path.to_string_lossy());
+    Ok(toml::to_string_pretty(&value)?)
+}
+
+/// The CurseForge API key from `CURSE_API_KEY`, falling back to the user
+/// settings written by `modpack-sync setup`.
+pub fn api_key() -> Result<String> {
+    if let Ok(key) = env::var("CURSE_API_KEY") {
+        if !key.trim().is_empty() {
+            return Ok(key);
+        }
+    }
+
+    UserSettings::load()?
+        .api_key
+        .filter(|k| !k.trim().is_empty())
+        .ok_or_else(|| anyhow!("no CurseForge API key found, set CURSE_API_KEY or run `modpack-sync setup`"))
+}
+
+/// A secret such as a password: `env` when set, otherwise the entry of
+/// the system keyring stored for `account` under the `modpack-sync`
+/// service, e.g. with `secret-tool store --label=modpack-sync service
+/// modpack-sync account <account>` or `security add-generic-password -s
+/// modpack-sync -a <account> -w`.
+pub fn secret(env: &str, account: &str) -> Option<String> {
+    if let Some(value) = env::var(env).ok().filter(|v| !v.is_empty()) {
+        return Some(value);
+    }
+    let mut command = match cfg!(target_os = "macos") {
+        true => {
+            let mut c = Command::new("security");
+            c.args(["find-generic-password", "-s", "modpack-sync", "-a", account, "-w"]);
+            c
+        }
+        false => {
+            let mut c = Command::new("secret-tool");
+            c.args(["lookup", "service", "modpack-sync", "account", account]);
+            c
+        }
+    };
+    let output = command.output().ok().filter(|o| o.status.success())?;
+    let value = String::from_utf8_lossy(&output.stdout).trim_end_matches(['\r', '\n']).to_string();
+    Some(value).filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hours_saturate() {
+        assert_eq!(hours(0), chrono::Duration::zero());
+        assert_eq!(hours(48), chrono::Duration::days(2));
+        assert_eq!(hours(u64::MAX), chrono::Duration::MAX);
+        assert_eq!(hours(i64::MAX as u64), chrono::Duration::MAX);
+        assert_eq!(days(u64::MAX), chrono::Duration::MAX);
+    }
+}
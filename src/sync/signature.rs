@@ -0,0 +1,180 @@
+use super::auth;
+use super::http::{self, RequestExt};
+use super::settings::{SignatureFormat, SigningSettings};
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Checks `file` against the detached signature at `url` with the
+/// configured keys. minisign signatures are checked here, OpenPGP ones are
+/// handed to `gpgv`.
+pub fn verify(file: &Path, url: &str, base_dir: &Path, keys: &SigningSettings) -> Result<()> {
+    let format = keys
+        .format
+        .ok_or_else(|| anyhow!("signing.format is not set, set it to minisign or openpgp to check signatures"))?;
+    let signature = fetch(url).with_context(|| format!("failed to fetch the signature {}", url))?;
+    let checked = match format {
+        SignatureFormat::Minisign => minisign(file, &signature, &keys.minisign),
+        SignatureFormat::Openpgp => gpg(file, &signature, base_dir, keys.gpg_keyring.as_deref()),
+    };
+    checked.with_context(|| format!("{} does not match its signature {}", file.display(), url))
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>> {
+    if url.starts_with("file:") {
+        let path = reqwest::Url::parse(url)?
+            .to_file_path()
+            .map_err(|_| anyhow!("{} is not a local path", url))?;
+        return Ok(fs::read(path)?);
+    }
+    http::decoded(auth::apply(http::api().get(url), url).send_captured()?.error_for_status()?)
+}
+
+/// Decodes the base64 line of a minisign key or signature: the algorithm,
+/// the key id and the key or signature itself.
+fn decode(line: &str, len: usize) -> Result<([u8; 2], [u8; 8], Vec<u8>)> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(line.trim())?;
+    if bytes.len() != 10 + len {
+        return Err(anyhow!("malformed minisign data"));
+    }
+    let algorithm = [bytes[0], bytes[1]];
+    let id = bytes[2..10].try_into().expect("slice of eight bytes");
+    Ok((algorithm, id, bytes[10..].to_vec()))
+}
+
+#[cfg(feature = "openssl")]
+fn ed25519(key: &[u8], message: &[u8], signature: &[u8]) -> Result<bool> {
+    use openssl::pkey::{Id, PKey};
+    let key = PKey::public_key_from_raw_bytes(key, Id::ED25519)?;
+    let mut verifier = openssl::sign::Verifier::new_without_digest(&key)?;
+    Ok(verifier.verify_oneshot(signature, message)?)
+}
+
+#[cfg(feature = "openssl")]
+fn blake2b(contents: &[u8]) -> Result<Vec<u8>> {
+    use openssl::hash::{self, MessageDigest};
+    let digest = MessageDigest::from_name("BLAKE2b512").ok_or_else(|| anyhow!("BLAKE2b is unavailable"))?;
+    Ok(hash::hash(digest, contents)?.to_vec())
+}
+
+#[cfg(not(feature = "openssl"))]
+fn ed25519(_key: &[u8], _message: &[u8], _signature: &[u8]) -> Result<bool> {
+    Err(anyhow!("minisign signatures need modpack-sync built with the openssl feature"))
+}
+
+#[cfg(not(feature = "openssl"))]
+fn blake2b(_contents: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow!("minisign signatures need modpack-sync built with the openssl feature"))
+}
+
+/// Checks a minisign signature, both over the file and over its trusted
+/// comment, made by one of `keys`.
+fn minisign(file: &Path, signature: &[u8], keys: &[String]) -> Result<()> {
+    let text = String::from_utf8_lossy(signature);
+    let mut lines = text.lines().filter(|l| !l.starts_with("untrusted comment:"));
+    let (Some(signature), Some(comment), Some(global)) = (lines.next(), lines.next(), lines.next()) else {
+        return Err(anyhow!("malformed minisign signature"));
+    };
+    let (algorithm, id, signature) = decode(signature, 64)?;
+    let comment = comment
+        .strip_prefix("trusted comment: ")
+        .ok_or_else(|| anyhow!("malformed minisign signature"))?;
+    let global = base64::engine::general_purpose::STANDARD.decode(global.trim())?;
+
+    let key = keys
+        .iter()
+        .filter_map(|k| decode(k, 32).ok())
+        .find(|(_, key_id, _)| *key_id == id)
+        .ok_or_else(|| anyhow!("no configured minisign key has the id {}", hex(&id)))?
+        .2;
+    let contents = fs::read(file)?;
+    let message = match &algorithm {
+        b"Ed" => contents,
+        // prehashed, what minisign signs large files with by default
+        b"ED" => blake2b(&contents)?,
+        _ => return Err(anyhow!("unknown minisign algorithm")),
+    };
+    if !ed25519(&key, &message, &signature)? {
+        return Err(anyhow!("the signature was not made over this file"));
+    }
+    let mut trusted = signature.clone();
+    trusted.extend_from_slice(comment.as_bytes());
+    if !ed25519(&key, &trusted, &global)? {
+        return Err(anyhow!("the trusted comment of the signature was altered"));
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().rev().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Checks an OpenPGP signature with `gpgv` against the configured keyring.
+/// The signature is written beside `file`, the download in the staging
+/// directory.
+fn gpg(file: &Path, signature: &[u8], base_dir: &Path, keyring: Option<&str>) -> Result<()> {
+    let keyring = keyring.ok_or_else(|| anyhow!("no gpg_keyring is configured to check OpenPGP signatures with"))?;
+    let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let signature_file = file.with_file_name(format!("{}.sig.part", name));
+    fs::write(&signature_file, signature)?;
+    let output = Command::new("gpgv")
+        .arg("--keyring")
+        .arg(base_dir.join(keyring))
+        .arg(&signature_file)
+        .arg(file)
+        .output();
+    let _ = fs::remove_file(&signature_file);
+    let output = output.context("failed to run gpgv, is it installed?")?;
+    if !output.status.success() {
+        return Err(anyhow!("gpgv: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_need_a_format() {
+        let checked = verify(Path::new("mod.jar"), "file:///mod.jar.minisig", Path::new("."), &SigningSettings::default());
+        assert!(format!("{:#}", checked.unwrap_err()).contains("signing.format"));
+    }
+
+    #[cfg(feature = "openssl")]
+    #[test]
+    fn minisign_signatures_are_checked() {
+        use openssl::pkey::PKey;
+        use openssl::sign::Signer;
+
+        let encode = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+        let key = PKey::generate_ed25519().unwrap();
+        let sign = |message: &[u8]| Signer::new_without_digest(&key).unwrap().sign_oneshot_to_vec(message).unwrap();
+        let id = [7u8; 8];
+        let public = [b"Ed".as_slice(), &id, &key.raw_public_key().unwrap()].concat();
+
+        let file = std::env::temp_dir().join(format!("modpack-sync-signature-{}.jar", std::process::id()));
+        fs::write(&file, b"the mod").unwrap();
+        let signature = sign(b"the mod");
+        let comment = "timestamp:0";
+        let global = sign(&[signature.as_slice(), comment.as_bytes()].concat());
+        let minisig = |comment: &str| {
+            format!(
+                "untrusted comment: test\n{}\ntrusted comment: {}\n{}\n",
+                encode(&[b"Ed".as_slice(), &id, &signature].concat()),
+                comment,
+                encode(&global)
+            )
+        };
+
+        let keys = [encode(&public)];
+        assert!(minisign(&file, minisig(comment).as_bytes(), &keys).is_ok());
+        assert!(minisign(&file, minisig("timestamp:1").as_bytes(), &keys).is_err());
+        assert!(minisign(&file, minisig(comment).as_bytes(), &[]).is_err());
+        fs::write(&file, b"another mod").unwrap();
+        assert!(minisign(&file, minisig(comment).as_bytes(), &keys).is_err());
+        let _ = fs::remove_file(&file);
+    }
+}
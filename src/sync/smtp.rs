@@ -0,0 +1,187 @@
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use chrono::Local;
+use native_tls::{TlsConnector, TlsStream};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// How the connection to the mail server is secured.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// TLS from the start, usually port 465.
+    Tls,
+    /// Plain connection upgraded with STARTTLS, usually port 587.
+    Starttls,
+    /// Unencrypted, only for relays on the local network.
+    None,
+}
+
+enum Connection {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Plain(s) => s.read(buf),
+            Connection::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Plain(s) => s.write(buf),
+            Connection::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Connection::Plain(s) => s.flush(),
+            Connection::Tls(s) => s.flush(),
+        }
+    }
+}
+
+pub struct Message<'a> {
+    pub from: &'a str,
+    pub to: &'a [String],
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+/// Credentials and address of the mail server.
+pub struct Server<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub security: Security,
+    /// User name and password, sent with `AUTH PLAIN`.
+    pub login: Option<(&'a str, &'a str)>,
+}
+
+struct Session {
+    connection: Connection,
+}
+
+impl Session {
+    /// Reads a possibly multi-line reply, failing unless its code starts
+    /// with `expect`.
+    fn reply(&mut self, expect: char) -> Result<String> {
+        let mut text = String::new();
+        loop {
+            let mut line = Vec::new();
+            let mut byte = [0; 1];
+            while !line.ends_with(b"\r\n") {
+                if self.connection.read(&mut byte)? == 0 {
+                    return Err(anyhow!("the mail server closed the connection"));
+                }
+                line.push(byte[0]);
+            }
+            let line = String::from_utf8_lossy(&line).to_string();
+            text.push_str(&line);
+            // `250-` continues a reply, `250 ` ends it
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        if !text.starts_with(expect) {
+            return Err(anyhow!("the mail server answered {}", text.trim()));
+        }
+        Ok(text)
+    }
+
+    fn command(&mut self, line: &str, expect: char) -> Result<String> {
+        self.connection.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.reply(expect)
+    }
+}
+
+/// Sends one plain text mail.
+pub fn send(server: &Server, message: &Message) -> Result<()> {
+    let stream = TcpStream::connect((server.host, server.port))
+        .with_context(|| format!("failed to connect to {}:{}", server.host, server.port))?;
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let tls = TlsConnector::new()?;
+    let connection = match server.security {
+        Security::Tls => Connection::Tls(Box::new(tls.connect(server.host, stream)?)),
+        _ => Connection::Plain(stream),
+    };
+    let mut session = Session { connection };
+    session.reply('2')?;
+    session.command("EHLO modpack-sync", '2')?;
+    if server.security == Security::Starttls {
+        session.command("STARTTLS", '2')?;
+        let Connection::Plain(stream) = session.connection else {
+            unreachable!("starttls only runs on plain connections");
+        };
+        session.connection = Connection::Tls(Box::new(tls.connect(server.host, stream)?));
+        session.command("EHLO modpack-sync", '2')?;
+    }
+    if let Some((user, password)) = server.login {
+        let token = base64::engine::general_purpose::STANDARD.encode(format!("\0{}\0{}", user, password));
+        session
+            .command(&format!("AUTH PLAIN {}", token), '2')
+            .context("the mail server rejected the login")?;
+    }
+
+    session.command(&format!("MAIL FROM:<{}>", message.from), '2')?;
+    for to in message.to.iter() {
+        session.command(&format!("RCPT TO:<{}>", to), '2')?;
+    }
+    session.command("DATA", '3')?;
+    // a line break in the subject would start headers of its own
+    let subject: String = message.subject.chars().map(|c| if c == '\r' || c == '\n' { ' ' } else { c }).collect();
+    let mut data = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n",
+        message.from,
+        message.to.join(", "),
+        subject,
+        Local::now().to_rfc2822()
+    );
+    for line in quoted_printable(message.body) {
+        // lines starting with a dot are doubled so they don't end the data
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(&line);
+        data.push_str("\r\n");
+    }
+    data.push('.');
+    session.command(&data, '2')?;
+    let _ = session.command("QUIT", '2');
+    Ok(())
+}
+
+/// Longest encoded line, soft breaks included.
+const QP_LINE: usize = 76;
+
+/// The lines of `body` quoted-printable encoded, longer ones broken with
+/// soft line breaks so none comes near the 998 characters SMTP allows.
+fn quoted_printable(body: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in body.lines() {
+        let mut encoded = String::new();
+        let bytes = line.as_bytes();
+        for (i, &b) in bytes.iter().enumerate() {
+            // spaces and tabs are only kept as they are inside a line
+            let plain = ((b == b' ' || b == b'\t') && i + 1 < bytes.len()) || ((33..=126).contains(&b) && b != b'=');
+            let piece = match plain {
+                true => (b as char).to_string(),
+                false => format!("={:02X}", b),
+            };
+            if encoded.len() + piece.len() > QP_LINE - 1 {
+                encoded.push('=');
+                lines.push(std::mem::take(&mut encoded));
+            }
+            encoded.push_str(&piece);
+        }
+        lines.push(encoded);
+    }
+    lines
+}
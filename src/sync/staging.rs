@@ -0,0 +1,68 @@
+use super::hash;
+use super::log::STATE_DIR;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The directory downloads of the instance at `base_dir` are written to
+/// until they are checked: the one it names, `.modpack-sync/staging`
+/// otherwise, so nothing unchecked is ever in the game directory.
+pub fn dir(base_dir: &Path, configured: Option<&str>) -> PathBuf {
+    match configured {
+        Some(dir) => base_dir.join(dir),
+        None => base_dir.join(STATE_DIR).join("staging"),
+    }
+}
+
+/// Where the file at `dest` is written until it is complete: into `dir`,
+/// beside `dest` without one, so moving it into place is a rename on the
+/// same file system.
+pub fn part(dir: Option<&Path>, dest: &Path) -> PathBuf {
+    let name = dest.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    match dir {
+        // files of the same name go to different directories
+        Some(dir) => {
+            let id = hash::Algorithm::Sha256.bytes(dest.to_string_lossy().as_bytes());
+            dir.join(format!("{}-{}.part", &id[..16], name))
+        }
+        None => dest.with_file_name(format!("{}.part", name)),
+    }
+}
+
+/// Moves the complete download `part` to `dest`. Across file systems it is
+/// copied beside `dest` and flushed to disk first, so `dest` is still
+/// replaced at once and never left half written.
+pub fn place(part: &Path, dest: &Path) -> io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(part, dest) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        moved => return moved,
+    }
+    let beside = self::part(None, dest);
+    let copied = fs::copy(part, &beside)
+        .and_then(|_| fs::File::open(&beside)?.sync_all())
+        .and_then(|_| fs::rename(&beside, dest));
+    if copied.is_err() {
+        let _ = fs::remove_file(&beside);
+    }
+    copied?;
+    fs::remove_file(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downloads_stay_out_of_the_game_directory() {
+        let base_dir = Path::new("/srv/pack");
+        let staging = dir(base_dir, None);
+        assert_eq!(staging, base_dir.join(".modpack-sync/staging"));
+        assert_eq!(dir(base_dir, Some("/fast/staging")), Path::new("/fast/staging"));
+        let part = part(Some(&staging), &base_dir.join("mods/jei.jar"));
+        assert!(part.starts_with(&staging) && part.to_string_lossy().ends_with("-jei.jar.part"));
+        assert_ne!(part, self::part(Some(&staging), &base_dir.join("config/jei.jar")));
+    }
+}
@@ -0,0 +1,118 @@
+use super::manifest::{self, PackHeader};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// File in the game directory telling which pack version the instance runs.
+pub const FILE: &str = "pack-version.json";
+/// Config of Better Compatibility Checker, which shows the pack on the main
+/// menu and in crash reports.
+const BCC: &str = "config/bcc-common.toml";
+/// The config Better Compatibility Checker writes on its first start.
+const BCC_DEFAULT: &str = "[general]\n\tmodpackProjectID = 0\n\tmodpackName = \"\"\n\tmodpackVersion = \"\"\n\tuseMetadata = false\n";
+
+#[derive(Serialize)]
+pub struct Stamp {
+    pub name: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minecraft: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loader: Option<String>,
+    pub synced_at: DateTime<Utc>,
+}
+
+/// Writes the stamp file and the config files the pack's `stamp` settings
+/// name with the pack's name and version.
+pub fn write(game_dir: &Path, pack: &PackHeader) -> Result<()> {
+    let stamp = Stamp {
+        name: pack.name.clone(),
+        version: pack.version.clone(),
+        minecraft: pack.minecraft.clone(),
+        loader: pack.loader.clone(),
+        synced_at: Utc::now(),
+    };
+    let path = game_dir.join(FILE);
+    fs::write(&path, serde_json::to_string_pretty(&stamp)?).with_context(|| format!("failed to write {}", path.display()))?;
+
+    let Some(settings) = &pack.stamp else {
+        return Ok(());
+    };
+    let mut vars = pack.variables(&[]);
+    vars.insert("pack_name".to_string(), pack.name.clone());
+    if settings.bcc {
+        let path = game_dir.join(BCC);
+        if !path.exists() {
+            fs::create_dir_all(path.parent().expect("the config lies in a directory"))?;
+            fs::write(&path, BCC_DEFAULT)?;
+        }
+        let keys = BTreeMap::from([
+            ("modpackName".to_string(), pack.name.clone()),
+            ("modpackVersion".to_string(), pack.version.clone()),
+        ]);
+        patch(&path, &keys)?;
+    }
+    for (file, keys) in settings.files.iter() {
+        let path = game_dir.join(manifest::contained(file, "stamp")?);
+        let keys = keys
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), manifest::substitute(value, &vars)?)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        patch(&path, &keys)?;
+    }
+    Ok(())
+}
+
+/// Sets `keys` in the `key = value` or `key: value` lines of the config at
+/// `path`, keeping the quotes of the values they replace. The file is only
+/// written when it changes, so its edits are not mistaken for the player's.
+fn patch(path: &Path, keys: &BTreeMap<String, String>) -> Result<()> {
+    let text = fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut patched = String::with_capacity(text.len());
+    let mut missing: Vec<&str> = keys.keys().map(String::as_str).collect();
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        match assignment(content).and_then(|(prefix, key, old)| Some((prefix, keys.get(key)?, old, key))) {
+            Some((prefix, value, old, key)) => {
+                missing.retain(|k| *k != key);
+                patched.push_str(prefix);
+                patched.push_str(&quoted(value, old));
+                patched.push_str(&line[content.len()..]);
+            }
+            None => patched.push_str(line),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(anyhow!("{} has no {} to stamp", path.display(), missing.join(", ")));
+    }
+    if patched != text {
+        fs::write(path, patched).with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The line split into everything before the value, the key and the value.
+fn assignment(line: &str) -> Option<(&str, &str, &str)> {
+    let start = line.len() - line.trim_start().len();
+    let length = line[start..].find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')))?;
+    let key = &line[start..start + length];
+    let rest = &line[start + length..];
+    let separator = rest.trim_start();
+    if key.is_empty() || !(separator.starts_with('=') || separator.starts_with(':')) {
+        return None;
+    }
+    let value = separator[1..].trim_start();
+    Some((&line[..line.len() - value.len()], key, value))
+}
+
+/// `value` in the quotes `old` is written in.
+fn quoted(value: &str, old: &str) -> String {
+    match old.chars().next() {
+        Some('"') => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        Some('\'') => format!("'{}'", value),
+        _ => value.to_string(),
+    }
+}
@@ -0,0 +1,175 @@
+use super::bisect::Bisect;
+use super::conditions::Tags;
+use super::durable;
+use super::log::STATE_DIR;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const STATE_FILE: &str = "state.json";
+
+/// A file the tool placed in the instance.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Managed {
+    pub sha256: String,
+    /// Provider the file came from, e.g. `curseforge`.
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<u64>,
+    pub installed_at: DateTime<Utc>,
+    /// Version of the pack whose sync installed the file.
+    pub pack_version: String,
+    /// Size of the file as installed, from the provider when it reports
+    /// one, to catch damaged files without hashing them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Files extracted from the archive of an `unpack` entry, relative to
+    /// the game directory. The archive itself is not kept.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    /// Tags of the entry that installed the file, to tell its removal is
+    /// because one of them was turned off.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Key of the mod a jar extracted from its bundled ones came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// File name of the entry the file was installed for, which differs
+    /// from its own for entries with `install_as`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+impl Managed {
+    /// Whether the file on disk no longer has the size the provider
+    /// reported, e.g. from a truncated download.
+    pub fn damaged(&self, path: &Path) -> bool {
+        match (self.size, fs::metadata(path)) {
+            (Some(size), std::result::Result::Ok(meta)) => meta.len() != size,
+            _ => false,
+        }
+    }
+}
+
+/// Everything the tool has installed into an instance, stored in
+/// `.modpack-sync/state.json`. Files are keyed by their path relative to the
+/// game directory so the instance can be moved.
+#[derive(Serialize, Deserialize, Default)]
+pub struct State {
+    pub files: BTreeMap<String, Managed>,
+    /// Answers to remember, keyed by situation and file, e.g.
+    /// `modified:config/jei.toml`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub decisions: BTreeMap<String, String>,
+    /// Tags last turned on and off, kept until changed.
+    #[serde(default, skip_serializing_if = "Tags::is_empty")]
+    pub tags: Tags,
+    /// When `verify` last hashed every file and found nothing wrong.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deep_verified_at: Option<DateTime<Utc>>,
+    /// The search for the mod behind a crash `bisect` is running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bisect: Option<Bisect>,
+    /// Pending pack version the daemon reported as held, so it is announced
+    /// once even across `daemon --once` runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub announced: Option<String>,
+    /// Pending pack version the daemon found, with when it first did, to
+    /// hold it until it is `updates.min_age_hours` old.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub found: Option<(String, DateTime<Utc>)>,
+    /// Directories of the worlds world entries installed, with when. They
+    /// aren't installed again, even when the player deleted them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub worlds: BTreeMap<String, DateTime<Utc>>,
+    /// Entries that still failed after the retries of their run, by
+    /// filename. Later runs and `retry` try them again.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub retry: BTreeMap<String, Queued>,
+}
+
+/// An entry waiting in the retry queue.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Queued {
+    /// Error of its last attempt.
+    pub error: String,
+    /// Runs it failed in.
+    pub attempts: u32,
+    /// When it first failed.
+    pub since: DateTime<Utc>,
+}
+
+pub fn path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join(STATE_FILE)
+}
+
+pub fn key(game_dir: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(game_dir).unwrap_or(file);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl State {
+    /// Loads the state of an instance, empty if nothing was recorded yet.
+    pub fn load(base_dir: &Path) -> Result<State> {
+        let path = path(base_dir);
+        if !path.exists() {
+            return Ok(State::default());
+        }
+        let contents = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("{} is corrupt, delete it to start over", path.display()))
+    }
+
+    pub fn save(&self, base_dir: &Path) -> Result<()> {
+        let path = path(base_dir);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // replaced at once, a crash while saving keeps the last state
+        durable::write(&path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    pub fn get(&self, game_dir: &Path, file: &Path) -> Option<&Managed> {
+        self.files.get(&key(game_dir, file))
+    }
+
+    pub fn record(&mut self, game_dir: &Path, file: &Path, managed: Managed) {
+        self.files.insert(key(game_dir, file), managed);
+    }
+
+    pub fn forget(&mut self, game_dir: &Path, file: &Path) {
+        self.files.remove(&key(game_dir, file));
+    }
+
+    /// Replaces the retry queue with the entries that failed in a run and
+    /// their error, counting the runs each failed in since it first did.
+    pub fn requeue<'a>(&mut self, failed: impl Iterator<Item = (&'a str, &'a str)>) {
+        let previous = std::mem::take(&mut self.retry);
+        let now = Utc::now();
+        for (filename, error) in failed {
+            let (attempts, since) = previous.get(filename).map_or((0, now), |q| (q.attempts, q.since));
+            self.retry.insert(filename.to_string(), Queued { error: error.to_string(), attempts: attempts + 1, since });
+        }
+    }
+
+    /// Every recorded file with its absolute path.
+    pub fn entries<'a>(&'a self, game_dir: &'a Path) -> impl Iterator<Item = (PathBuf, &'a Managed)> + 'a {
+        self.files.iter().map(move |(k, m)| (game_dir.join(k), m))
+    }
+
+    /// Every path the state accounts for, including the files extracted for
+    /// unpacked entries.
+    pub fn paths(&self, game_dir: &Path) -> Vec<PathBuf> {
+        self.entries(game_dir)
+            .flat_map(|(p, m)| std::iter::once(p).chain(m.files.iter().map(|f| game_dir.join(f))))
+            .collect()
+    }
+}
@@ -0,0 +1,66 @@
+use super::lock::{self, Lockfile};
+use super::report::human_size;
+use super::settings::InstanceSettings;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What a community website shows about the pack, kept small enough to
+/// fetch on every page view.
+#[derive(Serialize)]
+pub struct Stats {
+    pub pack: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minecraft: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loader: Option<String>,
+    /// Entries of the effective modlist.
+    pub mods: usize,
+    /// Files the lockfile pins.
+    pub files: usize,
+    /// Bytes of the pinned files as installed.
+    pub size: u64,
+    /// When the lockfile was last published.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Utc>>,
+}
+
+/// Fields `badge` makes a badge of.
+pub const BADGES: [&str; 5] = ["mods", "version", "minecraft", "size", "updated"];
+
+/// Gathers the statistics of the instance from its modlist and lockfile.
+pub fn build(base_dir: &Path, mods_file: &str) -> Result<Stats> {
+    let instance = InstanceSettings::load(base_dir)?;
+    let game_dir = PathBuf::from(instance.game_dir(&base_dir.to_string_lossy()));
+    let effective = super::load_effective(base_dir, mods_file)?;
+    let lock = Lockfile::load(base_dir)?.unwrap_or_default();
+    let size = lock.files.keys().filter_map(|key| fs::metadata(game_dir.join(key)).ok()).map(|m| m.len()).sum();
+    let updated = fs::metadata(lock::path(base_dir)).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from);
+    Ok(Stats {
+        pack: effective.pack.name.clone(),
+        version: effective.pack.version.clone(),
+        minecraft: effective.pack.minecraft.clone(),
+        loader: effective.pack.loader.clone(),
+        mods: effective.mods.len(),
+        files: lock.files.len(),
+        size,
+        updated,
+    })
+}
+
+/// The shields.io endpoint document of one of the `BADGES`.
+pub fn badge(stats: &Stats, field: &str) -> Result<Value> {
+    let (label, message) = match field {
+        "mods" => ("mods", stats.mods.to_string()),
+        "version" => ("version", stats.version.clone()),
+        "minecraft" => ("minecraft", stats.minecraft.clone().unwrap_or_else(|| "unknown".to_string())),
+        "size" => ("size", human_size(stats.size)),
+        "updated" => ("updated", stats.updated.map(|u| u.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "never".to_string())),
+        other => return Err(anyhow!("unknown badge {}, expected one of {}", other, BADGES.join(", "))),
+    };
+    Ok(json!({ "schemaVersion": 1, "label": label, "message": message, "color": "blue" }))
+}
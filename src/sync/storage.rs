@@ -0,0 +1,455 @@
+use super::dist;
+use super::hash;
+use super::log::{console, STATE_DIR};
+use super::log_to_file;
+use super::panel::Panel;
+use super::settings::PanelSettings;
+use super::staging;
+use super::state::{self, State};
+use crate::i18n::t;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Where the files of an instance are kept, for the file operations a sync
+/// makes on them. Downloads are fetched and staged on this machine first and
+/// handed over complete, so a backend reaching a dedicated server over ssh
+/// only needs to put files in place and delete them.
+pub trait Storage: Send + Sync {
+    /// Name of the backend for messages.
+    fn name(&self) -> &'static str;
+    /// Puts the complete download `part`, a file on this machine, at `dest`.
+    fn place(&self, part: &Path, dest: &Path) -> io::Result<()>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()>;
+    /// Called once a sync saved `state`, for backends that carry out its
+    /// changes elsewhere.
+    fn finish(&self, _base_dir: &Path, _game_dir: &Path, _state: &State) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The instance directory on this machine.
+pub struct Local;
+
+impl Storage for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn place(&self, part: &Path, dest: &Path) -> io::Result<()> {
+        staging::place(part, dest)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)
+    }
+}
+
+/// Where an instance's record of the files on its remote target is kept.
+pub fn record_path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("remote.json")
+}
+
+/// What was last uploaded to a remote target, by path relative to the game
+/// directory, to tell unchanged files from the remote size alone.
+#[derive(Serialize, Deserialize, Default)]
+struct Record {
+    target: String,
+    files: BTreeMap<String, Uploaded>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct Uploaded {
+    size: u64,
+    sha256: String,
+}
+
+impl Record {
+    fn load(base_dir: &Path, target: &str) -> Record {
+        let record: Option<Record> = fs::read_to_string(record_path(base_dir)).ok().and_then(|c| serde_json::from_str(&c).ok());
+        // another target has none of the files recorded for this one
+        record.filter(|r| r.target == target).unwrap_or_else(|| Record { target: target.to_string(), files: BTreeMap::new() })
+    }
+
+    fn save(&self, base_dir: &Path) -> Result<()> {
+        let path = record_path(base_dir);
+        fs::create_dir_all(path.parent().expect("the record lies in the state directory"))?;
+        fs::write(&path, serde_json::to_string_pretty(self)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// A game directory on another host the managed files are pushed to once
+/// the sync ran against the local instance, which keeps a full copy.
+trait Remote {
+    /// Names the target in messages and its record.
+    fn url(&self) -> &str;
+    /// Sizes of the files in the directories of `keys` on the host, by key.
+    /// Directories that don't exist yet have none.
+    fn sizes(&self, base_dir: &Path, keys: &[&String]) -> Result<HashMap<String, u64>>;
+    /// Uploads the files of `uploads` and deletes those of `removals`, all
+    /// relative to `game_dir`.
+    fn push(&self, base_dir: &Path, game_dir: &Path, uploads: &[&String], removals: &[String]) -> Result<()>;
+}
+
+/// Directories of `keys`, the game directory being `""`.
+fn dirs_of<'a>(keys: &[&'a String]) -> Vec<&'a str> {
+    let mut dirs: Vec<&str> = keys.iter().map(|k| k.rsplit_once('/').map_or("", |(dir, _)| dir)).collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// Every directory `key` lies in, outermost first.
+fn parents(key: &str) -> Vec<String> {
+    let parts: Vec<&str> = key.split('/').collect();
+    (1..parts.len()).map(|n| parts[..n].join("/")).collect()
+}
+
+/// Brings `remote` in line with the files `state` manages: files whose size
+/// on the host is the one last uploaded, of a file hashing the same, are
+/// not sent again, files last uploaded and no longer managed are deleted.
+fn push(remote: &dyn Remote, base_dir: &Path, game_dir: &Path, state: &State) -> Result<()> {
+    let mut record = Record::load(base_dir, remote.url());
+    let paths: Vec<PathBuf> = state.paths(game_dir).into_iter().filter(|p| p.is_file()).collect();
+    let mut cache = hash::Cache::load(base_dir);
+    let hashes = cache.hash_all(&paths)?;
+    let _ = cache.save();
+    let local: BTreeMap<String, Uploaded> = paths
+        .iter()
+        .filter_map(|p| {
+            let size = fs::metadata(p).ok()?.len();
+            Some((state::key(game_dir, p), Uploaded { size, sha256: hashes.get(p)?.clone() }))
+        })
+        .collect();
+    let keys: Vec<&String> = local.keys().chain(record.files.keys()).collect();
+    let sizes = remote.sizes(base_dir, &keys)?;
+    let uploads: Vec<&String> = local
+        .iter()
+        .filter(|(key, file)| sizes.get(*key) != Some(&file.size) || record.files.get(*key) != Some(file))
+        .map(|(key, _)| key)
+        .collect();
+    let removals: Vec<String> = record.files.keys().filter(|k| !local.contains_key(*k) && sizes.contains_key(*k)).cloned().collect();
+    let _ = log_to_file(&format!(
+        "[INFO] pushing to {}: {} files to upload, {} to delete, {} unchanged",
+        remote.url(),
+        uploads.len(),
+        removals.len(),
+        local.len() - uploads.len()
+    ));
+    if uploads.is_empty() && removals.is_empty() {
+        return Ok(());
+    }
+    remote.push(base_dir, game_dir, &uploads, &removals)?;
+    record.files.retain(|key, _| local.contains_key(key));
+    for key in uploads {
+        record.files.insert(key.clone(), local[key].clone());
+    }
+    record.save(base_dir)?;
+    console!("[INFO] {}", t!("sync-pushed", count = local.len(), target = remote.url().to_string()));
+    Ok(())
+}
+
+/// A game directory on a host only reached over sftp, such as a shared
+/// hosting server, driven with the `sftp` command in batches.
+pub struct Sftp {
+    /// `user@host` as `sftp` takes it.
+    host: String,
+    /// The game directory on the host.
+    path: String,
+    url: String,
+}
+
+impl Sftp {
+    /// Runs the sftp `batch` against the host, handing back what it printed.
+    fn batch(&self, base_dir: &Path, batch: &str) -> Result<String> {
+        let batch_file = base_dir.join(STATE_DIR).join("target.sftp");
+        fs::create_dir_all(base_dir.join(STATE_DIR))?;
+        fs::write(&batch_file, batch)?;
+        let output = Command::new("sftp")
+            .arg("-b")
+            .arg(&batch_file)
+            .arg(&self.host)
+            .stdin(Stdio::null())
+            .output()
+            .context("failed to run sftp, is it installed?");
+        let _ = fs::remove_file(&batch_file);
+        let output = output?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "sftp to {} exited with {}: {}",
+                self.host,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn remote(&self, key: &str) -> String {
+        format!("{}/{}", self.path.trim_end_matches('/'), key)
+    }
+
+    /// The sftp batch uploading `uploads` and deleting `removals`.
+    fn push_batch(&self, game_dir: &Path, uploads: &[&String], removals: &[String]) -> Result<String> {
+        let mut batch = String::new();
+        let mut made = Vec::new();
+        for key in uploads.iter() {
+            // mkdir makes one level, every parent is made in turn
+            for dir in parents(key) {
+                if !made.contains(&dir) {
+                    batch.push_str(&format!("-mkdir {}\n", dist::sftp_quoted(&self.remote(&dir))?));
+                    made.push(dir);
+                }
+            }
+            let local = dist::sftp_literal(&game_dir.join(key).to_string_lossy())?;
+            batch.push_str(&format!("put {} {}\n", local, dist::sftp_quoted(&self.remote(key))?));
+        }
+        for key in removals.iter() {
+            batch.push_str(&format!("-rm {}\n", dist::sftp_literal(&self.remote(key))?));
+        }
+        Ok(batch)
+    }
+}
+
+impl Remote for Sftp {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn sizes(&self, base_dir: &Path, keys: &[&String]) -> Result<HashMap<String, u64>> {
+        let dirs = dirs_of(keys);
+        let listed: Vec<(String, &str)> = dirs
+            .iter()
+            .map(|dir| Ok((dist::sftp_literal(self.remote(dir).trim_end_matches('/'))?, *dir)))
+            .collect::<Result<_>>()?;
+        let batch: String = listed.iter().map(|(arg, _)| format!("-ls -l {}\n", arg)).collect();
+        let listing = self.batch(base_dir, &batch)?;
+        let mut sizes = HashMap::new();
+        let mut dir = "";
+        for line in listing.lines() {
+            // sftp echoes each command of the batch before its output
+            if let Some(echoed) = line.strip_prefix("sftp> ") {
+                let echoed = echoed.trim_start_matches('-').trim_start_matches("ls -l ");
+                dir = listed.iter().find(|(arg, _)| arg == echoed).map_or("", |(_, d)| d);
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 9 || !fields[0].starts_with('-') {
+                continue;
+            }
+            let Ok(size) = fields[4].parse::<u64>() else {
+                continue;
+            };
+            let name = fields[8..].join(" ");
+            let name = name.rsplit('/').next().unwrap_or(&name);
+            let key = match dir {
+                "" => name.to_string(),
+                dir => format!("{}/{}", dir, name),
+            };
+            sizes.insert(key, size);
+        }
+        Ok(sizes)
+    }
+
+    fn push(&self, base_dir: &Path, game_dir: &Path, uploads: &[&String], removals: &[String]) -> Result<()> {
+        let batch = self.push_batch(game_dir, uploads, removals)?;
+        self.batch(base_dir, &batch).map(|_| ())
+    }
+}
+
+impl Storage for Sftp {
+    fn name(&self) -> &'static str {
+        "sftp"
+    }
+
+    fn place(&self, part: &Path, dest: &Path) -> io::Result<()> {
+        Local.place(part, dest)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        Local.remove_file(path)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        Local.create_dir_all(dir)
+    }
+
+    fn finish(&self, base_dir: &Path, game_dir: &Path, state: &State) -> Result<()> {
+        push(self, base_dir, game_dir, state)
+    }
+}
+
+/// The server directory of a Pterodactyl or Pelican panel, reached with its
+/// client API once there is something to push. The server is stopped while
+/// changed files are uploaded when the panel settings ask for it.
+pub struct Pterodactyl {
+    settings: PanelSettings,
+    url: String,
+}
+
+/// A panel server being pushed to, with the game directory found in its
+/// working directory.
+struct Connected<'a> {
+    panel: Panel,
+    /// The game directory, `""` being the server's working directory.
+    dir: String,
+    url: &'a str,
+}
+
+impl Connected<'_> {
+    fn remote(&self, key: &str) -> String {
+        match self.dir.as_str() {
+            "" => format!("/{}", key),
+            dir => format!("/{}/{}", dir, key),
+        }
+    }
+}
+
+impl Remote for Connected<'_> {
+    fn url(&self) -> &str {
+        self.url
+    }
+
+    fn sizes(&self, _base_dir: &Path, keys: &[&String]) -> Result<HashMap<String, u64>> {
+        let mut sizes = HashMap::new();
+        for dir in dirs_of(keys) {
+            for (name, size) in self.panel.list(&self.remote(dir))? {
+                let key = match dir {
+                    "" => name,
+                    dir => format!("{}/{}", dir, name),
+                };
+                sizes.insert(key, size);
+            }
+        }
+        Ok(sizes)
+    }
+
+    fn push(&self, _base_dir: &Path, game_dir: &Path, uploads: &[&String], removals: &[String]) -> Result<()> {
+        if self.panel.power {
+            console!("[INFO] {}", t!("sync-panel-stopping"));
+            self.panel.power("stop", "offline")?;
+        }
+        let mut made = Vec::new();
+        let mut pushed = || -> Result<()> {
+            for key in uploads.iter() {
+                for dir in parents(key) {
+                    if !made.contains(&dir) {
+                        let path = self.remote(&dir);
+                        let (root, name) = path.rsplit_once('/').expect("remote paths are absolute");
+                        self.panel.create_folder(if root.is_empty() { "/" } else { root }, name);
+                        made.push(dir.clone());
+                    }
+                }
+                self.panel.upload(&self.remote(key), &game_dir.join(key))?;
+                let _ = log_to_file(&format!("[INFO]  uploaded {} to the panel", key));
+            }
+            if !removals.is_empty() {
+                self.panel.delete(&removals.iter().map(|k| self.remote(k)).collect::<Vec<_>>())?;
+            }
+            Ok(())
+        };
+        let result = pushed();
+        // started again whether or not the uploads went through
+        if self.panel.power {
+            self.panel.power("start", "running")?;
+        }
+        result
+    }
+}
+
+impl Pterodactyl {
+    /// Connects to the panel and reads the server's working directory for
+    /// the game directory: the configured one, or the working directory
+    /// itself when it holds a `server.properties`.
+    fn connect(&self) -> Result<Connected<'_>> {
+        let panel = Panel::connect(&self.settings)?;
+        let dir = match &self.settings.directory {
+            Some(dir) => dir.trim_matches('/').to_string(),
+            None if panel.is_game_dir("/")? => String::new(),
+            None => {
+                return Err(anyhow!(
+                    "the working directory of the panel server {} has no server.properties, set panel.directory to the game directory in it",
+                    self.settings.server
+                ))
+            }
+        };
+        Ok(Connected { panel, dir, url: &self.url })
+    }
+}
+
+impl Storage for Pterodactyl {
+    fn name(&self) -> &'static str {
+        "pterodactyl"
+    }
+
+    fn place(&self, part: &Path, dest: &Path) -> io::Result<()> {
+        Local.place(part, dest)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        Local.remove_file(path)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        Local.create_dir_all(dir)
+    }
+
+    fn finish(&self, base_dir: &Path, game_dir: &Path, state: &State) -> Result<()> {
+        push(&self.connect()?, base_dir, game_dir, state)
+    }
+}
+
+/// The storage `--target` names, the instance's panel server without one,
+/// and the local instance without either.
+pub fn open(target: Option<&str>, panel: Option<&PanelSettings>) -> Result<Box<dyn Storage>> {
+    match (target, panel) {
+        (Some(url), _) => match dist::Backend::parse(url) {
+            Ok(dist::Backend::Sftp { host, path }) => Ok(Box::new(Sftp { host, path, url: url.to_string() })),
+            _ => Err(anyhow!("unsupported target {}, expected an sftp://user@host/path url", url)),
+        },
+        (None, Some(settings)) => Ok(Box::new(Pterodactyl {
+            settings: settings.clone(),
+            url: format!("{} server {}", settings.url.trim_end_matches('/'), settings.server),
+        })),
+        (None, None) => Ok(Box::new(Local)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sftp() -> Sftp {
+        Sftp { host: "user@host".to_string(), path: "/srv/mc".to_string(), url: "sftp://user@host/srv/mc".to_string() }
+    }
+
+    #[test]
+    fn batch_lines_stay_one_command() {
+        let names = ["mods/a\"b.jar".to_string(), "mods/[1.20] mod.jar".to_string()];
+        let uploads: Vec<&String> = names.iter().collect();
+        let batch = sftp().push_batch(Path::new("/game"), &uploads, &["mods/old*.jar".to_string()]).unwrap();
+        let lines: Vec<&str> = batch.lines().collect();
+        assert_eq!(lines, [
+            "-mkdir \"/srv/mc/mods\"",
+            "put \"/game/mods/a\\\"b.jar\" \"/srv/mc/mods/a\\\"b.jar\"",
+            "put \"/game/mods/\\\\[1.20\\\\] mod.jar\" \"/srv/mc/mods/[1.20] mod.jar\"",
+            "-rm \"/srv/mc/mods/old\\\\*.jar\"",
+        ]);
+    }
+
+    #[test]
+    fn line_breaks_are_refused() {
+        let key = "mods/a.jar\n!rm -rf ~".to_string();
+        assert!(sftp().push_batch(Path::new("/game"), &[&key], &[]).is_err());
+        assert!(sftp().push_batch(Path::new("/game"), &[], &[key]).is_err());
+    }
+}
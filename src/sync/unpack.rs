@@ -0,0 +1,153 @@
+use super::{links, staging};
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extracts the zip at `archive` into `dir`, returning every file written.
+/// Entries pointing outside of `dir` are refused.
+pub fn extract(archive: &Path, dir: &Path) -> Result<Vec<PathBuf>> {
+    let file = File::open(archive).with_context(|| format!("failed to open {}", archive.display()))?;
+    let mut zip = zip::ZipArchive::new(file).with_context(|| format!("{} is not a valid zip", archive.display()))?;
+    let mut written = Vec::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let name = entry
+            .enclosed_name()
+            .ok_or_else(|| anyhow!("{} contains entries pointing outside of its directory", archive.display()))?;
+        let dest = dir.join(name);
+        if entry.is_dir() {
+            fs::create_dir_all(&dest)?;
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = File::create(&dest).with_context(|| format!("failed to create {}", dest.display()))?;
+        io::copy(&mut entry, &mut out)?;
+        written.push(dest);
+    }
+    Ok(written)
+}
+
+/// Extracts the world in the zip at `archive` into `dir`, the directory
+/// holding its `level.dat` in the zip becoming `dir`, so worlds zipped in a
+/// folder of their own land where they are meant to. Returns the number of
+/// files written.
+pub fn extract_world(archive: &Path, dir: &Path) -> Result<usize> {
+    let file = File::open(archive).with_context(|| format!("failed to open {}", archive.display()))?;
+    let mut zip = zip::ZipArchive::new(file).with_context(|| format!("{} is not a valid zip", archive.display()))?;
+    let root = zip
+        .file_names()
+        .filter_map(|n| n.ok())
+        .filter_map(|n| n.strip_suffix("level.dat").filter(|prefix| prefix.is_empty() || prefix.ends_with('/')).map(str::to_string))
+        .min_by_key(|prefix| prefix.len())
+        .ok_or_else(|| anyhow!("{} is not a world, it has no level.dat", archive.display()))?;
+    let mut written = 0;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let name = entry
+            .enclosed_name()
+            .ok_or_else(|| anyhow!("{} contains entries pointing outside of its directory", archive.display()))?;
+        let Ok(name) = name.strip_prefix(&root) else {
+            continue;
+        };
+        let dest = dir.join(name);
+        if entry.is_dir() {
+            fs::create_dir_all(&dest)?;
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = File::create(&dest).with_context(|| format!("failed to create {}", dest.display()))?;
+        io::copy(&mut entry, &mut out)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Installs the world in the zip at `archive` as the directory `dir`, which
+/// must not exist yet. It is extracted into `staged` and renamed to `dir`
+/// once complete, so the game never sees half a world; beside `dir` when
+/// `staged` is on another file system. Returns the number of files written.
+pub fn install_world(archive: &Path, dir: &Path, staged: &Path) -> Result<usize> {
+    if dir.exists() {
+        return Err(anyhow!("{} already exists, not installing a world over it", dir.display()));
+    }
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut staged = staged.to_path_buf();
+    let _ = fs::remove_dir_all(&staged);
+    let mut written = extract_world(archive, &staged);
+    if written.is_ok() {
+        match fs::rename(&staged, dir) {
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                let _ = fs::remove_dir_all(&staged);
+                staged = staging::part(None, dir);
+                let _ = fs::remove_dir_all(&staged);
+                written = extract_world(archive, &staged).and_then(|written| Ok(fs::rename(&staged, dir).map(|_| written)?));
+            }
+            moved => written = written.and_then(|written| Ok(moved.map(|_| written)?)),
+        }
+    }
+    if written.is_err() {
+        let _ = fs::remove_dir_all(&staged);
+    }
+    written.with_context(|| format!("failed to install the world {}", dir.display()))
+}
+
+/// Deletes the files of an earlier extraction, `files` being relative to
+/// the game directory, along with the directories that leaves empty. Top
+/// level directories such as `mods/` are kept.
+pub fn remove(game_dir: &Path, files: &[String]) {
+    let mut dirs = Vec::new();
+    for file in files {
+        let path = game_dir.join(file);
+        if !links::inside(game_dir, &path) {
+            continue;
+        }
+        let _ = fs::remove_file(&path);
+        dirs.extend(
+            path.ancestors()
+                .skip(1)
+                .take_while(|d| *d != game_dir && d.parent() != Some(game_dir))
+                .map(|d| d.to_path_buf()),
+        );
+    }
+    // deepest first so parents are empty by the time they come up
+    dirs.sort_by(|a, b| b.components().count().cmp(&a.components().count()).then(a.cmp(b)));
+    dirs.dedup();
+    for dir in dirs {
+        let _ = fs::remove_dir(dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn worlds_appear_whole_and_never_over_another() {
+        let root = std::env::temp_dir().join(format!("modpack-sync-world-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let archive = root.join("world.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        for (name, contents) in [("Lobby/level.dat", "level"), ("Lobby/region/r.0.0.mca", "region")] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let dir = root.join("saves/Lobby");
+        let staged = root.join("staging/lobby.part");
+        assert_eq!(install_world(&archive, &dir, &staged).unwrap(), 2);
+        assert_eq!(fs::read_to_string(dir.join("region/r.0.0.mca")).unwrap(), "region");
+        assert!(!staged.exists());
+        assert!(install_world(&archive, &dir, &staged).is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,127 @@
+use super::cache;
+use super::log::{console, STATE_DIR};
+use super::log_to_file;
+use super::plan::Action;
+use super::Run;
+use crate::i18n::t;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Running totals of the syncs of an instance, for its admin to see what
+/// the caches, peers and incremental syncs save. Kept on this machine
+/// only, nothing reads it but `stats`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Usage {
+    /// When the first recorded sync ran.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<DateTime<Utc>>,
+    pub syncs: u64,
+    /// Syncs that failed or left entries failed.
+    pub failed: u64,
+    /// Seconds the syncs took in all, and the part spent downloading.
+    pub seconds: f64,
+    pub download_seconds: f64,
+    pub downloads: u64,
+    /// Bytes fetched from the files' hosts.
+    pub downloaded_bytes: u64,
+    /// Bytes fetched from peers on the network instead.
+    pub peer_bytes: u64,
+    /// Bytes of files several entries need, downloaded once and copied.
+    pub copied_bytes: u64,
+    /// Entries already installed that syncs left in place.
+    pub kept: u64,
+    /// Lookups of the shared response cache, see [`cache::Counts`].
+    pub cache_hits: u64,
+    pub cache_revalidated: u64,
+    pub cache_misses: u64,
+    pub cache_saved_bytes: u64,
+    /// Hashes taken from the hash cache instead of reading the file.
+    pub hash_cache_hits: u64,
+    pub hash_cache_misses: u64,
+}
+
+pub fn path(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join("usage.json")
+}
+
+/// `part` of `whole` in percent, none of nothing.
+fn rate(part: u64, whole: u64) -> Option<f64> {
+    (whole > 0).then(|| part as f64 * 100.0 / whole as f64)
+}
+
+impl Usage {
+    pub fn load(base_dir: &Path) -> Result<Usage> {
+        let path = path(base_dir);
+        if !path.exists() {
+            return Ok(Usage::default());
+        }
+        let contents = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("{} is corrupt", path.display()))
+    }
+
+    fn save(&self, base_dir: &Path) -> Result<()> {
+        let path = path(base_dir);
+        fs::create_dir_all(path.parent().expect("the usage file lies in the state directory"))?;
+        fs::write(&path, serde_json::to_string_pretty(self)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Adds a finished run and the cache lookups made during it.
+    fn add(&mut self, run: &Run, ok: bool, cache: cache::Counts) {
+        let now = Utc::now();
+        let stats = &run.stats;
+        self.since.get_or_insert(now);
+        self.last_sync = Some(now);
+        self.syncs += 1;
+        self.failed += u64::from(!ok);
+        self.seconds += stats.seconds;
+        self.download_seconds += stats.download_seconds;
+        self.downloads += stats.downloads as u64;
+        self.downloaded_bytes += stats.bytes - stats.peer_bytes;
+        self.peer_bytes += stats.peer_bytes;
+        self.copied_bytes += run.installed.iter().filter(|i| i.copy_of.is_some()).map(|i| i.bytes).sum::<u64>();
+        let actions = run.plan.iter().flat_map(|p| p.actions.iter());
+        self.kept += actions.filter(|a| matches!(a, Action::Keep { .. })).count() as u64;
+        self.cache_hits += cache.hits;
+        self.cache_revalidated += cache.revalidated;
+        self.cache_misses += cache.misses;
+        self.cache_saved_bytes += cache.saved;
+        self.hash_cache_hits += stats.cache_hits as u64;
+        self.hash_cache_misses += stats.cache_misses as u64;
+    }
+
+    /// Share of the shared cache's lookups it answered, by itself or after
+    /// the server said the response didn't change.
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let served = self.cache_hits + self.cache_revalidated;
+        rate(served, served + self.cache_misses)
+    }
+
+    pub fn hash_cache_hit_rate(&self) -> Option<f64> {
+        rate(self.hash_cache_hits, self.hash_cache_hits + self.hash_cache_misses)
+    }
+
+    pub fn average_seconds(&self) -> Option<f64> {
+        (self.syncs > 0).then(|| self.seconds / self.syncs as f64)
+    }
+}
+
+/// Adds the run to the instance's usage statistics. They are a courtesy,
+/// failing to record them never fails the sync.
+pub fn record(base_dir: &Path, run: &Run, ok: bool) {
+    let cache = cache::take_counts();
+    if !path(base_dir).exists() {
+        console!("[INFO] {}", t!("sync-usage-started", path = path(base_dir).display()));
+    }
+    let recorded = Usage::load(base_dir).and_then(|mut usage| {
+        usage.add(run, ok, cache);
+        usage.save(base_dir)
+    });
+    if let Err(e) = recorded {
+        let _ = log_to_file(&format!("[WARN] failed to record the usage statistics: {:#}", e));
+    }
+}
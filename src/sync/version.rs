@@ -0,0 +1,241 @@
+use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+
+/// Compares versions the way semver does. Release segments are compared
+/// numerically one by one, non numeric ones as strings, and missing ones
+/// count as zero, so `1.20` == `1.20.0`. A pre-release after `-` ranks below
+/// its release, `1.0.0-beta.2` < `1.0.0-rc.1` < `1.0.0`, and build metadata
+/// after `+` is ignored.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let (a_release, a_pre) = parts(a);
+    let (b_release, b_pre) = parts(b);
+    let a: Vec<&str> = a_release.split('.').filter(|s| !s.is_empty()).collect();
+    let b: Vec<&str> = b_release.split('.').filter(|s| !s.is_empty()).collect();
+
+    for i in 0..a.len().max(b.len()) {
+        let x = a.get(i).copied().unwrap_or("0");
+        let y = b.get(i).copied().unwrap_or("0");
+        let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    match (a_pre, b_pre) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => compare_pre(a, b),
+    }
+}
+
+/// The release and the pre-release of a version, without a leading `v` and
+/// its build metadata.
+fn parts(version: &str) -> (&str, Option<&str>) {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split_once('+').map_or(version, |(version, _)| version);
+    match version.split_once('-') {
+        Some((release, pre)) => (release, Some(pre)),
+        None => (version, None),
+    }
+}
+
+/// Compares pre-releases identifier by identifier, numeric ones by value
+/// and below the others, a pre-release that runs out first ranking lower.
+fn compare_pre(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => x.cmp(y),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Whether a manifest version is a requirement such as `>=0.5, <0.6`
+/// instead of the plain version of a pinned file.
+pub fn is_requirement(version: &str) -> bool {
+    let version = version.trim();
+    version.starts_with(['<', '>', '=']) || version.contains(',')
+}
+
+/// Version of a mod guessed from its file name, e.g. `0.5.8` for
+/// `sodium-fabric-0.5.8+mc1.20.1.jar`. Segments equal to the Minecraft
+/// version the file is built for are not taken for the mod's own.
+pub fn of_file(filename: &str, minecraft: Option<&str>) -> Option<String> {
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    stem.split(['-', '_'])
+        .map(|s| s.split('+').next().unwrap_or(s))
+        .rfind(|s| {
+            s.trim_start_matches('v').starts_with(|c: char| c.is_ascii_digit())
+                && minecraft.is_none_or(|mc| s.trim_start_matches("mc") != mc)
+        })
+        .map(|s| s.trim_start_matches('v').to_string())
+}
+
+/// How big a step an update is, from the first version segment it changes.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Change {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl Change {
+    pub fn parse(value: &str) -> Result<Change> {
+        match value {
+            "patch" => Ok(Change::Patch),
+            "minor" => Ok(Change::Minor),
+            "major" => Ok(Change::Major),
+            other => Err(anyhow!("unknown update kind {}, expected patch, minor or major", other)),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Change::Patch => "patch",
+            Change::Minor => "minor",
+            Change::Major => "major",
+        }
+    }
+}
+
+/// Classifies the step from `from` to `to` by their releases, build
+/// metadata and pre-releases left aside. Below 1.0 the segments move up a
+/// rank the way Cargo reads them: `0.5` -> `0.6` breaks things as often as
+/// a major release, and on `0.0.x` every step does.
+pub fn change(from: &str, to: &str) -> Change {
+    let a: Vec<&str> = parts(from).0.split('.').filter(|s| !s.is_empty()).collect();
+    let b: Vec<&str> = parts(to).0.split('.').filter(|s| !s.is_empty()).collect();
+    let same = |x: &str, y: &str| match (x.parse::<u64>(), y.parse::<u64>()) {
+        (Ok(x), Ok(y)) => x == y,
+        _ => x == y,
+    };
+    let Some(first) = (0..a.len().max(b.len())).find(|&i| !same(a.get(i).unwrap_or(&"0"), b.get(i).unwrap_or(&"0"))) else {
+        return Change::Patch;
+    };
+    let unstable = a.iter().take(2).take_while(|s| s.parse() == Ok(0u64)).count();
+    match first.saturating_sub(unstable) {
+        0 => Change::Major,
+        1 => Change::Minor,
+        _ => Change::Patch,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A comma separated list of comparisons that must all hold, e.g.
+/// `>=1.20, <1.21`. A bare version means equality.
+pub struct Requirement {
+    clauses: Vec<(Op, String)>,
+    text: String,
+}
+
+impl Requirement {
+    pub fn parse(input: &str) -> Result<Requirement> {
+        let mut clauses = Vec::new();
+        for clause in input.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            let (op, version) = if let Some(v) = clause.strip_prefix(">=") {
+                (Op::Ge, v)
+            } else if let Some(v) = clause.strip_prefix("<=") {
+                (Op::Le, v)
+            } else if let Some(v) = clause.strip_prefix('>') {
+                (Op::Gt, v)
+            } else if let Some(v) = clause.strip_prefix('<') {
+                (Op::Lt, v)
+            } else if let Some(v) = clause.strip_prefix("==").or(clause.strip_prefix('=')) {
+                (Op::Eq, v)
+            } else {
+                (Op::Eq, clause)
+            };
+
+            let version = version.trim();
+            if version.is_empty() {
+                return Err(anyhow!("version requirement {} is missing a version", input));
+            }
+            clauses.push((op, version.to_string()));
+        }
+
+        if clauses.is_empty() {
+            return Err(anyhow!("empty version requirement"));
+        }
+        Ok(Requirement { clauses, text: input.trim().to_string() })
+    }
+
+    pub fn matches(&self, version: &str) -> bool {
+        self.clauses.iter().all(|(op, wanted)| {
+            let ordering = compare(version, wanted);
+            match op {
+                Op::Eq => ordering == Ordering::Equal,
+                Op::Gt => ordering == Ordering::Greater,
+                Op::Ge => ordering != Ordering::Less,
+                Op::Lt => ordering == Ordering::Less,
+                Op::Le => ordering != Ordering::Greater,
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for Requirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pre_releases_rank_below_their_release() {
+        let ascending = ["1.0.0-alpha", "1.0.0-alpha.1", "1.0.0-alpha.beta", "1.0.0-beta.2", "1.0.0-beta.11", "1.0.0-rc.1", "1.0.0", "1.0.1", "1.10"];
+        for pair in ascending.windows(2) {
+            assert_eq!(compare(pair[0], pair[1]), Ordering::Less, "{} < {}", pair[0], pair[1]);
+            assert_eq!(compare(pair[1], pair[0]), Ordering::Greater, "{} > {}", pair[1], pair[0]);
+        }
+        assert_eq!(compare("1.20", "1.20.0"), Ordering::Equal);
+        assert_eq!(compare("v0.5.8+mc1.20.1", "0.5.8"), Ordering::Equal);
+    }
+
+    #[test]
+    fn requirements_leave_pre_releases_below_their_release() {
+        let range = Requirement::parse(">=1.0, <2.0").unwrap();
+        assert!(range.matches("1.5.3"));
+        assert!(!range.matches("1.0-rc.1"));
+        assert!(range.matches("2.0-beta"));
+        assert!(!range.matches("2.0"));
+    }
+
+    #[test]
+    fn updates_below_one_rank_higher() {
+        assert_eq!(change("1.2.3", "1.2.4"), Change::Patch);
+        assert_eq!(change("1.2.3", "1.3.0"), Change::Minor);
+        assert_eq!(change("1.2.3", "2.0.0"), Change::Major);
+        assert_eq!(change("0.5.1", "0.5.2"), Change::Minor);
+        assert_eq!(change("0.5.8", "0.6.0"), Change::Major);
+        assert_eq!(change("0.0.3", "0.0.4"), Change::Major);
+        assert_eq!(change("0.9", "1.0"), Change::Major);
+        assert_eq!(change("1.20", "1.20.0+build.7"), Change::Patch);
+        assert_eq!(change("2.1.0-beta.1", "2.1.0"), Change::Patch);
+    }
+}
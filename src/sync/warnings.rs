@@ -0,0 +1,173 @@
+use super::log::console;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Kinds of warnings a sync gives, with codes that stay the same across
+/// releases so `suppress` in the instance settings can name them.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Code {
+    /// An entry has no url, Maven coordinate or other source and is skipped.
+    #[serde(rename = "W001")]
+    MissingUrl,
+    /// Mods declared incompatible with each other are both installed.
+    #[serde(rename = "W002")]
+    Incompatible,
+    /// The Java of the instance doesn't fit the pack.
+    #[serde(rename = "W003")]
+    Java,
+    /// A download host failed or throttled during the run.
+    #[serde(rename = "W004")]
+    HostStruggled,
+    /// The launcher's record of the instance could not be updated.
+    #[serde(rename = "W005")]
+    LauncherOutdated,
+    /// An override was edited locally and upstream.
+    #[serde(rename = "W006")]
+    OverrideConflict,
+    /// An entry or override lies in an unmanaged directory and is left out.
+    #[serde(rename = "W007")]
+    Unmanaged,
+    /// A file isn't deleted because a link leads it outside the instance.
+    #[serde(rename = "W008")]
+    LinkOutside,
+    /// A parent pack is unreachable and its cached copy used.
+    #[serde(rename = "W009")]
+    ParentCached,
+    /// A CurseForge project changed hands since the lockfile recorded it.
+    #[serde(rename = "W010")]
+    OwnerChanged,
+    /// The permissions of the installed files could not be set.
+    #[serde(rename = "W011")]
+    Permissions,
+    /// The pack's launch settings could not be applied.
+    #[serde(rename = "W012")]
+    Launch,
+    /// The pack's name and version could not be stamped into the instance.
+    #[serde(rename = "W013")]
+    Stamp,
+    /// A CurseForge project's files belong in another directory than the
+    /// entry installs them into, e.g. a resource pack in mods/.
+    #[serde(rename = "W014")]
+    ContentType,
+    /// A post-install command was not run, the instance doesn't allow
+    /// scripts.
+    #[serde(rename = "W015")]
+    ScriptNotAllowed,
+    /// The day an entry was held on its file until has passed.
+    #[serde(rename = "W016")]
+    HoldExpired,
+    /// A file built for another loader than the pack's was not installed.
+    #[serde(rename = "W017")]
+    LoaderSkipped,
+}
+
+impl Code {
+    pub const ALL: [Code; 17] = [
+        Code::MissingUrl,
+        Code::Incompatible,
+        Code::Java,
+        Code::HostStruggled,
+        Code::LauncherOutdated,
+        Code::OverrideConflict,
+        Code::Unmanaged,
+        Code::LinkOutside,
+        Code::ParentCached,
+        Code::OwnerChanged,
+        Code::Permissions,
+        Code::Launch,
+        Code::Stamp,
+        Code::ContentType,
+        Code::ScriptNotAllowed,
+        Code::HoldExpired,
+        Code::LoaderSkipped,
+    ];
+
+    /// The codes there are, e.g. `W001 to W017`, for messages about an
+    /// unknown one.
+    pub fn range() -> String {
+        format!("{} to {}", Code::ALL[0].name(), Code::ALL[Code::ALL.len() - 1].name())
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Code::MissingUrl => "W001",
+            Code::Incompatible => "W002",
+            Code::Java => "W003",
+            Code::HostStruggled => "W004",
+            Code::LauncherOutdated => "W005",
+            Code::OverrideConflict => "W006",
+            Code::Unmanaged => "W007",
+            Code::LinkOutside => "W008",
+            Code::ParentCached => "W009",
+            Code::OwnerChanged => "W010",
+            Code::Permissions => "W011",
+            Code::Launch => "W012",
+            Code::Stamp => "W013",
+            Code::ContentType => "W014",
+            Code::ScriptNotAllowed => "W015",
+            Code::HoldExpired => "W016",
+            Code::LoaderSkipped => "W017",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Code> {
+        Code::ALL.into_iter().find(|c| c.name().eq_ignore_ascii_case(code.trim()))
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct Warning {
+    pub code: Code,
+    pub message: String,
+}
+
+/// The warnings of the running sync and the codes it keeps quiet.
+#[derive(Default)]
+struct Warnings {
+    suppressed: Vec<Code>,
+    raised: Vec<Warning>,
+    /// Warnings left out for their code.
+    quiet: usize,
+}
+
+static WARNINGS: Mutex<Option<Warnings>> = Mutex::new(None);
+
+/// Starts collecting the warnings of a run, keeping those with the
+/// `suppressed` codes off the console and out of the reports.
+pub fn start(suppressed: &[Code]) {
+    *WARNINGS.lock().expect("warnings lock poisoned") = Some(Warnings {
+        suppressed: suppressed.to_vec(),
+        ..Warnings::default()
+    });
+}
+
+/// Warns on the console unless `code` is suppressed, recording the warning
+/// for the reports. Outside a run every warning is shown. The log keeps
+/// what the caller writes to it either way.
+pub fn warn(code: Code, message: &str) {
+    let mut warnings = WARNINGS.lock().expect("warnings lock poisoned");
+    if let Some(warnings) = warnings.as_mut() {
+        if warnings.suppressed.contains(&code) {
+            warnings.quiet += 1;
+            return;
+        }
+        warnings.raised.push(Warning {
+            code,
+            message: message.to_string(),
+        });
+    }
+    console!("[WARN] {} ({})", message, code);
+}
+
+/// The warnings raised since [`start`] and how many were suppressed.
+pub fn finish() -> (Vec<Warning>, usize) {
+    let warnings = WARNINGS.lock().expect("warnings lock poisoned").take().unwrap_or_default();
+    (warnings.raised, warnings.quiet)
+}